@@ -297,6 +297,63 @@ bench_mapping_with_n_entries!(
     bench_mapping_with_1024_entries_unaligned
 );
 
+macro_rules! bench_translation_cache {
+    (do_bench, $name:ident, $aligned_memory_mapping:expr, $enable_translation_cache:expr) => {
+        #[bench]
+        fn $name(bencher: &mut Bencher) {
+            let content = vec![0; 4 * 4096];
+            let config = Config {
+                aligned_memory_mapping: $aligned_memory_mapping,
+                enable_translation_cache: $enable_translation_cache,
+                ..Config::default()
+            };
+            let memory_regions = vec![
+                MemoryRegion::new_readonly(&content[..], 0x100000000),
+                MemoryRegion::new_readonly(&content[..], 0x200000000),
+                MemoryRegion::new_readonly(&content[..], 0x300000000),
+            ];
+            let memory_mapping =
+                MemoryMapping::new(memory_regions, &config, SBPFVersion::V3).unwrap();
+            let mut prng = new_prng!();
+            // Accesses stay within a few pages, like the stack and input accesses of programs
+            bencher.iter(|| {
+                let region = prng.gen::<u64>() % 3 + 1;
+                let offset = prng.gen::<u64>() % 8192;
+                assert!(memory_mapping
+                    .map(AccessType::Load, region * 0x100000000 + offset, 8)
+                    .is_ok());
+            });
+        }
+    };
+    () => {
+        bench_translation_cache!(
+            do_bench,
+            bench_translation_cache_enabled_aligned,
+            true,
+            true
+        );
+        bench_translation_cache!(
+            do_bench,
+            bench_translation_cache_disabled_aligned,
+            true,
+            false
+        );
+        bench_translation_cache!(
+            do_bench,
+            bench_translation_cache_enabled_unaligned,
+            false,
+            true
+        );
+        bench_translation_cache!(
+            do_bench,
+            bench_translation_cache_disabled_unaligned,
+            false,
+            false
+        );
+    };
+}
+bench_translation_cache!();
+
 enum MemoryOperation {
     Map,
     Load,
@@ -0,0 +1,41 @@
+/*
+ * Executes an ELF on a one byte input through the C interface.
+ *
+ * cc -I include examples/execute.c target/debug/libsbpf_ffi.a -lpthread -ldl -lm
+ */
+
+#include <stdio.h>
+#include "sbpf.h"
+
+int main(int argc, char **argv) {
+    static uint8_t elf[1 << 16];
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <elf>\n", argv[0]);
+        return 1;
+    }
+    FILE *file = fopen(argv[1], "rb");
+    if (!file) {
+        return 1;
+    }
+    size_t elf_len = fread(elf, 1, sizeof(elf), file);
+    fclose(file);
+    SbpfProgram *program = sbpf_program_load(elf, elf_len);
+    if (!program) {
+        return 1;
+    }
+    uint8_t input[1] = {1};
+    SbpfExecutionResult result;
+    SbpfTaintSummary summary;
+    if (!sbpf_program_execute(program, input, sizeof(input), 0, 1000, &result)
+        || !sbpf_program_taint_summary(program, NULL, 0, &summary)) {
+        sbpf_program_free(program);
+        return 1;
+    }
+    printf("status: %u, return value: %llu, instructions: %llu, tainted loads: %llu\n",
+           result.status,
+           (unsigned long long)result.return_value,
+           (unsigned long long)result.instruction_count,
+           (unsigned long long)summary.tainted_loads);
+    sbpf_program_free(program);
+    return result.status == 0 ? 0 : 1;
+}
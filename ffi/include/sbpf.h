@@ -0,0 +1,73 @@
+/*
+ * C interface of the sBPF interpreter, see src/ffi.rs of solana-sbpf.
+ *
+ * Link against the cdylib or staticlib built from the sbpf_ffi crate.
+ * tests/ffi.rs checks that every exported function is declared here.
+ */
+
+#ifndef SBPF_H
+#define SBPF_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A loaded and verified program, opaque to C */
+typedef struct SbpfProgram SbpfProgram;
+
+/* Outcome of sbpf_program_execute */
+typedef struct SbpfExecutionResult {
+    /* 0 if the program returned, 1 if it threw an error */
+    uint32_t status;
+    /* Value of r0 if the program returned */
+    uint64_t return_value;
+    /* Number of executed instructions */
+    uint64_t instruction_count;
+    /* Number of distinct instructions executed */
+    uint64_t covered_instructions;
+} SbpfExecutionResult;
+
+/* Outcome of sbpf_program_taint_summary */
+typedef struct SbpfTaintSummary {
+    /* Number of distinct conditional jumps whose operands were derived from the input */
+    uint64_t tainted_comparisons;
+    /* Number of distinct loads which loaded values derived from the input */
+    uint64_t tainted_loads;
+    /* Number of distinct input bytes the tainted conditional jumps depend on */
+    uint64_t branch_input_bytes;
+    /* Number of instructions the replay could not follow, the summary is incomplete if not 0 */
+    uint64_t errors;
+} SbpfTaintSummary;
+
+/* Loads and verifies an ELF, returns NULL if it is invalid */
+SbpfProgram *sbpf_program_load(const uint8_t *elf, size_t elf_len);
+
+/* Releases a program returned by sbpf_program_load */
+void sbpf_program_free(SbpfProgram *program);
+
+/* Executes a program in the interpreter with the input mapped at MM_INPUT_START */
+bool sbpf_program_execute(SbpfProgram *program,
+                          uint8_t *input,
+                          size_t input_len,
+                          size_t heap_size,
+                          uint64_t instruction_limit,
+                          SbpfExecutionResult *result);
+
+/* Copies the per instruction hit counters of the last execution */
+size_t sbpf_program_coverage(const SbpfProgram *program, uint8_t *coverage, size_t coverage_len);
+
+/* Summarizes which parts of the last execution depended on the input */
+bool sbpf_program_taint_summary(const SbpfProgram *program,
+                                uint8_t *branch_input_bytes,
+                                size_t branch_input_bytes_len,
+                                SbpfTaintSummary *summary);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SBPF_H */
@@ -0,0 +1,6 @@
+//! C library of the sBPF interpreter
+//!
+//! Links the functions of [solana_sbpf::ffi] into a `cdylib` and a `staticlib`.
+//! They are declared in `include/sbpf.h`.
+
+pub use solana_sbpf::ffi::*;
@@ -0,0 +1,143 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Experimental calling conventions
+//!
+//! [AbiRestrictions] set on an [Executable](crate::elf::Executable) restrict the registers a
+//! program may use and the registers which carry arguments of calls. They are checked by
+//! [Executable::verify](crate::elf::Executable::verify) in addition to the regular verifier.
+//! This allows to prototype changes of the SBPF ABI without changing the execution engines.
+
+use crate::{
+    ebpf,
+    opcode_table::{opcode_info, InstructionClass, OperandSource},
+    program::{FunctionRegistry, SBPFVersion},
+    verifier::VerifierError,
+};
+use std::ops::Range;
+
+/// Registers r0 to r9
+const ALL_REGISTERS: u16 = (1 << ebpf::FRAME_PTR_REG) - 1;
+
+/// Restrictions of the register usage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbiRestrictions {
+    /// Bit mask of the registers r0 to r9 which the program may use (r10 is always usable)
+    pub usable_registers: u16,
+    /// Number of registers, starting at r1, which carry the arguments of functions
+    ///
+    /// The remaining registers up to r5 must be written by a function before it reads them.
+    pub argument_registers: u8,
+}
+
+impl Default for AbiRestrictions {
+    fn default() -> Self {
+        Self {
+            usable_registers: ALL_REGISTERS,
+            argument_registers: 5,
+        }
+    }
+}
+
+/// Registers an instruction reads and writes explicitly, as bit masks
+fn register_usage(sbpf_version: SBPFVersion, insn: &ebpf::Insn) -> (u16, u16) {
+    let Some(info) = opcode_info(insn.opc, sbpf_version) else {
+        return (0, 0);
+    };
+    let dst = 1u16 << (insn.dst & 0xf);
+    let src = if info.source == OperandSource::Register {
+        1u16 << (insn.src & 0xf)
+    } else {
+        0
+    };
+    match info.class {
+        InstructionClass::LoadImmediate => (0, dst),
+        InstructionClass::Alu if matches!(info.mnemonic, "mov32" | "mov64") => (src, dst),
+        InstructionClass::Alu | InstructionClass::Product => (dst | src, dst),
+        InstructionClass::Load => (1 << (insn.src & 0xf), dst),
+        InstructionClass::Store | InstructionClass::ConditionalJump => (dst | src, 0),
+        InstructionClass::Jump => (0, 0),
+        InstructionClass::Call if info.source == OperandSource::Register => {
+            let target = if sbpf_version.callx_uses_src_reg() {
+                insn.src
+            } else {
+                insn.imm as u8
+            };
+            (1 << (target & 0xf), 1)
+        }
+        InstructionClass::Call | InstructionClass::Syscall => (0, 1),
+        InstructionClass::Exit => (1, 0),
+    }
+}
+
+impl AbiRestrictions {
+    /// Allows only the registers r0 to r(n - 1) and r10
+    pub fn with_register_count(register_count: u8) -> Self {
+        Self {
+            usable_registers: ALL_REGISTERS & ((1u16 << register_count.min(10)) - 1),
+            argument_registers: register_count.saturating_sub(1).min(5),
+        }
+    }
+
+    /// Checks that the program adheres to the restrictions
+    ///
+    /// Argument registers are tracked per function in program order, which is exact for
+    /// functions without backward jumps.
+    pub fn verify(
+        &self,
+        prog: &[u8],
+        sbpf_version: SBPFVersion,
+        function_registry: &FunctionRegistry<usize>,
+    ) -> Result<(), VerifierError> {
+        self.verify_range(
+            prog,
+            sbpf_version,
+            function_registry,
+            0..prog.len() / ebpf::INSN_SIZE,
+        )
+    }
+
+    /// Checks the instructions in `range`, which must start at a function
+    pub(crate) fn verify_range(
+        &self,
+        prog: &[u8],
+        sbpf_version: SBPFVersion,
+        function_registry: &FunctionRegistry<usize>,
+        range: Range<usize>,
+    ) -> Result<(), VerifierError> {
+        let mut function_starts = function_registry
+            .iter()
+            .map(|(_key, (_name, pc))| pc)
+            .collect::<Vec<_>>();
+        function_starts.sort_unstable();
+        let arguments = ((1u16 << self.argument_registers.min(5)) - 1) << 1;
+        let undefined_at_entry = 0b11_1110 & !arguments & self.usable_registers;
+        let mut undefined = undefined_at_entry;
+        let mut pc = range.start;
+        while pc < range.end && (pc + 1) * ebpf::INSN_SIZE <= prog.len() {
+            if function_starts.binary_search(&pc).is_ok() {
+                undefined = undefined_at_entry;
+            }
+            let insn = ebpf::get_insn(prog, pc);
+            let (reads, writes) = register_usage(sbpf_version, &insn);
+            let used = (reads | writes) & ALL_REGISTERS;
+            if used & !self.usable_registers != 0 {
+                return Err(VerifierError::RestrictedRegister(
+                    (used & !self.usable_registers).trailing_zeros() as u8,
+                    pc,
+                ));
+            }
+            if reads & undefined != 0 {
+                return Err(VerifierError::UndefinedArgumentRegister(
+                    (reads & undefined).trailing_zeros() as u8,
+                    pc,
+                ));
+            }
+            undefined &= !writes;
+            pc += if insn.opc == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw() {
+                2
+            } else {
+                1
+            };
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,510 @@
+//! Accounts in the serialized input
+//!
+//! Maps offsets in the input region to the account and field they belong to,
+//! according to the [InputLayout::Aligned] serialization, and tracks which accounts
+//! a program writes to, how often it reads and writes each field and which accounts it
+//! never reads during execution.
+//! For inputs of unknown layout, candidate
+//! fields can be inferred from the access pattern of the program instead.
+
+#[cfg(doc)]
+use crate::harness::InputLayout;
+use crate::{
+    ebpf,
+    elf::Executable,
+    error::ProgramResult,
+    harness::memory_access,
+    interpreter::Interpreter,
+    vm::{ContextObject, EbpfVm},
+};
+use std::{collections::BTreeMap, convert::TryInto, ops::Range};
+
+/// Realloc padding after every account data
+pub(crate) const MAX_PERMITTED_DATA_INCREASE: usize = 10 * 1024;
+/// Marks an account which is not a duplicate
+pub(crate) const NON_DUP_MARKER: u8 = u8::MAX;
+
+/// Field of the serialized input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AccountField {
+    /// Number of accounts
+    AccountCount,
+    /// Duplicate marker, signer, writable and executable flags
+    Header,
+    /// Public key of the account
+    Key,
+    /// Public key of the owner
+    OwnerPubkey,
+    /// Balance
+    Lamports,
+    /// Length of the account data
+    DataLength,
+    /// Account data
+    Data,
+    /// Space for the account data to grow into, including the alignment
+    ReallocPadding,
+    /// Rent epoch
+    RentEpoch,
+    /// Length and content of the instruction data
+    InstructionData,
+    /// Public key of the program
+    ProgramId,
+}
+
+/// Offsets of the fields of the serialized input
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountLayout {
+    /// Field ranges in ascending order, with the index of the account they belong to
+    fields: Vec<(Range<usize>, Option<usize>, AccountField)>,
+}
+
+impl AccountLayout {
+    /// Parses an input serialized in the aligned layout
+    ///
+    /// Returns `None` if the input is truncated. Duplicate accounts only consist of a header.
+    pub fn parse_aligned(input: &[u8]) -> Option<Self> {
+        let mut layout = Self::default();
+        let mut offset = 0usize;
+        let read_u64 = |offset: usize| {
+            input
+                .get(offset..offset.checked_add(8)?)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()) as usize)
+        };
+        let start = layout.push(
+            &mut offset,
+            input.len(),
+            None,
+            AccountField::AccountCount,
+            8,
+        )?;
+        let account_count = read_u64(start)?;
+        for index in 0..account_count {
+            let account = Some(index);
+            let header = layout.push(&mut offset, input.len(), account, AccountField::Header, 8)?;
+            if input[header] != NON_DUP_MARKER {
+                continue;
+            }
+            for (field, len) in [
+                (AccountField::Key, 32),
+                (AccountField::OwnerPubkey, 32),
+                (AccountField::Lamports, 8),
+            ] {
+                layout.push(&mut offset, input.len(), account, field, len)?;
+            }
+            let data_length = read_u64(layout.push(
+                &mut offset,
+                input.len(),
+                account,
+                AccountField::DataLength,
+                8,
+            )?)?;
+            let padding = MAX_PERMITTED_DATA_INCREASE
+                .checked_add(data_length)?
+                .next_multiple_of(8)
+                .checked_sub(data_length)?;
+            for (field, len) in [
+                (AccountField::Data, data_length),
+                (AccountField::ReallocPadding, padding),
+                (AccountField::RentEpoch, 8),
+            ] {
+                layout.push(&mut offset, input.len(), account, field, len)?;
+            }
+        }
+        let instruction_data_length = read_u64(offset)?;
+        layout.push(
+            &mut offset,
+            input.len(),
+            None,
+            AccountField::InstructionData,
+            instruction_data_length.checked_add(8)?,
+        )?;
+        layout.push(&mut offset, input.len(), None, AccountField::ProgramId, 32)?;
+        Some(layout)
+    }
+
+    /// Appends a field at `offset` and advances it, `None` if it exceeds the input
+    fn push(
+        &mut self,
+        offset: &mut usize,
+        input_len: usize,
+        account: Option<usize>,
+        field: AccountField,
+        len: usize,
+    ) -> Option<usize> {
+        let start = *offset;
+        let end = start.checked_add(len).filter(|end| *end <= input_len)?;
+        self.fields.push((start..end, account, field));
+        *offset = end;
+        Some(start)
+    }
+
+    /// Account index and field at the offset into the input
+    pub fn lookup(&self, offset: usize) -> Option<(Option<usize>, AccountField)> {
+        let index = self
+            .fields
+            .partition_point(|(range, _account, _field)| range.end <= offset);
+        self.fields
+            .get(index)
+            .filter(|(range, _account, _field)| range.contains(&offset))
+            .map(|(_range, account, field)| (*account, *field))
+    }
+
+    /// Fields overlapping the range of offsets, clipped to it
+    pub fn fields_in(
+        &self,
+        range: Range<usize>,
+    ) -> impl Iterator<Item = (Range<usize>, Option<usize>, AccountField)> + '_ {
+        let index = self
+            .fields
+            .partition_point(|(field_range, _account, _field)| field_range.end <= range.start);
+        let Range { start, end } = range;
+        self.fields[index..]
+            .iter()
+            .take_while(move |(field_range, _account, _field)| field_range.start < end)
+            .map(move |(field_range, account, field)| {
+                (
+                    field_range.start.max(start)..field_range.end.min(end),
+                    *account,
+                    *field,
+                )
+            })
+    }
+
+    /// Ranges of a field in all accounts, with the index of the account they belong to
+    pub fn ranges_of(
+        &self,
+        field: AccountField,
+    ) -> impl Iterator<Item = (Range<usize>, Option<usize>)> + '_ {
+        self.fields
+            .iter()
+            .filter(move |(_range, _account, other)| *other == field)
+            .map(|(range, account, _field)| (range.clone(), *account))
+    }
+}
+
+/// Number of loads and stores which accessed a field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessCount {
+    /// Number of loads
+    pub reads: u64,
+    /// Number of stores
+    pub writes: u64,
+}
+
+/// Records the loads from and stores into the input region during execution
+#[derive(Debug)]
+pub struct AccountWriteTracker {
+    layout: AccountLayout,
+    /// Number of bytes written per account and field
+    writes: BTreeMap<(Option<usize>, AccountField), u64>,
+    /// Number of accesses per account and field
+    accesses: BTreeMap<(Option<usize>, AccountField), AccessCount>,
+}
+
+impl AccountWriteTracker {
+    /// Creates a tracker for the given layout of the input region
+    pub fn new(layout: AccountLayout) -> Self {
+        Self {
+            layout,
+            writes: BTreeMap::new(),
+            accesses: BTreeMap::new(),
+        }
+    }
+
+    /// Executes the program in the interpreter, like [EbpfVm::execute_program]
+    ///
+    /// Accesses through an [alias](crate::memory_region::MemoryMapping::add_alias) of the
+    /// input region count for the aliased fields.
+    pub fn execute<C: ContextObject>(
+        &mut self,
+        vm: &mut EbpfVm<C>,
+        executable: &Executable<C>,
+    ) -> (u64, ProgramResult) {
+        let sbpf_version = executable.get_sbpf_version();
+        vm.registers[11] = executable.get_entrypoint_instruction_offset() as u64;
+        let initial_insn_count = vm.context_object_pointer.get_remaining();
+        vm.previous_instruction_meter = initial_insn_count;
+        vm.due_insn_count = 0;
+        vm.program_result = ProgramResult::Ok(0);
+        {
+            let mut interpreter = Interpreter::new(vm, executable, vm.registers);
+            loop {
+                let pc = interpreter.reg[11] as usize;
+                let access = interpreter
+                    .program
+                    .get(pc.saturating_mul(ebpf::INSN_SIZE)..)
+                    .filter(|rest| rest.len() >= ebpf::INSN_SIZE)
+                    .map(|_| ebpf::get_insn_unchecked(interpreter.program, pc))
+                    .and_then(|insn| {
+                        let (is_load, base, size) = memory_access(sbpf_version, &insn)?;
+                        let vm_addr = (*interpreter.reg.get(base as usize)? as i64)
+                            .wrapping_add(insn.off as i64)
+                            as u64;
+                        let vm_addr = interpreter.vm.memory_mapping.resolve_alias(vm_addr);
+                        Some((is_load, vm_addr, size))
+                    });
+                if !interpreter.step() {
+                    break;
+                }
+                // Only accesses which did not fault are recorded
+                if let Some((is_load, vm_addr, size)) = access {
+                    self.record_access(is_load, vm_addr, size);
+                }
+            }
+        }
+        let instruction_count = if executable.get_config().enable_instruction_meter {
+            vm.context_object_pointer.consume(vm.due_insn_count);
+            initial_insn_count.saturating_sub(vm.context_object_pointer.get_remaining())
+        } else {
+            0
+        };
+        let mut result = ProgramResult::Ok(0);
+        std::mem::swap(&mut result, &mut vm.program_result);
+        (instruction_count, result)
+    }
+
+    fn record_access(&mut self, is_load: bool, vm_addr: u64, size: u64) {
+        let Some(offset) = vm_addr.checked_sub(ebpf::MM_INPUT_START) else {
+            return;
+        };
+        let range = offset as usize..(offset.saturating_add(size) as usize);
+        for (_range, account, field) in self.layout.fields_in(range) {
+            let count = self.accesses.entry((account, field)).or_default();
+            if is_load {
+                count.reads = count.reads.saturating_add(1);
+            } else {
+                count.writes = count.writes.saturating_add(1);
+            }
+        }
+        if is_load {
+            return;
+        }
+        for offset in offset..offset.saturating_add(size) {
+            if let Some(key) = self.layout.lookup(offset as usize) {
+                let counter = self.writes.entry(key).or_insert(0);
+                *counter = counter.saturating_add(1);
+            }
+        }
+    }
+
+    /// Number of bytes written per (account index, field) since the tracker was created
+    ///
+    /// Fields which do not belong to an account have no account index.
+    pub fn account_write_summary(&self) -> &BTreeMap<(Option<usize>, AccountField), u64> {
+        &self.writes
+    }
+
+    /// Number of loads and stores per (account index, field) since the tracker was created
+    ///
+    /// An access which spans several fields counts for each of them. Fields the program
+    /// never accessed are missing, which tells the parts of the input it does not consume.
+    pub fn access_histogram(&self) -> &BTreeMap<(Option<usize>, AccountField), AccessCount> {
+        &self.accesses
+    }
+
+    /// Indices of the accounts written to
+    pub fn written_accounts(&self) -> Vec<usize> {
+        let mut accounts = self
+            .writes
+            .keys()
+            .filter_map(|(account, _field)| *account)
+            .collect::<Vec<_>>();
+        accounts.dedup();
+        accounts
+    }
+
+    /// Indices of the accounts whose key, owner and data were never read
+    ///
+    /// Such accounts do not influence the execution beyond their header and balance, so
+    /// they are candidates for pruning from an input. Duplicate accounts are not reported.
+    pub fn unused_accounts(&self) -> Vec<usize> {
+        self.layout
+            .ranges_of(AccountField::Key)
+            .filter_map(|(_range, account)| account)
+            .filter(|account| {
+                [
+                    AccountField::Key,
+                    AccountField::OwnerPubkey,
+                    AccountField::Data,
+                ]
+                .iter()
+                .all(|field| {
+                    self.accesses
+                        .get(&(Some(*account), *field))
+                        .is_none_or(|count| count.reads == 0)
+                })
+            })
+            .collect()
+    }
+}
+
+/// Role of an inferred field, derived from how the program used the loaded value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InferredFieldKind {
+    /// Plain value
+    Scalar,
+    /// 8 byte value which pointed into the mapped address space
+    Pointer,
+    /// Value compared in the condition of a loop
+    Length,
+}
+
+/// A field of the input candidate layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InferredField {
+    /// Offset into the input
+    pub offset: u64,
+    /// Length in bytes
+    pub len: u64,
+    /// Role of the field
+    pub kind: InferredFieldKind,
+}
+
+/// How an offset of the input was read
+#[derive(Debug, Clone, Copy, Default)]
+struct ReadObservation {
+    max_size: u64,
+    pointer_like: bool,
+    length_like: bool,
+}
+
+/// Infers field boundaries of an input of unknown layout from the loads of the program
+///
+/// Observations accumulate over all executions, so running many different inputs
+/// yields a more complete candidate layout.
+#[derive(Debug, Default)]
+pub struct InputLayoutInference {
+    reads: BTreeMap<u64, ReadObservation>,
+}
+
+impl InputLayoutInference {
+    /// Executes the program in the interpreter while observing its loads from the input region
+    pub fn observe<C: ContextObject>(
+        &mut self,
+        vm: &mut EbpfVm<C>,
+        executable: &Executable<C>,
+    ) -> (u64, ProgramResult) {
+        let sbpf_version = executable.get_sbpf_version();
+        vm.registers[11] = executable.get_entrypoint_instruction_offset() as u64;
+        let initial_insn_count = vm.context_object_pointer.get_remaining();
+        vm.previous_instruction_meter = initial_insn_count;
+        vm.due_insn_count = 0;
+        vm.program_result = ProgramResult::Ok(0);
+        {
+            let mut interpreter = Interpreter::new(vm, executable, vm.registers);
+            // Input offset each register was loaded from
+            let mut origins = [None::<u64>; 11];
+            loop {
+                let pc = interpreter.reg[11] as usize;
+                let Some(insn) = interpreter
+                    .program
+                    .get(pc.saturating_mul(ebpf::INSN_SIZE)..)
+                    .filter(|rest| rest.len() >= ebpf::INSN_SIZE)
+                    .map(|_| ebpf::get_insn_unchecked(interpreter.program, pc))
+                else {
+                    interpreter.step();
+                    break;
+                };
+                let dst = (insn.dst as usize).min(origins.len().saturating_sub(1));
+                let src = (insn.src as usize).min(origins.len().saturating_sub(1));
+                let input_load =
+                    memory_access(sbpf_version, &insn).and_then(|(is_load, base, size)| {
+                        let vm_addr = (*interpreter.reg.get(base as usize)? as i64)
+                            .wrapping_add(insn.off as i64)
+                            as u64;
+                        let offset = vm_addr.checked_sub(ebpf::MM_INPUT_START)?;
+                        (is_load && offset < ebpf::MM_REGION_SIZE).then_some((offset, size))
+                    });
+                let is_conditional_jump = insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP
+                    && matches!(
+                        insn.opc & ebpf::BPF_ALU_OP_MASK,
+                        ebpf::BPF_JEQ
+                            | ebpf::BPF_JGT
+                            | ebpf::BPF_JGE
+                            | ebpf::BPF_JSET
+                            | ebpf::BPF_JNE
+                            | ebpf::BPF_JSGT
+                            | ebpf::BPF_JSGE
+                            | ebpf::BPF_JLT
+                            | ebpf::BPF_JLE
+                            | ebpf::BPF_JSLT
+                            | ebpf::BPF_JSLE
+                    );
+                if is_conditional_jump && insn.off < 0 {
+                    let mut operands = vec![origins[dst]];
+                    if insn.opc & ebpf::BPF_X != 0 {
+                        operands.push(origins[src]);
+                    }
+                    for offset in operands.into_iter().flatten() {
+                        self.reads.entry(offset).or_default().length_like = true;
+                    }
+                }
+                if !interpreter.step() {
+                    break;
+                }
+                if let Some((offset, size)) = input_load {
+                    let value = interpreter.reg[dst];
+                    let observation = self.reads.entry(offset).or_default();
+                    observation.max_size = observation.max_size.max(size);
+                    observation.pointer_like |= size == 8
+                        && (ebpf::MM_RODATA_START
+                            ..ebpf::MM_INPUT_START.saturating_add(ebpf::MM_REGION_SIZE))
+                            .contains(&value);
+                    origins[dst] = Some(offset);
+                } else if insn.opc == ebpf::MOV64_REG {
+                    origins[dst] = origins[src];
+                } else if matches!(insn.opc, ebpf::CALL_IMM | ebpf::CALL_REG)
+                    || (sbpf_version.static_syscalls() && insn.opc == ebpf::SYSCALL)
+                {
+                    origins[0..6].iter_mut().for_each(|origin| *origin = None);
+                } else if insn.opc & ebpf::BPF_CLS_MASK != ebpf::BPF_JMP
+                    && !memory_access(sbpf_version, &insn)
+                        .map(|(is_load, _base, _size)| !is_load)
+                        .unwrap_or(false)
+                {
+                    origins[dst] = None;
+                }
+            }
+        }
+        let instruction_count = if executable.get_config().enable_instruction_meter {
+            vm.context_object_pointer.consume(vm.due_insn_count);
+            initial_insn_count.saturating_sub(vm.context_object_pointer.get_remaining())
+        } else {
+            0
+        };
+        let mut result = ProgramResult::Ok(0);
+        std::mem::swap(&mut result, &mut vm.program_result);
+        (instruction_count, result)
+    }
+
+    /// Candidate fields in ascending order of their offsets
+    ///
+    /// Overlapping reads are merged into one field. Length and pointer roles take precedence.
+    pub fn infer(&self) -> Vec<InferredField> {
+        let mut fields: Vec<InferredField> = Vec::new();
+        for (offset, observation) in self.reads.iter() {
+            let kind = if observation.length_like {
+                InferredFieldKind::Length
+            } else if observation.pointer_like {
+                InferredFieldKind::Pointer
+            } else {
+                InferredFieldKind::Scalar
+            };
+            let end = offset.saturating_add(observation.max_size);
+            match fields.last_mut() {
+                Some(field) if field.offset.saturating_add(field.len) > *offset => {
+                    field.len = field.len.max(end.saturating_sub(field.offset));
+                    if kind != InferredFieldKind::Scalar {
+                        field.kind = kind;
+                    }
+                }
+                _ => fields.push(InferredField {
+                    offset: *offset,
+                    len: observation.max_size,
+                    kind,
+                }),
+            }
+        }
+        fields
+    }
+}
@@ -0,0 +1,137 @@
+//! Basic block level traces
+//!
+//! Recording the registers before every instruction quickly becomes too large for long
+//! executions. A [BlockTrace] only records the entries into the basic blocks of
+//! [Analysis::cfg_nodes], collapses a block re-entering itself into a repetition count and
+//! counts the iterations of every loop. It can be expanded back into the sequence of
+//! executed pcs, which is exact as long as no block is left early by a call or an error.
+
+use crate::{
+    ebpf,
+    observer::ExecutionObserver,
+    static_analysis::{Analysis, TraceLogEntry},
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Consecutive entries into the same basic block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockEntry {
+    /// Pc of the first instruction of the basic block
+    pub pc: u64,
+    /// Number of consecutive entries, at least 1
+    pub repetitions: u64,
+}
+
+/// Compressed trace of an execution
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockTrace {
+    /// Entries into basic blocks in the order they happened
+    pub entries: Vec<BlockEntry>,
+    /// Pc of a loop header => number of times it was reached by a backward jump
+    pub loop_iterations: BTreeMap<u64, u64>,
+    /// Number of instructions executed
+    pub instruction_count: u64,
+}
+
+impl BlockTrace {
+    /// Compresses a trace log recorded while executing the program of `analysis`
+    pub fn from_trace_log(analysis: &Analysis, trace_log: &[TraceLogEntry]) -> Self {
+        let mut recorder = BlockTraceRecorder::new(analysis);
+        for entry in trace_log.iter() {
+            recorder.record(entry[11]);
+        }
+        recorder.trace
+    }
+
+    /// Number of entries into basic blocks, including repetitions
+    pub fn block_count(&self) -> u64 {
+        self.entries
+            .iter()
+            .fold(0, |count, entry| count.saturating_add(entry.repetitions))
+    }
+
+    /// Reconstructs the sequence of executed pcs
+    ///
+    /// Every entry expands to all instructions of its basic block, so instructions after a call
+    /// appear before the callee and the instructions after an error are included.
+    pub fn expand(&self, analysis: &Analysis) -> Vec<u64> {
+        let mut pcs = Vec::new();
+        for entry in self.entries.iter() {
+            let Some(cfg_node) = analysis.cfg_nodes.get(&(entry.pc as usize)) else {
+                pcs.push(entry.pc);
+                continue;
+            };
+            let block = analysis.instructions[cfg_node.instructions.clone()]
+                .iter()
+                .map(|insn| insn.ptr as u64)
+                .collect::<Vec<_>>();
+            for _ in 0..entry.repetitions {
+                pcs.extend_from_slice(&block);
+            }
+        }
+        pcs
+    }
+}
+
+/// [ExecutionObserver] which records a [BlockTrace]
+#[derive(Debug, Clone, Default)]
+pub struct BlockTraceRecorder {
+    /// Pcs at which basic blocks start
+    block_starts: BTreeSet<u64>,
+    /// Pcs of the jump instructions
+    jumps: BTreeSet<u64>,
+    /// Pc of the previously executed instruction
+    previous_pc: Option<u64>,
+    /// Trace recorded so far
+    pub trace: BlockTrace,
+}
+
+impl BlockTraceRecorder {
+    /// Creates a recorder for the basic blocks of `analysis`
+    pub fn new(analysis: &Analysis) -> Self {
+        Self {
+            block_starts: analysis.cfg_nodes.keys().map(|pc| *pc as u64).collect(),
+            jumps: analysis
+                .instructions
+                .iter()
+                .filter(|insn| {
+                    insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP
+                        && !matches!(
+                            insn.opc,
+                            ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::EXIT | ebpf::RETURN
+                        )
+                })
+                .map(|insn| insn.ptr as u64)
+                .collect(),
+            previous_pc: None,
+            trace: BlockTrace::default(),
+        }
+    }
+
+    fn record(&mut self, pc: u64) {
+        self.trace.instruction_count = self.trace.instruction_count.saturating_add(1);
+        let previous_pc = self.previous_pc.replace(pc);
+        if !self.block_starts.contains(&pc) {
+            return;
+        }
+        // Returns from functions placed after the caller are no back edges
+        if previous_pc
+            .is_some_and(|previous_pc| previous_pc >= pc && self.jumps.contains(&previous_pc))
+        {
+            let iterations = self.trace.loop_iterations.entry(pc).or_insert(0);
+            *iterations = iterations.saturating_add(1);
+        }
+        match self.trace.entries.last_mut() {
+            Some(last) if last.pc == pc => {
+                last.repetitions = last.repetitions.saturating_add(1);
+            }
+            _ => self.trace.entries.push(BlockEntry { pc, repetitions: 1 }),
+        }
+    }
+}
+
+impl ExecutionObserver for BlockTraceRecorder {
+    fn on_insn(&mut self, pc: u64, _insn: &ebpf::Insn, _registers: &[u64; 12]) {
+        self.record(pc);
+    }
+}
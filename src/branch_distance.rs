@@ -0,0 +1,167 @@
+//! Branch distance feedback
+//!
+//! For every executed conditional jump, the distance between its operands tells how close
+//! the input came to flipping the branch. Recorded per pc over a trace, the minimal distances
+//! allow a fuzzer to keep inputs which approach an uncovered branch, even if they do not
+//! cover any new edge yet.
+//!
+//! The trace may come from elsewhere than the execution of the executable, e.g. from a file
+//! exported by an earlier run. Jumps which do not fit the trace are reported as
+//! [BranchDistanceError]s instead of aborting the evaluation.
+
+use crate::{
+    ebpf,
+    elf::Executable,
+    opcode_table::{opcode_info, InstructionClass, OperandSource},
+    static_analysis::TraceLogEntry,
+    vm::ContextObject,
+};
+use std::collections::BTreeMap;
+
+/// A conditional jump of a trace which [BranchDistances::new] could not evaluate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum BranchDistanceError {
+    /// The jump reads a register which is not part of a trace entry
+    #[error("jump at pc {pc} reads the invalid register r{register}")]
+    InvalidRegister {
+        /// Pc of the jump
+        pc: u64,
+        /// Index of the register
+        register: u8,
+    },
+}
+
+/// A conditional jump of a trace with the values of its operands
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Comparison {
+    pub pc: u64,
+    pub opc: u8,
+    pub signed: bool,
+    pub dst: u64,
+    /// Value of the source register or the immediate
+    pub src: u64,
+}
+
+/// The conditional jumps of a trace recorded while executing `executable`
+pub(crate) fn comparisons<'a, C: ContextObject>(
+    executable: &'a Executable<C>,
+    trace_log: &'a [TraceLogEntry],
+) -> impl Iterator<Item = Result<Comparison, BranchDistanceError>> + 'a {
+    let (_program_vm_addr, program) = executable.get_text_bytes();
+    let sbpf_version = executable.get_sbpf_version();
+    trace_log.iter().filter_map(move |entry| {
+        let pc = entry[11];
+        if (pc as usize)
+            .checked_add(1)
+            .and_then(|end| end.checked_mul(ebpf::INSN_SIZE))
+            .is_none_or(|end| end > program.len())
+        {
+            return None;
+        }
+        let insn = ebpf::get_insn(program, pc as usize);
+        let info = opcode_info(insn.opc, sbpf_version)
+            .filter(|info| info.class == InstructionClass::ConditionalJump)?;
+        let register = |register: u8| {
+            entry
+                .get(..11)
+                .and_then(|registers| registers.get(register as usize))
+                .copied()
+                .ok_or(BranchDistanceError::InvalidRegister { pc, register })
+        };
+        let comparison = || {
+            Ok(Comparison {
+                pc,
+                opc: insn.opc,
+                signed: info.signed,
+                dst: register(insn.dst)?,
+                src: match info.source {
+                    OperandSource::Register => register(insn.src)?,
+                    _ => insn.imm as u64,
+                },
+            })
+        };
+        Some(comparison())
+    })
+}
+
+/// Minimal operand distance per conditional jump (from a recorded trace)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BranchDistances {
+    /// pc => smallest distance observed
+    distances: BTreeMap<u64, u64>,
+    /// Jumps which could not be evaluated, in trace order
+    errors: Vec<BranchDistanceError>,
+}
+
+impl BranchDistances {
+    /// Evaluates the conditional jumps of a trace recorded while executing `executable`
+    ///
+    /// The distance is `|dst - src|` (or `|dst - imm|`), compared as signed values for signed
+    /// jumps. For `jset` it is the number of bits `dst` and `src` have in common.
+    pub fn new<C: ContextObject>(executable: &Executable<C>, trace_log: &[TraceLogEntry]) -> Self {
+        let mut result = Self::default();
+        for comparison in comparisons(executable, trace_log) {
+            let Comparison {
+                pc,
+                opc,
+                signed,
+                dst,
+                src,
+            } = match comparison {
+                Ok(comparison) => comparison,
+                Err(error) => {
+                    result.errors.push(error);
+                    continue;
+                }
+            };
+            let distance = if matches!(opc, ebpf::JSET_IMM | ebpf::JSET_REG) {
+                (dst & src).count_ones() as u64
+            } else if signed {
+                (dst as i64).abs_diff(src as i64)
+            } else {
+                dst.abs_diff(src)
+            };
+            result.record(pc, distance);
+        }
+        result
+    }
+
+    fn record(&mut self, pc: u64, distance: u64) {
+        self.distances
+            .entry(pc)
+            .and_modify(|minimum| *minimum = (*minimum).min(distance))
+            .or_insert(distance);
+    }
+
+    /// pc => smallest distance observed
+    pub fn branch_distance_map(&self) -> &BTreeMap<u64, u64> {
+        &self.distances
+    }
+
+    /// Jumps which could not be evaluated, in trace order
+    pub fn errors(&self) -> &[BranchDistanceError] {
+        &self.errors
+    }
+
+    /// Accumulates the distances of another trace, keeping the minimum per pc
+    pub fn merge(&mut self, other: &Self) {
+        for (pc, distance) in other.distances.iter() {
+            self.record(*pc, *distance);
+        }
+        self.errors.extend_from_slice(&other.errors);
+    }
+
+    /// Pcs at which `other` got closer than `self`, or which `self` never reached
+    pub fn improved_by(&self, other: &Self) -> Vec<u64> {
+        other
+            .distances
+            .iter()
+            .filter(|(pc, distance)| {
+                self.distances
+                    .get(pc)
+                    .is_none_or(|minimum| **distance < *minimum)
+            })
+            .map(|(pc, _distance)| *pc)
+            .collect()
+    }
+}
@@ -0,0 +1,214 @@
+//! Compatibility of programs with the SBPF versions
+//!
+//! The same instruction bytes do not necessarily mean the same in all [SBPFVersion]s: Opcodes
+//! were removed (e.g. `lddw`), reassigned (e.g. the memory instruction classes) or changed
+//! their semantics (e.g. `sub` with an immediate). [Executable::compatibility_report] checks
+//! which versions run the text section of a program exactly as the version it was loaded with.
+
+use crate::{
+    ebpf,
+    elf::Executable,
+    opcode_table::{opcode_info, InstructionClass, OpcodeInfo},
+    program::SBPFVersion,
+    vm::ContextObject,
+};
+use std::ops::RangeInclusive;
+
+/// All versions which can be enabled, in ascending order
+pub const SBPF_VERSIONS: [SBPFVersion; 5] = [
+    SBPFVersion::V0,
+    SBPFVersion::V1,
+    SBPFVersion::V2,
+    SBPFVersion::V3,
+    SBPFVersion::V4,
+];
+
+/// Why a program can not run under an [SBPFVersion]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Incompatibility {
+    /// The ELF file uses the legacy layout but the version requires the stricter one, or vice versa
+    ElfFormat,
+    /// The opcode of the instruction at `pc` is not available
+    UnsupportedOpcode {
+        /// Instruction index
+        pc: usize,
+        /// Operation code
+        opcode: u8,
+    },
+    /// The opcode of the instruction at `pc` encodes another instruction
+    ChangedOpcode {
+        /// Instruction index
+        pc: usize,
+        /// Operation code
+        opcode: u8,
+    },
+    /// The instruction at `pc` has the same mnemonic but behaves differently
+    ChangedSemantics {
+        /// Instruction index
+        pc: usize,
+        /// Operation code
+        opcode: u8,
+    },
+}
+
+/// Result of [Executable::compatibility_report]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatibilityReport {
+    /// Version the executable was loaded with
+    pub sbpf_version: SBPFVersion,
+    /// Whether the program contains `lddw`
+    pub uses_lddw: bool,
+    /// Whether the program contains product, quotient or remainder instructions (SIMD-0174)
+    pub uses_pqr: bool,
+    /// Whether the program contains `syscall` instructions (SIMD-0178)
+    pub uses_static_syscalls: bool,
+    /// Whether the program contains memory instructions in the moved classes (SIMD-0173)
+    pub uses_moved_memory_classes: bool,
+    /// Reasons per version, empty for the compatible versions
+    pub incompatibilities: Vec<(SBPFVersion, Vec<Incompatibility>)>,
+}
+
+impl CompatibilityReport {
+    /// Versions under which the program behaves the same
+    pub fn compatible_versions(&self) -> Vec<SBPFVersion> {
+        self.incompatibilities
+            .iter()
+            .filter(|(_version, reasons)| reasons.is_empty())
+            .map(|(version, _reasons)| *version)
+            .collect()
+    }
+
+    /// Largest range of compatible versions containing the one the executable was loaded with
+    ///
+    /// Suitable for [Config::enabled_sbpf_versions](crate::vm::Config::enabled_sbpf_versions).
+    pub fn enabled_sbpf_versions(&self) -> Option<RangeInclusive<SBPFVersion>> {
+        let compatible = |version: &SBPFVersion| {
+            self.incompatibilities
+                .iter()
+                .any(|(other, reasons)| other == version && reasons.is_empty())
+        };
+        let index = SBPF_VERSIONS
+            .iter()
+            .position(|version| *version == self.sbpf_version)
+            .filter(|index| compatible(&SBPF_VERSIONS[*index]))?;
+        let start = SBPF_VERSIONS[..index]
+            .iter()
+            .rev()
+            .take_while(|version| compatible(version))
+            .last()
+            .unwrap_or(&self.sbpf_version);
+        let end = SBPF_VERSIONS[index..]
+            .iter()
+            .take_while(|version| compatible(version))
+            .last()
+            .unwrap_or(&self.sbpf_version);
+        Some(*start..=*end)
+    }
+}
+
+/// Whether the instruction behaves differently under the two versions, given it has the same
+/// mnemonic in both
+///
+/// Calls of functions (as opposed to syscalls) also change when the callee gets a fixed stack
+/// frame in one version and has to allocate it dynamically in the other.
+fn changed_semantics(
+    insn: &ebpf::Insn,
+    info: &OpcodeInfo,
+    is_syscall: bool,
+    a: SBPFVersion,
+    b: SBPFVersion,
+) -> bool {
+    let changed_frames = a.dynamic_stack_frames() != b.dynamic_stack_frames();
+    match insn.opc {
+        ebpf::SUB32_IMM | ebpf::SUB64_IMM => {
+            a.swap_sub_reg_imm_operands() != b.swap_sub_reg_imm_operands()
+        }
+        ebpf::CALL_IMM => {
+            a.static_syscalls() != b.static_syscalls() || (!is_syscall && changed_frames)
+        }
+        ebpf::CALL_REG => a.callx_uses_src_reg() != b.callx_uses_src_reg() || changed_frames,
+        ebpf::ADD64_IMM if insn.dst == ebpf::FRAME_PTR_REG as u8 => changed_frames,
+        _ if info.class == InstructionClass::Alu && info.width == 4 => {
+            a.explicit_sign_extension_of_results() != b.explicit_sign_extension_of_results()
+        }
+        _ => false,
+    }
+}
+
+impl<C: ContextObject> Executable<C> {
+    /// Checks which versions run the program the same as the version it was loaded with
+    pub fn compatibility_report(&self) -> CompatibilityReport {
+        let sbpf_version = self.get_sbpf_version();
+        let (_vm_addr, text) = self.get_text_bytes();
+        let is_elf = self.is_elf();
+        let mut report = CompatibilityReport {
+            sbpf_version,
+            uses_lddw: false,
+            uses_pqr: false,
+            uses_static_syscalls: false,
+            uses_moved_memory_classes: false,
+            incompatibilities: SBPF_VERSIONS
+                .iter()
+                .map(|version| {
+                    let reasons = if is_elf
+                        && version.enable_stricter_elf_headers()
+                            != sbpf_version.enable_stricter_elf_headers()
+                    {
+                        vec![Incompatibility::ElfFormat]
+                    } else {
+                        Vec::new()
+                    };
+                    (*version, reasons)
+                })
+                .collect(),
+        };
+        let mut pc = 0usize;
+        while (pc.saturating_add(1)).saturating_mul(ebpf::INSN_SIZE) <= text.len() {
+            let insn = ebpf::get_insn(text, pc);
+            let info = opcode_info(insn.opc, sbpf_version);
+            // Before static syscalls, `call` resolves the key of a registered syscall first
+            let is_syscall = insn.opc == ebpf::CALL_IMM
+                && !sbpf_version.static_syscalls()
+                && self
+                    .get_loader()
+                    .get_function_registry()
+                    .lookup_by_key(insn.imm as u32)
+                    .is_some();
+            if let Some(info) = info {
+                match info.class {
+                    InstructionClass::LoadImmediate => report.uses_lddw = true,
+                    InstructionClass::Product => report.uses_pqr = true,
+                    InstructionClass::Syscall => report.uses_static_syscalls = true,
+                    _ if info.is_memory_access() => {
+                        report.uses_moved_memory_classes |=
+                            sbpf_version.move_memory_instruction_classes();
+                    }
+                    _ => {}
+                }
+            }
+            for (version, reasons) in report.incompatibilities.iter_mut() {
+                let opcode = insn.opc;
+                match (info, opcode_info(opcode, *version)) {
+                    (_, None) => reasons.push(Incompatibility::UnsupportedOpcode { pc, opcode }),
+                    (Some(a), Some(b)) if a.mnemonic != b.mnemonic => {
+                        reasons.push(Incompatibility::ChangedOpcode { pc, opcode })
+                    }
+                    (Some(a), Some(_))
+                        if changed_semantics(&insn, a, is_syscall, sbpf_version, *version) =>
+                    {
+                        reasons.push(Incompatibility::ChangedSemantics { pc, opcode })
+                    }
+                    _ => {}
+                }
+            }
+            pc = pc.saturating_add(
+                if info.is_some_and(|info| info.class == InstructionClass::LoadImmediate) {
+                    2
+                } else {
+                    1
+                },
+            );
+        }
+        report
+    }
+}
@@ -0,0 +1,674 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Conformance suite of the instruction set
+//!
+//! Exercises every opcode of the [OPCODE_TABLE] in every [SBPFVersion] which accepts it and
+//! compares the results of the interpreter and (if available) the JIT with the expected ones.
+//! It also checks that the verifier rejects all opcodes which a version does not accept.
+//! Forks which modify the execution engines, e.g. to add instrumentation, can run
+//! [run_conformance_suite] to find out whether they changed the semantics of the ISA.
+
+use crate::{
+    declare_builtin_function, ebpf,
+    elf::Executable,
+    error::ProgramResult,
+    harness::execute_with_input,
+    memory_region::MemoryMapping,
+    opcode_table::{opcode_info, InstructionClass, OperandSource, OPCODE_TABLE},
+    program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
+    verifier::RequisiteVerifier,
+    vm::{Config, ContextObject},
+};
+use std::{collections::BTreeSet, sync::Arc};
+
+/// Initial value of the destination register of arithmetic cases
+pub const DST_OPERAND: u64 = 0x8765_4321_f000_0005;
+/// Value of the source register and the immediate of arithmetic cases, except `le` and `be`
+pub const SRC_OPERAND: u64 = 3;
+
+/// Input region of the memory cases
+const INPUT: [u8; 16] = [
+    0x01, 0x82, 0x03, 0x84, 0x05, 0x86, 0x07, 0x88, 0x09, 0x8a, 0x0b, 0x8c, 0x0d, 0x8e, 0x0f, 0x90,
+];
+
+/// Expected result of a [ConformanceCase]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expectation {
+    /// The program returns the value
+    Return(u64),
+    /// The program throws an error whose debug representation starts with the given name
+    Error(&'static str),
+}
+
+/// A program and its expected result
+#[derive(Debug, Clone)]
+pub struct ConformanceCase {
+    /// Describes what is tested
+    pub name: String,
+    /// Instructions, the entrypoint is the first one
+    pub program: Vec<ebpf::Insn>,
+    /// Pcs of additional functions
+    pub functions: Vec<usize>,
+    /// Initial values of registers
+    pub registers: Vec<(usize, u64)>,
+    /// Expected result
+    pub expected: Expectation,
+}
+
+/// A case which did not produce the expected result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceFailure {
+    /// Version the case was executed in
+    pub sbpf_version: SBPFVersion,
+    /// Name of the case
+    pub case: String,
+    /// "verifier", "interpreter" or "JIT"
+    pub stage: &'static str,
+    /// Debug representation of the expected result
+    pub expected: String,
+    /// Debug representation of the actual result
+    pub actual: String,
+}
+
+/// Outcome of [run_conformance_suite]
+#[derive(Debug, Clone, Default)]
+pub struct ConformanceReport {
+    /// Number of executed cases
+    pub executed_cases: usize,
+    /// Cases which did not produce the expected result
+    pub failures: Vec<ConformanceFailure>,
+    /// Opcodes of the [OPCODE_TABLE] which no case covered
+    pub uncovered_opcodes: Vec<(SBPFVersion, u8)>,
+    /// Opcodes which are not in the [OPCODE_TABLE] but were accepted by the verifier
+    pub accepted_unknown_opcodes: Vec<(SBPFVersion, u8)>,
+}
+
+impl ConformanceReport {
+    /// Whether everything behaved as expected
+    pub fn is_conformant(&self) -> bool {
+        self.failures.is_empty()
+            && self.uncovered_opcodes.is_empty()
+            && self.accepted_unknown_opcodes.is_empty()
+    }
+}
+
+/// Context object of the conformance cases
+#[derive(Debug, Clone, Default)]
+pub struct ConformanceContextObject {
+    /// Remaining instruction budget
+    pub remaining: u64,
+}
+
+impl ContextObject for ConformanceContextObject {
+    fn trace(&mut self, _state: [u64; 12]) {}
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+declare_builtin_function!(
+    /// Returns `arg1 * 3 + arg2`
+    SyscallConformance,
+    fn rust(
+        _context_object: &mut ConformanceContextObject,
+        arg1: u64,
+        arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(arg1.wrapping_mul(3).wrapping_add(arg2))
+    }
+);
+
+/// Name of the syscall available to the conformance cases
+const SYSCALL_NAME: &str = "conformance_syscall";
+
+/// Reference semantics of the arithmetic instructions
+///
+/// `operand` is the source register or the sign extended immediate.
+fn arithmetic(
+    sbpf_version: SBPFVersion,
+    mnemonic: &str,
+    source: OperandSource,
+    dst: u64,
+    operand: u64,
+) -> u64 {
+    // Results of 32 bit additions and subtractions are sign extended until SBPFv2
+    let extend = |value: i32| {
+        if sbpf_version.explicit_sign_extension_of_results() {
+            value as u32 as u64
+        } else {
+            value as i64 as u64
+        }
+    };
+    let immediate = source == OperandSource::Immediate;
+    match mnemonic {
+        "add32" => extend((dst as i32).wrapping_add(operand as i32)),
+        "sub32" if immediate && sbpf_version.swap_sub_reg_imm_operands() => {
+            extend((operand as i32).wrapping_sub(dst as i32))
+        }
+        "sub32" => extend((dst as i32).wrapping_sub(operand as i32)),
+        "mul32" => (dst as i32).wrapping_mul(operand as i32) as u64,
+        "div32" | "udiv32" => (dst as u32 / operand as u32) as u64,
+        "mod32" | "urem32" => (dst as u32 % operand as u32) as u64,
+        "or32" => (dst as u32 | operand as u32) as u64,
+        "and32" => (dst as u32 & operand as u32) as u64,
+        "xor32" => (dst as u32 ^ operand as u32) as u64,
+        "lsh32" => (dst as u32).wrapping_shl(operand as u32) as u64,
+        "rsh32" => (dst as u32).wrapping_shr(operand as u32) as u64,
+        "arsh32" => (dst as i32).wrapping_shr(operand as u32) as u32 as u64,
+        "neg32" => (dst as i32).wrapping_neg() as u32 as u64,
+        "mov32" if immediate => operand as u32 as u64,
+        "mov32" if sbpf_version.explicit_sign_extension_of_results() => {
+            operand as i32 as i64 as u64
+        }
+        "mov32" => operand as u32 as u64,
+        "lmul32" => (dst as u32).wrapping_mul(operand as u32) as u64,
+        "sdiv32" => (dst as i32 / operand as i32) as u32 as u64,
+        "srem32" => (dst as i32 % operand as i32) as u32 as u64,
+        "add64" => dst.wrapping_add(operand),
+        "sub64" if immediate && sbpf_version.swap_sub_reg_imm_operands() => {
+            operand.wrapping_sub(dst)
+        }
+        "sub64" => dst.wrapping_sub(operand),
+        "mul64" | "lmul64" => dst.wrapping_mul(operand),
+        "div64" => dst / operand,
+        "mod64" => dst % operand,
+        "udiv64" if immediate => dst / (operand as u32 as u64),
+        "udiv64" => dst / operand,
+        "urem64" if immediate => dst % (operand as u32 as u64),
+        "urem64" => dst % operand,
+        "or64" => dst | operand,
+        "and64" => dst & operand,
+        "xor64" => dst ^ operand,
+        "lsh64" => dst.wrapping_shl(operand as u32),
+        "rsh64" => dst.wrapping_shr(operand as u32),
+        "arsh64" => (dst as i64).wrapping_shr(operand as u32) as u64,
+        "neg64" => (dst as i64).wrapping_neg() as u64,
+        "mov64" => operand,
+        "hor64" => dst | (operand << 32),
+        "uhmul64" if immediate => ((dst as u128 * operand as u32 as u128) >> 64) as u64,
+        "uhmul64" => ((dst as u128 * operand as u128) >> 64) as u64,
+        "shmul64" => ((dst as i64 as i128 * operand as i64 as i128) >> 64) as u64,
+        "sdiv64" => (dst as i64 / operand as i64) as u64,
+        "srem64" => (dst as i64 % operand as i64) as u64,
+        "le" => match operand {
+            16 => (dst as u16).to_le() as u64,
+            32 => (dst as u32).to_le() as u64,
+            _ => dst.to_le(),
+        },
+        "be" => match operand {
+            16 => (dst as u16).to_be() as u64,
+            32 => (dst as u32).to_be() as u64,
+            _ => dst.to_be(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn insn(opc: u8, dst: u8, src: u8, off: i16, imm: i64) -> ebpf::Insn {
+    ebpf::Insn {
+        ptr: 0,
+        opc,
+        dst,
+        src,
+        off,
+        imm,
+    }
+}
+
+fn exit_insn(sbpf_version: SBPFVersion) -> ebpf::Insn {
+    insn(
+        if sbpf_version.static_syscalls() {
+            ebpf::RETURN
+        } else {
+            ebpf::EXIT
+        },
+        0,
+        0,
+        0,
+        0,
+    )
+}
+
+/// Function start marker of SBPFv3, omitted in versions without it
+fn function_start(sbpf_version: SBPFVersion) -> Vec<ebpf::Insn> {
+    if sbpf_version.enable_stricter_verification() {
+        vec![insn(ebpf::ADD64_IMM, ebpf::FRAME_PTR_REG as u8, 0, 0, 0)]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Wraps the body into the entrypoint function
+fn entrypoint(sbpf_version: SBPFVersion, body: Vec<ebpf::Insn>) -> Vec<ebpf::Insn> {
+    let mut program = function_start(sbpf_version);
+    program.extend(body);
+    program.push(exit_insn(sbpf_version));
+    program
+}
+
+fn find_opcode(sbpf_version: SBPFVersion, mnemonic: &str, source: OperandSource) -> u8 {
+    OPCODE_TABLE
+        .iter()
+        .find(|info| {
+            info.mnemonic == mnemonic && info.source == source && (info.available)(sbpf_version)
+        })
+        .map(|info| info.opcode)
+        .unwrap_or_else(|| panic!("{} is not available in {:?}", mnemonic, sbpf_version))
+}
+
+fn compare(mnemonic: &str, dst: u64, src: u64) -> bool {
+    match mnemonic {
+        "jeq" => dst == src,
+        "jne" => dst != src,
+        "jgt" => dst > src,
+        "jge" => dst >= src,
+        "jlt" => dst < src,
+        "jle" => dst <= src,
+        "jset" => dst & src != 0,
+        "jsgt" => (dst as i64) > (src as i64),
+        "jsge" => (dst as i64) >= (src as i64),
+        "jslt" => (dst as i64) < (src as i64),
+        "jsle" => (dst as i64) <= (src as i64),
+        _ => unreachable!(),
+    }
+}
+
+fn read_input(offset: usize, len: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes[..len].copy_from_slice(&INPUT[offset..offset.saturating_add(len)]);
+    u64::from_le_bytes(bytes)
+}
+
+/// All cases of a version
+pub fn conformance_cases(sbpf_version: SBPFVersion) -> Vec<ConformanceCase> {
+    let exit = exit_insn(sbpf_version);
+    let mut cases = Vec::new();
+    for info in OPCODE_TABLE
+        .iter()
+        .filter(|info| (info.available)(sbpf_version))
+    {
+        match info.class {
+            InstructionClass::Alu | InstructionClass::Product => {
+                let imm = if matches!(info.mnemonic, "le" | "be") {
+                    32
+                } else {
+                    SRC_OPERAND as i64
+                };
+                let expected = arithmetic(
+                    sbpf_version,
+                    info.mnemonic,
+                    info.source,
+                    DST_OPERAND,
+                    imm as u64,
+                );
+                cases.push(ConformanceCase {
+                    name: format!("{} {:?}", info.mnemonic, info.source),
+                    program: entrypoint(sbpf_version, vec![insn(info.opcode, 0, 2, 0, imm)]),
+                    functions: Vec::new(),
+                    registers: vec![(0, DST_OPERAND), (2, SRC_OPERAND)],
+                    expected: Expectation::Return(expected),
+                });
+            }
+            InstructionClass::LoadImmediate => {
+                let value = 0x1122_3344_5566_7788u64;
+                let mut high = insn(0, 0, 0, 0, (value >> 32) as i64);
+                high.opc = 0;
+                cases.push(ConformanceCase {
+                    name: info.mnemonic.to_string(),
+                    program: entrypoint(
+                        sbpf_version,
+                        vec![insn(info.opcode, 0, 0, 0, value as u32 as i64), high],
+                    ),
+                    functions: Vec::new(),
+                    registers: Vec::new(),
+                    expected: Expectation::Return(value),
+                });
+            }
+            InstructionClass::Load => {
+                let len = info.width as usize;
+                cases.push(ConformanceCase {
+                    name: info.mnemonic.to_string(),
+                    program: entrypoint(sbpf_version, vec![insn(info.opcode, 0, 1, 1, 0)]),
+                    functions: Vec::new(),
+                    registers: vec![(1, ebpf::MM_INPUT_START)],
+                    expected: Expectation::Return(read_input(1, len)),
+                });
+                cases.push(ConformanceCase {
+                    name: format!("{} out of bounds", info.mnemonic),
+                    program: entrypoint(
+                        sbpf_version,
+                        vec![insn(info.opcode, 0, 1, INPUT.len() as i16, 0)],
+                    ),
+                    functions: Vec::new(),
+                    registers: vec![(1, ebpf::MM_INPUT_START)],
+                    expected: Expectation::Error("AccessViolation"),
+                });
+            }
+            InstructionClass::Store => {
+                let len = info.width as usize;
+                let value = if info.source == OperandSource::Register {
+                    0xa1a2_a3a4_a5a6_a7a8u64
+                } else {
+                    -2i64 as u64
+                };
+                let mut memory = INPUT;
+                memory[2..2 + len].copy_from_slice(&value.to_le_bytes()[..len]);
+                let mut expected = [0u8; 8];
+                expected.copy_from_slice(&memory[..8]);
+                cases.push(ConformanceCase {
+                    name: format!("{} {:?}", info.mnemonic, info.source),
+                    program: entrypoint(
+                        sbpf_version,
+                        vec![
+                            insn(info.opcode, 1, 2, 2, value as i64),
+                            insn(
+                                find_opcode(sbpf_version, "ldxdw", OperandSource::Register),
+                                0,
+                                1,
+                                0,
+                                0,
+                            ),
+                        ],
+                    ),
+                    functions: Vec::new(),
+                    registers: vec![(1, ebpf::MM_INPUT_START), (2, value)],
+                    expected: Expectation::Return(u64::from_le_bytes(expected)),
+                });
+            }
+            InstructionClass::Jump => {
+                cases.push(ConformanceCase {
+                    name: info.mnemonic.to_string(),
+                    program: entrypoint(
+                        sbpf_version,
+                        vec![
+                            insn(info.opcode, 0, 0, 1, 0),
+                            exit.clone(),
+                            insn(ebpf::MOV64_IMM, 0, 0, 0, 1),
+                        ],
+                    ),
+                    functions: Vec::new(),
+                    registers: vec![(0, 0)],
+                    expected: Expectation::Return(1),
+                });
+            }
+            InstructionClass::ConditionalJump => {
+                for dst in [SRC_OPERAND - 1, SRC_OPERAND, SRC_OPERAND + 1, u64::MAX] {
+                    cases.push(ConformanceCase {
+                        name: format!("{} {:?} with {:#x}", info.mnemonic, info.source, dst),
+                        program: entrypoint(
+                            sbpf_version,
+                            vec![
+                                insn(info.opcode, 3, 2, 1, SRC_OPERAND as i64),
+                                exit.clone(),
+                                insn(ebpf::MOV64_IMM, 0, 0, 0, 1),
+                            ],
+                        ),
+                        functions: Vec::new(),
+                        registers: vec![(0, 0), (2, SRC_OPERAND), (3, dst)],
+                        expected: Expectation::Return(
+                            compare(info.mnemonic, dst, SRC_OPERAND) as u64
+                        ),
+                    });
+                }
+            }
+            InstructionClass::Call => {
+                let mut program = function_start(sbpf_version);
+                let call_pc = program.len();
+                program.push(insn(0, 0, 0, 0, 0));
+                program.push(exit.clone());
+                let function_pc = program.len();
+                program.extend(function_start(sbpf_version));
+                program.push(insn(ebpf::MOV64_IMM, 0, 0, 0, 7));
+                program.push(exit.clone());
+                program[call_pc] = if info.source == OperandSource::Register {
+                    if sbpf_version.callx_uses_src_reg() {
+                        insn(info.opcode, 0, 2, 0, 0)
+                    } else {
+                        insn(info.opcode, 0, 0, 0, 2)
+                    }
+                } else if sbpf_version.static_syscalls() {
+                    insn(
+                        info.opcode,
+                        0,
+                        0,
+                        0,
+                        function_pc as i64 - call_pc as i64 - 1,
+                    )
+                } else {
+                    insn(info.opcode, 0, 0, 0, function_pc as i64)
+                };
+                let text_vm_addr = if sbpf_version.enable_lower_bytecode_vaddr() {
+                    ebpf::MM_BYTECODE_START
+                } else {
+                    ebpf::MM_RODATA_START
+                };
+                cases.push(ConformanceCase {
+                    name: info.mnemonic.to_string(),
+                    program,
+                    functions: vec![function_pc],
+                    registers: vec![(2, text_vm_addr + (function_pc * ebpf::INSN_SIZE) as u64)],
+                    expected: Expectation::Return(7),
+                });
+            }
+            InstructionClass::Syscall | InstructionClass::Exit => {}
+        }
+    }
+    // Syscalls are encoded as `call` before SBPFv3
+    cases.push(ConformanceCase {
+        name: "syscall".to_string(),
+        program: entrypoint(
+            sbpf_version,
+            vec![insn(
+                if sbpf_version.static_syscalls() {
+                    ebpf::SYSCALL
+                } else {
+                    ebpf::CALL_IMM
+                },
+                0,
+                0,
+                0,
+                ebpf::hash_symbol_name(SYSCALL_NAME.as_bytes()) as i64,
+            )],
+        ),
+        functions: Vec::new(),
+        registers: vec![(1, 5), (2, 4)],
+        expected: Expectation::Return(19),
+    });
+    let (div, rem, sdiv) = if sbpf_version.enable_pqr() {
+        ("udiv64", "urem32", Some("sdiv64"))
+    } else {
+        ("div64", "mod32", None)
+    };
+    for mnemonic in [div, rem] {
+        cases.push(ConformanceCase {
+            name: format!("{mnemonic} by zero"),
+            program: entrypoint(
+                sbpf_version,
+                vec![insn(
+                    find_opcode(sbpf_version, mnemonic, OperandSource::Register),
+                    0,
+                    2,
+                    0,
+                    0,
+                )],
+            ),
+            functions: Vec::new(),
+            registers: vec![(0, DST_OPERAND), (2, 0)],
+            expected: Expectation::Error("DivideByZero"),
+        });
+    }
+    if let Some(mnemonic) = sdiv {
+        cases.push(ConformanceCase {
+            name: format!("{mnemonic} overflow"),
+            program: entrypoint(
+                sbpf_version,
+                vec![insn(
+                    find_opcode(sbpf_version, mnemonic, OperandSource::Register),
+                    0,
+                    2,
+                    0,
+                    0,
+                )],
+            ),
+            functions: Vec::new(),
+            registers: vec![(0, i64::MIN as u64), (2, -1i64 as u64)],
+            expected: Expectation::Error("DivideOverflow"),
+        });
+    }
+    cases
+}
+
+fn to_text_bytes(program: &[ebpf::Insn]) -> Vec<u8> {
+    program.iter().flat_map(|insn| insn.to_array()).collect()
+}
+
+fn loader(sbpf_version: SBPFVersion) -> Arc<BuiltinProgram<ConformanceContextObject>> {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: sbpf_version..=sbpf_version,
+        ..Config::default()
+    });
+    loader
+        .register_function(SYSCALL_NAME, SyscallConformance::vm)
+        .unwrap();
+    Arc::new(loader)
+}
+
+fn execute(
+    executable: &Executable<ConformanceContextObject>,
+    registers: &[(usize, u64)],
+    interpreted: bool,
+) -> ProgramResult {
+    let mut context_object = ConformanceContextObject { remaining: 100 };
+    let mut input = INPUT;
+    execute_with_input(
+        executable,
+        &mut context_object,
+        registers,
+        &mut input,
+        interpreted,
+    )
+}
+
+fn matches_expectation(result: &ProgramResult, expected: &Expectation) -> bool {
+    match (result, expected) {
+        (ProgramResult::Ok(value), Expectation::Return(expected)) => value == expected,
+        (ProgramResult::Err(error), Expectation::Error(name)) => {
+            format!("{error:?}").starts_with(name)
+        }
+        _ => false,
+    }
+}
+
+/// Runs all cases of the given versions
+pub fn run_conformance_suite(sbpf_versions: &[SBPFVersion]) -> ConformanceReport {
+    let mut report = ConformanceReport::default();
+    for sbpf_version in sbpf_versions.iter().copied() {
+        let loader = loader(sbpf_version);
+        let mut covered = BTreeSet::new();
+        for case in conformance_cases(sbpf_version) {
+            report.executed_cases = report.executed_cases.saturating_add(1);
+            let mut skip_next = false;
+            for insn in case.program.iter() {
+                if !std::mem::take(&mut skip_next) {
+                    covered.insert(insn.opc);
+                    skip_next = insn.opc == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw();
+                }
+            }
+            let mut failure = |stage: &'static str, actual: String| {
+                report.failures.push(ConformanceFailure {
+                    sbpf_version,
+                    case: case.name.clone(),
+                    stage,
+                    expected: format!("{:?}", case.expected),
+                    actual,
+                });
+            };
+            let mut function_registry = FunctionRegistry::default();
+            for pc in case.functions.iter() {
+                function_registry
+                    .register_function(*pc as u32, format!("function_{pc}"), *pc)
+                    .unwrap();
+            }
+            #[allow(unused_mut)]
+            let mut executable = match Executable::new_from_text_bytes(
+                &to_text_bytes(&case.program),
+                loader.clone(),
+                sbpf_version,
+                function_registry,
+            ) {
+                Ok(executable) => executable,
+                Err(error) => {
+                    failure("verifier", format!("{error:?}"));
+                    continue;
+                }
+            };
+            if let Err(error) = executable.verify::<RequisiteVerifier>() {
+                failure("verifier", format!("{error:?}"));
+                continue;
+            }
+            let result = execute(&executable, &case.registers, true);
+            if !matches_expectation(&result, &case.expected) {
+                failure("interpreter", format!("{result:?}"));
+            }
+            #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+            {
+                let result = match executable.jit_compile() {
+                    Ok(()) => execute(&executable, &case.registers, false),
+                    Err(error) => ProgramResult::Err(error),
+                };
+                if !matches_expectation(&result, &case.expected) {
+                    failure("JIT", format!("{result:?}"));
+                }
+            }
+        }
+        for info in OPCODE_TABLE
+            .iter()
+            .filter(|info| (info.available)(sbpf_version) && !covered.contains(&info.opcode))
+        {
+            report.uncovered_opcodes.push((sbpf_version, info.opcode));
+        }
+        for opcode in 0..=u8::MAX {
+            if opcode_info(opcode, sbpf_version).is_some() {
+                continue;
+            }
+            let program = entrypoint(sbpf_version, vec![insn(opcode, 0, 0, 0, 0)]);
+            let accepted = Executable::new_from_text_bytes(
+                &to_text_bytes(&program),
+                loader.clone(),
+                sbpf_version,
+                FunctionRegistry::default(),
+            )
+            .is_ok_and(|executable| executable.verify::<RequisiteVerifier>().is_ok());
+            if accepted {
+                report.accepted_unknown_opcodes.push((sbpf_version, opcode));
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_conformance() {
+        let report = run_conformance_suite(&[
+            SBPFVersion::V0,
+            SBPFVersion::V1,
+            SBPFVersion::V2,
+            SBPFVersion::V3,
+            SBPFVersion::V4,
+        ]);
+        assert!(report.executed_cases > 0);
+        assert!(report.is_conformant(), "{:#?}", report);
+    }
+}
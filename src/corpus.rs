@@ -0,0 +1,588 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! On-disk format of fuzzing corpora
+//!
+//! A [CorpusEntry] bundles an input with the metadata needed to interpret it later: The
+//! [InputLayout] it was serialized in, a digest of the coverage it reached, where it was
+//! derived from and the version of this crate which wrote it. Serialized inputs mostly consist
+//! of zeroed realloc padding, so runs of zeros are compressed.
+//!
+//! The [CampaignState] collects everything a fuzzing campaign accumulated besides the
+//! corpus, so it can be resumed after a restart or on another host.
+
+use crate::harness::InputLayout;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    path::Path,
+};
+
+/// Version of the formats, incremented on every incompatible change
+pub const CORPUS_FORMAT_VERSION: u32 = 1;
+
+/// Magic number of serialized [CorpusEntry]s
+const CORPUS_MAGIC: &[u8; 8] = b"SBPFCRPS";
+
+/// Magic number of serialized [CampaignState]s
+const CAMPAIGN_MAGIC: &[u8; 8] = b"SBPFCMPN";
+
+/// Zero runs shorter than this are stored as they are
+const MIN_ZERO_RUN: usize = 16;
+
+/// Error definitions
+#[derive(Debug, thiserror::Error)]
+pub enum CorpusError {
+    /// Reading or writing the file failed
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// Not a file of the expected kind
+    #[error("invalid magic number")]
+    InvalidMagic,
+    /// The entry was written with a different format version
+    #[error("unsupported corpus format version {0}")]
+    UnsupportedFormatVersion(u32),
+    /// The file is truncated or contains invalid values
+    #[error("malformed corpus file")]
+    Malformed,
+    /// The content does not match the checksum it was written with
+    #[error("checksum mismatch")]
+    ChecksumMismatch,
+}
+
+/// How an entry was discovered
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lineage {
+    /// [CorpusEntry::id] of the entry it was mutated from, `None` for seeds
+    pub parent: Option<u64>,
+    /// Number of mutation steps since the seed
+    pub generation: u32,
+    /// Description of the mutation which produced it
+    pub mutation: String,
+}
+
+/// An input of the corpus and its metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorpusEntry {
+    /// Raw bytes of the input region
+    pub input: Vec<u8>,
+    /// Serialization of the accounts in `input`
+    pub layout: InputLayout,
+    /// See [coverage_digest]
+    pub coverage_digest: u64,
+    /// How the entry was discovered
+    pub lineage: Lineage,
+    /// Version of this crate which wrote the entry
+    pub crate_version: String,
+}
+
+/// FNV-1a
+pub(crate) fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Digest of an edge coverage map, e.g. [ForkServer::coverage](crate::fuzz_server::ForkServer)
+///
+/// Hit counts are bucketed like AFL does, so inputs which only differ in loop iteration
+/// counts within the same bucket have the same digest.
+pub fn coverage_digest(coverage: &[u8]) -> u64 {
+    let buckets = coverage
+        .iter()
+        .map(|hits| bucket(*hits))
+        .collect::<Vec<_>>();
+    hash(&buckets)
+}
+
+/// Hit count bucket as used by AFL
+fn bucket(hits: u8) -> u8 {
+    match hits {
+        0..=3 => hits,
+        4..=7 => 4,
+        8..=15 => 5,
+        16..=31 => 6,
+        32..=127 => 7,
+        128..=u8::MAX => 8,
+    }
+}
+
+pub(crate) fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+pub(crate) fn read_varint(reader: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = reader.split_first()?;
+        *reader = rest;
+        value |= u64::from(byte & 0x7f).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+pub(crate) fn take<'a>(reader: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    let head = reader.get(..len)?;
+    *reader = &reader[len..];
+    Some(head)
+}
+
+fn read_u64(reader: &mut &[u8]) -> Option<u64> {
+    let mut value = [0u8; 8];
+    value.copy_from_slice(take(reader, 8)?);
+    Some(u64::from_le_bytes(value))
+}
+
+fn write_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    write_varint(bytes, value.len() as u64);
+    bytes.extend_from_slice(value);
+}
+
+fn read_bytes<'a>(reader: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = read_varint(reader)? as usize;
+    take(reader, len)
+}
+
+/// Checks the magic number and format version, returns the remaining bytes
+fn read_header<'a>(bytes: &'a [u8], magic: &[u8; 8]) -> Result<&'a [u8], CorpusError> {
+    let mut reader = bytes.strip_prefix(magic).ok_or(CorpusError::InvalidMagic)?;
+    let mut format_version = [0u8; 4];
+    format_version.copy_from_slice(take(&mut reader, 4).ok_or(CorpusError::Malformed)?);
+    let format_version = u32::from_le_bytes(format_version);
+    if format_version != CORPUS_FORMAT_VERSION {
+        return Err(CorpusError::UnsupportedFormatVersion(format_version));
+    }
+    Ok(reader)
+}
+
+/// Alternating literal and zero run lengths, each literal followed by its bytes
+fn compress(input: &[u8], bytes: &mut Vec<u8>) {
+    write_varint(bytes, input.len() as u64);
+    let mut offset = 0;
+    while offset < input.len() {
+        // Extend the literal up to the next long enough zero run or the end of the input
+        let mut literal_end = offset;
+        let zero_end = loop {
+            let Some(zero_start) = input[literal_end..]
+                .iter()
+                .position(|byte| *byte == 0)
+                .map(|len| literal_end + len)
+            else {
+                literal_end = input.len();
+                break input.len();
+            };
+            let zero_end = input[zero_start..]
+                .iter()
+                .position(|byte| *byte != 0)
+                .map_or(input.len(), |len| zero_start + len);
+            if zero_end - zero_start >= MIN_ZERO_RUN || zero_end == input.len() {
+                literal_end = zero_start;
+                break zero_end;
+            }
+            literal_end = zero_end;
+        };
+        write_varint(bytes, (literal_end - offset) as u64);
+        bytes.extend_from_slice(&input[offset..literal_end]);
+        write_varint(bytes, (zero_end - literal_end) as u64);
+        offset = zero_end;
+    }
+}
+
+fn decompress(reader: &mut &[u8]) -> Option<Vec<u8>> {
+    let len = read_varint(reader)? as usize;
+    let mut input = Vec::new();
+    while input.len() < len {
+        let literal_len = read_varint(reader)? as usize;
+        input.extend_from_slice(take(reader, literal_len)?);
+        let zero_len = read_varint(reader)? as usize;
+        let end = input
+            .len()
+            .checked_add(zero_len)
+            .filter(|end| *end <= len)?;
+        input.resize(end, 0);
+    }
+    (input.len() == len).then_some(input)
+}
+
+impl CorpusEntry {
+    /// Creates a seed entry written by this version of the crate
+    pub fn new(input: Vec<u8>, layout: InputLayout, coverage_digest: u64) -> Self {
+        Self {
+            input,
+            layout,
+            coverage_digest,
+            lineage: Lineage::default(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Creates an entry derived from this one by `mutation`
+    pub fn derive(&self, input: Vec<u8>, coverage_digest: u64, mutation: &str) -> Self {
+        Self {
+            lineage: Lineage {
+                parent: Some(self.id()),
+                generation: self.lineage.generation.saturating_add(1),
+                mutation: mutation.to_string(),
+            },
+            ..Self::new(input, self.layout, coverage_digest)
+        }
+    }
+
+    /// Identifies the entry by its input, suitable as file name
+    pub fn id(&self) -> u64 {
+        hash(&self.input)
+    }
+
+    /// Serializes the entry
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = CORPUS_MAGIC.to_vec();
+        bytes.extend_from_slice(&CORPUS_FORMAT_VERSION.to_le_bytes());
+        bytes.push(match self.layout {
+            InputLayout::Aligned => 0,
+            InputLayout::Unknown => 1,
+        });
+        bytes.extend_from_slice(&self.coverage_digest.to_le_bytes());
+        match self.lineage.parent {
+            Some(parent) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&parent.to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+        write_varint(&mut bytes, u64::from(self.lineage.generation));
+        for text in [&self.lineage.mutation, &self.crate_version] {
+            write_bytes(&mut bytes, text.as_bytes());
+        }
+        compress(&self.input, &mut bytes);
+        bytes
+    }
+
+    /// Deserializes an entry written by [Self::to_bytes]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CorpusError> {
+        let reader = read_header(bytes, CORPUS_MAGIC)?;
+        let parse = |mut reader: &[u8]| -> Option<Self> {
+            let layout = match take(&mut reader, 1)?[0] {
+                0 => InputLayout::Aligned,
+                1 => InputLayout::Unknown,
+                _ => return None,
+            };
+            let coverage_digest = read_u64(&mut reader)?;
+            let parent = match take(&mut reader, 1)?[0] {
+                0 => None,
+                1 => Some(read_u64(&mut reader)?),
+                _ => return None,
+            };
+            let generation = u32::try_from(read_varint(&mut reader)?).ok()?;
+            let mutation = String::from_utf8(read_bytes(&mut reader)?.to_vec()).ok()?;
+            let crate_version = String::from_utf8(read_bytes(&mut reader)?.to_vec()).ok()?;
+            let input = decompress(&mut reader)?;
+            reader.is_empty().then_some(Self {
+                input,
+                layout,
+                coverage_digest,
+                lineage: Lineage {
+                    parent,
+                    generation,
+                    mutation,
+                },
+                crate_version,
+            })
+        };
+        parse(reader).ok_or(CorpusError::Malformed)
+    }
+
+    /// Writes the serialized entry to a file
+    pub fn save(&self, path: &Path) -> Result<(), CorpusError> {
+        Ok(std::fs::write(path, self.to_bytes())?)
+    }
+
+    /// Reads an entry written by [Self::save]
+    pub fn load(path: &Path) -> Result<Self, CorpusError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+/// Accumulated knowledge of a fuzzing campaign
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CampaignState {
+    /// Highest bucketed hit count per edge over all inputs, see [coverage_digest]
+    pub coverage: Vec<u8>,
+    /// Values worth inserting into inputs, e.g. constants compared against
+    pub dictionary: Vec<Vec<u8>>,
+    /// Input offset => number of mutations at it which changed the coverage
+    pub field_sensitivity: BTreeMap<u64, u64>,
+    /// Signatures of the crashes found so far, to deduplicate new ones
+    pub crash_signatures: BTreeSet<u64>,
+    /// One entry per edge, non zero if its hit count differs between runs of the same input
+    pub stability_mask: Vec<u8>,
+}
+
+impl CampaignState {
+    /// Merges the edge coverage of a run, returns whether a stable edge reached a new bucket
+    pub fn merge_coverage(&mut self, coverage: &[u8]) -> bool {
+        if self.coverage.len() < coverage.len() {
+            self.coverage.resize(coverage.len(), 0);
+        }
+        let mut new_coverage = false;
+        for (edge, hits) in coverage.iter().enumerate() {
+            let bucket = bucket(*hits);
+            if bucket > self.coverage[edge] {
+                self.coverage[edge] = bucket;
+                new_coverage |= self.stability_mask.get(edge).copied().unwrap_or(0) == 0;
+            }
+        }
+        new_coverage
+    }
+
+    /// Serializes the state, protected by a checksum
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        compress(&self.coverage, &mut payload);
+        write_varint(&mut payload, self.dictionary.len() as u64);
+        for token in self.dictionary.iter() {
+            write_bytes(&mut payload, token);
+        }
+        write_varint(&mut payload, self.field_sensitivity.len() as u64);
+        for (offset, count) in self.field_sensitivity.iter() {
+            write_varint(&mut payload, *offset);
+            write_varint(&mut payload, *count);
+        }
+        write_varint(&mut payload, self.crash_signatures.len() as u64);
+        for signature in self.crash_signatures.iter() {
+            payload.extend_from_slice(&signature.to_le_bytes());
+        }
+        compress(&self.stability_mask, &mut payload);
+        let mut bytes = CAMPAIGN_MAGIC.to_vec();
+        bytes.extend_from_slice(&CORPUS_FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&hash(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    /// Deserializes a state written by [Self::to_bytes]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CorpusError> {
+        let mut reader = read_header(bytes, CAMPAIGN_MAGIC)?;
+        let checksum = read_u64(&mut reader).ok_or(CorpusError::Malformed)?;
+        if hash(reader) != checksum {
+            return Err(CorpusError::ChecksumMismatch);
+        }
+        let parse = |mut reader: &[u8]| -> Option<Self> {
+            let coverage = decompress(&mut reader)?;
+            let mut dictionary = Vec::new();
+            for _ in 0..read_varint(&mut reader)? {
+                dictionary.push(read_bytes(&mut reader)?.to_vec());
+            }
+            let mut field_sensitivity = BTreeMap::new();
+            for _ in 0..read_varint(&mut reader)? {
+                field_sensitivity.insert(read_varint(&mut reader)?, read_varint(&mut reader)?);
+            }
+            let mut crash_signatures = BTreeSet::new();
+            for _ in 0..read_varint(&mut reader)? {
+                crash_signatures.insert(read_u64(&mut reader)?);
+            }
+            let stability_mask = decompress(&mut reader)?;
+            reader.is_empty().then_some(Self {
+                coverage,
+                dictionary,
+                field_sensitivity,
+                crash_signatures,
+                stability_mask,
+            })
+        };
+        parse(reader).ok_or(CorpusError::Malformed)
+    }
+
+    /// Writes the serialized state to a file
+    ///
+    /// The state is written to a temporary file first and then renamed,
+    /// so a crash while saving does not destroy the previous state.
+    pub fn save(&self, path: &Path) -> Result<(), CorpusError> {
+        let temporary_path = path.with_extension("tmp");
+        std::fs::write(&temporary_path, self.to_bytes())?;
+        Ok(std::fs::rename(temporary_path, path)?)
+    }
+
+    /// Reads a state written by [Self::save]
+    pub fn load(path: &Path) -> Result<Self, CorpusError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solana_input::{AccountDescription, InputBuilder};
+
+    #[test]
+    fn test_corpus_entry() {
+        let input = InputBuilder::default()
+            .account(AccountDescription {
+                key: [1; 32],
+                data: vec![0, 0, 3],
+                ..AccountDescription::default()
+            })
+            .instruction_data(&[0; 17])
+            .build()
+            .input
+            .as_slice()
+            .to_vec();
+        let seed = CorpusEntry::new(
+            input.clone(),
+            InputLayout::Aligned,
+            coverage_digest(&[1, 0]),
+        );
+        let mut mutated = input;
+        mutated[200] = 0xff;
+        let entry = seed.derive(mutated, coverage_digest(&[1, 2]), "flip byte 200");
+        assert_eq!(entry.lineage.parent, Some(seed.id()));
+        assert_eq!(entry.lineage.generation, 1);
+        assert_eq!(entry.crate_version, env!("CARGO_PKG_VERSION"));
+
+        let bytes = entry.to_bytes();
+        // The realloc padding is compressed
+        assert!(bytes.len() < 512);
+        assert_eq!(CorpusEntry::from_bytes(&bytes).unwrap(), entry);
+        let seed_bytes = seed.to_bytes();
+        assert_eq!(CorpusEntry::from_bytes(&seed_bytes).unwrap(), seed);
+        for input in [vec![], vec![0; 3], vec![0; 40], vec![1, 0, 2], vec![5; 40]] {
+            let entry = CorpusEntry::new(input, InputLayout::Unknown, 0);
+            assert_eq!(CorpusEntry::from_bytes(&entry.to_bytes()).unwrap(), entry);
+        }
+
+        // Loop iterations within the same bucket do not change the digest
+        assert_eq!(coverage_digest(&[1, 5]), coverage_digest(&[1, 7]));
+        assert_ne!(coverage_digest(&[1, 5]), coverage_digest(&[1, 8]));
+
+        assert!(matches!(
+            CorpusEntry::from_bytes(&bytes[1..]),
+            Err(CorpusError::InvalidMagic)
+        ));
+        let mut outdated = bytes.clone();
+        outdated[8] = 0;
+        assert!(matches!(
+            CorpusEntry::from_bytes(&outdated),
+            Err(CorpusError::UnsupportedFormatVersion(0))
+        ));
+        assert!(matches!(
+            CorpusEntry::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(CorpusError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_corpus_entry_file() {
+        let entry = CorpusEntry::new(vec![1, 2, 3], InputLayout::Aligned, 42).derive(
+            vec![0; 100],
+            43,
+            "zero fill",
+        );
+        let path = std::env::temp_dir().join(format!("sbpf_corpus_{}", std::process::id()));
+        entry.save(&path).unwrap();
+        assert_eq!(CorpusEntry::load(&path).unwrap(), entry);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(CorpusEntry::load(&path), Err(CorpusError::Io(_))));
+
+        let bytes = entry.to_bytes();
+        // The byte after the magic number and the format version encodes the layout
+        assert_eq!(bytes[12], 0);
+        let mut unknown_layout = bytes.clone();
+        unknown_layout[12] = 2;
+        assert!(matches!(
+            CorpusEntry::from_bytes(&unknown_layout),
+            Err(CorpusError::Malformed)
+        ));
+        let mut newer = bytes;
+        newer[8..12].copy_from_slice(&(CORPUS_FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            CorpusEntry::from_bytes(&newer),
+            Err(CorpusError::UnsupportedFormatVersion(version)) if version == CORPUS_FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_campaign_state() {
+        let mut state = CampaignState {
+            stability_mask: vec![0, 0, 1],
+            ..CampaignState::default()
+        };
+        assert!(state.merge_coverage(&[1, 0, 0]));
+        assert!(!state.merge_coverage(&[1, 0, 0]));
+        // Unstable edges do not count as new coverage
+        assert!(!state.merge_coverage(&[1, 0, 9]));
+        assert!(state.merge_coverage(&[1, 40, 9]));
+        assert_eq!(state.coverage, vec![1, 7, 5]);
+        state.dictionary.push(b"\x2a\0\0\0".to_vec());
+        state.field_sensitivity.insert(96, 3);
+        state.crash_signatures.insert(0xdead);
+
+        let path = std::env::temp_dir().join(format!("sbpf_campaign_{}", std::process::id()));
+        state.save(&path).unwrap();
+        assert_eq!(CampaignState::load(&path).unwrap(), state);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut bytes = state.to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(matches!(
+            CampaignState::from_bytes(&bytes),
+            Err(CorpusError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            CampaignState::from_bytes(
+                &CorpusEntry::new(vec![], InputLayout::Unknown, 0).to_bytes()
+            ),
+            Err(CorpusError::InvalidMagic)
+        ));
+    }
+
+    #[test]
+    fn test_campaign_state_file() {
+        let mut state = CampaignState {
+            coverage: vec![0; 1 << 16],
+            dictionary: vec![b"SBPF".to_vec(), vec![]],
+            field_sensitivity: BTreeMap::from([(0, 1), (u64::MAX, u64::MAX)]),
+            crash_signatures: BTreeSet::from([1, u64::MAX]),
+            stability_mask: vec![0; 1 << 16],
+        };
+        state.coverage[1000] = 3;
+        state.stability_mask[2000] = 1;
+        let path = std::env::temp_dir().join(format!("sbpf_bundle_{}", std::process::id()));
+        state.save(&path).unwrap();
+        // The zeroed maps are compressed and the temporary file is renamed
+        assert!(std::fs::metadata(&path).unwrap().len() < 256);
+        assert!(!path.with_extension("tmp").exists());
+        assert_eq!(CampaignState::load(&path).unwrap(), state);
+
+        let bytes = std::fs::read(&path).unwrap();
+        for corrupted_byte in [20, 21, bytes.len() / 2, bytes.len() - 1] {
+            let mut corrupted = bytes.clone();
+            corrupted[corrupted_byte] ^= 0x80;
+            std::fs::write(&path, &corrupted).unwrap();
+            assert!(matches!(
+                CampaignState::load(&path),
+                Err(CorpusError::ChecksumMismatch)
+            ));
+        }
+        // The checksum itself is corrupted
+        let mut corrupted = bytes.clone();
+        corrupted[12] ^= 1;
+        assert!(matches!(
+            CampaignState::from_bytes(&corrupted),
+            Err(CorpusError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            CampaignState::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(CorpusError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            CampaignState::from_bytes(&bytes[..16]),
+            Err(CorpusError::Malformed)
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
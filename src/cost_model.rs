@@ -0,0 +1,62 @@
+//! Compute unit prices of instructions and syscalls
+//!
+//! By default the instruction meter charges one unit per instruction. A [CostModel] in
+//! [Config::cost_model](crate::vm::Config::cost_model) prices opcodes individually and adds
+//! a base cost to every syscall, e.g. to match the compute units the Solana runtime charges.
+//! The charged units reach [ContextObject::consume](crate::vm::ContextObject::consume) like
+//! the ones of the default meter, so the consumption reported per input, e.g. by
+//! [Harness::instruction_count](crate::harness::Harness::instruction_count), is the priced
+//! one. Only the interpreter supports a cost model, the JIT refuses to compile or run it with
+//! [EbpfError::UnsupportedCostModel](crate::error::EbpfError::UnsupportedCostModel).
+
+use crate::ebpf;
+use std::collections::BTreeMap;
+
+/// Compute unit prices of instructions and syscalls
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CostModel {
+    /// Cost of the instructions whose opcode has no price of its own
+    pub default_instruction_cost: u64,
+    /// Opcode => cost of an instruction
+    pub opcode_costs: BTreeMap<u8, u64>,
+    /// Hash of the syscall name => cost charged in addition to the calling instruction
+    pub syscall_costs: BTreeMap<u32, u64>,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            default_instruction_cost: 1,
+            opcode_costs: BTreeMap::new(),
+            syscall_costs: BTreeMap::new(),
+        }
+    }
+}
+
+impl CostModel {
+    /// Prices the instructions with the given opcode
+    pub fn with_opcode_cost(mut self, opcode: u8, cost: u64) -> Self {
+        self.opcode_costs.insert(opcode, cost);
+        self
+    }
+
+    /// Prices the calls of the syscall with the given name
+    pub fn with_syscall_cost(mut self, name: &str, cost: u64) -> Self {
+        self.syscall_costs
+            .insert(ebpf::hash_symbol_name(name.as_bytes()), cost);
+        self
+    }
+
+    /// Cost of an instruction
+    pub fn instruction_cost(&self, opcode: u8) -> u64 {
+        self.opcode_costs
+            .get(&opcode)
+            .copied()
+            .unwrap_or(self.default_instruction_cost)
+    }
+
+    /// Cost of a syscall in addition to the calling instruction
+    pub fn syscall_cost(&self, hash: u32) -> u64 {
+        self.syscall_costs.get(&hash).copied().unwrap_or(0)
+    }
+}
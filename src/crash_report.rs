@@ -0,0 +1,162 @@
+//! Structured reports of failed executions
+//!
+//! A [CrashReport] combines the [EbpfError] of a failed execution with the state of the VM
+//! at that point: The symbolized call stack and, for memory errors, the address and the
+//! account field it belongs to. Its [signature](CrashReport::signature) only depends on the
+//! kind of error and the innermost frames, so the same bug reached through different inputs
+//! (or different payloads of the error) is deduplicated across fuzzing campaigns, e.g. in
+//! [CampaignState::crash_signatures](crate::corpus::CampaignState::crash_signatures).
+//!
+//! The VM does not track the input taint, but [CrashReport::with_taint] adds the taint of the
+//! faulting address from a replay of the trace, so the report shows whether the bad pointer
+//! was controlled by the input.
+
+use crate::{
+    accounts::{AccountField, AccountLayout},
+    corpus::hash,
+    ebpf,
+    elf::Executable,
+    error::EbpfError,
+    taint::{InputOffsets, InputTaint},
+    vm::{ContextObject, EbpfVm, StackFrame},
+};
+
+/// Number of innermost frames which make up the [signature](CrashReport::signature)
+pub const SIGNATURE_FRAMES: usize = 3;
+
+/// A failed execution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashReport {
+    /// Name of the [EbpfError] variant, e.g. `AccessViolation`
+    pub kind: String,
+    /// The error message
+    pub message: String,
+    /// Symbolized guest call stack, innermost frame first
+    pub call_stack: Vec<StackFrame>,
+    /// Address accessed by a failed load or store
+    pub vm_addr: Option<u64>,
+    /// Account index and field of the input at `vm_addr`
+    pub account_field: Option<(Option<usize>, AccountField)>,
+    /// Input bytes each byte of the register holding the base of `vm_addr` was derived from,
+    /// see [Self::with_taint]
+    pub address_labels: [Option<InputOffsets>; 8],
+    /// Deduplication hash of `kind` and the innermost [SIGNATURE_FRAMES] frames
+    pub signature: u64,
+}
+
+impl CrashReport {
+    /// Captures the state of a VM whose execution just failed with `error`
+    ///
+    /// `layout` describes the input, so that accesses to it can be attributed to accounts.
+    pub fn new<C: ContextObject>(
+        vm: &EbpfVm<C>,
+        executable: &Executable<C>,
+        error: &EbpfError,
+        layout: Option<&AccountLayout>,
+    ) -> Self {
+        let kind = format!("{error:?}")
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let call_stack = vm.call_stack(executable, vm.registers[11]);
+        let vm_addr = match error {
+            EbpfError::AccessViolation(_, vm_addr, _, _)
+            | EbpfError::StackAccessViolation(_, vm_addr, _, _)
+            | EbpfError::HeapPoisonAccess(_, vm_addr, _, _)
+            | EbpfError::UninitializedRead(vm_addr, _) => Some(*vm_addr),
+            _ => None,
+        };
+        let account_field = vm_addr
+            .and_then(|vm_addr| vm_addr.checked_sub(ebpf::MM_INPUT_START))
+            .zip(layout)
+            .and_then(|(offset, layout)| layout.lookup(offset as usize));
+        let signature = Self::signature_of(&kind, &call_stack);
+        Self {
+            kind,
+            message: error.to_string(),
+            call_stack,
+            vm_addr,
+            account_field,
+            address_labels: Default::default(),
+            signature,
+        }
+    }
+
+    /// Adds the taint of the faulting address from the [InputTaint] of the trace log of the
+    /// failed execution
+    ///
+    /// Only memory errors of loads and stores have an address. Those of syscalls are not
+    /// attributed.
+    pub fn with_taint(mut self, taint: &InputTaint) -> Self {
+        if self.vm_addr.is_some() {
+            self.address_labels = taint.last_address.clone();
+        }
+        self
+    }
+
+    /// Input bytes the faulting address was derived from, `None` if it is not input derived
+    pub fn address_taint(&self) -> Option<InputOffsets> {
+        let labels = self.address_labels.iter().flatten();
+        let start = labels.clone().map(|offsets| offsets.start).min()?;
+        let end = labels.map(|offsets| offsets.end).max()?;
+        Some(start..end)
+    }
+
+    /// Deduplication hash of an error kind and a call stack
+    ///
+    /// Frames in known functions are hashed by name and offset into the function, so the
+    /// signature survives unrelated changes which move the function.
+    pub fn signature_of(kind: &str, call_stack: &[StackFrame]) -> u64 {
+        let mut bytes = kind.as_bytes().to_vec();
+        for frame in call_stack.iter().take(SIGNATURE_FRAMES) {
+            bytes.push(0);
+            match &frame.function {
+                Some((function_pc, name)) => {
+                    bytes.extend_from_slice(name.as_bytes());
+                    bytes.extend_from_slice(
+                        &frame.pc.saturating_sub(*function_pc as u64).to_le_bytes(),
+                    );
+                }
+                None => bytes.extend_from_slice(&frame.pc.to_le_bytes()),
+            }
+        }
+        hash(&bytes)
+    }
+
+    /// Name of the `InstructionError` the runtime reports for this crash
+    pub fn instruction_error(&self) -> &'static str {
+        match self.kind.as_str() {
+            "ExceededMaxInstructions" => "ComputationalBudgetExceeded",
+            "CallDepthExceeded" => "CallDepth",
+            _ => "ProgramFailedToComplete",
+        }
+    }
+}
+
+impl std::fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} [{:016x}]: {}",
+            self.kind, self.signature, self.message
+        )?;
+        if let Some((account, field)) = &self.account_field {
+            match account {
+                Some(account) => writeln!(f, "  in {field:?} of account {account}")?,
+                None => writeln!(f, "  in {field:?}")?,
+            }
+        }
+        if let Some(offsets) = self.address_taint() {
+            writeln!(
+                f,
+                "  address derived from input[{}..{}]",
+                offsets.start, offsets.end
+            )?;
+        }
+        for frame in self.call_stack.iter() {
+            writeln!(f, "  at {frame}")?;
+        }
+        Ok(())
+    }
+}
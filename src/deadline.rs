@@ -0,0 +1,62 @@
+//! Wall-clock limit of the interpreter
+//!
+//! The instruction meter bounds the number of instructions, but not how long they take: Some
+//! legal inputs, e.g. with many expensive syscalls, run for a long time within a generous
+//! budget. When [crate::vm::EbpfVm::deadline] is set, the interpreter reads the clock every
+//! [Deadline::check_interval] instructions and fails the execution with
+//! [EbpfError::Timeout] once the deadline has passed, so fuzzing campaigns can not hang on
+//! such inputs.
+//!
+//! The JIT does not read the clock, so the deadline only works in the interpreter.
+
+use crate::error::EbpfError;
+use std::time::{Duration, Instant};
+
+/// Point in time after which executions fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline {
+    /// When executions start to fail
+    pub at: Instant,
+    /// Number of instructions between two reads of the clock
+    pub check_interval: u64,
+    /// Instructions until the next read of the clock
+    countdown: u64,
+}
+
+impl Deadline {
+    /// Creates a deadline at `at`, checked every `check_interval` instructions
+    pub fn new(at: Instant, check_interval: u64) -> Self {
+        Self {
+            at,
+            check_interval,
+            countdown: 0,
+        }
+    }
+
+    /// Creates a deadline `timeout` from now, checked every `check_interval` instructions
+    ///
+    /// Returns `None` if the point in time can not be represented, i.e. there is no deadline.
+    pub fn after(timeout: Duration, check_interval: u64) -> Option<Self> {
+        Instant::now()
+            .checked_add(timeout)
+            .map(|at| Self::new(at, check_interval))
+    }
+
+    /// Time left until the deadline, zero once it has passed
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+
+    /// Counts an instruction, reading the clock if it is due
+    pub fn check(&mut self) -> Result<(), EbpfError> {
+        if let Some(countdown) = self.countdown.checked_sub(1) {
+            self.countdown = countdown;
+            return Ok(());
+        }
+        self.countdown = self.check_interval.saturating_sub(1);
+        if Instant::now() >= self.at {
+            return Err(EbpfError::Timeout);
+        }
+        Ok(())
+    }
+}
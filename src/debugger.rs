@@ -1,4 +1,8 @@
 //! Debugger for the virtual machines' interpreter.
+//!
+//! Reverse execution (`reverse-stepi` and `reverse-continue`) replays the registers of the
+//! last [MAX_HISTORY_LEN] instructions. The memory is not rewound, it keeps the contents of the
+//! most recently executed instruction.
 
 use std::net::{TcpListener, TcpStream};
 
@@ -17,11 +21,14 @@ use core::convert::TryInto;
 use bpf_arch::reg::id::BpfRegId;
 use bpf_arch::reg::BpfRegs;
 use bpf_arch::Bpf;
+use gdbstub::target::ext::base::reverse_exec::{ReplayLogPosition, ReverseCont, ReverseStep};
 use gdbstub::target::ext::base::singlethread::{SingleThreadBase, SingleThreadResume};
 use gdbstub::target::ext::lldb_register_info_override::{Callback, CallbackToken};
+use gdbstub::target::ext::monitor_cmd::{outputln, ConsoleOutput};
 use gdbstub::target::ext::section_offsets::Offsets;
 
 use crate::{
+    accounts::AccountLayout,
     ebpf,
     error::{EbpfError, ProgramResult},
     interpreter::{DebugState, Interpreter},
@@ -31,6 +38,9 @@ use crate::{
 
 type DynResult<T> = Result<T, Box<dyn std::error::Error>>;
 
+/// Maximum number of instructions whose registers are kept for reverse execution
+pub const MAX_HISTORY_LEN: usize = 1 << 16;
+
 fn wait_for_tcp(port: u16) -> DynResult<TcpStream> {
     let sockaddr = format!("127.0.0.1:{}", port);
     eprintln!("Waiting for a Debugger connection on {:?}...", sockaddr);
@@ -72,43 +82,44 @@ pub fn execute<C: ContextObject>(interpreter: &mut Interpreter<C>, port: u16) {
                 let conn = dbg_inner.borrow_conn();
                 match interpreter.debug_state {
                     DebugState::Step => {
-                        let mut stop_reason = if interpreter.step() {
+                        let mut stop_reason = if step_forward(interpreter) {
                             SingleThreadStopReason::DoneStep
-                        } else if let ProgramResult::Ok(result) = &interpreter.vm.program_result {
-                            SingleThreadStopReason::Exited(*result as u8)
                         } else {
-                            SingleThreadStopReason::Terminated(Signal::SIGSTOP)
+                            termination_stop_reason(interpreter)
                         };
                         if interpreter.breakpoints.contains(&interpreter.get_dbg_pc()) {
                             stop_reason = SingleThreadStopReason::SwBreak(());
                         }
                         dbg_inner.report_stop(interpreter, stop_reason).unwrap()
                     }
+                    DebugState::ReverseStep => {
+                        let stop_reason =
+                            reverse_step(interpreter).unwrap_or(SingleThreadStopReason::DoneStep);
+                        dbg_inner.report_stop(interpreter, stop_reason).unwrap()
+                    }
                     DebugState::Continue => loop {
                         if conn.peek().unwrap().is_some() {
                             let byte = dbg_inner.borrow_conn().read().unwrap();
                             break dbg_inner.incoming_data(interpreter, byte).unwrap();
                         }
-                        if interpreter.step() {
+                        if step_forward(interpreter) {
                             if interpreter.breakpoints.contains(&interpreter.get_dbg_pc()) {
                                 break dbg_inner
                                     .report_stop(interpreter, SingleThreadStopReason::SwBreak(()))
                                     .unwrap();
                             }
-                        } else if let ProgramResult::Ok(result) = &interpreter.vm.program_result {
-                            break dbg_inner
-                                .report_stop(
-                                    interpreter,
-                                    SingleThreadStopReason::Exited(*result as u8),
-                                )
-                                .unwrap();
                         } else {
-                            break dbg_inner
-                                .report_stop(
-                                    interpreter,
-                                    SingleThreadStopReason::Terminated(Signal::SIGSTOP),
-                                )
-                                .unwrap();
+                            let stop_reason = termination_stop_reason(interpreter);
+                            break dbg_inner.report_stop(interpreter, stop_reason).unwrap();
+                        }
+                    },
+                    DebugState::ReverseContinue => loop {
+                        if conn.peek().unwrap().is_some() {
+                            let byte = dbg_inner.borrow_conn().read().unwrap();
+                            break dbg_inner.incoming_data(interpreter, byte).unwrap();
+                        }
+                        if let Some(stop_reason) = reverse_step(interpreter) {
+                            break dbg_inner.report_stop(interpreter, stop_reason).unwrap();
                         }
                     },
                 }
@@ -117,6 +128,86 @@ pub fn execute<C: ContextObject>(interpreter: &mut Interpreter<C>, port: u16) {
     }
 }
 
+fn termination_stop_reason<C: ContextObject>(
+    interpreter: &Interpreter<C>,
+) -> SingleThreadStopReason<u64> {
+    if let ProgramResult::Ok(result) = &interpreter.vm.program_result {
+        SingleThreadStopReason::Exited(*result as u8)
+    } else {
+        SingleThreadStopReason::Terminated(Signal::SIGSTOP)
+    }
+}
+
+/// Executes the next instruction or replays it from the history
+///
+/// Returns false if the program terminated or threw an error.
+fn step_forward<C: ContextObject>(interpreter: &mut Interpreter<C>) -> bool {
+    if let Some(position) = interpreter.history_position {
+        // The last entry of the history is the live state
+        let position = position.saturating_add(1);
+        if position < interpreter.history.len() {
+            interpreter.reg = interpreter.history[position];
+            if position.saturating_add(1) == interpreter.history.len() {
+                interpreter.history.pop_back();
+                interpreter.history_position = None;
+            } else {
+                interpreter.history_position = Some(position);
+            }
+            return true;
+        }
+        interpreter.history.pop_back();
+        interpreter.history_position = None;
+    }
+    record_history(interpreter);
+    interpreter.step()
+}
+
+/// Appends the live registers to the history, forgetting the oldest entry if it is full
+fn record_history<C: ContextObject>(interpreter: &mut Interpreter<C>) {
+    if interpreter.history.len() >= MAX_HISTORY_LEN {
+        interpreter.history.pop_front();
+    }
+    interpreter.history.push_back(interpreter.reg);
+}
+
+/// Restores the registers before the previous instruction
+///
+/// Only the registers are rewound, the memory keeps its current contents.
+/// Returns false at the beginning of the history.
+fn step_backward<C: ContextObject>(interpreter: &mut Interpreter<C>) -> bool {
+    let position = match interpreter.history_position {
+        Some(position) => position,
+        None => {
+            record_history(interpreter);
+            interpreter.history.len().saturating_sub(1)
+        }
+    };
+    if position == 0 {
+        interpreter.history_position = Some(position);
+        return false;
+    }
+    let position = position.saturating_sub(1);
+    interpreter.reg = interpreter.history[position];
+    interpreter.history_position = Some(position);
+    true
+}
+
+/// Steps backwards once, returns why to stop or `None` to keep going in reverse
+fn reverse_step<C: ContextObject>(
+    interpreter: &mut Interpreter<C>,
+) -> Option<SingleThreadStopReason<u64>> {
+    if !step_backward(interpreter) {
+        Some(SingleThreadStopReason::ReplayLog {
+            tid: None,
+            pos: ReplayLogPosition::Begin,
+        })
+    } else if interpreter.breakpoints.contains(&interpreter.get_dbg_pc()) {
+        Some(SingleThreadStopReason::SwBreak(()))
+    } else {
+        None
+    }
+}
+
 impl<'a, 'b, C: ContextObject> Target for Interpreter<'a, 'b, C> {
     type Arch = Bpf;
     type Error = &'static str;
@@ -140,6 +231,11 @@ impl<'a, 'b, C: ContextObject> Target for Interpreter<'a, 'b, C> {
         Some(self)
     }
 
+    #[inline(always)]
+    fn support_monitor_cmd(&mut self) -> Option<target::ext::monitor_cmd::MonitorCmdOps<'_, Self>> {
+        Some(self)
+    }
+
     #[inline(always)]
     fn support_lldb_register_info_override(
         &mut self,
@@ -281,6 +377,36 @@ impl<'a, 'b, C: ContextObject> SingleThreadResume for Interpreter<'a, 'b, C> {
     ) -> Option<target::ext::base::singlethread::SingleThreadSingleStepOps<'_, Self>> {
         Some(self)
     }
+
+    #[inline(always)]
+    fn support_reverse_step(
+        &mut self,
+    ) -> Option<target::ext::base::reverse_exec::ReverseStepOps<'_, (), Self>> {
+        Some(self)
+    }
+
+    #[inline(always)]
+    fn support_reverse_cont(
+        &mut self,
+    ) -> Option<target::ext::base::reverse_exec::ReverseContOps<'_, (), Self>> {
+        Some(self)
+    }
+}
+
+impl<'a, 'b, C: ContextObject> ReverseStep<()> for Interpreter<'a, 'b, C> {
+    fn reverse_step(&mut self, _tid: ()) -> Result<(), Self::Error> {
+        self.debug_state = DebugState::ReverseStep;
+
+        Ok(())
+    }
+}
+
+impl<'a, 'b, C: ContextObject> ReverseCont<()> for Interpreter<'a, 'b, C> {
+    fn reverse_cont(&mut self) -> Result<(), Self::Error> {
+        self.debug_state = DebugState::ReverseContinue;
+
+        Ok(())
+    }
 }
 
 impl<'a, 'b, C: ContextObject> target::ext::base::singlethread::SingleThreadSingleStep
@@ -309,6 +435,97 @@ impl<'a, 'b, C: ContextObject> target::ext::section_offsets::SectionOffsets
     }
 }
 
+impl<'a, 'b, C: ContextObject> target::ext::monitor_cmd::MonitorCmd for Interpreter<'a, 'b, C> {
+    fn handle_monitor_cmd(
+        &mut self,
+        cmd: &[u8],
+        mut out: ConsoleOutput<'_>,
+    ) -> Result<(), Self::Error> {
+        let cmd = String::from_utf8_lossy(cmd);
+        let mut args = cmd.split_whitespace();
+        match args.next() {
+            Some("fields") => {
+                let parse = |arg: Option<&str>| {
+                    let arg = arg?;
+                    match arg.strip_prefix("0x") {
+                        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                        None => arg.parse().ok(),
+                    }
+                };
+                match (parse(args.next()), parse(args.next())) {
+                    (Some(vm_addr), Some(len)) => {
+                        annotate_input_fields(self, &mut out, vm_addr, len)
+                    }
+                    _ => outputln!(out, "usage: fields <address> <length>"),
+                }
+            }
+            Some("map") => {
+                for entry in self.vm.address_space_map() {
+                    outputln!(out, "{}", entry);
+                }
+            }
+            _ => {
+                outputln!(out, "map");
+                outputln!(out, "    mapped regions with their permissions and roles");
+                outputln!(out, "fields <address> <length>");
+                outputln!(
+                    out,
+                    "    account and field of the input bytes, assuming the aligned layout"
+                );
+                outputln!(out, "    (taint labels are not tracked by this VM)");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prints the account fields overlapping a range of the input region
+fn annotate_input_fields<C: ContextObject>(
+    interpreter: &mut Interpreter<C>,
+    out: &mut ConsoleOutput<'_>,
+    vm_addr: u64,
+    len: u64,
+) {
+    let input = match interpreter
+        .vm
+        .memory_mapping
+        .find_region(ebpf::MM_INPUT_START)
+    {
+        Some((_index, region)) if region.vm_addr == ebpf::MM_INPUT_START => unsafe {
+            std::slice::from_raw_parts(region.host_addr as *const u8, region.len as usize)
+        },
+        _ => {
+            outputln!(out, "no input region");
+            return;
+        }
+    };
+    let layout = match AccountLayout::parse_aligned(input) {
+        Some(layout) => layout,
+        None => {
+            outputln!(out, "input is not in the aligned layout");
+            return;
+        }
+    };
+    let start = vm_addr.saturating_sub(ebpf::MM_INPUT_START) as usize;
+    let end = vm_addr
+        .saturating_add(len)
+        .saturating_sub(ebpf::MM_INPUT_START) as usize;
+    for (range, account, field) in layout.fields_in(start..end) {
+        let owner = match account {
+            Some(account) => format!("account {}", account),
+            None => "instruction".to_string(),
+        };
+        outputln!(
+            out,
+            "{:#x}..{:#x} {} {:?}",
+            ebpf::MM_INPUT_START.saturating_add(range.start as u64),
+            ebpf::MM_INPUT_START.saturating_add(range.end as u64),
+            owner,
+            field
+        );
+    }
+}
+
 impl<'a, 'b, C: ContextObject> target::ext::breakpoints::Breakpoints for Interpreter<'a, 'b, C> {
     #[inline(always)]
     fn support_sw_breakpoint(
@@ -552,3 +769,138 @@ mod bpf_arch {
         }
     }
 }
+
+#[allow(clippy::arithmetic_side_effects)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembler::assemble, conformance::ConformanceContextObject, elf::Executable,
+        memory_region::MemoryMapping, program::BuiltinProgram, vm::EbpfVm,
+    };
+    use std::sync::Arc;
+
+    fn debug<F: FnOnce(&mut Interpreter<ConformanceContextObject>)>(source: &str, debug: F) {
+        let executable: Executable<ConformanceContextObject> =
+            assemble(source, Arc::new(BuiltinProgram::new_mock())).unwrap();
+        let config = executable.get_config();
+        let sbpf_version = executable.get_sbpf_version();
+        let mut context_object = ConformanceContextObject { remaining: 100_000 };
+        let memory_mapping = MemoryMapping::new(Vec::new(), config, sbpf_version).unwrap();
+        let mut vm = EbpfVm::new(
+            executable.get_loader().clone(),
+            sbpf_version,
+            &mut context_object,
+            memory_mapping,
+            0,
+        );
+        vm.registers[11] = executable.get_entrypoint_instruction_offset() as u64;
+        vm.previous_instruction_meter = 100_000;
+        let registers = vm.registers;
+        debug(&mut Interpreter::new(&mut vm, &executable, registers));
+    }
+
+    #[test]
+    fn test_reverse_step() {
+        debug(
+            "
+            mov64 r0, 1
+            add64 r0, 2
+            add64 r0, 3
+            exit",
+            |interpreter| {
+                for _ in 0..3 {
+                    assert!(step_forward(interpreter));
+                }
+                assert_eq!((interpreter.reg[0], interpreter.reg[11]), (6, 3));
+                assert_eq!(reverse_step(interpreter), None);
+                assert_eq!((interpreter.reg[0], interpreter.reg[11]), (3, 2));
+                assert_eq!(reverse_step(interpreter), None);
+                assert_eq!(reverse_step(interpreter), None);
+                assert_eq!((interpreter.reg[0], interpreter.reg[11]), (0, 0));
+                assert_eq!(
+                    reverse_step(interpreter),
+                    Some(SingleThreadStopReason::ReplayLog {
+                        tid: None,
+                        pos: ReplayLogPosition::Begin,
+                    })
+                );
+                // Stepping forward replays the history up to the live state
+                assert!(step_forward(interpreter));
+                assert_eq!((interpreter.reg[0], interpreter.reg[11]), (1, 1));
+                assert!(step_forward(interpreter));
+                assert!(step_forward(interpreter));
+                assert_eq!((interpreter.reg[0], interpreter.reg[11]), (6, 3));
+                assert_eq!(interpreter.history_position, None);
+                assert!(!step_forward(interpreter));
+                assert!(matches!(
+                    interpreter.vm.program_result,
+                    ProgramResult::Ok(6)
+                ));
+            },
+        );
+    }
+
+    #[test]
+    fn test_reverse_continue() {
+        debug(
+            "
+            mov64 r0, 0
+            mov64 r1, 3
+            add64 r0, 1
+            add64 r1, -1
+            jne r1, 0, -3
+            exit",
+            |interpreter| {
+                while interpreter.reg[11] != 5 {
+                    assert!(step_forward(interpreter));
+                }
+                interpreter
+                    .breakpoints
+                    .push(interpreter.get_dbg_pc() - 3 * 8);
+                let mut steps = 0;
+                let stop_reason = loop {
+                    steps += 1;
+                    if let Some(stop_reason) = reverse_step(interpreter) {
+                        break stop_reason;
+                    }
+                };
+                // Stops at the last iteration of the loop body
+                assert_eq!(stop_reason, SingleThreadStopReason::SwBreak(()));
+                assert_eq!(steps, 3);
+                assert_eq!(interpreter.reg[..2], [2, 1]);
+                interpreter.breakpoints.clear();
+                while reverse_step(interpreter).is_none() {}
+                assert_eq!(interpreter.reg[..2], [0, 0]);
+                assert_eq!(interpreter.reg[11], 0);
+            },
+        );
+    }
+
+    #[test]
+    fn test_bounded_history() {
+        debug(
+            "
+            mov64 r1, 40000
+            add64 r1, -1
+            jne r1, 0, -2
+            exit",
+            |interpreter| {
+                while step_forward(interpreter) {}
+                assert!(matches!(
+                    interpreter.vm.program_result,
+                    ProgramResult::Ok(0)
+                ));
+                assert_eq!(interpreter.history.len(), MAX_HISTORY_LEN);
+                let mut steps = 0;
+                while reverse_step(interpreter).is_none() {
+                    steps += 1;
+                }
+                assert_eq!(steps, MAX_HISTORY_LEN - 1);
+                assert_eq!(interpreter.history.len(), MAX_HISTORY_LEN);
+                // The first 7233 iterations of the loop were forgotten
+                assert_eq!((interpreter.reg[1], interpreter.reg[11]), (40000 - 7233, 1));
+            },
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Internal counters of the interpreter, the memory mapping and the taint replay
+//!
+//! These are only meant to localize performance regressions in the VM itself.
+//! They are not part of the consensus relevant execution state.
+
+/// Counters collected by the interpreter
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InterpreterDiagnostics {
+    /// Number of instructions dispatched
+    pub dispatched_instructions: u64,
+    /// Opcode of the previously dispatched instruction
+    last_opcode: Option<u8>,
+    /// Flattened 256 x 256 matrix of (previous opcode, next opcode) pairs
+    ///
+    /// Empty until the first transition is recorded, so that VMs which never dispatch an
+    /// instruction in the interpreter do not pay for it.
+    opcode_transitions: Vec<u64>,
+}
+
+impl InterpreterDiagnostics {
+    /// Records the dispatch of an instruction
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn record_dispatch(&mut self, opcode: u8) {
+        self.dispatched_instructions += 1;
+        if let Some(last_opcode) = self.last_opcode {
+            if self.opcode_transitions.is_empty() {
+                self.opcode_transitions = vec![0; 256 * 256];
+            }
+            self.opcode_transitions[(last_opcode as usize) << 8 | opcode as usize] += 1;
+        }
+        self.last_opcode = Some(opcode);
+    }
+
+    /// Forgets the previous opcode, e.g. between two executions
+    pub fn end_of_execution(&mut self) {
+        self.last_opcode = None;
+    }
+
+    /// How often `next` was dispatched directly after `previous`
+    pub fn opcode_transition_count(&self, previous: u8, next: u8) -> u64 {
+        self.opcode_transitions
+            .get((previous as usize) << 8 | next as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// All observed (previous opcode, next opcode, count) triples
+    pub fn opcode_transitions(&self) -> impl Iterator<Item = (u8, u8, u64)> + '_ {
+        self.opcode_transitions
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(index, count)| ((index >> 8) as u8, index as u8, *count))
+    }
+
+    /// Number of distinct opcode successors per opcode, summed over all opcodes
+    ///
+    /// Serves as a proxy for the dispatch misprediction rate of the host:
+    /// The more successors an opcode has, the harder its indirect branch is to predict.
+    pub fn distinct_opcode_transitions(&self) -> usize {
+        self.opcode_transitions
+            .iter()
+            .filter(|count| **count > 0)
+            .count()
+    }
+
+    /// Resets all counters
+    pub fn reset(&mut self) {
+        self.dispatched_instructions = 0;
+        self.last_opcode = None;
+        self.opcode_transitions
+            .iter_mut()
+            .for_each(|count| *count = 0);
+    }
+
+    /// Writes the most frequent transitions in a human readable form
+    pub fn write_report<W: std::io::Write>(
+        &self,
+        output: &mut W,
+        max_entries: usize,
+    ) -> std::io::Result<()> {
+        let mut transitions = self.opcode_transitions().collect::<Vec<_>>();
+        transitions.sort_by_key(|(_previous, _next, count)| std::cmp::Reverse(*count));
+        writeln!(
+            output,
+            "dispatched instructions: {}, distinct transitions: {}",
+            self.dispatched_instructions,
+            self.distinct_opcode_transitions(),
+        )?;
+        for (previous, next, count) in transitions.into_iter().take(max_entries) {
+            writeln!(output, "{:#04x} -> {:#04x}: {}", previous, next, count,)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hit and miss counters of the address translation caches
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MappingCacheDiagnostics {
+    /// Translations answered by the cache
+    pub hits: u64,
+    /// Translations which needed a full region lookup
+    pub misses: u64,
+}
+
+impl MappingCacheDiagnostics {
+    /// Ratio of hits to all lookups, `None` if there were no lookups yet
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits.saturating_add(self.misses);
+        if total == 0 {
+            None
+        } else {
+            Some(self.hits as f64 / total as f64)
+        }
+    }
+}
+
+/// Counters of the [taint replay](crate::taint::InputTaint)
+///
+/// Instructions which the static analysis proves to be input independent take the fast path,
+/// their effect on the taint is not followed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TaintDiagnostics {
+    /// Instructions which were skipped
+    pub fast_path_hits: u64,
+    /// Instructions whose effect on the taint was followed
+    pub followed_instructions: u64,
+}
+
+impl TaintDiagnostics {
+    /// Ratio of skipped to all replayed instructions, `None` if nothing was replayed yet
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self
+            .fast_path_hits
+            .saturating_add(self.followed_instructions);
+        if total == 0 {
+            None
+        } else {
+            Some(self.fast_path_hits as f64 / total as f64)
+        }
+    }
+
+    /// Adds the counters of another replay
+    pub fn merge(&mut self, other: &Self) {
+        self.fast_path_hits = self.fast_path_hits.saturating_add(other.fast_path_hits);
+        self.followed_instructions = self
+            .followed_instructions
+            .saturating_add(other.followed_instructions);
+    }
+}
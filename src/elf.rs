@@ -7,6 +7,7 @@
 // this loader will need to be re-written to use the program headers instead.
 
 use crate::{
+    abi::AbiRestrictions,
     aligned_memory::{is_memory_aligned, AlignedMemory},
     ebpf::{self, EF_SBPF_V2, HOST_ALIGN, INSN_SIZE},
     elf_parser::{
@@ -14,18 +15,22 @@ use crate::{
             ELFCLASS64, ELFDATA2LSB, ELFOSABI_NONE, EM_BPF, EM_SBPF, ET_DYN, R_X86_64_32,
             R_X86_64_64, R_X86_64_NONE, R_X86_64_RELATIVE,
         },
-        types::{Elf64Phdr, Elf64Shdr, Elf64Word},
+        types::{Elf64Phdr, Elf64Rel, Elf64Shdr, Elf64Word},
         Elf64, ElfParserError,
     },
     error::EbpfError,
     memory_region::MemoryRegion,
     program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
-    verifier::Verifier,
+    verifier::{Verifier, VerifierExt, VerifierExtensions},
     vm::{Config, ContextObject},
 };
 
 #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
 use crate::jit::{JitCompiler, JitProgram};
+#[cfg(feature = "jit-cranelift")]
+use crate::jit_cranelift::CraneliftProgram;
+#[cfg(feature = "dwarf")]
+use crate::source_map::{SourceLocation, SourceMap};
 use byteorder::{ByteOrder, LittleEndian};
 use std::{collections::BTreeMap, fmt::Debug, mem, ops::Range, str};
 
@@ -131,6 +136,106 @@ impl From<ElfParserError> for ElfError {
     }
 }
 
+/// Part of an ELF file an [ElfError] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ElfIssueCategory {
+    /// File header, including the entrypoint and the SBPF version
+    FileHeader,
+    /// Program header table
+    ProgramHeader,
+    /// Placement and permissions of the sections
+    SectionPlacement,
+    /// Relocations and relative calls
+    Relocation,
+    /// Symbol resolution
+    Symbol,
+}
+
+impl ElfError {
+    /// Part of the ELF file this error is about
+    pub fn category(&self) -> ElfIssueCategory {
+        match self {
+            ElfError::FailedToParse(_)
+            | ElfError::EntrypointOutOfBounds
+            | ElfError::InvalidEntrypoint
+            | ElfError::WrongEndianess
+            | ElfError::WrongAbi
+            | ElfError::WrongMachine
+            | ElfError::WrongClass
+            | ElfError::WrongType
+            | ElfError::UnsupportedSBPFVersion => ElfIssueCategory::FileHeader,
+            ElfError::InvalidProgramHeader => ElfIssueCategory::ProgramHeader,
+            ElfError::FailedToGetSection(_)
+            | ElfError::SectionNotFound(_)
+            | ElfError::NotOneTextSection
+            | ElfError::WritableSectionNotSupported(_)
+            | ElfError::ValueOutOfBounds => ElfIssueCategory::SectionPlacement,
+            ElfError::RelativeJumpOutOfBounds(_)
+            | ElfError::AddressOutsideLoadableSection(_)
+            | ElfError::InvalidVirtualAddress(_)
+            | ElfError::UnknownRelocation(_)
+            | ElfError::FailedToReadRelocationInfo => ElfIssueCategory::Relocation,
+            ElfError::UnresolvedSymbol(_, _, _)
+            | ElfError::SymbolHashCollision(_)
+            | ElfError::UnknownSymbol(_) => ElfIssueCategory::Symbol,
+        }
+    }
+}
+
+/// All issues found in an ELF file, see [Executable::diagnose_elf]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ElfDiagnostics {
+    /// Issues in the order the loader found them
+    pub issues: Vec<ElfError>,
+}
+
+impl ElfDiagnostics {
+    /// Whether the file loads without issues
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Issues about the given part of the file
+    pub fn in_category(&self, category: ElfIssueCategory) -> impl Iterator<Item = &ElfError> {
+        self.issues
+            .iter()
+            .filter(move |issue| issue.category() == category)
+    }
+}
+
+/// Receives the issues found while loading an ELF file
+///
+/// Loading stops at the first issue, unless the issues are collected into [ElfDiagnostics].
+/// Then it only stops at issues which prevent checking the rest of the file.
+#[derive(Default)]
+struct ElfIssues<'a> {
+    collected: Option<&'a mut Vec<ElfError>>,
+    /// Loading stopped because of issues which were already collected
+    aborted: bool,
+}
+
+impl ElfIssues<'_> {
+    /// Reports an issue which does not prevent checking the rest of the file
+    fn report<E>(&mut self, error: E) -> Result<(), E>
+    where
+        ElfError: From<E>,
+    {
+        match self.collected.as_mut() {
+            Some(issues) => {
+                issues.push(error.into());
+                Ok(())
+            }
+            None => Err(error),
+        }
+    }
+
+    /// Stops loading because of the issues reported before
+    fn abort<E>(&mut self, error: E) -> E {
+        self.aborted = true;
+        error
+    }
+}
+
 fn get_section(elf: &Elf64, name: &[u8]) -> Result<Elf64Shdr, ElfError> {
     for section_header in elf.section_header_table() {
         if elf.section_name(section_header.sh_name)? == name {
@@ -248,9 +353,19 @@ pub struct Executable<C: ContextObject> {
     function_registry: FunctionRegistry<usize>,
     /// Loader built-in program
     loader: Arc<BuiltinProgram<C>>,
+    /// Experimental calling convention checked by [Self::verify]
+    abi_restrictions: Option<AbiRestrictions>,
+    /// Additional verification passes run by [Self::verify]
+    verifier_extensions: VerifierExtensions,
     /// Compiled program and argument
     #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
     compiled_program: Option<JitProgram>,
+    /// Program compiled by the portable JIT
+    #[cfg(feature = "jit-cranelift")]
+    cranelift_program: Option<CraneliftProgram>,
+    /// Source locations from the DWARF debug info
+    #[cfg(feature = "dwarf")]
+    source_map: Option<SourceMap>,
 }
 
 impl<C: ContextObject> Executable<C> {
@@ -272,6 +387,13 @@ impl<C: ContextObject> Executable<C> {
         )
     }
 
+    /// Whether the executable was loaded from an ELF file instead of text bytes
+    pub(crate) fn is_elf(&self) -> bool {
+        self.elf_bytes
+            .as_slice()
+            .starts_with(&crate::elf_parser::consts::ELFMAG)
+    }
+
     /// Get the concatenated read-only sections (including the text section)
     pub fn get_ro_section(&self) -> &[u8] {
         match &self.ro_section {
@@ -298,6 +420,18 @@ impl<C: ContextObject> Executable<C> {
         self.text_section_range.start as u64
     }
 
+    /// Get the source map parsed from the DWARF debug info of the ELF, if it had any
+    #[cfg(feature = "dwarf")]
+    pub fn get_source_map(&self) -> Option<&SourceMap> {
+        self.source_map.as_ref()
+    }
+
+    /// Get the source location the instruction at `pc` was compiled from
+    #[cfg(feature = "dwarf")]
+    pub fn pc_to_source(&self, pc: usize) -> Option<SourceLocation> {
+        self.source_map.as_ref()?.lookup(pc)
+    }
+
     /// Get the loader built-in program
     pub fn get_loader(&self) -> &Arc<BuiltinProgram<C>> {
         &self.loader
@@ -318,14 +452,120 @@ impl<C: ContextObject> Executable<C> {
             self.get_function_registry(),
             self.loader.get_function_registry(),
         )?;
+        if let Some(abi_restrictions) = &self.abi_restrictions {
+            abi_restrictions.verify(
+                self.get_text_bytes().1,
+                self.get_sbpf_version(),
+                self.get_function_registry(),
+            )?;
+        }
+        self.verifier_extensions.verify(
+            self.get_text_bytes().1,
+            self.get_sbpf_version(),
+            self.get_function_registry(),
+            0..self.get_text_bytes().1.len() / ebpf::INSN_SIZE,
+        )?;
         Ok(())
     }
 
+    /// Re-runs the checks of [Self::verify] which depend on the registries, for the functions
+    /// overlapping the instruction range `range`
+    ///
+    /// After entries of the function registry or of the syscall registry of the loader changed,
+    /// only the functions which refer to them need to be checked again. These checks are the
+    /// lookups of the `syscall` instructions (SBPFv3), the [AbiRestrictions] and the verifier
+    /// extensions. The other checks of the [Verifier] only depend on the bytecode.
+    pub fn reverify_functions(&self, range: Range<usize>) -> Result<(), EbpfError> {
+        let prog = self.get_text_bytes().1;
+        let mut function_starts = self
+            .get_function_registry()
+            .iter()
+            .map(|(_key, (_name, pc))| pc)
+            .collect::<Vec<_>>();
+        function_starts.sort_unstable();
+        let start = function_starts
+            .iter()
+            .rev()
+            .find(|pc| **pc <= range.start)
+            .copied()
+            .unwrap_or(0);
+        let end = function_starts
+            .iter()
+            .find(|pc| **pc >= range.end.max(start.saturating_add(1)))
+            .copied()
+            .unwrap_or(prog.len() / ebpf::INSN_SIZE);
+        crate::verifier::check_syscalls(
+            prog,
+            self.get_sbpf_version(),
+            self.loader.get_function_registry(),
+            start..end,
+        )?;
+        if let Some(abi_restrictions) = &self.abi_restrictions {
+            abi_restrictions.verify_range(
+                prog,
+                self.get_sbpf_version(),
+                self.get_function_registry(),
+                start..end,
+            )?;
+        }
+        self.verifier_extensions.verify(
+            prog,
+            self.get_sbpf_version(),
+            self.get_function_registry(),
+            start..end,
+        )?;
+        Ok(())
+    }
+
+    /// Get the experimental calling convention
+    pub fn get_abi_restrictions(&self) -> Option<&AbiRestrictions> {
+        self.abi_restrictions.as_ref()
+    }
+
+    /// Set an experimental calling convention, which [Self::verify] checks
+    pub fn set_abi_restrictions(&mut self, abi_restrictions: Option<AbiRestrictions>) {
+        self.abi_restrictions = abi_restrictions;
+    }
+
+    /// Register an additional verification pass, which [Self::verify] runs after `V`
+    pub fn add_verifier_extension<E: VerifierExt + 'static>(&mut self, extension: E) {
+        self.verifier_extensions.0.push(Box::new(extension));
+    }
+
     /// JIT compile the executable
     #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
     pub fn jit_compile(&mut self) -> Result<(), crate::error::EbpfError> {
+        if self.get_config().cost_model.is_some() {
+            return Err(crate::error::EbpfError::UnsupportedCostModel);
+        }
         let jit = JitCompiler::<C>::new(self)?;
         self.compiled_program = Some(jit.compile()?);
+        #[cfg(feature = "jit-cranelift")]
+        {
+            self.cranelift_program = None;
+        }
+        Ok(())
+    }
+
+    /// Get the program compiled by the portable JIT
+    #[cfg(feature = "jit-cranelift")]
+    pub fn get_cranelift_program(&self) -> Option<&CraneliftProgram> {
+        self.cranelift_program.as_ref()
+    }
+
+    /// JIT compile the executable with Cranelift, which supports hosts other than x86_64
+    ///
+    /// Replaces the program of [Self::jit_compile] and vice versa.
+    #[cfg(feature = "jit-cranelift")]
+    pub fn cranelift_compile(&mut self) -> Result<(), crate::error::EbpfError> {
+        if self.get_config().cost_model.is_some() {
+            return Err(crate::error::EbpfError::UnsupportedCostModel);
+        }
+        self.cranelift_program = Some(CraneliftProgram::compile(self)?);
+        #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+        {
+            self.compiled_program = None;
+        }
         Ok(())
     }
 
@@ -334,6 +574,15 @@ impl<C: ContextObject> Executable<C> {
         &self.function_registry
     }
 
+    /// Get the function registry for hot patching
+    ///
+    /// The interpreter looks up the functions on every call, but a JIT compiled program keeps
+    /// the ones it was compiled with. Check the affected functions again with
+    /// [Self::reverify_functions].
+    pub fn get_function_registry_mut(&mut self) -> &mut FunctionRegistry<usize> {
+        &mut self.function_registry
+    }
+
     /// Create from raw text section bytes (list of instructions)
     pub fn new_from_text_bytes(
         text_bytes: &[u8],
@@ -366,13 +615,48 @@ impl<C: ContextObject> Executable<C> {
             entry_pc,
             function_registry,
             loader,
+            abi_restrictions: None,
+            verifier_extensions: VerifierExtensions::default(),
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
             compiled_program: None,
+            #[cfg(feature = "jit-cranelift")]
+            cranelift_program: None,
+            #[cfg(feature = "dwarf")]
+            source_map: None,
         })
     }
 
     /// Fully loads an ELF
     pub fn load(bytes: &[u8], loader: Arc<BuiltinProgram<C>>) -> Result<Self, ElfError> {
+        Self::load_with_issues(bytes, loader, &mut ElfIssues::default())
+    }
+
+    /// Loads an ELF like [Self::load], but collects all issues instead of stopping at the first
+    ///
+    /// Issues which prevent checking the rest of the file, e.g. a broken file header, end the
+    /// list. Which issues are checked depends on the config of the loader, in particular on
+    /// [Config::reject_broken_elfs] and [Config::enabled_sbpf_versions].
+    pub fn diagnose_elf(bytes: &[u8], loader: Arc<BuiltinProgram<C>>) -> ElfDiagnostics {
+        let mut diagnostics = ElfDiagnostics::default();
+        let mut issues = ElfIssues {
+            collected: Some(&mut diagnostics.issues),
+            aborted: false,
+        };
+        let result = Self::load_with_issues(bytes, loader, &mut issues);
+        let aborted = issues.aborted;
+        if let Err(error) = result {
+            if !aborted {
+                diagnostics.issues.push(error);
+            }
+        }
+        diagnostics
+    }
+
+    fn load_with_issues(
+        bytes: &[u8],
+        loader: Arc<BuiltinProgram<C>>,
+        issues: &mut ElfIssues,
+    ) -> Result<Self, ElfError> {
         const E_FLAGS_OFFSET: usize = 48;
         let e_flags = LittleEndian::read_u32(
             bytes
@@ -401,11 +685,15 @@ impl<C: ContextObject> Executable<C> {
         }
 
         let mut executable = if sbpf_version.enable_stricter_elf_headers() {
-            Self::load_with_strict_parser(bytes, loader)?
+            Self::parse_strict(bytes, loader, issues)?
         } else {
-            Self::load_with_lenient_parser(bytes, loader)?
+            Self::load_with_lenient_parser(bytes, loader, issues)?
         };
         executable.sbpf_version = sbpf_version;
+        #[cfg(feature = "dwarf")]
+        {
+            executable.source_map = SourceMap::from_elf(bytes);
+        }
         Ok(executable)
     }
 
@@ -413,6 +701,14 @@ impl<C: ContextObject> Executable<C> {
     pub fn load_with_strict_parser(
         bytes: &[u8],
         loader: Arc<BuiltinProgram<C>>,
+    ) -> Result<Self, ElfParserError> {
+        Self::parse_strict(bytes, loader, &mut ElfIssues::default())
+    }
+
+    fn parse_strict(
+        bytes: &[u8],
+        loader: Arc<BuiltinProgram<C>>,
+        issues: &mut ElfIssues,
     ) -> Result<Self, ElfParserError> {
         use crate::elf_parser::{
             consts::{ELFMAG, EV_CURRENT, PF_R, PF_W, PF_X, PT_LOAD, SHN_UNDEF, STT_FUNC},
@@ -460,6 +756,7 @@ impl<C: ContextObject> Executable<C> {
         ];
         let program_header_table =
             Elf64::slice_from_bytes::<Elf64Phdr>(elf_bytes, program_header_table_range.clone())?;
+        let mut valid_program_headers = true;
         for (program_header, (p_flags, p_vaddr)) in program_header_table
             .iter()
             .zip(EXPECTED_PROGRAM_HEADERS.iter())
@@ -482,9 +779,13 @@ impl<C: ContextObject> Executable<C> {
                 || program_header.p_filesz.checked_rem(ebpf::INSN_SIZE as u64) != Some(0)
                 || program_header.p_memsz >= ebpf::MM_REGION_SIZE
             {
-                return Err(ElfParserError::InvalidProgramHeader);
+                issues.report(ElfParserError::InvalidProgramHeader)?;
+                valid_program_headers = false;
             }
         }
+        if !valid_program_headers {
+            return Err(issues.abort(ElfParserError::InvalidProgramHeader));
+        }
 
         let bytecode_header = &program_header_table[0];
         let rodata_header = &program_header_table[1];
@@ -511,7 +812,7 @@ impl<C: ContextObject> Executable<C> {
             .unwrap_or_default() as usize;
         let entry_insn = ebpf::get_insn(&elf_bytes[text_section_range.clone()], entry_pc);
         if !entry_insn.is_function_start_marker() {
-            return Err(ElfParserError::InvalidFileHeader);
+            issues.report(ElfParserError::InvalidFileHeader)?;
         }
 
         let mut function_registry = FunctionRegistry::<usize>::default();
@@ -585,8 +886,14 @@ impl<C: ContextObject> Executable<C> {
             entry_pc,
             function_registry,
             loader,
+            abi_restrictions: None,
+            verifier_extensions: VerifierExtensions::default(),
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
             compiled_program: None,
+            #[cfg(feature = "jit-cranelift")]
+            cranelift_program: None,
+            #[cfg(feature = "dwarf")]
+            source_map: None,
         })
     }
 
@@ -594,6 +901,7 @@ impl<C: ContextObject> Executable<C> {
     fn load_with_lenient_parser(
         bytes: &[u8],
         loader: Arc<BuiltinProgram<C>>,
+        issues: &mut ElfIssues,
     ) -> Result<Self, ElfError> {
         // We always need one memory copy to take ownership and for relocations
         let aligned_memory = AlignedMemory::<{ HOST_ALIGN }>::from_slice(bytes);
@@ -614,7 +922,7 @@ impl<C: ContextObject> Executable<C> {
             SBPFVersion::V0
         };
 
-        Self::validate(config, &elf, elf_bytes.as_slice())?;
+        Self::validate_with_issues(config, &elf, elf_bytes.as_slice(), issues)?;
 
         // calculate the text section info
         let text_section = get_section(&elf, b".text")?;
@@ -634,7 +942,7 @@ impl<C: ContextObject> Executable<C> {
             && text_section.sh_addr != text_section.sh_offset)
             || vaddr_end > ebpf::MM_STACK_START
         {
-            return Err(ElfError::ValueOutOfBounds);
+            issues.report(ElfError::ValueOutOfBounds)?;
         }
 
         // relocate symbols
@@ -644,12 +952,13 @@ impl<C: ContextObject> Executable<C> {
             &loader,
             &elf,
             elf_bytes.as_slice_mut(),
+            issues,
         )?;
 
         // calculate entrypoint offset into the text section
         let offset = header.e_entry.saturating_sub(text_section.sh_addr);
         if offset.checked_rem(ebpf::INSN_SIZE as u64) != Some(0) {
-            return Err(ElfError::InvalidEntrypoint);
+            issues.report(ElfError::InvalidEntrypoint)?;
         }
         let entry_pc = if let Some(entry_pc) = (offset as usize).checked_div(ebpf::INSN_SIZE) {
             if !sbpf_version.static_syscalls() {
@@ -684,8 +993,14 @@ impl<C: ContextObject> Executable<C> {
             entry_pc,
             function_registry,
             loader,
+            abi_restrictions: None,
+            verifier_extensions: VerifierExtensions::default(),
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
             compiled_program: None,
+            #[cfg(feature = "jit-cranelift")]
+            cranelift_program: None,
+            #[cfg(feature = "dwarf")]
+            source_map: None,
         })
     }
 
@@ -718,21 +1033,30 @@ impl<C: ContextObject> Executable<C> {
 
     /// Validates the ELF
     pub fn validate(config: &Config, elf: &Elf64, elf_bytes: &[u8]) -> Result<(), ElfError> {
+        Self::validate_with_issues(config, elf, elf_bytes, &mut ElfIssues::default())
+    }
+
+    fn validate_with_issues(
+        config: &Config,
+        elf: &Elf64,
+        elf_bytes: &[u8],
+        issues: &mut ElfIssues,
+    ) -> Result<(), ElfError> {
         let header = elf.file_header();
         if header.e_ident.ei_class != ELFCLASS64 {
-            return Err(ElfError::WrongClass);
+            issues.report(ElfError::WrongClass)?;
         }
         if header.e_ident.ei_data != ELFDATA2LSB {
-            return Err(ElfError::WrongEndianess);
+            issues.report(ElfError::WrongEndianess)?;
         }
         if header.e_ident.ei_osabi != ELFOSABI_NONE {
-            return Err(ElfError::WrongAbi);
+            issues.report(ElfError::WrongAbi)?;
         }
         if header.e_machine != EM_BPF && header.e_machine != EM_SBPF {
-            return Err(ElfError::WrongMachine);
+            issues.report(ElfError::WrongMachine)?;
         }
         if header.e_type != ET_DYN {
-            return Err(ElfError::WrongType);
+            issues.report(ElfError::WrongType)?;
         }
 
         let sbpf_version = if header.e_flags == EF_SBPF_V2 {
@@ -741,7 +1065,7 @@ impl<C: ContextObject> Executable<C> {
             SBPFVersion::V0
         };
         if !config.enabled_sbpf_versions.contains(&sbpf_version) {
-            return Err(ElfError::UnsupportedSBPFVersion);
+            issues.report(ElfError::UnsupportedSBPFVersion)?;
         }
 
         if sbpf_version.enable_elf_vaddr() {
@@ -749,7 +1073,7 @@ impl<C: ContextObject> Executable<C> {
                 // When optimize_rodata=false, we allocate a vector and copy all
                 // rodata sections into it. In that case we can't allow virtual
                 // addresses or we'd potentially have to do huge allocations.
-                return Err(ElfError::UnsupportedSBPFVersion);
+                issues.report(ElfError::UnsupportedSBPFVersion)?;
             }
 
             // The toolchain currently emits up to 4 program headers. 10 is a
@@ -758,7 +1082,7 @@ impl<C: ContextObject> Executable<C> {
             // program_headers() returns an ExactSizeIterator so count doesn't
             // actually iterate again.
             if elf.program_header_table().iter().count() >= 10 {
-                return Err(ElfError::InvalidProgramHeader);
+                issues.report(ElfError::InvalidProgramHeader)?;
             }
         }
 
@@ -774,7 +1098,7 @@ impl<C: ContextObject> Executable<C> {
                     count
                 });
         if 1 != num_text_sections {
-            return Err(ElfError::NotOneTextSection);
+            issues.report(ElfError::NotOneTextSection)?;
         }
 
         for section_header in elf.section_header_table().iter() {
@@ -783,26 +1107,27 @@ impl<C: ContextObject> Executable<C> {
                     || (section_header.is_writable()
                         && (name.starts_with(b".data") && !name.starts_with(b".data.rel")))
                 {
-                    return Err(ElfError::WritableSectionNotSupported(
+                    issues.report(ElfError::WritableSectionNotSupported(
                         String::from_utf8_lossy(name).to_string(),
-                    ));
+                    ))?;
                 }
             }
         }
 
         for section_header in elf.section_header_table().iter() {
             let start = section_header.sh_offset as usize;
-            let end = section_header
+            let in_bounds = section_header
                 .sh_offset
                 .checked_add(section_header.sh_size)
-                .ok_or(ElfError::ValueOutOfBounds)? as usize;
-            let _ = elf_bytes
-                .get(start..end)
-                .ok_or(ElfError::ValueOutOfBounds)?;
+                .and_then(|end| elf_bytes.get(start..end as usize))
+                .is_some();
+            if !in_bounds {
+                issues.report(ElfError::ValueOutOfBounds)?;
+            }
         }
         let text_section = get_section(elf, b".text")?;
         if !text_section.vm_range().contains(&header.e_entry) {
-            return Err(ElfError::EntrypointOutOfBounds);
+            issues.report(ElfError::EntrypointOutOfBounds)?;
         }
 
         Ok(())
@@ -992,6 +1317,7 @@ impl<C: ContextObject> Executable<C> {
         loader: &BuiltinProgram<C>,
         elf: &Elf64,
         elf_bytes: &mut [u8],
+        issues: &mut ElfIssues,
     ) -> Result<(), ElfError> {
         let mut syscall_cache = BTreeMap::new();
         let text_section = get_section(elf, b".text")?;
@@ -1017,7 +1343,8 @@ impl<C: ContextObject> Executable<C> {
                     .saturating_add(1)
                     .saturating_add(insn.imm as isize);
                 if target_pc < 0 || target_pc >= instruction_count as isize {
-                    return Err(ElfError::RelativeJumpOutOfBounds(i));
+                    issues.report(ElfError::RelativeJumpOutOfBounds(i))?;
+                    continue;
                 }
                 let name = if config.enable_symbol_and_section_labels {
                     format!("function_{target_pc}")
@@ -1043,7 +1370,7 @@ impl<C: ContextObject> Executable<C> {
         let mut program_header: Option<&Elf64Phdr> = None;
 
         // Fixup all the relocations in the relocation section if exists
-        for relocation in elf.dynamic_relocations_table().unwrap_or_default().iter() {
+        let mut relocate_entry = |relocation: &Elf64Rel| -> Result<(), ElfError> {
             let mut r_offset = relocation.r_offset as usize;
 
             // When sbpf_version.enable_elf_vaddr()=true, we allow section.sh_addr !=
@@ -1301,6 +1628,12 @@ impl<C: ContextObject> Executable<C> {
                 }
                 _ => return Err(ElfError::UnknownRelocation(relocation.r_type())),
             }
+            Ok(())
+        };
+        for relocation in elf.dynamic_relocations_table().unwrap_or_default().iter() {
+            if let Err(error) = relocate_entry(relocation) {
+                issues.report(error)?;
+            }
         }
 
         if config.enable_symbol_and_section_labels {
@@ -1310,7 +1643,8 @@ impl<C: ContextObject> Executable<C> {
                     continue;
                 }
                 if !text_section.vm_range().contains(&symbol.st_value) {
-                    return Err(ElfError::ValueOutOfBounds);
+                    issues.report(ElfError::ValueOutOfBounds)?;
+                    continue;
                 }
                 let target_pc = (symbol.st_value.saturating_sub(text_section.sh_addr) as usize)
                     .checked_div(ebpf::INSN_SIZE)
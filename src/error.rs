@@ -45,6 +45,9 @@ pub enum EbpfError {
     /// Program has not been JIT-compiled
     #[error("program has not been JIT-compiled")]
     JitNotCompiled,
+    /// The JIT does not implement [Config::cost_model](crate::vm::Config::cost_model)
+    #[error("cost models are not supported by the JIT")]
+    UnsupportedCostModel,
     /// Memory region index or virtual address space is invalid
     #[error("Invalid memory region at index {0}")]
     InvalidMemoryRegion(usize),
@@ -54,6 +57,25 @@ pub enum EbpfError {
     /// Access violation (stack specific)
     #[error("Access violation in stack frame {3} at address {1:#x} of size {2:?}")]
     StackAccessViolation(AccessType, u64, u64, i64),
+    /// Access to a red zone, freed or unallocated byte of a sanitized heap
+    #[error("Heap poison access ({3}) at address {1:#x} of size {2:?}")]
+    HeapPoisonAccess(AccessType, u64, u64, &'static str),
+    /// Load of stack bytes which were not written in the current frame
+    #[error("Uninitialized read at address {0:#x} of size {1:?}")]
+    UninitializedRead(u64, u64),
+    /// A back edge was taken more often than the budget of the [LoopDetector](crate::loop_detector::LoopDetector) allows
+    #[error("Loop budget exceeded by the jump from {0} to {1}")]
+    LoopBudgetExceeded(u64, u64),
+    /// The wall-clock [Deadline](crate::deadline::Deadline) of the execution has passed
+    #[error("Wall-clock deadline exceeded")]
+    Timeout,
+    /// The return target of a call frame differs from its [shadow](crate::vm::EbpfVm::shadow_call_stack)
+    #[error("Control flow integrity violated by the return at {0} to {1} instead of {2}")]
+    ControlFlowIntegrity(u64, u64, u64),
+    /// An [Interpreter::step_with](crate::interpreter::Interpreter::step_with) callback
+    /// stopped the execution before the instruction at the pc
+    #[error("Execution aborted at BPF instruction {0}")]
+    ExecutionAborted(u64),
     /// Invalid instruction
     #[error("invalid BPF instruction")]
     InvalidInstruction,
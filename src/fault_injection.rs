@@ -0,0 +1,143 @@
+//! Syscall fault injection
+//!
+//! A [FaultInjector] set in [crate::vm::EbpfVm::fault_injector] is consulted by the interpreter
+//! before every syscall. It can let the syscall fail, skip it and return a chosen value, or run it
+//! and mutate its return value. This allows fuzzers to reach error handling paths of a program
+//! which honest syscalls never take. Only the interpreter dispatches through the injector.
+
+use std::collections::BTreeMap;
+
+/// What happens to an intercepted syscall
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultAction {
+    /// Skip the syscall and throw an [InjectedSyscallError]
+    Fail,
+    /// Skip the syscall and return the value in r0
+    Return(u64),
+    /// Execute the syscall and xor its return value with the mask
+    XorResult(u64),
+}
+
+/// Error thrown by [FaultAction::Fail]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("injected fault in syscall {syscall:#x}")]
+pub struct InjectedSyscallError {
+    /// Hash of the syscall
+    pub syscall: u32,
+}
+
+/// Decides which syscalls to intercept
+pub trait FaultInjector {
+    /// Called before the syscall with the given hash is dispatched, with its arguments r1 to r5
+    fn on_syscall(&mut self, syscall: u32, arguments: [u64; 5]) -> Option<FaultAction>;
+}
+
+/// Condition of a [FaultRule]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultTrigger {
+    /// The nth (starting at 0) call of the syscall
+    NthCall(u64),
+    /// Randomly with the given probability
+    Probability {
+        /// Triggers in `numerator` out of `denominator` calls on average
+        numerator: u64,
+        /// Must not be 0
+        denominator: u64,
+    },
+    /// Whenever an argument has the given value
+    ArgumentEquals {
+        /// Index of the argument, 0 for r1
+        index: usize,
+        /// Value to compare against
+        value: u64,
+    },
+}
+
+/// A trigger and the action to take when it fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultRule {
+    /// Hash of the syscall, `None` for all syscalls
+    pub syscall: Option<u32>,
+    /// Condition
+    pub trigger: FaultTrigger,
+    /// Action
+    pub action: FaultAction,
+}
+
+/// A fault which was injected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InjectedFault {
+    /// Hash of the syscall
+    pub syscall: u32,
+    /// Number of calls of the syscall before this one
+    pub call_index: u64,
+    /// Action which was taken
+    pub action: FaultAction,
+}
+
+/// [FaultInjector] driven by a list of rules, the first rule which fires wins
+#[derive(Debug, Clone)]
+pub struct PolicyFaultInjector {
+    rules: Vec<FaultRule>,
+    /// Calls so far per syscall
+    call_counts: BTreeMap<u32, u64>,
+    /// State of the xorshift generator
+    rng_state: u64,
+    /// Faults injected so far
+    pub injected: Vec<InjectedFault>,
+}
+
+impl PolicyFaultInjector {
+    /// Creates an injector, `seed` makes the probabilistic triggers reproducible
+    pub fn new(rules: Vec<FaultRule>, seed: u64) -> Self {
+        Self {
+            rules,
+            call_counts: BTreeMap::new(),
+            rng_state: seed.max(1),
+            injected: Vec::new(),
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        self.rng_state
+    }
+}
+
+impl FaultInjector for PolicyFaultInjector {
+    fn on_syscall(&mut self, syscall: u32, arguments: [u64; 5]) -> Option<FaultAction> {
+        let call_count = self.call_counts.entry(syscall).or_insert(0);
+        let call_index = *call_count;
+        *call_count = call_count.saturating_add(1);
+        for index in 0..self.rules.len() {
+            let rule = self.rules[index];
+            if rule.syscall.is_some_and(|hash| hash != syscall) {
+                continue;
+            }
+            let fires = match rule.trigger {
+                FaultTrigger::NthCall(n) => call_index == n,
+                FaultTrigger::Probability {
+                    numerator,
+                    denominator,
+                } => self
+                    .next_random()
+                    .checked_rem(denominator)
+                    .is_some_and(|random| random < numerator),
+                FaultTrigger::ArgumentEquals { index, value } => {
+                    arguments.get(index) == Some(&value)
+                }
+            };
+            if fires {
+                self.injected.push(InjectedFault {
+                    syscall,
+                    call_index,
+                    action: rule.action,
+                });
+                return Some(rule.action);
+            }
+        }
+        None
+    }
+}
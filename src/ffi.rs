@@ -0,0 +1,290 @@
+//! C interface for embedding the interpreter
+//!
+//! Allows fuzzing frontends which are not written in Rust (e.g. libFuzzer harnesses or
+//! Python bindings) to load an ELF, execute it on an input buffer and retrieve
+//! a per instruction coverage map of the last execution.
+//! No syscalls are registered, so programs which invoke syscalls fail to load.
+//!
+//! [sbpf_program_taint_summary] replays the last execution with [InputTaint], so a frontend
+//! can focus its mutations on the input bytes which drive the conditional jumps.
+//!
+//! The `ffi` directory of the repository builds these functions as a C library and contains
+//! the matching header `sbpf.h`.
+
+use crate::{
+    aligned_memory::AlignedMemory,
+    ebpf,
+    elf::Executable,
+    error::ProgramResult,
+    memory_region::{MemoryMapping, MemoryRegion},
+    program::BuiltinProgram,
+    static_analysis::{Analysis, TraceLogEntry},
+    taint::{InputTaint, TaintLabels},
+    verifier::RequisiteVerifier,
+    vm::{Config, ContextObject, EbpfVm},
+};
+use std::sync::Arc;
+
+/// Context object of executions started through the C interface
+#[derive(Debug, Default)]
+struct FfiContextObject {
+    /// Remaining instruction budget
+    remaining: u64,
+    /// Saturating hit counter per instruction of the text section
+    coverage: Vec<u8>,
+    /// Register state before every executed instruction
+    trace_log: Vec<TraceLogEntry>,
+}
+
+impl ContextObject for FfiContextObject {
+    fn trace(&mut self, state: [u64; 12]) {
+        self.trace_log.push(state);
+        if let Some(counter) = self.coverage.get_mut(state[11] as usize) {
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+/// A loaded and verified program, opaque to C
+pub struct SbpfProgram {
+    executable: Executable<FfiContextObject>,
+    /// Coverage of the last execution
+    coverage: Vec<u8>,
+    /// Trace of the last execution
+    trace_log: Vec<TraceLogEntry>,
+}
+
+/// Outcome of [sbpf_program_execute]
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SbpfExecutionResult {
+    /// 0 if the program returned, 1 if it threw an error
+    pub status: u32,
+    /// Value of r0 if the program returned
+    pub return_value: u64,
+    /// Number of executed instructions
+    pub instruction_count: u64,
+    /// Number of distinct instructions executed
+    pub covered_instructions: u64,
+}
+
+/// Outcome of [sbpf_program_taint_summary]
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SbpfTaintSummary {
+    /// Number of distinct conditional jumps whose operands were derived from the input
+    pub tainted_comparisons: u64,
+    /// Number of distinct loads which loaded values derived from the input
+    pub tainted_loads: u64,
+    /// Number of distinct input bytes the tainted conditional jumps depend on
+    pub branch_input_bytes: u64,
+    /// Number of instructions the replay could not follow, the summary is incomplete if not 0
+    pub errors: u64,
+}
+
+/// Loads and verifies an ELF
+///
+/// Returns null if the ELF is invalid or does not pass the verifier.
+/// The program must be released with [sbpf_program_free].
+///
+/// # Safety
+/// `elf` must point to `elf_len` readable bytes.
+#[cfg_attr(not(test), no_mangle)]
+pub unsafe extern "C" fn sbpf_program_load(elf: *const u8, elf_len: usize) -> *mut SbpfProgram {
+    if elf.is_null() {
+        return std::ptr::null_mut();
+    }
+    let elf = std::slice::from_raw_parts(elf, elf_len);
+    let loader = Arc::new(BuiltinProgram::new_loader(Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    }));
+    let executable = match Executable::<FfiContextObject>::from_elf(elf, loader) {
+        Ok(executable) => executable,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    if executable.verify::<RequisiteVerifier>().is_err() {
+        return std::ptr::null_mut();
+    }
+    Box::into_raw(Box::new(SbpfProgram {
+        executable,
+        coverage: Vec::new(),
+        trace_log: Vec::new(),
+    }))
+}
+
+/// Releases a program returned by [sbpf_program_load]
+///
+/// # Safety
+/// `program` must be null or a pointer returned by [sbpf_program_load] which was not freed yet.
+#[cfg_attr(not(test), no_mangle)]
+pub unsafe extern "C" fn sbpf_program_free(program: *mut SbpfProgram) {
+    if !program.is_null() {
+        drop(Box::from_raw(program));
+    }
+}
+
+/// Executes a program in the interpreter
+///
+/// The input buffer is mapped writable at `MM_INPUT_START` and passed in r1,
+/// modifications by the program are visible to the caller afterwards.
+/// Returns false if any of the pointers is null or the memory mapping could not be created.
+///
+/// # Safety
+/// `program` must be a live pointer returned by [sbpf_program_load],
+/// `input` must point to `input_len` writable bytes (or be null if `input_len` is 0)
+/// and `result` must point to a writable [SbpfExecutionResult].
+#[cfg_attr(not(test), no_mangle)]
+pub unsafe extern "C" fn sbpf_program_execute(
+    program: *mut SbpfProgram,
+    input: *mut u8,
+    input_len: usize,
+    heap_size: usize,
+    instruction_limit: u64,
+    result: *mut SbpfExecutionResult,
+) -> bool {
+    if program.is_null() || result.is_null() || (input.is_null() && input_len > 0) {
+        return false;
+    }
+    let program = &mut *program;
+    let input: &mut [u8] = if input_len == 0 {
+        &mut []
+    } else {
+        std::slice::from_raw_parts_mut(input, input_len)
+    };
+    let executable = &program.executable;
+    let config = executable.get_config();
+    let sbpf_version = executable.get_sbpf_version();
+    let mut stack = AlignedMemory::<{ ebpf::HOST_ALIGN }>::zero_filled(config.stack_size());
+    let mut heap = AlignedMemory::<{ ebpf::HOST_ALIGN }>::zero_filled(heap_size);
+    let stack_len = stack.len();
+    let regions = vec![
+        executable.get_ro_region(),
+        MemoryRegion::new_writable_gapped(
+            stack.as_slice_mut(),
+            ebpf::MM_STACK_START,
+            if !sbpf_version.dynamic_stack_frames() && config.enable_stack_frame_gaps {
+                config.stack_frame_size as u64
+            } else {
+                0
+            },
+        ),
+        MemoryRegion::new_writable(heap.as_slice_mut(), ebpf::MM_HEAP_START),
+        MemoryRegion::new_writable(input, ebpf::MM_INPUT_START),
+    ];
+    let memory_mapping = match MemoryMapping::new(regions, config, sbpf_version) {
+        Ok(memory_mapping) => memory_mapping,
+        Err(_) => return false,
+    };
+    let mut context_object = FfiContextObject {
+        remaining: instruction_limit,
+        coverage: vec![0; executable.get_text_bytes().1.len() / ebpf::INSN_SIZE],
+        trace_log: Vec::new(),
+    };
+    let (instruction_count, program_result) = {
+        let mut vm = EbpfVm::new(
+            executable.get_loader().clone(),
+            sbpf_version,
+            &mut context_object,
+            memory_mapping,
+            stack_len,
+        );
+        vm.registers[1] = ebpf::MM_INPUT_START;
+        vm.execute_program(executable, true)
+    };
+    let (status, return_value) = match program_result {
+        ProgramResult::Ok(return_value) => (0, return_value),
+        ProgramResult::Err(_) => (1, 0),
+    };
+    *result = SbpfExecutionResult {
+        status,
+        return_value,
+        instruction_count,
+        covered_instructions: context_object
+            .coverage
+            .iter()
+            .filter(|counter| **counter > 0)
+            .count() as u64,
+    };
+    program.coverage = context_object.coverage;
+    program.trace_log = context_object.trace_log;
+    true
+}
+
+/// Copies the per instruction hit counters of the last execution
+///
+/// Copies at most `coverage_len` counters and returns the number of instructions
+/// in the text section, so that the required buffer size can be queried with a null pointer.
+///
+/// # Safety
+/// `program` must be a live pointer returned by [sbpf_program_load] and
+/// `coverage` must point to `coverage_len` writable bytes (or be null).
+#[cfg_attr(not(test), no_mangle)]
+pub unsafe extern "C" fn sbpf_program_coverage(
+    program: *const SbpfProgram,
+    coverage: *mut u8,
+    coverage_len: usize,
+) -> usize {
+    if program.is_null() {
+        return 0;
+    }
+    let program = &*program;
+    if !coverage.is_null() {
+        let len = coverage_len.min(program.coverage.len());
+        std::ptr::copy_nonoverlapping(program.coverage.as_ptr(), coverage, len);
+    }
+    program.coverage.len()
+}
+
+/// Summarizes which parts of the last execution depended on the input
+///
+/// Replays the trace of the last [sbpf_program_execute] with all bytes of the input region as
+/// taint sources. If `branch_input_bytes` is not null, the first `branch_input_bytes_len`
+/// entries are set to 1 for the input offsets the tainted conditional jumps depend on and to 0
+/// for all others. Returns false if any of the required pointers is null or the program could
+/// not be analyzed.
+///
+/// # Safety
+/// `program` must be a live pointer returned by [sbpf_program_load],
+/// `branch_input_bytes` must point to `branch_input_bytes_len` writable bytes (or be null)
+/// and `summary` must point to a writable [SbpfTaintSummary].
+#[cfg_attr(not(test), no_mangle)]
+pub unsafe extern "C" fn sbpf_program_taint_summary(
+    program: *const SbpfProgram,
+    branch_input_bytes: *mut u8,
+    branch_input_bytes_len: usize,
+    summary: *mut SbpfTaintSummary,
+) -> bool {
+    if program.is_null() || summary.is_null() {
+        return false;
+    }
+    let program = &*program;
+    let analysis = match Analysis::from_executable(&program.executable) {
+        Ok(analysis) => analysis,
+        Err(_) => return false,
+    };
+    let taint = InputTaint::from_trace_log(&analysis, &program.trace_log);
+    let branch_labels = TaintLabels::new(taint.tainted_comparisons.values().cloned());
+    *summary = SbpfTaintSummary {
+        tainted_comparisons: taint.tainted_comparisons.len() as u64,
+        tainted_loads: taint.tainted_loads.len() as u64,
+        branch_input_bytes: branch_labels.len(),
+        errors: taint.errors.len() as u64,
+    };
+    if !branch_input_bytes.is_null() {
+        let branch_input_bytes =
+            std::slice::from_raw_parts_mut(branch_input_bytes, branch_input_bytes_len);
+        for (offset, flag) in branch_input_bytes.iter_mut().enumerate() {
+            *flag = branch_labels.contains(offset as u64) as u8;
+        }
+    }
+    true
+}
@@ -0,0 +1,357 @@
+//! Fork server for out-of-process fuzzing
+//!
+//! The [ForkServer] owns a loaded and verified executable and forks a child process per input,
+//! similar to the fork server of AFL. The child executes the program and reports its result
+//! and an edge coverage map through memory shared with the parent. If the program takes the
+//! host down (e.g. a syscall panics or aborts), only the child dies and the crash is reported
+//! as [ForkOutcome::Crashed]. A child which exceeds [ForkServerConfig::timeout] is killed and
+//! reported as [ForkOutcome::TimedOut]. The ELF is loaded once, the children inherit it copy
+//! on write.
+//!
+//! The parent has to be single threaded while it runs inputs: `fork` only duplicates the
+//! calling thread, so a lock held by another thread (e.g. of the allocator) stays locked
+//! forever in the child. Fuzzers which run several fork servers in parallel need one process
+//! per server.
+
+use crate::{
+    aligned_memory::AlignedMemory,
+    ebpf,
+    elf::Executable,
+    error::ProgramResult,
+    memory_region::{MemoryMapping, MemoryRegion},
+    vm::{ContextObject, EbpfVm},
+};
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+/// Context object of executions in the child process
+#[derive(Debug)]
+pub struct FuzzContextObject {
+    /// Remaining instruction budget
+    pub remaining: u64,
+    /// Hit counters in the shared memory, indexed by edge hash
+    coverage: *mut u8,
+    /// Length of the coverage map, a power of two
+    coverage_len: usize,
+    /// Pc of the previously traced instruction
+    previous_pc: u64,
+}
+
+impl Default for FuzzContextObject {
+    fn default() -> Self {
+        Self {
+            remaining: 0,
+            coverage: std::ptr::null_mut(),
+            coverage_len: 0,
+            previous_pc: 0,
+        }
+    }
+}
+
+impl ContextObject for FuzzContextObject {
+    fn trace(&mut self, state: [u64; 12]) {
+        if self.coverage_len == 0 {
+            return;
+        }
+        let pc = state[11];
+        let index = ((self.previous_pc >> 1) ^ pc) as usize & self.coverage_len.saturating_sub(1);
+        // Safety: the index is masked to the length of the coverage map
+        unsafe {
+            let counter = self.coverage.add(index);
+            *counter = (*counter).wrapping_add(1);
+        }
+        self.previous_pc = pc;
+    }
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+/// Settings of a [ForkServer]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForkServerConfig {
+    /// Size of the coverage map, rounded up to a power of two
+    pub coverage_map_size: usize,
+    /// Instruction budget of every execution
+    pub instruction_limit: u64,
+    /// Size of the heap region
+    pub heap_size: usize,
+    /// Use the interpreter instead of the JIT compiled program
+    pub interpreted: bool,
+    /// Wall-clock time after which a child is killed (None = wait indefinitely)
+    pub timeout: Option<Duration>,
+}
+
+impl Default for ForkServerConfig {
+    fn default() -> Self {
+        Self {
+            coverage_map_size: 1 << 16,
+            instruction_limit: 1_000_000,
+            heap_size: 32 * 1024,
+            interpreted: true,
+            timeout: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+/// Result of running one input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForkOutcome {
+    /// The program returned with the value of r0
+    Returned {
+        /// Value of r0
+        return_value: u64,
+        /// Number of executed instructions
+        instruction_count: u64,
+    },
+    /// The program threw an error
+    Failed {
+        /// Debug representation of the error
+        error: String,
+        /// Number of executed instructions
+        instruction_count: u64,
+    },
+    /// The child process was terminated by a signal
+    Crashed(i32),
+    /// The child process exited without reporting a result
+    Aborted(i32),
+    /// The child process exceeded [ForkServerConfig::timeout] and was killed
+    TimedOut,
+}
+
+/// Maximum length of the error message passed back from the child
+const ERROR_MESSAGE_LEN: usize = 256;
+
+/// Interval in which the parent polls a child which has a timeout
+const POLL_INTERVAL: Duration = Duration::from_micros(50);
+
+/// Report of the child, at the start of the shared memory
+#[repr(C)]
+struct ChildReport {
+    /// 0 if the child did not report, 1 if the program returned, 2 if it threw an error
+    status: u64,
+    return_value: u64,
+    instruction_count: u64,
+    error_len: u64,
+    error: [u8; ERROR_MESSAGE_LEN],
+}
+
+/// Forks a child process per input
+pub struct ForkServer {
+    executable: Executable<FuzzContextObject>,
+    config: ForkServerConfig,
+    /// Shared mapping of a [ChildReport] followed by the coverage map
+    shared_memory: *mut u8,
+    shared_memory_len: usize,
+}
+
+impl ForkServer {
+    /// Takes a loaded and verified executable
+    ///
+    /// `Config::enable_instruction_tracing` of the executable must be set to collect coverage.
+    pub fn new(
+        executable: Executable<FuzzContextObject>,
+        mut config: ForkServerConfig,
+    ) -> std::io::Result<Self> {
+        config.coverage_map_size = config.coverage_map_size.max(1).next_power_of_two();
+        let shared_memory_len =
+            std::mem::size_of::<ChildReport>().saturating_add(config.coverage_map_size);
+        // Safety: anonymous mapping without a hint address
+        let shared_memory = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                shared_memory_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if shared_memory == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self {
+            executable,
+            config,
+            shared_memory: shared_memory.cast::<u8>(),
+            shared_memory_len,
+        })
+    }
+
+    /// Edge coverage of the last run
+    pub fn coverage(&self) -> &[u8] {
+        // Safety: the coverage map follows the report in the shared memory
+        unsafe {
+            std::slice::from_raw_parts(
+                self.shared_memory.add(std::mem::size_of::<ChildReport>()),
+                self.config.coverage_map_size,
+            )
+        }
+    }
+
+    /// Executes the program on `input` in a forked child process
+    ///
+    /// The input is copied and mapped writable at `MM_INPUT_START`. No other thread of the
+    /// process may be running, see the [module documentation](self).
+    pub fn run(&mut self, input: &[u8]) -> std::io::Result<ForkOutcome> {
+        // Safety: the shared memory is only accessed by the child until it is reaped
+        unsafe {
+            std::ptr::write_bytes(self.shared_memory, 0, self.shared_memory_len);
+        }
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+        // Safety: the child only executes the program and leaves with `_exit`
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if pid == 0 {
+            let completed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                self.execute_in_child(input)
+            }))
+            .is_ok();
+            // Safety: leaves the child without running destructors or atexit handlers
+            unsafe {
+                if completed {
+                    libc::_exit(0);
+                }
+                libc::abort();
+            }
+        }
+        let status = match self.wait_for_child(pid)? {
+            Some(status) => status,
+            None => return Ok(ForkOutcome::TimedOut),
+        };
+        if libc::WIFSIGNALED(status) {
+            return Ok(ForkOutcome::Crashed(libc::WTERMSIG(status)));
+        }
+        // Safety: the child was reaped, the report is not written anymore
+        let report = unsafe { &*self.shared_memory.cast::<ChildReport>() };
+        Ok(match report.status {
+            1 => ForkOutcome::Returned {
+                return_value: report.return_value,
+                instruction_count: report.instruction_count,
+            },
+            2 => ForkOutcome::Failed {
+                error: String::from_utf8_lossy(
+                    &report.error[..(report.error_len as usize).min(ERROR_MESSAGE_LEN)],
+                )
+                .to_string(),
+                instruction_count: report.instruction_count,
+            },
+            _ => ForkOutcome::Aborted(libc::WEXITSTATUS(status)),
+        })
+    }
+
+    /// Reaps the child, returns None if it was killed because of the timeout
+    fn wait_for_child(&self, pid: libc::pid_t) -> std::io::Result<Option<i32>> {
+        let mut status = 0;
+        let Some(timeout) = self.config.timeout else {
+            // Safety: pid is a child of this process
+            if unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            return Ok(Some(status));
+        };
+        let start = Instant::now();
+        loop {
+            // Safety: pid is a child of this process
+            match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+                0 if start.elapsed() >= timeout => break,
+                0 => std::thread::sleep(POLL_INTERVAL),
+                result if result < 0 => return Err(std::io::Error::last_os_error()),
+                _ => return Ok(Some(status)),
+            }
+        }
+        // Safety: pid is a child of this process which was not reaped yet
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+            if libc::waitpid(pid, &mut status, 0) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(None)
+    }
+
+    fn execute_in_child(&self, input: &[u8]) {
+        let executable = &self.executable;
+        let config = executable.get_config();
+        let sbpf_version = executable.get_sbpf_version();
+        let mut stack = AlignedMemory::<{ ebpf::HOST_ALIGN }>::zero_filled(config.stack_size());
+        let mut heap = AlignedMemory::<{ ebpf::HOST_ALIGN }>::zero_filled(self.config.heap_size);
+        let mut input = AlignedMemory::<{ ebpf::HOST_ALIGN }>::from_slice(input);
+        let stack_len = stack.len();
+        let regions = vec![
+            executable.get_ro_region(),
+            MemoryRegion::new_writable_gapped(
+                stack.as_slice_mut(),
+                ebpf::MM_STACK_START,
+                if !sbpf_version.dynamic_stack_frames() && config.enable_stack_frame_gaps {
+                    config.stack_frame_size as u64
+                } else {
+                    0
+                },
+            ),
+            MemoryRegion::new_writable(heap.as_slice_mut(), ebpf::MM_HEAP_START),
+            MemoryRegion::new_writable(input.as_slice_mut(), ebpf::MM_INPUT_START),
+        ];
+        // Safety: the report is only written by the child
+        let report = unsafe { &mut *self.shared_memory.cast::<ChildReport>() };
+        let memory_mapping = match MemoryMapping::new(regions, config, sbpf_version) {
+            Ok(memory_mapping) => memory_mapping,
+            Err(error) => {
+                report.write_error(&format!("{:?}", error));
+                return;
+            }
+        };
+        let mut context_object = FuzzContextObject {
+            remaining: self.config.instruction_limit,
+            // Safety: the coverage map follows the report in the shared memory
+            coverage: unsafe { self.shared_memory.add(std::mem::size_of::<ChildReport>()) },
+            coverage_len: self.config.coverage_map_size,
+            previous_pc: 0,
+        };
+        let mut vm = EbpfVm::new(
+            executable.get_loader().clone(),
+            sbpf_version,
+            &mut context_object,
+            memory_mapping,
+            stack_len,
+        );
+        vm.registers[1] = ebpf::MM_INPUT_START;
+        let (instruction_count, result) = vm.execute_program(executable, self.config.interpreted);
+        report.instruction_count = instruction_count;
+        match result {
+            ProgramResult::Ok(return_value) => {
+                report.return_value = return_value;
+                report.status = 1;
+            }
+            ProgramResult::Err(error) => report.write_error(&format!("{:?}", error)),
+        }
+    }
+}
+
+impl ChildReport {
+    fn write_error(&mut self, message: &str) {
+        let len = message.len().min(ERROR_MESSAGE_LEN);
+        self.error[..len].copy_from_slice(&message.as_bytes()[..len]);
+        self.error_len = len as u64;
+        self.status = 2;
+    }
+}
+
+impl Drop for ForkServer {
+    fn drop(&mut self) {
+        // Safety: the mapping was created in new() and is not referenced anymore
+        unsafe {
+            libc::munmap(self.shared_memory.cast(), self.shared_memory_len);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Basic block hit counts across executions
+//!
+//! Fuzzers keep inputs which reach new code, but once a block was reached it no longer
+//! matters how rarely it is. A [GlobalCoverage] counts for every basic block in how many
+//! executions it was entered and scores each execution by the rarity of the blocks it entered,
+//! so a scheduler can prioritize inputs which keep exercising the seldom taken paths.
+
+use crate::block_trace::BlockTrace;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Number of executions which entered each basic block
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GlobalCoverage {
+    /// Pc of the first instruction of a basic block => number of executions which entered it
+    hits: BTreeMap<u64, u64>,
+    /// Number of recorded executions
+    executions: u64,
+}
+
+impl GlobalCoverage {
+    /// Records an execution which entered the basic blocks starting at `block_pcs`, returns its
+    /// rarity score
+    ///
+    /// The score is the sum of the inverse hit counts of the distinct blocks, including this
+    /// execution. So a block no other execution entered adds 1, while one which every
+    /// execution enters adds almost nothing.
+    pub fn record(&mut self, block_pcs: impl IntoIterator<Item = u64>) -> f64 {
+        self.executions = self.executions.saturating_add(1);
+        let block_pcs = block_pcs.into_iter().collect::<BTreeSet<_>>();
+        for pc in block_pcs.iter() {
+            let hits = self.hits.entry(*pc).or_insert(0);
+            *hits = hits.saturating_add(1);
+        }
+        self.rarity(block_pcs)
+    }
+
+    /// Records the blocks entered in a [BlockTrace], see [Self::record]
+    pub fn record_trace(&mut self, trace: &BlockTrace) -> f64 {
+        self.record(trace.entries.iter().map(|entry| entry.pc))
+    }
+
+    /// Rarity score of an execution which entered the basic blocks starting at `block_pcs`,
+    /// without recording it
+    ///
+    /// Blocks which were never entered count like entered once.
+    pub fn rarity(&self, block_pcs: impl IntoIterator<Item = u64>) -> f64 {
+        block_pcs
+            .into_iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|pc| 1.0 / self.hits(pc).max(1) as f64)
+            .sum()
+    }
+
+    /// Number of executions which entered the basic block starting at `pc`
+    pub fn hits(&self, pc: u64) -> u64 {
+        self.hits.get(&pc).copied().unwrap_or(0)
+    }
+
+    /// Pc of the first instruction of a basic block => number of executions which entered it
+    pub fn hit_counts(&self) -> &BTreeMap<u64, u64> {
+        &self.hits
+    }
+
+    /// Number of recorded executions
+    pub fn executions(&self) -> u64 {
+        self.executions
+    }
+
+    /// The `count` blocks with the fewest hits, in ascending order of their hits
+    pub fn rarest(&self, count: usize) -> Vec<(u64, u64)> {
+        let mut blocks = self
+            .hits
+            .iter()
+            .map(|(pc, hits)| (*pc, *hits))
+            .collect::<Vec<_>>();
+        blocks.sort_by_key(|(pc, hits)| (*hits, *pc));
+        blocks.truncate(count);
+        blocks
+    }
+
+    /// Forgets all executions, e.g. when the program under test changes
+    pub fn reset(&mut self) {
+        self.hits.clear();
+        self.executions = 0;
+    }
+}
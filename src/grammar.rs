@@ -0,0 +1,171 @@
+//! Input grammars for structure aware mutators
+//!
+//! An [InputGrammar] describes the fields of the input a program reads: Their offsets and
+//! widths come from the loads and comparisons found by [InputTaint], their names from the
+//! [SemanticMemory] of the execution, e.g. the account and field of an
+//! [AccountLayout](crate::accounts::AccountLayout). The constants a field was compared
+//! against hint at its valid values, and a field which was only ever compared for equality is
+//! likely an enum, like the discriminator of the instruction data. With the `trace-export`
+//! feature the grammar can be written as JSON, so mutators can be generated from it per
+//! program.
+
+use crate::{
+    ebpf,
+    semantic::SemanticMemory,
+    taint::{ConcolicOperand, InputOffsets, InputTaint},
+};
+#[cfg(feature = "trace-export")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// A field of the input
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-export", derive(Serialize, Deserialize))]
+pub struct GrammarField {
+    /// Input offset of the first byte
+    pub offset: u64,
+    /// Length in bytes
+    pub width: u64,
+    /// Name of the region the field belongs to, if it is tagged
+    pub region: Option<String>,
+    /// Index of the entry, e.g. the account
+    pub index: Option<usize>,
+    /// Name of the attribute, if the field is tagged
+    pub attribute: Option<String>,
+    /// Constants the field was compared against, in ascending order
+    pub constants: Vec<u64>,
+    /// The constants, if the field was only ever compared for equality
+    pub enum_candidates: Vec<u64>,
+}
+
+/// Fields of the input in ascending order of their offsets
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-export", derive(Serialize, Deserialize))]
+pub struct InputGrammar {
+    /// Disjoint fields in ascending order of their offsets
+    pub fields: Vec<GrammarField>,
+}
+
+/// Constant operand of a comparison of input bytes
+struct Comparison {
+    offsets: InputOffsets,
+    constant: u64,
+    is_equality: bool,
+}
+
+/// Splits a conditional jump into the compared input bytes and the constant operand
+fn comparison(opcode: u8, dst: &ConcolicOperand, src: &ConcolicOperand) -> Option<Comparison> {
+    let (tainted, constant) = match (
+        dst.labels.iter().any(Option::is_some),
+        src.labels.iter().any(Option::is_some),
+    ) {
+        (true, false) => (dst, src),
+        (false, true) => (src, dst),
+        _ => return None,
+    };
+    let labels = tainted.labels.iter().flatten();
+    let start = labels.clone().map(|offsets| offsets.start).min()?;
+    let end = labels.map(|offsets| offsets.end).max()?;
+    let width = tainted
+        .labels
+        .iter()
+        .rposition(Option::is_some)?
+        .saturating_add(1);
+    let mask = u64::MAX >> 64usize.saturating_sub(width.saturating_mul(8));
+    Some(Comparison {
+        offsets: start..end,
+        constant: constant.value & mask,
+        is_equality: matches!(
+            opcode,
+            ebpf::JEQ_IMM | ebpf::JEQ_REG | ebpf::JNE_IMM | ebpf::JNE_REG
+        ),
+    })
+}
+
+impl InputGrammar {
+    /// Derives the grammar from the taint of one or more [merged](InputTaint::merge)
+    /// executions
+    ///
+    /// The constants require the [InputTaint::path_constraints] of concolic mode. The input
+    /// has to be registered as the region at [ebpf::MM_INPUT_START] in `memory`.
+    pub fn derive(memory: &SemanticMemory, taint: &InputTaint) -> Self {
+        let comparisons = taint
+            .path_constraints
+            .iter()
+            .filter_map(|constraint| {
+                comparison(constraint.opcode, &constraint.dst, &constraint.src)
+            })
+            .collect::<Vec<_>>();
+        let mut ranges = taint
+            .tainted_loads
+            .values()
+            .cloned()
+            .chain(
+                comparisons
+                    .iter()
+                    .map(|comparison| comparison.offsets.clone()),
+            )
+            .filter(|offsets| !offsets.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|offsets| (offsets.start, offsets.end));
+        let mut merged: Vec<InputOffsets> = Vec::new();
+        for offsets in ranges {
+            match merged.last_mut() {
+                Some(last) if offsets.start < last.end => last.end = last.end.max(offsets.end),
+                _ => merged.push(offsets),
+            }
+        }
+        let fields = merged
+            .into_iter()
+            .map(|offsets| {
+                let tag = memory.lookup(ebpf::MM_INPUT_START.saturating_add(offsets.start));
+                let compared = comparisons.iter().filter(|comparison| {
+                    offsets.start <= comparison.offsets.start
+                        && comparison.offsets.end <= offsets.end
+                });
+                let constants = compared
+                    .clone()
+                    .map(|comparison| comparison.constant)
+                    .collect::<BTreeSet<_>>();
+                let is_enum = compared.clone().next().is_some()
+                    && compared.clone().all(|comparison| comparison.is_equality);
+                GrammarField {
+                    offset: offsets.start,
+                    width: offsets.end.saturating_sub(offsets.start),
+                    region: tag.as_ref().map(|tag| tag.region.clone()),
+                    index: tag.as_ref().and_then(|tag| tag.index),
+                    attribute: tag.map(|tag| tag.attribute),
+                    enum_candidates: if is_enum {
+                        constants.iter().copied().collect()
+                    } else {
+                        Vec::new()
+                    },
+                    constants: constants.into_iter().collect(),
+                }
+            })
+            .collect();
+        Self { fields }
+    }
+
+    /// Field which contains the byte at input `offset`
+    pub fn field_at(&self, offset: u64) -> Option<&GrammarField> {
+        let position = self
+            .fields
+            .partition_point(|field| field.offset.saturating_add(field.width) <= offset);
+        self.fields
+            .get(position)
+            .filter(|field| field.offset <= offset)
+    }
+
+    /// Writes the grammar as JSON
+    #[cfg(feature = "trace-export")]
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// Reads a grammar written by [Self::to_json]
+    #[cfg(feature = "trace-export")]
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
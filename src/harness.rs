@@ -0,0 +1,608 @@
+//! Generation of harness skeletons from ELFs
+//!
+//! Inspects an ELF statically and derives what is needed to run it the first time:
+//! The syscalls it calls, its entrypoint, the input deserializer it was built with
+//! and a lower bound of the input size it reads.
+//!
+//! A [Harness] builds on this to load, verify and execute an ELF for fuzzing in a single
+//! process, collecting edge coverage of every run. The [solana_fuzz_target] macro keeps one
+//! per thread, so it can be called from the body of a libFuzzer `fuzz_target!`.
+
+use crate::{
+    aligned_memory::AlignedMemory,
+    ebpf,
+    elf::{ElfError, Executable},
+    elf_parser::Elf64,
+    error::{EbpfError, ProgramResult},
+    memory_region::{MemoryMapping, MemoryRegion},
+    observer::{summarize_calls, FunctionCoverage},
+    opcode_table::{opcode_info, InstructionClass},
+    program::{BuiltinFunction, BuiltinProgram, SBPFVersion},
+    solana_input::InputBuilder,
+    static_analysis::Analysis,
+    verifier::RequisiteVerifier,
+    vm::{Config, ContextObject, EbpfVm},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+/// A syscall the program calls
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequiredSyscall {
+    /// Hash of the symbol name, as used in the call instructions
+    pub hash: u32,
+    /// Symbol name, if the ELF has a dynamic symbol for it
+    pub name: Option<String>,
+}
+
+/// Realloc padding after every account data in the aligned input layout
+const MAX_PERMITTED_DATA_INCREASE: i64 = 10 * 1024;
+
+/// Input deserializer used by the entrypoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntrypointKind {
+    /// The Anchor framework, which wraps the solana_program deserializer
+    Anchor,
+    /// The `solana_program::entrypoint!` deserializer
+    SolanaProgram,
+    /// No known pattern matched
+    Custom,
+}
+
+/// Serialization of the accounts in the input region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputLayout {
+    /// Account data is followed by realloc padding and aligned to 8 bytes
+    Aligned,
+    /// Unknown, the program has to be inspected manually
+    Unknown,
+}
+
+impl EntrypointKind {
+    /// Matches the entrypoint against known code patterns
+    ///
+    /// Anchor is recognized by its symbol names, so it requires
+    /// `Config::enable_symbol_and_section_labels`. The solana_program deserializer
+    /// is recognized by the realloc padding it skips in the entrypoint or a function it calls.
+    pub fn fingerprint<C: ContextObject>(executable: &Executable<C>, analysis: &Analysis) -> Self {
+        if analysis
+            .functions
+            .values()
+            .any(|(_key, name)| name.contains("anchor_lang"))
+        {
+            return Self::Anchor;
+        }
+        let sbpf_version = executable.get_sbpf_version();
+        let function_range = |entry: usize| function_instructions(analysis, entry);
+        let mut functions = vec![analysis.entrypoint];
+        functions.extend(
+            function_range(analysis.entrypoint)
+                .filter(|insn| insn.opc == ebpf::CALL_IMM)
+                .filter_map(|insn| {
+                    let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+                    executable
+                        .get_function_registry()
+                        .lookup_by_key(key)
+                        .map(|(_name, target_pc)| target_pc)
+                }),
+        );
+        if functions.into_iter().flat_map(function_range).any(|insn| {
+            matches!(insn.opc, ebpf::ADD64_IMM | ebpf::MOV64_IMM)
+                && insn.imm == MAX_PERMITTED_DATA_INCREASE
+        }) {
+            return Self::SolanaProgram;
+        }
+        Self::Custom
+    }
+
+    /// Layout of the input the deserializer expects
+    pub fn input_layout(self) -> InputLayout {
+        match self {
+            Self::Anchor | Self::SolanaProgram => InputLayout::Aligned,
+            Self::Custom => InputLayout::Unknown,
+        }
+    }
+}
+
+/// Configuration to execute an ELF with
+#[derive(Debug, Clone)]
+pub struct HarnessSkeleton {
+    /// Version the ELF was built for
+    pub sbpf_version: SBPFVersion,
+    /// Instruction offset of the entrypoint
+    pub entrypoint: usize,
+    /// Name of the entrypoint function, if known
+    pub entrypoint_name: Option<String>,
+    /// Input deserializer of the entrypoint
+    pub entrypoint_kind: EntrypointKind,
+    /// Syscalls which need to be registered in the loader, sorted by hash
+    pub required_syscalls: Vec<RequiredSyscall>,
+    /// Highest offset accessed relative to the input pointer (r1) in the entrypoint function
+    ///
+    /// This is a lower bound of the input size, as only direct accesses are considered.
+    pub min_input_size: u64,
+    /// Config which accepts the ELF
+    pub config: Config,
+}
+
+impl HarnessSkeleton {
+    /// Inspects an ELF
+    ///
+    /// The ELF is loaded with `config`, but without rejecting unresolved syscalls.
+    pub fn from_elf<C: ContextObject>(elf_bytes: &[u8], config: Config) -> Result<Self, EbpfError> {
+        let loader = Arc::new(BuiltinProgram::<C>::new_loader(Config {
+            reject_broken_elfs: false,
+            enable_symbol_and_section_labels: true,
+            ..config.clone()
+        }));
+        let executable = Executable::<C>::load(elf_bytes, loader).map_err(EbpfError::ElfError)?;
+        let sbpf_version = executable.get_sbpf_version();
+        let analysis = Analysis::from_executable(&executable)?;
+
+        let mut symbol_names = BTreeMap::new();
+        if let Ok(elf) = Elf64::parse(elf_bytes) {
+            for symbol in elf.dynamic_symbol_table().unwrap_or_default() {
+                if symbol.st_value != 0 {
+                    continue;
+                }
+                if let Ok(name) = elf.dynamic_symbol_name(symbol.st_name) {
+                    if !name.is_empty() {
+                        symbol_names.insert(
+                            ebpf::hash_symbol_name(name),
+                            String::from_utf8_lossy(name).to_string(),
+                        );
+                    }
+                }
+            }
+        }
+        let mut required_syscalls = BTreeMap::new();
+        for insn in analysis.instructions.iter() {
+            let hash = match insn.opc {
+                ebpf::SYSCALL if sbpf_version.static_syscalls() => insn.imm as u32,
+                ebpf::CALL_IMM if !sbpf_version.static_syscalls() => {
+                    let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+                    if executable
+                        .get_function_registry()
+                        .lookup_by_key(key)
+                        .is_some()
+                    {
+                        continue;
+                    }
+                    insn.imm as u32
+                }
+                _ => continue,
+            };
+            required_syscalls
+                .entry(hash)
+                .or_insert_with(|| RequiredSyscall {
+                    hash,
+                    name: symbol_names.get(&hash).cloned(),
+                });
+        }
+
+        let entrypoint = analysis.entrypoint;
+        let entrypoint_name = analysis
+            .functions
+            .get(&entrypoint)
+            .map(|(_key, name)| name.clone());
+        let min_input_size =
+            input_accesses(sbpf_version, function_instructions(&analysis, entrypoint));
+
+        Ok(Self {
+            sbpf_version,
+            entrypoint,
+            entrypoint_name,
+            entrypoint_kind: EntrypointKind::fingerprint(&executable, &analysis),
+            required_syscalls: required_syscalls.into_values().collect(),
+            min_input_size,
+            config: Config {
+                enabled_sbpf_versions: sbpf_version..=sbpf_version,
+                ..config
+            },
+        })
+    }
+
+    /// Fails with `UnresolvedSymbol` if the loader lacks any of the required syscalls
+    pub fn check_loader<C: ContextObject>(
+        &self,
+        loader: &BuiltinProgram<C>,
+    ) -> Result<(), ElfError> {
+        for syscall in self.required_syscalls.iter() {
+            if loader
+                .get_function_registry()
+                .lookup_by_key(syscall.hash)
+                .is_none()
+            {
+                return Err(ElfError::UnresolvedSymbol(
+                    syscall
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("{:#x}", syscall.hash)),
+                    0,
+                    0,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Context object of [Harness] runs, which records edge and call coverage
+#[derive(Debug, Clone)]
+pub struct HarnessContextObject {
+    /// Remaining instruction budget
+    pub remaining: u64,
+    /// Hit counters, indexed by edge hash
+    coverage: Vec<u8>,
+    /// Pc of the previously traced instruction
+    previous_pc: u64,
+    /// Rolling hash of the edges in the order they were taken
+    coverage_hash: u64,
+    /// Pcs of the instructions calling functions
+    call_sites: BTreeSet<u64>,
+    /// Pc of the previously traced instruction, if it is a call site
+    pending_call: Option<u64>,
+    /// (caller pc, callee pc) => number of calls
+    calls: BTreeMap<(u64, u64), u64>,
+}
+
+impl ContextObject for HarnessContextObject {
+    fn trace(&mut self, state: [u64; 12]) {
+        let pc = state[11];
+        let edge = (self.previous_pc >> 1) ^ pc;
+        let index = edge as usize & self.coverage.len().saturating_sub(1);
+        if let Some(counter) = self.coverage.get_mut(index) {
+            *counter = counter.wrapping_add(1);
+        }
+        // xorshift64, which is a bijection, so the hash depends on every edge and their order
+        let mut hash = self.coverage_hash ^ edge;
+        hash ^= hash << 13;
+        hash ^= hash >> 7;
+        hash ^= hash << 17;
+        self.coverage_hash = hash;
+        self.previous_pc = pc;
+        // Syscalls continue after the call instruction
+        if let Some(call_site) = self.pending_call.take() {
+            if pc != call_site.saturating_add(1) {
+                let calls = self.calls.entry((call_site, pc)).or_insert(0);
+                *calls = calls.saturating_add(1);
+            }
+        }
+        if self.call_sites.contains(&pc) {
+            self.pending_call = Some(pc);
+        }
+    }
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+/// Builder of a [Harness]
+#[derive(Debug, Clone)]
+pub struct HarnessBuilder {
+    config: Config,
+    syscalls: Vec<(String, BuiltinFunction<HarnessContextObject>)>,
+    instruction_budget: u64,
+    coverage_map_size: usize,
+    jit: bool,
+}
+
+impl Default for HarnessBuilder {
+    fn default() -> Self {
+        Self {
+            config: Config::default(),
+            syscalls: Vec::new(),
+            instruction_budget: 200_000,
+            coverage_map_size: 1 << 16,
+            jit: false,
+        }
+    }
+}
+
+impl HarnessBuilder {
+    /// Config to load the ELF with, the version is narrowed down to the one of the ELF
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Registers a syscall in the loader
+    pub fn syscall(mut self, name: &str, function: BuiltinFunction<HarnessContextObject>) -> Self {
+        self.syscalls.push((name.to_string(), function));
+        self
+    }
+
+    /// Instruction budget of every run
+    pub fn instruction_budget(mut self, instruction_budget: u64) -> Self {
+        self.instruction_budget = instruction_budget;
+        self
+    }
+
+    /// Number of edge counters, rounded up to a power of two
+    pub fn coverage_map_size(mut self, coverage_map_size: usize) -> Self {
+        self.coverage_map_size = coverage_map_size.max(1).next_power_of_two();
+        self
+    }
+
+    /// Executes with the JIT where it is available instead of the interpreter
+    pub fn jit(mut self, jit: bool) -> Self {
+        self.jit = jit;
+        self
+    }
+
+    /// Loads and verifies the ELF
+    ///
+    /// Fails with `UnresolvedSymbol` if a syscall the ELF calls was not registered.
+    pub fn build(self, elf_bytes: &[u8]) -> Result<Harness, EbpfError> {
+        let skeleton = HarnessSkeleton::from_elf::<HarnessContextObject>(elf_bytes, self.config)?;
+        let mut loader = BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..skeleton.config.clone()
+        });
+        for (name, function) in self.syscalls {
+            loader
+                .register_function(&name, function)
+                .map_err(EbpfError::ElfError)?;
+        }
+        skeleton
+            .check_loader(&loader)
+            .map_err(EbpfError::ElfError)?;
+        #[allow(unused_mut)]
+        let mut executable =
+            Executable::load(elf_bytes, Arc::new(loader)).map_err(EbpfError::ElfError)?;
+        executable.verify::<RequisiteVerifier>()?;
+        let call_sites = Analysis::from_executable(&executable)?
+            .instructions
+            .iter()
+            .filter(|insn| matches!(insn.opc, ebpf::CALL_IMM | ebpf::CALL_REG))
+            .map(|insn| insn.ptr as u64)
+            .collect();
+        #[allow(unused_mut)]
+        let mut interpreted = true;
+        #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+        if self.jit {
+            executable.jit_compile()?;
+            interpreted = false;
+        }
+        Ok(Harness {
+            executable,
+            context_object: HarnessContextObject {
+                remaining: self.instruction_budget,
+                coverage: vec![0; self.coverage_map_size],
+                previous_pc: 0,
+                coverage_hash: 0,
+                call_sites,
+                pending_call: None,
+                calls: BTreeMap::new(),
+            },
+            instruction_budget: self.instruction_budget,
+            interpreted,
+        })
+    }
+}
+
+/// A loaded and verified ELF which is executed on one input after another
+#[derive(Debug)]
+pub struct Harness {
+    executable: Executable<HarnessContextObject>,
+    context_object: HarnessContextObject,
+    instruction_budget: u64,
+    interpreted: bool,
+}
+
+impl Harness {
+    /// Executes the program with a copy of `input` in the input region
+    ///
+    /// The coverage of the previous run is reset.
+    pub fn run(&mut self, input: &[u8]) -> ProgramResult {
+        self.context_object.remaining = self.instruction_budget;
+        self.context_object.coverage.fill(0);
+        self.context_object.previous_pc = 0;
+        self.context_object.coverage_hash = 0;
+        self.context_object.pending_call = None;
+        self.context_object.calls.clear();
+        execute_with_input(
+            &self.executable,
+            &mut self.context_object,
+            &[(1, ebpf::MM_INPUT_START)],
+            &mut input.to_vec(),
+            self.interpreted,
+        )
+    }
+
+    /// Serializes the accounts and executes the program with them
+    pub fn run_accounts(&mut self, input: &InputBuilder) -> ProgramResult {
+        self.run(input.build().input.as_slice())
+    }
+
+    /// Edge coverage of the last run
+    pub fn coverage(&self) -> &[u8] {
+        &self.context_object.coverage
+    }
+
+    /// Hash of the edges taken in the last run, in the order they were taken
+    ///
+    /// Maintained during the run, so comparing it is a cheap way to discard inputs which
+    /// behaved exactly like a previous one. Unlike [coverage_digest](crate::corpus::coverage_digest)
+    /// it also tells apart different numbers of loop iterations.
+    pub fn coverage_hash(&self) -> u64 {
+        self.context_object.coverage_hash
+    }
+
+    /// (caller pc, callee pc) => number of calls in the last run
+    pub fn calls(&self) -> &BTreeMap<(u64, u64), u64> {
+        &self.context_object.calls
+    }
+
+    /// Calls per function in the last run, e.g. to minimize a corpus by function reachability
+    pub fn function_coverage(&self) -> BTreeMap<u64, FunctionCoverage> {
+        summarize_calls(&self.executable, &self.context_object.calls)
+    }
+
+    /// Number of instructions executed in the last run
+    ///
+    /// With a [CostModel](crate::cost_model::CostModel) these are the compute units charged.
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_budget
+            .saturating_sub(self.context_object.remaining)
+    }
+
+    /// The loaded executable
+    pub fn executable(&self) -> &Executable<HarnessContextObject> {
+        &self.executable
+    }
+}
+
+/// Runs an input through a [Harness] which is created once per thread
+///
+/// The first argument creates the harness, the second is the input. Evaluates to the
+/// [ProgramResult](crate::error::ProgramResult) of the run.
+///
+/// ```ignore
+/// fuzz_target!(|data: &[u8]| {
+///     let _ = solana_sbpf::solana_fuzz_target!(
+///         HarnessBuilder::default().build(include_bytes!("program.so")).unwrap(),
+///         data
+///     );
+/// });
+/// ```
+#[macro_export]
+macro_rules! solana_fuzz_target {
+    ($harness:expr, $input:expr $(,)?) => {{
+        ::std::thread_local! {
+            static HARNESS: ::std::cell::RefCell<$crate::harness::Harness> =
+                ::std::cell::RefCell::new($harness);
+        }
+        HARNESS.with(|harness| harness.borrow_mut().run($input))
+    }};
+}
+
+/// Instructions of the function starting at `entry`, up to the next function
+fn function_instructions<'a>(
+    analysis: &'a Analysis,
+    entry: usize,
+) -> impl Iterator<Item = &'a ebpf::Insn> + 'a {
+    let end = analysis
+        .functions
+        .range(entry.saturating_add(1)..)
+        .next()
+        .map(|(pc, _function)| *pc)
+        .unwrap_or(usize::MAX);
+    analysis
+        .instructions
+        .iter()
+        .skip_while(move |insn| insn.ptr < entry)
+        .take_while(move |insn| insn.ptr < end)
+}
+
+/// Executes with a fresh stack, an empty heap and a copy of `input` at [ebpf::MM_INPUT_START]
+///
+/// The registers are set before the execution starts and the input is written back afterwards.
+pub(crate) fn execute_with_input<C: ContextObject>(
+    executable: &Executable<C>,
+    context_object: &mut C,
+    registers: &[(usize, u64)],
+    input: &mut [u8],
+    interpreted: bool,
+) -> ProgramResult {
+    let config = executable.get_config();
+    let sbpf_version = executable.get_sbpf_version();
+    let mut stack = AlignedMemory::<{ ebpf::HOST_ALIGN }>::zero_filled(config.stack_size());
+    let mut aligned_input = AlignedMemory::<{ ebpf::HOST_ALIGN }>::from_slice(input);
+    let stack_len = stack.len();
+    let regions = vec![
+        executable.get_ro_region(),
+        MemoryRegion::new_writable_gapped(
+            stack.as_slice_mut(),
+            ebpf::MM_STACK_START,
+            if !sbpf_version.dynamic_stack_frames() && config.enable_stack_frame_gaps {
+                config.stack_frame_size as u64
+            } else {
+                0
+            },
+        ),
+        MemoryRegion::new_writable(&mut [], ebpf::MM_HEAP_START),
+        MemoryRegion::new_writable(aligned_input.as_slice_mut(), ebpf::MM_INPUT_START),
+    ];
+    let memory_mapping = match MemoryMapping::new(regions, config, sbpf_version) {
+        Ok(memory_mapping) => memory_mapping,
+        Err(error) => return ProgramResult::Err(error),
+    };
+    let mut vm = EbpfVm::new(
+        executable.get_loader().clone(),
+        sbpf_version,
+        context_object,
+        memory_mapping,
+        stack_len,
+    );
+    for (register, value) in registers {
+        vm.registers[*register] = *value;
+    }
+    let (_instruction_count, result) = vm.execute_program(executable, interpreted);
+    input.copy_from_slice(aligned_input.as_slice());
+    result
+}
+
+/// Whether it is a load, base register and size of a memory access
+pub(crate) fn memory_access(
+    sbpf_version: SBPFVersion,
+    insn: &ebpf::Insn,
+) -> Option<(bool, u8, u64)> {
+    let info = opcode_info(insn.opc, sbpf_version).filter(|info| info.is_memory_access())?;
+    let is_load = info.class == InstructionClass::Load;
+    Some((
+        is_load,
+        if is_load { insn.src } else { insn.dst },
+        info.width as u64,
+    ))
+}
+
+/// Highest end of the accesses relative to r1 and its copies, in program order
+fn input_accesses<'a, I: Iterator<Item = &'a ebpf::Insn>>(
+    sbpf_version: SBPFVersion,
+    instructions: I,
+) -> u64 {
+    let mut aliases = [false; 11];
+    aliases[1] = true;
+    let mut end = 0u64;
+    for insn in instructions {
+        if let Some((_is_load, base, size)) = memory_access(sbpf_version, insn) {
+            if aliases.get(base as usize).copied().unwrap_or(false) && insn.off >= 0 {
+                end = end.max((insn.off as u64).saturating_add(size));
+            }
+        }
+        match insn.opc {
+            ebpf::MOV64_REG => {
+                let alias = aliases.get(insn.src as usize).copied().unwrap_or(false);
+                if let Some(dst) = aliases.get_mut(insn.dst as usize) {
+                    *dst = alias;
+                }
+            }
+            ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::SYSCALL => {
+                aliases[1..=5].iter_mut().for_each(|alias| *alias = false);
+            }
+            _ if (insn.opc & ebpf::BPF_CLS_MASK) == ebpf::BPF_JMP => {}
+            _ => {
+                let writes_dst = memory_access(sbpf_version, insn)
+                    .map(|(is_load, _base, _size)| is_load)
+                    .unwrap_or(true);
+                if writes_dst {
+                    if let Some(alias) = aliases.get_mut(insn.dst as usize) {
+                        *alias = false;
+                    }
+                }
+            }
+        }
+        if aliases.iter().all(|alias| !alias) {
+            break;
+        }
+    }
+    end
+}
@@ -0,0 +1,197 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Instrumented heap with red zones and use after free detection
+//!
+//! A [HeapSanitizer] takes over the heap region of a [MemoryMapping]: Allocations are
+//! handed out by the [SyscallSanitizedAllocFree] syscall, surrounded by poisoned red zones.
+//! Freed allocations stay poisoned forever (the allocator never reuses memory), as does the
+//! part of the heap which was not allocated yet. Every load and store through the mapping
+//! which touches a poisoned byte fails with [EbpfError::HeapPoisonAccess].
+//!
+//! This only works for programs which allocate through the syscall,
+//! as opposed to a bump allocator inside the program itself.
+
+use crate::{
+    declare_builtin_function,
+    error::EbpfError,
+    memory_region::{AccessType, MemoryMapping},
+    vm::ContextObject,
+};
+use std::collections::BTreeMap;
+
+/// State of a byte in the heap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shadow {
+    /// Not handed out by the allocator yet
+    Unallocated,
+    /// Padding around an allocation
+    RedZone,
+    /// Part of a live allocation
+    Addressable,
+    /// Part of a freed allocation
+    Freed,
+}
+
+impl Shadow {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Unallocated => "unallocated",
+            Self::RedZone => "red zone",
+            Self::Addressable => "addressable",
+            Self::Freed => "freed",
+        }
+    }
+}
+
+/// Poison tracking allocator of the heap region
+#[derive(Debug)]
+pub struct HeapSanitizer {
+    /// Start of the heap region
+    vm_addr: u64,
+    /// Size of the red zones before and after every allocation
+    red_zone_size: u64,
+    /// One entry per byte of the heap region
+    shadow: Vec<Shadow>,
+    /// Offset of the next allocation (before its leading red zone)
+    next_offset: u64,
+    /// Size of the live allocations by their address
+    allocations: BTreeMap<u64, u64>,
+}
+
+impl HeapSanitizer {
+    /// Default size of the red zones
+    pub const DEFAULT_RED_ZONE_SIZE: u64 = 16;
+
+    /// Creates an allocator for a heap region starting at `vm_addr` which is `len` bytes long
+    pub fn new(vm_addr: u64, len: u64, red_zone_size: u64) -> Self {
+        Self {
+            vm_addr,
+            red_zone_size,
+            shadow: vec![Shadow::Unallocated; len as usize],
+            next_offset: 0,
+            allocations: BTreeMap::new(),
+        }
+    }
+
+    /// Allocates `size` bytes aligned to `align`, `None` if the heap is exhausted
+    pub fn allocate(&mut self, size: u64, align: u64) -> Option<u64> {
+        let align = align.max(1);
+        if !align.is_power_of_two() {
+            return None;
+        }
+        let leading_red_zone = self.next_offset;
+        let start = self
+            .vm_addr
+            .checked_add(leading_red_zone)?
+            .checked_add(self.red_zone_size)?
+            .checked_next_multiple_of(align)?
+            .checked_sub(self.vm_addr)?;
+        let end = start.checked_add(size)?;
+        let trailing_red_zone = end.checked_add(self.red_zone_size)?;
+        if trailing_red_zone > self.shadow.len() as u64 {
+            return None;
+        }
+        self.poison(leading_red_zone..start, Shadow::RedZone);
+        self.poison(start..end, Shadow::Addressable);
+        self.poison(end..trailing_red_zone, Shadow::RedZone);
+        self.next_offset = trailing_red_zone;
+        let vm_addr = self.vm_addr.saturating_add(start);
+        self.allocations.insert(vm_addr, size);
+        Some(vm_addr)
+    }
+
+    /// Frees the allocation at `vm_addr`
+    ///
+    /// Fails on double frees and addresses which were not returned by [Self::allocate].
+    pub fn free(&mut self, vm_addr: u64) -> Result<(), EbpfError> {
+        match self.allocations.remove(&vm_addr) {
+            Some(size) => {
+                let start = vm_addr.saturating_sub(self.vm_addr);
+                self.poison(start..start.saturating_add(size), Shadow::Freed);
+                Ok(())
+            }
+            None => Err(EbpfError::HeapPoisonAccess(
+                AccessType::Store,
+                vm_addr,
+                0,
+                self.shadow_at(vm_addr)
+                    .unwrap_or(Shadow::Unallocated)
+                    .name(),
+            )),
+        }
+    }
+
+    /// Fails if the access touches any poisoned byte of the heap
+    pub fn check(&self, access_type: AccessType, vm_addr: u64, len: u64) -> Result<(), EbpfError> {
+        let heap_end = self.vm_addr.saturating_add(self.shadow.len() as u64);
+        let start = vm_addr.max(self.vm_addr);
+        let end = vm_addr.saturating_add(len).min(heap_end);
+        if start >= end {
+            return Ok(());
+        }
+        let range =
+            start.saturating_sub(self.vm_addr) as usize..end.saturating_sub(self.vm_addr) as usize;
+        let poisoned = self
+            .shadow
+            .get(range)
+            .unwrap_or_default()
+            .iter()
+            .find(|shadow| **shadow != Shadow::Addressable);
+        match poisoned {
+            Some(shadow) => Err(EbpfError::HeapPoisonAccess(
+                access_type,
+                vm_addr,
+                len,
+                shadow.name(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Number of live allocations, e.g. to find leaks after an execution
+    pub fn live_allocations(&self) -> usize {
+        self.allocations.len()
+    }
+
+    fn shadow_at(&self, vm_addr: u64) -> Option<Shadow> {
+        self.shadow
+            .get(vm_addr.checked_sub(self.vm_addr)? as usize)
+            .copied()
+    }
+
+    fn poison(&mut self, range: std::ops::Range<u64>, shadow: Shadow) {
+        if let Some(bytes) = self
+            .shadow
+            .get_mut(range.start as usize..range.end as usize)
+        {
+            bytes.fill(shadow);
+        }
+    }
+}
+
+declare_builtin_function!(
+    /// Allocates `size` bytes if `free_addr` is 0, otherwise frees `free_addr`
+    ///
+    /// Same interface as `sol_alloc_free_`, returns the address of the allocation
+    /// or 0 if the heap is exhausted. Requires [MemoryMapping::set_heap_sanitizer].
+    SyscallSanitizedAllocFree<C: ContextObject>,
+    fn rust(
+        _context_object: &mut C,
+        size: u64,
+        free_addr: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let Some(heap_sanitizer) = memory_mapping.heap_sanitizer() else {
+            return Ok(0);
+        };
+        let mut heap_sanitizer = heap_sanitizer.borrow_mut();
+        if free_addr == 0 {
+            Ok(heap_sanitizer.allocate(size, 8).unwrap_or(0))
+        } else {
+            heap_sanitizer.free(free_addr)?;
+            Ok(0)
+        }
+    }
+);
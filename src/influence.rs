@@ -0,0 +1,190 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Influence of the input bytes on the branches
+//!
+//! An [InfluenceMatrix] counts for every pair of input byte offset and conditional jump (or
+//! `sol_memcmp_` syscall) in how many executions the comparison depended on the byte, according
+//! to [InputTaint::tainted_comparisons]. A mutation engine trying to flip a specific branch
+//! can then concentrate on the bytes which influenced it most often. The matrix is sparse and
+//! serializes to a compact binary format, so it can be handed to external engines.
+
+use crate::{
+    corpus::{read_varint, take, write_varint},
+    taint::InputTaint,
+};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+/// Version of the format, incremented on every incompatible change
+pub const INFLUENCE_FORMAT_VERSION: u32 = 1;
+
+/// Magic number of serialized [InfluenceMatrix]es
+const INFLUENCE_MAGIC: &[u8; 8] = b"SBPFINFL";
+
+/// Error definitions
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum InfluenceMatrixError {
+    /// Not a serialized influence matrix
+    #[error("invalid magic number")]
+    InvalidMagic,
+    /// The matrix was written with a different format version
+    #[error("unsupported influence matrix format version {0}")]
+    UnsupportedFormatVersion(u32),
+    /// The bytes are truncated or contain invalid values
+    #[error("malformed influence matrix")]
+    Malformed,
+}
+
+/// Sparse matrix of input byte offset × branch pc influence counts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InfluenceMatrix {
+    /// Branch pc => input byte offset => number of executions in which it influenced the branch
+    branches: BTreeMap<u64, BTreeMap<u64, u64>>,
+    /// Number of recorded executions
+    executions: u64,
+}
+
+impl InfluenceMatrix {
+    /// Records the comparisons of an execution which depended on the input
+    pub fn record(&mut self, taint: &InputTaint) {
+        self.executions = self.executions.saturating_add(1);
+        for (pc, offsets) in taint.tainted_comparisons.iter() {
+            let bytes = self.branches.entry(*pc as u64).or_default();
+            for offset in offsets.clone() {
+                let count = bytes.entry(offset).or_insert(0);
+                *count = count.saturating_add(1);
+            }
+        }
+    }
+
+    /// Adds the counts of another matrix
+    pub fn merge(&mut self, other: &Self) {
+        self.executions = self.executions.saturating_add(other.executions);
+        for (pc, other_bytes) in other.branches.iter() {
+            let bytes = self.branches.entry(*pc).or_default();
+            for (offset, other_count) in other_bytes.iter() {
+                let count = bytes.entry(*offset).or_insert(0);
+                *count = count.saturating_add(*other_count);
+            }
+        }
+    }
+
+    /// Number of executions in which the input byte at `offset` influenced the branch at `pc`
+    pub fn count(&self, offset: u64, pc: u64) -> u64 {
+        self.branches
+            .get(&pc)
+            .and_then(|bytes| bytes.get(&offset))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Input byte offsets which influenced the branch at `pc` with their counts, the most
+    /// frequent first
+    pub fn bytes_influencing(&self, pc: u64) -> Vec<(u64, u64)> {
+        let mut bytes = self
+            .branches
+            .get(&pc)
+            .map(|bytes| {
+                bytes
+                    .iter()
+                    .map(|(offset, count)| (*offset, *count))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        bytes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        bytes
+    }
+
+    /// Pcs of the branches the input byte at `offset` influenced with their counts, in
+    /// ascending order of the pcs
+    pub fn branches_influenced_by(&self, offset: u64) -> Vec<(u64, u64)> {
+        self.branches
+            .iter()
+            .filter_map(|(pc, bytes)| Some((*pc, *bytes.get(&offset)?)))
+            .collect()
+    }
+
+    /// Number of non zero entries
+    pub fn len(&self) -> usize {
+        self.branches.values().map(BTreeMap::len).sum()
+    }
+
+    /// Whether no comparison depended on the input so far
+    pub fn is_empty(&self) -> bool {
+        self.branches.is_empty()
+    }
+
+    /// Number of recorded executions
+    pub fn executions(&self) -> u64 {
+        self.executions
+    }
+
+    /// Serializes the matrix
+    ///
+    /// After the magic number and format version follow the number of executions and of
+    /// branches, then per branch the delta of its pc to the previous branch and the number of
+    /// its entries, each of which is the delta of the offset to the previous entry followed by
+    /// the count. All numbers after the format version are LEB128 encoded.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = INFLUENCE_MAGIC.to_vec();
+        bytes.extend_from_slice(&INFLUENCE_FORMAT_VERSION.to_le_bytes());
+        write_varint(&mut bytes, self.executions);
+        write_varint(&mut bytes, self.branches.len() as u64);
+        let mut previous_pc = 0;
+        for (pc, entries) in self.branches.iter() {
+            write_varint(&mut bytes, pc - previous_pc);
+            write_varint(&mut bytes, entries.len() as u64);
+            let mut previous_offset = 0;
+            for (offset, count) in entries.iter() {
+                write_varint(&mut bytes, offset - previous_offset);
+                write_varint(&mut bytes, *count);
+                previous_offset = *offset;
+            }
+            previous_pc = *pc;
+        }
+        bytes
+    }
+
+    /// Deserializes a matrix written by [Self::to_bytes]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InfluenceMatrixError> {
+        let mut reader = bytes
+            .strip_prefix(INFLUENCE_MAGIC)
+            .ok_or(InfluenceMatrixError::InvalidMagic)?;
+        let format_version = take(&mut reader, 4)
+            .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+            .map(u32::from_le_bytes)
+            .ok_or(InfluenceMatrixError::Malformed)?;
+        if format_version != INFLUENCE_FORMAT_VERSION {
+            return Err(InfluenceMatrixError::UnsupportedFormatVersion(
+                format_version,
+            ));
+        }
+        let parse = |mut reader: &[u8]| -> Option<Self> {
+            let executions = read_varint(&mut reader)?;
+            let mut branches = BTreeMap::new();
+            let mut pc = 0u64;
+            for index in 0..read_varint(&mut reader)? {
+                let delta = read_varint(&mut reader)?;
+                // Pcs are strictly ascending, only the first may have a delta of 0
+                if index > 0 && delta == 0 {
+                    return None;
+                }
+                pc = pc.checked_add(delta)?;
+                let mut entries = BTreeMap::new();
+                let mut offset = 0u64;
+                for index in 0..read_varint(&mut reader)? {
+                    let delta = read_varint(&mut reader)?;
+                    if index > 0 && delta == 0 {
+                        return None;
+                    }
+                    offset = offset.checked_add(delta)?;
+                    entries.insert(offset, read_varint(&mut reader)?);
+                }
+                branches.insert(pc, entries);
+            }
+            reader.is_empty().then_some(Self {
+                branches,
+                executions,
+            })
+        };
+        parse(reader).ok_or(InfluenceMatrixError::Malformed)
+    }
+}
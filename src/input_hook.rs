@@ -0,0 +1,76 @@
+//! Mutation of the input region during execution
+//!
+//! An [InputHook] set in [crate::vm::EbpfVm::input_hook] is consulted by the interpreter before
+//! every load from the input region and can override the loaded bytes. This allows fuzzers to
+//! mutate or lazily synthesize the input while the program runs, instead of serializing a new
+//! input buffer for every attempt. The input region itself is not modified, so the hook has to
+//! return the same bytes for repeated loads if the program expects them to be stable. Only the
+//! interpreter consults the hook, loads inside of syscalls are not intercepted.
+//!
+//! [AttributeOverrides] resolves the bytes to their [SemanticTag] and overrides the ones of
+//! selected attributes, e.g. the lamports of all accounts.
+
+use crate::semantic::{SemanticMemory, SemanticTag};
+use std::fmt::Debug;
+
+/// Intercepts loads from the input region
+pub trait InputHook {
+    /// Called before the instruction at `pc` loads `bytes` from `vm_addr`
+    ///
+    /// The bytes hold the contents of the input region and can be modified to override the
+    /// loaded value.
+    fn on_input_load(&mut self, pc: u64, vm_addr: u64, bytes: &mut [u8]);
+}
+
+/// Generates the value of a byte of an overridden attribute
+pub type ByteGenerator = Box<dyn FnMut(&SemanticTag) -> u8>;
+
+/// Overrides the bytes of the attributes of a [SemanticMemory]
+pub struct AttributeOverrides {
+    memory: SemanticMemory,
+    overrides: Vec<(String, String, ByteGenerator)>,
+}
+
+impl AttributeOverrides {
+    /// Creates a hook without overrides which tags addresses with `memory`
+    pub fn new(memory: SemanticMemory) -> Self {
+        Self {
+            memory,
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Overrides the bytes of an attribute in the region named `region`
+    ///
+    /// `generator` is called with the tag of every loaded byte of the attribute. The first
+    /// override registered for an attribute takes precedence.
+    pub fn with_override(
+        mut self,
+        region: impl Into<String>,
+        attribute: impl Debug,
+        generator: impl FnMut(&SemanticTag) -> u8 + 'static,
+    ) -> Self {
+        self.overrides
+            .push((region.into(), format!("{attribute:?}"), Box::new(generator)));
+        self
+    }
+}
+
+impl InputHook for AttributeOverrides {
+    fn on_input_load(&mut self, _pc: u64, vm_addr: u64, bytes: &mut [u8]) {
+        for (byte, vm_addr) in bytes.iter_mut().zip(vm_addr..) {
+            let Some(tag) = self.memory.lookup(vm_addr) else {
+                continue;
+            };
+            if let Some((_region, _attribute, generator)) =
+                self.overrides
+                    .iter_mut()
+                    .find(|(region, attribute, _generator)| {
+                        *region == tag.region && *attribute == tag.attribute
+                    })
+            {
+                *byte = generator(&tag);
+            }
+        }
+    }
+}
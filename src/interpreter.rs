@@ -16,9 +16,17 @@ use crate::{
     ebpf,
     elf::Executable,
     error::{EbpfError, ProgramResult},
+    fault_injection::{FaultAction, InjectedSyscallError},
+    memory_region::AccessType,
+    observer::{CallFrameEvent, ExecutionObserver, FailedObserver},
+    opcode_table::{opcode_info, InstructionClass, OpcodeInfo, OperandSource},
     program::BuiltinFunction,
-    vm::{Config, ContextObject, EbpfVm},
+    vm::{
+        panic_message, Config, ContextObject, EbpfVm, InstrumentationComponent,
+        InstrumentationFailure, StackFrame,
+    },
 };
+use std::panic::{catch_unwind, AssertUnwindSafe};
 
 /// Virtual memory operation helper.
 macro_rules! translate_memory_access {
@@ -35,9 +43,14 @@ macro_rules! translate_memory_access {
     };
 
     // MemoryMapping::load()
-    ($self:ident, load, $vm_addr:ident, $T:ty) => {
-        translate_memory_access!(_impl, $self, load, $vm_addr, $T,)
-    };
+    ($self:ident, load, $vm_addr:ident, $T:ty) => {{
+        let value = translate_memory_access!(_impl, $self, load, $vm_addr, $T,);
+        if $self.vm.input_hook.is_some() {
+            $self.hook_input_load($vm_addr, value, std::mem::size_of::<$T>())
+        } else {
+            value
+        }
+    }};
 
     // MemoryMapping::store()
     ($self:ident, store, $value:expr, $vm_addr:ident, $T:ty) => {
@@ -81,6 +94,59 @@ macro_rules! check_pc {
     };
 }
 
+/// What the observers need to know about an instruction after it was executed
+struct ObservedInsn {
+    pc: u64,
+    info: Option<&'static OpcodeInfo>,
+    dst: usize,
+    target_pc: u64,
+    call_depth: u64,
+    frame_pointer: u64,
+    vm_addr: u64,
+    value: u64,
+}
+
+/// An access to memory which an instruction is about to make, see [StepInfo]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingAccess {
+    /// Whether it loads or stores
+    pub access_type: AccessType,
+    /// First accessed address
+    pub vm_addr: u64,
+    /// Accessed bytes
+    pub len: u64,
+    /// Value which is stored, 0 for loads
+    pub value: u64,
+}
+
+/// The instruction which is about to be executed, see [Interpreter::step_with]
+#[derive(Debug)]
+pub struct StepInfo<'a> {
+    /// Pc of the instruction
+    pub pc: u64,
+    /// Decoded instruction
+    pub insn: &'a ebpf::Insn,
+    /// Registers before the instruction
+    pub registers: &'a [u64; 12],
+    /// Depth of the current function
+    pub call_depth: u64,
+    /// Memory access of a load or store
+    ///
+    /// The address is not translated yet, so the access can still fail.
+    pub memory_access: Option<PendingAccess>,
+}
+
+/// What [Interpreter::step_with] does with an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepControl {
+    /// Execute the instruction
+    Continue,
+    /// Move on to the next instruction without executing or metering this one
+    Skip,
+    /// Stop the execution with [EbpfError::ExecutionAborted]
+    Abort,
+}
+
 /// State of the interpreter during a debugging session
 #[cfg(feature = "debugger")]
 pub enum DebugState {
@@ -88,6 +154,10 @@ pub enum DebugState {
     Step,
     /// Continue execution till the end or till a breakpoint is hit
     Continue,
+    /// Single step backwards through the recorded history, only the registers are rewound
+    ReverseStep,
+    /// Continue backwards till the beginning of the recorded history or till a breakpoint is hit
+    ReverseContinue,
 }
 
 /// State of an interpreter
@@ -99,11 +169,20 @@ pub struct Interpreter<'a, 'b, C: ContextObject> {
 
     /// General purpose registers and pc
     pub reg: [u64; 12],
+    /// Whether the context object traces the instructions, until its tracer fails
+    tracing: bool,
 
     #[cfg(feature = "debugger")]
     pub(crate) debug_state: DebugState,
     #[cfg(feature = "debugger")]
     pub(crate) breakpoints: Vec<u64>,
+    /// Registers before the most recent instructions executed under the debugger, at most
+    /// [MAX_HISTORY_LEN](crate::debugger::MAX_HISTORY_LEN)
+    #[cfg(feature = "debugger")]
+    pub(crate) history: std::collections::VecDeque<[u64; 12]>,
+    /// Position in the history while replaying it, `None` at the live state
+    #[cfg(feature = "debugger")]
+    pub(crate) history_position: Option<usize>,
 }
 
 impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
@@ -114,16 +193,22 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
         registers: [u64; 12],
     ) -> Self {
         let (program_vm_addr, program) = executable.get_text_bytes();
+        let config = executable.get_config();
         Self {
             vm,
             executable,
             program,
             program_vm_addr,
             reg: registers,
+            tracing: config.enable_instruction_tracing && config.instrumentation.records_coverage(),
             #[cfg(feature = "debugger")]
             debug_state: DebugState::Continue,
             #[cfg(feature = "debugger")]
             breakpoints: Vec::new(),
+            #[cfg(feature = "debugger")]
+            history: std::collections::VecDeque::new(),
+            #[cfg(feature = "debugger")]
+            history_position: None,
         }
     }
 
@@ -133,6 +218,11 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
         (self.reg[11] * ebpf::INSN_SIZE as u64) + self.executable.get_text_section_offset()
     }
 
+    /// Symbolized guest call stack at the current instruction, see [EbpfVm::call_stack]
+    pub fn call_stack(&self) -> Vec<StackFrame> {
+        self.vm.call_stack(self.executable, self.reg[11])
+    }
+
     fn push_frame(&mut self, config: &Config) -> bool {
         let frame = &mut self.vm.call_frames[self.vm.call_depth as usize];
         frame.caller_saved_registers.copy_from_slice(
@@ -140,6 +230,10 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
         );
         frame.frame_pointer = self.reg[ebpf::FRAME_PTR_REG];
         frame.target_pc = self.reg[11] + 1;
+        if let Some(shadow_call_stack) = self.vm.shadow_call_stack.as_mut() {
+            shadow_call_stack.truncate(self.vm.call_depth as usize);
+            shadow_call_stack.push(self.reg[11] + 1);
+        }
 
         self.vm.call_depth += 1;
         if self.vm.call_depth as usize == config.max_call_depth {
@@ -168,6 +262,56 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
         }
     }
 
+    /// Like [Self::step], but lets `callback` inspect the instruction before it is executed
+    ///
+    /// This allows external tools to follow, skip or stop an execution at every instruction.
+    /// The callback is not called if the pc lies outside of the text section, in which case the
+    /// execution fails like in [Self::step].
+    pub fn step_with(&mut self, callback: impl FnOnce(&StepInfo) -> StepControl) -> bool {
+        let pc = self.reg[11];
+        if (pc as usize + 1) * ebpf::INSN_SIZE > self.program.len() {
+            return self.step();
+        }
+        let insn = ebpf::get_insn_unchecked(self.program, pc as usize);
+        let info = opcode_info(insn.opc, self.executable.get_sbpf_version());
+        let memory_access = info.and_then(|info| {
+            let (vm_addr, value) = self.memory_operand(&insn, info);
+            let len = info.width as u64;
+            match info.class {
+                InstructionClass::Load => Some(PendingAccess {
+                    access_type: AccessType::Load,
+                    vm_addr,
+                    len,
+                    value: 0,
+                }),
+                InstructionClass::Store => Some(PendingAccess {
+                    access_type: AccessType::Store,
+                    vm_addr,
+                    len,
+                    value: value & info.value_mask(),
+                }),
+                _ => None,
+            }
+        });
+        let control = callback(&StepInfo {
+            pc,
+            insn: &insn,
+            registers: &self.reg,
+            call_depth: self.vm.call_depth,
+            memory_access,
+        });
+        match control {
+            StepControl::Continue => self.step(),
+            StepControl::Skip => {
+                let is_lddw =
+                    info.is_some_and(|info| info.class == InstructionClass::LoadImmediate);
+                self.reg[11] = pc + if is_lddw { 2 } else { 1 };
+                true
+            }
+            StepControl::Abort => throw_error!(self, EbpfError::ExecutionAborted(pc)),
+        }
+    }
+
     /// Advances the interpreter state by one instruction
     ///
     /// Returns false if the program terminated or threw an error.
@@ -179,18 +323,53 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
             throw_error!(self, EbpfError::ExceededMaxInstructions);
         }
         self.vm.due_insn_count += 1;
+        if let Some(deadline) = self.vm.deadline.as_mut() {
+            if let Err(err) = deadline.check() {
+                throw_error!(self, err);
+            }
+        }
         if self.reg[11] as usize * ebpf::INSN_SIZE >= self.program.len() {
             throw_error!(self, EbpfError::ExecutionOverrun);
         }
         let mut next_pc = self.reg[11] + 1;
         let mut insn = ebpf::get_insn_unchecked(self.program, self.reg[11] as usize);
+        if let Some(cost_model) = &config.cost_model {
+            self.vm.due_insn_count = self.vm.due_insn_count - 1 + cost_model.instruction_cost(insn.opc);
+        }
         let dst = insn.dst as usize;
         let src = insn.src as usize;
 
-        if config.enable_instruction_tracing {
-            self.vm.context_object_pointer.trace(self.reg);
+        #[cfg(feature = "diagnostics")]
+        self.vm.diagnostics.record_dispatch(insn.opc);
+
+        if let Some(profiler) = self.vm.profiler.as_mut() {
+            self.vm.context_object_pointer.consume_instrumentation(1);
+            if let Some(cycles) = profiler.record_dispatch(self.reg[11], insn.opc) {
+                self.vm.stopwatch_numerator += cycles;
+                self.vm.stopwatch_denominator += 1;
+            }
         }
 
+        if self.tracing {
+            self.vm.context_object_pointer.consume_instrumentation(1);
+            let (context_object, registers) = (&mut self.vm.context_object_pointer, self.reg);
+            if let Err(payload) = catch_unwind(AssertUnwindSafe(|| context_object.trace(registers))) {
+                self.tracing = false;
+                self.vm.instrumentation_failures.push(InstrumentationFailure {
+                    component: InstrumentationComponent::Tracer,
+                    pc: self.reg[11],
+                    message: panic_message(payload.as_ref()),
+                });
+            }
+        }
+
+        let observed = if self.vm.observers.is_empty() || !config.instrumentation.records_coverage() {
+            None
+        } else {
+            self.vm.context_object_pointer.consume_instrumentation(1);
+            Some(self.observe_insn(&insn))
+        };
+
         match insn.opc {
             ebpf::LD_DW_IMM if !self.executable.get_sbpf_version().disable_lddw() => {
                 ebpf::augment_lddw_unchecked(self.program, &mut insn);
@@ -532,7 +711,7 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
                     check_pc!(self, next_pc, key as u64);
                 } else if let Some((_, function)) = self.executable.get_loader().get_function_registry().lookup_by_key(insn.imm as u32) {
                     // SBPFv0 syscall
-                    self.reg[0] = match self.dispatch_syscall(function) {
+                    self.reg[0] = match self.dispatch_syscall(insn.imm as u32, function) {
                         ProgramResult::Ok(value) => *value,
                         ProgramResult::Err(_err) => return false,
                     };
@@ -552,7 +731,7 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
             ebpf::SYSCALL if self.executable.get_sbpf_version().static_syscalls() => {
                 if let Some((_, function)) = self.executable.get_loader().get_function_registry().lookup_by_key(insn.imm as u32) {
                     // SBPFv3 syscall
-                    self.reg[0] = match self.dispatch_syscall(function) {
+                    self.reg[0] = match self.dispatch_syscall(insn.imm as u32, function) {
                         ProgramResult::Ok(value) => *value,
                         ProgramResult::Err(_err) => return false,
                     };
@@ -571,12 +750,20 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
                     if config.enable_instruction_meter && self.vm.due_insn_count > self.vm.previous_instruction_meter {
                         throw_error!(self, EbpfError::ExceededMaxInstructions);
                     }
+                    let (pc, return_value) = (self.reg[11], self.reg[0]);
+                    self.notify(pc, |observer| observer.on_exit(pc, 0, return_value));
                     self.vm.program_result = ProgramResult::Ok(self.reg[0]);
                     return false;
                 }
                 // Return from BPF to BPF call
                 self.vm.call_depth -= 1;
                 let frame = &self.vm.call_frames[self.vm.call_depth as usize];
+                if let Some(expected_pc) = self.vm.shadow_call_stack.as_ref()
+                    .and_then(|shadow_call_stack| shadow_call_stack.get(self.vm.call_depth as usize)) {
+                    if *expected_pc != frame.target_pc {
+                        throw_error!(self, EbpfError::ControlFlowIntegrity(self.reg[11], frame.target_pc, *expected_pc));
+                    }
+                }
                 self.reg[ebpf::FRAME_PTR_REG] = frame.frame_pointer;
                 self.reg[ebpf::FIRST_SCRATCH_REG
                     ..ebpf::FIRST_SCRATCH_REG + ebpf::SCRATCH_REGS]
@@ -586,15 +773,213 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
             _ => throw_error!(self, EbpfError::UnsupportedInstruction),
         }
 
+        if let Some(loop_detector) = self.vm.loop_detector.as_mut() {
+            let pc = self.reg[11];
+            if next_pc <= pc && insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP
+                && !matches!(insn.opc, ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::EXIT | ebpf::RETURN) {
+                if let Err(err) = loop_detector.record(pc, next_pc) {
+                    throw_error!(self, err);
+                }
+            }
+        }
+        if let Some(observed) = observed {
+            self.notify_observers(observed, next_pc);
+        }
         self.reg[11] = next_pc;
         true
     }
 
-    fn dispatch_syscall(&mut self, function: BuiltinFunction<C>) -> &ProgramResult {
-        self.vm.due_insn_count = self.vm.previous_instruction_meter - self.vm.due_insn_count;
+    /// Lets the [InputHook](crate::input_hook::InputHook) override a value loaded from the input
+    /// region
+    fn hook_input_load(&mut self, vm_addr: u64, value: u64, len: usize) -> u64 {
+        let in_input_region =
+            (ebpf::MM_INPUT_START..ebpf::MM_INPUT_START + ebpf::MM_REGION_SIZE).contains(&vm_addr);
+        match self.vm.input_hook.as_mut() {
+            Some(input_hook) if in_input_region => {
+                let mut bytes = value.to_le_bytes();
+                input_hook.on_input_load(self.reg[11], vm_addr, &mut bytes[..len]);
+                u64::from_le_bytes(bytes)
+            }
+            _ => value,
+        }
+    }
+
+    /// Notifies the observers about the instruction and captures what is overwritten by it
+    fn observe_insn(&mut self, insn: &ebpf::Insn) -> ObservedInsn {
+        let pc = self.reg[11];
+        let registers = self.reg;
+        self.notify(pc, |observer| observer.on_insn(pc, insn, &registers));
+        let info = opcode_info(insn.opc, self.executable.get_sbpf_version());
+        let (vm_addr, value) = info.map_or((0, 0), |info| self.memory_operand(insn, info));
+        ObservedInsn {
+            pc,
+            info,
+            dst: insn.dst as usize,
+            target_pc: (pc as i64).wrapping_add(insn.off as i64).wrapping_add(1) as u64,
+            call_depth: self.vm.call_depth,
+            frame_pointer: self.reg[ebpf::FRAME_PTR_REG],
+            vm_addr,
+            value,
+        }
+    }
+
+    /// Address and stored value of a load or store, zeros for other instructions
+    fn memory_operand(&self, insn: &ebpf::Insn, info: &OpcodeInfo) -> (u64, u64) {
+        match info.class {
+            InstructionClass::Load => (
+                (self.reg[insn.src as usize] as i64).wrapping_add(insn.off as i64) as u64,
+                0,
+            ),
+            InstructionClass::Store => (
+                (self.reg[insn.dst as usize] as i64).wrapping_add(insn.off as i64) as u64,
+                if info.source == OperandSource::Register {
+                    self.reg[insn.src as usize]
+                } else {
+                    insn.imm as u64
+                },
+            ),
+            _ => (0, 0),
+        }
+    }
+
+    /// Notifies the observers about the effects of an instruction which completed
+    fn notify_observers(&mut self, observed: ObservedInsn, next_pc: u64) {
+        let Some(info) = observed.info else {
+            return;
+        };
+        let pc = observed.pc;
+        let len = info.width as u64;
+        let (call_depth, frame_pointer, return_value) = (
+            self.vm.call_depth,
+            self.reg[ebpf::FRAME_PTR_REG],
+            self.reg[0],
+        );
+        let loaded_value = if info.class == InstructionClass::Load {
+            self.reg[observed.dst]
+        } else {
+            0
+        };
+        self.notify(pc, |observer| match info.class {
+            InstructionClass::Load => observer.on_mem_read(pc, observed.vm_addr, len, loaded_value),
+            InstructionClass::Store => observer.on_mem_write(
+                pc,
+                observed.vm_addr,
+                len,
+                observed.value & info.value_mask(),
+            ),
+            InstructionClass::ConditionalJump => {
+                observer.on_branch(pc, observed.target_pc, next_pc != pc + 1)
+            }
+            InstructionClass::Call if call_depth > observed.call_depth => {
+                observer.on_call(pc, next_pc, call_depth);
+                observer.on_push_frame(&CallFrameEvent {
+                    caller_pc: pc,
+                    callee_pc: next_pc,
+                    call_depth,
+                    frame_pointer,
+                });
+            }
+            InstructionClass::Exit => {
+                observer.on_exit(pc, observed.call_depth, return_value);
+                observer.on_pop_frame(&CallFrameEvent {
+                    caller_pc: next_pc - 1,
+                    callee_pc: pc,
+                    call_depth: observed.call_depth,
+                    frame_pointer: observed.frame_pointer,
+                });
+            }
+            _ => {}
+        });
+    }
+
+    /// Calls `notify` for every observer
+    ///
+    /// An observer which panics is replaced by a [FailedObserver] and reported in
+    /// [EbpfVm::instrumentation_failures].
+    fn notify<F: FnMut(&mut dyn ExecutionObserver)>(&mut self, pc: u64, mut notify: F) {
+        if !self
+            .executable
+            .get_config()
+            .instrumentation
+            .records_coverage()
+        {
+            return;
+        }
+        for (index, observer) in self.vm.observers.iter_mut().enumerate() {
+            if let Err(payload) = catch_unwind(AssertUnwindSafe(|| notify(observer.as_mut()))) {
+                *observer = Box::new(FailedObserver);
+                self.vm
+                    .instrumentation_failures
+                    .push(InstrumentationFailure {
+                        component: InstrumentationComponent::Observer(index),
+                        pc,
+                        message: panic_message(payload.as_ref()),
+                    });
+            }
+        }
+    }
+
+    fn dispatch_syscall(&mut self, key: u32, function: BuiltinFunction<C>) -> &ProgramResult {
+        let mut arguments = [0; 5];
+        arguments.copy_from_slice(&self.reg[1..6]);
+        let pc = self.reg[11];
+        self.notify(pc, |observer| observer.on_syscall(pc, key, arguments));
+        let fault = match self.vm.fault_injector.as_mut().map(|fault_injector| {
+            catch_unwind(AssertUnwindSafe(|| {
+                fault_injector.on_syscall(key, arguments)
+            }))
+        }) {
+            Some(Ok(fault)) => fault,
+            Some(Err(payload)) => {
+                self.vm.fault_injector = None;
+                self.vm
+                    .instrumentation_failures
+                    .push(InstrumentationFailure {
+                        component: InstrumentationComponent::FaultInjector,
+                        pc: self.reg[11],
+                        message: panic_message(payload.as_ref()),
+                    });
+                None
+            }
+            None => None,
+        };
+        match fault {
+            Some(FaultAction::Fail) => {
+                self.vm.registers[11] = self.reg[11];
+                self.vm.program_result =
+                    ProgramResult::Err(EbpfError::SyscallError(Box::new(InjectedSyscallError {
+                        syscall: key,
+                    })));
+                return &self.vm.program_result;
+            }
+            Some(FaultAction::Return(value)) => {
+                self.vm.program_result = ProgramResult::Ok(value);
+                return &self.vm.program_result;
+            }
+            Some(FaultAction::XorResult(_)) | None => {}
+        }
+        if let Some(cost_model) = &self.executable.get_config().cost_model {
+            self.vm.due_insn_count += cost_model.syscall_cost(key);
+        }
+        // A priced syscall can overrun the meter, which must not go unnoticed
+        let Some(remaining) = self
+            .vm
+            .previous_instruction_meter
+            .checked_sub(self.vm.due_insn_count)
+        else {
+            self.vm.registers[11] = self.reg[11];
+            self.vm.program_result = ProgramResult::Err(EbpfError::ExceededMaxInstructions);
+            return &self.vm.program_result;
+        };
+        self.vm.due_insn_count = remaining;
         self.vm.registers[0..6].copy_from_slice(&self.reg[0..6]);
         self.vm.invoke_function(function);
         self.vm.due_insn_count = 0;
+        if let (Some(FaultAction::XorResult(mask)), ProgramResult::Ok(value)) =
+            (fault, &mut self.vm.program_result)
+        {
+            *value ^= mask;
+        }
         &self.vm.program_result
     }
 }
@@ -178,6 +178,11 @@ impl JitProgram {
         self.text_section.len()
     }
 
+    /// The host machinecode
+    pub fn machine_code(&self) -> &[u8] {
+        self.text_section
+    }
+
     /// The total memory used in bytes rounded up to page boundaries
     pub fn mem_size(&self) -> usize {
         let pc_loc_table_size =
@@ -256,6 +261,12 @@ const REGISTER_INSTRUCTION_METER: X86Register = CALLER_SAVED_REGISTERS[7];
 /// R11: Scratch register
 const REGISTER_SCRATCH: X86Register = CALLER_SAVED_REGISTERS[8];
 
+/// Called by the tracing routine, accounts the trace as instrumentation work
+fn trace_instrumented<C: ContextObject>(context_object: &mut C, state: [u64; 12]) {
+    context_object.consume_instrumentation(1);
+    context_object.trace(state);
+}
+
 /// Bit width of an instruction operand
 #[derive(Copy, Clone, Debug)]
 pub enum OperandSize {
@@ -384,14 +395,21 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         if config.noop_instruction_rate != 0 {
             code_length_estimate += code_length_estimate / config.noop_instruction_rate as usize;
         }
-        if config.instruction_meter_checkpoint_distance != 0 {
-            code_length_estimate += pc / config.instruction_meter_checkpoint_distance * MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT;
+        if let Some(instruction_meter_checkpoints) = pc.checked_div(config.instruction_meter_checkpoint_distance) {
+            code_length_estimate += instruction_meter_checkpoints * MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT;
         }
         // Relative jump destinations limit the maximum output size
         debug_assert!(code_length_estimate < (i32::MAX as usize));
 
         let runtime_environment_key = get_runtime_environment_key();
-        let mut diversification_rng = SmallRng::from_rng(thread_rng()).map_err(|_| EbpfError::JitNotCompiled)?;
+        #[cfg(feature = "fuzzer-not-safe-for-production")]
+        let mut diversification_rng = match config.diversification_seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_rng(thread_rng()).map_err(|_| EbpfError::JitNotCompiled)?,
+        };
+        #[cfg(not(feature = "fuzzer-not-safe-for-production"))]
+        let mut diversification_rng =
+            SmallRng::from_rng(thread_rng()).map_err(|_| EbpfError::JitNotCompiled)?;
         let immediate_value_key = diversification_rng.gen::<i64>();
 
         Ok(Self {
@@ -1383,7 +1401,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
             }
             self.emit_ins(X86Instruction::mov(OperandSize::S64, RSP, REGISTER_MAP[0]));
             self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, RSP, - 8 * 3, None)); // RSP -= 8 * 3;
-            self.emit_rust_call(Value::Constant64(C::trace as *const u8 as i64, false), &[
+            self.emit_rust_call(Value::Constant64(trace_instrumented::<C> as *const u8 as i64, false), &[
                 Argument { index: 1, value: Value::Register(REGISTER_MAP[0]) }, // registers
                 Argument { index: 0, value: Value::RegisterIndirect(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::ContextObjectPointer), false) },
             ], None);
@@ -0,0 +1,903 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Portable just-in-time compiler based on Cranelift
+//!
+//! Unlike the [x86 JIT](crate::jit) this compiler emits machine code for every host Cranelift
+//! supports, e.g. aarch64. The compiled code receives the same runtime environment pointer as
+//! the x86 JIT and accesses the [EbpfVm] through the [RuntimeEnvironmentSlot] layout. The guest
+//! registers live in Cranelift variables and are spilled to a stack frame whenever the code
+//! leaves to the host. Like in the interpreter, only the pc of an error reaches
+//! [EbpfVm::registers].
+//!
+//! ALU operations, jumps and memory accesses are translated to machine code, the latter calling
+//! into the [MemoryMapping](crate::memory_region::MemoryMapping). All other instructions
+//! (calls, syscalls, exits, `lddw`, divisions, byte swaps and the product / quotient /
+//! remainder class) are delegated to a single [Interpreter::step], which also raises every error
+//! other than memory access violations. So their semantics are the ones of the interpreter by
+//! construction.
+//!
+//! With [Config::enable_instruction_tracing](crate::vm::Config::enable_instruction_tracing)
+//! every natively executed instruction calls a tracing stub. Executions with
+//! [profiler](EbpfVm::profiler), [observers](EbpfVm::observers) or
+//! [loop detector](EbpfVm::loop_detector) attached are interpreted, as these observe every
+//! instruction. The same goes for a [deadline](EbpfVm::deadline), an
+//! [input hook](EbpfVm::input_hook) or a [shadow call stack](EbpfVm::shadow_call_stack).
+
+use crate::{
+    ebpf,
+    elf::Executable,
+    error::{EbpfError, ProgramResult},
+    interpreter::Interpreter,
+    vm::{get_runtime_environment_key, ContextObject, EbpfVm, RuntimeEnvironmentSlot},
+};
+use cranelift_codegen::{
+    ir::{
+        condcodes::IntCC,
+        types::{I32, I64, I8},
+        AbiParam, Block, BlockCall, InstBuilder, JumpTableData, MemFlags, SigRef, Signature,
+        StackSlot, StackSlotData, StackSlotKind, Type, Value,
+    },
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+use std::{convert::TryFrom, fmt::Debug};
+
+/// Signature of the compiled entrypoint: Runtime environment and executable
+type Entrypoint<C> = unsafe extern "C" fn(*mut u64, *const Executable<C>);
+
+/// The program compiled to native host machinecode by Cranelift
+pub struct CraneliftProgram {
+    /// Owns the executable memory of `entrypoint`
+    module: Option<JITModule>,
+    entrypoint: *const u8,
+    machine_code_length: usize,
+}
+
+// The module is not modified after the compilation finished and the machine code is immutable
+unsafe impl Send for CraneliftProgram {}
+unsafe impl Sync for CraneliftProgram {}
+
+impl CraneliftProgram {
+    /// Compiles the text section of `executable`
+    pub fn compile<C: ContextObject>(executable: &Executable<C>) -> Result<Self, EbpfError> {
+        let mut flags = settings::builder();
+        // Long-range relocations for the calls into the host, see `JITBuilder::with_flags`
+        flags
+            .set("use_colocated_libcalls", "false")
+            .map_err(|_| EbpfError::JitNotCompiled)?;
+        flags
+            .set("is_pic", "true")
+            .map_err(|_| EbpfError::JitNotCompiled)?;
+        flags
+            .set("opt_level", "speed")
+            .map_err(|_| EbpfError::JitNotCompiled)?;
+        let isa = cranelift_native::builder()
+            .map_err(|_| EbpfError::JitNotCompiled)?
+            .finish(settings::Flags::new(flags))
+            .map_err(|_| EbpfError::JitNotCompiled)?;
+        let mut module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+        let pointer_type = module.target_config().pointer_type();
+        let mut context = module.make_context();
+        context.func.signature.params = vec![AbiParam::new(pointer_type); 2];
+        let mut function_builder_context = FunctionBuilderContext::new();
+        Emitter::new(
+            executable,
+            FunctionBuilder::new(&mut context.func, &mut function_builder_context),
+            pointer_type,
+        )
+        .emit();
+        let function_id = module
+            .declare_function("entrypoint", Linkage::Local, &context.func.signature)
+            .map_err(|_| EbpfError::JitNotCompiled)?;
+        module
+            .define_function(function_id, &mut context)
+            .map_err(|_| EbpfError::JitNotCompiled)?;
+        let machine_code_length = context
+            .compiled_code()
+            .map_or(0, |compiled_code| compiled_code.code_buffer().len());
+        module.clear_context(&mut context);
+        module
+            .finalize_definitions()
+            .map_err(|_| EbpfError::JitNotCompiled)?;
+        let entrypoint = module.get_finalized_function(function_id);
+        Ok(Self {
+            module: Some(module),
+            entrypoint,
+            machine_code_length,
+        })
+    }
+
+    pub(crate) fn invoke<C: ContextObject>(
+        &self,
+        vm: &mut EbpfVm<C>,
+        executable: &Executable<C>,
+        registers: [u64; 12],
+    ) {
+        if vm.profiler.is_some()
+            || !vm.observers.is_empty()
+            || vm.loop_detector.is_some()
+            || vm.deadline.is_some()
+            || vm.input_hook.is_some()
+            || vm.shadow_call_stack.is_some()
+        {
+            let mut interpreter = Interpreter::new(vm, executable, registers);
+            while interpreter.step() {}
+            return;
+        }
+        vm.registers = registers;
+        unsafe {
+            let runtime_environment = std::ptr::addr_of_mut!(*vm)
+                .cast::<u64>()
+                .offset(get_runtime_environment_key() as isize);
+            let entrypoint = std::mem::transmute::<*const u8, Entrypoint<C>>(self.entrypoint);
+            entrypoint(runtime_environment, executable);
+        }
+    }
+
+    /// The length of the host machinecode in bytes
+    pub fn machine_code_length(&self) -> usize {
+        self.machine_code_length
+    }
+}
+
+impl Drop for CraneliftProgram {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+impl Debug for CraneliftProgram {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.write_fmt(format_args!("CraneliftProgram {:?}", self as *const _))
+    }
+}
+
+impl PartialEq for CraneliftProgram {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self as *const _, other as *const _)
+    }
+}
+
+/// Recovers the VM from the runtime environment pointer
+unsafe fn vm_from_runtime_environment<'a, C: ContextObject>(
+    runtime_environment: *mut u64,
+) -> &'a mut EbpfVm<'a, C> {
+    &mut *runtime_environment
+        .offset(-(get_runtime_environment_key() as isize))
+        .cast::<EbpfVm<C>>()
+}
+
+/// Executes the instruction at `registers[11]` in the interpreter, returns 0 if the program
+/// terminated or threw an error
+extern "C" fn step<C: ContextObject>(
+    runtime_environment: *mut u64,
+    executable: *const Executable<C>,
+    registers: *mut [u64; 12],
+) -> u8 {
+    unsafe {
+        let vm = vm_from_runtime_environment::<C>(runtime_environment);
+        let mut interpreter = Interpreter::new(vm, &*executable, *registers);
+        let running = interpreter.step();
+        *registers = interpreter.reg;
+        running as u8
+    }
+}
+
+/// Loads `len` bytes into `value`, returns 0 and sets the program result on errors
+extern "C" fn load<C: ContextObject>(
+    runtime_environment: *mut u64,
+    vm_addr: u64,
+    len: u64,
+    value: *mut u64,
+) -> u8 {
+    let vm = unsafe { vm_from_runtime_environment::<C>(runtime_environment) };
+    let result = match len {
+        1 => vm.memory_mapping.load::<u8>(vm_addr),
+        2 => vm.memory_mapping.load::<u16>(vm_addr),
+        4 => vm.memory_mapping.load::<u32>(vm_addr),
+        _ => vm.memory_mapping.load::<u64>(vm_addr),
+    };
+    match result {
+        ProgramResult::Ok(loaded) => {
+            unsafe { value.write(loaded) };
+            1
+        }
+        ProgramResult::Err(err) => {
+            vm.program_result = ProgramResult::Err(err);
+            0
+        }
+    }
+}
+
+/// Stores the lower `len` bytes of `value`, returns 0 and sets the program result on errors
+extern "C" fn store<C: ContextObject>(
+    runtime_environment: *mut u64,
+    vm_addr: u64,
+    value: u64,
+    len: u64,
+) -> u8 {
+    let vm = unsafe { vm_from_runtime_environment::<C>(runtime_environment) };
+    let result = match len {
+        1 => vm.memory_mapping.store(value as u8, vm_addr),
+        2 => vm.memory_mapping.store(value as u16, vm_addr),
+        4 => vm.memory_mapping.store(value as u32, vm_addr),
+        _ => vm.memory_mapping.store(value, vm_addr),
+    };
+    match result {
+        ProgramResult::Ok(_) => 1,
+        ProgramResult::Err(err) => {
+            vm.program_result = ProgramResult::Err(err);
+            0
+        }
+    }
+}
+
+/// Passes the registers before a natively executed instruction to the context object
+extern "C" fn trace<C: ContextObject>(runtime_environment: *mut u64, registers: *const [u64; 12]) {
+    let vm = unsafe { vm_from_runtime_environment::<C>(runtime_environment) };
+    vm.context_object_pointer.consume_instrumentation(1);
+    vm.context_object_pointer.trace(unsafe { *registers });
+}
+
+/// Host functions called by the compiled code
+struct Stubs {
+    step: (SigRef, usize),
+    load: (SigRef, usize),
+    store: (SigRef, usize),
+    trace: (SigRef, usize),
+}
+
+struct Emitter<'a, 'b, C: ContextObject> {
+    executable: &'a Executable<C>,
+    program: &'a [u8],
+    builder: FunctionBuilder<'b>,
+    pointer_type: Type,
+    runtime_environment_key: i32,
+    /// Guest registers r0 to r10
+    registers: [Variable; 11],
+    due_insn_count: Variable,
+    previous_instruction_meter: Variable,
+    runtime_environment: Value,
+    executable_pointer: Value,
+    /// Guest registers and pc while the host executes an instruction
+    spill_area: StackSlot,
+    /// First block of every instruction slot
+    instruction_blocks: Vec<Block>,
+    /// Continues at the pc passed as block parameter
+    dispatch: Block,
+    /// Executes the instruction at the pc passed as block parameter in the interpreter
+    interpret: Block,
+    /// Leaves after a memory access failed at the pc passed as block parameter
+    throw: Block,
+    stubs: Stubs,
+}
+
+impl<'a, 'b, C: ContextObject> Emitter<'a, 'b, C> {
+    fn new(
+        executable: &'a Executable<C>,
+        mut builder: FunctionBuilder<'b>,
+        pointer_type: Type,
+    ) -> Self {
+        let (_program_vm_addr, program) = executable.get_text_bytes();
+        let mut signature = |params: &[Type], returns: &[Type]| {
+            let mut signature = Signature::new(builder.func.signature.call_conv);
+            signature.params = params.iter().map(|ty| AbiParam::new(*ty)).collect();
+            signature.returns = returns.iter().map(|ty| AbiParam::new(*ty)).collect();
+            builder.import_signature(signature)
+        };
+        let stubs = Stubs {
+            step: (
+                signature(&[pointer_type, pointer_type, pointer_type], &[I8]),
+                step::<C> as *const () as usize,
+            ),
+            load: (
+                signature(&[pointer_type, I64, I64, pointer_type], &[I8]),
+                load::<C> as *const () as usize,
+            ),
+            store: (
+                signature(&[pointer_type, I64, I64, I64], &[I8]),
+                store::<C> as *const () as usize,
+            ),
+            trace: (
+                signature(&[pointer_type, pointer_type], &[]),
+                trace::<C> as *const () as usize,
+            ),
+        };
+        let variable = |index: u32| Variable::from_u32(index);
+        let registers = std::array::from_fn(|index| variable(index as u32));
+        for register in registers.iter() {
+            builder.declare_var(*register, I64);
+        }
+        let due_insn_count = variable(11);
+        let previous_instruction_meter = variable(12);
+        builder.declare_var(due_insn_count, I64);
+        builder.declare_var(previous_instruction_meter, I64);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        let runtime_environment = builder.block_params(entry)[0];
+        let executable_pointer = builder.block_params(entry)[1];
+        let instruction_blocks = (0..program.len() / ebpf::INSN_SIZE)
+            .map(|_| builder.create_block())
+            .collect();
+        let mut block_with_pc = || {
+            let block = builder.create_block();
+            builder.append_block_param(block, I64);
+            block
+        };
+        let dispatch = block_with_pc();
+        let interpret = block_with_pc();
+        let throw = block_with_pc();
+        let spill_area = builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            12 * 8,
+            3,
+        ));
+        builder.switch_to_block(entry);
+        Self {
+            executable,
+            program,
+            builder,
+            pointer_type,
+            runtime_environment_key: get_runtime_environment_key(),
+            registers,
+            due_insn_count,
+            previous_instruction_meter,
+            runtime_environment,
+            executable_pointer,
+            spill_area,
+            instruction_blocks,
+            dispatch,
+            interpret,
+            throw,
+            stubs,
+        }
+    }
+
+    fn slot_in_vm(&self, slot: RuntimeEnvironmentSlot, index: usize) -> i32 {
+        8 * (slot as i32 + index as i32 - self.runtime_environment_key)
+    }
+
+    fn load_slot(&mut self, slot: RuntimeEnvironmentSlot, index: usize) -> Value {
+        let offset = self.slot_in_vm(slot, index);
+        self.builder
+            .ins()
+            .load(I64, MemFlags::trusted(), self.runtime_environment, offset)
+    }
+
+    fn store_slot(&mut self, slot: RuntimeEnvironmentSlot, index: usize, value: Value) {
+        let offset = self.slot_in_vm(slot, index);
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, self.runtime_environment, offset);
+    }
+
+    /// Writes the guest registers to the spill area and the instruction meter to the VM before
+    /// leaving to the host
+    ///
+    /// Like the interpreter, [EbpfVm::registers] is not updated, only on errors its pc.
+    fn spill(&mut self, pc: Value) -> Value {
+        let registers = self.registers;
+        for (index, register) in registers.iter().copied().enumerate() {
+            let value = self.builder.use_var(register);
+            self.builder
+                .ins()
+                .stack_store(value, self.spill_area, index as i32 * 8);
+        }
+        self.builder.ins().stack_store(pc, self.spill_area, 11 * 8);
+        let due_insn_count = self.builder.use_var(self.due_insn_count);
+        self.store_slot(RuntimeEnvironmentSlot::DueInsnCount, 0, due_insn_count);
+        self.builder
+            .ins()
+            .stack_addr(self.pointer_type, self.spill_area, 0)
+    }
+
+    /// Reads the guest state after the host modified it, returns the pc
+    fn reload(&mut self) -> Value {
+        let registers = self.registers;
+        for (index, register) in registers.iter().copied().enumerate() {
+            let value = self
+                .builder
+                .ins()
+                .stack_load(I64, self.spill_area, index as i32 * 8);
+            self.builder.def_var(register, value);
+        }
+        self.reload_instruction_meter();
+        self.builder.ins().stack_load(I64, self.spill_area, 11 * 8)
+    }
+
+    fn reload_instruction_meter(&mut self) {
+        let due_insn_count = self.load_slot(RuntimeEnvironmentSlot::DueInsnCount, 0);
+        self.builder.def_var(self.due_insn_count, due_insn_count);
+        let previous_instruction_meter =
+            self.load_slot(RuntimeEnvironmentSlot::PreviousInstructionMeter, 0);
+        self.builder
+            .def_var(self.previous_instruction_meter, previous_instruction_meter);
+    }
+
+    fn call_stub(
+        &mut self,
+        (signature, address): (SigRef, usize),
+        args: &[Value],
+    ) -> Option<Value> {
+        let callee = self.builder.ins().iconst(self.pointer_type, address as i64);
+        let call = self.builder.ins().call_indirect(signature, callee, args);
+        self.builder.inst_results(call).first().copied()
+    }
+
+    fn emit(mut self) {
+        // Prologue
+        let registers = self.registers;
+        for (index, register) in registers.iter().copied().enumerate() {
+            let value = self.load_slot(RuntimeEnvironmentSlot::Registers, index);
+            self.builder.def_var(register, value);
+        }
+        self.reload_instruction_meter();
+        let pc = self.load_slot(RuntimeEnvironmentSlot::Registers, 11);
+        self.builder.ins().jump(self.dispatch, &[pc]);
+
+        // Continue at a pc computed at runtime
+        self.builder.switch_to_block(self.dispatch);
+        let pc = self.builder.block_params(self.dispatch)[0];
+        let jump_table_block = self.builder.create_block();
+        let in_range = self.builder.ins().icmp_imm(
+            IntCC::UnsignedLessThan,
+            pc,
+            self.instruction_blocks.len() as i64,
+        );
+        self.builder
+            .ins()
+            .brif(in_range, jump_table_block, &[], self.interpret, &[pc]);
+        self.builder.switch_to_block(jump_table_block);
+        let pool = &mut self.builder.func.dfg.value_lists;
+        let default = BlockCall::new(self.interpret, &[pc], pool);
+        let targets = self
+            .instruction_blocks
+            .iter()
+            .map(|block| BlockCall::new(*block, &[], pool))
+            .collect::<Vec<_>>();
+        let jump_table = self
+            .builder
+            .create_jump_table(JumpTableData::new(default, &targets));
+        let index = self.builder.ins().ireduce(I32, pc);
+        self.builder.ins().br_table(index, jump_table);
+
+        // Delegate an instruction to the interpreter
+        let epilogue = self.builder.create_block();
+        self.builder.switch_to_block(self.interpret);
+        let pc = self.builder.block_params(self.interpret)[0];
+        let spill_area = self.spill(pc);
+        let running = self
+            .call_stub(
+                self.stubs.step,
+                &[
+                    self.runtime_environment,
+                    self.executable_pointer,
+                    spill_area,
+                ],
+            )
+            .unwrap();
+        let pc = self.reload();
+        self.builder
+            .ins()
+            .brif(running, self.dispatch, &[pc], epilogue, &[]);
+
+        // Leave after a failed memory access, the stub already set the program result
+        self.builder.switch_to_block(self.throw);
+        let pc = self.builder.block_params(self.throw)[0];
+        self.store_slot(RuntimeEnvironmentSlot::Registers, 11, pc);
+        let due_insn_count = self.builder.use_var(self.due_insn_count);
+        self.store_slot(RuntimeEnvironmentSlot::DueInsnCount, 0, due_insn_count);
+        self.builder.ins().jump(epilogue, &[]);
+
+        self.builder.switch_to_block(epilogue);
+        self.builder.ins().return_(&[]);
+
+        for pc in 0..self.instruction_blocks.len() {
+            self.builder.switch_to_block(self.instruction_blocks[pc]);
+            self.emit_instruction(pc);
+        }
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+    }
+
+    /// Jumps to the block of `target_pc`, or lets the interpreter throw if there is none
+    fn jump_to(&mut self, target_pc: i64) {
+        match self.instruction_block(target_pc) {
+            Some(block) => self.builder.ins().jump(block, &[]),
+            None => {
+                let target_pc = self.builder.ins().iconst(I64, target_pc);
+                self.builder.ins().jump(self.interpret, &[target_pc])
+            }
+        };
+    }
+
+    fn instruction_block(&self, pc: i64) -> Option<Block> {
+        usize::try_from(pc)
+            .ok()
+            .and_then(|pc| self.instruction_blocks.get(pc).copied())
+    }
+
+    fn register(&mut self, index: u8) -> Value {
+        self.builder
+            .use_var(self.registers[index as usize % self.registers.len()])
+    }
+
+    fn set_register(&mut self, index: u8, value: Value) {
+        self.builder
+            .def_var(self.registers[index as usize % self.registers.len()], value);
+    }
+
+    fn emit_instruction(&mut self, pc: usize) {
+        let sbpf_version = self.executable.get_sbpf_version();
+        let insn = ebpf::get_insn_unchecked(self.program, pc);
+        let pc_value = self.builder.ins().iconst(I64, pc as i64);
+        if !self.is_native(&insn) {
+            self.builder.ins().jump(self.interpret, &[pc_value]);
+            return;
+        }
+
+        // Instruction meter, the interpreter throws if the budget is exhausted
+        let config = self.executable.get_config();
+        let body = self.builder.create_block();
+        if config.enable_instruction_meter {
+            let due_insn_count = self.builder.use_var(self.due_insn_count);
+            let previous_instruction_meter = self.builder.use_var(self.previous_instruction_meter);
+            let exhausted = self.builder.ins().icmp(
+                IntCC::UnsignedGreaterThanOrEqual,
+                due_insn_count,
+                previous_instruction_meter,
+            );
+            self.builder
+                .ins()
+                .brif(exhausted, self.interpret, &[pc_value], body, &[]);
+        } else {
+            self.builder.ins().jump(body, &[]);
+        }
+        self.builder.switch_to_block(body);
+        let due_insn_count = self.builder.use_var(self.due_insn_count);
+        let due_insn_count = self.builder.ins().iadd_imm(due_insn_count, 1);
+        self.builder.def_var(self.due_insn_count, due_insn_count);
+        if config.enable_instruction_tracing {
+            let pc_value = self.builder.ins().iconst(I64, pc as i64);
+            let spill_area = self.spill(pc_value);
+            self.call_stub(self.stubs.trace, &[self.runtime_environment, spill_area]);
+        }
+
+        if let Some((is_load, len)) = memory_access(&insn, sbpf_version) {
+            self.emit_memory_access(pc, &insn, is_load, len);
+            return;
+        }
+        let dst = self.register(insn.dst);
+        let src = self.register(insn.src);
+        let imm = self.builder.ins().iconst(I64, insn.imm);
+        let dst32 = self.builder.ins().ireduce(I32, dst);
+        let src32 = self.builder.ins().ireduce(I32, src);
+        let imm32 = self.builder.ins().iconst(I32, insn.imm as i32 as i64);
+        let sign_extension = |builder: &mut FunctionBuilder, value: Value| {
+            if sbpf_version.explicit_sign_extension_of_results() {
+                builder.ins().uextend(I64, value)
+            } else {
+                builder.ins().sextend(I64, value)
+            }
+        };
+        let next_pc = pc as i64 + 1;
+        let target_pc = next_pc + insn.off as i64;
+        let result = match insn.opc {
+            ebpf::ADD64_IMM => self.builder.ins().iadd(dst, imm),
+            ebpf::ADD64_REG => self.builder.ins().iadd(dst, src),
+            ebpf::SUB64_IMM if sbpf_version.swap_sub_reg_imm_operands() => {
+                self.builder.ins().isub(imm, dst)
+            }
+            ebpf::SUB64_IMM => self.builder.ins().isub(dst, imm),
+            ebpf::SUB64_REG => self.builder.ins().isub(dst, src),
+            ebpf::MUL64_IMM | ebpf::LMUL64_IMM => self.builder.ins().imul(dst, imm),
+            ebpf::MUL64_REG | ebpf::LMUL64_REG => self.builder.ins().imul(dst, src),
+            ebpf::OR64_IMM => self.builder.ins().bor(dst, imm),
+            ebpf::OR64_REG => self.builder.ins().bor(dst, src),
+            ebpf::AND64_IMM => self.builder.ins().band(dst, imm),
+            ebpf::AND64_REG => self.builder.ins().band(dst, src),
+            ebpf::XOR64_IMM => self.builder.ins().bxor(dst, imm),
+            ebpf::XOR64_REG => self.builder.ins().bxor(dst, src),
+            ebpf::LSH64_IMM => self.builder.ins().ishl(dst, imm),
+            ebpf::LSH64_REG => self.builder.ins().ishl(dst, src),
+            ebpf::RSH64_IMM => self.builder.ins().ushr(dst, imm),
+            ebpf::RSH64_REG => self.builder.ins().ushr(dst, src),
+            ebpf::ARSH64_IMM => self.builder.ins().sshr(dst, imm),
+            ebpf::ARSH64_REG => self.builder.ins().sshr(dst, src),
+            ebpf::NEG64 => self.builder.ins().ineg(dst),
+            ebpf::MOV64_IMM => imm,
+            ebpf::MOV64_REG => src,
+            ebpf::HOR64_IMM => {
+                let upper = self
+                    .builder
+                    .ins()
+                    .iconst(I64, ((insn.imm as u64) << 32) as i64);
+                self.builder.ins().bor(dst, upper)
+            }
+            ebpf::ADD32_IMM => {
+                let result = self.builder.ins().iadd(dst32, imm32);
+                sign_extension(&mut self.builder, result)
+            }
+            ebpf::ADD32_REG => {
+                let result = self.builder.ins().iadd(dst32, src32);
+                sign_extension(&mut self.builder, result)
+            }
+            ebpf::SUB32_IMM => {
+                let result = if sbpf_version.swap_sub_reg_imm_operands() {
+                    self.builder.ins().isub(imm32, dst32)
+                } else {
+                    self.builder.ins().isub(dst32, imm32)
+                };
+                sign_extension(&mut self.builder, result)
+            }
+            ebpf::SUB32_REG => {
+                let result = self.builder.ins().isub(dst32, src32);
+                sign_extension(&mut self.builder, result)
+            }
+            ebpf::MUL32_IMM | ebpf::LMUL32_IMM | ebpf::MUL32_REG | ebpf::LMUL32_REG => {
+                let operand = if insn.opc & ebpf::BPF_X != 0 {
+                    src32
+                } else {
+                    imm32
+                };
+                let result = self.builder.ins().imul(dst32, operand);
+                if insn.opc == ebpf::MUL32_IMM || insn.opc == ebpf::MUL32_REG {
+                    self.builder.ins().sextend(I64, result)
+                } else {
+                    self.builder.ins().uextend(I64, result)
+                }
+            }
+            ebpf::OR32_IMM
+            | ebpf::OR32_REG
+            | ebpf::AND32_IMM
+            | ebpf::AND32_REG
+            | ebpf::XOR32_IMM
+            | ebpf::XOR32_REG
+            | ebpf::LSH32_IMM
+            | ebpf::LSH32_REG
+            | ebpf::RSH32_IMM
+            | ebpf::RSH32_REG
+            | ebpf::ARSH32_IMM
+            | ebpf::ARSH32_REG => {
+                let operand = if insn.opc & ebpf::BPF_X != 0 {
+                    src32
+                } else {
+                    imm32
+                };
+                let result = match insn.opc & ebpf::BPF_ALU_OP_MASK {
+                    ebpf::BPF_OR => self.builder.ins().bor(dst32, operand),
+                    ebpf::BPF_AND => self.builder.ins().band(dst32, operand),
+                    ebpf::BPF_XOR => self.builder.ins().bxor(dst32, operand),
+                    ebpf::BPF_LSH => self.builder.ins().ishl(dst32, operand),
+                    ebpf::BPF_RSH => self.builder.ins().ushr(dst32, operand),
+                    _ => self.builder.ins().sshr(dst32, operand),
+                };
+                self.builder.ins().uextend(I64, result)
+            }
+            ebpf::NEG32 => {
+                let result = self.builder.ins().ineg(dst32);
+                self.builder.ins().uextend(I64, result)
+            }
+            ebpf::MOV32_IMM => self.builder.ins().iconst(I64, insn.imm as u32 as i64),
+            ebpf::MOV32_REG if sbpf_version.explicit_sign_extension_of_results() => {
+                self.builder.ins().sextend(I64, src32)
+            }
+            ebpf::MOV32_REG => self.builder.ins().uextend(I64, src32),
+            ebpf::JA => {
+                self.jump_to(target_pc);
+                return;
+            }
+            _ => {
+                self.emit_conditional_jump(&insn, next_pc, target_pc);
+                return;
+            }
+        };
+        self.set_register(insn.dst, result);
+        self.jump_to(next_pc);
+    }
+
+    fn emit_conditional_jump(&mut self, insn: &ebpf::Insn, next_pc: i64, target_pc: i64) {
+        let dst = self.register(insn.dst);
+        let operand = if insn.opc & ebpf::BPF_X != 0 {
+            self.register(insn.src)
+        } else {
+            self.builder.ins().iconst(I64, insn.imm)
+        };
+        let condition = match insn.opc & ebpf::BPF_ALU_OP_MASK {
+            ebpf::BPF_JSET => {
+                let masked = self.builder.ins().band(dst, operand);
+                self.builder.ins().icmp_imm(IntCC::NotEqual, masked, 0)
+            }
+            op => {
+                let condition = match op {
+                    ebpf::BPF_JEQ => IntCC::Equal,
+                    ebpf::BPF_JGT => IntCC::UnsignedGreaterThan,
+                    ebpf::BPF_JGE => IntCC::UnsignedGreaterThanOrEqual,
+                    ebpf::BPF_JLT => IntCC::UnsignedLessThan,
+                    ebpf::BPF_JLE => IntCC::UnsignedLessThanOrEqual,
+                    ebpf::BPF_JNE => IntCC::NotEqual,
+                    ebpf::BPF_JSGT => IntCC::SignedGreaterThan,
+                    ebpf::BPF_JSGE => IntCC::SignedGreaterThanOrEqual,
+                    ebpf::BPF_JSLT => IntCC::SignedLessThan,
+                    _ => IntCC::SignedLessThanOrEqual,
+                };
+                self.builder.ins().icmp(condition, dst, operand)
+            }
+        };
+        let taken = self.builder.create_block();
+        let not_taken = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(condition, taken, &[], not_taken, &[]);
+        self.builder.switch_to_block(taken);
+        self.jump_to(target_pc);
+        self.builder.switch_to_block(not_taken);
+        self.jump_to(next_pc);
+    }
+
+    fn emit_memory_access(&mut self, pc: usize, insn: &ebpf::Insn, is_load: bool, len: u64) {
+        let base = self.register(if is_load { insn.src } else { insn.dst });
+        let vm_addr = self.builder.ins().iadd_imm(base, insn.off as i64);
+        let len_value = self.builder.ins().iconst(I64, len as i64);
+        let continuation = self.builder.create_block();
+        let pc_value = self.builder.ins().iconst(I64, pc as i64);
+        if is_load {
+            let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
+                StackSlotKind::ExplicitSlot,
+                8,
+                3,
+            ));
+            let value_pointer = self.builder.ins().stack_addr(self.pointer_type, slot, 0);
+            let success = self
+                .call_stub(
+                    self.stubs.load,
+                    &[self.runtime_environment, vm_addr, len_value, value_pointer],
+                )
+                .unwrap();
+            self.builder
+                .ins()
+                .brif(success, continuation, &[], self.throw, &[pc_value]);
+            self.builder.switch_to_block(continuation);
+            let value = self.builder.ins().stack_load(I64, slot, 0);
+            self.set_register(insn.dst, value);
+        } else {
+            let value = match insn.opc {
+                ebpf::ST_1B_REG
+                | ebpf::ST_2B_REG
+                | ebpf::ST_4B_REG
+                | ebpf::ST_8B_REG
+                | ebpf::ST_B_REG
+                | ebpf::ST_H_REG
+                | ebpf::ST_W_REG
+                | ebpf::ST_DW_REG => self.register(insn.src),
+                _ => self.builder.ins().iconst(I64, insn.imm),
+            };
+            let success = self
+                .call_stub(
+                    self.stubs.store,
+                    &[self.runtime_environment, vm_addr, value, len_value],
+                )
+                .unwrap();
+            self.builder
+                .ins()
+                .brif(success, continuation, &[], self.throw, &[pc_value]);
+            self.builder.switch_to_block(continuation);
+        }
+        self.jump_to(pc as i64 + 1);
+    }
+
+    /// Whether the instruction is translated instead of delegated to the interpreter
+    ///
+    /// Mirrors the version guards of [Interpreter::step].
+    fn is_native(&self, insn: &ebpf::Insn) -> bool {
+        let sbpf_version = self.executable.get_sbpf_version();
+        if memory_access(insn, sbpf_version).is_some() {
+            return true;
+        }
+        match insn.opc {
+            ebpf::MUL32_IMM | ebpf::MUL32_REG | ebpf::MUL64_IMM | ebpf::MUL64_REG => {
+                !sbpf_version.enable_pqr()
+            }
+            ebpf::LMUL32_IMM | ebpf::LMUL32_REG | ebpf::LMUL64_IMM | ebpf::LMUL64_REG => {
+                sbpf_version.enable_pqr()
+            }
+            ebpf::NEG32 | ebpf::NEG64 => !sbpf_version.disable_neg(),
+            ebpf::HOR64_IMM => sbpf_version.disable_lddw(),
+            ebpf::ADD64_IMM
+            | ebpf::ADD64_REG
+            | ebpf::SUB64_IMM
+            | ebpf::SUB64_REG
+            | ebpf::OR64_IMM
+            | ebpf::OR64_REG
+            | ebpf::AND64_IMM
+            | ebpf::AND64_REG
+            | ebpf::XOR64_IMM
+            | ebpf::XOR64_REG
+            | ebpf::LSH64_IMM
+            | ebpf::LSH64_REG
+            | ebpf::RSH64_IMM
+            | ebpf::RSH64_REG
+            | ebpf::ARSH64_IMM
+            | ebpf::ARSH64_REG
+            | ebpf::MOV64_IMM
+            | ebpf::MOV64_REG
+            | ebpf::ADD32_IMM
+            | ebpf::ADD32_REG
+            | ebpf::SUB32_IMM
+            | ebpf::SUB32_REG
+            | ebpf::OR32_IMM
+            | ebpf::OR32_REG
+            | ebpf::AND32_IMM
+            | ebpf::AND32_REG
+            | ebpf::XOR32_IMM
+            | ebpf::XOR32_REG
+            | ebpf::LSH32_IMM
+            | ebpf::LSH32_REG
+            | ebpf::RSH32_IMM
+            | ebpf::RSH32_REG
+            | ebpf::ARSH32_IMM
+            | ebpf::ARSH32_REG
+            | ebpf::MOV32_IMM
+            | ebpf::MOV32_REG
+            | ebpf::JA
+            | ebpf::JEQ_IMM
+            | ebpf::JEQ_REG
+            | ebpf::JGT_IMM
+            | ebpf::JGT_REG
+            | ebpf::JGE_IMM
+            | ebpf::JGE_REG
+            | ebpf::JLT_IMM
+            | ebpf::JLT_REG
+            | ebpf::JLE_IMM
+            | ebpf::JLE_REG
+            | ebpf::JSET_IMM
+            | ebpf::JSET_REG
+            | ebpf::JNE_IMM
+            | ebpf::JNE_REG
+            | ebpf::JSGT_IMM
+            | ebpf::JSGT_REG
+            | ebpf::JSGE_IMM
+            | ebpf::JSGE_REG
+            | ebpf::JSLT_IMM
+            | ebpf::JSLT_REG
+            | ebpf::JSLE_IMM
+            | ebpf::JSLE_REG => true,
+            _ => false,
+        }
+    }
+}
+
+/// Whether an instruction is a load (or a store) and how many bytes it accesses
+fn memory_access(
+    insn: &ebpf::Insn,
+    sbpf_version: crate::program::SBPFVersion,
+) -> Option<(bool, u64)> {
+    if sbpf_version.move_memory_instruction_classes() {
+        match insn.opc {
+            ebpf::LD_1B_REG => Some((true, 1)),
+            ebpf::LD_2B_REG => Some((true, 2)),
+            ebpf::LD_4B_REG => Some((true, 4)),
+            ebpf::LD_8B_REG => Some((true, 8)),
+            ebpf::ST_1B_IMM | ebpf::ST_1B_REG => Some((false, 1)),
+            ebpf::ST_2B_IMM | ebpf::ST_2B_REG => Some((false, 2)),
+            ebpf::ST_4B_IMM | ebpf::ST_4B_REG => Some((false, 4)),
+            ebpf::ST_8B_IMM | ebpf::ST_8B_REG => Some((false, 8)),
+            _ => None,
+        }
+    } else {
+        match insn.opc {
+            ebpf::LD_B_REG => Some((true, 1)),
+            ebpf::LD_H_REG => Some((true, 2)),
+            ebpf::LD_W_REG => Some((true, 4)),
+            ebpf::LD_DW_REG => Some((true, 8)),
+            ebpf::ST_B_IMM | ebpf::ST_B_REG => Some((false, 1)),
+            ebpf::ST_H_IMM | ebpf::ST_H_REG => Some((false, 2)),
+            ebpf::ST_W_IMM | ebpf::ST_W_REG => Some((false, 4)),
+            ebpf::ST_DW_IMM | ebpf::ST_DW_REG => Some((false, 8)),
+            _ => None,
+        }
+    }
+}
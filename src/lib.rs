@@ -23,27 +23,82 @@ extern crate log;
 extern crate rand;
 extern crate thiserror;
 
+pub mod abi;
+pub mod accounts;
 pub mod aligned_memory;
 mod asm_parser;
 pub mod assembler;
+pub mod block_trace;
+pub mod branch_distance;
+pub mod compatibility;
+pub mod conformance;
+pub mod corpus;
+pub mod cost_model;
+pub mod crash_report;
+pub mod deadline;
 #[cfg(feature = "debugger")]
 pub mod debugger;
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
 pub mod disassembler;
 pub mod ebpf;
 pub mod elf;
 pub mod elf_parser;
 pub mod error;
+pub mod fault_injection;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(all(feature = "fuzz-server", unix))]
+pub mod fuzz_server;
+pub mod global_coverage;
+pub mod grammar;
+pub mod harness;
+pub mod heap_sanitizer;
+pub mod influence;
+pub mod input_hook;
 pub mod insn_builder;
 pub mod interpreter;
 #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
 pub mod jit;
+#[cfg(feature = "jit-cranelift")]
+pub mod jit_cranelift;
+pub mod lifter;
+pub mod loop_detector;
+#[cfg(all(feature = "mmap", unix))]
+pub mod mapped_file;
+pub mod memory_builtins;
 #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
 mod memory_management;
 pub mod memory_region;
+pub mod observer;
+pub mod opcode_table;
+pub mod prng;
+pub mod profiler;
 pub mod program;
+pub mod program_mutation;
+pub mod progress;
+pub mod redaction;
+pub mod replay;
+pub mod runtime;
+pub mod semantic;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod solana_input;
+#[cfg(feature = "dwarf")]
+pub mod source_map;
+pub mod stack_sanitizer;
+pub mod state_hash;
 pub mod static_analysis;
+pub mod sysvars;
+pub mod taint;
+pub mod trace_buffer;
+#[cfg(feature = "trace-export")]
+pub mod trace_export;
+pub mod value_profile;
 pub mod verifier;
 pub mod vm;
+pub mod vm_pool;
+pub mod watch;
 #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
 mod x86;
 
@@ -0,0 +1,713 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Lifting of SBPF programs to LLVM IR for offline analysis
+//!
+//! [lift] writes a textual LLVM module which existing static analyzers and symbolic executors
+//! can consume, e.g. after assembling it with `llvm-as`. Every function of the
+//! [Analysis] becomes an IR function and every basic block of its control-flow graph an IR
+//! block. The guest registers are `alloca` slots, which `mem2reg` promotes to SSA values.
+//!
+//! Calling convention: A function receives all eleven registers r0 to r10 and returns r0 to r5,
+//! because the VM only restores r6 to r10 of the caller. With fixed stack frames the caller
+//! advances r10 by the frame size before the call. The `sbpf.entrypoint` function starts the
+//! program with r1 pointing to the input and r10 being the initial frame pointer.
+//!
+//! Virtual addresses are converted to pointers as they are, so memory accesses are plain
+//! `load`s and `store`s without address translation. Syscalls are declared as external
+//! functions `syscall.<name>` of r1 to r5, and `callx` calls the external `sbpf.callx` with
+//! the target address. The instruction meter is not modeled. Errors the interpreter raises
+//! while executing an instruction become calls of the `noreturn` functions
+//! `sbpf.divide_by_zero`, `sbpf.divide_overflow` and `sbpf.unsupported_instruction` with the pc.
+
+use crate::{
+    ebpf,
+    static_analysis::{Analysis, CfgNode},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    io::Write,
+};
+
+/// Type of the registers a function returns
+const RETURN_TYPE: &str = "{ i64, i64, i64, i64, i64, i64 }";
+
+/// Parameter list of r0 to r10
+const REGISTER_PARAMS: &str =
+    "i64 %a0, i64 %a1, i64 %a2, i64 %a3, i64 %a4, i64 %a5, i64 %a6, i64 %a7, i64 %a8, i64 %a9, i64 %a10";
+
+/// Writes the analyzed executable as a textual LLVM module
+pub fn lift<W: Write>(analysis: &Analysis, output: &mut W) -> std::io::Result<()> {
+    let sbpf_version = analysis.sbpf_version();
+    let executable = analysis.executable();
+    let loader = executable.get_loader();
+    let config = executable.get_config();
+    let function_names = unique_function_names(analysis);
+    let mut syscalls = BTreeSet::new();
+
+    writeln!(
+        output,
+        "; SBPF {sbpf_version:?} program lifted by solana-sbpf"
+    )?;
+    let function_starts = analysis.functions.keys().copied().collect::<Vec<_>>();
+    for (index, function_start) in function_starts.iter().enumerate() {
+        let function_end = function_starts
+            .get(index + 1)
+            .copied()
+            .unwrap_or(usize::MAX);
+        let cfg_nodes = analysis
+            .cfg_nodes
+            .range(*function_start..function_end)
+            .filter(|(pc, _cfg_node)| **pc != analysis.super_root)
+            .collect::<Vec<_>>();
+        let mut function = FunctionLifter {
+            analysis,
+            function_names: &function_names,
+            syscalls: &mut syscalls,
+            blocks: cfg_nodes.iter().map(|(pc, _cfg_node)| **pc).collect(),
+            frame_size: if sbpf_version.dynamic_stack_frames() {
+                0
+            } else {
+                config.stack_frame_size as u64 * if config.enable_stack_frame_gaps { 2 } else { 1 }
+            },
+            body: String::new(),
+            next_value: 0,
+        };
+        writeln!(output)?;
+        writeln!(
+            output,
+            "; {}",
+            analysis.cfg_nodes[function_start].label.replace('\n', " ")
+        )?;
+        writeln!(
+            output,
+            "define {RETURN_TYPE} @{}({REGISTER_PARAMS}) {{",
+            quote(&function_names[function_start]),
+        )?;
+        writeln!(output, "entry:")?;
+        for register in 0..=10 {
+            writeln!(output, "  %r{register} = alloca i64, align 8")?;
+            writeln!(
+                output,
+                "  store i64 %a{register}, ptr %r{register}, align 8"
+            )?;
+        }
+        writeln!(output, "  br label %pc{function_start}")?;
+        for (pc, cfg_node) in cfg_nodes {
+            function.lift_basic_block(*pc, cfg_node);
+        }
+        output.write_all(function.body.as_bytes())?;
+        writeln!(output, "}}")?;
+    }
+
+    let entrypoint = analysis
+        .functions
+        .range(analysis.entrypoint..)
+        .next()
+        .filter(|(pc, _function)| **pc == analysis.entrypoint);
+    if let Some((pc, _function)) = entrypoint {
+        writeln!(output)?;
+        writeln!(
+            output,
+            "define i64 @sbpf.entrypoint(i64 %input, i64 %frame_pointer) {{"
+        )?;
+        writeln!(output, "entry:")?;
+        writeln!(
+            output,
+            "  %registers = call {RETURN_TYPE} @{}(i64 0, i64 %input, i64 0, i64 0, i64 0, i64 0, i64 0, i64 0, i64 0, i64 0, i64 %frame_pointer)",
+            quote(&function_names[pc]),
+        )?;
+        writeln!(
+            output,
+            "  %result = extractvalue {RETURN_TYPE} %registers, 0"
+        )?;
+        writeln!(output, "  ret i64 %result")?;
+        writeln!(output, "}}")?;
+    }
+
+    writeln!(output)?;
+    for key in syscalls {
+        let name = loader
+            .get_function_registry()
+            .lookup_by_key(key)
+            .map(|(name, _function)| String::from_utf8_lossy(name).to_string())
+            .unwrap_or_else(|| key.to_string());
+        writeln!(
+            output,
+            "declare i64 @{}(i64, i64, i64, i64, i64)",
+            quote(&format!("syscall.{name}")),
+        )?;
+    }
+    writeln!(
+        output,
+        "declare {RETURN_TYPE} @sbpf.callx(i64, i64, i64, i64, i64, i64, i64, i64, i64, i64, i64, i64)"
+    )?;
+    for trap in [
+        "divide_by_zero",
+        "divide_overflow",
+        "unsupported_instruction",
+    ] {
+        writeln!(output, "declare void @sbpf.{trap}(i64) noreturn")?;
+    }
+    for width in [16, 32, 64] {
+        writeln!(output, "declare i{width} @llvm.bswap.i{width}(i{width})")?;
+    }
+    Ok(())
+}
+
+/// Names of the IR functions, the symbol names made unique by their pc where they collide
+fn unique_function_names(analysis: &Analysis) -> BTreeMap<usize, String> {
+    let mut used = BTreeSet::new();
+    analysis
+        .functions
+        .iter()
+        .map(|(pc, (_key, name))| {
+            let mut name = name.clone();
+            if name.is_empty() || !used.insert(name.clone()) {
+                name = format!("{name}.{pc}");
+                used.insert(name.clone());
+            }
+            (*pc, name)
+        })
+        .collect()
+}
+
+/// Quotes an LLVM identifier
+fn quote(name: &str) -> String {
+    let mut quoted = String::from("\"");
+    for byte in name.bytes() {
+        if byte == b'"' || byte == b'\\' || !(0x20..0x7f).contains(&byte) {
+            let _ = write!(quoted, "\\{byte:02X}");
+        } else {
+            quoted.push(byte as char);
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Width and signedness of an ALU operation
+#[derive(Clone, Copy)]
+enum Width {
+    /// 32 bit operands, result zero extended
+    Zext32,
+    /// 32 bit operands, result sign extended
+    Sext32,
+    /// 64 bit operands
+    Bits64,
+}
+
+impl Width {
+    fn ty(self) -> &'static str {
+        match self {
+            Width::Zext32 | Width::Sext32 => "i32",
+            Width::Bits64 => "i64",
+        }
+    }
+}
+
+/// Lifts the basic blocks of one function
+struct FunctionLifter<'a, 'b> {
+    analysis: &'a Analysis<'b>,
+    function_names: &'a BTreeMap<usize, String>,
+    /// Keys of the syscalls which need to be declared
+    syscalls: &'a mut BTreeSet<u32>,
+    /// Start pcs of the basic blocks of the function
+    blocks: BTreeSet<usize>,
+    /// Increment of r10 for a call
+    frame_size: u64,
+    body: String,
+    next_value: usize,
+}
+
+impl FunctionLifter<'_, '_> {
+    /// Emits an instruction with a result and returns the name of the result
+    fn value(&mut self, instruction: impl std::fmt::Display) -> String {
+        let name = format!("%v{}", self.next_value);
+        self.next_value += 1;
+        let _ = writeln!(self.body, "  {name} = {instruction}");
+        name
+    }
+
+    fn emit(&mut self, instruction: impl std::fmt::Display) {
+        let _ = writeln!(self.body, "  {instruction}");
+    }
+
+    fn label(&mut self, label: &str) {
+        let _ = writeln!(self.body, "{label}:");
+    }
+
+    fn load_register(&mut self, register: u8) -> String {
+        self.value(format_args!("load i64, ptr %r{register}, align 8"))
+    }
+
+    fn store_register(&mut self, register: u8, value: &str) {
+        self.emit(format_args!("store i64 {value}, ptr %r{register}, align 8"));
+    }
+
+    /// Loads a register truncated to the operand width
+    fn operand(&mut self, register: u8, width: Width) -> String {
+        let value = self.load_register(register);
+        match width {
+            Width::Bits64 => value,
+            _ => self.value(format_args!("trunc i64 {value} to i32")),
+        }
+    }
+
+    /// Stores the result of an operation of the given width
+    fn store_result(&mut self, register: u8, value: &str, width: Width) {
+        let value = match width {
+            Width::Bits64 => value.to_string(),
+            Width::Zext32 => self.value(format_args!("zext i32 {value} to i64")),
+            Width::Sext32 => self.value(format_args!("sext i32 {value} to i64")),
+        };
+        self.store_register(register, &value);
+    }
+
+    /// Leaves through a `noreturn` function
+    fn trap(&mut self, trap: &str, pc: usize) {
+        self.emit(format_args!("call void @sbpf.{trap}(i64 {pc})"));
+        self.emit("unreachable");
+    }
+
+    /// Traps if `condition` holds and continues in a new block otherwise
+    fn trap_if(&mut self, condition: &str, trap: &str, pc: usize) {
+        let label = format!("pc{pc}.{trap}");
+        self.emit(format_args!(
+            "br i1 {condition}, label %{label}, label %{label}.ok"
+        ));
+        self.label(&label);
+        self.trap(trap, pc);
+        self.label(&format!("{label}.ok"));
+    }
+
+    /// Branches to the block at `target_pc`, if it belongs to this function
+    fn branch(&mut self, target_pc: usize) {
+        if self.blocks.contains(&target_pc) {
+            self.emit(format_args!("br label %pc{target_pc}"));
+        } else {
+            self.trap("unsupported_instruction", target_pc);
+        }
+    }
+
+    /// Emits `dst = dst op rhs`
+    fn binary(&mut self, op: &str, insn: &ebpf::Insn, rhs: &str, width: Width) {
+        let lhs = self.operand(insn.dst, width);
+        let result = self.value(format_args!("{op} {} {lhs}, {rhs}", width.ty()));
+        self.store_result(insn.dst, &result, width);
+    }
+
+    /// Right hand side of an operation with an immediate or register source
+    fn source(&mut self, insn: &ebpf::Insn, width: Width) -> String {
+        if insn.opc & ebpf::BPF_X != 0 {
+            self.operand(insn.src, width)
+        } else {
+            match width {
+                Width::Bits64 => insn.imm.to_string(),
+                _ => (insn.imm as i32).to_string(),
+            }
+        }
+    }
+
+    /// Emits a shift, the amount masked like by `wrapping_shl`
+    fn shift(&mut self, op: &str, insn: &ebpf::Insn, width: Width) {
+        let mask = match width {
+            Width::Bits64 => 63,
+            _ => 31,
+        };
+        let amount = if insn.opc & ebpf::BPF_X != 0 {
+            let amount = self.operand(insn.src, width);
+            self.value(format_args!("and {} {amount}, {mask}", width.ty()))
+        } else {
+            (insn.imm & mask).to_string()
+        };
+        self.binary(op, insn, &amount, width);
+    }
+
+    /// Emits a division or remainder with the checks of the interpreter
+    fn division(&mut self, op: &str, pc: usize, insn: &ebpf::Insn, rhs: &str, width: Width) {
+        let lhs = self.operand(insn.dst, width);
+        let ty = width.ty();
+        if insn.opc & ebpf::BPF_X != 0 {
+            let is_zero = self.value(format_args!("icmp eq {ty} {rhs}, 0"));
+            self.trap_if(&is_zero, "divide_by_zero", pc);
+        }
+        if op.starts_with('s') {
+            let min = match width {
+                Width::Bits64 => i64::MIN.to_string(),
+                _ => i32::MIN.to_string(),
+            };
+            let is_minus_one = self.value(format_args!("icmp eq {ty} {rhs}, -1"));
+            let is_min = self.value(format_args!("icmp eq {ty} {lhs}, {min}"));
+            let overflows = self.value(format_args!("and i1 {is_minus_one}, {is_min}"));
+            self.trap_if(&overflows, "divide_overflow", pc);
+        }
+        let result = self.value(format_args!("{op} {ty} {lhs}, {rhs}"));
+        self.store_result(insn.dst, &result, width);
+    }
+
+    /// Emits the high half of a 128 bit product
+    fn high_multiplication(&mut self, insn: &ebpf::Insn, signed: bool) {
+        let extension = if signed { "sext" } else { "zext" };
+        let lhs = self.load_register(insn.dst);
+        let lhs = self.value(format_args!("{extension} i64 {lhs} to i128"));
+        let rhs = if insn.opc & ebpf::BPF_X != 0 {
+            let rhs = self.load_register(insn.src);
+            self.value(format_args!("{extension} i64 {rhs} to i128"))
+        } else if signed {
+            insn.imm.to_string()
+        } else {
+            (insn.imm as u32).to_string()
+        };
+        let product = self.value(format_args!("mul i128 {lhs}, {rhs}"));
+        let high = self.value(format_args!("lshr i128 {product}, 64"));
+        let result = self.value(format_args!("trunc i128 {high} to i64"));
+        self.store_register(insn.dst, &result);
+    }
+
+    /// Emits a byte swap to big endian or a truncation for little endian
+    fn byte_swap(&mut self, pc: usize, insn: &ebpf::Insn, big_endian: bool) {
+        let width = match insn.imm {
+            16 | 32 | 64 => insn.imm,
+            _ => return self.trap("unsupported_instruction", pc),
+        };
+        let mut value = self.load_register(insn.dst);
+        if width < 64 {
+            value = self.value(format_args!("trunc i64 {value} to i{width}"));
+        }
+        if big_endian {
+            value = self.value(format_args!(
+                "call i{width} @llvm.bswap.i{width}(i{width} {value})"
+            ));
+        }
+        if width < 64 {
+            value = self.value(format_args!("zext i{width} {value} to i64"));
+        }
+        self.store_register(insn.dst, &value);
+    }
+
+    /// Emits a load or store of `len` bytes
+    fn memory_access(&mut self, insn: &ebpf::Insn, is_load: bool, len: u32) {
+        let ty = format!("i{}", len * 8);
+        let base = self.load_register(if is_load { insn.src } else { insn.dst });
+        let vm_addr = self.value(format_args!("add i64 {base}, {}", insn.off));
+        let pointer = self.value(format_args!("inttoptr i64 {vm_addr} to ptr"));
+        if is_load {
+            let mut value = self.value(format_args!("load {ty}, ptr {pointer}, align 1"));
+            if len < 8 {
+                value = self.value(format_args!("zext {ty} {value} to i64"));
+            }
+            self.store_register(insn.dst, &value);
+        } else {
+            let from_register = matches!(
+                insn.opc,
+                ebpf::ST_B_REG
+                    | ebpf::ST_H_REG
+                    | ebpf::ST_W_REG
+                    | ebpf::ST_DW_REG
+                    | ebpf::ST_1B_REG
+                    | ebpf::ST_2B_REG
+                    | ebpf::ST_4B_REG
+                    | ebpf::ST_8B_REG
+            );
+            let value = if from_register {
+                let value = self.load_register(insn.src);
+                if len < 8 {
+                    self.value(format_args!("trunc i64 {value} to {ty}"))
+                } else {
+                    value
+                }
+            } else {
+                match len {
+                    1 => (insn.imm as i8).to_string(),
+                    2 => (insn.imm as i16).to_string(),
+                    4 => (insn.imm as i32).to_string(),
+                    _ => insn.imm.to_string(),
+                }
+            };
+            self.emit(format_args!("store {ty} {value}, ptr {pointer}, align 1"));
+        }
+    }
+
+    /// Calls a function with the current registers and takes over r0 to r5 of the result
+    fn call(&mut self, callee: &str, extra_argument: Option<&str>) {
+        let mut arguments = Vec::new();
+        if let Some(argument) = extra_argument {
+            arguments.push(format!("i64 {argument}"));
+        }
+        for register in 0..=10 {
+            let mut value = self.load_register(register);
+            let frame_size = self.frame_size;
+            if register == ebpf::FRAME_PTR_REG as u8 && frame_size != 0 {
+                value = self.value(format_args!("add i64 {value}, {frame_size}"));
+            }
+            arguments.push(format!("i64 {value}"));
+        }
+        let registers = self.value(format_args!(
+            "call {RETURN_TYPE} {callee}({})",
+            arguments.join(", ")
+        ));
+        for register in 0..=5 {
+            let value = self.value(format_args!(
+                "extractvalue {RETURN_TYPE} {registers}, {register}"
+            ));
+            self.store_register(register, &value);
+        }
+    }
+
+    /// Calls a syscall with r1 to r5 and stores its result in r0
+    fn syscall(&mut self, key: u32) {
+        self.syscalls.insert(key);
+        let name = self
+            .analysis
+            .executable()
+            .get_loader()
+            .get_function_registry()
+            .lookup_by_key(key)
+            .map(|(name, _function)| String::from_utf8_lossy(name).to_string())
+            .unwrap_or_else(|| key.to_string());
+        let arguments = (1..=5)
+            .map(|register| format!("i64 {}", self.load_register(register)))
+            .collect::<Vec<_>>();
+        let result = self.value(format_args!(
+            "call i64 @{}({})",
+            quote(&format!("syscall.{name}")),
+            arguments.join(", ")
+        ));
+        self.store_register(0, &result);
+    }
+
+    /// Returns r0 to r5
+    fn exit(&mut self) {
+        let mut registers = "undef".to_string();
+        for register in 0..=5 {
+            let value = self.load_register(register);
+            registers = self.value(format_args!(
+                "insertvalue {RETURN_TYPE} {registers}, i64 {value}, {register}"
+            ));
+        }
+        self.emit(format_args!("ret {RETURN_TYPE} {registers}"));
+    }
+
+    fn lift_basic_block(&mut self, pc: usize, cfg_node: &CfgNode) {
+        let analysis = self.analysis;
+        self.label(&format!("pc{pc}"));
+        for insn in analysis.instructions[cfg_node.instructions.clone()].iter() {
+            if !self.lift_instruction(insn) {
+                return;
+            }
+        }
+        let fallthrough_pc = analysis
+            .instructions
+            .get(cfg_node.instructions.end)
+            .map(|insn| insn.ptr)
+            .unwrap_or(usize::MAX);
+        self.branch(fallthrough_pc);
+    }
+
+    /// Lifts an instruction, returns false if it terminated the basic block
+    #[rustfmt::skip]
+    fn lift_instruction(&mut self, insn: &ebpf::Insn) -> bool {
+        let sbpf_version = self.analysis.sbpf_version();
+        let executable = self.analysis.executable();
+        let pc = insn.ptr;
+        let memory_classes = sbpf_version.move_memory_instruction_classes();
+        let pqr = sbpf_version.enable_pqr();
+        let add_sub_width = if sbpf_version.explicit_sign_extension_of_results() { Width::Zext32 } else { Width::Sext32 };
+        let target_pc = (pc as i64 + insn.off as i64 + 1) as usize;
+        let condition = match insn.opc {
+            ebpf::JEQ_IMM | ebpf::JEQ_REG => Some("eq"),
+            ebpf::JGT_IMM | ebpf::JGT_REG => Some("ugt"),
+            ebpf::JGE_IMM | ebpf::JGE_REG => Some("uge"),
+            ebpf::JLT_IMM | ebpf::JLT_REG => Some("ult"),
+            ebpf::JLE_IMM | ebpf::JLE_REG => Some("ule"),
+            ebpf::JSET_IMM | ebpf::JSET_REG => Some("set"),
+            ebpf::JNE_IMM | ebpf::JNE_REG => Some("ne"),
+            ebpf::JSGT_IMM | ebpf::JSGT_REG => Some("sgt"),
+            ebpf::JSGE_IMM | ebpf::JSGE_REG => Some("sge"),
+            ebpf::JSLT_IMM | ebpf::JSLT_REG => Some("slt"),
+            ebpf::JSLE_IMM | ebpf::JSLE_REG => Some("sle"),
+            _ => None,
+        };
+        if let Some(condition) = condition {
+            let lhs = self.load_register(insn.dst);
+            let rhs = self.source(insn, Width::Bits64);
+            let taken = if condition == "set" {
+                let bits = self.value(format_args!("and i64 {lhs}, {rhs}"));
+                self.value(format_args!("icmp ne i64 {bits}, 0"))
+            } else {
+                self.value(format_args!("icmp {condition} i64 {lhs}, {rhs}"))
+            };
+            let not_taken_pc = pc + 1;
+            if self.blocks.contains(&target_pc) && self.blocks.contains(&not_taken_pc) {
+                self.emit(format_args!("br i1 {taken}, label %pc{target_pc}, label %pc{not_taken_pc}"));
+            } else {
+                self.trap("unsupported_instruction", pc);
+            }
+            return false;
+        }
+        match insn.opc {
+            ebpf::LD_DW_IMM if !sbpf_version.disable_lddw() => self.store_register(insn.dst, &insn.imm.to_string()),
+
+            ebpf::LD_B_REG  if !memory_classes => self.memory_access(insn, true, 1),
+            ebpf::LD_H_REG  if !memory_classes => self.memory_access(insn, true, 2),
+            ebpf::LD_W_REG  if !memory_classes => self.memory_access(insn, true, 4),
+            ebpf::LD_DW_REG if !memory_classes => self.memory_access(insn, true, 8),
+            ebpf::ST_B_IMM  | ebpf::ST_B_REG  if !memory_classes => self.memory_access(insn, false, 1),
+            ebpf::ST_H_IMM  | ebpf::ST_H_REG  if !memory_classes => self.memory_access(insn, false, 2),
+            ebpf::ST_W_IMM  | ebpf::ST_W_REG  if !memory_classes => self.memory_access(insn, false, 4),
+            ebpf::ST_DW_IMM | ebpf::ST_DW_REG if !memory_classes => self.memory_access(insn, false, 8),
+            ebpf::LD_1B_REG if memory_classes => self.memory_access(insn, true, 1),
+            ebpf::LD_2B_REG if memory_classes => self.memory_access(insn, true, 2),
+            ebpf::LD_4B_REG if memory_classes => self.memory_access(insn, true, 4),
+            ebpf::LD_8B_REG if memory_classes => self.memory_access(insn, true, 8),
+            ebpf::ST_1B_IMM | ebpf::ST_1B_REG if memory_classes => self.memory_access(insn, false, 1),
+            ebpf::ST_2B_IMM | ebpf::ST_2B_REG if memory_classes => self.memory_access(insn, false, 2),
+            ebpf::ST_4B_IMM | ebpf::ST_4B_REG if memory_classes => self.memory_access(insn, false, 4),
+            ebpf::ST_8B_IMM | ebpf::ST_8B_REG if memory_classes => self.memory_access(insn, false, 8),
+
+            ebpf::ADD32_IMM | ebpf::ADD32_REG => {
+                let rhs = self.source(insn, Width::Zext32);
+                self.binary("add", insn, &rhs, add_sub_width);
+            }
+            ebpf::SUB32_IMM if sbpf_version.swap_sub_reg_imm_operands() => {
+                let rhs = self.operand(insn.dst, Width::Zext32);
+                let result = self.value(format_args!("sub i32 {}, {rhs}", insn.imm as i32));
+                self.store_result(insn.dst, &result, add_sub_width);
+            }
+            ebpf::SUB32_IMM | ebpf::SUB32_REG => {
+                let rhs = self.source(insn, Width::Zext32);
+                self.binary("sub", insn, &rhs, add_sub_width);
+            }
+            ebpf::MUL32_IMM | ebpf::MUL32_REG if !pqr => {
+                let rhs = self.source(insn, Width::Sext32);
+                self.binary("mul", insn, &rhs, Width::Sext32);
+            }
+            ebpf::DIV32_IMM | ebpf::DIV32_REG if !pqr => {
+                let rhs = self.source(insn, Width::Zext32);
+                self.division("udiv", pc, insn, &rhs, Width::Zext32);
+            }
+            ebpf::MOD32_IMM | ebpf::MOD32_REG if !pqr => {
+                let rhs = self.source(insn, Width::Zext32);
+                self.division("urem", pc, insn, &rhs, Width::Zext32);
+            }
+            ebpf::OR32_IMM  | ebpf::OR32_REG  => { let rhs = self.source(insn, Width::Zext32); self.binary("or", insn, &rhs, Width::Zext32); }
+            ebpf::AND32_IMM | ebpf::AND32_REG => { let rhs = self.source(insn, Width::Zext32); self.binary("and", insn, &rhs, Width::Zext32); }
+            ebpf::XOR32_IMM | ebpf::XOR32_REG => { let rhs = self.source(insn, Width::Zext32); self.binary("xor", insn, &rhs, Width::Zext32); }
+            ebpf::LSH32_IMM | ebpf::LSH32_REG => self.shift("shl", insn, Width::Zext32),
+            ebpf::RSH32_IMM | ebpf::RSH32_REG => self.shift("lshr", insn, Width::Zext32),
+            ebpf::ARSH32_IMM | ebpf::ARSH32_REG => self.shift("ashr", insn, Width::Zext32),
+            ebpf::NEG32 if !sbpf_version.disable_neg() => {
+                let value = self.operand(insn.dst, Width::Zext32);
+                let result = self.value(format_args!("sub i32 0, {value}"));
+                self.store_result(insn.dst, &result, Width::Zext32);
+            }
+            ebpf::MOV32_IMM => self.store_register(insn.dst, &(insn.imm as u32).to_string()),
+            ebpf::MOV32_REG => {
+                let value = self.operand(insn.src, Width::Zext32);
+                let width = if sbpf_version.explicit_sign_extension_of_results() { Width::Sext32 } else { Width::Zext32 };
+                self.store_result(insn.dst, &value, width);
+            }
+            ebpf::LE if !sbpf_version.disable_le() => self.byte_swap(pc, insn, false),
+            ebpf::BE => self.byte_swap(pc, insn, true),
+
+            ebpf::ADD64_IMM | ebpf::ADD64_REG => { let rhs = self.source(insn, Width::Bits64); self.binary("add", insn, &rhs, Width::Bits64); }
+            ebpf::SUB64_IMM if sbpf_version.swap_sub_reg_imm_operands() => {
+                let rhs = self.load_register(insn.dst);
+                let result = self.value(format_args!("sub i64 {}, {rhs}", insn.imm));
+                self.store_register(insn.dst, &result);
+            }
+            ebpf::SUB64_IMM | ebpf::SUB64_REG => { let rhs = self.source(insn, Width::Bits64); self.binary("sub", insn, &rhs, Width::Bits64); }
+            ebpf::MUL64_IMM | ebpf::MUL64_REG if !pqr => { let rhs = self.source(insn, Width::Bits64); self.binary("mul", insn, &rhs, Width::Bits64); }
+            ebpf::DIV64_IMM | ebpf::DIV64_REG if !pqr => {
+                let rhs = self.source(insn, Width::Bits64);
+                self.division("udiv", pc, insn, &rhs, Width::Bits64);
+            }
+            ebpf::MOD64_IMM | ebpf::MOD64_REG if !pqr => {
+                let rhs = self.source(insn, Width::Bits64);
+                self.division("urem", pc, insn, &rhs, Width::Bits64);
+            }
+            ebpf::OR64_IMM  | ebpf::OR64_REG  => { let rhs = self.source(insn, Width::Bits64); self.binary("or", insn, &rhs, Width::Bits64); }
+            ebpf::AND64_IMM | ebpf::AND64_REG => { let rhs = self.source(insn, Width::Bits64); self.binary("and", insn, &rhs, Width::Bits64); }
+            ebpf::XOR64_IMM | ebpf::XOR64_REG => { let rhs = self.source(insn, Width::Bits64); self.binary("xor", insn, &rhs, Width::Bits64); }
+            ebpf::LSH64_IMM | ebpf::LSH64_REG => self.shift("shl", insn, Width::Bits64),
+            ebpf::RSH64_IMM | ebpf::RSH64_REG => self.shift("lshr", insn, Width::Bits64),
+            ebpf::ARSH64_IMM | ebpf::ARSH64_REG => self.shift("ashr", insn, Width::Bits64),
+            ebpf::NEG64 if !sbpf_version.disable_neg() => {
+                let value = self.load_register(insn.dst);
+                let result = self.value(format_args!("sub i64 0, {value}"));
+                self.store_register(insn.dst, &result);
+            }
+            ebpf::MOV64_IMM => self.store_register(insn.dst, &insn.imm.to_string()),
+            ebpf::MOV64_REG => {
+                let value = self.load_register(insn.src);
+                self.store_register(insn.dst, &value);
+            }
+            ebpf::HOR64_IMM if sbpf_version.disable_lddw() => {
+                let high = ((insn.imm as u64) << 32) as i64;
+                self.binary("or", insn, &high.to_string(), Width::Bits64);
+            }
+
+            ebpf::LMUL32_IMM | ebpf::LMUL32_REG if pqr => { let rhs = self.source(insn, Width::Zext32); self.binary("mul", insn, &rhs, Width::Zext32); }
+            ebpf::LMUL64_IMM | ebpf::LMUL64_REG if pqr => { let rhs = self.source(insn, Width::Bits64); self.binary("mul", insn, &rhs, Width::Bits64); }
+            ebpf::UHMUL64_IMM | ebpf::UHMUL64_REG if pqr => self.high_multiplication(insn, false),
+            ebpf::SHMUL64_IMM | ebpf::SHMUL64_REG if pqr => self.high_multiplication(insn, true),
+            ebpf::UDIV32_IMM | ebpf::UDIV32_REG if pqr => { let rhs = self.source(insn, Width::Zext32); self.division("udiv", pc, insn, &rhs, Width::Zext32); }
+            ebpf::UREM32_IMM | ebpf::UREM32_REG if pqr => { let rhs = self.source(insn, Width::Zext32); self.division("urem", pc, insn, &rhs, Width::Zext32); }
+            ebpf::SDIV32_IMM | ebpf::SDIV32_REG if pqr => { let rhs = self.source(insn, Width::Zext32); self.division("sdiv", pc, insn, &rhs, Width::Zext32); }
+            ebpf::SREM32_IMM | ebpf::SREM32_REG if pqr => { let rhs = self.source(insn, Width::Zext32); self.division("srem", pc, insn, &rhs, Width::Zext32); }
+            ebpf::UDIV64_IMM | ebpf::UREM64_IMM if pqr => {
+                let op = if insn.opc == ebpf::UDIV64_IMM { "udiv" } else { "urem" };
+                self.division(op, pc, insn, &(insn.imm as u32).to_string(), Width::Bits64);
+            }
+            ebpf::UDIV64_REG if pqr => { let rhs = self.source(insn, Width::Bits64); self.division("udiv", pc, insn, &rhs, Width::Bits64); }
+            ebpf::UREM64_REG if pqr => { let rhs = self.source(insn, Width::Bits64); self.division("urem", pc, insn, &rhs, Width::Bits64); }
+            ebpf::SDIV64_IMM | ebpf::SDIV64_REG if pqr => { let rhs = self.source(insn, Width::Bits64); self.division("sdiv", pc, insn, &rhs, Width::Bits64); }
+            ebpf::SREM64_IMM | ebpf::SREM64_REG if pqr => { let rhs = self.source(insn, Width::Bits64); self.division("srem", pc, insn, &rhs, Width::Bits64); }
+
+            ebpf::JA => {
+                self.branch(target_pc);
+                return false;
+            }
+            ebpf::CALL_REG => {
+                let target = if sbpf_version.callx_uses_src_reg() { insn.src } else { insn.imm as u8 };
+                let target = self.load_register(target);
+                self.call("@sbpf.callx", Some(&target));
+            }
+            ebpf::CALL_IMM => {
+                let key = sbpf_version.calculate_call_imm_target_pc(pc, insn.imm);
+                let function_pc = if sbpf_version.static_syscalls() {
+                    Some(key as usize)
+                } else if executable.get_loader().get_function_registry().lookup_by_key(insn.imm as u32).is_some() {
+                    self.syscall(insn.imm as u32);
+                    return true;
+                } else {
+                    executable.get_function_registry().lookup_by_key(key).map(|(_name, pc)| pc)
+                };
+                match function_pc.and_then(|pc| self.function_names.get(&pc)) {
+                    Some(name) => {
+                        let callee = format!("@{}", quote(name));
+                        self.call(&callee, None);
+                    }
+                    None => {
+                        self.trap("unsupported_instruction", pc);
+                        return false;
+                    }
+                }
+            }
+            ebpf::SYSCALL if sbpf_version.static_syscalls() => self.syscall(insn.imm as u32),
+            ebpf::RETURN if sbpf_version.static_syscalls() => {
+                self.exit();
+                return false;
+            }
+            ebpf::EXIT if !sbpf_version.static_syscalls() => {
+                self.exit();
+                return false;
+            }
+            _ => {
+                self.trap("unsupported_instruction", pc);
+                return false;
+            }
+        }
+        true
+    }
+}
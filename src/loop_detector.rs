@@ -0,0 +1,62 @@
+//! Back edge counting of the interpreter
+//!
+//! When [crate::vm::EbpfVm::loop_detector] is set, the interpreter reports every taken jump
+//! whose target does not lie after it. These back edges close the loops of a program, so their
+//! counters tell how often each loop iterated. With a budget, an execution which iterates a
+//! loop more often than that fails with [EbpfError::LoopBudgetExceeded], which lets fuzzers
+//! drop inputs that spend most of their instruction budget in a single (legal) loop.
+//!
+//! The JIT does not report jumps, so the detector only works in the interpreter.
+
+use crate::error::EbpfError;
+use std::collections::BTreeMap;
+
+/// Counts the iterations per back edge
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoopDetector {
+    /// Maximal number of iterations per back edge, unlimited if `None`
+    budget: Option<u64>,
+    /// (pc of the jump, target pc) => number of times it was taken
+    back_edges: BTreeMap<(u64, u64), u64>,
+}
+
+impl LoopDetector {
+    /// Creates a detector which fails an execution once a back edge was taken more than
+    /// `budget` times
+    pub fn new(budget: Option<u64>) -> Self {
+        Self {
+            budget,
+            back_edges: BTreeMap::new(),
+        }
+    }
+
+    /// Records a taken jump from `pc` to `target_pc <= pc`
+    pub fn record(&mut self, pc: u64, target_pc: u64) -> Result<(), EbpfError> {
+        let iterations = self.back_edges.entry((pc, target_pc)).or_insert(0);
+        *iterations = iterations.saturating_add(1);
+        match self.budget {
+            Some(budget) if *iterations > budget => {
+                Err(EbpfError::LoopBudgetExceeded(pc, target_pc))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// (pc of the jump, target pc) => number of times it was taken
+    pub fn back_edges(&self) -> &BTreeMap<(u64, u64), u64> {
+        &self.back_edges
+    }
+
+    /// The back edge which was taken most often and its count
+    pub fn hottest(&self) -> Option<((u64, u64), u64)> {
+        self.back_edges
+            .iter()
+            .max_by_key(|(_edge, iterations)| **iterations)
+            .map(|(edge, iterations)| (*edge, *iterations))
+    }
+
+    /// Resets the counters, e.g. before the next execution
+    pub fn clear(&mut self) {
+        self.back_edges.clear();
+    }
+}
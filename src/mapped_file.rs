@@ -0,0 +1,144 @@
+//! Memory regions backed by memory-mapped files
+//!
+//! Inputs of several megabytes, e.g. serialized accounts with large data, do not need to be
+//! copied into the VM for every execution: A [MappedFile] maps the file into the host address
+//! space once and the kernel pages it in on demand. Read-only mappings become readonly
+//! [MemoryRegion]s, copy-on-write mappings become writable ones whose stores only touch private
+//! copies of the affected pages and never reach the file. [MappedFile::reset] discards these
+//! private pages, which is how [EbpfVm::execute_batch_mapped](crate::vm::EbpfVm::execute_batch_mapped)
+//! restores every input before it is run.
+//!
+//! The file must not be truncated while it is mapped, accessing pages beyond its end raises
+//! `SIGBUS` in the host process.
+
+use crate::{error::EbpfError, memory_region::MemoryRegion};
+use std::{convert::TryFrom, fs::File, os::unix::io::AsRawFd, path::Path, ptr::NonNull};
+
+/// How a [MappedFile] is mapped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingMode {
+    /// Mapped readonly, stores of the program are access violations
+    ReadOnly,
+    /// Mapped privately and writable, stores are not written back to the file
+    CopyOnWrite,
+}
+
+/// A file mapped into the host address space
+#[derive(Debug)]
+pub struct MappedFile {
+    file: File,
+    host_addr: NonNull<u8>,
+    len: usize,
+    mode: MappingMode,
+}
+
+impl MappedFile {
+    /// Opens and maps the file at `path`
+    pub fn open<P: AsRef<Path>>(path: P, mode: MappingMode) -> std::io::Result<Self> {
+        Self::new(File::open(path)?, mode)
+    }
+
+    /// Maps the entire `file`, which has to be opened for reading
+    pub fn new(file: File, mode: MappingMode) -> std::io::Result<Self> {
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| std::io::Error::from_raw_os_error(libc::EFBIG))?;
+        let mut mapped_file = Self {
+            file,
+            host_addr: NonNull::dangling(),
+            len,
+            mode,
+        };
+        if len > 0 {
+            // Safety: a new mapping is created, no existing memory is affected
+            mapped_file.host_addr = unsafe { mapped_file.map(std::ptr::null_mut(), 0) }
+                .ok_or_else(std::io::Error::last_os_error)?;
+        }
+        Ok(mapped_file)
+    }
+
+    /// Maps the file at `addr`, which is replaced if `flags` contains `MAP_FIXED`
+    unsafe fn map(&self, addr: *mut libc::c_void, flags: libc::c_int) -> Option<NonNull<u8>> {
+        let (protection, flags) = match self.mode {
+            MappingMode::ReadOnly => (libc::PROT_READ, flags | libc::MAP_SHARED),
+            MappingMode::CopyOnWrite => (
+                libc::PROT_READ | libc::PROT_WRITE,
+                flags | libc::MAP_PRIVATE,
+            ),
+        };
+        let host_addr = libc::mmap(addr, self.len, protection, flags, self.file.as_raw_fd(), 0);
+        if host_addr == libc::MAP_FAILED {
+            None
+        } else {
+            NonNull::new(host_addr.cast::<u8>())
+        }
+    }
+
+    /// How the file is mapped
+    pub fn mode(&self) -> MappingMode {
+        self.mode
+    }
+
+    /// Length of the file in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the file is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The mapped bytes, including stores to a copy-on-write mapping since the last reset
+    pub fn as_slice(&self) -> &[u8] {
+        // Safety: `len` bytes are mapped readable at `host_addr` until the mapping is dropped
+        unsafe { std::slice::from_raw_parts(self.host_addr.as_ptr(), self.len) }
+    }
+
+    /// Creates a [MemoryRegion] at `vm_addr` which maps the file without copying it
+    ///
+    /// The region is writable if the file is mapped [MappingMode::CopyOnWrite]. It must not be
+    /// accessed after the [MappedFile] is dropped.
+    pub fn region(&mut self, vm_addr: u64) -> MemoryRegion {
+        match self.mode {
+            MappingMode::ReadOnly => MemoryRegion::new_readonly(self.as_slice(), vm_addr),
+            MappingMode::CopyOnWrite => {
+                // Safety: the mapping is private and writable
+                let slice =
+                    unsafe { std::slice::from_raw_parts_mut(self.host_addr.as_ptr(), self.len) };
+                MemoryRegion::new_writable(slice, vm_addr)
+            }
+        }
+    }
+
+    /// Discards all stores to a copy-on-write mapping, so it matches the file again
+    ///
+    /// The file is mapped again at the same host address, so [MemoryRegion]s created by
+    /// [MappedFile::region] stay valid. Does nothing for readonly mappings.
+    pub fn reset(&mut self) -> Result<(), EbpfError> {
+        if self.mode == MappingMode::ReadOnly || self.len == 0 {
+            return Ok(());
+        }
+        let host_addr = self.host_addr.as_ptr().cast::<libc::c_void>();
+        // Safety: replaces the mapping owned by `self` with one of the same length
+        if unsafe { self.map(host_addr, libc::MAP_FIXED) }.is_none() {
+            let error = std::io::Error::last_os_error();
+            return Err(EbpfError::LibcInvocationFailed(
+                "mmap",
+                vec![format!("{:?}", host_addr), format!("{:?}", self.len)],
+                error.raw_os_error().unwrap_or(0),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // Safety: the mapping is owned by `self` and no longer accessed
+            unsafe {
+                libc::munmap(self.host_addr.as_ptr().cast::<libc::c_void>(), self.len);
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Built-in implementations of the memory syscalls
+//!
+//! Programs built with the Solana toolchain call `sol_memcpy_`, `sol_memmove_`, `sol_memset_`
+//! and `sol_memcmp_` instead of inlining bulk memory operations. The builtins in this module
+//! implement them for any [ContextObject], so they can be registered in a [BuiltinProgram]
+//! without writing them against a specific context object (see [register_memory_builtins]).
+//!
+//! As the semantics of these syscalls are known, the taint replay of
+//! [InputTaint](crate::taint::InputTaint) follows the input bytes through them instead of
+//! treating them as opaque, and reports the input bytes compared by `sol_memcmp_`.
+
+use crate::{
+    declare_builtin_function, ebpf,
+    elf::ElfError,
+    error::EbpfError,
+    memory_region::{AccessType, MemoryMapping},
+    program::BuiltinProgram,
+    vm::ContextObject,
+};
+
+/// Errors of the memory builtins
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum MemoryBuiltinError {
+    /// The source and destination of `sol_memcpy_` overlap
+    #[error("Overlapping copy of {2} bytes from {1:#x} to {0:#x}")]
+    CopyOverlapping(u64, u64, u64),
+}
+
+/// The memory syscalls implemented in this module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryBuiltin {
+    /// `sol_memcpy_(dst, src, n)`
+    Memcpy,
+    /// `sol_memmove_(dst, src, n)`
+    Memmove,
+    /// `sol_memset_(dst, c, n)`
+    Memset,
+    /// `sol_memcmp_(s1, s2, n, result)`
+    Memcmp,
+}
+
+impl MemoryBuiltin {
+    /// All memory builtins
+    pub const ALL: [Self; 4] = [Self::Memcpy, Self::Memmove, Self::Memset, Self::Memcmp];
+
+    /// Name of the syscall
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Memcpy => "sol_memcpy_",
+            Self::Memmove => "sol_memmove_",
+            Self::Memset => "sol_memset_",
+            Self::Memcmp => "sol_memcmp_",
+        }
+    }
+
+    /// Looks up the builtin whose name hashes to the key of a syscall instruction
+    pub fn from_hash(hash: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|builtin| ebpf::hash_symbol_name(builtin.name().as_bytes()) == hash)
+    }
+}
+
+/// Registers all memory builtins under their syscall names
+pub fn register_memory_builtins<C: ContextObject>(
+    loader: &mut BuiltinProgram<C>,
+) -> Result<(), ElfError> {
+    loader.register_function(MemoryBuiltin::Memcpy.name(), SyscallMemcpy::vm::<C>)?;
+    loader.register_function(MemoryBuiltin::Memmove.name(), SyscallMemmove::vm::<C>)?;
+    loader.register_function(MemoryBuiltin::Memset.name(), SyscallMemset::vm::<C>)?;
+    loader.register_function(MemoryBuiltin::Memcmp.name(), SyscallMemcmp::vm::<C>)?;
+    Ok(())
+}
+
+fn map(
+    memory_mapping: &MemoryMapping,
+    access_type: AccessType,
+    vm_addr: u64,
+    len: u64,
+) -> Result<*mut u8, EbpfError> {
+    let host_addr: Result<u64, EbpfError> = memory_mapping.map(access_type, vm_addr, len).into();
+    Ok(host_addr? as *mut u8)
+}
+
+fn copy(memory_mapping: &MemoryMapping, dst: u64, src: u64, n: u64) -> Result<(), EbpfError> {
+    if n == 0 {
+        return Ok(());
+    }
+    let src = map(memory_mapping, AccessType::Load, src, n)?;
+    let dst = map(memory_mapping, AccessType::Store, dst, n)?;
+    // Both ranges were mapped with a length of `n`
+    unsafe { std::ptr::copy(src, dst, n as usize) };
+    Ok(())
+}
+
+declare_builtin_function!(
+    /// `sol_memcpy_(dst, src, n)`, fails if the ranges overlap
+    SyscallMemcpy<C: ContextObject>,
+    fn rust(
+        _context_object: &mut C,
+        dst: u64,
+        src: u64,
+        n: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        if dst < src.saturating_add(n) && src < dst.saturating_add(n) {
+            return Err(Box::new(MemoryBuiltinError::CopyOverlapping(dst, src, n)));
+        }
+        copy(memory_mapping, dst, src, n)?;
+        Ok(0)
+    }
+);
+
+declare_builtin_function!(
+    /// `sol_memmove_(dst, src, n)`
+    SyscallMemmove<C: ContextObject>,
+    fn rust(
+        _context_object: &mut C,
+        dst: u64,
+        src: u64,
+        n: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        copy(memory_mapping, dst, src, n)?;
+        Ok(0)
+    }
+);
+
+declare_builtin_function!(
+    /// `sol_memset_(dst, c, n)`, only the lowest byte of `c` is used
+    SyscallMemset<C: ContextObject>,
+    fn rust(
+        _context_object: &mut C,
+        dst: u64,
+        c: u64,
+        n: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        if n > 0 {
+            let dst = map(memory_mapping, AccessType::Store, dst, n)?;
+            unsafe { std::ptr::write_bytes(dst, c as u8, n as usize) };
+        }
+        Ok(0)
+    }
+);
+
+declare_builtin_function!(
+    /// `sol_memcmp_(s1, s2, n, result)`
+    ///
+    /// Stores the difference of the first pair of differing bytes as `i32` to `result`,
+    /// or 0 if the ranges are equal.
+    SyscallMemcmp<C: ContextObject>,
+    fn rust(
+        _context_object: &mut C,
+        s1: u64,
+        s2: u64,
+        n: u64,
+        result: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut difference = 0i32;
+        if n > 0 {
+            let s1 = map(memory_mapping, AccessType::Load, s1, n)?;
+            let s2 = map(memory_mapping, AccessType::Load, s2, n)?;
+            let (s1, s2) = unsafe {
+                (
+                    std::slice::from_raw_parts(s1 as *const u8, n as usize),
+                    std::slice::from_raw_parts(s2 as *const u8, n as usize),
+                )
+            };
+            if let Some((a, b)) = s1.iter().zip(s2.iter()).find(|(a, b)| a != b) {
+                difference = i32::from(*a).saturating_sub(i32::from(*b));
+            }
+        }
+        let result = map(
+            memory_mapping,
+            AccessType::Store,
+            result,
+            std::mem::size_of::<i32>() as u64,
+        )?;
+        unsafe { std::ptr::write_unaligned(result.cast::<i32>(), difference) };
+        Ok(0)
+    }
+);
@@ -4,10 +4,19 @@ use crate::{
     aligned_memory::Pod,
     ebpf,
     error::{EbpfError, ProgramResult},
+    heap_sanitizer::HeapSanitizer,
     program::SBPFVersion,
+    stack_sanitizer::StackSanitizer,
     vm::Config,
 };
-use std::{array, cell::UnsafeCell, fmt, mem, ops::Range, ptr};
+use std::{
+    array,
+    cell::{RefCell, UnsafeCell},
+    fmt, mem,
+    ops::Range,
+    ptr,
+    rc::Rc,
+};
 
 /* Explanation of the Gapped Memory
 
@@ -39,6 +48,259 @@ pub fn default_access_violation_handler(
 ) {
 }
 
+/// Violation of an access to a guard region, see [MemoryRegionBuilder::with_guard_regions]
+fn guard_access_violation(access_type: AccessType, vm_addr: u64, len: u64) -> ProgramResult {
+    ProgramResult::Err(EbpfError::AccessViolation(
+        access_type,
+        vm_addr,
+        len,
+        "guard",
+    ))
+}
+
+/// An input region which was extended by a [ZeroFillAccessViolationHandler]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntheticFill {
+    /// Start of the region
+    pub vm_addr: u64,
+    /// Length of the region before it was first extended
+    pub original_len: u64,
+    /// Length of the region after it was last extended
+    pub filled_len: u64,
+}
+
+/// Extends truncated or empty input regions with zero filled memory instead of failing
+///
+/// Only continuous regions at or above [ebpf::MM_INPUT_START] are extended, in whole pages
+/// and up to the start of the next region. A missing input can be emulated by mapping an
+/// empty region. The extended region is a copy, so stores of the program are not visible
+/// in the original input buffer anymore.
+#[derive(Default)]
+pub struct ZeroFillAccessViolationHandler {
+    fills: Rc<RefCell<Vec<SyntheticFill>>>,
+}
+
+impl ZeroFillAccessViolationHandler {
+    /// Size of the zero filled extensions
+    const PAGE_SIZE: u64 = 4096;
+
+    /// Returns the handler to be passed to the [MemoryMapping]
+    pub fn handler(&self) -> AccessViolationHandler {
+        let fills = self.fills.clone();
+        let buffers = RefCell::new(Vec::<Box<[u8]>>::new());
+        Box::new(
+            move |region: &mut MemoryRegion,
+                  region_max_len: u64,
+                  access_type: AccessType,
+                  vm_addr: u64,
+                  len: u64| {
+                if region.vm_addr < ebpf::MM_INPUT_START
+                    || region.vm_gap_shift != 63
+                    || (access_type == AccessType::Store && !region.writable)
+                {
+                    return;
+                }
+                let required_len = vm_addr.saturating_add(len).saturating_sub(region.vm_addr);
+                if required_len <= region.len {
+                    return;
+                }
+                let filled_len = required_len
+                    .checked_next_multiple_of(Self::PAGE_SIZE)
+                    .unwrap_or(u64::MAX)
+                    .min(region_max_len);
+                if filled_len < required_len {
+                    return;
+                }
+                let mut buffer = vec![0u8; filled_len as usize].into_boxed_slice();
+                // Safety: the region maps `region.len` readable bytes at `region.host_addr`
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        region.host_addr as *const u8,
+                        buffer.as_mut_ptr(),
+                        region.len as usize,
+                    );
+                }
+                let mut fills = fills.borrow_mut();
+                match fills.iter_mut().find(|fill| fill.vm_addr == region.vm_addr) {
+                    Some(fill) => fill.filled_len = filled_len,
+                    None => fills.push(SyntheticFill {
+                        vm_addr: region.vm_addr,
+                        original_len: region.len,
+                        filled_len,
+                    }),
+                }
+                region.host_addr = buffer.as_ptr() as u64;
+                region.len = filled_len;
+                // The buffer lives as long as the handler and thereby as long as the mapping
+                buffers.borrow_mut().push(buffer);
+            },
+        )
+    }
+
+    /// Regions which were extended so far
+    pub fn report(&self) -> Vec<SyntheticFill> {
+        self.fills.borrow().clone()
+    }
+}
+
+/// Provides the private copy of a shared region, given its payload and original contents
+pub type CopyOnWriteCallback = Box<dyn Fn(u16, &[u8]) -> Box<[u8]>>;
+
+/// Copies shared regions on their first store
+///
+/// Regions created with [MemoryRegion::new_copy_on_write] are mapped readonly, so many VMs can
+/// share one serialized input. The first store into such a region calls the
+/// [CopyOnWriteCallback] and maps the returned copy writable in its place. Only the regions
+/// which are actually written are copied. The copies live as long as the mapping.
+pub struct CopyOnWriteAccessViolationHandler {
+    callback: Rc<CopyOnWriteCallback>,
+    copied: Rc<RefCell<Vec<u16>>>,
+}
+
+impl Default for CopyOnWriteAccessViolationHandler {
+    fn default() -> Self {
+        Self::new(Box::new(|_payload, original| Box::from(original)))
+    }
+}
+
+impl CopyOnWriteAccessViolationHandler {
+    /// Creates a handler which obtains the copies from `callback`
+    pub fn new(callback: CopyOnWriteCallback) -> Self {
+        Self {
+            callback: Rc::new(callback),
+            copied: Rc::default(),
+        }
+    }
+
+    /// Returns the handler to be passed to the [MemoryMapping]
+    pub fn handler(&self) -> AccessViolationHandler {
+        let callback = self.callback.clone();
+        let copied = self.copied.clone();
+        let buffers = RefCell::new(Vec::<Box<[u8]>>::new());
+        Box::new(
+            move |region: &mut MemoryRegion,
+                  _region_max_len: u64,
+                  access_type: AccessType,
+                  _vm_addr: u64,
+                  _len: u64| {
+                let Some(payload) = region.access_violation_handler_payload else {
+                    return;
+                };
+                if access_type != AccessType::Store || region.writable {
+                    return;
+                }
+                // Safety: the region maps `region.len` readable bytes at `region.host_addr`
+                let original = unsafe {
+                    std::slice::from_raw_parts(region.host_addr as *const u8, region.len as usize)
+                };
+                let buffer = callback(payload, original);
+                if (buffer.len() as u64) < region.len {
+                    return;
+                }
+                region.host_addr = buffer.as_ptr() as u64;
+                region.writable = true;
+                copied.borrow_mut().push(payload);
+                // The buffer lives as long as the handler and thereby as long as the mapping
+                buffers.borrow_mut().push(buffer);
+            },
+        )
+    }
+
+    /// Payloads of the regions which were copied so far, in the order of their first store
+    pub fn copied_regions(&self) -> Vec<u16> {
+        self.copied.borrow().clone()
+    }
+}
+
+/// Placement policy of a [MemoryRegion] inside an [AlignedMemoryMapping]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RegionAlignment {
+    /// The region exclusively occupies the slot of the address space selected by its upper half
+    #[default]
+    Aligned,
+    /// The region can share its slot with other unaligned regions, e.g. direct mapped accounts
+    Unaligned,
+}
+
+/// Purpose of a region in the address space, derived from the slot of its virtual address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-export", derive(serde::Serialize, serde::Deserialize))]
+pub enum RegionRole {
+    /// Bytecode and read only data of the program
+    Text,
+    /// Call frames
+    Stack,
+    /// Heap of the allocator
+    Heap,
+    /// Serialized instruction input
+    Input,
+    /// Any region mapped beyond the input, e.g. sysvars
+    Extra,
+}
+
+impl RegionRole {
+    /// Role of the region starting at `vm_addr`
+    pub fn of(vm_addr: u64) -> Self {
+        match vm_addr >> ebpf::VIRTUAL_ADDRESS_BITS {
+            0 | 1 => Self::Text,
+            2 => Self::Stack,
+            3 => Self::Heap,
+            4 => Self::Input,
+            _ => Self::Extra,
+        }
+    }
+}
+
+/// A mapped region, as listed by [EbpfVm::address_space_map](crate::vm::EbpfVm::address_space_map)
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-export", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddressSpaceEntry {
+    /// Virtual addresses covered by the region, including the gaps of a gapped stack
+    pub vm_range: Range<u64>,
+    /// Whether loads are allowed
+    pub readable: bool,
+    /// Whether stores are allowed
+    pub writable: bool,
+    /// Number of bytes of host memory backing the region
+    pub backing_size: u64,
+    /// Size of the frames of a gapped region
+    pub vm_gap_size: Option<u64>,
+    /// Purpose of the region
+    pub role: RegionRole,
+}
+
+impl From<&MemoryRegion> for AddressSpaceEntry {
+    fn from(region: &MemoryRegion) -> Self {
+        Self {
+            vm_range: region.vm_addr_range(),
+            readable: !region.guard,
+            writable: region.writable && !region.guard,
+            backing_size: if region.guard { 0 } else { region.len },
+            vm_gap_size: (region.vm_gap_shift != 63).then(|| 1 << region.vm_gap_shift),
+            role: RegionRole::of(region.vm_addr),
+        }
+    }
+}
+
+impl fmt::Display for AddressSpaceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#012x}..{:#012x} {}{} {:?} {} bytes",
+            self.vm_range.start,
+            self.vm_range.end,
+            if self.readable { 'r' } else { '-' },
+            if self.writable { 'w' } else { '-' },
+            self.role,
+            self.backing_size,
+        )?;
+        if let Some(vm_gap_size) = self.vm_gap_size {
+            write!(f, " gapped by {vm_gap_size}")?;
+        }
+        Ok(())
+    }
+}
+
 /// Memory region for bounds checking and address translation
 #[derive(Default, Eq, PartialEq, Clone)]
 #[repr(C, align(32))]
@@ -55,6 +317,15 @@ pub struct MemoryRegion {
     pub writable: bool,
     /// User defined payload for the [AccessViolationHandler]
     pub access_violation_handler_payload: Option<u16>,
+    /// Placement policy, only relevant for [AlignedMemoryMapping]
+    pub alignment: RegionAlignment,
+    /// Input offset of the first byte, if the bytes are taint sources of
+    /// [InputTaint](crate::taint::InputTaint)
+    pub taint_offset: Option<u64>,
+    /// Virtual address of the bytes this region is an alias of, see [MemoryMapping::add_alias]
+    pub alias_of: Option<u64>,
+    /// Whether every access is a violation, see [MemoryRegionBuilder::with_guard_regions]
+    pub guard: bool,
 }
 
 impl MemoryRegion {
@@ -73,6 +344,10 @@ impl MemoryRegion {
             vm_gap_shift,
             writable,
             access_violation_handler_payload: None,
+            alignment: RegionAlignment::Aligned,
+            taint_offset: None,
+            alias_of: None,
+            guard: false,
         }
     }
 
@@ -86,6 +361,16 @@ impl MemoryRegion {
         Self::new(slice, vm_addr, 0, false)
     }
 
+    /// Creates a new readonly MemoryRegion from a slice whose bytes are taint sources
+    ///
+    /// The bytes are labeled with the input offsets starting at `taint_offset` when replaying
+    /// a trace with [InputTaint::from_trace_log_with_regions](crate::taint::InputTaint::from_trace_log_with_regions).
+    pub fn new_readonly_tainted(slice: &[u8], vm_addr: u64, taint_offset: u64) -> Self {
+        let mut region = Self::new(slice, vm_addr, 0, false);
+        region.taint_offset = Some(taint_offset);
+        region
+    }
+
     /// Creates a new writable MemoryRegion from a mutable slice
     pub fn new_writable(slice: &mut [u8], vm_addr: u64) -> Self {
         Self::new(&*slice, vm_addr, 0, true)
@@ -96,6 +381,38 @@ impl MemoryRegion {
         Self::new(&*slice, vm_addr, vm_gap_size, true)
     }
 
+    /// Creates a new MemoryRegion which is copied on its first store
+    ///
+    /// Requires a [CopyOnWriteAccessViolationHandler] in the [MemoryMapping], the `payload`
+    /// is passed to its [CopyOnWriteCallback] to identify the region.
+    pub fn new_copy_on_write(slice: &[u8], vm_addr: u64, payload: u16) -> Self {
+        let mut region = Self::new(slice, vm_addr, 0, false);
+        region.access_violation_handler_payload = Some(payload);
+        region
+    }
+
+    /// Creates a MemoryRegion without host memory which fails every access
+    pub fn new_guard(vm_addr: u64, len: u64) -> Self {
+        let mut region = Self::new(&[], vm_addr, 0, false);
+        region.len = len;
+        region.guard = true;
+        region
+    }
+
+    /// Sets the placement policy of this MemoryRegion
+    pub fn with_alignment(mut self, alignment: RegionAlignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Address of the aliased byte, if this region is an alias and contains `vm_addr`
+    pub fn resolve_alias(&self, vm_addr: u64) -> Option<u64> {
+        let aliased_vm_addr = self.alias_of?;
+        self.vm_addr_range()
+            .contains(&vm_addr)
+            .then(|| aliased_vm_addr.saturating_add(vm_addr.saturating_sub(self.vm_addr)))
+    }
+
     /// Returns the vm address space covered by this MemoryRegion
     pub fn vm_addr_range(&self) -> Range<u64> {
         if self.vm_gap_shift == 63 {
@@ -107,7 +424,7 @@ impl MemoryRegion {
 
     /// Convert a virtual machine address into a host address
     pub fn vm_to_host(&self, access_type: AccessType, vm_addr: u64, len: u64) -> Option<u64> {
-        if access_type == AccessType::Store && !self.writable {
+        if self.guard || (access_type == AccessType::Store && !self.writable) {
             return None;
         }
 
@@ -140,7 +457,7 @@ impl fmt::Debug for MemoryRegion {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "host_addr: {:#x?}-{:#x?}, vm_addr: {:#x?}-{:#x?}, len: {}, writable: {}, payload {:?}",
+            "host_addr: {:#x?}-{:#x?}, vm_addr: {:#x?}-{:#x?}, len: {}, writable: {}, payload {:?}, alignment: {:?}, taint_offset: {:?}, alias_of: {:?}, guard: {}",
             self.host_addr,
             self.host_addr.saturating_add(self.len),
             self.vm_addr,
@@ -148,6 +465,10 @@ impl fmt::Debug for MemoryRegion {
             self.len,
             self.writable,
             self.access_violation_handler_payload,
+            self.alignment,
+            self.taint_offset,
+            self.alias_of,
+            self.guard,
         )
     }
 }
@@ -181,9 +502,53 @@ pub struct CommonMemoryMapping<'a> {
     config: &'a Config,
     /// Executable sbpf_version
     sbpf_version: SBPFVersion,
+    /// Cache of recent vm page => host address translations
+    translation_cache: UnsafeCell<TranslationCache>,
+    /// Poison checks of the heap, see [MemoryMapping::set_heap_sanitizer]
+    heap_sanitizer: Option<Rc<RefCell<HeapSanitizer>>>,
+    /// Written bytes tracking of the stack, see [MemoryMapping::set_stack_sanitizer]
+    stack_sanitizer: Option<Rc<RefCell<StackSanitizer>>>,
 }
 
 impl CommonMemoryMapping<'_> {
+    fn new(
+        regions: Box<[MemoryRegion]>,
+        access_violation_handler: AccessViolationHandler,
+        config: &Config,
+        sbpf_version: SBPFVersion,
+    ) -> CommonMemoryMapping<'_> {
+        CommonMemoryMapping {
+            regions,
+            access_violation_handler,
+            config,
+            sbpf_version,
+            translation_cache: UnsafeCell::new(TranslationCache::new()),
+            heap_sanitizer: None,
+            stack_sanitizer: None,
+        }
+    }
+
+    fn check_heap_poison(&self, access_type: AccessType, vm_addr: u64, len: u64) -> ProgramResult {
+        if let Some(heap_sanitizer) = &self.heap_sanitizer {
+            if let Err(err) = heap_sanitizer.borrow().check(access_type, vm_addr, len) {
+                return ProgramResult::Err(err);
+            }
+        }
+        ProgramResult::Ok(0)
+    }
+
+    fn check_stack_shadow(&self, access_type: AccessType, vm_addr: u64, len: u64) -> ProgramResult {
+        if let Some(stack_sanitizer) = &self.stack_sanitizer {
+            if let Err(err) = stack_sanitizer
+                .borrow_mut()
+                .check(access_type, vm_addr, len)
+            {
+                return ProgramResult::Err(err);
+            }
+        }
+        ProgramResult::Ok(0)
+    }
+
     fn generate_access_violation(
         &self,
         access_type: AccessType,
@@ -278,12 +643,12 @@ impl<'a> UnalignedMemoryMapping<'a> {
             }
         }
         let mut result = Self {
-            common: CommonMemoryMapping {
-                regions: regions.into_boxed_slice(),
+            common: CommonMemoryMapping::new(
+                regions.into_boxed_slice(),
                 access_violation_handler,
                 config,
                 sbpf_version,
-            },
+            ),
             region_addresses: vec![0; number_of_regions].into_boxed_slice(),
             region_index_lookup: vec![0; number_of_regions].into_boxed_slice(),
             cache: UnsafeCell::new(MappingCache::new()),
@@ -301,11 +666,19 @@ impl<'a> UnalignedMemoryMapping<'a> {
         // guaranteed to be unique.
         let cache = unsafe { &mut *self.cache.get() };
         if let Some(index) = cache.find(vm_addr) {
+            #[cfg(feature = "diagnostics")]
+            {
+                cache.diagnostics.hits = cache.diagnostics.hits.saturating_add(1);
+            }
             // Safety:
             // Cached index, we validated it before caching it. See the corresponding safety section
             // in the miss branch.
             Some((index, unsafe { self.common.regions.get_unchecked(index) }))
         } else {
+            #[cfg(feature = "diagnostics")]
+            {
+                cache.diagnostics.misses = cache.diagnostics.misses.saturating_add(1);
+            }
             let mut index = 1;
             while index <= self.region_addresses.len() {
                 // Safety:
@@ -329,19 +702,32 @@ impl<'a> UnalignedMemoryMapping<'a> {
         }
     }
 
+    /// Returns the hit and miss counters of the region lookup cache
+    #[cfg(feature = "diagnostics")]
+    pub fn cache_diagnostics(&self) -> crate::diagnostics::MappingCacheDiagnostics {
+        // Safety: see find_region()
+        unsafe { &*self.cache.get() }.diagnostics
+    }
+
     /// Replaces the `MemoryRegion` at the given index
     pub fn replace_region(&mut self, index: usize, region: MemoryRegion) -> Result<(), EbpfError> {
         self.common.regions[index] = region;
         self.cache.get_mut().flush();
+        self.common.translation_cache.get_mut().flush();
         Ok(())
     }
 }
 
 /// Memory mapping that uses the upper half of an address to identify the
 /// underlying memory region.
+///
+/// Regions with [RegionAlignment::Unaligned] can share a slot, in which case
+/// the region is searched for inside the slot.
 pub struct AlignedMemoryMapping<'a> {
     /// Common parts
     common: CommonMemoryMapping<'a>,
+    /// Range of region indices per slot of the address space
+    slots: Box<[(usize, usize)]>,
 }
 
 impl fmt::Debug for AlignedMemoryMapping<'_> {
@@ -362,32 +748,60 @@ impl<'a> AlignedMemoryMapping<'a> {
     ) -> Result<Self, EbpfError> {
         regions.insert(0, MemoryRegion::new_readonly(&[], 0));
         regions.sort();
+        let mut slots: Vec<(usize, usize)> = Vec::with_capacity(regions.len());
         for (index, region) in regions.iter().enumerate() {
-            if region
+            let slot = region
                 .vm_addr
                 .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
-                .unwrap_or(0)
-                != index as u64
-            {
+                .unwrap_or(0);
+            if slot == slots.len().saturating_sub(1) as u64 && index > 0 {
+                // Shares the slot with the previous region
+                let previous = &regions[index.saturating_sub(1)];
+                if region.alignment != RegionAlignment::Unaligned
+                    || previous.alignment != RegionAlignment::Unaligned
+                    || previous.vm_addr_range().end > region.vm_addr
+                {
+                    return Err(EbpfError::InvalidMemoryRegion(index));
+                }
+                if let Some(last) = slots.last_mut() {
+                    last.1 = index.saturating_add(1);
+                }
+            } else if slot == slots.len() as u64 {
+                slots.push((index, index.saturating_add(1)));
+            } else {
                 return Err(EbpfError::InvalidMemoryRegion(index));
             }
         }
         Ok(Self {
-            common: CommonMemoryMapping {
-                regions: regions.into_boxed_slice(),
+            common: CommonMemoryMapping::new(
+                regions.into_boxed_slice(),
                 access_violation_handler,
                 config,
                 sbpf_version,
-            },
+            ),
+            slots: slots.into_boxed_slice(),
         })
     }
 
     /// Returns the `MemoryRegion` which may contain the given address.
     #[inline]
     pub fn find_region(&self, vm_addr: u64) -> Option<(usize, &MemoryRegion)> {
-        let index = vm_addr.wrapping_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32) as usize;
-        if (1..self.common.regions.len()).contains(&index) {
+        let slot = vm_addr.wrapping_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32) as usize;
+        if (1..self.slots.len()).contains(&slot) {
             // Safety: bounds check above
+            let (begin, end) = unsafe { *self.slots.get_unchecked(slot) };
+            let index = if end.saturating_sub(begin) == 1 {
+                begin
+            } else {
+                // Safety: slots only contain valid region index ranges
+                let in_slot = unsafe { self.common.regions.get_unchecked(begin..end) };
+                begin.saturating_add(
+                    in_slot
+                        .partition_point(|region| region.vm_addr <= vm_addr)
+                        .saturating_sub(1),
+                )
+            };
+            // Safety: slots only contain valid region indices
             let region = unsafe { self.common.regions.get_unchecked(index) };
             return Some((index, region));
         }
@@ -396,19 +810,27 @@ impl<'a> AlignedMemoryMapping<'a> {
 
     /// Replaces the `MemoryRegion` at the given index
     pub fn replace_region(&mut self, index: usize, region: MemoryRegion) -> Result<(), EbpfError> {
-        let begin_index = region
+        let slot = self
+            .slots
+            .iter()
+            .position(|(begin, end)| (*begin..*end).contains(&index));
+        let begin_slot = region
             .vm_addr
             .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
             .unwrap_or(0) as usize;
-        let end_index = region
+        let end_slot = region
             .vm_addr
             .saturating_add(region.len.saturating_sub(1))
             .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
             .unwrap_or(0) as usize;
-        if begin_index != index || end_index != index {
+        if slot != Some(begin_slot)
+            || end_slot != begin_slot
+            || region.alignment != self.common.regions[index].alignment
+        {
             return Err(EbpfError::InvalidMemoryRegion(index));
         }
         self.common.regions[index] = region;
+        self.common.translation_cache.get_mut().flush();
         Ok(())
     }
 }
@@ -433,14 +855,15 @@ impl<'a> MemoryMapping<'a> {
     /// Creates a new memory mapping.
     ///
     /// Uses aligned or unaligned memory mapping depending on the value of
-    /// `config.aligned_memory_mapping=true`.
+    /// `config.aligned_memory_mapping=true`. In an aligned memory mapping, regions can still opt
+    /// into unaligned placement individually, see [RegionAlignment].
     pub fn new_with_access_violation_handler(
         regions: Vec<MemoryRegion>,
         config: &'a Config,
         sbpf_version: SBPFVersion,
         access_violation_handler: AccessViolationHandler,
     ) -> Result<Self, EbpfError> {
-        if sbpf_version == SBPFVersion::V4 || config.aligned_memory_mapping {
+        if uses_aligned_mapping(config, sbpf_version) {
             AlignedMemoryMapping::new_with_access_violation_handler(
                 regions,
                 config,
@@ -477,16 +900,38 @@ impl<'a> MemoryMapping<'a> {
 
     /// Map virtual memory to host memory.
     pub fn map(&self, access_type: AccessType, vm_addr: u64, len: u64) -> ProgramResult {
-        if let Some((_index, region)) = self.find_region(vm_addr) {
-            if let Some(host_addr) = region.vm_to_host(access_type, vm_addr, len) {
-                return ProgramResult::Ok(host_addr);
-            }
-        }
         let common = match &self {
             MemoryMapping::Identity => return ProgramResult::Ok(vm_addr),
             MemoryMapping::Aligned(m) => &m.common,
             MemoryMapping::Unaligned(m) => &m.common,
         };
+        if let ProgramResult::Err(err) = common.check_heap_poison(access_type, vm_addr, len) {
+            return ProgramResult::Err(err);
+        }
+        if let ProgramResult::Err(err) = common.check_stack_shadow(access_type, vm_addr, len) {
+            return ProgramResult::Err(err);
+        }
+        // Safety:
+        // &mut references to the translation cache are only created internally from methods that
+        // do not invoke each other. MemoryMapping is !Sync, so the cache reference is unique.
+        let translation_cache = unsafe { &mut *common.translation_cache.get() };
+        let cached = common.config.enable_translation_cache;
+        if cached {
+            if let Some(host_addr) = translation_cache.translate(access_type, vm_addr, len) {
+                return ProgramResult::Ok(host_addr);
+            }
+        }
+        if let Some((_index, region)) = self.find_region(vm_addr) {
+            if region.guard {
+                return guard_access_violation(access_type, vm_addr, len);
+            }
+            if let Some(host_addr) = region.vm_to_host(access_type, vm_addr, len) {
+                if cached {
+                    translation_cache.insert(region, vm_addr);
+                }
+                return ProgramResult::Ok(host_addr);
+            }
+        }
         common.generate_access_violation(access_type, vm_addr, len)
     }
 
@@ -506,8 +951,28 @@ impl<'a> MemoryMapping<'a> {
             MemoryMapping::Aligned(m) => &m.common,
             MemoryMapping::Unaligned(m) => &m.common,
         };
+        if let ProgramResult::Err(err) = common.check_heap_poison(access_type, vm_addr, len) {
+            return ProgramResult::Err(err);
+        }
+        if let ProgramResult::Err(err) = common.check_stack_shadow(access_type, vm_addr, len) {
+            return ProgramResult::Err(err);
+        }
+        // Safety: see map()
+        let translation_cache = unsafe { &mut *common.translation_cache.get() };
+        let cached = common.config.enable_translation_cache;
+        if cached {
+            if let Some(host_addr) = translation_cache.translate(access_type, vm_addr, len) {
+                return ProgramResult::Ok(host_addr);
+            }
+        }
         if let Some((index, region)) = self.find_region(vm_addr) {
+            if region.guard {
+                return guard_access_violation(access_type, vm_addr, len);
+            }
             if let Some(host_addr) = region.vm_to_host(access_type, vm_addr, len) {
+                if cached {
+                    translation_cache.insert(region, vm_addr);
+                }
                 return ProgramResult::Ok(host_addr);
             }
             let mut region = (*region).clone();
@@ -568,6 +1033,88 @@ impl<'a> MemoryMapping<'a> {
         }
     }
 
+    /// Returns the hit and miss counters of the region lookup cache, if there is one.
+    #[cfg(feature = "diagnostics")]
+    pub fn cache_diagnostics(&self) -> Option<crate::diagnostics::MappingCacheDiagnostics> {
+        match self {
+            MemoryMapping::Identity | MemoryMapping::Aligned(_) => None,
+            MemoryMapping::Unaligned(m) => Some(m.cache_diagnostics()),
+        }
+    }
+
+    /// Forgets all cached page translations
+    ///
+    /// Happens implicitly whenever a region is added or replaced. Has to be called by embedders
+    /// which change the host memory or the permissions of a region in place.
+    pub fn flush_translation_cache(&mut self) {
+        match self {
+            MemoryMapping::Identity => {}
+            MemoryMapping::Aligned(m) => m.common.translation_cache.get_mut().flush(),
+            MemoryMapping::Unaligned(m) => m.common.translation_cache.get_mut().flush(),
+        }
+    }
+
+    /// Returns the hit and miss counters of the page translation cache, if there is one.
+    ///
+    /// There is none if it is disabled by [Config::enable_translation_cache].
+    #[cfg(feature = "diagnostics")]
+    pub fn translation_cache_diagnostics(
+        &self,
+    ) -> Option<crate::diagnostics::MappingCacheDiagnostics> {
+        match self {
+            MemoryMapping::Identity => None,
+            MemoryMapping::Aligned(m) if !m.common.config.enable_translation_cache => None,
+            MemoryMapping::Unaligned(m) if !m.common.config.enable_translation_cache => None,
+            // Safety: see map()
+            MemoryMapping::Aligned(m) => {
+                Some(unsafe { &*m.common.translation_cache.get() }.diagnostics)
+            }
+            MemoryMapping::Unaligned(m) => {
+                Some(unsafe { &*m.common.translation_cache.get() }.diagnostics)
+            }
+        }
+    }
+
+    /// Checks every load and store against the poisoned bytes of the [HeapSanitizer]
+    ///
+    /// Has no effect on the identity mapping.
+    pub fn set_heap_sanitizer(&mut self, heap_sanitizer: Option<Rc<RefCell<HeapSanitizer>>>) {
+        match self {
+            MemoryMapping::Identity => {}
+            MemoryMapping::Aligned(m) => m.common.heap_sanitizer = heap_sanitizer,
+            MemoryMapping::Unaligned(m) => m.common.heap_sanitizer = heap_sanitizer,
+        }
+    }
+
+    /// Returns the [HeapSanitizer], if there is one.
+    pub fn heap_sanitizer(&self) -> Option<&Rc<RefCell<HeapSanitizer>>> {
+        match self {
+            MemoryMapping::Identity => None,
+            MemoryMapping::Aligned(m) => m.common.heap_sanitizer.as_ref(),
+            MemoryMapping::Unaligned(m) => m.common.heap_sanitizer.as_ref(),
+        }
+    }
+
+    /// Tracks the written bytes of the stack and checks every load against them
+    ///
+    /// Has no effect on the identity mapping.
+    pub fn set_stack_sanitizer(&mut self, stack_sanitizer: Option<Rc<RefCell<StackSanitizer>>>) {
+        match self {
+            MemoryMapping::Identity => {}
+            MemoryMapping::Aligned(m) => m.common.stack_sanitizer = stack_sanitizer,
+            MemoryMapping::Unaligned(m) => m.common.stack_sanitizer = stack_sanitizer,
+        }
+    }
+
+    /// Returns the [StackSanitizer], if there is one.
+    pub fn stack_sanitizer(&self) -> Option<&Rc<RefCell<StackSanitizer>>> {
+        match self {
+            MemoryMapping::Identity => None,
+            MemoryMapping::Aligned(m) => m.common.stack_sanitizer.as_ref(),
+            MemoryMapping::Unaligned(m) => m.common.stack_sanitizer.as_ref(),
+        }
+    }
+
     /// Returns the `MemoryRegion`s in this mapping.
     pub fn get_regions(&self) -> &[MemoryRegion] {
         match self {
@@ -577,6 +1124,108 @@ impl<'a> MemoryMapping<'a> {
         }
     }
 
+    /// Maps an additional region, e.g. to grow the heap beyond its initial region
+    ///
+    /// The region has to satisfy the same invariants as the ones passed to the constructor,
+    /// otherwise the mapping is left unchanged. Returns the index of the new region, the
+    /// indices of the regions after it are shifted by one. The host memory of the region has
+    /// to outlive the mapping. Tainted regions (see [MemoryRegion::new_readonly_tainted])
+    /// become taint sources when replaying with the regions of the mapping.
+    pub fn add_region(&mut self, region: MemoryRegion) -> Result<usize, EbpfError> {
+        // The constructors sort stably, so the region ends up behind the ones at the same address
+        let index = self
+            .get_regions()
+            .partition_point(|other| other.vm_addr <= region.vm_addr);
+        let mut regions = match self {
+            MemoryMapping::Identity => return Err(EbpfError::InvalidMemoryRegion(0)),
+            // Skips the empty region at address 0 inserted by the constructor
+            MemoryMapping::Aligned(m) => m.common.regions[1..].to_vec(),
+            MemoryMapping::Unaligned(m) => m.common.regions.to_vec(),
+        };
+        regions.push(region);
+        let common = match self {
+            MemoryMapping::Identity => return Err(EbpfError::InvalidMemoryRegion(0)),
+            MemoryMapping::Aligned(m) => &mut m.common,
+            MemoryMapping::Unaligned(m) => &mut m.common,
+        };
+        let mut mapping = Self::new_with_access_violation_handler(
+            regions,
+            common.config,
+            common.sbpf_version,
+            Box::new(default_access_violation_handler),
+        )?;
+        let new_common = match &mut mapping {
+            MemoryMapping::Identity => return Err(EbpfError::InvalidMemoryRegion(0)),
+            MemoryMapping::Aligned(m) => &mut m.common,
+            MemoryMapping::Unaligned(m) => &mut m.common,
+        };
+        mem::swap(
+            &mut new_common.access_violation_handler,
+            &mut common.access_violation_handler,
+        );
+        new_common.heap_sanitizer = common.heap_sanitizer.take();
+        new_common.stack_sanitizer = common.stack_sanitizer.take();
+        *self = mapping;
+        self.flush_translation_cache();
+        Ok(index)
+    }
+
+    /// Maps the `len` bytes at `aliased_vm_addr` a second time at `vm_addr`
+    ///
+    /// Both ranges share their host memory, e.g. account data in the input region which is
+    /// also handed to a syscall as a slice. The alias inherits the permission to store and the
+    /// [taint_offset](MemoryRegion::taint_offset) of the aliased bytes, which have to be in a
+    /// single continuous region. [Self::resolve_alias] translates its addresses back, so that
+    /// [InputTaint](crate::taint::InputTaint) and the
+    /// [AccountWriteTracker](crate::accounts::AccountWriteTracker) treat the accesses through
+    /// either range alike. Returns the index of the new region like [Self::add_region].
+    pub fn add_alias(
+        &mut self,
+        vm_addr: u64,
+        aliased_vm_addr: u64,
+        len: u64,
+    ) -> Result<usize, EbpfError> {
+        let aliased = self
+            .get_regions()
+            .iter()
+            .filter(|region| region.vm_gap_shift == 63)
+            .find_map(|region| {
+                let host_addr = region.vm_to_host(AccessType::Load, aliased_vm_addr, len)?;
+                let offset = aliased_vm_addr.saturating_sub(region.vm_addr);
+                Some(MemoryRegion {
+                    host_addr,
+                    vm_addr,
+                    len,
+                    writable: region.writable,
+                    alignment: region.alignment,
+                    taint_offset: region
+                        .taint_offset
+                        .map(|taint_offset| taint_offset.saturating_add(offset)),
+                    alias_of: Some(
+                        region
+                            .resolve_alias(aliased_vm_addr)
+                            .unwrap_or(aliased_vm_addr),
+                    ),
+                    ..MemoryRegion::new_readonly(&[], vm_addr)
+                })
+            })
+            .ok_or(EbpfError::AccessViolation(
+                AccessType::Load,
+                aliased_vm_addr,
+                len,
+                "alias",
+            ))?;
+        self.add_region(aliased)
+    }
+
+    /// Address of the byte which `vm_addr` is an alias of, or `vm_addr` itself
+    pub fn resolve_alias(&self, vm_addr: u64) -> u64 {
+        self.get_regions()
+            .iter()
+            .find_map(|region| region.resolve_alias(vm_addr))
+            .unwrap_or(vm_addr)
+    }
+
     /// Replaces the `MemoryRegion` at the given index
     pub fn replace_region(&mut self, index: usize, region: MemoryRegion) -> Result<(), EbpfError> {
         let regions = self.get_regions();
@@ -597,6 +1246,240 @@ impl<'a> MemoryMapping<'a> {
     }
 }
 
+/// Whether [MemoryMapping::new] creates an [AlignedMemoryMapping]
+fn uses_aligned_mapping(config: &Config, sbpf_version: SBPFVersion) -> bool {
+    sbpf_version == SBPFVersion::V4 || config.aligned_memory_mapping
+}
+
+/// Slot of the aligned address space which `vm_addr` lies in
+fn slot_of(vm_addr: u64) -> u64 {
+    vm_addr
+        .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+        .unwrap_or(0)
+}
+
+/// Invalid regions found by a [MemoryRegionBuilder]
+///
+/// Regions are identified by the order in which they were added to the builder.
+#[derive(Debug, thiserror::Error)]
+pub enum MemoryRegionError {
+    /// The vm address ranges of two regions overlap
+    #[error("Regions {0} and {1} overlap in the vm address space")]
+    VmOverlap(usize, usize),
+    /// Two regions which are no aliases share host memory and one of them is writable
+    #[error("Regions {0} and {1} overlap in host memory and one of them is writable")]
+    HostOverlap(usize, usize),
+    /// The length of a gapped region is not a multiple of its gap size
+    #[error("Length of region {0} is not a multiple of its gap size")]
+    MisalignedGap(usize),
+    /// A region of an aligned mapping lies in slot 0, which is reserved
+    #[error("Region {0} lies in the reserved slot 0")]
+    ReservedSlot(usize),
+    /// A region of an aligned mapping extends into the next slot
+    #[error("Region {0} crosses the end of its slot")]
+    CrossesSlot(usize),
+    /// Two regions of an aligned mapping share a slot, but not both are unaligned
+    #[error("Regions {0} and {1} share a slot, but not both are unaligned")]
+    SlotCollision(usize, usize),
+    /// A slot of an aligned mapping is empty, but regions follow it
+    #[error("Slot {0} is empty, but regions follow it")]
+    MissingSlot(u64),
+    /// The mapping rejected the regions
+    #[error("{0}")]
+    Mapping(#[from] EbpfError),
+}
+
+/// Validates regions before they are mapped
+///
+/// The constructors of [MemoryMapping] only reject regions whose vm addresses overlap, and
+/// only by their index after sorting. Host memory which two regions share is not noticed at
+/// all, so e.g. a writable region can silently modify the bytes of a readonly one. The builder
+/// checks all regions up front and reports which ones conflict. In
+/// [debug mode](Self::with_guard_regions) it additionally fills the unmapped vm addresses
+/// around the regions with guard regions.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryRegionBuilder {
+    /// Regions in the order they were added
+    regions: Vec<MemoryRegion>,
+    /// Whether to fill the gaps between the regions with guard regions
+    guard_regions: bool,
+}
+
+impl MemoryRegionBuilder {
+    /// Adds a region
+    pub fn region(mut self, region: MemoryRegion) -> Self {
+        self.regions.push(region);
+        self
+    }
+
+    /// Adds several regions
+    pub fn regions(mut self, regions: impl IntoIterator<Item = MemoryRegion>) -> Self {
+        self.regions.extend(regions);
+        self
+    }
+
+    /// Fills the gaps after the regions up to the next region or the end of their slot with
+    /// [guard regions](MemoryRegion::new_guard)
+    ///
+    /// A stray access into a gap then fails with an access violation in the "guard" section,
+    /// instead of one named after the slot. In an [AlignedMemoryMapping] only the gaps after
+    /// unaligned regions are filled, as aligned regions occupy their slot exclusively. Guard
+    /// regions keep regions from growing into the gaps, e.g. by a
+    /// [ZeroFillAccessViolationHandler], and from being added there later.
+    pub fn with_guard_regions(mut self, guard_regions: bool) -> Self {
+        self.guard_regions = guard_regions;
+        self
+    }
+
+    /// Checks the regions for a mapping with the given `config` and `sbpf_version`
+    pub fn validate(
+        &self,
+        config: &Config,
+        sbpf_version: SBPFVersion,
+    ) -> Result<(), MemoryRegionError> {
+        let regions = &self.regions;
+        let mut order = (0..regions.len()).collect::<Vec<_>>();
+        order.sort_by_key(|index| regions[*index].vm_addr);
+        for index in order.iter() {
+            let region = &regions[*index];
+            if region.vm_gap_shift != 63 {
+                let gap_size = 1u64.checked_shl(region.vm_gap_shift as u32).unwrap_or(0);
+                if region.len & gap_size.saturating_sub(1) != 0 {
+                    return Err(MemoryRegionError::MisalignedGap(*index));
+                }
+            }
+        }
+        for pair in order.windows(2) {
+            if regions[pair[0]].vm_addr_range().end > regions[pair[1]].vm_addr {
+                return Err(MemoryRegionError::VmOverlap(pair[0], pair[1]));
+            }
+        }
+        // Aliases share host memory on purpose
+        let mut by_host_addr = order
+            .iter()
+            .copied()
+            .filter(|index| {
+                let region = &regions[*index];
+                region.len > 0 && region.alias_of.is_none() && !region.guard
+            })
+            .collect::<Vec<_>>();
+        by_host_addr.sort_by_key(|index| regions[*index].host_addr);
+        for (position, first) in by_host_addr.iter().enumerate() {
+            let first_region = &regions[*first];
+            let first_end = first_region.host_addr.saturating_add(first_region.len);
+            for second in by_host_addr.iter().skip(position.saturating_add(1)) {
+                let second_region = &regions[*second];
+                if first_end <= second_region.host_addr {
+                    break;
+                }
+                if first_region.writable || second_region.writable {
+                    return Err(MemoryRegionError::HostOverlap(
+                        *first.min(second),
+                        *first.max(second),
+                    ));
+                }
+            }
+        }
+        if !uses_aligned_mapping(config, sbpf_version) {
+            return Ok(());
+        }
+        let mut previous: Option<usize> = None;
+        for index in order {
+            let region = &regions[index];
+            let slot = slot_of(region.vm_addr);
+            if slot == 0 {
+                return Err(MemoryRegionError::ReservedSlot(index));
+            }
+            if slot_of(region.vm_addr_range().end.saturating_sub(1)) > slot {
+                return Err(MemoryRegionError::CrossesSlot(index));
+            }
+            match previous {
+                Some(previous) if slot_of(regions[previous].vm_addr) == slot => {
+                    if region.alignment != RegionAlignment::Unaligned
+                        || regions[previous].alignment != RegionAlignment::Unaligned
+                    {
+                        return Err(MemoryRegionError::SlotCollision(previous, index));
+                    }
+                }
+                _ => {
+                    let expected = previous.map_or(1, |previous| {
+                        slot_of(regions[previous].vm_addr).saturating_add(1)
+                    });
+                    if slot != expected {
+                        return Err(MemoryRegionError::MissingSlot(expected));
+                    }
+                }
+            }
+            previous = Some(index);
+        }
+        Ok(())
+    }
+
+    /// Validates the regions and returns them, followed by the guard regions if enabled
+    pub fn build_regions(
+        &self,
+        config: &Config,
+        sbpf_version: SBPFVersion,
+    ) -> Result<Vec<MemoryRegion>, MemoryRegionError> {
+        self.validate(config, sbpf_version)?;
+        let mut regions = self.regions.clone();
+        if self.guard_regions {
+            let aligned = uses_aligned_mapping(config, sbpf_version);
+            let mut sorted = self.regions.clone();
+            sorted.sort();
+            for (index, region) in sorted.iter().enumerate() {
+                if aligned && region.alignment != RegionAlignment::Unaligned {
+                    continue;
+                }
+                let end = region.vm_addr_range().end;
+                let slot_end = slot_of(region.vm_addr)
+                    .saturating_add(1)
+                    .checked_shl(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+                    .unwrap_or(u64::MAX);
+                let next = sorted
+                    .get(index.saturating_add(1))
+                    .map_or(slot_end, |next| next.vm_addr.min(slot_end));
+                if next > end {
+                    regions.push(
+                        MemoryRegion::new_guard(end, next.saturating_sub(end))
+                            .with_alignment(RegionAlignment::Unaligned),
+                    );
+                }
+            }
+        }
+        Ok(regions)
+    }
+
+    /// Validates the regions and maps them, see [MemoryMapping::new]
+    pub fn build(
+        self,
+        config: &Config,
+        sbpf_version: SBPFVersion,
+    ) -> Result<MemoryMapping<'_>, MemoryRegionError> {
+        self.build_with_access_violation_handler(
+            config,
+            sbpf_version,
+            Box::new(default_access_violation_handler),
+        )
+    }
+
+    /// Validates the regions and maps them, see [MemoryMapping::new_with_access_violation_handler]
+    pub fn build_with_access_violation_handler(
+        self,
+        config: &Config,
+        sbpf_version: SBPFVersion,
+        access_violation_handler: AccessViolationHandler,
+    ) -> Result<MemoryMapping<'_>, MemoryRegionError> {
+        let regions = self.build_regions(config, sbpf_version)?;
+        Ok(MemoryMapping::new_with_access_violation_handler(
+            regions,
+            config,
+            sbpf_version,
+            access_violation_handler,
+        )?)
+    }
+}
+
 /// Fast, small linear cache used to speed up unaligned memory mapping.
 #[derive(Debug)]
 struct MappingCache {
@@ -607,6 +1490,9 @@ struct MappingCache {
     // New entries are written backwards, so that find() can always scan
     // forward which is faster.
     head: isize,
+    // Hit and miss counters
+    #[cfg(feature = "diagnostics")]
+    diagnostics: crate::diagnostics::MappingCacheDiagnostics,
 }
 
 impl MappingCache {
@@ -616,6 +1502,8 @@ impl MappingCache {
         MappingCache {
             entries: array::from_fn(|_| (0..0, 0)),
             head: 0,
+            #[cfg(feature = "diagnostics")]
+            diagnostics: crate::diagnostics::MappingCacheDiagnostics::default(),
         }
     }
 
@@ -646,8 +1534,122 @@ impl MappingCache {
 
     #[inline]
     fn flush(&mut self) {
-        self.entries = array::from_fn(|_| (0..0, 0));
-        self.head = 0;
+        self.entries = array::from_fn(|_| (0..0, 0));
+        self.head = 0;
+    }
+}
+
+/// A contiguous piece of a page which maps linearly to host memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct TranslationCacheEntry {
+    // First vm address covered, inclusive.
+    vm_start: u64,
+    // Last vm address covered, exclusive. Empty entries have vm_start == vm_end.
+    vm_end: u64,
+    // Host address corresponding to vm_start.
+    host_start: u64,
+    // Is `AccessType::Store` allowed.
+    writable: bool,
+}
+
+/// Direct mapped cache of recent vm page => host address translations.
+///
+/// It is consulted before the region lookup and must be flushed whenever a region changes.
+#[derive(Debug)]
+struct TranslationCache {
+    // The cached entries, indexed by the lower bits of the vm page number.
+    entries: [TranslationCacheEntry; TranslationCache::SIZE],
+    // Hit and miss counters
+    #[cfg(feature = "diagnostics")]
+    diagnostics: crate::diagnostics::MappingCacheDiagnostics,
+}
+
+impl TranslationCache {
+    const PAGE_SHIFT: u32 = 12;
+    const SIZE: usize = 16;
+
+    fn new() -> TranslationCache {
+        TranslationCache {
+            entries: [TranslationCacheEntry::default(); Self::SIZE],
+            #[cfg(feature = "diagnostics")]
+            diagnostics: crate::diagnostics::MappingCacheDiagnostics::default(),
+        }
+    }
+
+    #[inline]
+    fn slot(vm_addr: u64) -> usize {
+        (vm_addr >> Self::PAGE_SHIFT) as usize % Self::SIZE
+    }
+
+    #[allow(clippy::arithmetic_side_effects)]
+    #[inline]
+    fn translate(&mut self, access_type: AccessType, vm_addr: u64, len: u64) -> Option<u64> {
+        // Safety:
+        // slot() is guaranteed to be between 0..Self::SIZE
+        let entry = unsafe { self.entries.get_unchecked(Self::slot(vm_addr)) };
+        let hit = vm_addr >= entry.vm_start
+            && vm_addr < entry.vm_end
+            && vm_addr
+                .checked_add(len)
+                .is_some_and(|end| end <= entry.vm_end)
+            && (access_type == AccessType::Load || entry.writable);
+        #[cfg(feature = "diagnostics")]
+        if hit {
+            self.diagnostics.hits = self.diagnostics.hits.saturating_add(1);
+        } else {
+            self.diagnostics.misses = self.diagnostics.misses.saturating_add(1);
+        }
+        hit.then(|| entry.host_start + (vm_addr - entry.vm_start))
+    }
+
+    /// Caches the page of `region` which contains `vm_addr`.
+    ///
+    /// `vm_addr` must have been successfully translated by `region` before.
+    fn insert(&mut self, region: &MemoryRegion, vm_addr: u64) {
+        // Regions with gaps smaller than a page can not be cached at page granularity
+        if (region.vm_gap_shift as u32) < Self::PAGE_SHIFT {
+            return;
+        }
+        let page_mask = !((1u64 << Self::PAGE_SHIFT).saturating_sub(1));
+        let page_start = vm_addr & page_mask;
+        let page_end = page_start.saturating_add(1u64 << Self::PAGE_SHIFT);
+        // Find the contiguous piece (frame) of the region containing vm_addr
+        let begin_offset = vm_addr.saturating_sub(region.vm_addr);
+        let gap_mask = (-1i64).checked_shl(region.vm_gap_shift as u32).unwrap_or(0) as u64;
+        let frame_vm_offset = begin_offset & gap_mask;
+        let frame_host_offset = frame_vm_offset.checked_shr(1).unwrap_or(0);
+        let frame_vm_start = region.vm_addr.saturating_add(frame_vm_offset);
+        let frame_len = (!gap_mask)
+            .saturating_add(1)
+            .min(region.len.saturating_sub(frame_host_offset));
+        let vm_start = page_start.max(frame_vm_start);
+        let vm_end = page_end.min(frame_vm_start.saturating_add(frame_len));
+        if vm_start >= vm_end {
+            return;
+        }
+        let host_start = region
+            .host_addr
+            .saturating_add(frame_host_offset)
+            .saturating_add(vm_start.saturating_sub(frame_vm_start));
+        debug_assert_eq!(
+            region.vm_to_host(AccessType::Load, vm_start, vm_end.saturating_sub(vm_start)),
+            Some(host_start)
+        );
+        // Safety:
+        // slot() is guaranteed to be between 0..Self::SIZE
+        unsafe {
+            *self.entries.get_unchecked_mut(Self::slot(vm_addr)) = TranslationCacheEntry {
+                vm_start,
+                vm_end,
+                host_start,
+                writable: region.writable,
+            };
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) {
+        self.entries = [TranslationCacheEntry::default(); Self::SIZE];
     }
 }
 
@@ -716,6 +1718,165 @@ mod test {
         assert_eq!(cache.find(0), None);
     }
 
+    #[test]
+    fn test_translation_cache() {
+        let mut cache = TranslationCache::new();
+        let mem = vec![0u8; 0x3000];
+        let region = MemoryRegion::new_readonly(&mem, ebpf::MM_INPUT_START + 0x800);
+        assert_eq!(
+            cache.translate(AccessType::Load, ebpf::MM_INPUT_START + 0x800, 1),
+            None
+        );
+        cache.insert(&region, ebpf::MM_INPUT_START + 0x900);
+        // the cached piece is clipped to the page and to the region
+        assert_eq!(
+            cache.translate(AccessType::Load, ebpf::MM_INPUT_START + 0x800, 8),
+            Some(mem.as_ptr() as u64)
+        );
+        assert_eq!(
+            cache.translate(AccessType::Load, ebpf::MM_INPUT_START + 0xFF8, 8),
+            Some(mem.as_ptr() as u64 + 0x7F8)
+        );
+        assert_eq!(
+            cache.translate(AccessType::Load, ebpf::MM_INPUT_START + 0xFF9, 8),
+            None
+        );
+        assert_eq!(
+            cache.translate(AccessType::Load, ebpf::MM_INPUT_START + 0x7FF, 1),
+            None
+        );
+        // read only
+        assert_eq!(
+            cache.translate(AccessType::Store, ebpf::MM_INPUT_START + 0x800, 1),
+            None
+        );
+        cache.flush();
+        assert_eq!(
+            cache.translate(AccessType::Load, ebpf::MM_INPUT_START + 0x800, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_translation_cache_gapped() {
+        let mut cache = TranslationCache::new();
+        let mut mem = vec![0u8; 0x4000];
+        let host_addr = mem.as_ptr() as u64;
+        let region = MemoryRegion::new_writable_gapped(&mut mem, ebpf::MM_STACK_START, 0x1000);
+        // frame 1 starts at vm offset 0x2000 and host offset 0x1000
+        cache.insert(&region, ebpf::MM_STACK_START + 0x2010);
+        assert_eq!(
+            cache.translate(AccessType::Store, ebpf::MM_STACK_START + 0x2000, 8),
+            Some(host_addr + 0x1000)
+        );
+        assert_eq!(
+            cache.translate(AccessType::Load, ebpf::MM_STACK_START + 0x1FF8, 8),
+            None
+        );
+        // gaps smaller than a page are never cached
+        let region = MemoryRegion::new_writable_gapped(&mut mem, ebpf::MM_STACK_START, 0x10);
+        cache.flush();
+        cache.insert(&region, ebpf::MM_STACK_START);
+        assert_eq!(
+            cache.translate(AccessType::Load, ebpf::MM_STACK_START, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_map_with_translation_cache() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                enable_translation_cache: true,
+                ..Config::default()
+            };
+            let mut mem1 = vec![0u8; 8];
+            let mut mem2 = vec![0u8; 8];
+            let mut m = MemoryMapping::new(
+                vec![MemoryRegion::new_readonly(&mem1, ebpf::MM_RODATA_START)],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            assert_eq!(
+                m.map(AccessType::Load, ebpf::MM_RODATA_START, 1).unwrap(),
+                mem1.as_ptr() as u64
+            );
+            assert!(m.map(AccessType::Store, ebpf::MM_RODATA_START, 1).is_err());
+            // a cached read only translation does not survive a change of the permissions
+            let (index, _region) = m.find_region(ebpf::MM_RODATA_START).unwrap();
+            m.replace_region(
+                index,
+                MemoryRegion::new_writable(&mut mem1, ebpf::MM_RODATA_START),
+            )
+            .unwrap();
+            assert_eq!(
+                m.map(AccessType::Store, ebpf::MM_RODATA_START, 1).unwrap(),
+                mem1.as_ptr() as u64
+            );
+            m.add_region(MemoryRegion::new_writable(&mut mem2, ebpf::MM_STACK_START))
+                .unwrap();
+            #[cfg(feature = "diagnostics")]
+            assert_eq!(
+                m.translation_cache_diagnostics(),
+                Some(crate::diagnostics::MappingCacheDiagnostics::default())
+            );
+            assert_eq!(
+                m.map(AccessType::Store, ebpf::MM_STACK_START, 8).unwrap(),
+                mem2.as_ptr() as u64
+            );
+            m.flush_translation_cache();
+            assert_eq!(
+                m.map(AccessType::Load, ebpf::MM_STACK_START, 8).unwrap(),
+                mem2.as_ptr() as u64
+            );
+            #[cfg(feature = "diagnostics")]
+            assert_eq!(
+                m.translation_cache_diagnostics().map(|cache| cache.hits),
+                Some(0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_map_without_translation_cache() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                enable_translation_cache: false,
+                ..Config::default()
+            };
+            let mem1 = vec![0u8; 8];
+            let mem2 = vec![0u8; 8];
+            let mut m = MemoryMapping::new(
+                vec![MemoryRegion::new_readonly(&mem1, ebpf::MM_RODATA_START)],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            for _ in 0..2 {
+                assert_eq!(
+                    m.map(AccessType::Load, ebpf::MM_RODATA_START + 4, 4)
+                        .unwrap(),
+                    mem1.as_ptr() as u64 + 4
+                );
+            }
+            let (index, _region) = m.find_region(ebpf::MM_RODATA_START).unwrap();
+            m.replace_region(
+                index,
+                MemoryRegion::new_readonly(&mem2, ebpf::MM_RODATA_START),
+            )
+            .unwrap();
+            assert_eq!(
+                m.map(AccessType::Load, ebpf::MM_RODATA_START, 1).unwrap(),
+                mem2.as_ptr() as u64
+            );
+            #[cfg(feature = "diagnostics")]
+            assert_eq!(m.translation_cache_diagnostics(), None);
+        }
+    }
+
     #[test]
     fn test_map_empty() {
         for aligned_memory_mapping in [false, true] {
@@ -1225,6 +2386,377 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_map_add_region() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let mut heap = [11; 8];
+            let mut grown_heap = [22; 8];
+            let mut m = MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_readonly(&[0; 8], ebpf::MM_RODATA_START),
+                    MemoryRegion::new_readonly(&[0; 8], ebpf::MM_STACK_START),
+                    MemoryRegion::new_writable(&mut heap, ebpf::MM_HEAP_START)
+                        .with_alignment(RegionAlignment::Unaligned),
+                ],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            let stack_index = m.find_region(ebpf::MM_STACK_START).unwrap().0;
+            assert_error!(
+                m.map(AccessType::Load, ebpf::MM_HEAP_START + 8, 1),
+                "AccessViolation"
+            );
+
+            // Overlaps the heap
+            assert_error!(
+                m.add_region(
+                    MemoryRegion::new_writable(&mut grown_heap, ebpf::MM_HEAP_START + 4)
+                        .with_alignment(RegionAlignment::Unaligned)
+                ),
+                "InvalidMemoryRegion"
+            );
+            assert_eq!(
+                m.get_regions().len(),
+                if aligned_memory_mapping { 4 } else { 3 }
+            );
+
+            let index = m
+                .add_region(
+                    MemoryRegion::new_writable(&mut grown_heap, ebpf::MM_HEAP_START + 8)
+                        .with_alignment(RegionAlignment::Unaligned),
+                )
+                .unwrap();
+            assert_eq!(m.get_regions()[index].vm_addr, ebpf::MM_HEAP_START + 8);
+            assert_eq!(m.find_region(ebpf::MM_STACK_START).unwrap().0, stack_index);
+            assert_eq!(
+                m.map(AccessType::Store, ebpf::MM_HEAP_START + 9, 1)
+                    .unwrap(),
+                grown_heap.as_ptr() as u64 + 1
+            );
+            assert_eq!(
+                m.map(AccessType::Load, ebpf::MM_HEAP_START, 1).unwrap(),
+                heap.as_ptr() as u64
+            );
+        }
+        assert_error!(
+            MemoryMapping::new_identity().add_region(MemoryRegion::new_readonly(&[0], 0)),
+            "InvalidMemoryRegion(0)"
+        );
+
+        // Empty regions can share their address, the new one is placed behind the old one
+        let config = Config {
+            aligned_memory_mapping: false,
+            ..Config::default()
+        };
+        let mut m = MemoryMapping::new(
+            vec![
+                MemoryRegion::new_readonly(&[], ebpf::MM_INPUT_START),
+                MemoryRegion::new_readonly(&[0; 8], ebpf::MM_INPUT_START + 8),
+            ],
+            &config,
+            SBPFVersion::V3,
+        )
+        .unwrap();
+        let index = m
+            .add_region(MemoryRegion::new_writable(&mut [], ebpf::MM_INPUT_START))
+            .unwrap();
+        assert_eq!(index, 1);
+        assert!(m.get_regions()[index].writable);
+        assert!(!m.get_regions()[0].writable);
+        let index = m
+            .add_region(MemoryRegion::new_readonly(&[0; 8], ebpf::MM_RODATA_START))
+            .unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(m.get_regions()[index].vm_addr, ebpf::MM_RODATA_START);
+    }
+
+    #[test]
+    fn test_map_add_alias() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let mut input = [1, 2, 3, 4];
+            let mut input_region = MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START);
+            input_region.taint_offset = Some(16);
+            let mut m = MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_readonly(&[0; 8], ebpf::MM_RODATA_START),
+                    MemoryRegion::new_readonly(&[0; 8], ebpf::MM_STACK_START),
+                    MemoryRegion::new_readonly(&[0; 8], ebpf::MM_HEAP_START),
+                    input_region,
+                ],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            let alias_vm_addr = ebpf::MM_INPUT_START + ebpf::MM_REGION_SIZE;
+            assert_error!(
+                m.add_alias(alias_vm_addr, ebpf::MM_INPUT_START + 2, 4),
+                "AccessViolation"
+            );
+            let index = m
+                .add_alias(alias_vm_addr, ebpf::MM_INPUT_START + 1, 2)
+                .unwrap();
+            let alias = &m.get_regions()[index];
+            assert_eq!(alias.vm_addr_range(), alias_vm_addr..alias_vm_addr + 2);
+            assert_eq!(alias.alias_of, Some(ebpf::MM_INPUT_START + 1));
+            assert_eq!(alias.taint_offset, Some(17));
+            assert!(alias.writable);
+            m.store(5u8, alias_vm_addr + 1).unwrap();
+            assert_eq!(m.load::<u8>(ebpf::MM_INPUT_START + 2).unwrap(), 5);
+            assert_error!(m.load::<u16>(alias_vm_addr + 1), "AccessViolation");
+            assert_eq!(m.resolve_alias(alias_vm_addr + 1), ebpf::MM_INPUT_START + 2);
+            assert_eq!(m.resolve_alias(alias_vm_addr + 2), alias_vm_addr + 2);
+
+            // An alias of an alias refers to the original bytes
+            let index = m
+                .add_alias(alias_vm_addr + ebpf::MM_REGION_SIZE, alias_vm_addr + 1, 1)
+                .unwrap();
+            assert_eq!(
+                m.get_regions()[index].alias_of,
+                Some(ebpf::MM_INPUT_START + 2)
+            );
+        }
+    }
+
+    #[test]
+    fn test_memory_region_builder() {
+        let unaligned = Config {
+            aligned_memory_mapping: false,
+            ..Config::default()
+        };
+        let aligned = Config::default();
+        let mut mem = [0u8; 16];
+        let writable = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let readonly = MemoryRegion::new_readonly(&mem[8..], ebpf::MM_HEAP_START);
+        let validate = |builder: MemoryRegionBuilder, config: &Config| {
+            builder.validate(config, SBPFVersion::V3)
+        };
+
+        assert_error!(
+            validate(
+                MemoryRegionBuilder::default()
+                    .region(MemoryRegion::new_readonly(
+                        &[0; 8],
+                        ebpf::MM_INPUT_START + 8
+                    ))
+                    .region(writable.clone()),
+                &unaligned
+            ),
+            "VmOverlap(1, 0)"
+        );
+        let builder = MemoryRegionBuilder::default().regions([writable.clone(), readonly.clone()]);
+        assert_error!(validate(builder.clone(), &unaligned), "HostOverlap(0, 1)");
+        assert_error!(
+            builder.build(&unaligned, SBPFVersion::V3),
+            "HostOverlap(0, 1)"
+        );
+        // Readonly regions and aliases may share host memory
+        let mut alias = writable.clone();
+        alias.vm_addr = ebpf::MM_STACK_START;
+        alias.alias_of = Some(ebpf::MM_INPUT_START);
+        for region in [
+            MemoryRegion::new_readonly(&mem, ebpf::MM_INPUT_START),
+            alias,
+        ] {
+            let builder = MemoryRegionBuilder::default().regions([region, readonly.clone()]);
+            assert!(validate(builder, &unaligned).is_ok());
+        }
+        let mut stack = [0u8; 12];
+        assert_error!(
+            validate(
+                MemoryRegionBuilder::default().region(MemoryRegion::new_writable_gapped(
+                    &mut stack,
+                    ebpf::MM_STACK_START,
+                    8
+                )),
+                &unaligned
+            ),
+            "MisalignedGap(0)"
+        );
+
+        // Slots of the aligned address space
+        let region = |vm_addr| MemoryRegion::new_readonly(&[0; 8], vm_addr);
+        for (regions, error) in [
+            (vec![region(8)], "ReservedSlot(0)"),
+            (vec![region(ebpf::MM_STACK_START)], "MissingSlot(1)"),
+            (
+                vec![
+                    region(ebpf::MM_RODATA_START),
+                    region(ebpf::MM_RODATA_START + 8),
+                ],
+                "SlotCollision(0, 1)",
+            ),
+            (
+                vec![MemoryRegion::new_readonly(
+                    &[0; 8],
+                    ebpf::MM_STACK_START - 4,
+                )],
+                "CrossesSlot(0)",
+            ),
+        ] {
+            let builder = MemoryRegionBuilder::default().regions(regions.clone());
+            assert_error!(validate(builder, &aligned), "{}", error);
+        }
+        let builder = MemoryRegionBuilder::default().regions([
+            region(ebpf::MM_RODATA_START).with_alignment(RegionAlignment::Unaligned),
+            region(ebpf::MM_RODATA_START + 8).with_alignment(RegionAlignment::Unaligned),
+            region(ebpf::MM_STACK_START),
+        ]);
+        assert!(validate(builder, &aligned).is_ok());
+    }
+
+    #[test]
+    fn test_memory_region_builder_guard_regions() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let input = [1u8; 8];
+            let account = [2u8; 8];
+            let builder = MemoryRegionBuilder::default().regions([
+                MemoryRegion::new_readonly(&[0; 8], ebpf::MM_RODATA_START),
+                MemoryRegion::new_readonly(&[0; 8], ebpf::MM_STACK_START),
+                MemoryRegion::new_readonly(&[0; 8], ebpf::MM_HEAP_START),
+                MemoryRegion::new_readonly(&input, ebpf::MM_INPUT_START)
+                    .with_alignment(RegionAlignment::Unaligned),
+                MemoryRegion::new_readonly(&account, ebpf::MM_INPUT_START + 16)
+                    .with_alignment(RegionAlignment::Unaligned),
+            ]);
+            let m = builder.clone().build(&config, SBPFVersion::V3).unwrap();
+            assert_eq!(m.get_regions().iter().filter(|r| r.guard).count(), 0);
+            assert_error!(
+                m.map(AccessType::Load, ebpf::MM_INPUT_START + 8, 1),
+                "AccessViolation(Load, {}, 1, \"input\")",
+                ebpf::MM_INPUT_START + 8
+            );
+
+            let mut m = builder
+                .with_guard_regions(true)
+                .build(&config, SBPFVersion::V3)
+                .unwrap();
+            let guards = m
+                .get_regions()
+                .iter()
+                .filter(|r| r.guard)
+                .map(|r| r.vm_addr_range())
+                .collect::<Vec<_>>();
+            // The gaps after the aligned regions are not filled in an aligned mapping
+            assert_eq!(guards.len(), if aligned_memory_mapping { 2 } else { 5 });
+            assert!(guards.contains(&(ebpf::MM_INPUT_START + 8..ebpf::MM_INPUT_START + 16)));
+            assert!(guards.contains(
+                &(ebpf::MM_INPUT_START + 24..ebpf::MM_INPUT_START + ebpf::MM_REGION_SIZE)
+            ));
+            for vm_addr in [ebpf::MM_INPUT_START + 8, ebpf::MM_INPUT_START + 24] {
+                assert_error!(
+                    m.map(AccessType::Load, vm_addr, 1),
+                    "AccessViolation(Load, {}, 1, \"guard\")",
+                    vm_addr
+                );
+            }
+            assert_eq!(m.load::<u8>(ebpf::MM_INPUT_START + 16).unwrap(), 2);
+        }
+    }
+
+    #[test]
+    fn test_aligned_map_with_unaligned_regions() {
+        let config = Config::default();
+        let mut mem1 = [11, 11];
+        let mem2 = [22, 22, 22];
+        let mem3 = [33];
+        let mut m = MemoryMapping::new(
+            vec![
+                MemoryRegion::new_readonly(&[0; 8], ebpf::MM_RODATA_START),
+                MemoryRegion::new_readonly(&[0; 8], ebpf::MM_STACK_START),
+                MemoryRegion::new_readonly(&[0; 8], ebpf::MM_HEAP_START),
+                MemoryRegion::new_writable(&mut mem1, ebpf::MM_INPUT_START)
+                    .with_alignment(RegionAlignment::Unaligned),
+                MemoryRegion::new_readonly(&mem2, ebpf::MM_INPUT_START + 0x10)
+                    .with_alignment(RegionAlignment::Unaligned),
+            ],
+            &config,
+            SBPFVersion::V4,
+        )
+        .unwrap();
+        assert_eq!(m.find_region(ebpf::MM_STACK_START).unwrap().0, 2);
+        assert_eq!(m.find_region(ebpf::MM_HEAP_START).unwrap().0, 3);
+        assert_eq!(m.find_region(ebpf::MM_INPUT_START + 0x20).unwrap().0, 5);
+        assert_eq!(
+            m.map(AccessType::Store, ebpf::MM_INPUT_START + 1, 1)
+                .unwrap(),
+            mem1.as_ptr() as u64 + 1
+        );
+        assert_eq!(
+            m.map(AccessType::Load, ebpf::MM_INPUT_START + 0x12, 1)
+                .unwrap(),
+            mem2.as_ptr() as u64 + 2
+        );
+        assert_error!(
+            m.map(AccessType::Load, ebpf::MM_INPUT_START + 0x2, 1),
+            "AccessViolation"
+        );
+        assert_error!(
+            m.map(AccessType::Store, ebpf::MM_INPUT_START + 0x10, 1),
+            "AccessViolation"
+        );
+
+        let (index, _region) = m.find_region(ebpf::MM_INPUT_START + 0x10).unwrap();
+        m.replace_region(
+            index,
+            MemoryRegion::new_readonly(&mem3, ebpf::MM_INPUT_START + 0x10)
+                .with_alignment(RegionAlignment::Unaligned),
+        )
+        .unwrap();
+        assert_eq!(
+            m.map(AccessType::Load, ebpf::MM_INPUT_START + 0x10, 1)
+                .unwrap(),
+            mem3.as_ptr() as u64
+        );
+        assert_error!(
+            m.replace_region(
+                index,
+                MemoryRegion::new_readonly(&mem3, ebpf::MM_INPUT_START + 0x10)
+            ),
+            "InvalidMemoryRegion"
+        );
+
+        // Aligned regions can not share a slot
+        assert_error!(
+            MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_writable(&mut mem1, ebpf::MM_RODATA_START)
+                        .with_alignment(RegionAlignment::Unaligned),
+                    MemoryRegion::new_readonly(&mem2, ebpf::MM_RODATA_START + 0x10),
+                ],
+                &config,
+                SBPFVersion::V4,
+            ),
+            "InvalidMemoryRegion(2)"
+        );
+        // Unaligned regions sharing a slot must not overlap
+        assert_error!(
+            MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_writable(&mut mem1, ebpf::MM_RODATA_START)
+                        .with_alignment(RegionAlignment::Unaligned),
+                    MemoryRegion::new_readonly(&mem2, ebpf::MM_RODATA_START + 1)
+                        .with_alignment(RegionAlignment::Unaligned),
+                ],
+                &config,
+                SBPFVersion::V4,
+            ),
+            "InvalidMemoryRegion(2)"
+        );
+    }
+
     #[test]
     fn test_access_violation_handler_map() {
         for aligned_memory_mapping in [true, false] {
@@ -1343,6 +2875,44 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_copy_on_write_regions() {
+        for aligned_memory_mapping in [true, false] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let shared = [1u8, 2, 3, 4, 5, 6, 7, 8];
+            let copy_on_write =
+                CopyOnWriteAccessViolationHandler::new(Box::new(|payload, original| {
+                    assert_eq!(payload as usize, original.len());
+                    Box::from(original)
+                }));
+            let mut m = MemoryMapping::new_with_access_violation_handler(
+                vec![
+                    MemoryRegion::new_copy_on_write(&shared[..4], ebpf::MM_RODATA_START, 4),
+                    MemoryRegion::new_copy_on_write(&shared[4..], ebpf::MM_STACK_START, 4),
+                    MemoryRegion::new_readonly(&shared, ebpf::MM_HEAP_START),
+                ],
+                &config,
+                SBPFVersion::V3,
+                copy_on_write.handler(),
+            )
+            .unwrap();
+
+            assert_eq!(m.load::<u8>(ebpf::MM_RODATA_START).unwrap(), 1);
+            assert!(copy_on_write.copied_regions().is_empty());
+            m.store(55u8, ebpf::MM_RODATA_START + 1).unwrap();
+            m.store(66u8, ebpf::MM_RODATA_START + 2).unwrap();
+            assert_eq!(copy_on_write.copied_regions(), vec![4]);
+            assert_eq!(m.load::<u8>(ebpf::MM_RODATA_START + 1).unwrap(), 55);
+            assert_eq!(m.load::<u8>(ebpf::MM_STACK_START + 1).unwrap(), 6);
+            assert_eq!(shared, [1, 2, 3, 4, 5, 6, 7, 8]);
+            assert_error!(m.store(0u8, ebpf::MM_HEAP_START), "AccessViolation");
+            assert_eq!(copy_on_write.copied_regions(), vec![4]);
+        }
+    }
+
     #[test]
     #[should_panic(expected = "AccessViolation")]
     fn test_map_access_violation_handler_error() {
@@ -0,0 +1,509 @@
+//! Observers of interpreted executions
+//!
+//! Every [ExecutionObserver] in [crate::vm::EbpfVm::observers] is notified by the interpreter
+//! about the instructions it executes, the branches it takes, its memory accesses, calls,
+//! syscalls and returns. This allows to build custom tracers and feedback mechanisms without
+//! modifying the interpreter. The JIT does not notify observers.
+//!
+//! A [CoverageMapObserver] writes edge coverage directly into a map shared with an in-process
+//! fuzzer. A [StackProfiler] reports the call depth and stack usage per function.
+
+use crate::{corpus::hash, ebpf, elf::Executable, vm::ContextObject};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+/// Callbacks of the interpreter, all of which do nothing by default
+pub trait ExecutionObserver {
+    /// Before the instruction at `pc` is executed
+    fn on_insn(&mut self, _pc: u64, _insn: &ebpf::Insn, _registers: &[u64; 12]) {}
+
+    /// After the conditional jump at `pc` was evaluated
+    ///
+    /// A jump with an offset of 0 is always reported as not taken.
+    fn on_branch(&mut self, _pc: u64, _target_pc: u64, _taken: bool) {}
+
+    /// After `len` bytes were loaded from `vm_addr`
+    fn on_mem_read(&mut self, _pc: u64, _vm_addr: u64, _len: u64, _value: u64) {}
+
+    /// After `len` bytes were stored at `vm_addr`
+    fn on_mem_write(&mut self, _pc: u64, _vm_addr: u64, _len: u64, _value: u64) {}
+
+    /// After a function was entered, `call_depth` is the depth of the callee
+    fn on_call(&mut self, _pc: u64, _target_pc: u64, _call_depth: u64) {}
+
+    /// Before the syscall with the given hash is dispatched, with its arguments r1 to r5
+    fn on_syscall(&mut self, _pc: u64, _syscall: u32, _arguments: [u64; 5]) {}
+
+    /// When a function returns, `call_depth` is the depth of the returning function
+    ///
+    /// A depth of 0 means that the program terminates with `return_value`.
+    fn on_exit(&mut self, _pc: u64, _call_depth: u64, _return_value: u64) {}
+
+    /// After a call pushed a frame, with the frame pointer of the callee
+    fn on_push_frame(&mut self, _frame: &CallFrameEvent) {}
+
+    /// After a return popped a frame, `callee_pc` is the pc of the return instruction
+    fn on_pop_frame(&mut self, _frame: &CallFrameEvent) {}
+}
+
+/// A call frame which is pushed or popped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrameEvent {
+    /// Pc of the call instruction
+    pub caller_pc: u64,
+    /// Pc of the first instruction of the callee when pushed, of its return when popped
+    pub callee_pc: u64,
+    /// Depth of the callee
+    pub call_depth: u64,
+    /// Frame pointer (r10) of the callee
+    pub frame_pointer: u64,
+}
+
+/// Allows to keep a handle to an observer which is owned by the VM
+impl<T: ExecutionObserver> ExecutionObserver for Rc<RefCell<T>> {
+    fn on_insn(&mut self, pc: u64, insn: &ebpf::Insn, registers: &[u64; 12]) {
+        self.borrow_mut().on_insn(pc, insn, registers);
+    }
+
+    fn on_branch(&mut self, pc: u64, target_pc: u64, taken: bool) {
+        self.borrow_mut().on_branch(pc, target_pc, taken);
+    }
+
+    fn on_mem_read(&mut self, pc: u64, vm_addr: u64, len: u64, value: u64) {
+        self.borrow_mut().on_mem_read(pc, vm_addr, len, value);
+    }
+
+    fn on_mem_write(&mut self, pc: u64, vm_addr: u64, len: u64, value: u64) {
+        self.borrow_mut().on_mem_write(pc, vm_addr, len, value);
+    }
+
+    fn on_call(&mut self, pc: u64, target_pc: u64, call_depth: u64) {
+        self.borrow_mut().on_call(pc, target_pc, call_depth);
+    }
+
+    fn on_syscall(&mut self, pc: u64, syscall: u32, arguments: [u64; 5]) {
+        self.borrow_mut().on_syscall(pc, syscall, arguments);
+    }
+
+    fn on_exit(&mut self, pc: u64, call_depth: u64, return_value: u64) {
+        self.borrow_mut().on_exit(pc, call_depth, return_value);
+    }
+
+    fn on_push_frame(&mut self, frame: &CallFrameEvent) {
+        self.borrow_mut().on_push_frame(frame);
+    }
+
+    fn on_pop_frame(&mut self, frame: &CallFrameEvent) {
+        self.borrow_mut().on_pop_frame(frame);
+    }
+}
+
+/// Replaces an observer which panicked, so that it is not notified anymore
+pub(crate) struct FailedObserver;
+
+impl ExecutionObserver for FailedObserver {}
+
+/// Outcomes of a conditional jump
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchOutcomes {
+    /// Number of times the jump was taken
+    pub taken: u64,
+    /// Number of times execution fell through
+    pub not_taken: u64,
+}
+
+/// Counts the outcomes of every conditional jump
+#[derive(Debug, Clone, Default)]
+pub struct BranchRecorder {
+    /// pc => outcomes
+    pub branches: BTreeMap<u64, BranchOutcomes>,
+}
+
+impl BranchRecorder {
+    /// Pcs of the conditional jumps which were only ever evaluated in one direction
+    pub fn one_sided_branches(&self) -> Vec<u64> {
+        self.branches
+            .iter()
+            .filter(|(_pc, outcomes)| outcomes.taken == 0 || outcomes.not_taken == 0)
+            .map(|(pc, _outcomes)| *pc)
+            .collect()
+    }
+}
+
+impl ExecutionObserver for BranchRecorder {
+    fn on_branch(&mut self, pc: u64, _target_pc: u64, taken: bool) {
+        let outcomes = self.branches.entry(pc).or_default();
+        if taken {
+            outcomes.taken = outcomes.taken.saturating_add(1);
+        } else {
+            outcomes.not_taken = outcomes.not_taken.saturating_add(1);
+        }
+    }
+}
+
+/// Counts the edges of the control flow in a coverage map owned by the caller
+///
+/// Every transfer of control which does not fall through to the next instruction (taken
+/// branches, jumps, calls and returns) as well as every conditional jump which is not taken
+/// increments the counter at [Self::edge_index], wrapping around at 255. The map is written
+/// during the execution, so it can be the map of a LibAFL `StdMapObserver` without any
+/// conversion afterwards. The edges are taken from the notifications of the control flow
+/// instructions themselves, so the observer keeps no state between them and can be reused
+/// after an execution which failed.
+#[derive(Debug)]
+pub struct CoverageMapObserver {
+    /// Hit counters, indexed by edge hash
+    map: *mut u8,
+    /// Number of counters
+    len: usize,
+}
+
+impl CoverageMapObserver {
+    /// Creates an observer writing into `map`, which is not cleared
+    pub fn new(map: &'static mut [u8]) -> Self {
+        // Safety: The map is borrowed for the lifetime of the observer
+        unsafe { Self::from_raw_parts(map.as_mut_ptr(), map.len()) }
+    }
+
+    /// Creates an observer writing into the `len` counters at `map`, which are not cleared
+    ///
+    /// # Safety
+    ///
+    /// The map has to stay valid and must not be accessed otherwise while the VM executes,
+    /// like the maps of the LibAFL observers created from pointers.
+    pub unsafe fn from_raw_parts(map: *mut u8, len: usize) -> Self {
+        Self { map, len }
+    }
+
+    /// Index of the counter of the edge from `src_pc` to `dst_pc` in a map of `len` counters
+    pub fn edge_index(src_pc: u64, dst_pc: u64, len: usize) -> usize {
+        let mut edge = [0; 16];
+        edge[..8].copy_from_slice(&src_pc.to_le_bytes());
+        edge[8..].copy_from_slice(&dst_pc.to_le_bytes());
+        hash(&edge).checked_rem(len as u64).unwrap_or(0) as usize
+    }
+
+    fn hit(&mut self, src_pc: u64, dst_pc: u64) {
+        if self.len == 0 {
+            return;
+        }
+        // Safety: The index is in bounds and the map is valid, see [Self::from_raw_parts]
+        unsafe {
+            let counter = self.map.add(Self::edge_index(src_pc, dst_pc, self.len));
+            *counter = (*counter).wrapping_add(1);
+        }
+    }
+}
+
+impl ExecutionObserver for CoverageMapObserver {
+    fn on_insn(&mut self, pc: u64, insn: &ebpf::Insn, _registers: &[u64; 12]) {
+        // Unconditional jumps are not reported as branches
+        if insn.opc == ebpf::JA && insn.off != 0 {
+            let target_pc = (pc as i64)
+                .saturating_add(1)
+                .saturating_add(insn.off as i64);
+            self.hit(pc, target_pc as u64);
+        }
+    }
+
+    fn on_branch(&mut self, pc: u64, target_pc: u64, taken: bool) {
+        if taken {
+            self.hit(pc, target_pc);
+        } else {
+            self.hit(pc, pc.saturating_add(1));
+        }
+    }
+
+    fn on_call(&mut self, pc: u64, target_pc: u64, _call_depth: u64) {
+        self.hit(pc, target_pc);
+    }
+
+    fn on_pop_frame(&mut self, frame: &CallFrameEvent) {
+        self.hit(frame.callee_pc, frame.caller_pc.saturating_add(1));
+    }
+}
+
+/// Counts the calls between functions and keeps a shadow stack of the active frames
+#[derive(Debug, Clone, Default)]
+pub struct CallGraphRecorder {
+    /// (caller pc, callee pc) => number of calls
+    pub edges: BTreeMap<(u64, u64), u64>,
+    /// Frames which were pushed and not popped yet
+    pub shadow_stack: Vec<CallFrameEvent>,
+    /// Deepest call depth reached
+    pub max_call_depth: u64,
+}
+
+impl ExecutionObserver for CallGraphRecorder {
+    fn on_push_frame(&mut self, frame: &CallFrameEvent) {
+        let count = self
+            .edges
+            .entry((frame.caller_pc, frame.callee_pc))
+            .or_insert(0);
+        *count = count.saturating_add(1);
+        self.max_call_depth = self.max_call_depth.max(frame.call_depth);
+        self.shadow_stack.push(*frame);
+    }
+
+    fn on_pop_frame(&mut self, _frame: &CallFrameEvent) {
+        self.shadow_stack.pop();
+    }
+}
+
+impl CallGraphRecorder {
+    /// Summary of the calls per function, see [summarize_calls]
+    pub fn function_coverage<C: ContextObject>(
+        &self,
+        executable: &Executable<C>,
+    ) -> BTreeMap<u64, FunctionCoverage> {
+        summarize_calls(executable, &self.edges)
+    }
+}
+
+/// Calls of a function
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    /// Name in the function registry of the executable, which requires
+    /// [Config::enable_symbol_and_section_labels](crate::vm::Config::enable_symbol_and_section_labels)
+    pub name: Option<String>,
+    /// Number of calls
+    pub calls: u64,
+    /// Pcs of the call instructions it was called from
+    pub call_sites: BTreeSet<u64>,
+}
+
+/// Groups the calls of (caller pc, callee pc) => number of calls by the entry pc of the callee
+///
+/// Functions which were never called are not included, so two inputs reach the same functions
+/// if the keys of their summaries are equal.
+pub fn summarize_calls<C: ContextObject>(
+    executable: &Executable<C>,
+    edges: &BTreeMap<(u64, u64), u64>,
+) -> BTreeMap<u64, FunctionCoverage> {
+    let names = executable
+        .get_function_registry()
+        .iter()
+        .filter(|(_key, (name, _pc))| !name.is_empty())
+        .map(|(_key, (name, pc))| (pc as u64, String::from_utf8_lossy(name).to_string()))
+        .collect::<BTreeMap<_, _>>();
+    let mut functions = BTreeMap::<u64, FunctionCoverage>::new();
+    for ((caller_pc, callee_pc), calls) in edges.iter() {
+        let function = functions
+            .entry(*callee_pc)
+            .or_insert_with(|| FunctionCoverage {
+                name: names.get(callee_pc).cloned(),
+                ..FunctionCoverage::default()
+            });
+        function.calls = function.calls.saturating_add(*calls);
+        function.call_sites.insert(*caller_pc);
+    }
+    functions
+}
+
+/// A load or store observed by a [MemoryAccessRecorder]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObservedAccess {
+    /// Pc of the instruction
+    pub pc: u64,
+    /// Whether it was a store
+    pub is_store: bool,
+    /// Accessed address
+    pub vm_addr: u64,
+    /// Accessed bytes
+    pub len: u64,
+    /// Loaded or stored value
+    pub value: u64,
+}
+
+/// Records all memory accesses in the order they happened
+#[derive(Debug, Clone, Default)]
+pub struct MemoryAccessRecorder {
+    /// Accesses so far
+    pub accesses: Vec<ObservedAccess>,
+}
+
+impl ExecutionObserver for MemoryAccessRecorder {
+    fn on_mem_read(&mut self, pc: u64, vm_addr: u64, len: u64, value: u64) {
+        self.accesses.push(ObservedAccess {
+            pc,
+            is_store: false,
+            vm_addr,
+            len,
+            value,
+        });
+    }
+
+    fn on_mem_write(&mut self, pc: u64, vm_addr: u64, len: u64, value: u64) {
+        self.accesses.push(ObservedAccess {
+            pc,
+            is_store: true,
+            vm_addr,
+            len,
+            value,
+        });
+    }
+}
+
+/// Stack usage of a function, see [StackProfiler]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionStackUsage {
+    /// Most bytes below the frame pointer at entry which the function used
+    pub max_frame_usage: u64,
+    /// Deepest call depth the function was executed at
+    pub max_call_depth: u64,
+    /// Whether the frame usage came close to
+    /// [Config::stack_frame_size](crate::vm::Config::stack_frame_size), fixed frames only
+    pub near_frame_limit: bool,
+}
+
+/// Stack depth and usage of the executions observed by a [StackProfiler]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StackReport {
+    /// Deepest call depth reached
+    pub max_call_depth: u64,
+    /// Most bytes of the stack used by all active frames at once
+    pub max_stack_usage: u64,
+    /// Entry pc of each executed function => its usage
+    pub functions: BTreeMap<u64, FunctionStackUsage>,
+    /// Whether the call depth came close to
+    /// [Config::max_call_depth](crate::vm::Config::max_call_depth)
+    pub near_call_depth_limit: bool,
+    /// Whether the stack usage came close to the size of the stack region
+    pub near_stack_limit: bool,
+}
+
+/// A frame observed by a [StackProfiler]
+#[derive(Debug, Clone, Copy)]
+struct ActiveFrame {
+    /// Entry pc of the function
+    function: u64,
+    /// Frame pointer at entry
+    frame_pointer: u64,
+    /// Lowest frame pointer or accessed stack address below the frame pointer at entry
+    lowest: u64,
+    /// Call depth of the function
+    call_depth: u64,
+}
+
+/// Records the call depth and the stack usage per function
+///
+/// The usage of a frame is the distance between its frame pointer (r10) at entry and the
+/// lowest stack address it accessed below it. With dynamic stack frames, moving the frame
+/// pointer down counts as usage as well, even if the reserved bytes are never accessed. Usage
+/// above the given percentage of a limit is flagged in the [StackReport], which helps to tune
+/// [Config::stack_frame_size](crate::vm::Config::stack_frame_size) and lets fuzzers prioritize
+/// inputs which recurse deeply.
+#[derive(Debug, Clone)]
+pub struct StackProfiler {
+    /// Percentage of a limit from which on usage is flagged
+    pub threshold_percent: u64,
+    stack_frame_size: u64,
+    stack_size: u64,
+    max_call_depth: u64,
+    dynamic_stack_frames: bool,
+    frames: Vec<ActiveFrame>,
+    report: StackReport,
+}
+
+impl StackProfiler {
+    /// Creates a profiler for executions of `executable`, flagging usage above 90%
+    pub fn new<C: ContextObject>(executable: &Executable<C>) -> Self {
+        let config = executable.get_config();
+        Self {
+            threshold_percent: 90,
+            stack_frame_size: config.stack_frame_size as u64,
+            stack_size: config.stack_size() as u64,
+            max_call_depth: config.max_call_depth as u64,
+            dynamic_stack_frames: executable.get_sbpf_version().dynamic_stack_frames(),
+            frames: Vec::new(),
+            report: StackReport::default(),
+        }
+    }
+
+    /// Stack depth and usage of all executions observed so far
+    pub fn stack_report(&self) -> &StackReport {
+        &self.report
+    }
+
+    /// Discards the report, e.g. before the next input
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.report = StackReport::default();
+    }
+
+    fn is_near(&self, usage: u64, limit: u64) -> bool {
+        usage.saturating_mul(100) >= limit.saturating_mul(self.threshold_percent)
+    }
+
+    fn record_access(&mut self, vm_addr: u64) {
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+        if vm_addr >= ebpf::MM_STACK_START && vm_addr < frame.frame_pointer {
+            frame.lowest = frame.lowest.min(vm_addr);
+        }
+        let frame = *frame;
+        let frame_usage = frame.frame_pointer.saturating_sub(frame.lowest);
+        let near_frame_limit =
+            !self.dynamic_stack_frames && self.is_near(frame_usage, self.stack_frame_size);
+        let stack_usage = self.frames.iter().fold(0u64, |usage, frame| {
+            usage.saturating_add(frame.frame_pointer.saturating_sub(frame.lowest))
+        });
+        let near_stack_limit = self.is_near(stack_usage, self.stack_size);
+        let function = self.report.functions.entry(frame.function).or_default();
+        function.max_frame_usage = function.max_frame_usage.max(frame_usage);
+        function.max_call_depth = function.max_call_depth.max(frame.call_depth);
+        function.near_frame_limit |= near_frame_limit;
+        self.report.max_stack_usage = self.report.max_stack_usage.max(stack_usage);
+        self.report.near_stack_limit |= near_stack_limit;
+    }
+}
+
+impl ExecutionObserver for StackProfiler {
+    fn on_insn(&mut self, pc: u64, _insn: &ebpf::Insn, registers: &[u64; 12]) {
+        let frame_pointer = registers[ebpf::FRAME_PTR_REG];
+        if self.frames.is_empty() {
+            self.frames.push(ActiveFrame {
+                function: pc,
+                frame_pointer,
+                lowest: frame_pointer,
+                call_depth: 0,
+            });
+        }
+        self.record_access(frame_pointer);
+    }
+
+    fn on_mem_read(&mut self, _pc: u64, vm_addr: u64, _len: u64, _value: u64) {
+        self.record_access(vm_addr);
+    }
+
+    fn on_mem_write(&mut self, _pc: u64, vm_addr: u64, _len: u64, _value: u64) {
+        self.record_access(vm_addr);
+    }
+
+    fn on_exit(&mut self, _pc: u64, call_depth: u64, _return_value: u64) {
+        if call_depth == 0 {
+            self.frames.clear();
+        }
+    }
+
+    fn on_push_frame(&mut self, frame: &CallFrameEvent) {
+        self.frames.push(ActiveFrame {
+            function: frame.callee_pc,
+            frame_pointer: frame.frame_pointer,
+            lowest: frame.frame_pointer,
+            call_depth: frame.call_depth,
+        });
+        self.report.max_call_depth = self.report.max_call_depth.max(frame.call_depth);
+        // The interpreter fails when the depth reaches the limit, so it is at most one less
+        self.report.near_call_depth_limit |=
+            self.is_near(frame.call_depth.saturating_add(1), self.max_call_depth);
+    }
+
+    fn on_pop_frame(&mut self, _frame: &CallFrameEvent) {
+        self.frames.pop();
+    }
+}
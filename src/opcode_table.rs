@@ -0,0 +1,331 @@
+//! Canonical description of all opcodes
+//!
+//! Which instruction an opcode encodes depends on the [SBPFVersion], e.g. `0x2c` is `mul32`
+//! before SBPFv2 and `ldxb` since. The table below mirrors the rules of the verifier, so that
+//! analyses can look up operand kinds and access widths instead of matching opcodes themselves.
+
+use crate::{ebpf, program::SBPFVersion};
+
+/// What kind of instruction an opcode encodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionClass {
+    /// `lddw`, occupies two instruction slots
+    LoadImmediate,
+    /// Memory load into `dst`
+    Load,
+    /// Memory store from `src` or `imm`
+    Store,
+    /// Arithmetic and logic
+    Alu,
+    /// Product, quotient and remainder (SIMD-0174)
+    Product,
+    /// `ja`
+    Jump,
+    /// Jump if the comparison of `dst` with the source operand holds
+    ConditionalJump,
+    /// Call of a function or (before SBPFv3) a syscall
+    Call,
+    /// `syscall` (since SBPFv3)
+    Syscall,
+    /// Return from the current function
+    Exit,
+}
+
+/// Where the second operand of an instruction comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandSource {
+    /// There is no second operand
+    None,
+    /// The `src` register
+    Register,
+    /// The `imm` field
+    Immediate,
+}
+
+/// Description of an opcode
+#[derive(Debug, Clone, Copy)]
+pub struct OpcodeInfo {
+    /// Operation code
+    pub opcode: u8,
+    /// Mnemonic in the assembler syntax
+    pub mnemonic: &'static str,
+    /// Kind of instruction
+    pub class: InstructionClass,
+    /// Second operand
+    pub source: OperandSource,
+    /// Accessed bytes for loads and stores, otherwise the operand width in bytes
+    pub width: u8,
+    /// Whether the operands are interpreted as signed
+    pub signed: bool,
+    /// Versions which accept the opcode
+    pub available: fn(SBPFVersion) -> bool,
+}
+
+impl OpcodeInfo {
+    /// Whether the instruction accesses memory
+    pub fn is_memory_access(&self) -> bool {
+        matches!(self.class, InstructionClass::Load | InstructionClass::Store)
+    }
+
+    /// Mask of the low [Self::width] bytes of a value
+    pub fn value_mask(&self) -> u64 {
+        u64::MAX
+            .checked_shr(64u32.saturating_sub(u32::from(self.width).saturating_mul(8)))
+            .unwrap_or(0)
+    }
+}
+
+use InstructionClass as Class;
+use OperandSource as Source;
+
+const fn op(
+    opcode: u8,
+    mnemonic: &'static str,
+    class: InstructionClass,
+    source: OperandSource,
+    width: u8,
+    signed: bool,
+    available: fn(SBPFVersion) -> bool,
+) -> OpcodeInfo {
+    OpcodeInfo {
+        opcode,
+        mnemonic,
+        class,
+        source,
+        width,
+        signed,
+        available,
+    }
+}
+
+fn always(_sbpf_version: SBPFVersion) -> bool {
+    true
+}
+fn with_lddw(sbpf_version: SBPFVersion) -> bool {
+    !sbpf_version.disable_lddw()
+}
+fn without_lddw(sbpf_version: SBPFVersion) -> bool {
+    sbpf_version.disable_lddw()
+}
+fn legacy_memory_classes(sbpf_version: SBPFVersion) -> bool {
+    !sbpf_version.move_memory_instruction_classes()
+}
+fn moved_memory_classes(sbpf_version: SBPFVersion) -> bool {
+    sbpf_version.move_memory_instruction_classes()
+}
+fn with_pqr(sbpf_version: SBPFVersion) -> bool {
+    sbpf_version.enable_pqr()
+}
+fn without_pqr(sbpf_version: SBPFVersion) -> bool {
+    !sbpf_version.enable_pqr()
+}
+fn with_neg(sbpf_version: SBPFVersion) -> bool {
+    !sbpf_version.disable_neg()
+}
+fn with_le(sbpf_version: SBPFVersion) -> bool {
+    !sbpf_version.disable_le()
+}
+fn static_syscalls(sbpf_version: SBPFVersion) -> bool {
+    sbpf_version.static_syscalls()
+}
+fn dynamic_syscalls(sbpf_version: SBPFVersion) -> bool {
+    !sbpf_version.static_syscalls()
+}
+
+/// All opcodes, an opcode appears multiple times if its meaning changed between versions
+#[rustfmt::skip]
+pub static OPCODE_TABLE: &[OpcodeInfo] = &[
+    op(ebpf::LD_DW_IMM, "lddw", Class::LoadImmediate, Source::Immediate, 8, false, with_lddw),
+    op(ebpf::LD_B_REG, "ldxb", Class::Load, Source::Register, 1, false, legacy_memory_classes),
+    op(ebpf::LD_H_REG, "ldxh", Class::Load, Source::Register, 2, false, legacy_memory_classes),
+    op(ebpf::LD_W_REG, "ldxw", Class::Load, Source::Register, 4, false, legacy_memory_classes),
+    op(ebpf::LD_DW_REG, "ldxdw", Class::Load, Source::Register, 8, false, legacy_memory_classes),
+    op(ebpf::ST_B_IMM, "stb", Class::Store, Source::Immediate, 1, false, legacy_memory_classes),
+    op(ebpf::ST_H_IMM, "sth", Class::Store, Source::Immediate, 2, false, legacy_memory_classes),
+    op(ebpf::ST_W_IMM, "stw", Class::Store, Source::Immediate, 4, false, legacy_memory_classes),
+    op(ebpf::ST_DW_IMM, "stdw", Class::Store, Source::Immediate, 8, false, legacy_memory_classes),
+    op(ebpf::ST_B_REG, "stxb", Class::Store, Source::Register, 1, false, legacy_memory_classes),
+    op(ebpf::ST_H_REG, "stxh", Class::Store, Source::Register, 2, false, legacy_memory_classes),
+    op(ebpf::ST_W_REG, "stxw", Class::Store, Source::Register, 4, false, legacy_memory_classes),
+    op(ebpf::ST_DW_REG, "stxdw", Class::Store, Source::Register, 8, false, legacy_memory_classes),
+    op(ebpf::LD_1B_REG, "ldxb", Class::Load, Source::Register, 1, false, moved_memory_classes),
+    op(ebpf::LD_2B_REG, "ldxh", Class::Load, Source::Register, 2, false, moved_memory_classes),
+    op(ebpf::LD_4B_REG, "ldxw", Class::Load, Source::Register, 4, false, moved_memory_classes),
+    op(ebpf::LD_8B_REG, "ldxdw", Class::Load, Source::Register, 8, false, moved_memory_classes),
+    op(ebpf::ST_1B_IMM, "stb", Class::Store, Source::Immediate, 1, false, moved_memory_classes),
+    op(ebpf::ST_2B_IMM, "sth", Class::Store, Source::Immediate, 2, false, moved_memory_classes),
+    op(ebpf::ST_4B_IMM, "stw", Class::Store, Source::Immediate, 4, false, moved_memory_classes),
+    op(ebpf::ST_8B_IMM, "stdw", Class::Store, Source::Immediate, 8, false, moved_memory_classes),
+    op(ebpf::ST_1B_REG, "stxb", Class::Store, Source::Register, 1, false, moved_memory_classes),
+    op(ebpf::ST_2B_REG, "stxh", Class::Store, Source::Register, 2, false, moved_memory_classes),
+    op(ebpf::ST_4B_REG, "stxw", Class::Store, Source::Register, 4, false, moved_memory_classes),
+    op(ebpf::ST_8B_REG, "stxdw", Class::Store, Source::Register, 8, false, moved_memory_classes),
+    op(ebpf::ADD32_IMM, "add32", Class::Alu, Source::Immediate, 4, false, always),
+    op(ebpf::ADD32_REG, "add32", Class::Alu, Source::Register, 4, false, always),
+    op(ebpf::SUB32_IMM, "sub32", Class::Alu, Source::Immediate, 4, false, always),
+    op(ebpf::SUB32_REG, "sub32", Class::Alu, Source::Register, 4, false, always),
+    op(ebpf::MUL32_IMM, "mul32", Class::Alu, Source::Immediate, 4, false, without_pqr),
+    op(ebpf::MUL32_REG, "mul32", Class::Alu, Source::Register, 4, false, without_pqr),
+    op(ebpf::DIV32_IMM, "div32", Class::Alu, Source::Immediate, 4, false, without_pqr),
+    op(ebpf::DIV32_REG, "div32", Class::Alu, Source::Register, 4, false, without_pqr),
+    op(ebpf::OR32_IMM, "or32", Class::Alu, Source::Immediate, 4, false, always),
+    op(ebpf::OR32_REG, "or32", Class::Alu, Source::Register, 4, false, always),
+    op(ebpf::AND32_IMM, "and32", Class::Alu, Source::Immediate, 4, false, always),
+    op(ebpf::AND32_REG, "and32", Class::Alu, Source::Register, 4, false, always),
+    op(ebpf::LSH32_IMM, "lsh32", Class::Alu, Source::Immediate, 4, false, always),
+    op(ebpf::LSH32_REG, "lsh32", Class::Alu, Source::Register, 4, false, always),
+    op(ebpf::RSH32_IMM, "rsh32", Class::Alu, Source::Immediate, 4, false, always),
+    op(ebpf::RSH32_REG, "rsh32", Class::Alu, Source::Register, 4, false, always),
+    op(ebpf::MOD32_IMM, "mod32", Class::Alu, Source::Immediate, 4, false, without_pqr),
+    op(ebpf::MOD32_REG, "mod32", Class::Alu, Source::Register, 4, false, without_pqr),
+    op(ebpf::XOR32_IMM, "xor32", Class::Alu, Source::Immediate, 4, false, always),
+    op(ebpf::XOR32_REG, "xor32", Class::Alu, Source::Register, 4, false, always),
+    op(ebpf::MOV32_IMM, "mov32", Class::Alu, Source::Immediate, 4, false, always),
+    op(ebpf::MOV32_REG, "mov32", Class::Alu, Source::Register, 4, false, always),
+    op(ebpf::ARSH32_IMM, "arsh32", Class::Alu, Source::Immediate, 4, true, always),
+    op(ebpf::ARSH32_REG, "arsh32", Class::Alu, Source::Register, 4, true, always),
+    op(ebpf::NEG32, "neg32", Class::Alu, Source::None, 4, false, with_neg),
+    op(ebpf::ADD64_IMM, "add64", Class::Alu, Source::Immediate, 8, false, always),
+    op(ebpf::ADD64_REG, "add64", Class::Alu, Source::Register, 8, false, always),
+    op(ebpf::SUB64_IMM, "sub64", Class::Alu, Source::Immediate, 8, false, always),
+    op(ebpf::SUB64_REG, "sub64", Class::Alu, Source::Register, 8, false, always),
+    op(ebpf::MUL64_IMM, "mul64", Class::Alu, Source::Immediate, 8, false, without_pqr),
+    op(ebpf::MUL64_REG, "mul64", Class::Alu, Source::Register, 8, false, without_pqr),
+    op(ebpf::DIV64_IMM, "div64", Class::Alu, Source::Immediate, 8, false, without_pqr),
+    op(ebpf::DIV64_REG, "div64", Class::Alu, Source::Register, 8, false, without_pqr),
+    op(ebpf::OR64_IMM, "or64", Class::Alu, Source::Immediate, 8, false, always),
+    op(ebpf::OR64_REG, "or64", Class::Alu, Source::Register, 8, false, always),
+    op(ebpf::AND64_IMM, "and64", Class::Alu, Source::Immediate, 8, false, always),
+    op(ebpf::AND64_REG, "and64", Class::Alu, Source::Register, 8, false, always),
+    op(ebpf::LSH64_IMM, "lsh64", Class::Alu, Source::Immediate, 8, false, always),
+    op(ebpf::LSH64_REG, "lsh64", Class::Alu, Source::Register, 8, false, always),
+    op(ebpf::RSH64_IMM, "rsh64", Class::Alu, Source::Immediate, 8, false, always),
+    op(ebpf::RSH64_REG, "rsh64", Class::Alu, Source::Register, 8, false, always),
+    op(ebpf::MOD64_IMM, "mod64", Class::Alu, Source::Immediate, 8, false, without_pqr),
+    op(ebpf::MOD64_REG, "mod64", Class::Alu, Source::Register, 8, false, without_pqr),
+    op(ebpf::XOR64_IMM, "xor64", Class::Alu, Source::Immediate, 8, false, always),
+    op(ebpf::XOR64_REG, "xor64", Class::Alu, Source::Register, 8, false, always),
+    op(ebpf::MOV64_IMM, "mov64", Class::Alu, Source::Immediate, 8, false, always),
+    op(ebpf::MOV64_REG, "mov64", Class::Alu, Source::Register, 8, false, always),
+    op(ebpf::ARSH64_IMM, "arsh64", Class::Alu, Source::Immediate, 8, true, always),
+    op(ebpf::ARSH64_REG, "arsh64", Class::Alu, Source::Register, 8, true, always),
+    op(ebpf::NEG64, "neg64", Class::Alu, Source::None, 8, false, with_neg),
+    op(ebpf::LE, "le", Class::Alu, Source::Immediate, 8, false, with_le),
+    op(ebpf::BE, "be", Class::Alu, Source::Immediate, 8, false, always),
+    op(ebpf::HOR64_IMM, "hor64", Class::Alu, Source::Immediate, 8, false, without_lddw),
+    op(ebpf::LMUL32_IMM, "lmul32", Class::Product, Source::Immediate, 4, false, with_pqr),
+    op(ebpf::LMUL32_REG, "lmul32", Class::Product, Source::Register, 4, false, with_pqr),
+    op(ebpf::LMUL64_IMM, "lmul64", Class::Product, Source::Immediate, 8, false, with_pqr),
+    op(ebpf::LMUL64_REG, "lmul64", Class::Product, Source::Register, 8, false, with_pqr),
+    op(ebpf::UHMUL64_IMM, "uhmul64", Class::Product, Source::Immediate, 8, false, with_pqr),
+    op(ebpf::UHMUL64_REG, "uhmul64", Class::Product, Source::Register, 8, false, with_pqr),
+    op(ebpf::SHMUL64_IMM, "shmul64", Class::Product, Source::Immediate, 8, true, with_pqr),
+    op(ebpf::SHMUL64_REG, "shmul64", Class::Product, Source::Register, 8, true, with_pqr),
+    op(ebpf::UDIV32_IMM, "udiv32", Class::Product, Source::Immediate, 4, false, with_pqr),
+    op(ebpf::UDIV32_REG, "udiv32", Class::Product, Source::Register, 4, false, with_pqr),
+    op(ebpf::UDIV64_IMM, "udiv64", Class::Product, Source::Immediate, 8, false, with_pqr),
+    op(ebpf::UDIV64_REG, "udiv64", Class::Product, Source::Register, 8, false, with_pqr),
+    op(ebpf::UREM32_IMM, "urem32", Class::Product, Source::Immediate, 4, false, with_pqr),
+    op(ebpf::UREM32_REG, "urem32", Class::Product, Source::Register, 4, false, with_pqr),
+    op(ebpf::UREM64_IMM, "urem64", Class::Product, Source::Immediate, 8, false, with_pqr),
+    op(ebpf::UREM64_REG, "urem64", Class::Product, Source::Register, 8, false, with_pqr),
+    op(ebpf::SDIV32_IMM, "sdiv32", Class::Product, Source::Immediate, 4, true, with_pqr),
+    op(ebpf::SDIV32_REG, "sdiv32", Class::Product, Source::Register, 4, true, with_pqr),
+    op(ebpf::SDIV64_IMM, "sdiv64", Class::Product, Source::Immediate, 8, true, with_pqr),
+    op(ebpf::SDIV64_REG, "sdiv64", Class::Product, Source::Register, 8, true, with_pqr),
+    op(ebpf::SREM32_IMM, "srem32", Class::Product, Source::Immediate, 4, true, with_pqr),
+    op(ebpf::SREM32_REG, "srem32", Class::Product, Source::Register, 4, true, with_pqr),
+    op(ebpf::SREM64_IMM, "srem64", Class::Product, Source::Immediate, 8, true, with_pqr),
+    op(ebpf::SREM64_REG, "srem64", Class::Product, Source::Register, 8, true, with_pqr),
+    op(ebpf::JA, "ja", Class::Jump, Source::None, 8, false, always),
+    op(ebpf::JEQ_IMM, "jeq", Class::ConditionalJump, Source::Immediate, 8, false, always),
+    op(ebpf::JEQ_REG, "jeq", Class::ConditionalJump, Source::Register, 8, false, always),
+    op(ebpf::JGT_IMM, "jgt", Class::ConditionalJump, Source::Immediate, 8, false, always),
+    op(ebpf::JGT_REG, "jgt", Class::ConditionalJump, Source::Register, 8, false, always),
+    op(ebpf::JGE_IMM, "jge", Class::ConditionalJump, Source::Immediate, 8, false, always),
+    op(ebpf::JGE_REG, "jge", Class::ConditionalJump, Source::Register, 8, false, always),
+    op(ebpf::JLT_IMM, "jlt", Class::ConditionalJump, Source::Immediate, 8, false, always),
+    op(ebpf::JLT_REG, "jlt", Class::ConditionalJump, Source::Register, 8, false, always),
+    op(ebpf::JLE_IMM, "jle", Class::ConditionalJump, Source::Immediate, 8, false, always),
+    op(ebpf::JLE_REG, "jle", Class::ConditionalJump, Source::Register, 8, false, always),
+    op(ebpf::JSET_IMM, "jset", Class::ConditionalJump, Source::Immediate, 8, false, always),
+    op(ebpf::JSET_REG, "jset", Class::ConditionalJump, Source::Register, 8, false, always),
+    op(ebpf::JNE_IMM, "jne", Class::ConditionalJump, Source::Immediate, 8, false, always),
+    op(ebpf::JNE_REG, "jne", Class::ConditionalJump, Source::Register, 8, false, always),
+    op(ebpf::JSGT_IMM, "jsgt", Class::ConditionalJump, Source::Immediate, 8, true, always),
+    op(ebpf::JSGT_REG, "jsgt", Class::ConditionalJump, Source::Register, 8, true, always),
+    op(ebpf::JSGE_IMM, "jsge", Class::ConditionalJump, Source::Immediate, 8, true, always),
+    op(ebpf::JSGE_REG, "jsge", Class::ConditionalJump, Source::Register, 8, true, always),
+    op(ebpf::JSLT_IMM, "jslt", Class::ConditionalJump, Source::Immediate, 8, true, always),
+    op(ebpf::JSLT_REG, "jslt", Class::ConditionalJump, Source::Register, 8, true, always),
+    op(ebpf::JSLE_IMM, "jsle", Class::ConditionalJump, Source::Immediate, 8, true, always),
+    op(ebpf::JSLE_REG, "jsle", Class::ConditionalJump, Source::Register, 8, true, always),
+    op(ebpf::CALL_IMM, "call", Class::Call, Source::Immediate, 8, false, always),
+    op(ebpf::CALL_REG, "callx", Class::Call, Source::Register, 8, false, always),
+    op(ebpf::EXIT, "exit", Class::Exit, Source::None, 8, false, dynamic_syscalls),
+    op(ebpf::RETURN, "return", Class::Exit, Source::None, 8, false, static_syscalls),
+    op(ebpf::SYSCALL, "syscall", Class::Syscall, Source::Immediate, 8, false, static_syscalls),
+];
+
+/// Looks up the instruction an opcode encodes in the given version
+pub fn opcode_info(opcode: u8, sbpf_version: SBPFVersion) -> Option<&'static OpcodeInfo> {
+    OPCODE_TABLE
+        .iter()
+        .find(|info| info.opcode == opcode && (info.available)(sbpf_version))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VERSIONS: [SBPFVersion; 5] = [
+        SBPFVersion::V0,
+        SBPFVersion::V1,
+        SBPFVersion::V2,
+        SBPFVersion::V3,
+        SBPFVersion::V4,
+    ];
+
+    #[test]
+    fn test_opcodes_are_unambiguous() {
+        for sbpf_version in VERSIONS {
+            for opcode in 0..=u8::MAX {
+                let matches = OPCODE_TABLE
+                    .iter()
+                    .filter(|info| info.opcode == opcode && (info.available)(sbpf_version))
+                    .count();
+                assert!(
+                    matches <= 1,
+                    "{:#x} is ambiguous in {:?}",
+                    opcode,
+                    sbpf_version
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_memory_access_widths() {
+        for info in OPCODE_TABLE.iter().filter(|info| info.is_memory_access()) {
+            assert!(
+                (1..=8).contains(&info.width),
+                "{} ({:#x}) accesses {} bytes",
+                info.mnemonic,
+                info.opcode,
+                info.width
+            );
+            assert_eq!(
+                info.value_mask().count_ones(),
+                u32::from(info.width) * 8,
+                "{} ({:#x})",
+                info.mnemonic,
+                info.opcode
+            );
+        }
+        let info = |width| op(0, "", Class::Load, Source::None, width, false, always);
+        assert_eq!(info(0).value_mask(), 0);
+        assert_eq!(info(1).value_mask(), 0xFF);
+        assert_eq!(info(8).value_mask(), u64::MAX);
+        assert_eq!(info(16).value_mask(), u64::MAX);
+    }
+}
@@ -0,0 +1,141 @@
+//! Deterministic randomness for builtins
+//!
+//! Every [EbpfVm] owns a [Prng], seeded from [Config::rng_seed]. Builtin functions which need
+//! randomness, e.g. [SyscallRandomBytes], draw from it instead of the OS, so fuzzing runs with
+//! the same seed are reproducible bit for bit. Without a seed every VM draws one at random.
+//! Components with their own generator, like the
+//! [PolicyFaultInjector](crate::fault_injection::PolicyFaultInjector), can be seeded from
+//! [Prng::next_u64] to stay reproducible as well. The JIT is diversified independently, for
+//! fuzzing it can be pinned with `Config::diversification_seed`.
+
+use crate::{
+    elf::ElfError,
+    error::{EbpfError, ProgramResult},
+    memory_region::AccessType,
+    program::BuiltinProgram,
+    vm::{get_runtime_environment_key, Config, ContextObject, EbpfVm},
+};
+use std::hash::{BuildHasher, Hasher};
+
+/// Seedable pseudo random number generator (SplitMix64)
+///
+/// Not cryptographically secure, the output only depends on the seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prng {
+    state: u64,
+}
+
+impl Prng {
+    /// Creates a generator whose output only depends on `seed`
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Creates a generator with the seed of `config`, or a random one if it has none
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.rng_seed.unwrap_or_else(|| {
+            std::collections::hash_map::RandomState::new()
+                .build_hasher()
+                .finish()
+        }))
+    }
+
+    /// Next 64 random bits
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = self.state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        value ^ (value >> 31)
+    }
+
+    /// Random value below `bound`, `None` if `bound` is 0
+    pub fn next_below(&mut self, bound: u64) -> Option<u64> {
+        self.next_u64().checked_rem(bound)
+    }
+
+    /// Fills `bytes` with random bytes
+    pub fn fill_bytes(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let random = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+    }
+}
+
+/// Compute units charged for a call of [SyscallRandomBytes], at least
+pub const RANDOM_BYTES_BASE_COST: u64 = 10;
+/// Bytes [SyscallRandomBytes] fills per compute unit, once they exceed the base cost
+pub const RANDOM_BYTES_PER_UNIT: u64 = 250;
+
+/// Compute units charged for filling `len` bytes, priced like the memory syscalls of Solana
+pub fn random_bytes_cost(len: u64) -> u64 {
+    RANDOM_BYTES_BASE_COST.max(len.saturating_div(RANDOM_BYTES_PER_UNIT))
+}
+
+/// Registers [SyscallRandomBytes] as `sol_random_bytes`
+pub fn register_random_syscalls<C: ContextObject>(
+    loader: &mut BuiltinProgram<C>,
+) -> Result<(), ElfError> {
+    loader.register_function("sol_random_bytes", SyscallRandomBytes::vm::<C>)
+}
+
+/// `sol_random_bytes(addr, len)`, fills the memory at `addr` from the [Prng] of the VM
+///
+/// Charges [random_bytes_cost] compute units, and fails with
+/// [EbpfError::ExceededMaxInstructions] without filling any byte if they exceed the budget.
+pub struct SyscallRandomBytes {}
+
+impl SyscallRandomBytes {
+    /// VM interface
+    ///
+    /// Implemented without [declare_builtin_function](crate::declare_builtin_function), as
+    /// the generator is not reachable from its Rust interface.
+    pub fn vm<C: ContextObject>(
+        vm: *mut EbpfVm<C>,
+        addr: u64,
+        len: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+    ) {
+        let vm = unsafe {
+            &mut *(vm
+                .cast::<u64>()
+                .offset((get_runtime_environment_key() as isize).wrapping_neg())
+                .cast::<EbpfVm<C>>())
+        };
+        let config = vm.loader.get_config();
+        if config.enable_instruction_meter {
+            vm.context_object_pointer.consume(
+                vm.previous_instruction_meter
+                    .saturating_sub(vm.due_insn_count),
+            );
+        }
+        if config.enable_instruction_meter {
+            let cost = random_bytes_cost(len);
+            let exceeded = vm.context_object_pointer.get_remaining() < cost;
+            vm.context_object_pointer.consume(cost);
+            if exceeded {
+                vm.program_result = ProgramResult::Err(EbpfError::ExceededMaxInstructions);
+                vm.previous_instruction_meter = vm.context_object_pointer.get_remaining();
+                return;
+            }
+        }
+        let host_addr: Result<u64, EbpfError> =
+            vm.memory_mapping.map(AccessType::Store, addr, len).into();
+        vm.program_result = match host_addr {
+            Ok(host_addr) => {
+                // The whole range was mapped for writing
+                let bytes =
+                    unsafe { std::slice::from_raw_parts_mut(host_addr as *mut u8, len as usize) };
+                vm.rng.fill_bytes(bytes);
+                ProgramResult::Ok(0)
+            }
+            Err(error) => ProgramResult::Err(error),
+        };
+        if config.enable_instruction_meter {
+            vm.previous_instruction_meter = vm.context_object_pointer.get_remaining();
+        }
+    }
+}
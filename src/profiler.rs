@@ -0,0 +1,185 @@
+//! Instruction level profiler of the interpreter
+//!
+//! When [crate::vm::EbpfVm::profiler] is set, the interpreter counts how often every
+//! instruction is executed and how many host cycles it took until the next instruction
+//! was dispatched. The cycles are also accumulated in the stop watch of the VM.
+//! Together with an [Analysis] the counters are aggregated into basic blocks and functions,
+//! so that the hot spots of a program can be found by their symbol names.
+
+use crate::static_analysis::Analysis;
+
+/// Execution count and host cycles
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileCounter {
+    /// Number of executions
+    pub count: u64,
+    /// Host cycles spent
+    pub cycles: u64,
+}
+
+impl ProfileCounter {
+    fn add(&mut self, other: ProfileCounter) {
+        self.count = self.count.saturating_add(other.count);
+        self.cycles = self.cycles.saturating_add(other.cycles);
+    }
+}
+
+/// Counters of a basic block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlockProfile {
+    /// Pc of the first instruction of the basic block
+    pub pc: usize,
+    /// Name of the function the basic block belongs to, if known
+    pub function: Option<String>,
+    /// Summed over all instructions of the basic block
+    pub counter: ProfileCounter,
+}
+
+/// Per pc and per opcode counters
+#[derive(Debug, Clone)]
+pub struct InstructionProfiler {
+    /// Indexed by pc, grows on demand
+    per_pc: Vec<ProfileCounter>,
+    /// Indexed by opcode
+    per_opcode: Vec<ProfileCounter>,
+    /// Pc, opcode and start timestamp of the instruction being executed
+    pending: Option<(u64, u8, u64)>,
+}
+
+impl Default for InstructionProfiler {
+    fn default() -> Self {
+        Self {
+            per_pc: Vec::new(),
+            per_opcode: vec![ProfileCounter::default(); 256],
+            pending: None,
+        }
+    }
+}
+
+impl InstructionProfiler {
+    /// Records the dispatch of an instruction
+    ///
+    /// Returns the cycles spent on the previous instruction of the same execution.
+    pub fn record_dispatch(&mut self, pc: u64, opcode: u8) -> Option<u64> {
+        let now = timestamp();
+        let cycles = self.finish_pending(now);
+        self.pending = Some((pc, opcode, now));
+        cycles
+    }
+
+    /// Attributes the remaining cycles to the last instruction of an execution
+    pub fn end_of_execution(&mut self) -> Option<u64> {
+        self.finish_pending(timestamp())
+    }
+
+    fn finish_pending(&mut self, now: u64) -> Option<u64> {
+        let (pc, opcode, start) = self.pending.take()?;
+        let counter = ProfileCounter {
+            count: 1,
+            cycles: now.saturating_sub(start),
+        };
+        if self.per_pc.len() <= pc as usize {
+            self.per_pc
+                .resize((pc as usize).saturating_add(1), ProfileCounter::default());
+        }
+        self.per_pc[pc as usize].add(counter);
+        self.per_opcode[opcode as usize].add(counter);
+        Some(counter.cycles)
+    }
+
+    /// Counters of the instruction at `pc`
+    pub fn pc_counter(&self, pc: u64) -> ProfileCounter {
+        self.per_pc.get(pc as usize).copied().unwrap_or_default()
+    }
+
+    /// Counters of all instructions with the given opcode
+    pub fn opcode_counter(&self, opcode: u8) -> ProfileCounter {
+        self.per_opcode[opcode as usize]
+    }
+
+    /// All executed (pc, counter) pairs in ascending pc order
+    pub fn pc_counters(&self) -> impl Iterator<Item = (u64, ProfileCounter)> + '_ {
+        self.per_pc
+            .iter()
+            .enumerate()
+            .filter(|(_pc, counter)| counter.count > 0)
+            .map(|(pc, counter)| (pc as u64, *counter))
+    }
+
+    /// Aggregates the counters into the basic blocks of `analysis`, hottest (most cycles) first
+    pub fn hot_basic_blocks(&self, analysis: &Analysis) -> Vec<BasicBlockProfile> {
+        let mut basic_blocks = std::collections::BTreeMap::<usize, ProfileCounter>::new();
+        for (pc, counter) in self.pc_counters() {
+            if let Some((block_pc, _cfg_node)) =
+                analysis.cfg_nodes.range(..=pc as usize).next_back()
+            {
+                basic_blocks.entry(*block_pc).or_default().add(counter);
+            }
+        }
+        let mut result = basic_blocks
+            .into_iter()
+            .map(|(pc, counter)| BasicBlockProfile {
+                pc,
+                function: analysis
+                    .functions
+                    .range(..=pc)
+                    .next_back()
+                    .map(|(_function_pc, (_key, name))| name.clone()),
+                counter,
+            })
+            .collect::<Vec<_>>();
+        result.sort_by(|a, b| {
+            b.counter
+                .cycles
+                .cmp(&a.counter.cycles)
+                .then(b.counter.count.cmp(&a.counter.count))
+                .then(a.pc.cmp(&b.pc))
+        });
+        result
+    }
+
+    /// Writes the `limit` hottest basic blocks in a human readable form
+    pub fn write_report<W: std::io::Write>(
+        &self,
+        output: &mut W,
+        analysis: &Analysis,
+        limit: usize,
+    ) -> std::io::Result<()> {
+        writeln!(output, "{:>12} {:>12}  basic block", "cycles", "count")?;
+        for basic_block in self.hot_basic_blocks(analysis).iter().take(limit) {
+            writeln!(
+                output,
+                "{:>12} {:>12}  {} (pc {})",
+                basic_block.counter.cycles,
+                basic_block.counter.count,
+                basic_block.function.as_deref().unwrap_or("?"),
+                basic_block.pc,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Resets all counters
+    pub fn reset(&mut self) {
+        self.per_pc.clear();
+        self.per_opcode
+            .iter_mut()
+            .for_each(|counter| *counter = ProfileCounter::default());
+        self.pending = None;
+    }
+}
+
+/// Host cycle counter
+#[cfg(target_arch = "x86_64")]
+#[allow(unused_unsafe)]
+fn timestamp() -> u64 {
+    unsafe { std::arch::x86_64::_rdtsc() }
+}
+
+/// Nanoseconds since the first call, on hosts without a cycle counter
+#[cfg(not(target_arch = "x86_64"))]
+fn timestamp() -> u64 {
+    use std::{sync::OnceLock, time::Instant};
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
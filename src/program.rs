@@ -356,6 +356,7 @@ macro_rules! declare_builtin_function {
                 $arg_d: u64,
                 $arg_e: u64,
             ) {
+                #[allow(unused_imports)]
                 use $crate::vm::ContextObject;
                 let vm = unsafe {
                     &mut *($vm.cast::<u64>().offset(-($crate::vm::get_runtime_environment_key() as isize)).cast::<$crate::vm::EbpfVm<$ContextObject>>())
@@ -0,0 +1,463 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Fuzzing of the VM by mutating programs
+//!
+//! Instead of the input, the [ProgramMutator] mutates the text section of a program:
+//! It replaces opcodes by others of the same class, and changes registers, immediates
+//! and offsets, keeping only mutations which still pass the verifier. Every mutant runs
+//! in the interpreter and the JIT, and any difference between them is a fault of the VM.
+//! Faults are reported as [ReproductionBundle]s which contain everything to reproduce them.
+
+use crate::{
+    ebpf,
+    elf::Executable,
+    error::EbpfError,
+    harness::execute_with_input,
+    opcode_table::{opcode_info, InstructionClass, OPCODE_TABLE},
+    program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
+    redaction::Redaction,
+    verifier::RequisiteVerifier,
+    vm::ContextObject,
+};
+use std::{path::Path, sync::Arc};
+
+/// Immediates which tend to hit edge cases
+const INTERESTING_IMMEDIATES: [i64; 10] = [
+    0,
+    1,
+    -1,
+    7,
+    31,
+    32,
+    63,
+    64,
+    i32::MIN as i64,
+    i32::MAX as i64,
+];
+
+/// A single change of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgramMutation {
+    /// Replaced the opcode by another one of the same class
+    Opcode {
+        /// Instruction index
+        pc: usize,
+        /// New opcode
+        opcode: u8,
+    },
+    /// Replaced the destination register
+    DestinationRegister {
+        /// Instruction index
+        pc: usize,
+        /// New register
+        register: u8,
+    },
+    /// Replaced the source register
+    SourceRegister {
+        /// Instruction index
+        pc: usize,
+        /// New register
+        register: u8,
+    },
+    /// Replaced the immediate
+    Immediate {
+        /// Instruction index
+        pc: usize,
+        /// New immediate
+        imm: i64,
+    },
+    /// Replaced the offset
+    Offset {
+        /// Instruction index
+        pc: usize,
+        /// New offset
+        off: i16,
+    },
+}
+
+/// Everything needed to reproduce a fault found by program mutation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReproductionBundle {
+    /// Version the program was verified and executed in
+    pub sbpf_version: SBPFVersion,
+    /// (key, pc) of the registered functions
+    pub functions: Vec<(u32, usize)>,
+    /// Mutated text section
+    pub text: Vec<u8>,
+    /// Input the program was executed with
+    pub input: Vec<u8>,
+    /// Mutations which turned the original program into `text`
+    pub mutations: Vec<ProgramMutation>,
+    /// Describes the fault
+    pub fault: String,
+}
+
+/// Magic number of serialized [ReproductionBundle]s
+const BUNDLE_MAGIC: &[u8; 8] = b"SBPFREPR";
+
+impl ReproductionBundle {
+    /// Serializes the bundle, the mutations are not included
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = BUNDLE_MAGIC.to_vec();
+        bytes.push(self.sbpf_version as u8);
+        bytes.extend_from_slice(&(self.functions.len() as u64).to_le_bytes());
+        for (key, pc) in self.functions.iter() {
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.extend_from_slice(&(*pc as u64).to_le_bytes());
+        }
+        for section in [&self.text, &self.input, self.fault.as_bytes()] {
+            bytes.extend_from_slice(&(section.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(section);
+        }
+        bytes
+    }
+
+    /// Deserializes a bundle written by [Self::to_bytes]
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        fn take<'a>(reader: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            let head = reader.get(..len)?;
+            *reader = &reader[len..];
+            Some(head)
+        }
+        fn read_u64(reader: &mut &[u8]) -> Option<u64> {
+            let mut value = [0u8; 8];
+            value.copy_from_slice(take(reader, 8)?);
+            Some(u64::from_le_bytes(value))
+        }
+        let mut reader = bytes.strip_prefix(BUNDLE_MAGIC)?;
+        let sbpf_version = match take(&mut reader, 1)?[0] {
+            0 => SBPFVersion::V0,
+            1 => SBPFVersion::V1,
+            2 => SBPFVersion::V2,
+            3 => SBPFVersion::V3,
+            4 => SBPFVersion::V4,
+            _ => return None,
+        };
+        let function_count = read_u64(&mut reader)?;
+        let mut functions = Vec::new();
+        for _ in 0..function_count {
+            let mut key = [0u8; 4];
+            key.copy_from_slice(take(&mut reader, 4)?);
+            functions.push((u32::from_le_bytes(key), read_u64(&mut reader)? as usize));
+        }
+        let mut sections = Vec::new();
+        for _ in 0..3 {
+            let len = read_u64(&mut reader)? as usize;
+            sections.push(take(&mut reader, len)?.to_vec());
+        }
+        let fault = String::from_utf8(sections.pop()?).ok()?;
+        let input = sections.pop()?;
+        let text = sections.pop()?;
+        Some(Self {
+            sbpf_version,
+            functions,
+            text,
+            input,
+            mutations: Vec::new(),
+            fault,
+        })
+    }
+
+    /// Replaces the sensitive parts of the input, see [Redaction::input]
+    ///
+    /// The fault may no longer reproduce if it depends on the replaced bytes.
+    pub fn redact(&mut self, redaction: &Redaction) {
+        redaction.input(&mut self.input);
+    }
+
+    /// Writes the serialized bundle to a file
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+
+    /// Reads a bundle written by [Self::save]
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid reproduction bundle",
+            )
+        })
+    }
+
+    /// Loads and verifies the mutated program
+    pub fn executable<C: ContextObject>(
+        &self,
+        loader: Arc<BuiltinProgram<C>>,
+    ) -> Result<Executable<C>, EbpfError> {
+        build_executable(&self.text, loader, self.sbpf_version, &self.functions)
+    }
+}
+
+fn build_executable<C: ContextObject>(
+    text: &[u8],
+    loader: Arc<BuiltinProgram<C>>,
+    sbpf_version: SBPFVersion,
+    functions: &[(u32, usize)],
+) -> Result<Executable<C>, EbpfError> {
+    let mut function_registry = FunctionRegistry::default();
+    for (key, pc) in functions.iter() {
+        function_registry
+            .register_function(*key, format!("function_{pc}"), *pc)
+            .map_err(EbpfError::ElfError)?;
+    }
+    let executable = Executable::new_from_text_bytes(text, loader, sbpf_version, function_registry)
+        .map_err(EbpfError::ElfError)?;
+    executable.verify::<RequisiteVerifier>()?;
+    Ok(executable)
+}
+
+/// Executes a verified program in the interpreter and the JIT and describes their difference
+///
+/// Without the JIT there is nothing to compare against and no fault is reported.
+#[allow(unused_variables, unused_mut)]
+pub fn check_program<C: ContextObject + Clone>(
+    executable: &mut Executable<C>,
+    context_object: &C,
+    input: &[u8],
+) -> Option<String> {
+    let registers = [(1, ebpf::MM_INPUT_START)];
+    let mut interpreter_context_object = context_object.clone();
+    let mut interpreter_input = input.to_vec();
+    let interpreter_result = execute_with_input(
+        executable,
+        &mut interpreter_context_object,
+        &registers,
+        &mut interpreter_input,
+        true,
+    );
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    {
+        if let Err(error) = executable.jit_compile() {
+            return Some(format!("verified program failed to compile: {error:?}"));
+        }
+        let mut jit_context_object = context_object.clone();
+        let mut jit_input = input.to_vec();
+        let jit_result = execute_with_input(
+            executable,
+            &mut jit_context_object,
+            &registers,
+            &mut jit_input,
+            false,
+        );
+        let interpreter_result = format!("{interpreter_result:?}");
+        let jit_result = format!("{jit_result:?}");
+        if interpreter_result != jit_result {
+            return Some(format!(
+                "interpreter returned {interpreter_result} but JIT returned {jit_result}"
+            ));
+        }
+        if interpreter_context_object.get_remaining() != jit_context_object.get_remaining() {
+            return Some(format!(
+                "interpreter left {} instructions but JIT left {}",
+                interpreter_context_object.get_remaining(),
+                jit_context_object.get_remaining()
+            ));
+        }
+        if interpreter_input != jit_input {
+            return Some("interpreter and JIT left different input regions".to_string());
+        }
+    }
+    None
+}
+
+/// Mutates a program while keeping it verifiable
+pub struct ProgramMutator<C: ContextObject> {
+    loader: Arc<BuiltinProgram<C>>,
+    sbpf_version: SBPFVersion,
+    /// (key, pc) of the functions of the original program
+    functions: Vec<(u32, usize)>,
+    /// State of the xorshift generator
+    rng_state: u64,
+    /// Attempts to find a verifiable mutant before giving up
+    pub max_attempts: usize,
+    /// Mutations applied on top of each other per mutant
+    pub max_stacked_mutations: usize,
+}
+
+impl<C: ContextObject> ProgramMutator<C> {
+    /// Creates a mutator for the program of `executable`, `seed` makes it reproducible
+    pub fn new(executable: &Executable<C>, seed: u64) -> Self {
+        Self {
+            loader: executable.get_loader().clone(),
+            sbpf_version: executable.get_sbpf_version(),
+            functions: executable
+                .get_function_registry()
+                .iter()
+                .map(|(key, (_name, pc))| (key, pc))
+                .collect(),
+            rng_state: seed.max(1),
+            max_attempts: 64,
+            max_stacked_mutations: 3,
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        self.rng_state
+    }
+
+    fn choose<T: Copy>(&mut self, options: &[T]) -> Option<T> {
+        let index = self.next_random().checked_rem(options.len() as u64)?;
+        options.get(index as usize).copied()
+    }
+
+    /// Picks a mutation of the instruction at `pc`
+    fn mutation_of(&mut self, pc: usize, insn: &ebpf::Insn) -> Option<ProgramMutation> {
+        let info = opcode_info(insn.opc, self.sbpf_version)?;
+        let mutable = matches!(
+            info.class,
+            InstructionClass::Alu
+                | InstructionClass::Product
+                | InstructionClass::Load
+                | InstructionClass::Store
+                | InstructionClass::Jump
+                | InstructionClass::ConditionalJump
+        );
+        // Keep the function start markers of SBPFv3
+        if !mutable || (insn.opc == ebpf::ADD64_IMM && insn.dst == ebpf::FRAME_PTR_REG as u8) {
+            return None;
+        }
+        Some(match self.next_random() % 5 {
+            0 => {
+                let sbpf_version = self.sbpf_version;
+                let alternatives = OPCODE_TABLE
+                    .iter()
+                    .filter(|other| {
+                        other.class == info.class
+                            && other.opcode != insn.opc
+                            && (other.available)(sbpf_version)
+                    })
+                    .map(|other| other.opcode)
+                    .collect::<Vec<_>>();
+                ProgramMutation::Opcode {
+                    pc,
+                    opcode: self.choose(&alternatives)?,
+                }
+            }
+            1 => ProgramMutation::DestinationRegister {
+                pc,
+                register: (self.next_random() % 11) as u8,
+            },
+            2 => ProgramMutation::SourceRegister {
+                pc,
+                register: (self.next_random() % 11) as u8,
+            },
+            3 => ProgramMutation::Immediate {
+                pc,
+                imm: if self.next_random() & 1 == 0 {
+                    self.choose(&INTERESTING_IMMEDIATES)?
+                } else {
+                    insn.imm ^ (1 << (self.next_random() % 32))
+                },
+            },
+            _ => ProgramMutation::Offset {
+                pc,
+                off: insn
+                    .off
+                    .wrapping_add((self.next_random() % 9) as i16)
+                    .wrapping_sub(4),
+            },
+        })
+    }
+
+    fn apply(text: &mut [u8], mutation: ProgramMutation) {
+        let (pc, offset, bytes) = match mutation {
+            ProgramMutation::Opcode { pc, opcode } => (pc, 0, vec![opcode]),
+            ProgramMutation::DestinationRegister { pc, register } => (
+                pc,
+                1,
+                vec![(text[pc * ebpf::INSN_SIZE + 1] & 0xf0) | register],
+            ),
+            ProgramMutation::SourceRegister { pc, register } => (
+                pc,
+                1,
+                vec![(text[pc * ebpf::INSN_SIZE + 1] & 0x0f) | (register << 4)],
+            ),
+            ProgramMutation::Offset { pc, off } => (pc, 2, off.to_le_bytes().to_vec()),
+            ProgramMutation::Immediate { pc, imm } => (pc, 4, (imm as i32).to_le_bytes().to_vec()),
+        };
+        let start = pc * ebpf::INSN_SIZE + offset;
+        text[start..start + bytes.len()].copy_from_slice(&bytes);
+    }
+
+    /// Applies up to [Self::max_stacked_mutations] mutations to `text`
+    ///
+    /// Returns the mutant and the mutations applied, or `None` if no verifiable mutant was found.
+    pub fn mutate(&mut self, text: &[u8]) -> Option<(Vec<u8>, Vec<ProgramMutation>)> {
+        let instructions = text.len() / ebpf::INSN_SIZE;
+        for _attempt in 0..self.max_attempts {
+            let mut mutant = text.to_vec();
+            let mut mutations = Vec::new();
+            let stacked = 1 + self.next_random() % self.max_stacked_mutations.max(1) as u64;
+            for _ in 0..stacked {
+                let pc = self.next_random().checked_rem(instructions as u64)? as usize;
+                let insn = ebpf::get_insn_unchecked(&mutant, pc);
+                // Do not split the immediate of lddw
+                if pc > 0 && ebpf::get_insn_unchecked(&mutant, pc - 1).opc == ebpf::LD_DW_IMM {
+                    continue;
+                }
+                if let Some(mutation) = self.mutation_of(pc, &insn) {
+                    let before = mutant.clone();
+                    Self::apply(&mut mutant, mutation);
+                    if mutant != before {
+                        mutations.push(mutation);
+                    }
+                }
+            }
+            if !mutations.is_empty()
+                && build_executable(
+                    &mutant,
+                    self.loader.clone(),
+                    self.sbpf_version,
+                    &self.functions,
+                )
+                .is_ok()
+            {
+                return Some((mutant, mutations));
+            }
+        }
+        None
+    }
+
+    /// Executes `iterations` mutants of `text` and bundles every fault found
+    ///
+    /// Mutants may loop forever, so `context_object` must limit the instructions.
+    pub fn fuzz(
+        &mut self,
+        text: &[u8],
+        input: &[u8],
+        context_object: &C,
+        iterations: usize,
+    ) -> Vec<ReproductionBundle>
+    where
+        C: Clone,
+    {
+        let mut bundles = Vec::new();
+        for _iteration in 0..iterations {
+            let Some((mutant, mutations)) = self.mutate(text) else {
+                continue;
+            };
+            let Ok(mut executable) = build_executable(
+                &mutant,
+                self.loader.clone(),
+                self.sbpf_version,
+                &self.functions,
+            ) else {
+                continue;
+            };
+            if let Some(fault) = check_program(&mut executable, context_object, input) {
+                bundles.push(ReproductionBundle {
+                    sbpf_version: self.sbpf_version,
+                    functions: self.functions.clone(),
+                    text: mutant,
+                    input: input.to_vec(),
+                    mutations,
+                    fault,
+                });
+            }
+        }
+        bundles
+    }
+}
@@ -0,0 +1,117 @@
+//! Progress of meter bounded executions
+//!
+//! Programs which exhaust the instruction meter on most inputs rarely reach new edges,
+//! so coverage alone can not tell which inputs are worth mutating further. A
+//! [ProgressTracker] additionally rewards inputs which reach a deeper call tree than any
+//! input before and inputs which cover more distinct edges within their budget.
+
+use crate::{
+    ebpf,
+    error::{EbpfError, ProgramResult},
+    static_analysis::{Analysis, TraceLogEntry},
+};
+use std::collections::BTreeSet;
+
+/// Weight of an edge no previous run covered
+const NEW_EDGE_WEIGHT: u64 = 1024;
+/// Weight of a call depth level no previous run reached
+const NEW_CALL_DEPTH_WEIGHT: u64 = 64;
+
+/// Progress of a single run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunProgress {
+    /// Edges between basic blocks which no previous run covered
+    pub new_edges: usize,
+    /// Distinct edges between basic blocks covered by this run
+    pub covered_edges: usize,
+    /// Deepest call depth reached, 0 being the entrypoint
+    pub max_call_depth: usize,
+    /// Levels by which the call depth exceeds the deepest of all previous runs
+    pub new_call_depth: usize,
+    /// Whether the run ended by exhausting the instruction meter
+    pub meter_exhausted: bool,
+}
+
+impl RunProgress {
+    /// Combined metric, higher is better
+    ///
+    /// New edges dominate new call depth, which dominates the edges covered by the run.
+    pub fn score(&self) -> u64 {
+        (self.new_edges as u64)
+            .saturating_mul(NEW_EDGE_WEIGHT)
+            .saturating_add((self.new_call_depth as u64).saturating_mul(NEW_CALL_DEPTH_WEIGHT))
+            .saturating_add(self.covered_edges as u64)
+    }
+}
+
+/// Accumulates progress over the runs of a campaign
+#[derive(Debug, Clone, Default)]
+pub struct ProgressTracker {
+    /// Edges covered by any run so far
+    seen_edges: BTreeSet<(usize, usize)>,
+    /// Deepest call depth of any run so far
+    max_call_depth: usize,
+}
+
+impl ProgressTracker {
+    /// Evaluates a run from its trace log and result, and adds it to the campaign
+    pub fn evaluate(
+        &mut self,
+        analysis: &Analysis,
+        trace_log: &[TraceLogEntry],
+        result: &ProgramResult,
+    ) -> RunProgress {
+        let mut covered_edges = BTreeSet::new();
+        let mut last_basic_block = usize::MAX;
+        let mut call_depth = 0usize;
+        let mut max_call_depth = 0usize;
+        for (index, entry) in trace_log.iter().enumerate() {
+            let pc = entry[11] as usize;
+            if analysis.cfg_nodes.contains_key(&pc) {
+                covered_edges.insert((last_basic_block, pc));
+                last_basic_block = pc;
+            }
+            let opcode = analysis
+                .instructions
+                .binary_search_by_key(&pc, |insn| insn.ptr)
+                .ok()
+                .map(|index| analysis.instructions[index].opc);
+            let next_pc = trace_log.get(index.saturating_add(1)).map(|next| next[11]);
+            match opcode {
+                // Syscalls continue at the next instruction
+                Some(ebpf::CALL_IMM | ebpf::CALL_REG)
+                    if next_pc.is_some_and(|next_pc| next_pc != entry[11].saturating_add(1)) =>
+                {
+                    call_depth = call_depth.saturating_add(1);
+                    max_call_depth = max_call_depth.max(call_depth);
+                }
+                Some(ebpf::EXIT | ebpf::RETURN) => {
+                    call_depth = call_depth.saturating_sub(1);
+                }
+                _ => {}
+            }
+        }
+        let new_edges = covered_edges
+            .iter()
+            .filter(|edge| !self.seen_edges.contains(*edge))
+            .count();
+        let new_call_depth = max_call_depth.saturating_sub(self.max_call_depth);
+        self.seen_edges.extend(covered_edges.iter().copied());
+        self.max_call_depth = self.max_call_depth.max(max_call_depth);
+        RunProgress {
+            new_edges,
+            covered_edges: covered_edges.len(),
+            max_call_depth,
+            new_call_depth,
+            meter_exhausted: matches!(
+                result,
+                ProgramResult::Err(EbpfError::ExceededMaxInstructions)
+            ),
+        }
+    }
+
+    /// Number of distinct edges covered by all runs so far
+    pub fn total_edges(&self) -> usize {
+        self.seen_edges.len()
+    }
+}
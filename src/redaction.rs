@@ -0,0 +1,90 @@
+//! Redaction of sensitive values before sharing
+//!
+//! Traces and reproduction bundles contain register values and inputs, which may include
+//! public keys and account data of real users. A [Redaction] replaces these while keeping
+//! the structure intact: Lengths, pointers into the VM regions and small values such as
+//! counters and offsets survive, and [Redaction::Hash] maps equal values to equal outputs,
+//! so comparisons between redacted values still behave the same.
+
+use crate::{
+    accounts::{AccountField, AccountLayout},
+    ebpf,
+};
+
+/// Register values below this are kept, as they are most likely lengths, counters or offsets
+pub const PRESERVED_VALUE_LIMIT: u64 = 1 << 16;
+
+/// How sensitive values are replaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redaction {
+    /// Replace by zeros
+    Zero,
+    /// Replace by a keyed hash, which is not reversible without knowing the salt
+    Hash {
+        /// Key of the hash, should be secret and random per shared report
+        salt: u64,
+    },
+}
+
+/// Finalizer of SplitMix64
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+impl Redaction {
+    /// Replaces a register value, unless it is small or points into a VM region
+    pub fn register(&self, value: u64) -> u64 {
+        let is_vm_address = (ebpf::MM_RODATA_START
+            ..ebpf::MM_INPUT_START.saturating_add(ebpf::MM_REGION_SIZE))
+            .contains(&value);
+        if value < PRESERVED_VALUE_LIMIT || is_vm_address {
+            return value;
+        }
+        match self {
+            Self::Zero => 0,
+            Self::Hash { salt } => mix(value ^ mix(*salt)),
+        }
+    }
+
+    /// Replaces all bytes, equal slices of equal length are replaced by equal bytes
+    pub fn bytes(&self, bytes: &mut [u8]) {
+        let Self::Hash { salt } = self else {
+            bytes.fill(0);
+            return;
+        };
+        let mut state = bytes
+            .iter()
+            .fold(mix(*salt), |state, byte| mix(state ^ u64::from(*byte)));
+        for chunk in bytes.chunks_mut(8) {
+            state = mix(state);
+            chunk.copy_from_slice(&state.to_le_bytes()[..chunk.len()]);
+        }
+    }
+
+    /// Replaces the public keys, account data and instruction data of a serialized input
+    ///
+    /// Lamports, lengths and flags are kept. An input which is not in the aligned layout
+    /// is replaced entirely.
+    pub fn input(&self, input: &mut [u8]) {
+        let Some(layout) = AccountLayout::parse_aligned(input) else {
+            self.bytes(input);
+            return;
+        };
+        for (range, _account, field) in layout.fields_in(0..input.len()) {
+            let range = match field {
+                AccountField::Key
+                | AccountField::OwnerPubkey
+                | AccountField::Data
+                | AccountField::ProgramId => range,
+                // Keep the length prefix
+                AccountField::InstructionData => range.start.saturating_add(8)..range.end,
+                _ => continue,
+            };
+            if let Some(bytes) = input.get_mut(range) {
+                self.bytes(bytes);
+            }
+        }
+    }
+}
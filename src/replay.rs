@@ -0,0 +1,142 @@
+//! Deterministic replay of recorded executions
+//!
+//! A recording is the trace log collected by [ContextObject::trace] while
+//! `Config::enable_instruction_tracing` is set: It contains the registers
+//! (including the pc) before every executed instruction.
+//! The [Replayer] executes the program again in the interpreter and compares
+//! every step against the recording, reporting the first divergence. This
+//! helps to find non-deterministic syscalls and context objects.
+
+use crate::{
+    elf::Executable,
+    error::ProgramResult,
+    interpreter::Interpreter,
+    static_analysis::TraceLogEntry,
+    vm::{ContextObject, EbpfVm},
+};
+
+/// The first point at which a replay departs from its recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// The registers before the instruction at `index` differ
+    Registers {
+        /// Index into the recording
+        index: usize,
+        /// Recorded registers
+        expected: TraceLogEntry,
+        /// Registers of the replay
+        actual: TraceLogEntry,
+    },
+    /// The replay terminated before reaching the end of the recording
+    TerminatedEarly {
+        /// Number of instructions replayed
+        replayed: usize,
+        /// Number of instructions recorded
+        recorded: usize,
+    },
+    /// The replay continued beyond the end of the recording
+    ExceededRecording {
+        /// Number of instructions recorded
+        recorded: usize,
+    },
+}
+
+impl Divergence {
+    /// Indices of the registers which differ, 11 being the pc
+    pub fn differing_registers(&self) -> Vec<usize> {
+        match self {
+            Self::Registers {
+                expected, actual, ..
+            } => (0..expected.len())
+                .filter(|index| expected[*index] != actual[*index])
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Outcome of a replay
+#[derive(Debug)]
+pub struct ReplayReport {
+    /// Number of instructions which matched the recording
+    pub replayed_instructions: usize,
+    /// First divergence, if any
+    pub divergence: Option<Divergence>,
+    /// Result of the replayed execution, `Ok(0)` if it was stopped at a divergence
+    pub result: ProgramResult,
+}
+
+/// Re-executes a recorded trace and verifies it step by step
+pub struct Replayer<'a> {
+    recording: &'a [TraceLogEntry],
+}
+
+impl<'a> Replayer<'a> {
+    /// Creates a replayer for the given recording
+    pub fn new(recording: &'a [TraceLogEntry]) -> Self {
+        Self { recording }
+    }
+
+    /// Replays the recording in the interpreter
+    ///
+    /// The VM must be set up with the same memory contents as during the recording.
+    /// The initial registers are taken from the first recorded instruction.
+    pub fn replay<C: ContextObject>(
+        &self,
+        vm: &mut EbpfVm<C>,
+        executable: &Executable<C>,
+    ) -> ReplayReport {
+        let initial_registers = self.recording.first().copied().unwrap_or_else(|| {
+            let mut registers = vm.registers;
+            registers[11] = executable.get_entrypoint_instruction_offset() as u64;
+            registers
+        });
+        vm.previous_instruction_meter = vm.context_object_pointer.get_remaining();
+        vm.due_insn_count = 0;
+        vm.program_result = ProgramResult::Ok(0);
+        let mut divergence = None;
+        let mut index = 0;
+        {
+            let mut interpreter = Interpreter::new(vm, executable, initial_registers);
+            loop {
+                match self.recording.get(index) {
+                    Some(expected) if *expected != interpreter.reg => {
+                        divergence = Some(Divergence::Registers {
+                            index,
+                            expected: *expected,
+                            actual: interpreter.reg,
+                        });
+                        break;
+                    }
+                    Some(_) => {}
+                    None => {
+                        divergence = Some(Divergence::ExceededRecording {
+                            recorded: self.recording.len(),
+                        });
+                        break;
+                    }
+                }
+                index = index.saturating_add(1);
+                if !interpreter.step() {
+                    break;
+                }
+            }
+        }
+        if divergence.is_none() && index < self.recording.len() {
+            divergence = Some(Divergence::TerminatedEarly {
+                replayed: index,
+                recorded: self.recording.len(),
+            });
+        }
+        if executable.get_config().enable_instruction_meter {
+            vm.context_object_pointer.consume(vm.due_insn_count);
+        }
+        let mut result = ProgramResult::Ok(0);
+        std::mem::swap(&mut result, &mut vm.program_result);
+        ReplayReport {
+            replayed_instructions: index,
+            divergence,
+            result,
+        }
+    }
+}
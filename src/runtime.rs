@@ -0,0 +1,297 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Execution of programs which invoke each other
+//!
+//! A [Runtime] hosts the executables of several programs, keyed by their program id, which
+//! share one loader. Besides the syscalls registered by the harness, the loader provides
+//! `sol_invoke_(program_id, input, input_len)`, a simplified cross-program invocation:
+//! The callee runs in a nested VM on a copy of the `input_len` bytes at `input` in the memory
+//! of the caller, which are expected to be serialized like the input of the outermost
+//! program, e.g. by an [InputBuilder](crate::solana_input::InputBuilder). Its modifications
+//! are copied back when it succeeds, and its return value becomes the one of the syscall.
+//! A callee which fails makes the syscall fail as well.
+//!
+//! Every execution records an [Invocation] in a [Timeline], and with
+//! [Config::enable_instruction_tracing](crate::vm::Config::enable_instruction_tracing) the
+//! trace of all programs is merged into one timeline in execution order. When the caller
+//! passes on a part of its own input region, the input of the callee is mapped to the input
+//! offsets of the outermost program, so the taint of all invocations refers to the same bytes.
+
+use crate::{
+    declare_builtin_function, ebpf,
+    elf::{ElfError, Executable},
+    error::{EbpfError, ProgramResult},
+    harness::execute_with_input,
+    memory_region::{AccessType, MemoryMapping, MemoryRegion},
+    program::BuiltinProgram,
+    static_analysis::{Analysis, TraceLogEntry},
+    taint::InputTaint,
+    vm::ContextObject,
+};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc, sync::Arc};
+
+/// Public key of a program
+pub type ProgramId = [u8; 32];
+
+/// Errors of cross-program invocations
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// No program was added under the program id
+    #[error("Unknown program {0:02x?}")]
+    UnknownProgram(ProgramId),
+    /// Invocations are nested deeper than the runtime allows
+    #[error("Invocations nested deeper than {0}")]
+    InvokeDepthExceeded(usize),
+}
+
+/// An execution of a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    /// Program which was executed
+    pub program_id: ProgramId,
+    /// Number of invocations it is nested in, 0 for the outermost program
+    pub depth: usize,
+    /// Index of the invoking execution in [Timeline::invocations]
+    pub caller: Option<usize>,
+    /// Offset of the input in the input of the outermost program, if it was passed on from
+    /// there
+    pub input_offset: Option<u64>,
+    /// Input when the execution started
+    pub input: Vec<u8>,
+    /// Value returned in r0, if the execution succeeded
+    pub return_value: Option<u64>,
+}
+
+/// Executions of all programs in the order they started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timeline {
+    /// Executions in the order they started
+    pub invocations: Vec<Invocation>,
+    /// Traced instructions of all executions in execution order, with the index of the
+    /// invocation which executed them
+    pub entries: Vec<(usize, TraceLogEntry)>,
+}
+
+impl Timeline {
+    /// Trace log of one execution
+    pub fn trace_log(&self, invocation: usize) -> Vec<TraceLogEntry> {
+        self.entries
+            .iter()
+            .filter(|(index, _entry)| *index == invocation)
+            .map(|(_index, entry)| *entry)
+            .collect()
+    }
+
+    /// Input taint of one execution, labeled with the input offsets of the outermost program
+    ///
+    /// `analysis` has to be the one of the executed program. Executions whose input was not
+    /// passed on from the outermost program have no taint sources.
+    pub fn input_taint(&self, invocation: usize, analysis: &Analysis) -> InputTaint {
+        let Some(record) = self.invocations.get(invocation) else {
+            return InputTaint::default();
+        };
+        let regions = record
+            .input_offset
+            .map(|offset| {
+                MemoryRegion::new_readonly_tainted(&record.input, ebpf::MM_INPUT_START, offset)
+            })
+            .into_iter()
+            .collect::<Vec<_>>();
+        InputTaint::from_trace_log_with_regions(analysis, &self.trace_log(invocation), &regions)
+    }
+}
+
+/// Context object of the executions of a [Runtime]
+#[derive(Debug)]
+pub struct InvokeContext {
+    /// Remaining instruction budget, shared by all nested executions
+    pub remaining: u64,
+    programs: Rc<BTreeMap<ProgramId, Executable<InvokeContext>>>,
+    max_invoke_depth: usize,
+    timeline: Rc<RefCell<Timeline>>,
+    /// Index of the current execution in [Timeline::invocations]
+    invocation: usize,
+}
+
+impl ContextObject for InvokeContext {
+    fn trace(&mut self, state: [u64; 12]) {
+        self.timeline
+            .borrow_mut()
+            .entries
+            .push((self.invocation, state));
+    }
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+impl InvokeContext {
+    /// Executes a program in a nested VM, recording it as invoked by the current execution
+    fn execute(
+        &mut self,
+        program_id: ProgramId,
+        input: &mut [u8],
+        caller: Option<usize>,
+        input_offset: Option<u64>,
+    ) -> ProgramResult {
+        let Some(executable) = self.programs.get(&program_id) else {
+            return ProgramResult::Err(EbpfError::SyscallError(Box::new(
+                RuntimeError::UnknownProgram(program_id),
+            )));
+        };
+        let depth = {
+            let mut timeline = self.timeline.borrow_mut();
+            let depth = caller.map_or(0, |caller| timeline.invocations[caller].depth + 1);
+            timeline.invocations.push(Invocation {
+                program_id,
+                depth,
+                caller,
+                input_offset,
+                input: input.to_vec(),
+                return_value: None,
+            });
+            depth
+        };
+        if depth > self.max_invoke_depth {
+            return ProgramResult::Err(EbpfError::SyscallError(Box::new(
+                RuntimeError::InvokeDepthExceeded(self.max_invoke_depth),
+            )));
+        }
+        let mut context_object = InvokeContext {
+            remaining: self.remaining,
+            programs: self.programs.clone(),
+            max_invoke_depth: self.max_invoke_depth,
+            timeline: self.timeline.clone(),
+            invocation: self.timeline.borrow().invocations.len() - 1,
+        };
+        let result = execute_with_input(
+            executable,
+            &mut context_object,
+            &[(1, ebpf::MM_INPUT_START)],
+            input,
+            true,
+        );
+        self.remaining = context_object.remaining;
+        if let ProgramResult::Ok(return_value) = result {
+            self.timeline.borrow_mut().invocations[context_object.invocation].return_value =
+                Some(return_value);
+        }
+        result
+    }
+}
+
+declare_builtin_function!(
+    /// `sol_invoke_(program_id, input, input_len)`, executes another program of the [Runtime]
+    SyscallInvoke,
+    fn rust(
+        context_object: &mut InvokeContext,
+        program_id_addr: u64,
+        input_addr: u64,
+        input_len: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let program_id_host_addr: Result<u64, EbpfError> = memory_mapping
+            .map(AccessType::Load, program_id_addr, 32)
+            .into();
+        // The program id was mapped with a length of 32
+        let program_id =
+            unsafe { std::ptr::read_unaligned(program_id_host_addr? as *const ProgramId) };
+        let input_host_addr: Result<u64, EbpfError> = memory_mapping
+            .map(AccessType::Store, input_addr, input_len)
+            .into();
+        // The input was mapped with a length of `input_len`
+        let input = unsafe {
+            std::slice::from_raw_parts_mut(input_host_addr? as *mut u8, input_len as usize)
+        };
+        // Copy the input, as the regions of the caller still refer to it
+        let mut callee_input = input.to_vec();
+        let caller = context_object.invocation;
+        let input_offset = {
+            let timeline = context_object.timeline.borrow();
+            let record = &timeline.invocations[caller];
+            input_addr
+                .checked_sub(ebpf::MM_INPUT_START)
+                .filter(|offset| {
+                    offset.saturating_add(input_len) <= record.input.len() as u64
+                })
+                .and_then(|offset| record.input_offset?.checked_add(offset))
+        };
+        let result: Result<u64, EbpfError> = context_object
+            .execute(program_id, &mut callee_input, Some(caller), input_offset)
+            .into();
+        let return_value = result?;
+        input.copy_from_slice(&callee_input);
+        Ok(return_value)
+    }
+);
+
+/// Programs which can invoke each other
+#[derive(Debug)]
+pub struct Runtime {
+    loader: Arc<BuiltinProgram<InvokeContext>>,
+    programs: Rc<BTreeMap<ProgramId, Executable<InvokeContext>>>,
+    max_invoke_depth: usize,
+}
+
+impl Runtime {
+    /// Creates a runtime without programs, registers `sol_invoke_` in the loader
+    ///
+    /// Invocations can be nested `max_invoke_depth` deep.
+    pub fn new(
+        mut loader: BuiltinProgram<InvokeContext>,
+        max_invoke_depth: usize,
+    ) -> Result<Self, ElfError> {
+        loader.register_function("sol_invoke_", SyscallInvoke::vm)?;
+        Ok(Self {
+            loader: Arc::new(loader),
+            programs: Rc::default(),
+            max_invoke_depth,
+        })
+    }
+
+    /// Loader the executables have to be loaded with
+    pub fn loader(&self) -> &Arc<BuiltinProgram<InvokeContext>> {
+        &self.loader
+    }
+
+    /// Adds a program, replacing the one with the same program id
+    pub fn add_program(&mut self, program_id: ProgramId, executable: Executable<InvokeContext>) {
+        debug_assert!(Arc::ptr_eq(executable.get_loader(), &self.loader));
+        Rc::get_mut(&mut self.programs)
+            .expect("no execution is in progress")
+            .insert(program_id, executable);
+    }
+
+    /// Executable of a program
+    pub fn program(&self, program_id: &ProgramId) -> Option<&Executable<InvokeContext>> {
+        self.programs.get(program_id)
+    }
+
+    /// Executes a program on `input` in the interpreter, with all invocations it makes
+    ///
+    /// The input is updated with the modifications of the program. All executions share the
+    /// instruction budget.
+    pub fn execute(
+        &self,
+        program_id: ProgramId,
+        input: &mut [u8],
+        instruction_budget: u64,
+    ) -> (ProgramResult, Timeline) {
+        let timeline = Rc::new(RefCell::new(Timeline::default()));
+        let mut context_object = InvokeContext {
+            remaining: instruction_budget,
+            programs: self.programs.clone(),
+            max_invoke_depth: self.max_invoke_depth,
+            timeline: timeline.clone(),
+            invocation: 0,
+        };
+        let result = context_object.execute(program_id, input, None, Some(0));
+        (result, timeline.take())
+    }
+}
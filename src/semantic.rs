@@ -0,0 +1,176 @@
+//! Semantic tags of memory regions
+//!
+//! An [AccountLayout] tags the bytes of the input region with the account and field they
+//! belong to. Harnesses which map further regions, e.g. sysvar data or the return data buffer
+//! of a CPI, describe them with a [RegionLayout] over an attribute enum of their own.
+//! Registered in a [SemanticMemory], all regions are treated alike: Any virtual address
+//! resolves to a [SemanticTag], and the stores to an attribute become [TaintSink]s.
+
+use crate::{accounts::AccountLayout, ebpf, taint::TaintSink};
+use std::{convert::TryFrom, fmt::Debug, ops::Range};
+
+/// Attributes of the bytes of a memory region, independent of its attribute type
+pub trait TaggedLayout: Debug {
+    /// Index (e.g. of the account) and attribute name of the byte at `offset`
+    fn tag_at(&self, offset: usize) -> Option<(Option<usize>, String)>;
+
+    /// Offset ranges of the attribute named `attribute` in the region
+    fn ranges_of_tag(&self, attribute: &str) -> Vec<Range<usize>>;
+}
+
+impl TaggedLayout for AccountLayout {
+    fn tag_at(&self, offset: usize) -> Option<(Option<usize>, String)> {
+        self.lookup(offset)
+            .map(|(account, field)| (account, format!("{field:?}")))
+    }
+
+    fn ranges_of_tag(&self, attribute: &str) -> Vec<Range<usize>> {
+        self.fields_in(0..usize::MAX)
+            .filter(|(_range, _account, field)| format!("{field:?}") == attribute)
+            .map(|(range, _account, _field)| range)
+            .collect()
+    }
+}
+
+/// Attributes of the bytes of a memory region
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionLayout<A> {
+    /// Attribute ranges in ascending order, with the index of the entry they belong to
+    fields: Vec<(Range<usize>, Option<usize>, A)>,
+}
+
+impl<A> Default for RegionLayout<A> {
+    fn default() -> Self {
+        Self { fields: Vec::new() }
+    }
+}
+
+impl<A: Copy + Debug + Eq> RegionLayout<A> {
+    /// Tags a range of offsets, `index` distinguishes repeated entries like accounts
+    ///
+    /// Ranges overlapping an already tagged one are ignored.
+    pub fn with_field(mut self, range: Range<usize>, index: Option<usize>, attribute: A) -> Self {
+        let position = self
+            .fields
+            .partition_point(|(other, _index, _attribute)| other.end <= range.start);
+        let overlaps = self
+            .fields
+            .get(position)
+            .is_some_and(|(other, _index, _attribute)| other.start < range.end);
+        if !range.is_empty() && !overlaps {
+            self.fields.insert(position, (range, index, attribute));
+        }
+        self
+    }
+
+    /// Index and attribute at the offset into the region
+    pub fn lookup(&self, offset: usize) -> Option<(Option<usize>, A)> {
+        let position = self
+            .fields
+            .partition_point(|(range, _index, _attribute)| range.end <= offset);
+        self.fields
+            .get(position)
+            .filter(|(range, _index, _attribute)| range.contains(&offset))
+            .map(|(_range, index, attribute)| (*index, *attribute))
+    }
+
+    /// Ranges of an attribute in all entries, with the index of the entry they belong to
+    pub fn ranges_of(
+        &self,
+        attribute: A,
+    ) -> impl Iterator<Item = (Range<usize>, Option<usize>)> + '_ {
+        self.fields
+            .iter()
+            .filter(move |(_range, _index, other)| *other == attribute)
+            .map(|(range, index, _attribute)| (range.clone(), *index))
+    }
+}
+
+impl<A: Copy + Debug + Eq> TaggedLayout for RegionLayout<A> {
+    fn tag_at(&self, offset: usize) -> Option<(Option<usize>, String)> {
+        self.lookup(offset)
+            .map(|(index, attribute)| (index, format!("{attribute:?}")))
+    }
+
+    fn ranges_of_tag(&self, attribute: &str) -> Vec<Range<usize>> {
+        self.fields
+            .iter()
+            .filter(|(_range, _index, other)| format!("{other:?}") == attribute)
+            .map(|(range, _index, _attribute)| range.clone())
+            .collect()
+    }
+}
+
+/// Semantic tag of a virtual address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticTag {
+    /// Name the region was registered with
+    pub region: String,
+    /// Index of the entry, e.g. the account
+    pub index: Option<usize>,
+    /// Name of the attribute
+    pub attribute: String,
+    /// Offset into the region
+    pub offset: usize,
+}
+
+/// Tagged memory regions of an execution
+#[derive(Debug, Default)]
+pub struct SemanticMemory {
+    regions: Vec<(String, u64, Box<dyn TaggedLayout>)>,
+}
+
+impl SemanticMemory {
+    /// Creates a semantic memory with the input region tagged by `layout`
+    pub fn with_input(layout: AccountLayout) -> Self {
+        let mut memory = Self::default();
+        memory.register("input", ebpf::MM_INPUT_START, layout);
+        memory
+    }
+
+    /// Registers a region which starts at `vm_addr`
+    ///
+    /// Regions registered earlier take precedence where they overlap.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        vm_addr: u64,
+        layout: impl TaggedLayout + 'static,
+    ) {
+        self.regions.push((name.into(), vm_addr, Box::new(layout)));
+    }
+
+    /// Semantic tag of the byte at `vm_addr`
+    pub fn lookup(&self, vm_addr: u64) -> Option<SemanticTag> {
+        self.regions.iter().find_map(|(name, start, layout)| {
+            let offset = usize::try_from(vm_addr.checked_sub(*start)?).ok()?;
+            let (index, attribute) = layout.tag_at(offset)?;
+            Some(SemanticTag {
+                region: name.clone(),
+                index,
+                attribute,
+                offset,
+            })
+        })
+    }
+
+    /// Sinks for stores to an attribute of a region, in all its entries
+    pub fn taint_sinks(&self, region: &str, attribute: impl Debug) -> Vec<TaintSink> {
+        let attribute = format!("{attribute:?}");
+        self.regions
+            .iter()
+            .filter(|(name, _start, _layout)| name == region)
+            .flat_map(|(_name, start, layout)| {
+                layout
+                    .ranges_of_tag(&attribute)
+                    .into_iter()
+                    .map(move |range| {
+                        TaintSink::Store(
+                            start.saturating_add(range.start as u64)
+                                ..start.saturating_add(range.end as u64),
+                        )
+                    })
+            })
+            .collect()
+    }
+}
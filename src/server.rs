@@ -0,0 +1,328 @@
+//! JSON-RPC server for remote execution
+//!
+//! A [Server] holds loaded programs and executes them on behalf of remote clients, e.g. a
+//! fleet of fuzzing workers or a web based debugger. It speaks JSON-RPC 2.0 with one message
+//! per line, over any reader and writer ([Server::serve]) or TCP ([Server::listen]).
+//! Byte strings (ELF files and inputs) are hex encoded.
+//!
+//! | Method    | Params                                         | Result                                   |
+//! |-----------|------------------------------------------------|------------------------------------------|
+//! | `load`    | `elf` or `assembly`                            | `program` id                             |
+//! | `unload`  | `program`                                      | `true`                                   |
+//! | `execute` | `program`, `input`, `instruction_budget`       | `result`, `instruction_count`, `input`   |
+//! | `trace`   | like `execute`, `stream`                       | like `execute`, `trace` unless streamed  |
+//! | `taint`   | like `execute`                                 | like `execute`, tainted pcs and offsets  |
+//!
+//! With `stream` set, `trace` sends every [InstructionRecord](crate::trace_export::InstructionRecord) as a `trace.instruction`
+//! notification before the response, instead of including the [TraceExport] in it.
+
+use crate::{
+    assembler::assemble,
+    elf::Executable,
+    error::ProgramResult,
+    harness::execute_with_input,
+    memory_builtins::register_memory_builtins,
+    program::BuiltinProgram,
+    static_analysis::{Analysis, TraceLogEntry},
+    taint::{InputOffsets, InputTaint},
+    trace_export::TraceExport,
+    vm::{Config, ContextObject},
+};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::Arc,
+};
+
+/// Instruction budget of a run if the request does not specify one
+pub const DEFAULT_INSTRUCTION_BUDGET: u64 = 1_000_000;
+
+/// Context object of the executions of a [Server]
+#[derive(Debug, Clone, Default)]
+pub struct ServerContextObject {
+    /// Remaining instruction budget
+    pub remaining: u64,
+    /// Registers before every executed instruction, if tracing is enabled
+    pub trace_log: Vec<TraceLogEntry>,
+}
+
+impl ContextObject for ServerContextObject {
+    fn trace(&mut self, state: [u64; 12]) {
+        self.trace_log.push(state);
+    }
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+/// JSON-RPC error object
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    const PARSE_ERROR: i64 = -32700;
+    const INVALID_REQUEST: i64 = -32600;
+    const METHOD_NOT_FOUND: i64 = -32601;
+    const INVALID_PARAMS: i64 = -32602;
+    const SERVER_ERROR: i64 = -32000;
+
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn invalid_params(message: impl ToString) -> Self {
+        Self::new(Self::INVALID_PARAMS, message)
+    }
+}
+
+/// Executes loaded programs for remote clients
+pub struct Server {
+    loader: Arc<BuiltinProgram<ServerContextObject>>,
+    programs: BTreeMap<u64, Executable<ServerContextObject>>,
+    next_program: u64,
+}
+
+impl Default for Server {
+    /// Traces every execution and provides the [memory builtins](crate::memory_builtins)
+    fn default() -> Self {
+        let mut loader = BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        });
+        register_memory_builtins(&mut loader).unwrap();
+        Self::new(Arc::new(loader))
+    }
+}
+
+impl Server {
+    /// Creates a server which loads programs with `loader`
+    ///
+    /// `trace` and `taint` require [Config::enable_instruction_tracing].
+    pub fn new(loader: Arc<BuiltinProgram<ServerContextObject>>) -> Self {
+        Self {
+            loader,
+            programs: BTreeMap::new(),
+            next_program: 0,
+        }
+    }
+
+    /// Accepts one connection after another and serves each until it is closed
+    pub fn listen(&mut self, listener: &TcpListener) -> std::io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            self.serve(BufReader::new(stream.try_clone()?), stream)?;
+        }
+        Ok(())
+    }
+
+    /// Answers the requests read from `reader`, one per line, until it is exhausted
+    pub fn serve<R: BufRead, W: Write>(&mut self, reader: R, mut writer: W) -> std::io::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut notifications = Vec::new();
+            let response = self.handle(&line, &mut |notification| notifications.push(notification));
+            for message in notifications.iter().chain(response.iter()) {
+                writeln!(writer, "{message}")?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Handles a single request
+    ///
+    /// Returns the response, or `None` if the request was a notification. Streamed events are
+    /// passed to `notify` before.
+    pub fn handle(&mut self, request: &str, notify: &mut dyn FnMut(Value)) -> Option<Value> {
+        let request = match serde_json::from_str::<Value>(request) {
+            Ok(request) => request,
+            Err(error) => {
+                return Some(Self::response(
+                    Value::Null,
+                    Err(RpcError::new(RpcError::PARSE_ERROR, error)),
+                ))
+            }
+        };
+        let id = request.get("id").cloned();
+        let result = match request.get("method").and_then(Value::as_str) {
+            Some(method) => {
+                let params = request.get("params").cloned().unwrap_or(Value::Null);
+                self.dispatch(method, &params, notify)
+            }
+            None => Err(RpcError::new(RpcError::INVALID_REQUEST, "missing method")),
+        };
+        id.map(|id| Self::response(id, result))
+    }
+
+    fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+        match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": error.code, "message": error.message },
+            }),
+        }
+    }
+
+    fn dispatch(
+        &mut self,
+        method: &str,
+        params: &Value,
+        notify: &mut dyn FnMut(Value),
+    ) -> Result<Value, RpcError> {
+        match method {
+            "load" => self.load(params),
+            "unload" => {
+                let program = Self::program_id(params)?;
+                self.programs
+                    .remove(&program)
+                    .map(|_| Value::Bool(true))
+                    .ok_or_else(|| RpcError::invalid_params("unknown program"))
+            }
+            "execute" => self.execute(params, false).map(|(response, _)| response),
+            "trace" => {
+                let (mut response, trace_log) = self.execute(params, true)?;
+                let executable = &self.programs[&Self::program_id(params)?];
+                let trace = TraceExport::from_trace_log(executable, &trace_log);
+                if params.get("stream").and_then(Value::as_bool) == Some(true) {
+                    for record in trace.instructions.iter() {
+                        notify(json!({
+                            "jsonrpc": "2.0",
+                            "method": "trace.instruction",
+                            "params": record,
+                        }));
+                    }
+                } else {
+                    response["trace"] = json!(trace);
+                }
+                Ok(response)
+            }
+            "taint" => {
+                let (mut response, trace_log) = self.execute(params, true)?;
+                let executable = &self.programs[&Self::program_id(params)?];
+                let analysis = Analysis::from_executable(executable)
+                    .map_err(|error| RpcError::new(RpcError::SERVER_ERROR, error))?;
+                let taint = InputTaint::from_trace_log(&analysis, &trace_log);
+                let offsets = |map: &BTreeMap<usize, InputOffsets>| {
+                    map.iter()
+                        .map(|(pc, offsets)| json!([pc, offsets.start, offsets.end]))
+                        .collect::<Vec<_>>()
+                };
+                response["tainted_comparisons"] = json!(offsets(&taint.tainted_comparisons));
+                response["tainted_loads"] = json!(offsets(&taint.tainted_loads));
+                Ok(response)
+            }
+            _ => Err(RpcError::new(RpcError::METHOD_NOT_FOUND, method)),
+        }
+    }
+
+    fn load(&mut self, params: &Value) -> Result<Value, RpcError> {
+        let executable = if let Some(elf) = params.get("elf") {
+            let elf = Self::bytes(elf)?;
+            Executable::load(&elf, self.loader.clone())
+                .map_err(|error| RpcError::new(RpcError::SERVER_ERROR, error))?
+        } else if let Some(assembly) = params.get("assembly").and_then(Value::as_str) {
+            assemble(assembly, self.loader.clone())
+                .map_err(|error| RpcError::new(RpcError::SERVER_ERROR, error))?
+        } else {
+            return Err(RpcError::invalid_params("expected elf or assembly"));
+        };
+        let program = self.next_program;
+        self.next_program = self.next_program.saturating_add(1);
+        self.programs.insert(program, executable);
+        Ok(json!({ "program": program }))
+    }
+
+    /// Runs a program in the interpreter, returns the response and the trace log
+    fn execute(
+        &mut self,
+        params: &Value,
+        trace: bool,
+    ) -> Result<(Value, Vec<TraceLogEntry>), RpcError> {
+        let executable = self
+            .programs
+            .get(&Self::program_id(params)?)
+            .ok_or_else(|| RpcError::invalid_params("unknown program"))?;
+        if trace && !executable.get_config().enable_instruction_tracing {
+            return Err(RpcError::new(
+                RpcError::SERVER_ERROR,
+                "instruction tracing is disabled",
+            ));
+        }
+        let mut input = match params.get("input") {
+            Some(input) => Self::bytes(input)?,
+            None => Vec::new(),
+        };
+        let instruction_budget = params
+            .get("instruction_budget")
+            .and_then(Value::as_u64)
+            .unwrap_or(DEFAULT_INSTRUCTION_BUDGET);
+        let mut context_object = ServerContextObject {
+            remaining: instruction_budget,
+            trace_log: Vec::new(),
+        };
+        let result = execute_with_input(
+            executable,
+            &mut context_object,
+            &[(1, crate::ebpf::MM_INPUT_START)],
+            &mut input,
+            true,
+        );
+        let result = match result {
+            ProgramResult::Ok(value) => json!({ "ok": value }),
+            ProgramResult::Err(error) => json!({ "error": error.to_string() }),
+        };
+        let response = json!({
+            "result": result,
+            "instruction_count": instruction_budget.saturating_sub(context_object.remaining),
+            "input": encode_hex(&input),
+        });
+        Ok((response, context_object.trace_log))
+    }
+
+    fn program_id(params: &Value) -> Result<u64, RpcError> {
+        params
+            .get("program")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| RpcError::invalid_params("expected program"))
+    }
+
+    fn bytes(value: &Value) -> Result<Vec<u8>, RpcError> {
+        value
+            .as_str()
+            .and_then(decode_hex)
+            .ok_or_else(|| RpcError::invalid_params("expected a hex string"))
+    }
+}
+
+/// Lower case hex encoding
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Decodes a hex string, `None` if it is malformed
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|digits| match digits {
+            [high, low] => u8::from_str_radix(std::str::from_utf8(&[*high, *low]).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,143 @@
+//! Construction of serialized program inputs
+//!
+//! The [InputBuilder] serializes accounts, instruction data and the program id into
+//! the aligned layout the Solana runtime passes to programs in r1. It returns the input
+//! together with its [AccountLayout], so harnesses do not have to assemble the bytes by hand.
+
+use crate::{
+    accounts::{AccountLayout, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER},
+    aligned_memory::AlignedMemory,
+    ebpf,
+    memory_region::MemoryRegion,
+};
+
+/// An account as seen by the program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountDescription {
+    /// Public key of the account
+    pub key: [u8; 32],
+    /// Public key of the owner
+    pub owner: [u8; 32],
+    /// Balance
+    pub lamports: u64,
+    /// Account data
+    pub data: Vec<u8>,
+    /// Whether the transaction is signed by the account
+    pub is_signer: bool,
+    /// Whether the program may modify the account
+    pub is_writable: bool,
+    /// Whether the account is a program
+    pub executable: bool,
+    /// Rent epoch
+    pub rent_epoch: u64,
+}
+
+/// Entry of the account list
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum InputAccount {
+    /// Serialized in full
+    Unique(AccountDescription),
+    /// Refers to an earlier account by its index
+    Duplicate(u8),
+}
+
+/// Builder of an input in the aligned layout
+#[derive(Debug, Clone, Default)]
+pub struct InputBuilder {
+    accounts: Vec<InputAccount>,
+    instruction_data: Vec<u8>,
+    program_id: [u8; 32],
+}
+
+/// A serialized input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerializedInput {
+    /// Serialized bytes
+    pub input: AlignedMemory<{ ebpf::HOST_ALIGN }>,
+    /// Fields of the serialized bytes
+    pub layout: AccountLayout,
+}
+
+impl SerializedInput {
+    /// Writable region of the input at [ebpf::MM_INPUT_START]
+    pub fn region(&mut self) -> MemoryRegion {
+        MemoryRegion::new_writable(self.input.as_slice_mut(), ebpf::MM_INPUT_START)
+    }
+}
+
+impl InputBuilder {
+    /// Appends an account
+    pub fn account(mut self, account: AccountDescription) -> Self {
+        self.accounts.push(InputAccount::Unique(account));
+        self
+    }
+
+    /// Appends a reference to the account at `index`
+    pub fn duplicate(mut self, index: u8) -> Self {
+        self.accounts.push(InputAccount::Duplicate(index));
+        self
+    }
+
+    /// Sets the instruction data
+    pub fn instruction_data(mut self, instruction_data: &[u8]) -> Self {
+        self.instruction_data = instruction_data.to_vec();
+        self
+    }
+
+    /// Sets the public key of the program
+    pub fn program_id(mut self, program_id: [u8; 32]) -> Self {
+        self.program_id = program_id;
+        self
+    }
+
+    /// Serializes the input
+    pub fn build(&self) -> SerializedInput {
+        let mut input = Vec::new();
+        input.extend_from_slice(&(self.accounts.len() as u64).to_le_bytes());
+        for account in self.accounts.iter() {
+            match account {
+                InputAccount::Duplicate(index) => {
+                    input.extend_from_slice(&[*index, 0, 0, 0, 0, 0, 0, 0]);
+                }
+                InputAccount::Unique(account) => {
+                    input.extend_from_slice(&[
+                        NON_DUP_MARKER,
+                        account.is_signer as u8,
+                        account.is_writable as u8,
+                        account.executable as u8,
+                        0,
+                        0,
+                        0,
+                        0,
+                    ]);
+                    input.extend_from_slice(&account.key);
+                    input.extend_from_slice(&account.owner);
+                    input.extend_from_slice(&account.lamports.to_le_bytes());
+                    input.extend_from_slice(&(account.data.len() as u64).to_le_bytes());
+                    input.extend_from_slice(&account.data);
+                    let padded_len = account
+                        .data
+                        .len()
+                        .saturating_add(MAX_PERMITTED_DATA_INCREASE)
+                        .next_multiple_of(8);
+                    input.resize(
+                        input
+                            .len()
+                            .saturating_add(padded_len.saturating_sub(account.data.len())),
+                        0,
+                    );
+                    input.extend_from_slice(&account.rent_epoch.to_le_bytes());
+                }
+            }
+        }
+        input.extend_from_slice(&(self.instruction_data.len() as u64).to_le_bytes());
+        input.extend_from_slice(&self.instruction_data);
+        input.extend_from_slice(&self.program_id);
+        let layout = AccountLayout::parse_aligned(&input)
+            .expect("the serialized input is parsed by the same layout");
+        SerializedInput {
+            input: AlignedMemory::from_slice(&input),
+            layout,
+        }
+    }
+}
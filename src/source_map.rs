@@ -0,0 +1,148 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Source locations of instructions, from the DWARF line tables of an ELF
+//!
+//! Programs built with debug info carry `.debug_*` sections, which the loader parses into a
+//! [SourceMap] so that [Executable::pc_to_source](crate::elf::Executable::pc_to_source) can
+//! translate the pcs of traces and crash reports back to lines of the Rust or C source.
+//! Debug info is never required for loading: Programs without it, or with debug info which
+//! can not be parsed, simply have no source map.
+
+use crate::{ebpf, elf_parser::Elf64};
+use gimli::{EndianSlice, LittleEndian, SectionId};
+use std::{collections::BTreeMap, fmt};
+
+/// A location in the source of a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// Path of the source file, including its directory if the debug info has one
+    pub file: String,
+    /// Line number, 0 if unknown
+    pub line: u64,
+    /// Column number, 0 if unknown
+    pub column: u64,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if self.line != 0 {
+            write!(f, ":{}", self.line)?;
+            if self.column != 0 {
+                write!(f, ":{}", self.column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Mapping of pcs to the source locations they were compiled from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Paths of the source files
+    files: Vec<String>,
+    /// First pc of each row (file index, line, column), or `None` where a sequence ends
+    rows: BTreeMap<usize, Option<(usize, u64, u64)>>,
+}
+
+impl SourceMap {
+    /// Parses the line tables of all compilation units in the debug sections of an ELF
+    ///
+    /// Returns `None` if the ELF has no `.debug_line` section or its debug info is malformed.
+    pub fn from_elf(elf_bytes: &[u8]) -> Option<Self> {
+        let elf = Elf64::parse(elf_bytes).ok()?;
+        let section = |name: &[u8]| {
+            elf.section_header_table()
+                .iter()
+                .find(|section_header| elf.section_name(section_header.sh_name).ok() == Some(name))
+        };
+        section(b".debug_line")?;
+        let text_address = section(b".text")?.sh_addr;
+        Self::new(text_address, |id| {
+            section(id.name().as_bytes())
+                .and_then(|section_header| section_header.file_range())
+                .and_then(|range| elf_bytes.get(range))
+                .unwrap_or_default()
+        })
+        .ok()
+    }
+
+    /// Parses the line tables of all compilation units in the given debug sections
+    ///
+    /// `text_address` is the address of the `.text` section which the debug info refers to,
+    /// `load_section` returns the contents of a section or an empty slice if it is missing.
+    pub fn new<'a>(
+        text_address: u64,
+        load_section: impl Fn(SectionId) -> &'a [u8],
+    ) -> Result<Self, gimli::Error> {
+        let dwarf = gimli::Dwarf::load(|id| {
+            Ok::<_, gimli::Error>(EndianSlice::new(load_section(id), LittleEndian))
+        })?;
+        let mut source_map = Self::default();
+        let mut file_indices = BTreeMap::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let Some(line_program) = unit.line_program.clone() else {
+                continue;
+            };
+            let mut rows = line_program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                let Some(pc) = row
+                    .address()
+                    .checked_sub(text_address)
+                    .map(|offset| offset as usize / ebpf::INSN_SIZE)
+                else {
+                    continue;
+                };
+                if row.end_sequence() {
+                    source_map.rows.entry(pc).or_insert(None);
+                    continue;
+                }
+                if matches!(source_map.rows.get(&pc), Some(Some(_))) {
+                    continue;
+                }
+                let Some(file) = row.file(header) else {
+                    continue;
+                };
+                let mut path = dwarf
+                    .attr_string(&unit, file.path_name())?
+                    .to_string_lossy()
+                    .into_owned();
+                if let Some(directory) = file.directory(header) {
+                    let directory = dwarf.attr_string(&unit, directory)?.to_string_lossy();
+                    if !path.starts_with('/') && !directory.is_empty() {
+                        path = format!("{}/{path}", directory.trim_end_matches('/'));
+                    }
+                }
+                let file_index = *file_indices.entry(path).or_insert_with_key(|path| {
+                    source_map.files.push(path.clone());
+                    source_map.files.len() - 1
+                });
+                let column = match row.column() {
+                    gimli::ColumnType::LeftEdge => 0,
+                    gimli::ColumnType::Column(column) => column.get(),
+                };
+                source_map.rows.insert(
+                    pc,
+                    Some((file_index, row.line().map_or(0, |line| line.get()), column)),
+                );
+            }
+        }
+        Ok(source_map)
+    }
+
+    /// Source location of the instruction at `pc`
+    pub fn lookup(&self, pc: usize) -> Option<SourceLocation> {
+        let (file_index, line, column) = (*self.rows.range(..=pc).next_back()?.1)?;
+        Some(SourceLocation {
+            file: self.files[file_index].clone(),
+            line,
+            column,
+        })
+    }
+
+    /// Whether the debug info had no line table rows in the text section
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
@@ -0,0 +1,136 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Detection of uninitialized stack reads
+//!
+//! A [StackSanitizer] keeps one shadow bit per byte of the stack region of a
+//! [MemoryMapping](crate::memory_region::MemoryMapping), which records whether the byte was written since its frame was entered. Loads through the
+//! mapping (see [MemoryMapping::set_stack_sanitizer]) which touch a never written byte are recorded as [UninitializedRead]s and, in
+//! [UninitializedReadMode::Error], fail with [EbpfError::UninitializedRead].
+//!
+//! The shadow of a frame is cleared when it is pushed, which requires the sanitizer to also be
+//! registered as an [ExecutionObserver]. As the JIT does not notify observers, JIT executions
+//! only detect reads of bytes which were not written in any frame so far.
+
+use crate::{
+    ebpf,
+    error::EbpfError,
+    memory_region::AccessType,
+    observer::{CallFrameEvent, ExecutionObserver},
+    program::SBPFVersion,
+    vm::Config,
+};
+
+/// What happens on a load of an uninitialized byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UninitializedReadMode {
+    /// Only record the read
+    Warn,
+    /// Record the read and fail with [EbpfError::UninitializedRead]
+    Error,
+}
+
+/// A load which touched at least one uninitialized byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UninitializedRead {
+    /// Pc of the load, 0 if the sanitizer is not registered as observer
+    pub pc: u64,
+    /// Address of the load
+    pub vm_addr: u64,
+    /// Size of the load
+    pub len: u64,
+}
+
+/// Written bytes tracking of the stack region
+#[derive(Debug)]
+pub struct StackSanitizer {
+    /// What happens on a load of an uninitialized byte
+    mode: UninitializedReadMode,
+    /// Size of a frame, `None` with dynamic stack frames
+    frame_size: Option<u64>,
+    /// One entry per byte of the virtual address range of the stack region
+    written: Vec<bool>,
+    /// Pc of the instruction being executed
+    pc: u64,
+    /// Loads of uninitialized bytes in the order they happened
+    uninitialized_reads: Vec<UninitializedRead>,
+}
+
+impl StackSanitizer {
+    /// Creates a sanitizer for the stack region which `config` and `sbpf_version` produce
+    pub fn new(config: &Config, sbpf_version: SBPFVersion, mode: UninitializedReadMode) -> Self {
+        let gapped = !sbpf_version.dynamic_stack_frames() && config.enable_stack_frame_gaps;
+        let len = config.stack_size() * if gapped { 2 } else { 1 };
+        Self {
+            mode,
+            frame_size: (!sbpf_version.dynamic_stack_frames())
+                .then_some(config.stack_frame_size as u64),
+            written: vec![false; len],
+            pc: 0,
+            uninitialized_reads: Vec::new(),
+        }
+    }
+
+    /// Marks stores as written and checks loads
+    pub fn check(
+        &mut self,
+        access_type: AccessType,
+        vm_addr: u64,
+        len: u64,
+    ) -> Result<(), EbpfError> {
+        let Some(shadow) = self.shadow_mut(vm_addr, len) else {
+            return Ok(());
+        };
+        match access_type {
+            AccessType::Store => {
+                shadow.fill(true);
+                Ok(())
+            }
+            AccessType::Load if shadow.iter().all(|written| *written) => Ok(()),
+            AccessType::Load => {
+                self.uninitialized_reads.push(UninitializedRead {
+                    pc: self.pc,
+                    vm_addr,
+                    len,
+                });
+                match self.mode {
+                    UninitializedReadMode::Warn => Ok(()),
+                    UninitializedReadMode::Error => Err(EbpfError::UninitializedRead(vm_addr, len)),
+                }
+            }
+        }
+    }
+
+    /// Loads of uninitialized bytes in the order they happened
+    pub fn uninitialized_reads(&self) -> &[UninitializedRead] {
+        &self.uninitialized_reads
+    }
+
+    fn shadow_mut(&mut self, vm_addr: u64, len: u64) -> Option<&mut [bool]> {
+        let start = vm_addr.max(ebpf::MM_STACK_START);
+        let end = vm_addr
+            .saturating_add(len)
+            .min(ebpf::MM_STACK_START.saturating_add(self.written.len() as u64));
+        if start >= end {
+            return None;
+        }
+        self.written
+            .get_mut((start - ebpf::MM_STACK_START) as usize..(end - ebpf::MM_STACK_START) as usize)
+    }
+}
+
+impl ExecutionObserver for StackSanitizer {
+    fn on_insn(&mut self, pc: u64, _insn: &ebpf::Insn, _registers: &[u64; 12]) {
+        self.pc = pc;
+    }
+
+    fn on_push_frame(&mut self, frame: &CallFrameEvent) {
+        // Fixed frames lie below their frame pointer, dynamic frames below the one of the caller
+        let start = match self.frame_size {
+            Some(frame_size) => frame.frame_pointer.saturating_sub(frame_size),
+            None => ebpf::MM_STACK_START,
+        };
+        let len = frame.frame_pointer.saturating_sub(start);
+        if let Some(shadow) = self.shadow_mut(start, len) {
+            shadow.fill(false);
+        }
+    }
+}
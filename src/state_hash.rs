@@ -0,0 +1,121 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Hashes of the execution state for divergence detection
+//!
+//! Comparing two full trace logs entry by entry pinpoints where an execution departs from
+//! another, e.g. between the interpreter and the JIT or between two runs of a
+//! non-deterministic program, but the full trace logs of long executions do not fit into
+//! memory. A [StateHashTrace] instead folds the entries into a running hash and only keeps it
+//! every `interval` instructions. Call [StateHashTrace::record] from [ContextObject::trace]
+//! while `Config::enable_instruction_tracing` is set, then [StateHashTrace::first_divergence]
+//! narrows the first difference down to a window of at most `interval` instructions, which a
+//! second, fully traced execution of only that window can inspect.
+//!
+//! [ContextObject::trace]: crate::vm::ContextObject::trace
+
+use crate::static_analysis::TraceLogEntry;
+use std::ops::Range;
+
+/// Running hash after a number of instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateHash {
+    /// Number of instructions folded into the hash
+    pub instructions: u64,
+    /// Pc of the last folded instruction
+    pub pc: u64,
+    /// Hash of the registers (including the pc) of all folded instructions
+    pub hash: u64,
+}
+
+/// Trace of the hashes of the execution state every `interval` instructions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateHashTrace {
+    /// Number of instructions between two recorded hashes, at least 1
+    interval: u64,
+    /// Running hash of all recorded entries
+    hash: u64,
+    /// Number of recorded entries
+    recorded: u64,
+    /// Running hash after every `interval` entries
+    hashes: Vec<StateHash>,
+}
+
+impl StateHashTrace {
+    /// Creates an empty trace which keeps the running hash every `interval` instructions
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            hash: 0,
+            recorded: 0,
+            hashes: Vec::new(),
+        }
+    }
+
+    /// Hashes an entire trace log
+    pub fn from_trace_log(interval: u64, trace_log: &[TraceLogEntry]) -> Self {
+        let mut trace = Self::new(interval);
+        for entry in trace_log {
+            trace.record(entry);
+        }
+        trace
+    }
+
+    /// Folds the registers before an instruction into the running hash
+    pub fn record(&mut self, entry: &TraceLogEntry) {
+        for value in entry {
+            // Multiplicative mixing as in FxHash, which is stable across platforms and releases
+            self.hash = (self.hash.rotate_left(5) ^ value).wrapping_mul(0x517c_c1b7_2722_0a95);
+        }
+        self.recorded += 1;
+        if self.recorded.is_multiple_of(self.interval) {
+            self.hashes.push(StateHash {
+                instructions: self.recorded,
+                pc: entry[11],
+                hash: self.hash,
+            });
+        }
+    }
+
+    /// Number of instructions between two recorded hashes
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Number of recorded instructions
+    pub fn recorded(&self) -> u64 {
+        self.recorded
+    }
+
+    /// Running hash of all recorded instructions
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Running hash after every `interval` instructions
+    pub fn hashes(&self) -> &[StateHash] {
+        &self.hashes
+    }
+
+    /// Indices of the instructions among which the first difference to `other` lies, `None`
+    /// if both recorded the same execution
+    ///
+    /// The window starts after the last matching hash and ends with the first differing one,
+    /// or with the end of the longer execution if one is a prefix of the other. Both traces
+    /// must use the same interval, otherwise the window covers the entire executions.
+    pub fn first_divergence(&self, other: &Self) -> Option<Range<u64>> {
+        if self.recorded == other.recorded && self.hash == other.hash {
+            return None;
+        }
+        let end = self.recorded.max(other.recorded);
+        if self.interval != other.interval {
+            return Some(0..end);
+        }
+        let matching = self
+            .hashes
+            .iter()
+            .zip(other.hashes.iter())
+            .take_while(|(a, b)| a == b)
+            .count() as u64;
+        let start = matching * self.interval;
+        Some(start..(start + self.interval).min(end))
+    }
+}
@@ -6,7 +6,9 @@ use crate::{
     ebpf,
     elf::Executable,
     error::EbpfError,
+    opcode_table::{opcode_info, InstructionClass, OperandSource},
     program::SBPFVersion,
+    taint::InputTaint,
     vm::{ContextObject, DynamicAnalysis},
 };
 use rustc_demangle::demangle;
@@ -124,7 +126,7 @@ impl Default for CfgNode {
     }
 }
 
-struct DummyContextObject {}
+pub(crate) struct DummyContextObject {}
 
 impl ContextObject for DummyContextObject {
     fn trace(&mut self, _state: [u64; 12]) {}
@@ -136,6 +138,57 @@ impl ContextObject for DummyContextObject {
     }
 }
 
+/// Origin of the value of a register, as far as the input pointer annotation is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PointerOrigin {
+    Unknown,
+    /// Address inside the input region
+    Input,
+    /// Pointer which was loaded from the input region
+    Loaded,
+}
+
+/// Instructions which load pointers from the input region and dereference them
+///
+/// The input region contains pointers (e.g. the `data` of an account) which the program
+/// follows. These annotations allow to attribute memory accesses to the input without
+/// tracking values at runtime.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputPointerAnnotations {
+    /// Pcs of 8 byte loads from the input region, the loaded values are treated as pointers
+    pub pointer_loads: BTreeSet<usize>,
+    /// Pcs of memory accesses through a pointer loaded at one of the `pointer_loads`
+    pub dereferences: BTreeSet<usize>,
+}
+
+/// Execution statistics of an instruction in a recorded trace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InstructionCoverage {
+    /// Number of times the instruction was executed
+    pub executions: u64,
+    /// Number of times a conditional jump was taken
+    pub taken: u64,
+    /// Number of times a conditional jump fell through
+    pub not_taken: u64,
+}
+
+/// Output format of [Analysis::disassemble_coverage]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverageFormat {
+    /// Plain text listing
+    Text,
+    /// Standalone HTML page with the instructions colored by their coverage
+    Html,
+}
+
+fn html_escape(string: &str) -> String {
+    string
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\"', "&quot;")
+}
+
 /// Result of the executable analysis
 pub struct Analysis<'a> {
     /// The program which is analyzed
@@ -156,6 +209,10 @@ pub struct Analysis<'a> {
     pub dfg_forward_edges: BTreeMap<DfgNode, BTreeSet<DfgEdge>>,
     /// Data flow edges (the keys are DfgEdge destinations)
     pub dfg_reverse_edges: BTreeMap<DfgNode, BTreeSet<DfgEdge>>,
+    /// Indexed by pc, instructions which can never touch an input derived value
+    ///
+    /// See [Self::input_independent_instructions].
+    pub input_independent: Vec<bool>,
 }
 
 impl<'a> Analysis<'a> {
@@ -204,6 +261,7 @@ impl<'a> Analysis<'a> {
             super_root: insn_ptr,
             dfg_forward_edges: BTreeMap::new(),
             dfg_reverse_edges: BTreeMap::new(),
+            input_independent: Vec::new(),
         };
         result.split_into_basic_blocks(false, executable.get_sbpf_version());
         result.control_flow_graph_tarjan();
@@ -211,6 +269,7 @@ impl<'a> Analysis<'a> {
         result.label_basic_blocks();
         let basic_block_outputs = result.intra_basic_block_data_flow();
         result.inter_basic_block_data_flow(basic_block_outputs);
+        result.input_independent = result.input_independent_instructions();
         Ok(result)
     }
 
@@ -446,6 +505,16 @@ impl<'a> Analysis<'a> {
         Ok(())
     }
 
+    /// Version the analyzed executable is verified for
+    pub fn sbpf_version(&self) -> SBPFVersion {
+        self.executable.get_sbpf_version()
+    }
+
+    /// The analyzed executable
+    pub(crate) fn executable(&self) -> &Executable<DummyContextObject> {
+        self.executable
+    }
+
     /// Generates assembler code for a single instruction
     pub fn disassemble_instruction(&self, insn: &ebpf::Insn, pc: usize) -> String {
         disassemble_instruction(
@@ -505,6 +574,152 @@ impl<'a> Analysis<'a> {
         Ok(())
     }
 
+    /// Execution count and branch outcomes per pc of a trace log
+    ///
+    /// A conditional jump with an offset of 0 is always counted as not taken.
+    pub fn instruction_coverage(
+        &self,
+        trace_log: &[TraceLogEntry],
+    ) -> BTreeMap<usize, InstructionCoverage> {
+        let sbpf_version = self.executable.get_sbpf_version();
+        let mut coverage = BTreeMap::<usize, InstructionCoverage>::new();
+        for (index, entry) in trace_log.iter().enumerate() {
+            let pc = entry[11] as usize;
+            let statistics = coverage.entry(pc).or_default();
+            statistics.executions += 1;
+            let Some(next_pc) = trace_log.get(index + 1).map(|next| next[11] as usize) else {
+                continue;
+            };
+            let Ok(insn_index) = self.instructions.binary_search_by_key(&pc, |insn| insn.ptr)
+            else {
+                continue;
+            };
+            let insn = &self.instructions[insn_index];
+            if opcode_info(insn.opc, sbpf_version)
+                .is_some_and(|info| info.class == InstructionClass::ConditionalJump)
+            {
+                if insn.off != 0 && next_pc as i64 == pc as i64 + 1 + insn.off as i64 {
+                    statistics.taken += 1;
+                } else {
+                    statistics.not_taken += 1;
+                }
+            }
+        }
+        coverage
+    }
+
+    /// Generates assembler code annotated with the [Self::instruction_coverage] of a trace log
+    ///
+    /// Every instruction is prefixed by its execution count, the taken (T) and not taken (N)
+    /// counts of conditional jumps and an `I` if it accesses the input region through a pointer
+    /// found by [Self::annotate_input_pointers].
+    pub fn disassemble_coverage<W: std::io::Write>(
+        &self,
+        output: &mut W,
+        trace_log: &[TraceLogEntry],
+        format: CoverageFormat,
+    ) -> std::io::Result<()> {
+        let sbpf_version = self.executable.get_sbpf_version();
+        let coverage = self.instruction_coverage(trace_log);
+        let input_pointers = self.annotate_input_pointers();
+        let mut lines = Vec::new();
+        let (mut executed, mut branches, mut covered_branches) = (0, 0, 0);
+        let mut last_basic_block = usize::MAX;
+        for (pc, insn) in self.instructions.iter().enumerate() {
+            let mut label = Vec::new();
+            self.disassemble_label(
+                &mut label,
+                Some(insn) == self.instructions.first(),
+                insn.ptr,
+                &mut last_basic_block,
+            )?;
+            for line in String::from_utf8_lossy(&label).lines() {
+                lines.push((None, line.to_string()));
+            }
+            let statistics = coverage.get(&insn.ptr).copied().unwrap_or_default();
+            let is_conditional_jump = opcode_info(insn.opc, sbpf_version)
+                .is_some_and(|info| info.class == InstructionClass::ConditionalJump);
+            let branch = if is_conditional_jump {
+                branches += 1;
+                if statistics.taken > 0 && statistics.not_taken > 0 {
+                    covered_branches += 1;
+                }
+                format!("T:{} N:{}", statistics.taken, statistics.not_taken)
+            } else {
+                String::new()
+            };
+            let class = if statistics.executions == 0 {
+                "miss"
+            } else if is_conditional_jump && (statistics.taken == 0 || statistics.not_taken == 0) {
+                executed += 1;
+                "partial"
+            } else {
+                executed += 1;
+                "hit"
+            };
+            let executions = if statistics.executions == 0 {
+                "-".to_string()
+            } else {
+                statistics.executions.to_string()
+            };
+            let input = if input_pointers.pointer_loads.contains(&insn.ptr)
+                || input_pointers.dereferences.contains(&insn.ptr)
+            {
+                "I"
+            } else {
+                " "
+            };
+            lines.push((
+                Some(class),
+                format!(
+                    "{:>8} {:<15} {} {}",
+                    executions,
+                    branch,
+                    input,
+                    self.disassemble_instruction(insn, pc)
+                ),
+            ));
+        }
+        let summary = format!(
+            "{} of {} instructions executed, {} of {} branches taken in both directions",
+            executed,
+            self.instructions.len(),
+            covered_branches,
+            branches,
+        );
+        match format {
+            CoverageFormat::Text => {
+                writeln!(output, "; {summary}")?;
+                for (_class, line) in lines.iter() {
+                    writeln!(output, "{line}")?;
+                }
+            }
+            CoverageFormat::Html => {
+                writeln!(output, "<!DOCTYPE html>")?;
+                writeln!(output, "<html><head><meta charset=\"utf-8\"><style>")?;
+                writeln!(
+                    output,
+                    ".hit {{ background: #cfc; }} .partial {{ background: #ffc; }} .miss {{ background: #fcc; }}"
+                )?;
+                writeln!(output, "</style></head><body>")?;
+                writeln!(output, "<p>{}</p><pre>", html_escape(&summary))?;
+                for (class, line) in lines.iter() {
+                    match class {
+                        Some(class) => writeln!(
+                            output,
+                            "<span class=\"{}\">{}</span>",
+                            class,
+                            html_escape(line)
+                        )?,
+                        None => writeln!(output, "{}", html_escape(line))?,
+                    }
+                }
+                writeln!(output, "</pre></body></html>")?;
+            }
+        }
+        Ok(())
+    }
+
     /// Iterates over the cfg_nodes while providing the PC range of the function they belong to.
     pub fn iter_cfg_by_function(
         &self,
@@ -526,30 +741,232 @@ impl<'a> Analysis<'a> {
             })
     }
 
+    /// Finds the loads of pointers from the input region and the accesses through them
+    ///
+    /// Starts at the entrypoint with r1 pointing to the input and follows the copies and
+    /// offsets of it, also into the functions called with them as arguments. Each function
+    /// is scanned in program order, so values joining from different paths are approximated.
+    pub fn annotate_input_pointers(&self) -> InputPointerAnnotations {
+        let sbpf_version = self.executable.get_sbpf_version();
+        let mut result = InputPointerAnnotations::default();
+        let mut entry_states = [PointerOrigin::Unknown; 11];
+        entry_states[1] = PointerOrigin::Input;
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![(self.entrypoint, entry_states)];
+        while let Some((function_start, entry_states)) = pending.pop() {
+            if !visited.insert((function_start, entry_states)) {
+                continue;
+            }
+            let function_end = self
+                .functions
+                .range(function_start + 1..)
+                .next()
+                .map(|(pc, _function)| *pc)
+                .unwrap_or(usize::MAX);
+            let mut states = entry_states;
+            for insn in self
+                .instructions
+                .iter()
+                .skip_while(|insn| insn.ptr < function_start)
+                .take_while(|insn| insn.ptr < function_end)
+            {
+                let dst = insn.dst as usize % states.len();
+                if let Some((is_load, base, size)) =
+                    crate::harness::memory_access(sbpf_version, insn)
+                {
+                    let base_state = states[base as usize % states.len()];
+                    if base_state == PointerOrigin::Loaded {
+                        result.dereferences.insert(insn.ptr);
+                    }
+                    if is_load {
+                        states[dst] = if base_state == PointerOrigin::Input && size == 8 {
+                            result.pointer_loads.insert(insn.ptr);
+                            PointerOrigin::Loaded
+                        } else {
+                            PointerOrigin::Unknown
+                        };
+                    }
+                    continue;
+                }
+                match insn.opc {
+                    ebpf::MOV64_REG => {
+                        states[dst] = states[insn.src as usize % states.len()];
+                    }
+                    ebpf::ADD64_IMM | ebpf::SUB64_IMM => {}
+                    ebpf::ADD64_REG
+                        if states[insn.src as usize % states.len()] == PointerOrigin::Unknown => {}
+                    ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::SYSCALL => {
+                        if insn.opc == ebpf::CALL_IMM {
+                            let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+                            if let Some((_function_name, target_pc)) =
+                                self.executable.get_function_registry().lookup_by_key(key)
+                            {
+                                let mut callee_states = [PointerOrigin::Unknown; 11];
+                                callee_states[1..=5].copy_from_slice(&states[1..=5]);
+                                pending.push((target_pc, callee_states));
+                            }
+                        }
+                        states[0..=5].fill(PointerOrigin::Unknown);
+                    }
+                    _ if (insn.opc & ebpf::BPF_CLS_MASK) == ebpf::BPF_JMP => {}
+                    _ => states[dst] = PointerOrigin::Unknown,
+                }
+            }
+        }
+        result
+    }
+
+    /// Finds the instructions which can never read or write an input derived value
+    ///
+    /// Returns a bitmap indexed by pc. Registers only become input derived by loads, as any
+    /// memory could hold a copy of the input, and by mixing with such registers. This is
+    /// followed to a fixpoint over the control-flow graph and into the called functions.
+    /// After a call `r0` to `r5` are assumed to be input derived, the callee saved registers
+    /// keep their state. Loads, stores, calls and exits are never input independent.
+    /// Unreachable instructions are not input independent either.
+    pub fn input_independent_instructions(&self) -> Vec<bool> {
+        let sbpf_version = self.executable.get_sbpf_version();
+        let mut result = vec![false; self.super_root];
+        // Bit i is set if register i may hold an input derived value
+        let mut entry_states = BTreeMap::<usize, u16>::new();
+        let mut pending = vec![self.entrypoint];
+        entry_states.insert(self.entrypoint, 0);
+        fn propagate(
+            pending: &mut Vec<usize>,
+            entry_states: &mut BTreeMap<usize, u16>,
+            pc: usize,
+            state: u16,
+        ) {
+            let entry_state = entry_states.entry(pc).or_insert_with(|| {
+                pending.push(pc);
+                state
+            });
+            if *entry_state | state != *entry_state {
+                *entry_state |= state;
+                pending.push(pc);
+            }
+        }
+        while let Some(cfg_node_start) = pending.pop() {
+            let Some(cfg_node) = self.cfg_nodes.get(&cfg_node_start) else {
+                continue;
+            };
+            let mut state = entry_states[&cfg_node_start];
+            for insn in &self.instructions[cfg_node.instructions.clone()] {
+                let Some(info) = opcode_info(insn.opc, sbpf_version) else {
+                    state = (1 << 11) - 1;
+                    continue;
+                };
+                let dst = 1u16 << (insn.dst % 11);
+                let src = if info.source == OperandSource::Register {
+                    1u16 << (insn.src % 11)
+                } else {
+                    0
+                };
+                let independent = match info.class {
+                    InstructionClass::Jump => true,
+                    InstructionClass::LoadImmediate
+                    | InstructionClass::Alu
+                    | InstructionClass::Product
+                    | InstructionClass::ConditionalJump => state & (dst | src) == 0,
+                    _ => false,
+                };
+                result[insn.ptr] = independent;
+                match info.class {
+                    InstructionClass::LoadImmediate => state &= !dst,
+                    InstructionClass::Alu => match info.mnemonic {
+                        "mov64" | "mov32" => {
+                            state = if state & src != 0 {
+                                state | dst
+                            } else {
+                                state & !dst
+                            }
+                        }
+                        "hor64" => {}
+                        _ if state & src != 0 => state |= dst,
+                        _ => {}
+                    },
+                    InstructionClass::Product if state & src != 0 => state |= dst,
+                    InstructionClass::Load => state |= dst,
+                    InstructionClass::Call => {
+                        let callees = if insn.opc == ebpf::CALL_REG {
+                            self.functions.keys().copied().collect()
+                        } else {
+                            let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+                            self.executable
+                                .get_function_registry()
+                                .lookup_by_key(key)
+                                .map(|(_function_name, target_pc)| vec![target_pc])
+                                .unwrap_or_default()
+                        };
+                        for callee in callees {
+                            propagate(&mut pending, &mut entry_states, callee, state);
+                        }
+                        state |= 0b11_1111;
+                    }
+                    InstructionClass::Syscall => state &= !1,
+                    _ => {}
+                }
+            }
+            for destination in &cfg_node.destinations {
+                propagate(&mut pending, &mut entry_states, *destination, state);
+            }
+        }
+        result
+    }
+
     /// Generates a graphviz DOT of the analyzed executable
     pub fn visualize_graphically<W: std::io::Write>(
         &self,
         output: &mut W,
         dynamic_analysis: Option<&DynamicAnalysis>,
     ) -> std::io::Result<()> {
-        fn html_escape(string: &str) -> String {
-            string
-                .replace('&', "&amp;")
-                .replace('<', "&lt;")
-                .replace('>', "&gt;")
-                .replace('\"', "&quot;")
-        }
+        self.visualize(output, dynamic_analysis, None)
+    }
+
+    /// Generates a graphviz DOT with the basic blocks highlighted which contain comparisons
+    /// of input derived values
+    ///
+    /// The tooltip of a highlighted basic block names the input bytes its comparisons depend
+    /// on, the edges are weighted by their execution count as in [Self::visualize_graphically].
+    pub fn visualize_with_taint<W: std::io::Write>(
+        &self,
+        output: &mut W,
+        dynamic_analysis: &DynamicAnalysis,
+        taint: &InputTaint,
+    ) -> std::io::Result<()> {
+        self.visualize(output, Some(dynamic_analysis), Some(taint))
+    }
+
+    fn visualize<W: std::io::Write>(
+        &self,
+        output: &mut W,
+        dynamic_analysis: Option<&DynamicAnalysis>,
+        taint: Option<&InputTaint>,
+    ) -> std::io::Result<()> {
         fn emit_cfg_node<W: std::io::Write>(
             output: &mut W,
             dynamic_analysis: Option<&DynamicAnalysis>,
+            taint: Option<&InputTaint>,
             analysis: &Analysis,
             function_range: std::ops::Range<usize>,
             alias_nodes: &mut HashSet<usize>,
             cfg_node_start: usize,
         ) -> std::io::Result<()> {
             let cfg_node = &analysis.cfg_nodes[&cfg_node_start];
-            writeln!(output, "    lbb_{} [label=<<table border=\"0\" cellborder=\"0\" cellpadding=\"3\">{}</table>>];",
+            let tainted_offsets = taint.and_then(|taint| {
+                analysis.instructions[cfg_node.instructions.clone()]
+                    .iter()
+                    .filter_map(|insn| taint.tainted_comparisons.get(&insn.ptr))
+                    .map(|offsets| format!("input[{}..{}]", offsets.start, offsets.end))
+                    .reduce(|a, b| format!("{a} {b}"))
+            });
+            let attributes = match tainted_offsets {
+                Some(offsets) => format!("fillcolor=\"#ffc0c0\";tooltip=\"{offsets}\";"),
+                None => String::new(),
+            };
+            writeln!(output, "    lbb_{} [{}label=<<table border=\"0\" cellborder=\"0\" cellpadding=\"3\">{}</table>>];",
                 cfg_node_start,
+                attributes,
                 analysis.instructions[cfg_node.instructions.clone()].iter()
                 .enumerate().map(|(pc, insn)| {
                     let desc = analysis.disassemble_instruction(
@@ -581,6 +998,7 @@ impl<'a> Analysis<'a> {
                 emit_cfg_node(
                     output,
                     dynamic_analysis,
+                    taint,
                     analysis,
                     function_range.clone(),
                     alias_nodes,
@@ -627,6 +1045,7 @@ impl<'a> Analysis<'a> {
             emit_cfg_node(
                 output,
                 dynamic_analysis,
+                taint,
                 self,
                 *function_start..function_end,
                 &mut alias_nodes,
@@ -0,0 +1,306 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Emulation of the Solana sysvars
+//!
+//! Programs read the [Clock], [Rent] and [EpochSchedule] of the cluster through the syscalls
+//! `sol_get_clock_sysvar`, `sol_get_rent_sysvar` and `sol_get_epoch_schedule_sysvar`, which
+//! copy the sysvar to a buffer of the program. [register_sysvar_syscalls] registers
+//! implementations of them for any [ContextObject], which copy from a readonly region at
+//! [MM_SYSVARS_START] holding the serialized [Sysvars]. So the values are configured per
+//! execution by the bytes mapped there, without a runtime.
+//!
+//! When the region is [tainted](Sysvars::region), the taint replay of
+//! [InputTaint](crate::taint::InputTaint) follows the sysvar bytes into the program, like the
+//! bytes of the input. E.g. with taint offsets after the end of the input, a fuzzer can tell
+//! which branches depend on the clock and mutate the sysvars alongside the input.
+
+use crate::{
+    declare_builtin_function, ebpf,
+    elf::ElfError,
+    error::EbpfError,
+    memory_region::{AccessType, MemoryMapping, MemoryRegion},
+    program::BuiltinProgram,
+    vm::ContextObject,
+};
+
+/// Virtual address of the serialized [Sysvars], the slot after the input region
+pub const MM_SYSVARS_START: u64 = ebpf::MM_INPUT_START + ebpf::MM_REGION_SIZE;
+
+/// Errors of the sysvar syscalls
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum SysvarError {
+    /// No sysvars are mapped at [MM_SYSVARS_START]
+    #[error("Sysvar {0} is not mapped")]
+    NotMapped(&'static str),
+}
+
+/// The sysvars emulated in this module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sysvar {
+    /// `sol_get_clock_sysvar(addr)`
+    Clock,
+    /// `sol_get_rent_sysvar(addr)`
+    Rent,
+    /// `sol_get_epoch_schedule_sysvar(addr)`
+    EpochSchedule,
+}
+
+impl Sysvar {
+    /// All emulated sysvars
+    pub const ALL: [Self; 3] = [Self::Clock, Self::Rent, Self::EpochSchedule];
+
+    /// Name of the syscall
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Clock => "sol_get_clock_sysvar",
+            Self::Rent => "sol_get_rent_sysvar",
+            Self::EpochSchedule => "sol_get_epoch_schedule_sysvar",
+        }
+    }
+
+    /// Offset of the sysvar in the serialized [Sysvars]
+    pub fn offset(self) -> u64 {
+        match self {
+            Self::Clock => 0,
+            Self::Rent => Clock::SIZE,
+            Self::EpochSchedule => Clock::SIZE + Rent::SIZE,
+        }
+    }
+
+    /// Number of bytes the syscall copies
+    pub fn size(self) -> u64 {
+        match self {
+            Self::Clock => Clock::SIZE,
+            Self::Rent => Rent::SIZE,
+            Self::EpochSchedule => EpochSchedule::SIZE,
+        }
+    }
+
+    /// Looks up the sysvar whose syscall name hashes to the key of a syscall instruction
+    pub fn from_hash(hash: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|sysvar| ebpf::hash_symbol_name(sysvar.name().as_bytes()) == hash)
+    }
+}
+
+/// Slot and time of the cluster
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Clock {
+    /// Current slot
+    pub slot: u64,
+    /// Unix timestamp of the first slot of the epoch
+    pub epoch_start_timestamp: i64,
+    /// Current epoch
+    pub epoch: u64,
+    /// Epoch for which the leader schedule was most recently calculated
+    pub leader_schedule_epoch: u64,
+    /// Estimated unix timestamp of the slot
+    pub unix_timestamp: i64,
+}
+
+impl Clock {
+    /// Size of the `repr(C)` layout
+    pub const SIZE: u64 = 40;
+
+    fn serialize(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.slot.to_le_bytes());
+        bytes.extend_from_slice(&self.epoch_start_timestamp.to_le_bytes());
+        bytes.extend_from_slice(&self.epoch.to_le_bytes());
+        bytes.extend_from_slice(&self.leader_schedule_epoch.to_le_bytes());
+        bytes.extend_from_slice(&self.unix_timestamp.to_le_bytes());
+    }
+}
+
+/// Rent parameters of the cluster
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rent {
+    /// Rental rate in lamports per byte and year
+    pub lamports_per_byte_year: u64,
+    /// Years of rent an account has to hold to be exempt
+    pub exemption_threshold: f64,
+    /// Percentage of the collected rent which is burned
+    pub burn_percent: u8,
+}
+
+impl Default for Rent {
+    fn default() -> Self {
+        Self {
+            lamports_per_byte_year: 3480,
+            exemption_threshold: 2.0,
+            burn_percent: 50,
+        }
+    }
+}
+
+impl Rent {
+    /// Size of the `repr(C)` layout, including the padding after `burn_percent`
+    pub const SIZE: u64 = 24;
+
+    fn serialize(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.lamports_per_byte_year.to_le_bytes());
+        bytes.extend_from_slice(&self.exemption_threshold.to_le_bytes());
+        bytes.push(self.burn_percent);
+        bytes.extend_from_slice(&[0; 7]);
+    }
+}
+
+/// Epoch lengths of the cluster
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochSchedule {
+    /// Number of slots in an epoch after the warmup
+    pub slots_per_epoch: u64,
+    /// Number of slots before an epoch its leader schedule is calculated
+    pub leader_schedule_slot_offset: u64,
+    /// Whether the epochs start short and grow
+    pub warmup: bool,
+    /// First epoch with `slots_per_epoch` slots
+    pub first_normal_epoch: u64,
+    /// First slot of `first_normal_epoch`
+    pub first_normal_slot: u64,
+}
+
+impl Default for EpochSchedule {
+    fn default() -> Self {
+        Self {
+            slots_per_epoch: 432_000,
+            leader_schedule_slot_offset: 432_000,
+            warmup: true,
+            first_normal_epoch: 14,
+            first_normal_slot: 524_256,
+        }
+    }
+}
+
+impl EpochSchedule {
+    /// Size of the `repr(C)` layout, including the padding after `warmup`
+    pub const SIZE: u64 = 40;
+
+    fn serialize(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.slots_per_epoch.to_le_bytes());
+        bytes.extend_from_slice(&self.leader_schedule_slot_offset.to_le_bytes());
+        bytes.push(self.warmup as u8);
+        bytes.extend_from_slice(&[0; 7]);
+        bytes.extend_from_slice(&self.first_normal_epoch.to_le_bytes());
+        bytes.extend_from_slice(&self.first_normal_slot.to_le_bytes());
+    }
+}
+
+/// Values of all emulated sysvars
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sysvars {
+    /// Returned by `sol_get_clock_sysvar`
+    pub clock: Clock,
+    /// Returned by `sol_get_rent_sysvar`
+    pub rent: Rent,
+    /// Returned by `sol_get_epoch_schedule_sysvar`
+    pub epoch_schedule: EpochSchedule,
+}
+
+impl Sysvars {
+    /// Serializes the sysvars at their [offsets](Sysvar::offset)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity((Clock::SIZE + Rent::SIZE + EpochSchedule::SIZE) as usize);
+        self.clock.serialize(&mut bytes);
+        self.rent.serialize(&mut bytes);
+        self.epoch_schedule.serialize(&mut bytes);
+        bytes
+    }
+
+    /// Readonly region of the bytes returned by [Self::to_bytes] at [MM_SYSVARS_START]
+    ///
+    /// With a `taint_offset` the bytes are taint sources, labeled with the input offsets
+    /// starting there.
+    pub fn region(bytes: &[u8], taint_offset: Option<u64>) -> MemoryRegion {
+        match taint_offset {
+            Some(taint_offset) => {
+                MemoryRegion::new_readonly_tainted(bytes, MM_SYSVARS_START, taint_offset)
+            }
+            None => MemoryRegion::new_readonly(bytes, MM_SYSVARS_START),
+        }
+    }
+}
+
+/// Registers the syscalls of all sysvars
+pub fn register_sysvar_syscalls<C: ContextObject>(
+    loader: &mut BuiltinProgram<C>,
+) -> Result<(), ElfError> {
+    loader.register_function(Sysvar::Clock.name(), SyscallGetClockSysvar::vm::<C>)?;
+    loader.register_function(Sysvar::Rent.name(), SyscallGetRentSysvar::vm::<C>)?;
+    loader.register_function(
+        Sysvar::EpochSchedule.name(),
+        SyscallGetEpochScheduleSysvar::vm::<C>,
+    )?;
+    Ok(())
+}
+
+/// Copies a sysvar from the region at [MM_SYSVARS_START] to `addr`
+fn get_sysvar(
+    memory_mapping: &MemoryMapping,
+    sysvar: Sysvar,
+    addr: u64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let src: Result<u64, EbpfError> = memory_mapping
+        .map(
+            AccessType::Load,
+            MM_SYSVARS_START + sysvar.offset(),
+            sysvar.size(),
+        )
+        .into();
+    let src = src.map_err(|_| SysvarError::NotMapped(sysvar.name()))?;
+    let dst: Result<u64, EbpfError> = memory_mapping
+        .map(AccessType::Store, addr, sysvar.size())
+        .into();
+    // Both ranges were mapped with the size of the sysvar
+    unsafe { std::ptr::copy(src as *const u8, dst? as *mut u8, sysvar.size() as usize) };
+    Ok(0)
+}
+
+declare_builtin_function!(
+    /// `sol_get_clock_sysvar(addr)`
+    SyscallGetClockSysvar<C: ContextObject>,
+    fn rust(
+        _context_object: &mut C,
+        addr: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        get_sysvar(memory_mapping, Sysvar::Clock, addr)
+    }
+);
+
+declare_builtin_function!(
+    /// `sol_get_rent_sysvar(addr)`
+    SyscallGetRentSysvar<C: ContextObject>,
+    fn rust(
+        _context_object: &mut C,
+        addr: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        get_sysvar(memory_mapping, Sysvar::Rent, addr)
+    }
+);
+
+declare_builtin_function!(
+    /// `sol_get_epoch_schedule_sysvar(addr)`
+    SyscallGetEpochScheduleSysvar<C: ContextObject>,
+    fn rust(
+        _context_object: &mut C,
+        addr: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        get_sysvar(memory_mapping, Sysvar::EpochSchedule, addr)
+    }
+);
@@ -0,0 +1,1170 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Input taint
+//!
+//! A value derived from the input is labeled with the offsets of the input bytes it was
+//! derived from. Sets of such offsets, e.g. the bytes of an account key, are [TaintLabels]
+//! with the usual set operations, so questions like whether the key of an account ever meets
+//! the instruction data become a single call. [LabelStatistics] tells how many copies of an
+//! input byte a per byte taint state holds.
+//!
+//! [InputTaint] replays a trace log and follows the values loaded from the input region (or
+//! other taint sources, see [MemoryRegion::new_readonly_tainted]) through registers and
+//! memory, so the conditional jumps whose outcome depends on the input can be attributed to
+//! the input bytes which drive them, e.g. in
+//! [Analysis::visualize_with_taint].
+//!
+//! The taint is tracked per byte of registers and memory. Arithmetic mixes the bytes of its
+//! operands, so every byte of its result is derived from the hull of their offset ranges.
+//! Constants are never tainted, also when they are assembled by `mov32` and `hor64`, while a
+//! `hor64` into an input derived value keeps the taint of the bits it does not set.
+//! Memory is tracked by the virtual addresses recorded in the trace, so values stay tainted
+//! across the frame switches of calls and the frame pointer adjustments of dynamic stack
+//! frames. The callee saved registers are restored when a function returns.
+//! Values are only followed through the syscalls of [MemoryBuiltin] and the
+//! [Sysvar] syscalls, the results of other syscalls are untainted.
+//! The instructions which the static [Analysis::input_independent] proves to never touch an
+//! input derived value are skipped.
+//! The access widths of loads and stores come from the [opcode table](opcode_info), an
+//! instruction whose width does not fit a register is reported in [InputTaint::errors]
+//! instead of aborting the replay. A panic of the replay is reported there as well, with the
+//! taint of the instructions before it.
+//!
+//! Tainted values reaching a [TaintSink], e.g. a store to the lamports of an account, are
+//! reported as [PolicyViolation]s, together with the instructions which propagated them.
+//! For a failed execution, [InputTaint::crash_relevant_bytes] tells the input bytes the
+//! faulting instruction depends on.
+//!
+//! In concolic mode ([InputTaint::from_trace_log_concolic]) every conditional jump with a
+//! tainted operand is additionally recorded as a [PathConstraint], with the concrete values and
+//! the per byte taint of both operands. The list describes the path the input took and can be
+//! translated into a query for an SMT solver, e.g. to negate the last constraint. With the
+//! `trace-export` feature it is serializable. The constants the instruction data is compared
+//! against are collected by [InputTaint::extract_dictionary].
+
+use crate::{
+    accounts::{AccountField, AccountLayout},
+    ebpf,
+    memory_builtins::MemoryBuiltin,
+    memory_region::MemoryRegion,
+    opcode_table::{opcode_info, InstructionClass, OpcodeInfo, OperandSource},
+    static_analysis::{Analysis, TraceLogEntry},
+    sysvars::{Sysvar, MM_SYSVARS_START},
+    vm::panic_message,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    rc::Rc,
+};
+
+/// Input offsets a value is derived from
+pub type InputOffsets = Range<u64>;
+
+/// Set of input offsets, kept as sorted and disjoint ranges
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaintLabels(Vec<InputOffsets>);
+
+impl TaintLabels {
+    /// Set of the offsets in the ranges, which may overlap and come in any order
+    pub fn new(ranges: impl IntoIterator<Item = InputOffsets>) -> Self {
+        let mut ranges = ranges
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+        let mut merged: Vec<InputOffsets> = Vec::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        Self(merged)
+    }
+
+    /// Offsets of a field in the serialized input, of all accounts if `account` is `None`
+    pub fn of_field(layout: &AccountLayout, field: AccountField, account: Option<usize>) -> Self {
+        Self::new(
+            layout
+                .ranges_of(field)
+                .filter(|(_range, other)| account.is_none() || *other == account)
+                .map(|(range, _account)| range.start as u64..range.end as u64),
+        )
+    }
+
+    /// The sorted and disjoint ranges
+    pub fn ranges(&self) -> &[InputOffsets] {
+        &self.0
+    }
+
+    /// Whether the set has no offsets
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Number of offsets in the set
+    pub fn len(&self) -> u64 {
+        self.0.iter().map(|range| range.end - range.start).sum()
+    }
+
+    /// Whether the offset is in the set
+    pub fn contains(&self, offset: u64) -> bool {
+        self.0.iter().any(|range| range.contains(&offset))
+    }
+
+    /// Offsets in either set
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(self.0.iter().chain(other.0.iter()).cloned())
+    }
+
+    /// Offsets in both sets
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self::new(self.0.iter().flat_map(|a| {
+            other
+                .0
+                .iter()
+                .map(move |b| a.start.max(b.start)..a.end.min(b.end))
+        }))
+    }
+
+    /// Offsets in this set but not in the other one
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        for range in self.0.iter() {
+            let mut start = range.start;
+            for excluded in other.0.iter() {
+                if excluded.end <= start || range.end <= excluded.start {
+                    continue;
+                }
+                ranges.push(start..excluded.start.max(start));
+                start = excluded.end;
+            }
+            ranges.push(start..range.end.max(start));
+        }
+        Self::new(ranges)
+    }
+
+    /// Whether all offsets of this set are in the other one
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.difference(other).is_empty()
+    }
+
+    /// Whether the sets share an offset
+    pub fn intersects(&self, other: &Self) -> bool {
+        !self.intersection(other).is_empty()
+    }
+}
+
+impl std::iter::FromIterator<InputOffsets> for TaintLabels {
+    fn from_iter<I: IntoIterator<Item = InputOffsets>>(ranges: I) -> Self {
+        Self::new(ranges)
+    }
+}
+
+/// Number of copies of an input byte in a taint state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LabelStatistics {
+    /// Registers with a byte derived from it
+    pub registers: usize,
+    /// Bytes of registers derived from it
+    pub register_bytes: usize,
+    /// Bytes of memory derived from it
+    pub memory_bytes: usize,
+}
+
+impl LabelStatistics {
+    /// Counts the bytes derived from the input byte at `label`
+    ///
+    /// `registers` holds the taint of every byte of every register and `memory` the taint of
+    /// the tainted bytes of memory.
+    pub fn count<'a>(
+        label: u64,
+        registers: &[[Option<InputOffsets>; 8]],
+        memory: impl IntoIterator<Item = &'a InputOffsets>,
+    ) -> Self {
+        let mut statistics = Self::default();
+        for register in registers.iter() {
+            let bytes = register
+                .iter()
+                .flatten()
+                .filter(|offsets| offsets.contains(&label))
+                .count();
+            statistics.registers += (bytes > 0) as usize;
+            statistics.register_bytes += bytes;
+        }
+        statistics.memory_bytes = memory
+            .into_iter()
+            .filter(|offsets| offsets.contains(&label))
+            .count();
+        statistics
+    }
+}
+
+fn merge(a: Option<InputOffsets>, b: Option<InputOffsets>) -> Option<InputOffsets> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.start.min(b.start)..a.end.max(b.end)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Instruction which propagated a tainted value, linked to the one it got the value from
+#[derive(Debug, PartialEq, Eq)]
+struct Propagation {
+    pc: usize,
+    previous: Option<Rc<Propagation>>,
+}
+
+impl Propagation {
+    fn extend(previous: Option<Rc<Self>>, pc: usize) -> Rc<Self> {
+        Rc::new(Self { pc, previous })
+    }
+
+    /// Pcs from the first instruction to this one
+    fn pcs(&self) -> Vec<usize> {
+        let mut pcs = vec![self.pc];
+        let mut link = self.previous.as_ref();
+        while let Some(propagation) = link {
+            pcs.push(propagation.pc);
+            link = propagation.previous.as_ref();
+        }
+        pcs.reverse();
+        pcs
+    }
+}
+
+/// Taint of the bytes of a register, in little endian order, and how the value got there
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct RegisterTaint([Option<InputOffsets>; 8], Option<Rc<Propagation>>);
+
+impl RegisterTaint {
+    /// Every byte derived from all input bytes of the operands
+    fn mix(a: &Self, b: &Self) -> Self {
+        let offsets = merge(a.hull(), b.hull());
+        Self(
+            std::array::from_fn(|_| offsets.clone()),
+            a.1.clone().or_else(|| b.1.clone()),
+        )
+    }
+
+    fn hull(&self) -> Option<InputOffsets> {
+        self.0.iter().cloned().fold(None, merge)
+    }
+
+    /// Keeps the lower `len` bytes, the others hold constant zeros or ones
+    fn truncate(mut self, len: usize) -> Self {
+        for byte in self.0.iter_mut().skip(len) {
+            *byte = None;
+        }
+        self
+    }
+
+    /// Ors `imm` into the upper half, a byte only becomes constant if `imm` sets all its bits
+    fn or_upper_half(mut self, imm: u32) -> Self {
+        for (byte, imm) in self.0.iter_mut().skip(4).zip(imm.to_le_bytes()) {
+            if imm == u8::MAX {
+                *byte = None;
+            }
+        }
+        self
+    }
+}
+
+/// An instruction the replay could not follow, its effect on the taint is skipped
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TaintError {
+    /// The access width of a load or store in the opcode table does not fit a register
+    #[error("access width {width} of opcode {opcode:#04x} at pc {pc} does not fit a register")]
+    AccessWidth {
+        /// Pc of the instruction
+        pc: usize,
+        /// Opcode of the instruction
+        opcode: u8,
+        /// Width in the opcode table
+        width: u8,
+    },
+    /// The replay panicked, the taint only covers the instructions before
+    #[error("the taint replay panicked: {message}")]
+    Internal {
+        /// Message of the panic
+        message: String,
+    },
+}
+
+/// Number of bytes a load or store accesses, according to the [opcode table](opcode_info)
+fn access_width(pc: usize, insn: &ebpf::Insn, info: &OpcodeInfo) -> Result<usize, TaintError> {
+    match info.width {
+        1..=8 => Ok(info.width as usize),
+        width => Err(TaintError::AccessWidth {
+            pc,
+            opcode: insn.opc,
+            width,
+        }),
+    }
+}
+
+/// Whether a call invokes a syscall instead of a function, decided like the interpreter does
+///
+/// Before SBPFv3 `call imm` invokes the syscall with the hash `imm` if the loader has one.
+fn is_syscall(analysis: &Analysis, insn: &ebpf::Insn, info: &OpcodeInfo) -> bool {
+    match info.class {
+        InstructionClass::Syscall => true,
+        InstructionClass::Call => {
+            insn.opc == ebpf::CALL_IMM
+                && !analysis.sbpf_version().static_syscalls()
+                && analysis
+                    .executable()
+                    .get_loader()
+                    .get_function_registry()
+                    .lookup_by_key(insn.imm as u32)
+                    .is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Adds the offsets to the ones recorded for `pc`
+fn widen(map: &mut BTreeMap<usize, InputOffsets>, pc: usize, offsets: InputOffsets) {
+    let recorded = map.entry(pc).or_insert_with(|| offsets.clone());
+    *recorded = recorded.start.min(offsets.start)..recorded.end.max(offsets.end);
+}
+
+/// Where a byte of a value is held during a replay
+///
+/// Registers and memory are separate key spaces, so the byte of a register never aliases the
+/// memory byte at the virtual address of the same number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TaintLocation {
+    /// A byte of a register, in little endian order
+    Register {
+        /// Index of the register
+        index: u8,
+        /// Index of the byte, 0 is the least significant one
+        byte: u8,
+    },
+    /// A byte of memory
+    Memory {
+        /// Virtual address of the byte
+        vm_addr: u64,
+    },
+}
+
+/// Virtual address range of a taint source and the input offset of its first byte
+type TaintSource = (Range<u64>, u64);
+
+/// Virtual address range of an alias and the address of the first byte it aliases
+type Alias = (Range<u64>, u64);
+
+/// Taint of the memory, the sources hold for the bytes which were not written since the
+/// execution started
+///
+/// Holds the [TaintLocation::Memory] bytes, the [TaintLocation::Register] bytes are tracked
+/// apart from it as [RegisterTaint]s.
+/// Accesses through an alias use the address of the aliased byte.
+struct Memory<'a> {
+    sources: &'a [TaintSource],
+    aliases: &'a [Alias],
+    written: HashMap<u64, Option<InputOffsets>>,
+    propagation: HashMap<u64, Rc<Propagation>>,
+}
+
+impl Memory<'_> {
+    /// Address of the byte which `vm_addr` is an alias of, or `vm_addr` itself
+    fn resolve_alias(&self, vm_addr: u64) -> u64 {
+        self.aliases
+            .iter()
+            .find(|(range, _aliased_vm_addr)| range.contains(&vm_addr))
+            .map_or(vm_addr, |(range, aliased_vm_addr)| {
+                aliased_vm_addr + (vm_addr - range.start)
+            })
+    }
+
+    /// Taint of a byte in memory
+    fn get(&self, vm_addr: u64) -> Option<InputOffsets> {
+        let vm_addr = self.resolve_alias(vm_addr);
+        if let Some(taint) = self.written.get(&vm_addr) {
+            return taint.clone();
+        }
+        self.sources
+            .iter()
+            .find(|(range, _offset)| range.contains(&vm_addr))
+            .map(|(range, offset)| {
+                let offset = offset + (vm_addr - range.start);
+                offset..offset + 1
+            })
+    }
+
+    /// How the tainted value of a byte got there, `None` for the sources
+    fn get_propagation(&self, vm_addr: u64) -> Option<Rc<Propagation>> {
+        self.propagation.get(&self.resolve_alias(vm_addr)).cloned()
+    }
+
+    fn set(
+        &mut self,
+        vm_addr: u64,
+        taint: Option<InputOffsets>,
+        propagation: Option<Rc<Propagation>>,
+    ) {
+        let vm_addr = self.resolve_alias(vm_addr);
+        match propagation.filter(|_| taint.is_some()) {
+            Some(propagation) => self.propagation.insert(vm_addr, propagation),
+            None => self.propagation.remove(&vm_addr),
+        };
+        self.written.insert(vm_addr, taint);
+    }
+}
+
+/// Where tainted values must not end up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaintSink {
+    /// Argument register (1 to 5) of the syscall whose name hashes to `hash`
+    SyscallArgument {
+        /// Hash of the syscall name
+        hash: u32,
+        /// Index of the argument register
+        argument: usize,
+    },
+    /// Stores to a range of virtual addresses
+    Store(Range<u64>),
+}
+
+impl TaintSink {
+    /// Sink for an argument register of a syscall
+    pub fn syscall_argument(name: &str, argument: usize) -> Self {
+        Self::SyscallArgument {
+            hash: ebpf::hash_symbol_name(name.as_bytes()),
+            argument,
+        }
+    }
+
+    /// Sinks for stores to a field of all accounts in the input region
+    pub fn account_field(layout: &AccountLayout, field: AccountField) -> Vec<Self> {
+        layout
+            .ranges_of(field)
+            .map(|(range, _account)| {
+                Self::Store(
+                    ebpf::MM_INPUT_START + range.start as u64
+                        ..ebpf::MM_INPUT_START + range.end as u64,
+                )
+            })
+            .collect()
+    }
+}
+
+/// A tainted value which reached a [TaintSink]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation {
+    /// Index of the sink
+    pub sink: usize,
+    /// Pc of the store or syscall
+    pub pc: usize,
+    /// Input bytes the value was derived from
+    pub offsets: InputOffsets,
+    /// Pcs of the instructions which propagated the value, from the load of the input to `pc`
+    pub propagation: Vec<usize>,
+}
+
+/// Operand of a conditional jump in a [PathConstraint]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-export", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConcolicOperand {
+    /// Concrete value in the recorded execution
+    pub value: u64,
+    /// Input bytes each byte of the value was derived from, in little endian order
+    pub labels: [Option<InputOffsets>; 8],
+}
+
+/// A conditional jump whose outcome depends on the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-export", derive(serde::Serialize, serde::Deserialize))]
+pub struct PathConstraint {
+    /// Pc of the conditional jump
+    pub pc: usize,
+    /// Opcode of the conditional jump
+    pub opcode: u8,
+    /// The `dst` register
+    pub dst: ConcolicOperand,
+    /// The `src` register or the sign extended immediate, which is never tainted
+    pub src: ConcolicOperand,
+    /// Whether the condition held
+    pub taken: bool,
+}
+
+/// A constant the instruction data was compared against
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DictionaryEntry {
+    /// Input offset of the first compared byte
+    pub offset: u64,
+    /// The constant in little endian order, as wide as the compared bytes
+    pub value: Vec<u8>,
+}
+
+/// Whether the condition of a conditional jump holds for the operands
+fn condition_holds(opcode: u8, dst: u64, src: u64) -> bool {
+    match opcode {
+        ebpf::JEQ_IMM | ebpf::JEQ_REG => dst == src,
+        ebpf::JGT_IMM | ebpf::JGT_REG => dst > src,
+        ebpf::JGE_IMM | ebpf::JGE_REG => dst >= src,
+        ebpf::JLT_IMM | ebpf::JLT_REG => dst < src,
+        ebpf::JLE_IMM | ebpf::JLE_REG => dst <= src,
+        ebpf::JSET_IMM | ebpf::JSET_REG => dst & src != 0,
+        ebpf::JNE_IMM | ebpf::JNE_REG => dst != src,
+        ebpf::JSGT_IMM | ebpf::JSGT_REG => (dst as i64) > src as i64,
+        ebpf::JSGE_IMM | ebpf::JSGE_REG => (dst as i64) >= src as i64,
+        ebpf::JSLT_IMM | ebpf::JSLT_REG => (dst as i64) < src as i64,
+        ebpf::JSLE_IMM | ebpf::JSLE_REG => (dst as i64) <= src as i64,
+        _ => false,
+    }
+}
+
+/// Taint of the input found in a trace log
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputTaint {
+    /// Pc of a conditional jump or a `sol_memcmp_` syscall => input bytes its operands were
+    /// derived from
+    pub tainted_comparisons: BTreeMap<usize, InputOffsets>,
+    /// Pc of a load => input bytes it loaded, directly or through a copy in memory
+    pub tainted_loads: BTreeMap<usize, InputOffsets>,
+    /// Input bytes each byte of the registers was derived from after the last instruction
+    pub final_registers: [[Option<InputOffsets>; 8]; 11],
+    /// Address of a byte written during the execution => input bytes its final value was
+    /// derived from
+    ///
+    /// The bytes of the sources which were never overwritten are not included.
+    pub final_memory: BTreeMap<u64, InputOffsets>,
+    /// Instructions the replay could not follow, in execution order
+    pub errors: Vec<TaintError>,
+    /// Tainted values which reached the sinks, in execution order
+    pub policy_violations: Vec<PolicyViolation>,
+    /// Conditional jumps with tainted operands in execution order, only in concolic mode
+    pub path_constraints: Vec<PathConstraint>,
+    /// Input bytes the operands of the last traced instruction were derived from
+    ///
+    /// A failed execution ends with the faulting instruction, see [Self::crash_relevant_bytes].
+    pub last_operands: Vec<InputOffsets>,
+    /// Input bytes each byte of the address of the last traced instruction was derived from,
+    /// if it is a load or store
+    ///
+    /// For an access violation this tells whether the faulting pointer was controlled by the
+    /// input, see [CrashReport::with_taint](crate::crash_report::CrashReport::with_taint).
+    pub last_address: [Option<InputOffsets>; 8],
+    /// How many instructions of the trace log were skipped or followed
+    #[cfg(feature = "diagnostics")]
+    pub diagnostics: crate::diagnostics::TaintDiagnostics,
+}
+
+impl InputTaint {
+    /// Replays a trace log recorded while executing the program of `analysis`
+    ///
+    /// All bytes of the input region are sources, labeled with their offset in the region.
+    pub fn from_trace_log(analysis: &Analysis, trace_log: &[TraceLogEntry]) -> Self {
+        Self::replay(
+            analysis,
+            trace_log,
+            &[(
+                ebpf::MM_INPUT_START..ebpf::MM_INPUT_START + ebpf::MM_REGION_SIZE,
+                0,
+            )],
+            &[],
+            &[],
+            false,
+        )
+    }
+
+    /// Replays a trace log like [Self::from_trace_log] and collects the
+    /// [Self::path_constraints]
+    pub fn from_trace_log_concolic(analysis: &Analysis, trace_log: &[TraceLogEntry]) -> Self {
+        Self::replay(
+            analysis,
+            trace_log,
+            &[(
+                ebpf::MM_INPUT_START..ebpf::MM_INPUT_START + ebpf::MM_REGION_SIZE,
+                0,
+            )],
+            &[],
+            &[],
+            true,
+        )
+    }
+
+    /// Replays a trace log like [Self::from_trace_log] and reports the tainted values reaching
+    /// the sinks in [Self::policy_violations]
+    pub fn from_trace_log_with_sinks(
+        analysis: &Analysis,
+        trace_log: &[TraceLogEntry],
+        sinks: &[TaintSink],
+    ) -> Self {
+        Self::replay(
+            analysis,
+            trace_log,
+            &[(
+                ebpf::MM_INPUT_START..ebpf::MM_INPUT_START + ebpf::MM_REGION_SIZE,
+                0,
+            )],
+            &[],
+            sinks,
+            false,
+        )
+    }
+
+    /// Replays a trace log with the bytes of the regions which have a
+    /// [taint_offset](MemoryRegion::taint_offset) as sources
+    ///
+    /// Regions which are an [alias](MemoryRegion::alias_of) share the taint of the aliased
+    /// bytes, including the values stored through either of them.
+    pub fn from_trace_log_with_regions(
+        analysis: &Analysis,
+        trace_log: &[TraceLogEntry],
+        regions: &[MemoryRegion],
+    ) -> Self {
+        let sources = regions
+            .iter()
+            .filter_map(|region| Some((region.vm_addr_range(), region.taint_offset?)))
+            .collect::<Vec<_>>();
+        let aliases = regions
+            .iter()
+            .filter_map(|region| Some((region.vm_addr_range(), region.alias_of?)))
+            .collect::<Vec<_>>();
+        Self::replay(analysis, trace_log, &sources, &aliases, &[], false)
+    }
+
+    fn replay(
+        analysis: &Analysis,
+        trace_log: &[TraceLogEntry],
+        sources: &[TaintSource],
+        aliases: &[Alias],
+        sinks: &[TaintSink],
+        concolic: bool,
+    ) -> Self {
+        let mut result = Self::default();
+        let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            result.follow(analysis, trace_log, sources, aliases, sinks, concolic)
+        }));
+        if let Err(payload) = outcome {
+            result.errors.push(TaintError::Internal {
+                message: panic_message(payload.as_ref()),
+            });
+        }
+        result
+    }
+
+    /// Follows the taint through the trace log
+    fn follow(
+        &mut self,
+        analysis: &Analysis,
+        trace_log: &[TraceLogEntry],
+        sources: &[TaintSource],
+        aliases: &[Alias],
+        sinks: &[TaintSink],
+        concolic: bool,
+    ) {
+        let sbpf_version = analysis.sbpf_version();
+        let result = self;
+        let mut registers: [RegisterTaint; 11] = Default::default();
+        let mut memory = Memory {
+            sources,
+            aliases,
+            written: HashMap::new(),
+            propagation: HashMap::new(),
+        };
+        let mut saved_registers = Vec::new();
+        for (index, entry) in trace_log.iter().enumerate() {
+            let pc = entry[11] as usize;
+            // Would leave the taint of the registers and memory unchanged
+            if analysis.input_independent.get(pc) == Some(&true) {
+                #[cfg(feature = "diagnostics")]
+                {
+                    result.diagnostics.fast_path_hits += 1;
+                }
+                continue;
+            }
+            #[cfg(feature = "diagnostics")]
+            {
+                result.diagnostics.followed_instructions += 1;
+            }
+            let Ok(insn_index) = analysis
+                .instructions
+                .binary_search_by_key(&pc, |insn| insn.ptr)
+            else {
+                continue;
+            };
+            let insn = &analysis.instructions[insn_index];
+            let Some(info) = opcode_info(insn.opc, sbpf_version) else {
+                continue;
+            };
+            let dst = insn.dst as usize % registers.len();
+            let src = insn.src as usize % registers.len();
+            let source = if info.source == OperandSource::Register {
+                registers[src].clone()
+            } else {
+                RegisterTaint::default()
+            };
+            if index + 1 == trace_log.len() {
+                let operands = match info.class {
+                    InstructionClass::Load => vec![&registers[src]],
+                    InstructionClass::Store
+                    | InstructionClass::Alu
+                    | InstructionClass::Product
+                    | InstructionClass::ConditionalJump => vec![&registers[dst], &source],
+                    InstructionClass::Syscall => registers[1..=5].iter().collect(),
+                    _ => Vec::new(),
+                };
+                result.last_operands = operands
+                    .into_iter()
+                    .flat_map(|register| register.0.iter().flatten().cloned())
+                    .collect();
+                match info.class {
+                    InstructionClass::Load => result.last_address = registers[src].0.clone(),
+                    InstructionClass::Store => result.last_address = registers[dst].0.clone(),
+                    _ => {}
+                }
+            }
+            match info.class {
+                // Both slots of `lddw` form a single constant
+                InstructionClass::LoadImmediate => registers[dst] = RegisterTaint::default(),
+                InstructionClass::Alu => {
+                    registers[dst] = match info.mnemonic {
+                        "mov64" => source,
+                        "mov32" => source.truncate(4),
+                        // After a `mov32` of a constant the upper half is untainted already
+                        "hor64" => registers[dst].clone().or_upper_half(insn.imm as u32),
+                        _ => RegisterTaint::mix(&registers[dst], &source),
+                    };
+                }
+                InstructionClass::Product => {
+                    registers[dst] = RegisterTaint::mix(&registers[dst], &source);
+                }
+                InstructionClass::Load => {
+                    let vm_addr = entry[src].wrapping_add(insn.off as i64 as u64);
+                    let mut taint = RegisterTaint::default();
+                    let width = match access_width(pc, insn, info) {
+                        Ok(width) => width,
+                        Err(error) => {
+                            result.errors.push(error);
+                            registers[dst] = taint;
+                            continue;
+                        }
+                    };
+                    for (byte, vm_addr) in taint.0.iter_mut().zip(vm_addr..).take(width) {
+                        *byte = memory.get(vm_addr);
+                        if byte.is_some() && taint.1.is_none() {
+                            taint.1 = memory.get_propagation(vm_addr);
+                        }
+                    }
+                    if let Some(offsets) = taint.hull() {
+                        widen(&mut result.tainted_loads, pc, offsets);
+                    }
+                    registers[dst] = taint;
+                }
+                InstructionClass::Store => {
+                    let vm_addr = entry[dst].wrapping_add(insn.off as i64 as u64);
+                    let bytes = match access_width(pc, insn, info) {
+                        Ok(width) => &source.0[..width],
+                        Err(error) => {
+                            result.errors.push(error);
+                            continue;
+                        }
+                    };
+                    let propagation = source
+                        .1
+                        .clone()
+                        .map(|previous| Propagation::extend(Some(previous), pc));
+                    result.check_stores(
+                        sinks,
+                        pc,
+                        memory.resolve_alias(vm_addr),
+                        bytes,
+                        propagation.as_ref(),
+                    );
+                    for (byte, vm_addr) in bytes.iter().zip(vm_addr..) {
+                        memory.set(vm_addr, byte.clone(), propagation.clone());
+                    }
+                }
+                InstructionClass::ConditionalJump => {
+                    if let Some(offsets) = RegisterTaint::mix(&registers[dst], &source).hull() {
+                        widen(&mut result.tainted_comparisons, pc, offsets);
+                        if concolic {
+                            let src_value = if info.source == OperandSource::Register {
+                                entry[src]
+                            } else {
+                                insn.imm as u64
+                            };
+                            result.path_constraints.push(PathConstraint {
+                                pc,
+                                opcode: insn.opc,
+                                dst: ConcolicOperand {
+                                    value: entry[dst],
+                                    labels: registers[dst].0.clone(),
+                                },
+                                src: ConcolicOperand {
+                                    value: src_value,
+                                    labels: source.0.clone(),
+                                },
+                                taken: condition_holds(insn.opc, entry[dst], src_value),
+                            });
+                        }
+                    }
+                }
+                InstructionClass::Jump => {}
+                InstructionClass::Call | InstructionClass::Syscall => {
+                    let next_pc = trace_log.get(index + 1).map(|next| next[11] as usize);
+                    if is_syscall(analysis, insn, info) {
+                        result.check_syscall(sinks, pc, insn.imm as u32, &registers);
+                        // Only a successful syscall is followed by another entry
+                        if let Some(builtin) =
+                            next_pc.and(MemoryBuiltin::from_hash(insn.imm as u32))
+                        {
+                            result.memory_builtin(
+                                builtin,
+                                pc,
+                                entry,
+                                &registers,
+                                &mut memory,
+                                sinks,
+                            );
+                        }
+                        // A sysvar syscall copies from the sysvars region like `sol_memcpy_`
+                        if let Some(sysvar) = next_pc.and(Sysvar::from_hash(insn.imm as u32)) {
+                            let mut copy = *entry;
+                            copy[2] = MM_SYSVARS_START + sysvar.offset();
+                            copy[3] = sysvar.size();
+                            result.memory_builtin(
+                                MemoryBuiltin::Memcpy,
+                                pc,
+                                &copy,
+                                &registers,
+                                &mut memory,
+                                sinks,
+                            );
+                        }
+                        registers[0] = RegisterTaint::default();
+                    } else {
+                        saved_registers.push(registers[6..=9].to_vec());
+                    }
+                }
+                InstructionClass::Exit => {
+                    if let Some(saved) = saved_registers.pop() {
+                        registers[6..=9].clone_from_slice(&saved);
+                    }
+                }
+            }
+            // Instructions which write a register extend the propagation of its value
+            if matches!(
+                info.class,
+                InstructionClass::Alu | InstructionClass::Product | InstructionClass::Load
+            ) {
+                let register = &mut registers[dst];
+                register.1 = if register.hull().is_some() {
+                    Some(Propagation::extend(register.1.take(), pc))
+                } else {
+                    None
+                };
+            }
+        }
+        result.final_registers = std::array::from_fn(|index| registers[index].0.clone());
+        result.final_memory = memory
+            .written
+            .into_iter()
+            .filter_map(|(vm_addr, taint)| Some((vm_addr, taint?)))
+            .collect();
+    }
+
+    /// Input bytes which any comparison or load depends on
+    pub fn labels(&self) -> TaintLabels {
+        TaintLabels::new(
+            self.tainted_comparisons
+                .values()
+                .chain(self.tainted_loads.values())
+                .cloned(),
+        )
+    }
+
+    /// Pcs of the comparisons and loads whose operands are derived from both sets, sorted
+    ///
+    /// E.g. whether the key of an account is ever compared with the instruction data. As the
+    /// taint of a value is the hull of the offsets it was derived from, a value derived from
+    /// bytes on both sides of a set also counts as derived from it.
+    pub fn meeting_pcs(&self, a: &TaintLabels, b: &TaintLabels) -> Vec<usize> {
+        let mut pcs = self
+            .tainted_comparisons
+            .iter()
+            .chain(self.tainted_loads.iter())
+            .filter(|(_pc, offsets)| {
+                let offsets = TaintLabels::new(std::iter::once((*offsets).clone()));
+                offsets.intersects(a) && offsets.intersects(b)
+            })
+            .map(|(pc, _offsets)| *pc)
+            .collect::<Vec<_>>();
+        pcs.sort_unstable();
+        pcs.dedup();
+        pcs
+    }
+
+    /// How many registers and bytes are derived from the input byte at `label` in the end
+    pub fn label_statistics(&self, label: u64) -> LabelStatistics {
+        LabelStatistics::count(label, &self.final_registers, self.final_memory.values())
+    }
+
+    /// Input bytes the byte at `location` was derived from after the last instruction
+    ///
+    /// Like [Self::final_memory], the bytes of the sources which were never overwritten are
+    /// untainted.
+    pub fn final_taint(&self, location: TaintLocation) -> Option<InputOffsets> {
+        match location {
+            TaintLocation::Register { index, byte } => self
+                .final_registers
+                .get(index as usize)?
+                .get(byte as usize)?
+                .clone(),
+            TaintLocation::Memory { vm_addr } => self.final_memory.get(&vm_addr).cloned(),
+        }
+    }
+
+    /// The tainted bytes after the last instruction, the registers first
+    pub fn final_locations(&self) -> impl Iterator<Item = (TaintLocation, &InputOffsets)> + '_ {
+        let registers = self
+            .final_registers
+            .iter()
+            .zip(0..)
+            .flat_map(|(register, index)| {
+                register.iter().zip(0..).filter_map(move |(offsets, byte)| {
+                    Some((TaintLocation::Register { index, byte }, offsets.as_ref()?))
+                })
+            });
+        let memory = self
+            .final_memory
+            .iter()
+            .map(|(vm_addr, offsets)| (TaintLocation::Memory { vm_addr: *vm_addr }, offsets));
+        registers.chain(memory)
+    }
+
+    /// Reports the tainted bytes stored to the sinks
+    fn check_stores(
+        &mut self,
+        sinks: &[TaintSink],
+        pc: usize,
+        vm_addr: u64,
+        bytes: &[Option<InputOffsets>],
+        propagation: Option<&Rc<Propagation>>,
+    ) {
+        for (sink_index, sink) in sinks.iter().enumerate() {
+            let TaintSink::Store(range) = sink else {
+                continue;
+            };
+            let offsets = bytes
+                .iter()
+                .zip(vm_addr..)
+                .filter(|(_byte, vm_addr)| range.contains(vm_addr))
+                .map(|(byte, _vm_addr)| byte.clone())
+                .fold(None, merge);
+            if let Some(offsets) = offsets {
+                self.policy_violations.push(PolicyViolation {
+                    sink: sink_index,
+                    pc,
+                    offsets,
+                    propagation: propagation
+                        .map(|propagation| propagation.pcs())
+                        .unwrap_or_default(),
+                });
+            }
+        }
+    }
+
+    /// Reports the tainted arguments of a syscall
+    fn check_syscall(
+        &mut self,
+        sinks: &[TaintSink],
+        pc: usize,
+        hash: u32,
+        registers: &[RegisterTaint; 11],
+    ) {
+        for (sink_index, sink) in sinks.iter().enumerate() {
+            let TaintSink::SyscallArgument {
+                hash: sink_hash,
+                argument,
+            } = sink
+            else {
+                continue;
+            };
+            let Some(register) = registers.get(*argument).filter(|_| *sink_hash == hash) else {
+                continue;
+            };
+            if let Some(offsets) = register.hull() {
+                let mut propagation = register
+                    .1
+                    .as_ref()
+                    .map(|propagation| propagation.pcs())
+                    .unwrap_or_default();
+                propagation.push(pc);
+                self.policy_violations.push(PolicyViolation {
+                    sink: sink_index,
+                    pc,
+                    offsets,
+                    propagation,
+                });
+            }
+        }
+    }
+
+    /// Adds the taint of another execution of the same program, e.g. a nested one separated
+    /// by [TraceCheckpoints](crate::trace_buffer::TraceCheckpoints)
+    ///
+    /// The final registers and memory stay the ones of this execution.
+    pub fn merge(&mut self, other: &Self) {
+        for (pc, offsets) in other.tainted_comparisons.iter() {
+            widen(&mut self.tainted_comparisons, *pc, offsets.clone());
+        }
+        for (pc, offsets) in other.tainted_loads.iter() {
+            widen(&mut self.tainted_loads, *pc, offsets.clone());
+        }
+        self.policy_violations
+            .extend(other.policy_violations.iter().cloned());
+        self.path_constraints
+            .extend(other.path_constraints.iter().cloned());
+        self.errors.extend(other.errors.iter().cloned());
+        #[cfg(feature = "diagnostics")]
+        self.diagnostics.merge(&other.diagnostics);
+    }
+
+    /// Constants which the instruction data was compared against, e.g. discriminators
+    ///
+    /// Built from the [Self::path_constraints] with one constant operand and the other one
+    /// loaded from the instruction data in `layout`, so it requires concolic mode. The entries
+    /// are sorted and can be passed to a fuzzer as dictionary tokens.
+    pub fn extract_dictionary(&self, layout: &AccountLayout) -> Vec<DictionaryEntry> {
+        // The field includes the length of the instruction data
+        let instruction_data = layout
+            .ranges_of(AccountField::InstructionData)
+            .map(|(range, _account)| range.start as u64 + 8..range.end as u64)
+            .collect::<Vec<_>>();
+        let within_instruction_data = |offsets: &InputOffsets| {
+            instruction_data
+                .iter()
+                .any(|range| range.start <= offsets.start && offsets.end <= range.end)
+        };
+        let mut dictionary = self
+            .path_constraints
+            .iter()
+            .filter_map(|constraint| {
+                let (tainted, constant) = match (
+                    constraint.dst.labels.iter().any(Option::is_some),
+                    constraint.src.labels.iter().any(Option::is_some),
+                ) {
+                    (true, false) => (&constraint.dst, &constraint.src),
+                    (false, true) => (&constraint.src, &constraint.dst),
+                    _ => return None,
+                };
+                let width = tainted.labels.iter().rposition(Option::is_some)? + 1;
+                let labels = &tainted.labels[..width];
+                if !labels
+                    .iter()
+                    .all(|label| label.as_ref().is_some_and(within_instruction_data))
+                {
+                    return None;
+                }
+                Some(DictionaryEntry {
+                    offset: labels[0].as_ref()?.start,
+                    value: constant.value.to_le_bytes()[..width].to_vec(),
+                })
+            })
+            .collect::<Vec<_>>();
+        dictionary.sort();
+        dictionary.dedup();
+        dictionary
+    }
+
+    /// Input bytes which a failed execution depends on to reach its fault, sorted and disjoint
+    ///
+    /// These are the bytes the operands of the faulting instruction were derived from, e.g.
+    /// the address of an access violation or the divisor of a division by zero. A minimizer
+    /// has to keep them, as well as the bytes of the [Self::tainted_comparisons] which steer
+    /// the execution to the fault. All other bytes are irrelevant to the crash.
+    pub fn crash_relevant_bytes(&self) -> Vec<InputOffsets> {
+        let mut offsets = self.last_operands.clone();
+        offsets.sort_by_key(|range| range.start);
+        let mut merged: Vec<InputOffsets> = Vec::new();
+        for range in offsets {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    /// Applies a syscall of a [MemoryBuiltin] with the arguments in `entry`
+    fn memory_builtin(
+        &mut self,
+        builtin: MemoryBuiltin,
+        pc: usize,
+        entry: &TraceLogEntry,
+        registers: &[RegisterTaint; 11],
+        memory: &mut Memory,
+        sinks: &[TaintSink],
+    ) {
+        let (dst, src, n) = (entry[1], entry[2], entry[3]);
+        match builtin {
+            MemoryBuiltin::Memcpy | MemoryBuiltin::Memmove => {
+                let bytes = (0..n)
+                    .map(|offset| {
+                        let vm_addr = src.wrapping_add(offset);
+                        (memory.get(vm_addr), memory.get_propagation(vm_addr))
+                    })
+                    .collect::<Vec<_>>();
+                for (vm_addr, (taint, previous)) in (dst..).zip(bytes) {
+                    let propagation = Propagation::extend(previous, pc);
+                    self.check_stores(
+                        sinks,
+                        pc,
+                        memory.resolve_alias(vm_addr),
+                        std::slice::from_ref(&taint),
+                        Some(&propagation),
+                    );
+                    memory.set(vm_addr, taint, Some(propagation));
+                }
+            }
+            MemoryBuiltin::Memset => {
+                let taint = &registers[2].0[0];
+                let propagation = registers[2]
+                    .1
+                    .clone()
+                    .map(|previous| Propagation::extend(Some(previous), pc));
+                for vm_addr in dst..dst.saturating_add(n) {
+                    self.check_stores(
+                        sinks,
+                        pc,
+                        memory.resolve_alias(vm_addr),
+                        std::slice::from_ref(taint),
+                        propagation.as_ref(),
+                    );
+                    memory.set(vm_addr, taint.clone(), propagation.clone());
+                }
+            }
+            MemoryBuiltin::Memcmp => {
+                let offsets = (0..n)
+                    .flat_map(|offset| {
+                        [
+                            memory.get(dst.wrapping_add(offset)),
+                            memory.get(src.wrapping_add(offset)),
+                        ]
+                    })
+                    .fold(None, merge);
+                if let Some(offsets) = offsets.clone() {
+                    widen(&mut self.tainted_comparisons, pc, offsets);
+                }
+                let propagation = (0..n)
+                    .flat_map(|offset| {
+                        [
+                            memory.get_propagation(dst.wrapping_add(offset)),
+                            memory.get_propagation(src.wrapping_add(offset)),
+                        ]
+                    })
+                    .flatten()
+                    .next();
+                let propagation = Propagation::extend(propagation, pc);
+                for vm_addr in entry[4]..entry[4].saturating_add(4) {
+                    memory.set(vm_addr, offsets.clone(), Some(propagation.clone()));
+                }
+            }
+        }
+    }
+}
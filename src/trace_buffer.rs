@@ -0,0 +1,562 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Bounded recording of traces
+//!
+//! A trace log collected in a `Vec` grows by 96 bytes per executed instruction, which adds up
+//! quickly for long executions. A [TraceBuffer] holds at most a fixed number of entries and
+//! either keeps the most recent ones or an evenly spaced sample of the whole execution,
+//! counting the entries it dropped in both cases.
+//!
+//! When a program invokes nested VMs which share its context object, their entries end up in
+//! the same trace. [TraceCheckpoints] separates the entries of each nested execution, so they
+//! can be analyzed on their own.
+//!
+//! Traces of executions running for hours do not fit into memory at all. A [StreamingTrace]
+//! writes the entries to a [TraceSink] while they are recorded, e.g. to a file, a socket or a
+//! compression stream through a [WriterSink], and [TraceLogReader] reads them back.
+
+use crate::static_analysis::TraceLogEntry;
+use byteorder::{ByteOrder, LittleEndian};
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind, Read, Write},
+};
+
+/// What happens when a [TraceBuffer] is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Overwrite the oldest entry
+    RingBuffer,
+    /// Drop every other entry and only record every second entry from now on
+    ///
+    /// The retained entries are spaced evenly over the whole execution.
+    Sampling,
+}
+
+/// Trace with a capacity limit
+#[derive(Debug, Clone)]
+pub struct TraceBuffer<T> {
+    /// Maximum number of entries, at least 1
+    capacity: usize,
+    /// What happens when the buffer is full
+    policy: OverflowPolicy,
+    /// Retained entries in the order they were pushed
+    entries: VecDeque<T>,
+    /// Only every n-th pushed entry is retained, a power of two which is always 1 for
+    /// [OverflowPolicy::RingBuffer]
+    sampling_interval: u64,
+    /// Number of pushed entries
+    pushed: u64,
+    /// Number of pushed entries which are not retained
+    dropped: u64,
+}
+
+impl<T> TraceBuffer<T> {
+    /// Creates an empty buffer which holds at most `capacity` entries
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            policy,
+            entries: VecDeque::new(),
+            sampling_interval: 1,
+            pushed: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Records an entry
+    pub fn push(&mut self, entry: T) {
+        let index = self.pushed;
+        self.pushed = self.pushed.saturating_add(1);
+        if index & (self.sampling_interval - 1) != 0 {
+            self.dropped = self.dropped.saturating_add(1);
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            match self.policy {
+                OverflowPolicy::RingBuffer => {
+                    self.entries.pop_front();
+                    self.dropped = self.dropped.saturating_add(1);
+                }
+                OverflowPolicy::Sampling => {
+                    let retained = self.entries.len();
+                    let mut position = 0;
+                    self.entries.retain(|_entry| {
+                        position += 1;
+                        position & 1 == 1
+                    });
+                    self.dropped = self
+                        .dropped
+                        .saturating_add((retained - self.entries.len()) as u64);
+                    self.sampling_interval = self.sampling_interval.saturating_mul(2);
+                    if index & (self.sampling_interval - 1) != 0 {
+                        self.dropped = self.dropped.saturating_add(1);
+                        return;
+                    }
+                }
+            }
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Retained entries in the order they were pushed
+    pub fn entries(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter()
+    }
+
+    /// Number of retained entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no entry is retained
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of pushed entries, including the dropped ones
+    pub fn pushed(&self) -> u64 {
+        self.pushed
+    }
+
+    /// Number of pushed entries which are not retained
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Distance between retained entries, 1 unless sampling kicked in
+    pub fn sampling_interval(&self) -> u64 {
+        self.sampling_interval
+    }
+
+    /// Removes all entries and resets the counters
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.sampling_interval = 1;
+        self.pushed = 0;
+        self.dropped = 0;
+    }
+}
+
+impl<T: Clone> TraceBuffer<T> {
+    /// Copies the retained entries, e.g. to pass a trace log to the analyses
+    pub fn to_vec(&self) -> Vec<T> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+/// Stack of positions in a trace at which nested executions started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceCheckpoints {
+    /// Length of the trace when each nested execution started, innermost last
+    starts: Vec<usize>,
+}
+
+impl TraceCheckpoints {
+    /// Marks the start of a nested execution, before it records its first entry
+    pub fn push<T>(&mut self, trace_log: &[T]) {
+        self.starts.push(trace_log.len());
+    }
+
+    /// Removes the entries of the innermost nested execution from the trace and returns them
+    ///
+    /// The trace then continues where the outer execution left off. Returns `None` if there
+    /// is no nested execution.
+    pub fn pop<T>(&mut self, trace_log: &mut Vec<T>) -> Option<Vec<T>> {
+        let start = self.starts.pop()?;
+        Some(trace_log.split_off(start.min(trace_log.len())))
+    }
+
+    /// Number of nested executions which were pushed but not popped yet
+    pub fn depth(&self) -> usize {
+        self.starts.len()
+    }
+}
+
+/// Size of an entry written by a [WriterSink]
+pub const TRACE_LOG_ENTRY_SIZE: usize = std::mem::size_of::<TraceLogEntry>();
+
+/// Destination of the entries of a [StreamingTrace]
+pub trait TraceSink {
+    /// Writes an entry
+    ///
+    /// An error of kind [ErrorKind::WouldBlock] signals that the sink can not accept the entry
+    /// yet, it is offered again later. Any other error ends the trace.
+    fn write_event(&mut self, entry: &TraceLogEntry) -> io::Result<()>;
+
+    /// Writes out the entries the sink buffered, fails like [Self::write_event]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes entries as twelve little endian `u64` to a writer
+///
+/// Writers which can not take all bytes at once, e.g. non-blocking sockets, are supported:
+/// The remainder of an entry is kept and written before the next entry is accepted.
+#[derive(Debug)]
+pub struct WriterSink<W: Write> {
+    writer: W,
+    /// Bytes of accepted entries which were not written yet
+    pending: Vec<u8>,
+}
+
+impl<W: Write> WriterSink<W> {
+    /// Creates a sink writing to `writer`
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns the writer, bytes which were not written yet are lost
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes as many pending bytes as the writer takes
+    fn write_pending(&mut self) -> io::Result<()> {
+        while !self.pending.is_empty() {
+            match self.writer.write(&self.pending) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.pending.drain(..written);
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> TraceSink for WriterSink<W> {
+    fn write_event(&mut self, entry: &TraceLogEntry) -> io::Result<()> {
+        self.write_pending()?;
+        self.pending
+            .extend(entry.iter().flat_map(|value| value.to_le_bytes()));
+        match self.write_pending() {
+            // The entry was accepted, the rest is written later
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(()),
+            result => result,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.writer.flush()
+    }
+}
+
+/// Trace which is written to a [TraceSink] while it is recorded
+///
+/// Entries the sink does not accept yet are queued. Once `capacity` entries are queued,
+/// recording blocks until the sink accepts some of them, which slows the execution down to
+/// the pace of the sink instead of growing the queue. After the sink failed, further entries
+/// are dropped and counted.
+#[derive(Debug)]
+pub struct StreamingTrace<S: TraceSink> {
+    sink: S,
+    /// Maximum number of queued entries
+    capacity: usize,
+    /// Entries the sink did not accept yet
+    queue: VecDeque<TraceLogEntry>,
+    /// Number of entries the sink accepted
+    written: u64,
+    /// Number of entries lost because the sink failed
+    dropped: u64,
+    /// Error which ended the trace
+    error: Option<io::Error>,
+}
+
+impl<S: TraceSink> StreamingTrace<S> {
+    /// Creates a trace writing to `sink`, which queues at most `capacity` entries
+    pub fn new(sink: S, capacity: usize) -> Self {
+        Self {
+            sink,
+            capacity,
+            queue: VecDeque::new(),
+            written: 0,
+            dropped: 0,
+            error: None,
+        }
+    }
+
+    /// Records an entry, e.g. in [ContextObject::trace](crate::vm::ContextObject::trace)
+    pub fn push(&mut self, entry: TraceLogEntry) {
+        if self.error.is_some() {
+            self.dropped = self.dropped.saturating_add(1);
+            return;
+        }
+        self.queue.push_back(entry);
+        self.drain(self.capacity);
+    }
+
+    /// Offers the queued entries to the sink, blocking while more than `max_queued` remain
+    fn drain(&mut self, max_queued: usize) {
+        while let Some(entry) = self.queue.front() {
+            match self.sink.write_event(entry) {
+                Ok(()) => {
+                    self.queue.pop_front();
+                    self.written = self.written.saturating_add(1);
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    if self.queue.len() <= max_queued {
+                        break;
+                    }
+                    std::thread::yield_now();
+                }
+                Err(error) => {
+                    self.dropped = self.dropped.saturating_add(self.queue.len() as u64);
+                    self.queue.clear();
+                    self.error = Some(error);
+                }
+            }
+        }
+    }
+
+    /// Writes all queued entries and flushes the sink, blocking until it accepted them
+    ///
+    /// Fails with the error which ended the trace, if any.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.drain(0);
+        while self.error.is_none() {
+            match self.sink.flush() {
+                Ok(()) => break,
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) =>
+                {
+                    std::thread::yield_now();
+                }
+                Err(error) => self.error = Some(error),
+            }
+        }
+        match &self.error {
+            Some(error) => Err(io::Error::new(error.kind(), error.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes the trace and returns the sink
+    pub fn finish(mut self) -> io::Result<S> {
+        self.flush()?;
+        Ok(self.sink)
+    }
+
+    /// Number of entries the sink accepted
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Number of entries lost because the sink failed
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Number of entries waiting for the sink
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Error which ended the trace, if the sink failed
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+/// Reads the entries written by a [WriterSink] one at a time
+#[derive(Debug)]
+pub struct TraceLogReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> TraceLogReader<R> {
+    /// Creates a reader of the entries in `reader`
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: Read> Iterator for TraceLogReader<R> {
+    type Item = io::Result<TraceLogEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = [0; TRACE_LOG_ENTRY_SIZE];
+        let mut filled = 0;
+        while filled < bytes.len() {
+            match self.reader.read(&mut bytes[filled..]) {
+                Ok(0) if filled == 0 => return None,
+                Ok(0) => return Some(Err(ErrorKind::UnexpectedEof.into())),
+                Ok(read) => filled += read,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        let mut entry = TraceLogEntry::default();
+        for (value, chunk) in entry.iter_mut().zip(bytes.chunks_exact(8)) {
+            *value = LittleEndian::read_u64(chunk);
+        }
+        Some(Ok(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_buffer() {
+        let mut ring_buffer = TraceBuffer::new(4, OverflowPolicy::RingBuffer);
+        for pc in 0..10u64 {
+            ring_buffer.push(pc);
+        }
+        assert_eq!(ring_buffer.to_vec(), vec![6, 7, 8, 9]);
+        assert_eq!((ring_buffer.pushed(), ring_buffer.dropped()), (10, 6));
+
+        let mut sampling = TraceBuffer::new(4, OverflowPolicy::Sampling);
+        for pc in 0..10u64 {
+            sampling.push(pc);
+        }
+        assert_eq!(sampling.to_vec(), vec![0, 4, 8]);
+        assert_eq!(sampling.sampling_interval(), 4);
+        assert_eq!((sampling.pushed(), sampling.dropped()), (10, 7));
+        for pc in 10..1_000_000u64 {
+            sampling.push(pc);
+        }
+        assert!(sampling.len() <= 4);
+        assert_eq!(
+            sampling.dropped() + sampling.len() as u64,
+            sampling.pushed()
+        );
+        assert!(sampling
+            .entries()
+            .all(|pc| pc & (sampling.sampling_interval() - 1) == 0));
+
+        sampling.clear();
+        sampling.push(1);
+        assert_eq!(sampling.to_vec(), vec![1]);
+        assert_eq!(sampling.sampling_interval(), 1);
+    }
+
+    #[test]
+    fn test_trace_buffer_capacity() {
+        for capacity in [0, 1, 3, 8] {
+            for len in 0..50u64 {
+                let mut ring_buffer = TraceBuffer::new(capacity, OverflowPolicy::RingBuffer);
+                let mut sampling = TraceBuffer::new(capacity, OverflowPolicy::Sampling);
+                for pc in 0..len {
+                    ring_buffer.push(pc);
+                    sampling.push(pc);
+                }
+                let retained = len.min(capacity.max(1) as u64);
+                assert_eq!(ring_buffer.len() as u64, retained);
+                assert_eq!(ring_buffer.dropped(), len - retained);
+                assert!(ring_buffer.to_vec().into_iter().eq(len - retained..len));
+
+                assert!(sampling.len() <= capacity.max(1));
+                assert_eq!(sampling.pushed(), len);
+                assert_eq!(sampling.dropped() + sampling.len() as u64, len);
+                if len <= capacity as u64 {
+                    assert_eq!(sampling.dropped(), 0);
+                    assert_eq!(sampling.sampling_interval(), 1);
+                }
+                let interval = sampling.sampling_interval();
+                assert!(sampling
+                    .to_vec()
+                    .into_iter()
+                    .eq((0..len).step_by(interval as usize).take(sampling.len())));
+            }
+        }
+    }
+
+    #[test]
+    fn test_trace_checkpoints() {
+        let mut trace_log = vec![0, 1];
+        let mut checkpoints = TraceCheckpoints::default();
+        checkpoints.push(&trace_log);
+        trace_log.extend([10, 11]);
+        checkpoints.push(&trace_log);
+        trace_log.push(20);
+        assert_eq!(checkpoints.depth(), 2);
+        assert_eq!(checkpoints.pop(&mut trace_log), Some(vec![20]));
+        trace_log.push(12);
+        assert_eq!(checkpoints.pop(&mut trace_log), Some(vec![10, 11, 12]));
+        assert_eq!(checkpoints.pop(&mut trace_log), None);
+        assert_eq!(trace_log, vec![0, 1]);
+    }
+
+    /// Takes at most 10 bytes per write and every other write would block
+    #[derive(Default)]
+    struct ThrottledWriter {
+        bytes: Vec<u8>,
+        writes: u64,
+    }
+
+    impl Write for ThrottledWriter {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            if self.writes & 1 == 0 {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let len = bytes.len().min(10);
+            self.bytes.extend_from_slice(&bytes[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_streaming_trace() {
+        let entries = (0..100u64).map(|pc| [pc; 12]).collect::<Vec<_>>();
+        let mut trace = StreamingTrace::new(WriterSink::new(ThrottledWriter::default()), 2);
+        for entry in entries.iter() {
+            trace.push(*entry);
+            assert!(trace.queued() <= 2);
+        }
+        trace.flush().unwrap();
+        assert_eq!(
+            (trace.written(), trace.dropped(), trace.queued()),
+            (100, 0, 0)
+        );
+        let bytes = trace.finish().unwrap().into_inner().bytes;
+        assert_eq!(bytes.len(), 100 * TRACE_LOG_ENTRY_SIZE);
+        let read = TraceLogReader::new(bytes.as_slice())
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, entries);
+
+        let mut reader = TraceLogReader::new(&bytes[..TRACE_LOG_ENTRY_SIZE + 1]);
+        assert_eq!(reader.next().unwrap().unwrap(), entries[0]);
+        assert_eq!(
+            reader.next().unwrap().unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_streaming_trace_failing_sink() {
+        #[derive(Debug)]
+        struct FailingSink(Vec<TraceLogEntry>);
+        impl TraceSink for FailingSink {
+            fn write_event(&mut self, entry: &TraceLogEntry) -> io::Result<()> {
+                if self.0.len() == 3 {
+                    return Err(ErrorKind::BrokenPipe.into());
+                }
+                self.0.push(*entry);
+                Ok(())
+            }
+        }
+
+        let mut trace = StreamingTrace::new(FailingSink(Vec::new()), 4);
+        for pc in 0..5 {
+            trace.push([pc; 12]);
+        }
+        assert_eq!((trace.written(), trace.dropped()), (3, 2));
+        assert_eq!(trace.error().unwrap().kind(), ErrorKind::BrokenPipe);
+        assert_eq!(trace.finish().unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+}
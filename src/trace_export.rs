@@ -0,0 +1,406 @@
+#![allow(clippy::arithmetic_side_effects)]
+//! Serialization of recorded traces
+//!
+//! Converts the trace log collected by [crate::vm::ContextObject::trace] into a versioned,
+//! self contained format which can be written as JSON or bincode. This allows analysis
+//! tools to consume traces without linking against this crate.
+//!
+//! Loaded traces can also be analyzed offline, on a machine which never executed the program:
+//! Coverage and diffs only need the traces, [DynamicAnalysis] additionally the ELF.
+
+use crate::{
+    ebpf,
+    elf::Executable,
+    opcode_table::{opcode_info, InstructionClass, OperandSource},
+    redaction::Redaction,
+    static_analysis::{Analysis, TraceLogEntry},
+    vm::{ContextObject, DynamicAnalysis},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Version of the schema, incremented on every incompatible change
+pub const TRACE_SCHEMA_VERSION: u32 = 1;
+
+/// Error definitions
+#[derive(Debug, thiserror::Error)]
+pub enum TraceExportError {
+    /// The trace was written with a different schema version
+    #[error("unsupported trace schema version {0}")]
+    UnsupportedSchemaVersion(u32),
+    /// JSON (de)serialization failed
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    /// Bincode (de)serialization failed
+    #[error("bincode error: {0}")]
+    Bincode(#[from] bincode::Error),
+}
+
+/// State before an executed instruction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstructionRecord {
+    /// Instruction offset in the text section
+    pub pc: u64,
+    /// Opcode of the instruction
+    pub opcode: u8,
+    /// Registers r0 to r10
+    pub registers: [u64; 11],
+}
+
+/// A taken control flow transfer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JumpRecord {
+    /// Index of the jump instruction in the instruction records
+    pub index: u64,
+    /// Instruction offset of the jump instruction
+    pub from: u64,
+    /// Instruction offset of the next executed instruction
+    pub to: u64,
+}
+
+/// Difference between two recorded executions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDiff {
+    /// Index of the first instruction record which differs in pc or registers,
+    /// `None` if one trace is a prefix of the other
+    pub first_divergence: Option<usize>,
+    /// Pcs only executed in the first trace
+    pub only_in_first: BTreeSet<u64>,
+    /// Pcs only executed in the second trace
+    pub only_in_second: BTreeSet<u64>,
+    /// Number of basic block entries in the longest common subsequence of both traces
+    pub common_blocks: usize,
+    /// The branch at which the traces took different paths first
+    pub divergent_branch: Option<DivergentBranch>,
+}
+
+/// A branch which two traces left towards different targets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DivergentBranch {
+    /// Instruction offset of the branch
+    pub pc: u64,
+    /// Opcode of the branch
+    pub opcode: u8,
+    /// Index of the branch in the instruction records of the first and the second trace
+    pub index: (u64, u64),
+    /// Next executed instruction offset in the first and the second trace, `None` if it ended
+    pub target: (Option<u64>, Option<u64>),
+    /// Values of `dst` and the source operand of a conditional jump in the first and the
+    /// second trace, only filled in by [TraceDiff::compare]
+    pub operands: Option<((u64, u64), (u64, u64))>,
+    /// First basic block after the divergence which both traces enter again, according to
+    /// the longest common subsequence
+    pub reconvergence: Option<u64>,
+}
+
+impl TraceDiff {
+    /// Compares two traces recorded while executing `executable`, like [TraceExport::diff],
+    /// and adds the operands of the [Self::divergent_branch]
+    ///
+    /// The operands tell why the first divergent conditional jump went another way, e.g. to
+    /// steer a directed fuzzer towards the target not taken by `trace_a`.
+    pub fn compare<C: ContextObject>(
+        executable: &Executable<C>,
+        trace_a: &TraceExport,
+        trace_b: &TraceExport,
+    ) -> Self {
+        let mut diff = trace_a.diff(trace_b);
+        let (_program_vm_addr, program) = executable.get_text_bytes();
+        let sbpf_version = executable.get_sbpf_version();
+        if let Some(branch) = diff.divergent_branch.as_mut() {
+            let insn = program
+                .get((branch.pc as usize).saturating_mul(ebpf::INSN_SIZE)..)
+                .filter(|rest| rest.len() >= ebpf::INSN_SIZE)
+                .map(|_| ebpf::get_insn(program, branch.pc as usize));
+            let info = insn.as_ref().and_then(|insn| {
+                opcode_info(insn.opc, sbpf_version)
+                    .filter(|info| info.class == InstructionClass::ConditionalJump)
+            });
+            if let (Some(insn), Some(info)) = (insn, info) {
+                let operands = |trace: &TraceExport, index: u64| {
+                    let registers = &trace.instructions[index as usize].registers;
+                    let src = match info.source {
+                        OperandSource::Register => registers[insn.src as usize % 11],
+                        _ => insn.imm as u64,
+                    };
+                    (registers[insn.dst as usize % 11], src)
+                };
+                branch.operands = Some((
+                    operands(trace_a, branch.index.0),
+                    operands(trace_b, branch.index.1),
+                ));
+            }
+        }
+        diff
+    }
+}
+
+/// A recorded execution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceExport {
+    /// See [TRACE_SCHEMA_VERSION]
+    pub schema_version: u32,
+    /// Every executed instruction in order
+    pub instructions: Vec<InstructionRecord>,
+    /// Every taken jump, call and return in order
+    pub jumps: Vec<JumpRecord>,
+}
+
+impl TraceExport {
+    /// Converts a trace log recorded while executing `executable`
+    pub fn from_trace_log<C: ContextObject>(
+        executable: &Executable<C>,
+        trace_log: &[TraceLogEntry],
+    ) -> Self {
+        let (_program_vm_addr, program) = executable.get_text_bytes();
+        let opcode_at = |pc: u64| {
+            program
+                .get((pc as usize).saturating_mul(ebpf::INSN_SIZE))
+                .copied()
+                .unwrap_or(0)
+        };
+        let instructions = trace_log
+            .iter()
+            .map(|entry| {
+                let mut registers = [0; 11];
+                registers.copy_from_slice(&entry[0..11]);
+                InstructionRecord {
+                    pc: entry[11],
+                    opcode: opcode_at(entry[11]),
+                    registers,
+                }
+            })
+            .collect::<Vec<_>>();
+        let jumps = instructions
+            .windows(2)
+            .enumerate()
+            .filter(|(_index, pair)| {
+                pair[0].opcode & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP
+                    && pair[1].pc != pair[0].pc.saturating_add(1)
+            })
+            .map(|(index, pair)| JumpRecord {
+                index: index as u64,
+                from: pair[0].pc,
+                to: pair[1].pc,
+            })
+            .collect();
+        Self {
+            schema_version: TRACE_SCHEMA_VERSION,
+            instructions,
+            jumps,
+        }
+    }
+
+    /// Serializes to JSON
+    pub fn to_json(&self) -> Result<String, TraceExportError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Deserializes from JSON, rejecting other schema versions
+    pub fn from_json(json: &str) -> Result<Self, TraceExportError> {
+        Self::check_schema_version(serde_json::from_str(json)?)
+    }
+
+    /// Serializes to bincode
+    pub fn to_bincode(&self) -> Result<Vec<u8>, TraceExportError> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    /// Deserializes from bincode, rejecting other schema versions
+    pub fn from_bincode(bytes: &[u8]) -> Result<Self, TraceExportError> {
+        Self::check_schema_version(bincode::deserialize(bytes)?)
+    }
+
+    /// Reconstructs the trace log, e.g. to feed it into [DynamicAnalysis]
+    pub fn to_trace_log(&self) -> Vec<TraceLogEntry> {
+        self.instructions
+            .iter()
+            .map(|record| {
+                let mut entry = [0; 12];
+                entry[0..11].copy_from_slice(&record.registers);
+                entry[11] = record.pc;
+                entry
+            })
+            .collect()
+    }
+
+    /// Copy with the register values replaced, see [Redaction::register]
+    ///
+    /// Pcs, opcodes and jumps are kept, so coverage and diffs of the copy are unchanged.
+    pub fn redact(&self, redaction: &Redaction) -> Self {
+        let mut trace = self.clone();
+        for record in trace.instructions.iter_mut() {
+            for register in record.registers.iter_mut() {
+                *register = redaction.register(*register);
+            }
+        }
+        trace
+    }
+
+    /// Execution count per pc
+    pub fn coverage(&self) -> BTreeMap<u64, u64> {
+        let mut coverage = BTreeMap::new();
+        for record in self.instructions.iter() {
+            let counter = coverage.entry(record.pc).or_insert(0u64);
+            *counter = counter.saturating_add(1);
+        }
+        coverage
+    }
+
+    /// Edge statistics over the basic blocks of `analysis`, which must stem from the traced ELF
+    pub fn dynamic_analysis(&self, analysis: &Analysis) -> DynamicAnalysis {
+        DynamicAnalysis::new(&self.to_trace_log(), analysis)
+    }
+
+    /// Compares against another trace of the same program
+    pub fn diff(&self, other: &Self) -> TraceDiff {
+        let first_divergence = self
+            .instructions
+            .iter()
+            .zip(other.instructions.iter())
+            .position(|(a, b)| a.pc != b.pc || a.registers != b.registers);
+        let covered = |trace: &Self| {
+            trace
+                .instructions
+                .iter()
+                .map(|record| record.pc)
+                .collect::<BTreeSet<_>>()
+        };
+        let (first, second) = (covered(self), covered(other));
+        let (common_blocks, divergent_branch) = self.align(other);
+        TraceDiff {
+            first_divergence,
+            only_in_first: first.difference(&second).copied().collect(),
+            only_in_second: second.difference(&first).copied().collect(),
+            common_blocks,
+            divergent_branch,
+        }
+    }
+
+    /// Entered basic blocks with the index of their first instruction record
+    ///
+    /// A block starts at the first instruction and at the target of every taken jump.
+    fn blocks(&self) -> Vec<(u64, u64)> {
+        self.instructions
+            .first()
+            .map(|record| (record.pc, 0))
+            .into_iter()
+            .chain(
+                self.jumps
+                    .iter()
+                    .map(|jump| (jump.to, jump.index.saturating_add(1))),
+            )
+            .collect()
+    }
+
+    /// Aligns the entered basic blocks of both traces by their longest common subsequence
+    ///
+    /// The blocks after the common prefix and before the common suffix are aligned in
+    /// quadratic time and memory.
+    fn align(&self, other: &Self) -> (usize, Option<DivergentBranch>) {
+        let (blocks_a, blocks_b) = (self.blocks(), other.blocks());
+        let prefix = blocks_a
+            .iter()
+            .zip(blocks_b.iter())
+            .take_while(|(a, b)| a.0 == b.0)
+            .count();
+        if prefix == blocks_a.len() && prefix == blocks_b.len() {
+            return (prefix, None);
+        }
+        let suffix = blocks_a[prefix..]
+            .iter()
+            .rev()
+            .zip(blocks_b[prefix..].iter().rev())
+            .take_while(|(a, b)| a.0 == b.0)
+            .count();
+        let middle_a = &blocks_a[prefix..blocks_a.len() - suffix];
+        let middle_b = &blocks_b[prefix..blocks_b.len() - suffix];
+        // lcs[i][j] is the length of the common subsequence of middle_a[i..] and middle_b[j..]
+        let width = middle_b.len() + 1;
+        let mut lcs = vec![0u32; (middle_a.len() + 1) * width];
+        for i in (0..middle_a.len()).rev() {
+            for j in (0..middle_b.len()).rev() {
+                lcs[i * width + j] = if middle_a[i].0 == middle_b[j].0 {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        let mut reconvergence = blocks_a.get(blocks_a.len() - suffix).map(|block| block.0);
+        while i < middle_a.len() && j < middle_b.len() {
+            if middle_a[i].0 == middle_b[j].0 {
+                reconvergence = Some(middle_a[i].0);
+                break;
+            } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        let common_blocks = prefix + suffix + lcs[0] as usize;
+        let divergent_branch = prefix
+            .checked_sub(1)
+            .and_then(|last| self.divergent_branch(other, blocks_a[last].1, blocks_b[last].1))
+            .map(|mut branch| {
+                branch.reconvergence = reconvergence;
+                branch
+            });
+        (common_blocks, divergent_branch)
+    }
+
+    /// The first branch after entering the same basic block at `entry_a` and `entry_b`
+    /// which the traces leave differently
+    ///
+    /// Both traces execute the same straight line of instructions until one of them takes a
+    /// jump, so the jump with the lower pc is the branch.
+    fn divergent_branch(
+        &self,
+        other: &Self,
+        entry_a: u64,
+        entry_b: u64,
+    ) -> Option<DivergentBranch> {
+        let next_jump = |trace: &Self, entry: u64| {
+            trace
+                .jumps
+                .iter()
+                .find(|jump| jump.index >= entry)
+                .map(|jump| jump.from)
+        };
+        let pc = match (next_jump(self, entry_a), next_jump(other, entry_b)) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b)?,
+        };
+        let index_of = |trace: &Self, entry: u64| {
+            trace.instructions[entry as usize..]
+                .iter()
+                .position(|record| record.pc == pc)
+                .map(|offset| entry + offset as u64)
+        };
+        let index = (index_of(self, entry_a)?, index_of(other, entry_b)?);
+        let target = |trace: &Self, index: u64| {
+            trace
+                .instructions
+                .get(index as usize + 1)
+                .map(|record| record.pc)
+        };
+        Some(DivergentBranch {
+            pc,
+            opcode: self.instructions[index.0 as usize].opcode,
+            index,
+            target: (target(self, index.0), target(other, index.1)),
+            operands: None,
+            reconvergence: None,
+        })
+    }
+
+    fn check_schema_version(trace: Self) -> Result<Self, TraceExportError> {
+        if trace.schema_version != TRACE_SCHEMA_VERSION {
+            return Err(TraceExportError::UnsupportedSchemaVersion(
+                trace.schema_version,
+            ));
+        }
+        Ok(trace)
+    }
+}
@@ -0,0 +1,74 @@
+//! Operand values per comparison site
+//!
+//! Once a campaign stops finding new edges, the values a program compares are a richer
+//! feedback signal, similar to the value profile of libFuzzer: An input which makes a
+//! comparison see a pair of operands it never saw before is worth keeping, even if it takes
+//! the same branches. A [ValueProfile] accumulates these pairs per conditional jump across
+//! executions, bounded per site so that a comparison in a loop can not grow it without limit.
+
+use crate::{
+    branch_distance::{comparisons, Comparison},
+    elf::Executable,
+    static_analysis::TraceLogEntry,
+    vm::ContextObject,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Distinct operand pairs (dst, src or imm) per conditional jump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueProfile {
+    /// Number of pairs kept per pc, further ones are dropped
+    max_values_per_site: usize,
+    /// pc => operand pairs observed
+    sites: BTreeMap<u64, BTreeSet<(u64, u64)>>,
+}
+
+impl ValueProfile {
+    /// Creates an empty profile which keeps up to `max_values_per_site` pairs per pc
+    pub fn new(max_values_per_site: usize) -> Self {
+        Self {
+            max_values_per_site,
+            sites: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, pc: u64, operands: (u64, u64)) -> bool {
+        let values = self.sites.entry(pc).or_default();
+        values.len() < self.max_values_per_site && values.insert(operands)
+    }
+
+    /// Accumulates the conditional jumps of a trace recorded while executing `executable`
+    ///
+    /// Returns the number of pairs which were not observed before. Jumps which read a register
+    /// missing from the trace entries are skipped.
+    pub fn record_trace<C: ContextObject>(
+        &mut self,
+        executable: &Executable<C>,
+        trace_log: &[TraceLogEntry],
+    ) -> usize {
+        comparisons(executable, trace_log)
+            .filter_map(Result::ok)
+            .filter(|Comparison { pc, dst, src, .. }| self.record(*pc, (*dst, *src)))
+            .count()
+    }
+
+    /// Accumulates the pairs of another profile, returns the number of new ones
+    pub fn merge(&mut self, other: &Self) -> usize {
+        other
+            .sites
+            .iter()
+            .flat_map(|(pc, values)| values.iter().map(move |operands| (*pc, *operands)))
+            .filter(|(pc, operands)| self.record(*pc, *operands))
+            .count()
+    }
+
+    /// pc => operand pairs observed
+    pub fn value_profile(&self) -> &BTreeMap<u64, BTreeSet<(u64, u64)>> {
+        &self.sites
+    }
+
+    /// Forgets all pairs, e.g. when the program under test changes
+    pub fn clear(&mut self) {
+        self.sites.clear();
+    }
+}
@@ -17,6 +17,7 @@ use crate::{
     program::{BuiltinFunction, FunctionRegistry, SBPFVersion},
     vm::{Config, ContextObject},
 };
+use std::ops::Range;
 use thiserror::Error;
 
 /// Error definitions
@@ -79,6 +80,15 @@ pub enum VerifierError {
     /// Unaligned immediate
     #[error("Unaligned immediate (insn #{0})")]
     UnalignedImmediate(usize),
+    /// Register excluded by the ABI restrictions
+    #[error("register r{0} is not available (insn #{1})")]
+    RestrictedRegister(u8, usize),
+    /// Register which carries no argument is read before it is written
+    #[error("register r{0} carries no argument and is read before it is written (insn #{1})")]
+    UndefinedArgumentRegister(u8, usize),
+    /// Instruction rejected by a [VerifierExt]
+    #[error("rejected by verifier extension: {0} (insn #{1})")]
+    RejectedByExtension(String, usize),
 }
 
 /// eBPF Verifier
@@ -100,6 +110,78 @@ pub trait Verifier {
     ) -> Result<(), VerifierError>;
 }
 
+/// Additional verification pass, which [Executable::verify](crate::elf::Executable::verify)
+/// runs after the [Verifier] succeeded
+///
+/// Registered with [Executable::add_verifier_extension](crate::elf::Executable::add_verifier_extension).
+pub trait VerifierExt: Send + Sync {
+    /// Checks an instruction, returns the reason if it is rejected
+    ///
+    /// Called for every instruction in order, `lddw` only once with the full immediate.
+    fn verify_insn(
+        &self,
+        insn: &ebpf::Insn,
+        sbpf_version: SBPFVersion,
+        function_registry: &FunctionRegistry<usize>,
+    ) -> Result<(), String>;
+}
+
+/// Extensions registered in an executable
+#[derive(Default)]
+pub(crate) struct VerifierExtensions(pub(crate) Vec<Box<dyn VerifierExt>>);
+
+impl VerifierExtensions {
+    /// Runs all extensions on the instructions in `range` of a program which passed the
+    /// [Verifier]
+    pub(crate) fn verify(
+        &self,
+        prog: &[u8],
+        sbpf_version: SBPFVersion,
+        function_registry: &FunctionRegistry<usize>,
+        range: Range<usize>,
+    ) -> Result<(), VerifierError> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let mut insn_ptr = range.start;
+        while insn_ptr < range.end && (insn_ptr + 1) * ebpf::INSN_SIZE <= prog.len() {
+            let mut insn = ebpf::get_insn(prog, insn_ptr);
+            if insn.opc == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw() {
+                ebpf::augment_lddw_unchecked(prog, &mut insn);
+            }
+            for extension in self.0.iter() {
+                extension
+                    .verify_insn(&insn, sbpf_version, function_registry)
+                    .map_err(|reason| VerifierError::RejectedByExtension(reason, insn_ptr))?;
+            }
+            insn_ptr += if insn.opc == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw() {
+                2
+            } else {
+                1
+            };
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for VerifierExtensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "VerifierExtensions({})", self.0.len())
+    }
+}
+
+impl PartialEq for VerifierExtensions {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(other.0.iter()).all(|(a, b)| {
+                std::ptr::eq(
+                    (a.as_ref() as *const dyn VerifierExt).cast::<u8>(),
+                    (b.as_ref() as *const dyn VerifierExt).cast::<u8>(),
+                )
+            })
+    }
+}
+
 fn check_prog_len(prog: &[u8]) -> Result<(), VerifierError> {
     if prog.len().checked_rem(ebpf::INSN_SIZE) != Some(0) {
         return Err(VerifierError::ProgramLengthNotMultiple);
@@ -218,6 +300,28 @@ fn check_callx_register(
     Ok(())
 }
 
+/// Checks that the `syscall` instructions in `range` refer to registered syscalls
+pub(crate) fn check_syscalls<C: ContextObject>(
+    prog: &[u8],
+    sbpf_version: SBPFVersion,
+    syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
+    range: Range<usize>,
+) -> Result<(), VerifierError> {
+    if !sbpf_version.static_syscalls() {
+        return Ok(());
+    }
+    for insn_ptr in range {
+        if (insn_ptr + 1) * ebpf::INSN_SIZE > prog.len() {
+            break;
+        }
+        let insn = ebpf::get_insn(prog, insn_ptr);
+        if insn.opc == ebpf::SYSCALL && syscall_registry.lookup_by_key(insn.imm as u32).is_none() {
+            return Err(VerifierError::InvalidSyscall(insn.imm as u32));
+        }
+    }
+    Ok(())
+}
+
 /// Mandatory verifier for solana programs to run on-chain
 #[derive(Debug)]
 pub struct RequisiteVerifier {}
@@ -13,15 +13,28 @@
 //! Virtual machine for eBPF programs.
 
 use crate::{
+    aligned_memory::AlignedMemory,
+    corpus::hash,
+    cost_model::CostModel,
+    deadline::Deadline,
     ebpf,
     elf::Executable,
     error::{EbpfError, ProgramResult},
+    fault_injection::FaultInjector,
+    input_hook::InputHook,
     interpreter::Interpreter,
-    memory_region::MemoryMapping,
+    loop_detector::LoopDetector,
+    memory_region::{AddressSpaceEntry, MemoryMapping, MemoryRegion},
+    observer::ExecutionObserver,
+    prng::Prng,
+    profiler::InstructionProfiler,
     program::{BuiltinFunction, BuiltinProgram, FunctionRegistry, SBPFVersion},
-    static_analysis::Analysis,
+    static_analysis::{Analysis, TraceLogEntry},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
 };
-use std::{collections::BTreeMap, fmt::Debug};
 
 #[cfg(feature = "shuttle-test")]
 use shuttle::sync::Arc;
@@ -41,17 +54,82 @@ const PROGRAM_ENVIRONMENT_KEY_SHIFT: u32 = 4;
 #[cfg(feature = "jit")]
 static RUNTIME_ENVIRONMENT_KEY: std::sync::OnceLock<i32> = std::sync::OnceLock::<i32>::new();
 
+/// Environment variable which pins the random bits of the RUNTIME_ENVIRONMENT_KEY
+///
+/// Only available for fuzzing, as a predictable key defeats the encryption of the VM pointer.
+#[cfg(feature = "fuzzer-not-safe-for-production")]
+pub const RUNTIME_ENVIRONMENT_KEY_VAR: &str = "SBPF_RUNTIME_ENVIRONMENT_KEY";
+
+/// Parses the value of [RUNTIME_ENVIRONMENT_KEY_VAR], None if it is missing or not an i32
+#[cfg(all(feature = "jit", feature = "fuzzer-not-safe-for-production"))]
+fn parse_runtime_environment_key(value: Option<&str>) -> Option<i32> {
+    value.and_then(|value| value.parse::<i32>().ok())
+}
+
 /// Returns (and if not done before generates) the encryption key for the VM pointer
+///
+/// The key is drawn at random once per process. With the `fuzzer-not-safe-for-production`
+/// feature `SBPF_RUNTIME_ENVIRONMENT_KEY` can hold an i32 to derive it from instead, which makes
+/// the JIT pointer encoding reproducible across runs.
 pub fn get_runtime_environment_key() -> i32 {
     #[cfg(feature = "jit")]
     {
-        *RUNTIME_ENVIRONMENT_KEY
-            .get_or_init(|| thread_rng().gen::<i32>() >> PROGRAM_ENVIRONMENT_KEY_SHIFT)
+        *RUNTIME_ENVIRONMENT_KEY.get_or_init(|| {
+            #[cfg(feature = "fuzzer-not-safe-for-production")]
+            if let Some(key) = parse_runtime_environment_key(
+                std::env::var(RUNTIME_ENVIRONMENT_KEY_VAR).ok().as_deref(),
+            ) {
+                return key >> PROGRAM_ENVIRONMENT_KEY_SHIFT;
+            }
+            thread_rng().gen::<i32>() >> PROGRAM_ENVIRONMENT_KEY_SHIFT
+        })
     }
     #[cfg(not(feature = "jit"))]
     0
 }
 
+/// Instrumentation performed by the interpreter, from the cheapest to the most detailed level
+///
+/// Higher levels include everything the lower ones record. Instrumentation which a level does
+/// not need is skipped in [Interpreter::step](crate::interpreter::Interpreter::step), so runs
+/// which only need edge coverage do not pay for the rest. From [Coverage](Self::Coverage)
+/// upward the instructions are traced and the [EbpfVm::observers] are notified.
+///
+/// The levels only decide what is recorded, not how an execution is bounded: The
+/// [EbpfVm::deadline], the [Config::cost_model], the [EbpfVm::loop_detector] and the
+/// [EbpfVm::profiler] apply at every level, so an input consumes the same compute units and
+/// fails the same way no matter how much is recorded. Only the interpreter consults it, the
+/// JIT emits its instrumentation according to the other settings of [Config].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InstrumentationConfig {
+    /// Execute without any instrumentation, not even [Config::enable_instruction_tracing]
+    None,
+    /// Record the control flow, e.g. the trace of [Config::enable_instruction_tracing]
+    Coverage,
+    /// Also record the operands of comparisons
+    CoverageCmpLog,
+    /// Record everything, including the internal counters of the interpreter
+    #[default]
+    FullTaint,
+}
+
+impl InstrumentationConfig {
+    /// Whether the control flow is recorded
+    pub fn records_coverage(self) -> bool {
+        self >= Self::Coverage
+    }
+
+    /// Whether the operands of comparisons are recorded
+    pub fn records_comparisons(self) -> bool {
+        self >= Self::CoverageCmpLog
+    }
+
+    /// Whether data flow is recorded
+    pub fn records_everything(self) -> bool {
+        self >= Self::FullTaint
+    }
+}
+
 /// VM configuration settings
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Config {
@@ -69,6 +147,8 @@ pub struct Config {
     pub enable_instruction_meter: bool,
     /// Enable instruction tracing
     pub enable_instruction_tracing: bool,
+    /// Instrumentation performed by the interpreter on every instruction
+    pub instrumentation: InstrumentationConfig,
     /// Enable dynamic string allocation for labels
     pub enable_symbol_and_section_labels: bool,
     /// Reject ELF files containing issues that the verifier did not catch before (up to v0.2.21)
@@ -79,12 +159,30 @@ pub struct Config {
     #[cfg(feature = "jit")]
     /// Enable disinfection of immediate values and offsets provided by the user in JIT
     pub sanitize_user_provided_values: bool,
+    #[cfg(all(feature = "jit", feature = "fuzzer-not-safe-for-production"))]
+    /// Seed of the random no-ops and immediate value keys in JIT (None = drawn from the OS)
+    ///
+    /// Independent of `rng_seed`, so reproducible builtins do not make the JIT predictable. Only
+    /// available for fuzzing, as a predictable JIT defeats the diversification.
+    pub diversification_seed: Option<u64>,
     /// Avoid copying read only sections when possible
     pub optimize_rodata: bool,
     /// Use aligned memory mapping
     pub aligned_memory_mapping: bool,
+    /// Cache recent page translations of the memory mapping in front of the region lookup
+    ///
+    /// Off by default: Host memory handed out by the mapping is trusted to stay valid until the
+    /// regions change, which embedders which mutate regions behind its back can not guarantee.
+    pub enable_translation_cache: bool,
     /// Allowed [SBPFVersion]s
     pub enabled_sbpf_versions: std::ops::RangeInclusive<SBPFVersion>,
+    /// Prices of instructions and syscalls for the instruction meter (None = 1 per instruction)
+    ///
+    /// Only the interpreter supports it, compiling or running the JIT fails with
+    /// [EbpfError::UnsupportedCostModel].
+    pub cost_model: Option<CostModel>,
+    /// Seed of [EbpfVm::rng] (None = drawn at random for every VM)
+    pub rng_seed: Option<u64>,
 }
 
 impl Config {
@@ -104,15 +202,21 @@ impl Default for Config {
             instruction_meter_checkpoint_distance: 10000,
             enable_instruction_meter: true,
             enable_instruction_tracing: false,
+            instrumentation: InstrumentationConfig::default(),
             enable_symbol_and_section_labels: false,
             reject_broken_elfs: false,
             #[cfg(feature = "jit")]
             noop_instruction_rate: 256,
             #[cfg(feature = "jit")]
             sanitize_user_provided_values: true,
+            #[cfg(all(feature = "jit", feature = "fuzzer-not-safe-for-production"))]
+            diversification_seed: None,
             optimize_rodata: true,
             aligned_memory_mapping: true,
+            enable_translation_cache: false,
             enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V4,
+            cost_model: None,
+            rng_seed: None,
         }
     }
 }
@@ -144,6 +248,113 @@ pub trait ContextObject {
     fn consume(&mut self, amount: u64);
     /// Get the number of remaining instructions allowed
     fn get_remaining(&self) -> u64;
+    /// Account work done for instrumentation, separately from the instruction meter
+    ///
+    /// Called once per instruction for tracing, notifying observers and profiling each.
+    fn consume_instrumentation(&mut self, _amount: u64) {}
+    /// Get the amount of instrumentation work accounted so far
+    fn get_instrumentation(&self) -> u64 {
+        0
+    }
+    /// The trace recorded since the last [ContextObject::reset_trace], if one is kept
+    fn trace_log(&self) -> &[TraceLogEntry] {
+        &[]
+    }
+    /// Discards the recorded trace, e.g. between the runs of [EbpfVm::execute_batch]
+    fn reset_trace(&mut self) {}
+}
+
+/// Summary of the trace of a single run, see [EbpfVm::execute_batch]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraceSummary {
+    /// Number of traced instructions
+    pub len: u64,
+    /// Number of distinct pcs which were traced
+    pub unique_pcs: u64,
+    /// Number of distinct pairs of consecutively traced pcs
+    pub unique_edges: u64,
+    /// Pc of the last traced instruction
+    pub last_pc: Option<u64>,
+}
+
+impl TraceSummary {
+    /// Summarizes a trace and hashes its edge coverage
+    ///
+    /// The hash only depends on the set of edges, not on how often or in which order they were
+    /// taken, so runs covering the same edges have the same hash.
+    pub fn from_trace_log(trace_log: &[TraceLogEntry]) -> (Self, u64) {
+        let pcs = trace_log.iter().map(|state| state[11]);
+        let unique_pcs = pcs.clone().collect::<BTreeSet<_>>().len();
+        let edges = pcs
+            .clone()
+            .zip(pcs.clone().skip(1))
+            .collect::<BTreeSet<_>>();
+        let mut bytes = Vec::with_capacity(edges.len().saturating_mul(16));
+        for (from, to) in edges.iter() {
+            bytes.extend_from_slice(&from.to_le_bytes());
+            bytes.extend_from_slice(&to.to_le_bytes());
+        }
+        let summary = Self {
+            len: trace_log.len() as u64,
+            unique_pcs: unique_pcs as u64,
+            unique_edges: edges.len() as u64,
+            last_pc: trace_log.last().map(|state| state[11]),
+        };
+        (summary, hash(&bytes))
+    }
+}
+
+/// Outcome of a single input of [EbpfVm::execute_batch]
+#[derive(Debug)]
+pub struct BatchResult {
+    /// Number of executed instructions
+    pub instruction_count: u64,
+    /// Result of the program
+    pub result: ProgramResult,
+    /// Hash of the edge coverage, see [TraceSummary::from_trace_log]
+    pub coverage_hash: u64,
+    /// Summary of the trace
+    pub trace: TraceSummary,
+    /// Instrumentation which failed during the run
+    pub instrumentation_failures: Vec<InstrumentationFailure>,
+}
+
+/// Instrumentation component of the interpreter, see [InstrumentationFailure]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstrumentationComponent {
+    /// The tracer of the context object, see [ContextObject::trace]
+    Tracer,
+    /// The [EbpfVm::fault_injector]
+    FaultInjector,
+    /// The observer at the given index of [EbpfVm::observers]
+    Observer(usize),
+}
+
+/// A panic of an instrumentation component, which the interpreter caught
+///
+/// The execution continues without the component, so its results are still valid, but the
+/// instrumentation lost fidelity from `pc` on. The tracer stops recording for the rest of the
+/// run and a failed fault injector is removed from the VM.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{component:?} panicked at pc {pc}: {message}")]
+pub struct InstrumentationFailure {
+    /// Component which panicked
+    pub component: InstrumentationComponent,
+    /// Pc of the instruction during which it panicked
+    pub pc: u64,
+    /// Message of the panic
+    pub message: String,
+}
+
+/// Message of a caught panic
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
 }
 
 /// Statistic of taken branches (from a recorded trace)
@@ -191,6 +402,30 @@ pub struct CallFrame {
     pub target_pc: u64,
 }
 
+/// A frame of the guest call stack, see [EbpfVm::call_stack]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    /// Instruction offset of the current instruction, or of the call instruction in outer frames
+    pub pc: u64,
+    /// Instruction offset and name of the function containing `pc`, if it is registered
+    pub function: Option<(usize, String)>,
+}
+
+impl std::fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.function {
+            Some((function_pc, name)) => write!(
+                f,
+                "{}+{} (pc {})",
+                name,
+                self.pc.saturating_sub(*function_pc as u64),
+                self.pc
+            ),
+            None => write!(f, "[unknown] (pc {})", self.pc),
+        }
+    }
+}
+
 /// Indices of slots inside [EbpfVm]
 pub enum RuntimeEnvironmentSlot {
     /// [EbpfVm::host_stack_pointer]
@@ -300,6 +535,29 @@ pub struct EbpfVm<'a, C: ContextObject> {
     /// TCP port for the debugger interface
     #[cfg(feature = "debugger")]
     pub debug_port: Option<u16>,
+    /// Internal counters of the interpreter
+    #[cfg(feature = "diagnostics")]
+    pub diagnostics: crate::diagnostics::InterpreterDiagnostics,
+    /// Opt-in instruction level profiler of the interpreter
+    pub profiler: Option<Box<InstructionProfiler>>,
+    /// Consulted by the interpreter before every syscall
+    pub fault_injector: Option<Box<dyn FaultInjector>>,
+    /// Instrumentation which failed during the last execution in the interpreter
+    pub instrumentation_failures: Vec<InstrumentationFailure>,
+    /// Notified by the interpreter about every executed instruction
+    pub observers: Vec<Box<dyn ExecutionObserver>>,
+    /// Opt-in back edge counting of the interpreter
+    pub loop_detector: Option<Box<LoopDetector>>,
+    /// Opt-in wall-clock limit of the interpreter
+    pub deadline: Option<Deadline>,
+    /// Consulted by the interpreter before every load from the input region
+    pub input_hook: Option<Box<dyn InputHook>>,
+    /// Opt-in copies of the return targets of the call frames, verified by the interpreter on return
+    pub shadow_call_stack: Option<Vec<u64>>,
+    /// Source of randomness for builtins, seeded from `Config::rng_seed`
+    pub rng: Prng,
+    /// Backing memory of the input region during [EbpfVm::execute_batch]
+    batch_input: AlignedMemory<{ ebpf::HOST_ALIGN }>,
 }
 
 impl<'a, C: ContextObject> EbpfVm<'a, C> {
@@ -324,6 +582,7 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
         if !config.enable_address_translation {
             memory_mapping = MemoryMapping::new_identity();
         }
+        let rng = Prng::from_config(config);
         EbpfVm {
             host_stack_pointer: std::ptr::null_mut(),
             call_depth: 0,
@@ -339,6 +598,18 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
             loader,
             #[cfg(feature = "debugger")]
             debug_port: None,
+            #[cfg(feature = "diagnostics")]
+            diagnostics: crate::diagnostics::InterpreterDiagnostics::default(),
+            profiler: None,
+            fault_injector: None,
+            instrumentation_failures: Vec::new(),
+            observers: Vec::new(),
+            loop_detector: None,
+            deadline: None,
+            input_hook: None,
+            shadow_call_stack: None,
+            rng,
+            batch_input: AlignedMemory::with_capacity(0),
         }
     }
 
@@ -357,6 +628,11 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
         self.previous_instruction_meter = initial_insn_count;
         self.due_insn_count = 0;
         self.program_result = ProgramResult::Ok(0);
+        self.instrumentation_failures.clear();
+        // The compiled instruction meters charge one unit per instruction
+        if !interpreted && config.cost_model.is_some() {
+            return (0, ProgramResult::Err(EbpfError::UnsupportedCostModel));
+        }
         if interpreted {
             #[cfg(feature = "debugger")]
             let debug_port = self.debug_port.clone();
@@ -369,20 +645,32 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
             }
             #[cfg(not(feature = "debugger"))]
             while interpreter.step() {}
+            #[cfg(feature = "diagnostics")]
+            self.diagnostics.end_of_execution();
+            if let Some(cycles) = self
+                .profiler
+                .as_mut()
+                .and_then(|profiler| profiler.end_of_execution())
+            {
+                self.stopwatch_numerator += cycles;
+                self.stopwatch_denominator += 1;
+            }
         } else {
+            #[allow(unused_mut)]
+            let mut invoked = false;
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
-            {
-                let compiled_program = match executable
-                    .get_compiled_program()
-                    .ok_or_else(|| EbpfError::JitNotCompiled)
-                {
-                    Ok(compiled_program) => compiled_program,
-                    Err(error) => return (0, ProgramResult::Err(error)),
-                };
+            if let Some(compiled_program) = executable.get_compiled_program() {
                 compiled_program.invoke(config, self, self.registers);
+                invoked = true;
             }
-            #[cfg(not(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64")))]
-            {
+            #[cfg(feature = "jit-cranelift")]
+            if !invoked {
+                if let Some(cranelift_program) = executable.get_cranelift_program() {
+                    cranelift_program.invoke(self, executable, self.registers);
+                    invoked = true;
+                }
+            }
+            if !invoked {
                 return (0, ProgramResult::Err(EbpfError::JitNotCompiled));
             }
         };
@@ -397,6 +685,216 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
         (instruction_count, result)
     }
 
+    /// Replaces the context object and returns the previous one
+    ///
+    /// Meant for executions which are paused between [Interpreter::step]s. The instructions
+    /// executed so far are consumed from the previous context object, the remaining ones are
+    /// metered against the budget of the new one.
+    pub fn swap_context_object(&mut self, context_object: &'a mut C) -> &'a mut C {
+        if self.loader.get_config().enable_instruction_meter {
+            self.context_object_pointer.consume(self.due_insn_count);
+        }
+        let previous = std::mem::replace(&mut self.context_object_pointer, context_object);
+        // The new context object may come with its own view of the mapped memory
+        self.memory_mapping.flush_translation_cache();
+        self.previous_instruction_meter = self.context_object_pointer.get_remaining();
+        self.due_insn_count = 0;
+        previous
+    }
+
+    /// All mapped regions in ascending order of their virtual addresses
+    ///
+    /// Empty for an identity mapping, which does not translate addresses.
+    pub fn address_space_map(&self) -> Vec<AddressSpaceEntry> {
+        let mut map = self
+            .memory_mapping
+            .get_regions()
+            .iter()
+            .filter(|region| region.len > 0)
+            .map(AddressSpaceEntry::from)
+            .collect::<Vec<_>>();
+        map.sort_by_key(|entry| entry.vm_range.start);
+        map
+    }
+
+    /// Symbolized guest call stack, innermost frame first
+    ///
+    /// Reconstructed from the [CallFrame]s maintained by the interpreter, so it is only
+    /// meaningful while interpreting or after the interpreter threw an error,
+    /// in which case `pc` is `self.registers[11]`.
+    pub fn call_stack(&self, executable: &Executable<C>, pc: u64) -> Vec<StackFrame> {
+        let function_registry = executable.get_function_registry();
+        let containing_function = |pc: u64| {
+            function_registry
+                .iter()
+                .filter(|(_key, (_name, function_pc))| *function_pc as u64 <= pc)
+                .max_by_key(|(_key, (_name, function_pc))| *function_pc)
+                .map(|(_key, (name, function_pc))| {
+                    (function_pc, String::from_utf8_lossy(name).to_string())
+                })
+        };
+        let call_depth = (self.call_depth as usize).min(self.call_frames.len());
+        std::iter::once(pc)
+            .chain(
+                self.call_frames[0..call_depth]
+                    .iter()
+                    .rev()
+                    .map(|frame| frame.target_pc.saturating_sub(1)),
+            )
+            .map(|pc| StackFrame {
+                pc,
+                function: containing_function(pc),
+            })
+            .collect()
+    }
+
+    /// Execute the program once per input
+    ///
+    /// The memory mapping is reused: For every input the input region (at [ebpf::MM_INPUT_START])
+    /// is replaced by a copy of the input, the stack and heap are zeroed, the trace of the context
+    /// object is reset and the registers are restored to their values at the time of the call.
+    /// The call frames, the [shadow call stack](Self::shadow_call_stack) and the back edges of
+    /// the [loop detector](Self::loop_detector) are cleared, and every input gets the time which
+    /// was left of the [deadline](Self::deadline) when the batch started. `prepare` is called
+    /// before every run, e.g. to reset the instruction meter. The original input region and
+    /// deadline are restored before returning.
+    ///
+    /// Returns the result, coverage hash and trace summary of every input in order. Coverage is
+    /// only collected with `Config::enable_instruction_tracing` and a context object which
+    /// exposes its [trace](ContextObject::trace_log).
+    pub fn execute_batch<F: FnMut(usize, &mut C)>(
+        &mut self,
+        executable: &Executable<C>,
+        inputs: &[&[u8]],
+        interpreted: bool,
+        prepare: F,
+    ) -> Result<Vec<BatchResult>, EbpfError> {
+        self.execute_batch_with(
+            executable,
+            inputs.len(),
+            interpreted,
+            prepare,
+            |index, batch_input| {
+                let input = inputs[index];
+                if batch_input.len() < input.len() {
+                    *batch_input = AlignedMemory::zero_filled(input.len());
+                }
+                let batch_input = &mut batch_input.as_slice_mut()[..input.len()];
+                batch_input.copy_from_slice(input);
+                Ok(MemoryRegion::new_writable(
+                    batch_input,
+                    ebpf::MM_INPUT_START,
+                ))
+            },
+        )
+    }
+
+    /// Execute the program once per memory-mapped input file
+    ///
+    /// Like [EbpfVm::execute_batch], but the input region maps the file directly instead of a
+    /// copy. Every input is [reset](crate::mapped_file::MappedFile::reset) before it is run, so
+    /// stores to copy-on-write inputs of earlier runs or batches are discarded.
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn execute_batch_mapped<F: FnMut(usize, &mut C)>(
+        &mut self,
+        executable: &Executable<C>,
+        inputs: &mut [crate::mapped_file::MappedFile],
+        interpreted: bool,
+        prepare: F,
+    ) -> Result<Vec<BatchResult>, EbpfError> {
+        self.execute_batch_with(
+            executable,
+            inputs.len(),
+            interpreted,
+            prepare,
+            |index, _batch_input| {
+                let input = &mut inputs[index];
+                input.reset()?;
+                Ok(input.region(ebpf::MM_INPUT_START))
+            },
+        )
+    }
+
+    fn execute_batch_with<F: FnMut(usize, &mut C), R>(
+        &mut self,
+        executable: &Executable<C>,
+        input_count: usize,
+        interpreted: bool,
+        mut prepare: F,
+        mut input_region: R,
+    ) -> Result<Vec<BatchResult>, EbpfError>
+    where
+        R: FnMut(
+            usize,
+            &mut AlignedMemory<{ ebpf::HOST_ALIGN }>,
+        ) -> Result<MemoryRegion, EbpfError>,
+    {
+        let (input_region_index, original_input_region) = self
+            .memory_mapping
+            .find_region(ebpf::MM_INPUT_START)
+            .filter(|(_, region)| region.vm_addr == ebpf::MM_INPUT_START)
+            .map(|(index, region)| (index, region.clone()))
+            .ok_or(EbpfError::InvalidMemoryRegion(0))?;
+        let zeroed_regions = [ebpf::MM_STACK_START, ebpf::MM_HEAP_START]
+            .iter()
+            .filter_map(|vm_addr| {
+                self.memory_mapping
+                    .find_region(*vm_addr)
+                    .filter(|(_, region)| region.writable && region.vm_addr == *vm_addr)
+                    .map(|(_, region)| (region.host_addr, region.len))
+            })
+            .collect::<Vec<_>>();
+        let initial_registers = self.registers;
+        let original_deadline = self.deadline;
+        let time_limit =
+            original_deadline.map(|deadline| (deadline.remaining(), deadline.check_interval));
+        let mut results = Vec::with_capacity(input_count);
+        let mut run_inputs = || {
+            for index in 0..input_count {
+                let mut region = input_region(index, &mut self.batch_input)?;
+                region.access_violation_handler_payload =
+                    original_input_region.access_violation_handler_payload;
+                region.alignment = original_input_region.alignment;
+                self.memory_mapping
+                    .replace_region(input_region_index, region)?;
+                for (host_addr, len) in zeroed_regions.iter() {
+                    // Safety: the region is writable and owned by the memory mapping of this VM
+                    unsafe { std::ptr::write_bytes(*host_addr as *mut u8, 0, *len as usize) };
+                }
+                self.registers = initial_registers;
+                self.call_depth = 0;
+                self.call_frames.fill(CallFrame::default());
+                if let Some(shadow_call_stack) = self.shadow_call_stack.as_mut() {
+                    shadow_call_stack.clear();
+                }
+                if let Some(loop_detector) = self.loop_detector.as_mut() {
+                    loop_detector.clear();
+                }
+                self.deadline = time_limit
+                    .and_then(|(timeout, check_interval)| Deadline::after(timeout, check_interval));
+                self.context_object_pointer.reset_trace();
+                prepare(index, self.context_object_pointer);
+                let (instruction_count, result) = self.execute_program(executable, interpreted);
+                let (trace, coverage_hash) =
+                    TraceSummary::from_trace_log(self.context_object_pointer.trace_log());
+                results.push(BatchResult {
+                    instruction_count,
+                    result,
+                    coverage_hash,
+                    trace,
+                    instrumentation_failures: std::mem::take(&mut self.instrumentation_failures),
+                });
+            }
+            Ok(())
+        };
+        let outcome = run_inputs();
+        self.deadline = original_deadline;
+        // The batch inputs may not outlive this call, so they must not stay mapped
+        self.memory_mapping
+            .replace_region(input_region_index, original_input_region)?;
+        outcome.map(|()| results)
+    }
+
     /// Invokes a built-in function
     pub fn invoke_function(&mut self, function: BuiltinFunction<C>) {
         function(
@@ -414,3 +912,23 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
         );
     }
 }
+
+#[cfg(all(test, feature = "jit", feature = "fuzzer-not-safe-for-production"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_runtime_environment_key() {
+        assert_eq!(parse_runtime_environment_key(Some("0")), Some(0));
+        assert_eq!(parse_runtime_environment_key(Some("123456")), Some(123456));
+        assert_eq!(
+            parse_runtime_environment_key(Some("-2147483648")),
+            Some(i32::MIN)
+        );
+        assert_eq!(parse_runtime_environment_key(None), None);
+        assert_eq!(parse_runtime_environment_key(Some("")), None);
+        assert_eq!(parse_runtime_environment_key(Some("0x10")), None);
+        assert_eq!(parse_runtime_environment_key(Some(" 1")), None);
+        assert_eq!(parse_runtime_environment_key(Some("2147483648")), None);
+    }
+}
@@ -0,0 +1,385 @@
+//! Parallel execution of many inputs
+//!
+//! An [EbpfVm] is bound to one thread, e.g. because its observers are not `Send`. An
+//! [EbpfVmPool] therefore creates one VM per worker thread, which share the [Executable] and
+//! take the next input until all are executed. Each worker runs its inputs with
+//! [EbpfVm::execute_batch] and collects their edges in its own [EdgeCoverage], the coverage of
+//! all workers is merged when they are done.
+//!
+//! A [canary](EbpfVmPool::canary) input warms up every VM before it takes inputs and serves
+//! as its health check: The canary is run again every
+//! [health_check_interval](EbpfVmPool::health_check_interval) inputs, and a VM whose result
+//! differs from the first one is considered poisoned, e.g. by state its context object kept
+//! from earlier inputs. It is replaced by a fresh VM with a new context object, stack and heap.
+
+use crate::{
+    aligned_memory::AlignedMemory,
+    ebpf,
+    elf::Executable,
+    error::{EbpfError, ProgramResult},
+    memory_region::{MemoryMapping, MemoryRegion},
+    static_analysis::TraceLogEntry,
+    vm::{ContextObject, EbpfVm, TraceSummary},
+};
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Number of executions which took each edge, a pair of consecutively traced pcs
+///
+/// Owned by a single worker of an [EbpfVmPool] and merged into the coverage of the pool.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EdgeCoverage {
+    /// (from pc, to pc) => number of executions which took the edge
+    edges: BTreeMap<(u64, u64), u64>,
+    /// Number of recorded executions
+    executions: u64,
+}
+
+impl EdgeCoverage {
+    /// Records the edges of a trace, returns whether any of them was not taken before
+    pub fn record(&mut self, trace_log: &[TraceLogEntry]) -> bool {
+        self.executions = self.executions.saturating_add(1);
+        let mut edges = trace_log
+            .windows(2)
+            .map(|states| (states[0][11], states[1][11]))
+            .collect::<Vec<_>>();
+        edges.sort_unstable();
+        edges.dedup();
+        let mut new_coverage = false;
+        for edge in edges {
+            let hits = self.edges.entry(edge).or_insert(0);
+            new_coverage |= *hits == 0;
+            *hits = hits.saturating_add(1);
+        }
+        new_coverage
+    }
+
+    /// Adds the executions recorded by `other`, returns whether it took any new edge
+    pub fn merge(&mut self, other: &Self) -> bool {
+        self.executions = self.executions.saturating_add(other.executions);
+        let mut new_coverage = false;
+        for (edge, other_hits) in other.edges.iter() {
+            let hits = self.edges.entry(*edge).or_insert(0);
+            new_coverage |= *hits == 0;
+            *hits = hits.saturating_add(*other_hits);
+        }
+        new_coverage
+    }
+
+    /// Number of executions which took the edge from `from_pc` to `to_pc`
+    pub fn hits(&self, from_pc: u64, to_pc: u64) -> u64 {
+        self.edges.get(&(from_pc, to_pc)).copied().unwrap_or(0)
+    }
+
+    /// (from pc, to pc) => number of executions which took the edge
+    pub fn edges(&self) -> &BTreeMap<(u64, u64), u64> {
+        &self.edges
+    }
+
+    /// Number of distinct edges taken
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Returns true if no edge was taken
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    /// Number of recorded executions
+    pub fn executions(&self) -> u64 {
+        self.executions
+    }
+}
+
+/// Outcome of a single input of [EbpfVmPool::execute]
+///
+/// Unlike a [BatchResult](crate::vm::BatchResult) it can be sent between threads, so errors
+/// are reported by their message: [EbpfError::SyscallError] boxes errors which are not `Send`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolResult {
+    /// Number of executed instructions
+    pub instruction_count: u64,
+    /// Return value of the program or its error message
+    pub result: Result<u64, String>,
+    /// Hash of the edge coverage, see [TraceSummary::from_trace_log]
+    pub coverage_hash: u64,
+    /// Summary of the trace
+    pub trace: TraceSummary,
+}
+
+/// Results of an [EbpfVmPool::execute]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolResults {
+    /// Outcome of every input in order
+    pub results: Vec<PoolResult>,
+    /// Merged coverage of all workers
+    pub coverage: EdgeCoverage,
+    /// Result of the canary on a fresh VM, if the pool has one
+    pub canary: Option<PoolResult>,
+    /// Number of poisoned VMs which were replaced
+    pub replaced_vms: usize,
+}
+
+/// What a worker of an [EbpfVmPool] collected
+#[derive(Default)]
+struct WorkerResults {
+    results: Vec<(usize, PoolResult)>,
+    coverage: EdgeCoverage,
+    canary: Option<PoolResult>,
+    replaced_vms: usize,
+}
+
+/// Executes inputs on a number of threads, each with its own [EbpfVm]
+///
+/// The VMs map the read-only section, a stack, a heap and the input, like the entrypoint of a
+/// program expects it. Coverage is only collected with `Config::enable_instruction_tracing`
+/// and a context object which exposes its [trace](ContextObject::trace_log).
+pub struct EbpfVmPool<'a, C: ContextObject> {
+    executable: &'a Executable<C>,
+    threads: usize,
+    heap_size: usize,
+    interpreted: bool,
+    canary: Option<&'a [u8]>,
+    health_check_interval: usize,
+}
+
+impl<'a, C: ContextObject> EbpfVmPool<'a, C> {
+    /// Creates a pool of at least one thread which interprets `executable`
+    pub fn new(executable: &'a Executable<C>, threads: usize) -> Self {
+        Self {
+            executable,
+            threads: threads.max(1),
+            heap_size: 0,
+            interpreted: true,
+            canary: None,
+            health_check_interval: 0,
+        }
+    }
+
+    /// Sets the size of the zeroed heap of every VM
+    pub fn heap_size(mut self, heap_size: usize) -> Self {
+        self.heap_size = heap_size;
+        self
+    }
+
+    /// Runs the JIT compiled program instead of interpreting it
+    pub fn jit(mut self, jit: bool) -> Self {
+        self.interpreted = !jit;
+        self
+    }
+
+    /// Runs `input` on every VM before it takes the first input, to warm it up
+    ///
+    /// The result on a fresh VM is the reference of the [health checks](Self::health_check_interval).
+    /// `prepare` is called with `usize::MAX` as index for the canary.
+    pub fn canary(mut self, input: &'a [u8]) -> Self {
+        self.canary = Some(input);
+        self
+    }
+
+    /// Runs the canary again after every `inputs` inputs of a VM and replaces the VM if its
+    /// result changed, `0` disables the checks
+    pub fn health_check_interval(mut self, inputs: usize) -> Self {
+        self.health_check_interval = inputs;
+        self
+    }
+
+    /// Number of worker threads
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Executes the program once per input
+    ///
+    /// Every worker creates its context object with `new_context_object`. Like in
+    /// [EbpfVm::execute_batch] `prepare` is called with the index of the input before it is
+    /// run, e.g. to reset the instruction meter.
+    pub fn execute<N, P>(
+        &self,
+        inputs: &[&[u8]],
+        new_context_object: N,
+        prepare: P,
+    ) -> Result<PoolResults, EbpfError>
+    where
+        C: Send,
+        Executable<C>: Sync,
+        N: Fn() -> C + Sync,
+        P: Fn(usize, &mut C) + Sync,
+    {
+        let next_input = AtomicUsize::new(0);
+        let workers = std::thread::scope(|scope| {
+            let handles = (0..self.threads.min(inputs.len()))
+                .map(|_| {
+                    scope.spawn(|| {
+                        self.run_worker(inputs, &next_input, &new_context_object, &prepare)
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<Vec<_>>()
+        });
+        let mut results = BTreeMap::new();
+        let mut pool_results = PoolResults::default();
+        for worker in workers {
+            // Errors of the workers are converted to messages, as they have to be sent
+            let worker = worker.map_err(|error| EbpfError::SyscallError(error))?;
+            results.extend(worker.results);
+            pool_results.coverage.merge(&worker.coverage);
+            pool_results.replaced_vms = pool_results
+                .replaced_vms
+                .saturating_add(worker.replaced_vms);
+            match (&pool_results.canary, worker.canary) {
+                (Some(canary), Some(worker_canary)) if *canary != worker_canary => {
+                    return Err(EbpfError::SyscallError(
+                        "the canary differs between fresh VMs".into(),
+                    ));
+                }
+                (None, worker_canary) => pool_results.canary = worker_canary,
+                _ => {}
+            }
+        }
+        pool_results.results = results.into_values().collect();
+        Ok(pool_results)
+    }
+
+    /// Executes inputs until there are none left, replacing poisoned VMs
+    ///
+    /// Fails if a VM can not be set up or the canary differs on a fresh VM, errors of the
+    /// program are part of its results.
+    fn run_worker<N, P>(
+        &self,
+        inputs: &[&[u8]],
+        next_input: &AtomicUsize,
+        new_context_object: &N,
+        prepare: &P,
+    ) -> Result<WorkerResults, Box<dyn std::error::Error + Send + Sync>>
+    where
+        N: Fn() -> C,
+        P: Fn(usize, &mut C),
+    {
+        let mut worker = WorkerResults::default();
+        while !self.run_vm(inputs, next_input, new_context_object, prepare, &mut worker)? {
+            worker.replaced_vms = worker.replaced_vms.saturating_add(1);
+        }
+        Ok(worker)
+    }
+
+    /// Executes inputs on a fresh VM, returns false if a health check found it poisoned
+    fn run_vm<N, P>(
+        &self,
+        inputs: &[&[u8]],
+        next_input: &AtomicUsize,
+        new_context_object: &N,
+        prepare: &P,
+        worker: &mut WorkerResults,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>
+    where
+        N: Fn() -> C,
+        P: Fn(usize, &mut C),
+    {
+        let config = self.executable.get_config();
+        let sbpf_version = self.executable.get_sbpf_version();
+        let mut context_object = new_context_object();
+        let mut stack = AlignedMemory::<{ ebpf::HOST_ALIGN }>::zero_filled(config.stack_size());
+        let mut heap = AlignedMemory::<{ ebpf::HOST_ALIGN }>::zero_filled(self.heap_size);
+        let stack_len = stack.len();
+        let regions = vec![
+            self.executable.get_ro_region(),
+            MemoryRegion::new_writable_gapped(
+                stack.as_slice_mut(),
+                ebpf::MM_STACK_START,
+                if !sbpf_version.dynamic_stack_frames() && config.enable_stack_frame_gaps {
+                    config.stack_frame_size as u64
+                } else {
+                    0
+                },
+            ),
+            MemoryRegion::new_writable(heap.as_slice_mut(), ebpf::MM_HEAP_START),
+            MemoryRegion::new_writable(&mut [], ebpf::MM_INPUT_START),
+        ];
+        let memory_mapping =
+            MemoryMapping::new(regions, config, sbpf_version).map_err(|error| error.to_string())?;
+        let mut vm = EbpfVm::new(
+            self.executable.get_loader().clone(),
+            sbpf_version,
+            &mut context_object,
+            memory_mapping,
+            stack_len,
+        );
+        vm.registers[1] = ebpf::MM_INPUT_START;
+        if let Some(canary) = self.canary {
+            let result = self.run_input(&mut vm, usize::MAX, canary, prepare)?;
+            match &worker.canary {
+                Some(expected) if *expected != result => {
+                    return Err("the canary differs on a fresh VM".into());
+                }
+                Some(_) => {}
+                None => worker.canary = Some(result),
+            }
+        }
+        let mut inputs_since_check = 0;
+        loop {
+            if let Some(canary) = self
+                .canary
+                .filter(|_| self.health_check_interval > 0)
+                .filter(|_| inputs_since_check == self.health_check_interval)
+            {
+                inputs_since_check = 0;
+                if worker.canary.as_ref()
+                    != Some(&self.run_input(&mut vm, usize::MAX, canary, prepare)?)
+                {
+                    return Ok(false);
+                }
+            }
+            let index = next_input.fetch_add(1, Ordering::Relaxed);
+            let Some(input) = inputs.get(index) else {
+                break;
+            };
+            let result = self.run_input(&mut vm, index, input, prepare)?;
+            worker
+                .coverage
+                .record(vm.context_object_pointer.trace_log());
+            worker.results.push((index, result));
+            inputs_since_check = inputs_since_check.saturating_add(1);
+        }
+        Ok(true)
+    }
+
+    /// Executes a single input
+    fn run_input<P>(
+        &self,
+        vm: &mut EbpfVm<C>,
+        index: usize,
+        input: &[u8],
+        prepare: &P,
+    ) -> Result<PoolResult, Box<dyn std::error::Error + Send + Sync>>
+    where
+        P: Fn(usize, &mut C),
+    {
+        let batch_result = vm
+            .execute_batch(self.executable, &[input], self.interpreted, |_, context| {
+                prepare(index, context)
+            })
+            .map_err(|error| error.to_string())?
+            .pop()
+            .expect("one result per input");
+        Ok(PoolResult {
+            instruction_count: batch_result.instruction_count,
+            result: match batch_result.result {
+                ProgramResult::Ok(value) => Ok(value),
+                ProgramResult::Err(error) => Err(error.to_string()),
+            },
+            coverage_hash: batch_result.coverage_hash,
+            trace: batch_result.trace,
+        })
+    }
+}
@@ -0,0 +1,191 @@
+//! Watch expressions sampled during execution
+//!
+//! A [Watcher] executes a program in the interpreter and evaluates a set of
+//! [WatchExpression]s over the registers and memory, either before every instruction
+//! or at the start of every basic block. The samples form a time series which can be
+//! exported as CSV for plotting, e.g. to follow a balance in the input across a transaction.
+
+use crate::{
+    elf::Executable,
+    error::ProgramResult,
+    interpreter::Interpreter,
+    memory_region::{AccessType, MemoryMapping},
+    static_analysis::Analysis,
+    vm::{ContextObject, EbpfVm},
+};
+use std::collections::BTreeSet;
+
+/// Formula over the registers and memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchExpression {
+    /// Constant value
+    Constant(u64),
+    /// Register 0 to 10, 11 being the pc
+    Register(u8),
+    /// Little endian load of 1, 2, 4 or 8 bytes at the address given by the inner expression
+    Load {
+        /// Virtual address
+        address: Box<WatchExpression>,
+        /// Size in bytes
+        size: u8,
+    },
+    /// Wrapping addition
+    Add(Box<WatchExpression>, Box<WatchExpression>),
+    /// Wrapping subtraction
+    Sub(Box<WatchExpression>, Box<WatchExpression>),
+}
+
+impl WatchExpression {
+    /// Load of `size` bytes at a constant address
+    pub fn load(address: u64, size: u8) -> Self {
+        Self::Load {
+            address: Box::new(Self::Constant(address)),
+            size,
+        }
+    }
+
+    /// Evaluates the expression, `None` if it accesses unmapped memory
+    pub fn evaluate(&self, registers: &[u64; 12], memory_mapping: &MemoryMapping) -> Option<u64> {
+        match self {
+            Self::Constant(value) => Some(*value),
+            Self::Register(register) => registers.get(*register as usize).copied(),
+            Self::Load { address, size } => {
+                if !matches!(size, 1 | 2 | 4 | 8) {
+                    return None;
+                }
+                let vm_addr = address.evaluate(registers, memory_mapping)?;
+                let host_addr = match memory_mapping.map(AccessType::Load, vm_addr, *size as u64) {
+                    ProgramResult::Ok(host_addr) => host_addr,
+                    ProgramResult::Err(_) => return None,
+                };
+                let mut bytes = [0u8; 8];
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        host_addr as *const u8,
+                        bytes.as_mut_ptr(),
+                        *size as usize,
+                    );
+                }
+                Some(u64::from_le_bytes(bytes))
+            }
+            Self::Add(lhs, rhs) => Some(
+                lhs.evaluate(registers, memory_mapping)?
+                    .wrapping_add(rhs.evaluate(registers, memory_mapping)?),
+            ),
+            Self::Sub(lhs, rhs) => Some(
+                lhs.evaluate(registers, memory_mapping)?
+                    .wrapping_sub(rhs.evaluate(registers, memory_mapping)?),
+            ),
+        }
+    }
+}
+
+/// Values of all watch expressions at one point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchSample {
+    /// Number of instructions executed before the sample was taken
+    pub instruction_index: u64,
+    /// Pc of the next instruction
+    pub pc: u64,
+    /// One value per watch expression, in the order they were registered
+    pub values: Vec<Option<u64>>,
+}
+
+/// Executes programs while sampling watch expressions
+#[derive(Debug, Default)]
+pub struct Watcher {
+    expressions: Vec<(String, WatchExpression)>,
+    /// Sample only at these pcs, `None` to sample before every instruction
+    basic_blocks: Option<BTreeSet<u64>>,
+    /// Samples of the executions so far
+    pub samples: Vec<WatchSample>,
+}
+
+impl Watcher {
+    /// Registers a named expression
+    pub fn watch(&mut self, name: &str, expression: WatchExpression) {
+        self.expressions.push((name.to_string(), expression));
+    }
+
+    /// Samples only at the start of the basic blocks of the given analysis
+    pub fn sample_basic_blocks(&mut self, analysis: &Analysis) {
+        self.basic_blocks = Some(analysis.cfg_nodes.keys().map(|pc| *pc as u64).collect());
+    }
+
+    /// Samples before every instruction
+    pub fn sample_instructions(&mut self) {
+        self.basic_blocks = None;
+    }
+
+    /// Executes the program in the interpreter, like [EbpfVm::execute_program]
+    pub fn execute<C: ContextObject>(
+        &mut self,
+        vm: &mut EbpfVm<C>,
+        executable: &Executable<C>,
+    ) -> (u64, ProgramResult) {
+        vm.registers[11] = executable.get_entrypoint_instruction_offset() as u64;
+        let initial_insn_count = vm.context_object_pointer.get_remaining();
+        vm.previous_instruction_meter = initial_insn_count;
+        vm.due_insn_count = 0;
+        vm.program_result = ProgramResult::Ok(0);
+        {
+            let mut interpreter = Interpreter::new(vm, executable, vm.registers);
+            let mut instruction_index = 0u64;
+            loop {
+                let pc = interpreter.reg[11];
+                if self
+                    .basic_blocks
+                    .as_ref()
+                    .map(|basic_blocks| basic_blocks.contains(&pc))
+                    .unwrap_or(true)
+                {
+                    let values = self
+                        .expressions
+                        .iter()
+                        .map(|(_name, expression)| {
+                            expression.evaluate(&interpreter.reg, &interpreter.vm.memory_mapping)
+                        })
+                        .collect();
+                    self.samples.push(WatchSample {
+                        instruction_index,
+                        pc,
+                        values,
+                    });
+                }
+                instruction_index = instruction_index.saturating_add(1);
+                if !interpreter.step() {
+                    break;
+                }
+            }
+        }
+        let instruction_count = if executable.get_config().enable_instruction_meter {
+            vm.context_object_pointer.consume(vm.due_insn_count);
+            initial_insn_count.saturating_sub(vm.context_object_pointer.get_remaining())
+        } else {
+            0
+        };
+        let mut result = ProgramResult::Ok(0);
+        std::mem::swap(&mut result, &mut vm.program_result);
+        (instruction_count, result)
+    }
+
+    /// Writes the samples as CSV with one column per expression, unmapped values are left empty
+    pub fn write_csv<W: std::io::Write>(&self, output: &mut W) -> std::io::Result<()> {
+        write!(output, "instruction,pc")?;
+        for (name, _expression) in self.expressions.iter() {
+            write!(output, ",{}", name)?;
+        }
+        writeln!(output)?;
+        for sample in self.samples.iter() {
+            write!(output, "{},{}", sample.instruction_index, sample.pc)?;
+            for value in sample.values.iter() {
+                match value {
+                    Some(value) => write!(output, ",{}", value)?,
+                    None => write!(output, ",")?,
+                }
+            }
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+}
@@ -27,6 +27,8 @@ pub struct TestContextObject {
     pub trace_log: Vec<TraceLogEntry>,
     /// Maximal amount of instructions which still can be executed
     pub remaining: u64,
+    /// Instrumentation work done so far
+    pub instrumentation: u64,
 }
 
 impl ContextObject for TestContextObject {
@@ -41,6 +43,22 @@ impl ContextObject for TestContextObject {
     fn get_remaining(&self) -> u64 {
         self.remaining
     }
+
+    fn consume_instrumentation(&mut self, amount: u64) {
+        self.instrumentation = self.instrumentation.saturating_add(amount);
+    }
+
+    fn get_instrumentation(&self) -> u64 {
+        self.instrumentation
+    }
+
+    fn trace_log(&self) -> &[TraceLogEntry] {
+        &self.trace_log
+    }
+
+    fn reset_trace(&mut self) {
+        self.trace_log.clear();
+    }
 }
 
 impl TestContextObject {
@@ -49,6 +67,7 @@ impl TestContextObject {
         Self {
             trace_log: Vec::new(),
             remaining,
+            instrumentation: 0,
         }
     }
 
@@ -316,6 +335,40 @@ macro_rules! test_interpreter_and_jit {
                 vm.context_object_pointer.clone(),
             )
         };
+        #[cfg(feature = "jit-cranelift")]
+        {
+            $executable.cranelift_compile().unwrap();
+            let mut mem = $mem;
+            let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+            let mut context_object = context_object.clone();
+            create_vm!(
+                vm,
+                &$executable,
+                &mut context_object,
+                stack,
+                heap,
+                vec![mem_region],
+                None
+            );
+            vm.registers[1] = ebpf::MM_INPUT_START;
+            let (instruction_count_cranelift, result_cranelift) = vm.execute_program(&$executable, false);
+            assert_eq!(
+                format!("{:?}", result_interpreter), format!("{:?}", result_cranelift),
+                "Result of interpreter and Cranelift JIT diverged",
+            );
+            assert_eq!(
+                instruction_count_interpreter, instruction_count_cranelift,
+                "Instruction meter of interpreter and Cranelift JIT diverged",
+            );
+            assert_eq!(
+                interpreter_final_pc, vm.registers[11],
+                "Final PC of interpreter and Cranelift JIT diverged",
+            );
+            assert!(
+                TestContextObject::compare_trace_log(&_tracer_interpreter, &vm.context_object_pointer),
+                "Trace of interpreter and Cranelift JIT diverged",
+            );
+        }
         #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
         {
             #[allow(unused_mut)]
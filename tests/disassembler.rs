@@ -11,9 +11,15 @@
 extern crate solana_sbpf;
 use solana_sbpf::program::SBPFVersion;
 use solana_sbpf::{
-    assembler::assemble, program::BuiltinProgram, static_analysis::Analysis, vm::Config,
+    assembler::assemble,
+    disassembler::disassemble_instruction,
+    ebpf,
+    opcode_table::OPCODE_TABLE,
+    program::{BuiltinProgram, FunctionRegistry},
+    static_analysis::Analysis,
+    vm::Config,
 };
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 use test_utils::TestContextObject;
 
 // Using a macro to keep actual line numbers in failure output
@@ -368,3 +374,47 @@ fn test_large_immediate() {
     disasm!("entrypoint:\n    add64 r1, -1\n");
     disasm!("entrypoint:\n    add64 r1, -1\n");
 }
+
+#[test]
+fn test_opcode_table_mnemonics() {
+    let loader = BuiltinProgram::<TestContextObject>::new_loader(Config::default());
+    let function_registry = FunctionRegistry::default();
+    for sbpf_version in [
+        SBPFVersion::V0,
+        SBPFVersion::V1,
+        SBPFVersion::V2,
+        SBPFVersion::V3,
+        SBPFVersion::V4,
+    ] {
+        for info in OPCODE_TABLE
+            .iter()
+            .filter(|info| (info.available)(sbpf_version))
+        {
+            // Without a registered function the disassembler prints calls as syscalls
+            if info.opcode == ebpf::CALL_IMM {
+                continue;
+            }
+            let insn = ebpf::Insn {
+                opc: info.opcode,
+                imm: 16,
+                ..ebpf::Insn::default()
+            };
+            let text = disassemble_instruction(
+                &insn,
+                0,
+                &BTreeMap::new(),
+                &function_registry,
+                &loader,
+                sbpf_version,
+            );
+            assert!(
+                text.starts_with(info.mnemonic),
+                "{:#x} in {:?}: {} vs {}",
+                info.opcode,
+                sbpf_version,
+                info.mnemonic,
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "dwarf")]
+
+use gimli::{
+    write::{Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Sections},
+    Encoding, Format, LineEncoding, LittleEndian,
+};
+use solana_sbpf::{
+    elf::Executable,
+    program::BuiltinProgram,
+    source_map::{SourceLocation, SourceMap},
+    vm::Config,
+};
+use std::{collections::BTreeMap, sync::Arc};
+use test_utils::TestContextObject;
+
+const TEXT_ADDRESS: u64 = 0x120;
+
+fn debug_sections() -> BTreeMap<gimli::SectionId, Vec<u8>> {
+    let encoding = Encoding {
+        format: Format::Dwarf32,
+        version: 4,
+        address_size: 8,
+    };
+    let mut dwarf = DwarfUnit::new(encoding);
+    let mut line_program = LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        LineString::String(b"/src".to_vec()),
+        LineString::String(b"lib.rs".to_vec()),
+        None,
+    );
+    let directory = line_program.default_directory();
+    let lib = line_program.add_file(LineString::String(b"lib.rs".to_vec()), directory, None);
+    let util = line_program.add_file(
+        LineString::String(b"/deps/util.rs".to_vec()),
+        directory,
+        None,
+    );
+    line_program.begin_sequence(Some(Address::Constant(TEXT_ADDRESS)));
+    for (address_offset, file, line, column) in [(0, lib, 3, 5), (16, lib, 7, 0), (24, util, 2, 1)]
+    {
+        let row = line_program.row();
+        row.address_offset = address_offset;
+        row.file = file;
+        row.line = line;
+        row.column = column;
+        line_program.generate_row();
+    }
+    line_program.end_sequence(32);
+    dwarf.unit.line_program = line_program;
+    let root = dwarf.unit.root();
+    dwarf.unit.get_mut(root).set(
+        gimli::DW_AT_comp_dir,
+        AttributeValue::String(b"/src".to_vec()),
+    );
+    let mut sections = Sections::new(EndianVec::new(LittleEndian));
+    dwarf.write(&mut sections).unwrap();
+    let mut result = BTreeMap::new();
+    sections
+        .for_each(|id, data| {
+            result.insert(id, data.slice().to_vec());
+            Ok::<_, gimli::write::Error>(())
+        })
+        .unwrap();
+    result
+}
+
+#[test]
+fn test_source_map() {
+    let sections = debug_sections();
+    let source_map = SourceMap::new(TEXT_ADDRESS, |id| {
+        sections.get(&id).map_or(&[], |data| data.as_slice())
+    })
+    .unwrap();
+    assert!(!source_map.is_empty());
+    let location = |file: &str, line, column| {
+        Some(SourceLocation {
+            file: file.to_string(),
+            line,
+            column,
+        })
+    };
+    assert_eq!(source_map.lookup(0), location("/src/lib.rs", 3, 5));
+    assert_eq!(source_map.lookup(1), location("/src/lib.rs", 3, 5));
+    assert_eq!(source_map.lookup(2), location("/src/lib.rs", 7, 0));
+    assert_eq!(source_map.lookup(3), location("/deps/util.rs", 2, 1));
+    assert_eq!(source_map.lookup(4), None);
+    assert_eq!(source_map.lookup(0).unwrap().to_string(), "/src/lib.rs:3:5");
+    assert_eq!(source_map.lookup(2).unwrap().to_string(), "/src/lib.rs:7");
+
+    // Rows before the text section are not mapped to pcs
+    let source_map = SourceMap::new(TEXT_ADDRESS + 16, |id| {
+        sections.get(&id).map_or(&[], |data| data.as_slice())
+    })
+    .unwrap();
+    assert_eq!(source_map.lookup(0), location("/src/lib.rs", 7, 0));
+    assert_eq!(source_map.lookup(2), None);
+}
+
+#[test]
+fn test_source_map_without_debug_info() {
+    let elf = std::fs::read("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    assert_eq!(SourceMap::from_elf(&elf), None);
+    let executable = Executable::<TestContextObject>::load(
+        &elf,
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    assert!(executable.get_source_map().is_none());
+    assert_eq!(executable.pc_to_source(0), None);
+}
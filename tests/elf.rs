@@ -2,15 +2,23 @@
 
 use byteorder::{ByteOrder, LittleEndian};
 use solana_sbpf::{
-    ebpf,
-    elf::{get_ro_region, ElfError, Executable, Section},
+    assembler::assemble,
+    compatibility::Incompatibility,
+    declare_builtin_function, ebpf,
+    elf::{get_ro_region, ElfError, ElfIssueCategory, Executable, Section},
     elf_parser::{
         consts::{ELFCLASS32, ELFCLASS64, ELFDATA2LSB, ELFDATA2MSB, ELFOSABI_NONE, EM_BPF, ET_REL},
         types::{Elf64Ehdr, Elf64Phdr, Elf64Shdr},
         Elf64, ElfParserError, SECTION_NAME_LENGTH_MAXIMUM,
     },
+    error::ProgramResult,
+    harness::{
+        EntrypointKind, HarnessBuilder, HarnessContextObject, HarnessSkeleton, InputLayout,
+        RequiredSyscall,
+    },
     memory_region::{AccessType, MemoryMapping},
     program::{BuiltinProgram, SBPFVersion},
+    static_analysis::Analysis,
     vm::Config,
 };
 use std::{fs::File, io::Read, sync::Arc};
@@ -843,6 +851,59 @@ fn test_err_unresolved_syscall_reloc_64_32() {
     ElfExecutable::load(&elf_bytes, Arc::new(loader)).expect("validation failed");
 }
 
+#[test]
+fn test_diagnose_elf() {
+    let elf_bytes = std::fs::read("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    assert!(ElfExecutable::diagnose_elf(&elf_bytes, loader()).is_empty());
+
+    // Report all issues of the lenient parser
+    let mut elf_bytes = elf_bytes.clone();
+    elf_bytes[7] = 1;
+    LittleEndian::write_i32(&mut elf_bytes[0x164..0x168], -11i32);
+    LittleEndian::write_i32(&mut elf_bytes[0x17c..0x180], 5);
+    let diagnostics = ElfExecutable::diagnose_elf(&elf_bytes, loader());
+    assert_eq!(
+        diagnostics.issues,
+        vec![
+            ElfError::WrongAbi,
+            ElfError::RelativeJumpOutOfBounds(8),
+            ElfError::RelativeJumpOutOfBounds(11),
+        ]
+    );
+    assert_eq!(
+        diagnostics
+            .in_category(ElfIssueCategory::Relocation)
+            .count(),
+        2
+    );
+    assert_eq!(
+        ElfExecutable::load(&elf_bytes, loader()).unwrap_err(),
+        ElfError::WrongAbi
+    );
+
+    // Report all broken program headers of the strict parser
+    let mut elf_bytes = std::fs::read("tests/elfs/strict_header.so").unwrap();
+    for header_index in [1, 3] {
+        elf_bytes
+            [std::mem::size_of::<Elf64Ehdr>() + std::mem::size_of::<Elf64Phdr>() * header_index] =
+            0xAF;
+    }
+    assert_eq!(
+        ElfExecutable::diagnose_elf(&elf_bytes, loader()).issues,
+        vec![
+            ElfError::InvalidProgramHeader,
+            ElfError::InvalidProgramHeader
+        ]
+    );
+
+    // Issues which prevent checking the rest of the file end the list
+    elf_bytes[0] = 0;
+    assert_eq!(
+        ElfExecutable::diagnose_elf(&elf_bytes, loader()).issues,
+        vec![ElfError::FailedToParse("invalid file header".to_string())]
+    );
+}
+
 #[test]
 fn test_long_section_name() {
     let elf_bytes = std::fs::read("tests/elfs/long_section_name.so").unwrap();
@@ -855,3 +916,217 @@ fn test_long_section_name() {
         SECTION_NAME_LENGTH_MAXIMUM
     );
 }
+
+#[test]
+fn test_harness_skeleton() {
+    let elf_bytes = std::fs::read("tests/elfs/syscall_reloc_64_32_sbpfv0.so").unwrap();
+    let skeleton =
+        HarnessSkeleton::from_elf::<TestContextObject>(&elf_bytes, Config::default()).unwrap();
+    assert_eq!(skeleton.sbpf_version, SBPFVersion::V0);
+    assert_eq!(skeleton.entrypoint_name.as_deref(), Some("entrypoint"));
+    assert_eq!(
+        skeleton.required_syscalls,
+        vec![RequiredSyscall {
+            hash: ebpf::hash_symbol_name(b"log"),
+            name: Some("log".to_string()),
+        }]
+    );
+    skeleton.check_loader(&loader()).unwrap();
+    let empty_loader = BuiltinProgram::<TestContextObject>::new_loader(Config::default());
+    assert_error!(
+        skeleton.check_loader(&empty_loader),
+        "UnresolvedSymbol(\"log\""
+    );
+
+    let elf_bytes = std::fs::read("tests/elfs/syscall_static.so").unwrap();
+    let skeleton =
+        HarnessSkeleton::from_elf::<TestContextObject>(&elf_bytes, Config::default()).unwrap();
+    assert_eq!(skeleton.required_syscalls.len(), 1);
+    assert_eq!(
+        skeleton.required_syscalls[0].hash,
+        ebpf::hash_symbol_name(b"log")
+    );
+
+    let elf_bytes = std::fs::read("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    let skeleton =
+        HarnessSkeleton::from_elf::<TestContextObject>(&elf_bytes, Config::default()).unwrap();
+    assert!(skeleton.required_syscalls.is_empty());
+    assert_eq!(skeleton.min_input_size, 1);
+}
+
+declare_builtin_function!(
+    SyscallIgnore,
+    fn rust(
+        _context_object: &mut HarnessContextObject,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(0)
+    }
+);
+
+#[test]
+fn test_harness() {
+    let elf_bytes = std::fs::read("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    for jit in [false, true] {
+        let mut harness = HarnessBuilder::default()
+            .coverage_map_size(1000)
+            .jit(jit)
+            .build(&elf_bytes)
+            .unwrap();
+        assert_eq!(harness.coverage().len(), 1024);
+        assert!(matches!(harness.run(&[5]), ProgramResult::Ok(11)));
+        assert!(harness.instruction_count() > 0);
+        assert!(harness.coverage().iter().any(|hits| *hits > 0));
+        assert_eq!(harness.calls().values().sum::<u64>(), 2);
+        let function_coverage = harness.function_coverage();
+        assert_eq!(
+            function_coverage.keys().copied().collect::<Vec<_>>(),
+            [0, 13]
+        );
+        assert!(function_coverage
+            .values()
+            .all(|function| function.name.is_none()));
+        assert_eq!(
+            function_coverage[&0].call_sites.iter().collect::<Vec<_>>(),
+            [&8]
+        );
+        let coverage_hash = harness.coverage_hash();
+        assert_ne!(coverage_hash, 0);
+        // Takes the same path with a different result
+        assert!(matches!(harness.run(&[7]), ProgramResult::Ok(15)));
+        assert_eq!(harness.coverage_hash(), coverage_hash);
+    }
+    let mut harness = HarnessBuilder::default()
+        .config(Config {
+            enable_symbol_and_section_labels: true,
+            ..Config::default()
+        })
+        .build(&elf_bytes)
+        .unwrap();
+    assert!(matches!(harness.run(&[5]), ProgramResult::Ok(11)));
+    assert_eq!(
+        harness.function_coverage()[&13].name.as_deref(),
+        Some("function_13")
+    );
+    let run = |input: &[u8]| {
+        solana_sbpf::solana_fuzz_target!(
+            HarnessBuilder::default()
+                .build(&std::fs::read("tests/elfs/relative_call_sbpfv0.so").unwrap())
+                .unwrap(),
+            input
+        )
+    };
+    assert!(matches!(run(&[1]), ProgramResult::Ok(3)));
+    assert!(matches!(run(&[2]), ProgramResult::Ok(5)));
+
+    let elf_bytes = std::fs::read("tests/elfs/syscall_static.so").unwrap();
+    assert_error!(
+        HarnessBuilder::default().build(&elf_bytes),
+        "UnresolvedSymbol({:?}",
+        format!("{:#x}", ebpf::hash_symbol_name(b"log"))
+    );
+    let mut harness = HarnessBuilder::default()
+        .syscall("log", SyscallIgnore::vm)
+        .build(&elf_bytes)
+        .unwrap();
+    assert!(matches!(harness.run(&[]), ProgramResult::Ok(0)));
+}
+
+#[test]
+fn test_entrypoint_fingerprint() {
+    let fingerprint = |source: &str| {
+        let executable = assemble::<TestContextObject>(
+            source,
+            Arc::new(BuiltinProgram::new_loader(Config {
+                enable_symbol_and_section_labels: true,
+                ..Config::default()
+            })),
+        )
+        .unwrap();
+        let analysis = Analysis::from_executable(&executable).unwrap();
+        EntrypointKind::fingerprint(&executable, &analysis)
+    };
+    let kind = fingerprint(
+        "
+        entrypoint:
+        call function_deserialize
+        exit
+        function_deserialize:
+        ldxdw r2, [r1+88]
+        add64 r2, 10240
+        exit",
+    );
+    assert_eq!(kind, EntrypointKind::SolanaProgram);
+    assert_eq!(kind.input_layout(), InputLayout::Aligned);
+    let kind = fingerprint(
+        "
+        entrypoint:
+        call function_anchor_lang_entry
+        exit
+        function_anchor_lang_entry:
+        exit",
+    );
+    assert_eq!(kind, EntrypointKind::Anchor);
+    let kind = fingerprint(
+        "
+        ldxdw r0, [r1]
+        exit",
+    );
+    assert_eq!(kind, EntrypointKind::Custom);
+    assert_eq!(kind.input_layout(), InputLayout::Unknown);
+}
+
+#[test]
+fn test_compatibility_report() {
+    let elf_bytes = std::fs::read("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    let executable = ElfExecutable::load(&elf_bytes, loader()).unwrap();
+    let report = executable.compatibility_report();
+    assert_eq!(report.sbpf_version, SBPFVersion::V0);
+    assert!(!report.uses_lddw && !report.uses_static_syscalls);
+    assert_eq!(report.compatible_versions(), vec![SBPFVersion::V0]);
+    // The callees get fixed stack frames only in SBPFv0
+    assert_eq!(
+        report.incompatibilities[1],
+        (
+            SBPFVersion::V1,
+            vec![
+                Incompatibility::ChangedSemantics {
+                    pc: 8,
+                    opcode: ebpf::CALL_IMM
+                },
+                Incompatibility::ChangedSemantics {
+                    pc: 11,
+                    opcode: ebpf::CALL_IMM
+                },
+            ]
+        )
+    );
+    assert!(report.incompatibilities[3]
+        .1
+        .contains(&Incompatibility::ElfFormat));
+    assert_eq!(
+        report.enabled_sbpf_versions(),
+        Some(SBPFVersion::V0..=SBPFVersion::V0)
+    );
+
+    let elf_bytes = std::fs::read("tests/elfs/syscall_static.so").unwrap();
+    let executable = ElfExecutable::load(&elf_bytes, loader()).unwrap();
+    let report = executable.compatibility_report();
+    assert_eq!(report.sbpf_version, SBPFVersion::V4);
+    assert!(report.uses_static_syscalls);
+    assert_eq!(
+        report.enabled_sbpf_versions(),
+        Some(SBPFVersion::V3..=SBPFVersion::V4)
+    );
+    assert!(report.incompatibilities[2]
+        .1
+        .contains(&Incompatibility::ChangedOpcode {
+            pc: 4,
+            opcode: ebpf::SYSCALL
+        }));
+}
@@ -19,6 +19,7 @@ use byteorder::{ByteOrder, LittleEndian};
 use rand::{rngs::SmallRng, RngCore, SeedableRng};
 use solana_sbpf::{
     assembler::assemble,
+    cost_model::CostModel,
     declare_builtin_function, ebpf,
     elf::Executable,
     error::{EbpfError, ProgramResult},
@@ -1317,6 +1318,59 @@ fn test_stxb_chain() {
     );
 }
 
+#[test]
+fn test_translation_cache() {
+    for enable_translation_cache in [false, true] {
+        let config = Config {
+            enable_translation_cache,
+            ..Config::default()
+        };
+        test_interpreter_and_jit_asm!(
+            "
+            add64 r10, 0
+            mov64 r0, 0
+            mov64 r2, 0
+            ldxb r3, [r1+0]
+            add64 r0, r3
+            ldxh r3, [r1+3]
+            add64 r0, r3
+            stxdw [r10-8], r0
+            ldxdw r0, [r10-8]
+            add64 r1, 1
+            add64 r2, 1
+            jlt r2, 8, -9
+            exit",
+            config.clone(),
+            [
+                0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, //
+                0x09, 0x0a, 0x0b, 0x0c, //
+            ],
+            TestContextObject::new(76),
+            ProgramResult::Ok(0x4460),
+        );
+        test_interpreter_and_jit_asm!(
+            "
+            add64 r10, 0
+            ldxdw r0, [r1+0]
+            stxdw [r1+4], r0
+            ldxdw r0, [r1+6]
+            exit",
+            config,
+            [
+                0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, //
+                0x09, 0x0a, 0x0b, 0x0c, //
+            ],
+            TestContextObject::new(4),
+            ProgramResult::Err(EbpfError::AccessViolation(
+                AccessType::Load,
+                0x400000006,
+                8,
+                "input"
+            )),
+        );
+    }
+}
+
 // BPF_JMP : Branches
 
 #[test]
@@ -2746,6 +2800,79 @@ fn test_nested_vm_syscall() {
     assert_error!(result, "CallDepthExceeded");
 }
 
+declare_builtin_function!(
+    /// For test_translation_cache_invalidation()
+    SyscallReplaceInputRegion,
+    fn rust(
+        _context_object: &mut TestContextObject,
+        shift: u64,
+        writable: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let (index, region) = memory_mapping.find_region(ebpf::MM_INPUT_START).unwrap();
+        let region = MemoryRegion {
+            host_addr: region.host_addr + shift,
+            len: region.len - shift,
+            writable: writable != 0,
+            ..region.clone()
+        };
+        memory_mapping.replace_region(index, region)?;
+        Ok(0)
+    }
+);
+
+#[test]
+fn test_translation_cache_invalidation() {
+    for enable_translation_cache in [false, true] {
+        let config = Config {
+            enable_translation_cache,
+            enable_instruction_tracing: true,
+            ..Config::default()
+        };
+        let mut loader = BuiltinProgram::new_loader(config);
+        loader
+            .register_function("input_region_syscall", SyscallReplaceInputRegion::vm)
+            .unwrap();
+        let mut executable = assemble::<TestContextObject>(
+            "
+            add64 r10, 0
+            mov64 r6, r1
+            ldxdw r7, [r6+0]
+            stxdw [r6+0], r7
+            mov64 r1, 8
+            mov64 r2, 1
+            syscall input_region_syscall
+            ldxdw r0, [r6+0]
+            stxdw [r6+0], r7
+            mov64 r1, 0
+            mov64 r2, 0
+            syscall input_region_syscall
+            ldxdw r0, [r6+0]
+            stxdw [r6+0], r0
+            exit",
+            Arc::new(loader),
+        )
+        .unwrap();
+        test_interpreter_and_jit!(
+            executable,
+            [
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+                0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+            ],
+            TestContextObject::new(14),
+            ProgramResult::Err(EbpfError::AccessViolation(
+                AccessType::Store,
+                ebpf::MM_INPUT_START,
+                8,
+                "input"
+            )),
+        );
+    }
+}
+
 // Instruction Meter Limit
 
 #[test]
@@ -2843,6 +2970,96 @@ fn test_err_instruction_count_syscall_capped() {
     );
 }
 
+#[test]
+fn test_instruction_count_cost_model() {
+    for (budget, expected_result) in [
+        (17, ProgramResult::Ok(0)),
+        (16, ProgramResult::Err(EbpfError::ExceededMaxInstructions)),
+    ] {
+        let config = Config {
+            enable_instruction_tracing: true,
+            enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+            cost_model: Some(
+                CostModel::default()
+                    .with_opcode_cost(ebpf::MOV64_IMM, 2)
+                    .with_syscall_cost("bpf_syscall_string", 10),
+            ),
+            ..Config::default()
+        };
+        let mut loader = BuiltinProgram::new_loader(config);
+        loader
+            .register_function("bpf_syscall_string", syscalls::SyscallString::vm)
+            .unwrap();
+        #[allow(unused_mut)]
+        let mut executable = assemble(
+            "
+            add64 r10, 0
+            mov64 r2, 0x5
+            syscall bpf_syscall_string
+            mov64 r0, 0x0
+            exit",
+            Arc::new(loader),
+        )
+        .unwrap();
+        // Only the interpreter supports a cost model
+        #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+        assert_error!(executable.jit_compile(), "UnsupportedCostModel");
+        let mut mem = [72, 101, 108, 108, 111];
+        let mut context_object = TestContextObject::new(budget);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+            None
+        );
+        vm.registers[1] = ebpf::MM_INPUT_START;
+        let (_instruction_count, result) = vm.execute_program(&executable, false);
+        assert_error!(result, "UnsupportedCostModel");
+        let (instruction_count, result) = vm.execute_program(&executable, true);
+        assert_eq!(format!("{:?}", result), format!("{:?}", expected_result));
+        assert_eq!(instruction_count, budget.min(17));
+    }
+}
+
+#[test]
+fn test_syscall_cost_exceeds_meter() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+        cost_model: Some(CostModel::default().with_syscall_cost("bpf_syscall_u64", 10)),
+        ..Config::default()
+    };
+    let mut loader = BuiltinProgram::new_loader(config);
+    loader
+        .register_function("bpf_syscall_u64", syscalls::SyscallU64::vm)
+        .unwrap();
+    let executable = assemble(
+        "
+        add64 r10, 0
+        syscall bpf_syscall_u64
+        mov64 r0, 0x0
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(5);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert_error!(result, "ExceededMaxInstructions");
+    // Reported by the syscall instruction, which is not dispatched
+    assert_eq!(vm.registers[11], 1);
+}
+
 #[test]
 fn test_err_non_terminate_capped() {
     test_interpreter_and_jit_asm!(
@@ -0,0 +1,132 @@
+#![cfg(feature = "ffi")]
+
+use solana_sbpf::ffi::{
+    sbpf_program_coverage, sbpf_program_execute, sbpf_program_free, sbpf_program_load,
+    sbpf_program_taint_summary, SbpfExecutionResult, SbpfTaintSummary,
+};
+use std::{fs::File, io::Read};
+
+#[test]
+fn test_ffi_execute() {
+    let mut file = File::open("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    let mut elf = Vec::new();
+    file.read_to_end(&mut elf).unwrap();
+    unsafe {
+        assert!(sbpf_program_load(elf.as_ptr(), 4).is_null());
+        let program = sbpf_program_load(elf.as_ptr(), elf.len());
+        assert!(!program.is_null());
+        let mut input = [1u8];
+        let mut result = SbpfExecutionResult::default();
+        assert!(sbpf_program_execute(
+            program,
+            input.as_mut_ptr(),
+            input.len(),
+            0,
+            16,
+            &mut result,
+        ));
+        assert_eq!(result.status, 0);
+        assert_eq!(result.return_value, 3);
+        assert!(result.covered_instructions > 0);
+        assert!(result.covered_instructions <= result.instruction_count);
+        let text_len = sbpf_program_coverage(program, std::ptr::null_mut(), 0);
+        let mut coverage = vec![0u8; text_len];
+        sbpf_program_coverage(program, coverage.as_mut_ptr(), coverage.len());
+        assert_eq!(
+            coverage.iter().filter(|counter| **counter > 0).count() as u64,
+            result.covered_instructions
+        );
+
+        assert!(sbpf_program_execute(
+            program,
+            input.as_mut_ptr(),
+            input.len(),
+            0,
+            1,
+            &mut result,
+        ));
+        assert_eq!(result.status, 1);
+        sbpf_program_free(program);
+    }
+}
+
+#[test]
+fn test_ffi_taint_summary() {
+    let mut file = File::open("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    let mut elf = Vec::new();
+    file.read_to_end(&mut elf).unwrap();
+    unsafe {
+        let program = sbpf_program_load(elf.as_ptr(), elf.len());
+        assert!(!program.is_null());
+        let mut summary = SbpfTaintSummary::default();
+        assert!(!sbpf_program_taint_summary(
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            0,
+            &mut summary,
+        ));
+        assert!(!sbpf_program_taint_summary(
+            program,
+            std::ptr::null_mut(),
+            0,
+            std::ptr::null_mut(),
+        ));
+        // nothing was executed yet
+        assert!(sbpf_program_taint_summary(
+            program,
+            std::ptr::null_mut(),
+            0,
+            &mut summary,
+        ));
+        assert_eq!(summary, SbpfTaintSummary::default());
+
+        let mut input = [1u8];
+        let mut result = SbpfExecutionResult::default();
+        assert!(sbpf_program_execute(
+            program,
+            input.as_mut_ptr(),
+            input.len(),
+            0,
+            16,
+            &mut result,
+        ));
+        assert_eq!(result.status, 0);
+        // the input byte is loaded twice but never compared
+        let mut branch_input_bytes = [0xFFu8; 4];
+        assert!(sbpf_program_taint_summary(
+            program,
+            branch_input_bytes.as_mut_ptr(),
+            branch_input_bytes.len(),
+            &mut summary,
+        ));
+        assert_eq!(summary.tainted_comparisons, 0);
+        assert_eq!(summary.tainted_loads, 2);
+        assert_eq!(summary.branch_input_bytes, 0);
+        assert_eq!(summary.errors, 0);
+        assert_eq!(branch_input_bytes, [0; 4]);
+        sbpf_program_free(program);
+    }
+}
+
+#[test]
+fn test_ffi_header() {
+    let source = std::fs::read_to_string("src/ffi.rs").unwrap();
+    let header = std::fs::read_to_string("ffi/include/sbpf.h").unwrap();
+    let mut declared = 0;
+    for line in source.lines() {
+        let name = line
+            .strip_prefix("pub unsafe extern \"C\" fn ")
+            .or_else(|| line.strip_prefix("pub struct "))
+            .and_then(|rest| rest.split(['(', ' ']).next());
+        if let Some(name) = name {
+            assert!(
+                header.contains(&format!("{}(", name))
+                    || header.contains(&format!("struct {} ", name)),
+                "{} is not declared in sbpf.h",
+                name
+            );
+            declared += 1;
+        }
+    }
+    assert_eq!(declared, 8);
+}
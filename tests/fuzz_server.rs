@@ -0,0 +1,130 @@
+#![cfg(all(feature = "fuzz-server", unix))]
+
+use solana_sbpf::{
+    assembler::assemble,
+    declare_builtin_function,
+    fuzz_server::{ForkOutcome, ForkServer, ForkServerConfig, FuzzContextObject},
+    memory_region::MemoryMapping,
+    program::{BuiltinProgram, SBPFVersion},
+    verifier::RequisiteVerifier,
+    vm::Config,
+};
+use std::{sync::Arc, time::Duration};
+
+/// Signal number of abort() on Linux and macOS
+const SIGABRT: i32 = 6;
+
+declare_builtin_function!(
+    /// Takes the host process down
+    SyscallAbort,
+    fn rust(
+        _context_object: &mut FuzzContextObject,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        std::process::abort();
+    }
+);
+
+#[test]
+fn test_fork_server() {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        enable_instruction_tracing: true,
+        ..Config::default()
+    });
+    loader.register_function("abort", SyscallAbort::vm).unwrap();
+    let executable = assemble::<FuzzContextObject>(
+        "
+        ldxb r2, [r1]
+        jeq r2, 1, +3
+        jeq r2, 2, +4
+        stxb [r1], r2
+        exit
+        mov64 r0, 42
+        exit
+        syscall abort
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    let mut fork_server = ForkServer::new(executable, ForkServerConfig::default()).unwrap();
+
+    assert_eq!(
+        fork_server.run(&[0]).unwrap(),
+        ForkOutcome::Returned {
+            return_value: 0,
+            instruction_count: 5,
+        }
+    );
+    let coverage = fork_server.coverage().to_vec();
+    assert!(coverage.iter().any(|counter| *counter > 0));
+
+    assert_eq!(
+        fork_server.run(&[1]).unwrap(),
+        ForkOutcome::Returned {
+            return_value: 42,
+            instruction_count: 4,
+        }
+    );
+    match fork_server.run(&[]).unwrap() {
+        ForkOutcome::Failed {
+            error,
+            instruction_count,
+        } => {
+            assert!(error.starts_with("AccessViolation(Load"));
+            assert_eq!(instruction_count, 1);
+        }
+        outcome => panic!("unexpected {:?}", outcome),
+    }
+    assert_eq!(
+        fork_server.run(&[2]).unwrap(),
+        ForkOutcome::Crashed(SIGABRT)
+    );
+    // The server survives the crash of a child
+    assert!(matches!(
+        fork_server.run(&[0]).unwrap(),
+        ForkOutcome::Returned { .. }
+    ));
+    assert_eq!(fork_server.coverage(), coverage.as_slice());
+}
+
+#[test]
+fn test_fork_server_timeout() {
+    let executable = assemble::<FuzzContextObject>(
+        "
+        ldxb r2, [r1]
+        jeq r2, 0, +1
+        ja -1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    let mut fork_server = ForkServer::new(
+        executable,
+        ForkServerConfig {
+            instruction_limit: u64::MAX,
+            timeout: Some(Duration::from_millis(100)),
+            ..ForkServerConfig::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(fork_server.run(&[1]).unwrap(), ForkOutcome::TimedOut);
+    // The killed child is reaped and the server keeps working
+    assert_eq!(
+        fork_server.run(&[0]).unwrap(),
+        ForkOutcome::Returned {
+            return_value: 0,
+            instruction_count: 3,
+        }
+    );
+}
@@ -141,11 +141,8 @@ fn test_code_length_estimate() {
             };
             let mut executable = create_mockup_executable(config, &prog);
             let result = Executable::<TestContextObject>::jit_compile(&mut executable);
-            if result.is_err() {
-                assert!(matches!(
-                    result.unwrap_err(),
-                    EbpfError::UnsupportedInstruction
-                ));
+            if let Err(err) = result {
+                assert!(matches!(err, EbpfError::UnsupportedInstruction));
                 continue;
             }
             let machine_code_length = executable
@@ -178,3 +175,41 @@ fn test_code_length_estimate() {
         }
     }
 }
+
+#[cfg(feature = "fuzzer-not-safe-for-production")]
+#[test]
+fn test_diversification_seed() {
+    let mut prog = vec![0; ebpf::INSN_SIZE * 1024];
+    for pc in 0..1024 {
+        prog[pc * ebpf::INSN_SIZE] = ebpf::ADD64_IMM;
+    }
+    let machine_code = |diversification_seed, rng_seed| {
+        let config = Config {
+            diversification_seed,
+            rng_seed,
+            ..Config::default()
+        };
+        let mut executable = create_mockup_executable(config, &prog);
+        Executable::<TestContextObject>::jit_compile(&mut executable).unwrap();
+        executable
+            .get_compiled_program()
+            .unwrap()
+            .machine_code()
+            .to_vec()
+    };
+    for seed in 0..4 {
+        assert_eq!(
+            machine_code(Some(seed), None),
+            machine_code(Some(seed), Some(seed))
+        );
+        assert_ne!(
+            machine_code(Some(seed), None),
+            machine_code(Some(seed + 1), None)
+        );
+        // The seed of the builtins does not make the JIT predictable
+        assert_ne!(
+            machine_code(None, Some(seed)),
+            machine_code(None, Some(seed))
+        );
+    }
+}
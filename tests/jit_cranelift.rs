@@ -0,0 +1,225 @@
+#![cfg(feature = "jit-cranelift")]
+
+use solana_sbpf::{
+    assembler::assemble,
+    ebpf,
+    elf::Executable,
+    error::{EbpfError, ProgramResult},
+    memory_region::{AccessType, MemoryRegion},
+    program::{BuiltinProgram, SBPFVersion},
+    verifier::RequisiteVerifier,
+    vm::Config,
+};
+use std::sync::Arc;
+use test_utils::{create_vm, syscalls, TestContextObject};
+
+fn assemble_program(source: &str) -> Executable<TestContextObject> {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    let mut loader = BuiltinProgram::new_loader(config);
+    loader
+        .register_function("gather_bytes", syscalls::SyscallGatherBytes::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(source, Arc::new(loader)).unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    executable
+}
+
+fn execute(
+    executable: &Executable<TestContextObject>,
+    mut mem: Vec<u8>,
+    budget: u64,
+    interpreted: bool,
+) -> (u64, ProgramResult, u64, TestContextObject, Vec<u8>) {
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut context_object = TestContextObject::new(budget);
+    let (instruction_count, result, pc) = {
+        create_vm!(
+            vm,
+            executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![mem_region],
+            None
+        );
+        let (instruction_count, result) = vm.execute_program(executable, interpreted);
+        (instruction_count, result, vm.registers[11])
+    };
+    (instruction_count, result, pc, context_object, mem)
+}
+
+/// Executes the program in the interpreter and with Cranelift, and returns the common result
+fn test_interpreter_and_cranelift(source: &str, mem: &[u8], budget: u64) -> ProgramResult {
+    let mut executable = assemble_program(source);
+    let interpreter = execute(&executable, mem.to_vec(), budget, true);
+    executable.cranelift_compile().unwrap();
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    assert!(executable.get_compiled_program().is_none());
+    let cranelift = execute(&executable, mem.to_vec(), budget, false);
+    assert_eq!(
+        format!("{:?}", interpreter.1),
+        format!("{:?}", cranelift.1),
+        "Result of interpreter and Cranelift JIT diverged",
+    );
+    assert_eq!(
+        interpreter.0, cranelift.0,
+        "Instruction meter of interpreter and Cranelift JIT diverged",
+    );
+    assert_eq!(
+        interpreter.2, cranelift.2,
+        "Final PC of interpreter and Cranelift JIT diverged",
+    );
+    assert!(
+        TestContextObject::compare_trace_log(&interpreter.3, &cranelift.3),
+        "Trace of interpreter and Cranelift JIT diverged",
+    );
+    assert_eq!(
+        interpreter.4, cranelift.4,
+        "Memory of interpreter and Cranelift JIT diverged"
+    );
+    interpreter.1
+}
+
+#[test]
+fn test_cranelift_compile() {
+    let mut executable = assemble_program(
+        "
+        mov64 r0, 1
+        exit",
+    );
+    assert!(executable.get_cranelift_program().is_none());
+    let result = execute(&executable, vec![], 2, false).1;
+    assert_eq!(
+        format!("{:?}", result),
+        format!("{:?}", ProgramResult::Err(EbpfError::JitNotCompiled)),
+    );
+    executable.cranelift_compile().unwrap();
+    let program = executable.get_cranelift_program().unwrap();
+    assert!(program.machine_code_length() > 0);
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    {
+        executable.jit_compile().unwrap();
+        assert!(executable.get_cranelift_program().is_none());
+        assert!(executable.get_compiled_program().is_some());
+    }
+}
+
+#[test]
+fn test_cranelift_alu_and_branches() {
+    let result = test_interpreter_and_cranelift(
+        "
+        mov64 r0, 0
+        mov64 r1, 10
+        mov32 r2, -1
+        add64 r0, r1
+        xor64 r0, 0x55
+        lsh64 r0, 3
+        arsh32 r0, 1
+        sub64 r1, 1
+        jne r1, 0, -7
+        add64 r0, r2
+        exit",
+        &[],
+        74,
+    );
+    assert!(matches!(result, ProgramResult::Ok(_)));
+}
+
+#[test]
+fn test_cranelift_memory() {
+    let result = test_interpreter_and_cranelift(
+        "
+        ldxdw r2, [r1]
+        stxw [r1+8], r2
+        ldxh r3, [r1+1]
+        stxb [r10-1], r3
+        ldxb r0, [r10-1]
+        add64 r0, r2
+        exit",
+        &[0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0, 0, 0, 0],
+        7,
+    );
+    assert_eq!(
+        format!("{:?}", result),
+        format!("{:?}", ProgramResult::Ok(0x8877665544332233)),
+    );
+    let result = test_interpreter_and_cranelift(
+        "
+        ldxdw r0, [r1+6]
+        exit",
+        &[0; 12],
+        2,
+    );
+    assert_eq!(
+        format!("{:?}", result),
+        format!(
+            "{:?}",
+            ProgramResult::Err(EbpfError::AccessViolation(
+                AccessType::Load,
+                ebpf::MM_INPUT_START + 6,
+                8,
+                "input"
+            ))
+        ),
+    );
+}
+
+#[test]
+fn test_cranelift_delegated_instructions() {
+    let result = test_interpreter_and_cranelift(
+        "
+        lddw r0, 0x1122334455667788
+        mov64 r1, 1
+        mov64 r2, 2
+        mov64 r3, 3
+        mov64 r4, 4
+        mov64 r5, 5
+        syscall gather_bytes
+        call function_foo
+        div64 r0, 3
+        exit
+        function_foo:
+        be32 r0
+        exit",
+        &[],
+        12,
+    );
+    assert!(matches!(result, ProgramResult::Ok(_)));
+    let result = test_interpreter_and_cranelift(
+        "
+        mov64 r0, 1
+        mov64 r1, 0
+        div64 r0, r1
+        exit",
+        &[],
+        3,
+    );
+    assert_eq!(
+        format!("{:?}", result),
+        format!("{:?}", ProgramResult::Err(EbpfError::DivideByZero)),
+    );
+}
+
+#[test]
+fn test_cranelift_instruction_meter() {
+    let result = test_interpreter_and_cranelift(
+        "
+        mov64 r0, 0
+        add64 r0, 1
+        ja -2
+        exit",
+        &[],
+        16,
+    );
+    assert_eq!(
+        format!("{:?}", result),
+        format!(
+            "{:?}",
+            ProgramResult::Err(EbpfError::ExceededMaxInstructions)
+        ),
+    );
+}
@@ -0,0 +1,69 @@
+use solana_sbpf::{
+    assembler::assemble, lifter::lift, program::BuiltinProgram, static_analysis::Analysis,
+    vm::Config,
+};
+use std::sync::Arc;
+use test_utils::{syscalls, TestContextObject};
+
+fn lift_assembly(source: &str) -> String {
+    let mut loader = BuiltinProgram::new_loader(Config::default());
+    loader
+        .register_function("bpf_gather_bytes", syscalls::SyscallGatherBytes::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(source, Arc::new(loader)).unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut output = Vec::new();
+    lift(&analysis, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_lift_functions() {
+    let ir = lift_assembly(
+        "
+        entrypoint:
+        ldxdw r1, [r1]
+        call function_square
+        jgt r0, 100, +1
+        syscall bpf_gather_bytes
+        return
+        function_square:
+        mov64 r0, r1
+        lmul64 r0, r1
+        return",
+    );
+    let definitions = ir
+        .lines()
+        .filter(|line| line.starts_with("define "))
+        .collect::<Vec<_>>();
+    assert_eq!(definitions.len(), 3);
+    assert!(definitions[0].contains("@\"entrypoint\"("));
+    assert!(definitions[1].contains("@\"function_square\"("));
+    assert!(definitions[2].starts_with("define i64 @sbpf.entrypoint("));
+    assert!(ir.contains("call { i64, i64, i64, i64, i64, i64 } @\"function_square\"("));
+    assert!(ir.contains("icmp ugt i64 %v"));
+    assert!(ir.contains("br i1 %v"));
+    assert!(ir.contains("declare i64 @\"syscall.bpf_gather_bytes\"(i64, i64, i64, i64, i64)"));
+    assert!(ir.contains("mul i64"));
+    assert_eq!(
+        ir.matches("ret { i64, i64, i64, i64, i64, i64 }").count(),
+        2
+    );
+}
+
+#[test]
+fn test_lift_traps() {
+    let ir = lift_assembly(
+        "
+        mov64 r0, 1
+        mov64 r1, -1
+        sdiv64 r0, r1
+        le16 r0
+        return",
+    );
+    assert!(ir.contains("call void @sbpf.divide_by_zero(i64 2)"));
+    assert!(ir.contains("call void @sbpf.divide_overflow(i64 2)"));
+    assert!(ir.contains("sdiv i64"));
+    // le is not available in the default version
+    assert!(ir.contains("call void @sbpf.unsupported_instruction(i64 3)"));
+}
@@ -0,0 +1,109 @@
+use solana_sbpf::{
+    assembler::assemble,
+    error::ProgramResult,
+    program::BuiltinProgram,
+    runtime::{InvokeContext, Runtime},
+    solana_input::{AccountDescription, InputBuilder},
+    static_analysis::Analysis,
+    vm::Config,
+};
+use std::convert::TryInto;
+use test_utils::assert_error;
+
+const CALLER: [u8; 32] = [1; 32];
+const CALLEE: [u8; 32] = [2; 32];
+
+fn create_runtime(max_invoke_depth: usize, input_len: usize) -> Runtime {
+    let mut runtime = Runtime::new(
+        BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        }),
+        max_invoke_depth,
+    )
+    .unwrap();
+    let caller = assemble::<InvokeContext>(
+        &format!(
+            "
+            mov64 r6, r1
+            mov64 r2, r1
+            add64 r1, 16
+            mov64 r3, {input_len}
+            syscall sol_invoke_
+            ldxdw r0, [r6+80]
+            return"
+        ),
+        runtime.loader().clone(),
+    )
+    .unwrap();
+    let callee = assemble::<InvokeContext>(
+        "
+        ldxdw r2, [r1+80]
+        add64 r2, 1
+        stxdw [r1+80], r2
+        ldxb r0, [r1+16]
+        return",
+        runtime.loader().clone(),
+    )
+    .unwrap();
+    runtime.add_program(CALLER, caller);
+    runtime.add_program(CALLEE, callee);
+    runtime
+}
+
+fn create_input() -> Vec<u8> {
+    InputBuilder::default()
+        .account(AccountDescription {
+            key: CALLEE,
+            lamports: 5,
+            ..AccountDescription::default()
+        })
+        .program_id(CALLER)
+        .build()
+        .input
+        .as_slice()
+        .to_vec()
+}
+
+#[test]
+fn test_runtime_invoke() {
+    let mut input = create_input();
+    let runtime = create_runtime(1, input.len());
+    let (result, timeline) = runtime.execute(CALLER, &mut input, 1000);
+    assert!(matches!(result, ProgramResult::Ok(6)));
+    assert_eq!(u64::from_le_bytes(input[80..88].try_into().unwrap()), 6);
+    assert_eq!(timeline.invocations.len(), 2);
+    let callee = &timeline.invocations[1];
+    assert_eq!(callee.program_id, CALLEE);
+    assert_eq!((callee.depth, callee.caller), (1, Some(0)));
+    assert_eq!(callee.input_offset, Some(0));
+    assert_eq!(callee.return_value, Some(CALLEE[0] as u64));
+    assert_eq!(timeline.invocations[0].return_value, Some(6));
+    let mut order = timeline
+        .entries
+        .iter()
+        .map(|(invocation, _entry)| *invocation)
+        .collect::<Vec<_>>();
+    order.dedup();
+    assert_eq!(order, vec![0, 1, 0]);
+    assert_eq!(timeline.trace_log(1).len(), 5);
+
+    let analysis = Analysis::from_executable(runtime.program(&CALLEE).unwrap()).unwrap();
+    let taint = timeline.input_taint(1, &analysis);
+    assert_eq!(taint.tainted_loads.get(&0), Some(&(80..88)));
+    assert_eq!(taint.tainted_loads.get(&3), Some(&(16..17)));
+}
+
+#[test]
+fn test_runtime_invoke_errors() {
+    let mut input = create_input();
+    let runtime = create_runtime(0, input.len());
+    let (result, timeline) = runtime.execute(CALLER, &mut input, 1000);
+    assert_error!(result, "InvokeDepthExceeded(0)");
+    assert_eq!(timeline.invocations.len(), 2);
+    assert_eq!(timeline.invocations[1].return_value, None);
+    assert_eq!(u64::from_le_bytes(input[80..88].try_into().unwrap()), 5);
+
+    let (result, _timeline) = runtime.execute([3; 32], &mut input, 1000);
+    assert_error!(result, "UnknownProgram");
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "server")]
+
+use solana_sbpf::server::Server;
+
+fn serve(server: &mut Server, requests: &[&str]) -> Vec<json::JsonValue> {
+    let mut output = Vec::new();
+    server
+        .serve(requests.join("\n").as_bytes(), &mut output)
+        .unwrap();
+    String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| json::parse(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_server() {
+    let mut server = Server::default();
+    let responses = serve(
+        &mut server,
+        &[
+            r#"{"jsonrpc":"2.0","id":1,"method":"load","params":{"assembly":"ldxb r2, [r1]\nstxb [r1+1], r2\nmov64 r0, r2\njne r2, 0x41, +1\nmov64 r0, 0\nexit"}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"execute","params":{"program":0,"input":"4100"}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"execute","params":{"program":0,"input":"4200","instruction_budget":3}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"taint","params":{"program":0,"input":"4100"}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"trace","params":{"program":0,"input":"4200"}}"#,
+        ],
+    );
+    assert_eq!(responses.len(), 5);
+    assert_eq!(responses[0]["result"]["program"], 0);
+
+    let execution = &responses[1]["result"];
+    assert_eq!(execution["result"]["ok"], 0);
+    assert_eq!(execution["instruction_count"], 6);
+    assert_eq!(execution["input"], "4141");
+
+    let execution = &responses[2]["result"];
+    assert!(execution["result"]["error"]
+        .as_str()
+        .unwrap()
+        .contains("exceeded CUs meter"));
+    assert_eq!(execution["input"], "4242");
+
+    let taint = &responses[3]["result"];
+    assert_eq!(taint["tainted_comparisons"][0][0], 3);
+    assert_eq!(taint["tainted_comparisons"][0][1], 0);
+    assert_eq!(taint["tainted_comparisons"][0][2], 1);
+    assert_eq!(taint["tainted_loads"][0][0], 0);
+
+    let trace = &responses[4]["result"]["trace"];
+    assert_eq!(trace["instructions"].len(), 5);
+    assert_eq!(trace["instructions"][4]["pc"], 5);
+    assert_eq!(trace["instructions"][4]["registers"][0], 0x42);
+}
+
+#[test]
+fn test_server_streaming() {
+    let mut server = Server::default();
+    let messages = serve(
+        &mut server,
+        &[
+            r#"{"jsonrpc":"2.0","id":1,"method":"load","params":{"assembly":"mov64 r0, 1\nadd64 r0, 1\nexit"}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"trace","params":{"program":0,"stream":true}}"#,
+        ],
+    );
+    assert_eq!(messages.len(), 5);
+    for (pc, notification) in messages[1..4].iter().enumerate() {
+        assert_eq!(notification["method"], "trace.instruction");
+        assert!(notification["id"].is_null());
+        assert_eq!(notification["params"]["pc"], pc);
+    }
+    assert_eq!(messages[4]["id"], 2);
+    assert_eq!(messages[4]["result"]["result"]["ok"], 2);
+    assert!(messages[4]["result"]["trace"].is_null());
+}
+
+#[test]
+fn test_server_errors() {
+    let mut server = Server::default();
+    let responses = serve(
+        &mut server,
+        &[
+            "{",
+            r#"{"jsonrpc":"2.0","id":1,"method":"run"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"execute","params":{"program":0}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"load","params":{"elf":"7f454c"}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"load","params":{"elf":"xyz"}}"#,
+            r#"{"jsonrpc":"2.0","method":"unload","params":{"program":0}}"#,
+        ],
+    );
+    let codes = responses
+        .iter()
+        .map(|response| response["error"]["code"].as_i64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(codes, [-32700, -32601, -32602, -32000, -32602]);
+}
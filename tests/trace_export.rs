@@ -0,0 +1,294 @@
+#![cfg(feature = "trace-export")]
+
+use solana_sbpf::{
+    accounts::{AccountField, AccountLayout},
+    assembler::assemble,
+    ebpf,
+    grammar::{GrammarField, InputGrammar},
+    memory_region::MemoryRegion,
+    program::BuiltinProgram,
+    redaction::Redaction,
+    solana_input::{AccountDescription, InputBuilder},
+    static_analysis::Analysis,
+    trace_export::{
+        DivergentBranch, TraceDiff, TraceExport, TraceExportError, TRACE_SCHEMA_VERSION,
+    },
+    vm::Config,
+};
+use std::sync::Arc;
+use test_utils::{create_vm, TestContextObject};
+
+#[test]
+fn test_trace_export() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 2
+        add64 r1, -1
+        jne r1, 0, -2
+        mov64 r0, 7
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(7);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert_eq!(result.unwrap(), 7);
+    let trace = TraceExport::from_trace_log(&executable, &vm.context_object_pointer.trace_log);
+    assert_eq!(trace.schema_version, TRACE_SCHEMA_VERSION);
+    assert_eq!(trace.instructions.len(), 7);
+    assert_eq!(trace.instructions[1].registers[1], 2);
+    assert_eq!(trace.jumps.len(), 1);
+    assert_eq!((trace.jumps[0].from, trace.jumps[0].to), (2, 1));
+
+    let json = trace.to_json().unwrap();
+    assert_eq!(TraceExport::from_json(&json).unwrap(), trace);
+    let bytes = trace.to_bincode().unwrap();
+    assert_eq!(TraceExport::from_bincode(&bytes).unwrap(), trace);
+
+    let mut outdated = trace.clone();
+    outdated.schema_version = 0;
+    assert!(matches!(
+        TraceExport::from_json(&outdated.to_json().unwrap()),
+        Err(TraceExportError::UnsupportedSchemaVersion(0))
+    ));
+}
+
+#[test]
+fn test_offline_analysis() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r1, [r1]
+        jeq r1, 0, +1
+        mov64 r0, 1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let record = |input: u8| {
+        let mut mem = [input];
+        let mut context_object = TestContextObject::new(4);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+            None
+        );
+        vm.execute_program(&executable, true).1.unwrap();
+        let trace = TraceExport::from_trace_log(&executable, &context_object.trace_log);
+        // Only the serialized form leaves the execution machine
+        TraceExport::from_bincode(&trace.to_bincode().unwrap()).unwrap()
+    };
+    let taken = record(0);
+    let not_taken = record(1);
+
+    assert_eq!(
+        not_taken.coverage().into_iter().collect::<Vec<_>>(),
+        vec![(0, 1), (1, 1), (2, 1), (3, 1)]
+    );
+    let diff = taken.diff(&not_taken);
+    assert_eq!(diff.first_divergence, Some(1));
+    assert!(diff.only_in_first.is_empty());
+    assert_eq!(diff.only_in_second.into_iter().collect::<Vec<_>>(), vec![2]);
+    assert_eq!(taken.diff(&taken).first_divergence, None);
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let dynamic_analysis = taken.dynamic_analysis(&analysis);
+    assert_eq!(dynamic_analysis.edges[&0][&3], 1);
+    assert_eq!(dynamic_analysis.edge_counter_max, 1);
+}
+
+#[test]
+fn test_redaction() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 0x12345678
+        mov64 r2, r1
+        mov64 r3, r10
+        mov64 r0, 1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(5);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.execute_program(&executable, true).1.unwrap();
+    let trace = TraceExport::from_trace_log(&executable, &context_object.trace_log);
+
+    let zeroed = trace.redact(&Redaction::Zero);
+    let last = zeroed.instructions.last().unwrap();
+    assert_eq!(
+        last.registers[1..4],
+        [0, 0, trace.instructions[4].registers[10]]
+    );
+    assert_eq!(last.registers[10], trace.instructions[4].registers[10]);
+    assert_eq!(zeroed.coverage(), trace.coverage());
+    assert_eq!(zeroed.jumps, trace.jumps);
+    let hashed = trace.redact(&Redaction::Hash { salt: 1 });
+    let last = hashed.instructions.last().unwrap();
+    assert_ne!(last.registers[1], 0x12345678);
+    assert_eq!(last.registers[1], last.registers[2]);
+    assert_ne!(
+        trace.redact(&Redaction::Hash { salt: 2 }).instructions[4].registers[1],
+        last.registers[1]
+    );
+
+    let account = AccountDescription {
+        key: [7; 32],
+        owner: [8; 32],
+        lamports: 5,
+        data: vec![1, 2, 3],
+        ..AccountDescription::default()
+    };
+    let serialized = InputBuilder::default()
+        .account(account.clone())
+        .account(AccountDescription {
+            data: vec![4],
+            ..account
+        })
+        .instruction_data(&[9, 9])
+        .program_id([6; 32])
+        .build();
+    let layout = &serialized.layout;
+    let field = |input: &[u8], account, field| {
+        layout
+            .fields_in(0..input.len())
+            .find(|(_range, index, f)| *index == account && *f == field)
+            .map(|(range, _index, _field)| input[range].to_vec())
+            .unwrap()
+    };
+    let mut input = serialized.input.as_slice().to_vec();
+    Redaction::Hash { salt: 1 }.input(&mut input);
+    assert_eq!(AccountLayout::parse_aligned(&input).as_ref(), Some(layout));
+    assert_ne!(field(&input, Some(0), AccountField::Key), vec![7; 32]);
+    assert_eq!(
+        field(&input, Some(0), AccountField::Key),
+        field(&input, Some(1), AccountField::Key)
+    );
+    assert_ne!(
+        field(&input, Some(0), AccountField::Data),
+        field(&input, Some(1), AccountField::Data)
+    );
+    assert_eq!(
+        field(&input, Some(0), AccountField::Lamports),
+        5u64.to_le_bytes()
+    );
+    let mut input = serialized.input.as_slice().to_vec();
+    Redaction::Zero.input(&mut input);
+    assert_eq!(field(&input, Some(1), AccountField::Data), vec![0]);
+    assert_eq!(field(&input, None, AccountField::ProgramId), vec![0; 32]);
+    assert_eq!(
+        field(&input, None, AccountField::InstructionData),
+        [2, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn test_input_grammar_json() {
+    let grammar = InputGrammar {
+        fields: vec![GrammarField {
+            offset: 10352,
+            width: 1,
+            region: Some("input".to_string()),
+            index: None,
+            attribute: Some("InstructionData".to_string()),
+            constants: vec![7, 42],
+            enum_candidates: vec![7, 42],
+        }],
+    };
+    let json = grammar.to_json().unwrap();
+    assert!(json.contains("\"enum_candidates\":[7,42]"));
+    assert_eq!(InputGrammar::from_json(&json).unwrap(), grammar);
+}
+
+#[test]
+fn test_trace_diff_compare() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1]
+        mov64 r3, 0
+        jgt r2, 5, +2
+        add64 r3, 1
+        ja +1
+        add64 r3, 2
+        mov64 r4, 2
+        add64 r4, -1
+        jne r4, 0, -2
+        mov64 r0, r3
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let record = |input: u8| {
+        let mut mem = [input];
+        let mut context_object = TestContextObject::new(12);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+            None
+        );
+        vm.execute_program(&executable, true).1.unwrap();
+        TraceExport::from_trace_log(&executable, &context_object.trace_log)
+    };
+    let below = record(3);
+    let above = record(9);
+
+    let diff = TraceDiff::compare(&executable, &below, &above);
+    assert_eq!(diff.common_blocks, 2);
+    assert_eq!(
+        diff.divergent_branch,
+        Some(DivergentBranch {
+            pc: 2,
+            opcode: ebpf::JGT_IMM,
+            index: (2, 2),
+            target: (Some(3), Some(5)),
+            operands: Some(((3, 5), (9, 5))),
+            reconvergence: Some(7),
+        })
+    );
+    let diff = below.diff(&above);
+    assert_eq!(
+        diff.divergent_branch
+            .map(|branch| (branch.pc, branch.operands)),
+        Some((2, None))
+    );
+
+    let diff = TraceDiff::compare(&executable, &below, &below);
+    assert_eq!(diff.common_blocks, 3);
+    assert_eq!(diff.divergent_branch, None);
+}
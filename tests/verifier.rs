@@ -25,11 +25,12 @@ extern crate solana_sbpf;
 extern crate thiserror;
 
 use solana_sbpf::{
+    abi::AbiRestrictions,
     assembler::assemble,
     ebpf,
     elf::Executable,
     program::{BuiltinFunction, BuiltinProgram, FunctionRegistry, SBPFVersion},
-    verifier::{RequisiteVerifier, Verifier, VerifierError},
+    verifier::{RequisiteVerifier, Verifier, VerifierError, VerifierExt},
     vm::{Config, ContextObject},
 };
 use std::sync::Arc;
@@ -594,3 +595,183 @@ fn function_without_return() {
     let result = executable.verify::<RequisiteVerifier>();
     assert_error!(result, "VerifierError(InvalidFunction(2))");
 }
+
+#[test]
+fn test_verifier_abi_restrictions() {
+    let mut executable = assemble::<TestContextObject>(
+        "
+        add64 r10, 0
+        ldxdw r2, [r1]
+        mov64 r3, r2
+        add64 r3, r1
+        mov64 r0, r3
+        return",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V4,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    executable.set_abi_restrictions(Some(AbiRestrictions::default()));
+    assert!(executable.verify::<RequisiteVerifier>().is_ok());
+    executable.set_abi_restrictions(Some(AbiRestrictions::with_register_count(4)));
+    assert!(executable.verify::<RequisiteVerifier>().is_ok());
+    executable.set_abi_restrictions(Some(AbiRestrictions::with_register_count(3)));
+    assert_error!(
+        executable.verify::<RequisiteVerifier>(),
+        "VerifierError(RestrictedRegister(3, 2))"
+    );
+    executable.set_abi_restrictions(Some(AbiRestrictions::default()));
+    assert!(executable.verify::<RequisiteVerifier>().is_ok());
+
+    let mut executable = assemble::<TestContextObject>(
+        "
+        add64 r10, 0
+        mov64 r0, r1
+        add64 r0, r2
+        return",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V4,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    executable.set_abi_restrictions(Some(AbiRestrictions {
+        argument_registers: 1,
+        ..AbiRestrictions::default()
+    }));
+    assert_error!(
+        executable.verify::<RequisiteVerifier>(),
+        "VerifierError(UndefinedArgumentRegister(2, 2))"
+    );
+}
+
+struct BanCallx;
+impl VerifierExt for BanCallx {
+    fn verify_insn(
+        &self,
+        insn: &ebpf::Insn,
+        _sbpf_version: SBPFVersion,
+        _function_registry: &FunctionRegistry<usize>,
+    ) -> Result<(), String> {
+        if insn.opc == ebpf::CALL_REG {
+            return Err("callx".to_string());
+        }
+        Ok(())
+    }
+}
+
+struct BanSyscall(u32);
+impl VerifierExt for BanSyscall {
+    fn verify_insn(
+        &self,
+        insn: &ebpf::Insn,
+        _sbpf_version: SBPFVersion,
+        _function_registry: &FunctionRegistry<usize>,
+    ) -> Result<(), String> {
+        if insn.opc == ebpf::SYSCALL && insn.imm as u32 == self.0 {
+            return Err(format!("syscall {:#x}", self.0));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_verifier_extension() {
+    let mut loader = BuiltinProgram::new_loader(Config::default());
+    loader
+        .register_function("bpf_syscall_u64", syscalls::SyscallU64::vm)
+        .unwrap();
+    let mut executable = assemble::<TestContextObject>(
+        "
+        lddw r1, 0x100000000
+        callx r1
+        syscall bpf_syscall_u64
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+    assert!(executable.verify::<TautologyVerifier>().is_ok());
+    executable.add_verifier_extension(BanSyscall(ebpf::hash_symbol_name(b"bpf_syscall_u64")));
+    assert_error!(
+        executable.verify::<TautologyVerifier>(),
+        "VerifierError(RejectedByExtension(\"syscall {:#x}\", 3))",
+        ebpf::hash_symbol_name(b"bpf_syscall_u64")
+    );
+    executable.add_verifier_extension(BanCallx);
+    assert_error!(
+        executable.verify::<TautologyVerifier>(),
+        "VerifierError(RejectedByExtension(\"callx\", 2))"
+    );
+}
+
+struct RequireRegisteredCalls;
+impl VerifierExt for RequireRegisteredCalls {
+    fn verify_insn(
+        &self,
+        insn: &ebpf::Insn,
+        sbpf_version: SBPFVersion,
+        function_registry: &FunctionRegistry<usize>,
+    ) -> Result<(), String> {
+        let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+        if insn.opc == ebpf::CALL_IMM && function_registry.lookup_by_key(key).is_none() {
+            return Err(format!("call {key:#x}"));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_verifier_reverify_functions() {
+    let mut executable = assemble::<TestContextObject>(
+        "
+        add64 r10, 0
+        call function_bar
+        return
+        function_foo:
+        add64 r10, 0
+        mov64 r0, 1
+        return
+        function_bar:
+        add64 r10, 0
+        mov64 r0, 2
+        return",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V4,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    executable.add_verifier_extension(RequireRegisteredCalls);
+    assert!(executable.verify::<RequisiteVerifier>().is_ok());
+    assert!(executable.reverify_functions(0..9).is_ok());
+
+    let (key, (name, pc)) = executable
+        .get_function_registry()
+        .iter()
+        .find(|(_key, (name, _pc))| *name == b"function_bar")
+        .map(|(key, (name, pc))| (key, (name.to_vec(), pc)))
+        .unwrap();
+    assert_eq!(pc, 6);
+    executable
+        .get_function_registry_mut()
+        .unregister_function(key);
+    assert!(executable.reverify_functions(3..4).is_ok());
+    assert_error!(
+        executable.reverify_functions(0..1),
+        "VerifierError(RejectedByExtension(\"call {:#x}\", 1))",
+        key
+    );
+    assert_error!(
+        executable.verify::<RequisiteVerifier>(),
+        "VerifierError(RejectedByExtension(\"call {:#x}\", 1))",
+        key
+    );
+
+    executable
+        .get_function_registry_mut()
+        .register_function(key, name, pc)
+        .unwrap();
+    assert!(executable.reverify_functions(1..2).is_ok());
+    assert!(executable.verify::<RequisiteVerifier>().is_ok());
+}
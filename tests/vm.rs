@@ -1,12 +1,83 @@
 #![allow(clippy::literal_string_with_formatting_args)]
 
 use solana_sbpf::{
+    accounts::{
+        AccessCount, AccountField, AccountLayout, AccountWriteTracker, InferredField,
+        InferredFieldKind, InputLayoutInference,
+    },
+    aligned_memory::AlignedMemory,
+    assembler::assemble,
+    block_trace::{BlockTrace, BlockTraceRecorder},
+    branch_distance::{BranchDistanceError, BranchDistances},
+    cost_model::CostModel,
+    crash_report::CrashReport,
+    deadline::Deadline,
+    declare_builtin_function, ebpf,
     elf::Executable,
-    program::BuiltinProgram,
-    vm::{Config, RuntimeEnvironmentSlot},
+    error::{EbpfError, ProgramResult},
+    fault_injection::{FaultAction, FaultInjector, FaultRule, FaultTrigger, PolicyFaultInjector},
+    global_coverage::GlobalCoverage,
+    grammar::{GrammarField, InputGrammar},
+    heap_sanitizer::{HeapSanitizer, SyscallSanitizedAllocFree},
+    influence::{InfluenceMatrix, InfluenceMatrixError},
+    input_hook::AttributeOverrides,
+    interpreter::{Interpreter, PendingAccess, StepControl},
+    loop_detector::LoopDetector,
+    memory_builtins::register_memory_builtins,
+    memory_region::{
+        AccessType, AddressSpaceEntry, CopyOnWriteAccessViolationHandler, MemoryMapping,
+        MemoryRegion, MemoryRegionBuilder, MemoryRegionError, RegionAlignment, RegionRole,
+        SyntheticFill, ZeroFillAccessViolationHandler,
+    },
+    observer::{
+        BranchRecorder, CallFrameEvent, CallGraphRecorder, CoverageMapObserver, ExecutionObserver,
+        MemoryAccessRecorder, ObservedAccess, StackProfiler,
+    },
+    prng::{
+        random_bytes_cost, register_random_syscalls, Prng, RANDOM_BYTES_BASE_COST,
+        RANDOM_BYTES_PER_UNIT,
+    },
+    program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
+    program_mutation::{check_program, ProgramMutator, ReproductionBundle},
+    progress::ProgressTracker,
+    replay::{Divergence, Replayer},
+    semantic::{RegionLayout, SemanticMemory, SemanticTag},
+    solana_input::{AccountDescription, InputBuilder},
+    stack_sanitizer::{StackSanitizer, UninitializedRead, UninitializedReadMode},
+    state_hash::StateHashTrace,
+    static_analysis::{
+        Analysis, CoverageFormat, InputPointerAnnotations, InstructionCoverage, TraceLogEntry,
+    },
+    sysvars::{register_sysvar_syscalls, Clock, Sysvar, Sysvars},
+    taint::{
+        ConcolicOperand, DictionaryEntry, InputTaint, LabelStatistics, PathConstraint,
+        PolicyViolation, TaintLabels, TaintLocation, TaintSink,
+    },
+    trace_buffer::{
+        StreamingTrace, TraceCheckpoints, TraceLogReader, TraceSink, WriterSink,
+        TRACE_LOG_ENTRY_SIZE,
+    },
+    value_profile::ValueProfile,
+    vm::{
+        Config, ContextObject, DynamicAnalysis, EbpfVm, InstrumentationComponent,
+        InstrumentationConfig, InstrumentationFailure, RuntimeEnvironmentSlot, TraceSummary,
+    },
+    vm_pool::{EbpfVmPool, EdgeCoverage},
+    watch::{WatchExpression, Watcher},
 };
-use std::{fs::File, io::Read, sync::Arc};
-use test_utils::{create_vm, syscalls, TestContextObject};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    rc::Rc,
+    sync::{
+        mpsc::{sync_channel, SyncSender, TrySendError},
+        Arc,
+    },
+    time::Duration,
+};
+use test_utils::{assert_error, create_vm, syscalls, TestContextObject};
 
 #[test]
 fn test_runtime_environment_slots() {
@@ -75,3 +146,4632 @@ fn test_builtin_program_eq() {
     assert_eq!(builtin_program_a, builtin_program_b);
     assert_ne!(builtin_program_a, builtin_program_c);
 }
+
+#[cfg(feature = "diagnostics")]
+#[test]
+fn test_interpreter_diagnostics() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r0, 0
+        mov64 r1, 3
+        add64 r0, 1
+        add64 r1, -1
+        jne r1, 0, -3
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(14);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    assert_eq!(vm.diagnostics.distinct_opcode_transitions(), 0);
+    assert_eq!(
+        vm.diagnostics
+            .opcode_transition_count(ebpf::JNE_IMM, ebpf::ADD64_IMM),
+        0
+    );
+    let (instruction_count, result) = vm.execute_program(&executable, true);
+    assert_eq!(result.unwrap(), 3);
+    assert_eq!(vm.diagnostics.dispatched_instructions, instruction_count);
+    assert_eq!(
+        vm.diagnostics
+            .opcode_transition_count(ebpf::JNE_IMM, ebpf::ADD64_IMM),
+        2
+    );
+    assert_eq!(
+        vm.diagnostics
+            .opcode_transitions()
+            .map(|(_previous, _next, count)| count)
+            .sum::<u64>(),
+        instruction_count - 1
+    );
+    vm.diagnostics.reset();
+    assert_eq!(vm.diagnostics.distinct_opcode_transitions(), 0);
+}
+
+#[test]
+fn test_instrumentation_config() {
+    for (instrumentation, traced) in [
+        (InstrumentationConfig::None, false),
+        (InstrumentationConfig::Coverage, true),
+        (InstrumentationConfig::FullTaint, true),
+    ] {
+        let config = Config {
+            enable_instruction_tracing: true,
+            instrumentation,
+            ..Config::default()
+        };
+        let executable = assemble::<TestContextObject>(
+            "
+            mov64 r0, 1
+            add64 r0, 2
+            exit",
+            Arc::new(BuiltinProgram::new_loader(config)),
+        )
+        .unwrap();
+        let mut context_object = TestContextObject::new(3);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        let (instruction_count, result) = vm.execute_program(&executable, true);
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(instruction_count, 3);
+        assert_eq!(
+            vm.context_object_pointer.trace_log.len(),
+            if traced { 3 } else { 0 }
+        );
+    }
+}
+
+#[test]
+fn test_instrumentation_config_keeps_budgets() {
+    for instrumentation in [
+        InstrumentationConfig::None,
+        InstrumentationConfig::Coverage,
+        InstrumentationConfig::CoverageCmpLog,
+        InstrumentationConfig::FullTaint,
+    ] {
+        let config = Config {
+            enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+            instrumentation,
+            cost_model: Some(
+                CostModel::default()
+                    .with_opcode_cost(ebpf::ADD64_IMM, 3)
+                    .with_syscall_cost("bpf_syscall_u64", 10),
+            ),
+            ..Config::default()
+        };
+        let mut loader = BuiltinProgram::new_loader(config);
+        loader
+            .register_function("bpf_syscall_u64", syscalls::SyscallU64::vm)
+            .unwrap();
+        let executable = assemble::<TestContextObject>(
+            "
+            mov64 r1, 10
+            add64 r1, -1
+            jne r1, 0, -2
+            syscall bpf_syscall_u64
+            exit",
+            Arc::new(loader),
+        )
+        .unwrap();
+        let mut context_object = TestContextObject::new(100);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        let branches = Rc::new(RefCell::new(BranchRecorder::default()));
+        vm.observers.push(Box::new(branches.clone()));
+        // 13 instructions at the default price, 10 priced additions and the priced syscall
+        let (instruction_count, result) = vm.execute_program(&executable, true);
+        assert!(result.is_ok());
+        assert_eq!(instruction_count, 13 + 10 * 3 + 10);
+        let recorded = branches.borrow().branches.get(&2).copied();
+        if instrumentation.records_coverage() {
+            assert_eq!(
+                recorded.map(|outcomes| (outcomes.taken, outcomes.not_taken)),
+                Some((9, 1))
+            );
+        } else {
+            assert_eq!(recorded, None);
+        }
+
+        vm.context_object_pointer.remaining = 100;
+        vm.loop_detector = Some(Box::new(LoopDetector::new(Some(5))));
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        assert_error!(result, "LoopBudgetExceeded(2, 1)");
+        vm.loop_detector = None;
+
+        vm.context_object_pointer.remaining = 100;
+        vm.deadline = Deadline::after(Duration::ZERO, 1);
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        assert_error!(result, "Timeout");
+    }
+}
+
+#[test]
+fn test_execute_batch() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1]
+        ldxb r3, [r10-1]
+        stxb [r10-1], r2
+        stxb [r1], r3
+        mov64 r0, r2
+        add64 r0, r3
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::default();
+    let mut mem = [0u8; 1];
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        None
+    );
+    let inputs: [&[u8]; 3] = [&[1], &[2, 3], &[]];
+    let results = vm
+        .execute_batch(&executable, &inputs, true, |_index, context_object| {
+            context_object.remaining = 7;
+        })
+        .unwrap();
+    assert_eq!(results.len(), 3);
+    // The stack is zeroed before every run, so r3 is always 0
+    assert_eq!(results[0].instruction_count, 7);
+    assert!(matches!(results[0].result, ProgramResult::Ok(1)));
+    assert!(matches!(results[1].result, ProgramResult::Ok(2)));
+    assert_error!(results[2].result, "AccessViolation");
+    // The trace is reset before every run
+    assert_eq!(
+        results[0].trace,
+        TraceSummary {
+            len: 7,
+            unique_pcs: 7,
+            unique_edges: 6,
+            last_pc: Some(6),
+        }
+    );
+    assert_eq!(results[0].coverage_hash, results[1].coverage_hash);
+    assert_eq!(results[2].trace.len, 1);
+    assert_ne!(results[0].coverage_hash, results[2].coverage_hash);
+    assert_eq!(vm.context_object_pointer.trace_log.len(), 1);
+    assert_eq!(vm.context_object_pointer.remaining, 6);
+    // The original input region is mapped again afterwards
+    let (_, region) = vm.memory_mapping.find_region(ebpf::MM_INPUT_START).unwrap();
+    assert_eq!(region.host_addr, mem.as_ptr() as u64);
+}
+
+#[test]
+fn test_execute_batch_resets_budgets() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1]
+        jne r2, 0, +2
+        call function_fail
+        exit
+        add64 r2, -1
+        jne r2, 0, -2
+        exit
+        function_fail:
+        ldxb r0, [r1+8]
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::default();
+    let mut mem = [0u8; 1];
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        None
+    );
+    vm.shadow_call_stack = Some(Vec::new());
+    vm.loop_detector = Some(Box::new(LoopDetector::new(Some(5))));
+    let deadline = Deadline::after(Duration::from_secs(3600), 1);
+    vm.deadline = deadline;
+    // Each looping input takes the back edge 3 times, both together exceed the budget of 5
+    let inputs: [&[u8]; 3] = [&[0], &[4], &[4]];
+    let results = vm
+        .execute_batch(&executable, &inputs, true, |_index, context_object| {
+            context_object.remaining = 100;
+        })
+        .unwrap();
+    assert_error!(results[0].result, "AccessViolation");
+    assert!(matches!(results[1].result, ProgramResult::Ok(0)));
+    assert!(matches!(results[2].result, ProgramResult::Ok(0)));
+    assert_eq!(
+        vm.loop_detector.as_ref().unwrap().back_edges(),
+        &BTreeMap::from([((5, 4), 3)])
+    );
+    // The frame of the failed call is not left behind
+    assert_eq!(vm.shadow_call_stack, Some(Vec::new()));
+    assert_eq!(vm.deadline, deadline);
+}
+
+#[cfg(all(feature = "mmap", unix))]
+#[test]
+fn test_execute_batch_mapped() {
+    use solana_sbpf::mapped_file::{MappedFile, MappingMode};
+
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r0, [r1]
+        add64 r0, 1
+        stxb [r1], r0
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let directory = std::env::temp_dir().join(format!("sbpf-mapped-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let mut inputs = Vec::new();
+    for (index, content) in [&[1u8, 0, 0][..], &[5], &[]].iter().enumerate() {
+        let path = directory.join(index.to_string());
+        std::fs::write(&path, content).unwrap();
+        inputs.push(MappedFile::open(&path, MappingMode::CopyOnWrite).unwrap());
+    }
+    assert_eq!(inputs[0].len(), 3);
+    assert!(inputs[2].is_empty());
+    let mut context_object = TestContextObject::default();
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![inputs[0].region(ebpf::MM_INPUT_START)],
+        None
+    );
+    for _ in 0..2 {
+        let results = vm
+            .execute_batch_mapped(&executable, &mut inputs, true, |_index, context_object| {
+                context_object.remaining = 4;
+            })
+            .unwrap();
+        // Every input is reset before it is run, so the stores of the first batch are discarded
+        assert!(matches!(results[0].result, ProgramResult::Ok(2)));
+        assert!(matches!(results[1].result, ProgramResult::Ok(6)));
+        assert_error!(results[2].result, "AccessViolation");
+        assert_eq!(inputs[0].as_slice(), &[2, 0, 0]);
+    }
+    assert_eq!(std::fs::read(directory.join("0")).unwrap(), vec![1, 0, 0]);
+    drop(vm);
+
+    // Readonly inputs can not be written to
+    let mut inputs = vec![MappedFile::open(directory.join("1"), MappingMode::ReadOnly).unwrap()];
+    let mut context_object = TestContextObject::default();
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![inputs[0].region(ebpf::MM_INPUT_START)],
+        None
+    );
+    let results = vm
+        .execute_batch_mapped(&executable, &mut inputs, true, |_index, context_object| {
+            context_object.remaining = 4;
+        })
+        .unwrap();
+    assert_error!(results[0].result, "AccessViolation");
+    drop(vm);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_vm_pool() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r0, [r1]
+        jeq r0, 7, +2
+        add64 r0, 1
+        exit
+        mov64 r0, 0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let inputs: [&[u8]; 6] = [&[1], &[7], &[2], &[], &[7], &[3]];
+    let run = |threads| {
+        EbpfVmPool::new(&executable, threads)
+            .execute(
+                &inputs,
+                TestContextObject::default,
+                |_index, context_object| {
+                    context_object.remaining = 10;
+                },
+            )
+            .unwrap()
+    };
+    let pool_results = run(3);
+    assert_eq!(pool_results.results, run(1).results);
+    let results = &pool_results.results;
+    assert_eq!(results.len(), 6);
+    assert_eq!(results[0].result, Ok(2));
+    assert_eq!(results[1].result, Ok(0));
+    assert_eq!(results[2].result, Ok(3));
+    assert!(results[3]
+        .result
+        .as_ref()
+        .unwrap_err()
+        .contains("Access violation"));
+    assert_eq!(results[0].coverage_hash, results[2].coverage_hash);
+    assert_ne!(results[0].coverage_hash, results[1].coverage_hash);
+    assert_eq!(results[1].trace.last_pc, Some(5));
+
+    // The coverage of all workers is merged
+    let coverage = &pool_results.coverage;
+    assert_eq!(coverage.executions(), 6);
+    assert_eq!(coverage.hits(1, 2), 3);
+    assert_eq!(coverage.hits(1, 4), 2);
+    assert_eq!(coverage.hits(0, 1), 5);
+    let mut merged = EdgeCoverage::default();
+    let mut taken = EdgeCoverage::default();
+    assert!(taken.record(&[[0; 12], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]]));
+    assert!(!taken.record(&[[0; 12], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]]));
+    assert!(merged.merge(&taken));
+    assert!(!merged.merge(&taken));
+    assert_eq!(merged.hits(0, 1), 4);
+    assert_eq!(merged.executions(), 4);
+}
+
+#[test]
+fn test_replay() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r0, 0
+        mov64 r1, 3
+        add64 r0, r1
+        add64 r1, -1
+        jne r1, 0, -3
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(14);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (instruction_count, result) = vm.execute_program(&executable, true);
+    assert_eq!(result.unwrap(), 6);
+    let recording = std::mem::take(&mut vm.context_object_pointer.trace_log);
+    assert_eq!(recording.len() as u64, instruction_count);
+
+    vm.context_object_pointer.remaining = 14;
+    let report = Replayer::new(&recording).replay(&mut vm, &executable);
+    assert_eq!(report.divergence, None);
+    assert_eq!(report.replayed_instructions, recording.len());
+    assert!(matches!(report.result, ProgramResult::Ok(6)));
+    assert_eq!(vm.context_object_pointer.trace_log, recording);
+
+    let mut tampered = recording.clone();
+    tampered[5][0] = 42;
+    vm.context_object_pointer.remaining = 14;
+    let report = Replayer::new(&tampered).replay(&mut vm, &executable);
+    assert_eq!(report.replayed_instructions, 5);
+    let divergence = report.divergence.unwrap();
+    assert_eq!(divergence.differing_registers(), vec![0]);
+    assert!(matches!(divergence, Divergence::Registers { index: 5, .. }));
+
+    vm.context_object_pointer.remaining = 14;
+    let report = Replayer::new(&recording[..4]).replay(&mut vm, &executable);
+    assert_eq!(
+        report.divergence,
+        Some(Divergence::ExceededRecording { recorded: 4 })
+    );
+}
+
+#[test]
+fn test_state_hash() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r0, 0
+        mov64 r1, 3
+        add64 r0, r1
+        add64 r1, -1
+        jne r1, 0, -3
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(14);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert_eq!(result.unwrap(), 6);
+    let recording = std::mem::take(&mut vm.context_object_pointer.trace_log);
+    assert_eq!(recording.len(), 12);
+
+    let hashes = StateHashTrace::from_trace_log(4, &recording);
+    assert_eq!(hashes.recorded(), 12);
+    assert_eq!(
+        hashes
+            .hashes()
+            .iter()
+            .map(|hash| (hash.instructions, hash.pc))
+            .collect::<Vec<_>>(),
+        vec![(4, 3), (8, 4), (12, 5)],
+    );
+    let mut incremental = StateHashTrace::new(4);
+    for entry in recording.iter() {
+        incremental.record(entry);
+    }
+    assert_eq!(incremental, hashes);
+    assert_eq!(hashes.first_divergence(&incremental), None);
+
+    let mut tampered = recording.clone();
+    tampered[5][0] = 42;
+    let tampered = StateHashTrace::from_trace_log(4, &tampered);
+    assert_eq!(hashes.first_divergence(&tampered), Some(4..8));
+    let truncated = StateHashTrace::from_trace_log(4, &recording[..10]);
+    assert_eq!(hashes.first_divergence(&truncated), Some(8..12));
+    assert_eq!(truncated.first_divergence(&hashes), Some(8..12));
+    let coarse = StateHashTrace::from_trace_log(8, &recording[..10]);
+    assert_eq!(hashes.first_divergence(&coarse), Some(0..12));
+}
+
+#[test]
+fn test_watch_expressions() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r2, 3
+        ldxdw r3, [r1]
+        add64 r3, 10
+        stxdw [r1], r3
+        add64 r2, -1
+        jne r2, 0, -5
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(17);
+    let mut mem = 5u64.to_le_bytes();
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        None
+    );
+    let mut watcher = Watcher::default();
+    watcher.watch("balance", WatchExpression::load(ebpf::MM_INPUT_START, 8));
+    watcher.watch(
+        "counter",
+        WatchExpression::Sub(
+            Box::new(WatchExpression::Constant(3)),
+            Box::new(WatchExpression::Register(2)),
+        ),
+    );
+    watcher.watch("unmapped", WatchExpression::load(0, 8));
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    watcher.sample_basic_blocks(&analysis);
+    let (instruction_count, result) = watcher.execute(&mut vm, &executable);
+    assert_eq!(instruction_count, 17);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+    let balances = watcher
+        .samples
+        .iter()
+        .map(|sample| sample.values[0])
+        .collect::<Vec<_>>();
+    assert_eq!(
+        balances,
+        vec![Some(5), Some(5), Some(15), Some(25), Some(35)]
+    );
+    assert_eq!(watcher.samples[4].values[1], Some(3));
+    assert_eq!(watcher.samples[4].values[2], None);
+    let mut csv = Vec::new();
+    watcher.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert!(csv.starts_with("instruction,pc,balance,counter,unmapped\n0,0,5,"));
+    assert_eq!(csv.lines().count(), 6);
+}
+
+#[test]
+fn test_account_write_tracking() {
+    let mut input = vec![0u8; 10402];
+    input[0] = 2;
+    input[8] = u8::MAX;
+    input[88] = 4;
+    input[10360] = 2;
+    let layout = AccountLayout::parse_aligned(&input).unwrap();
+    assert_eq!(layout.lookup(0), Some((None, AccountField::AccountCount)));
+    assert_eq!(layout.lookup(80), Some((Some(0), AccountField::Lamports)));
+    assert_eq!(layout.lookup(99), Some((Some(0), AccountField::Data)));
+    assert_eq!(
+        layout.lookup(100),
+        Some((Some(0), AccountField::ReallocPadding))
+    );
+    assert_eq!(layout.lookup(10352), Some((Some(1), AccountField::Header)));
+    assert_eq!(layout.lookup(10401), Some((None, AccountField::ProgramId)));
+    assert_eq!(layout.lookup(10402), None);
+    assert_eq!(
+        layout.fields_in(84..98).collect::<Vec<_>>(),
+        vec![
+            (84..88, Some(0), AccountField::Lamports),
+            (88..96, Some(0), AccountField::DataLength),
+            (96..98, Some(0), AccountField::Data),
+        ]
+    );
+    assert_eq!(AccountLayout::parse_aligned(&input[0..10401]), None);
+
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r2, 1
+        stxdw [r1+80], r2
+        stxb [r1+96], r2
+        stxh [r1+10368], r2
+        ldxb r3, [r1+0]
+        ldxdw r3, [r1+84]
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(7);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let mut tracker = AccountWriteTracker::new(layout);
+    let (_instruction_count, result) = tracker.execute(&mut vm, &executable);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+    assert_eq!(
+        tracker
+            .account_write_summary()
+            .iter()
+            .map(|(key, bytes)| (*key, *bytes))
+            .collect::<Vec<_>>(),
+        vec![
+            ((None, AccountField::InstructionData), 2),
+            ((Some(0), AccountField::Lamports), 8),
+            ((Some(0), AccountField::Data), 1),
+        ]
+    );
+    assert_eq!(tracker.written_accounts(), vec![0]);
+    let access = |reads, writes| AccessCount { reads, writes };
+    assert_eq!(
+        tracker
+            .access_histogram()
+            .iter()
+            .map(|(key, count)| (*key, *count))
+            .collect::<Vec<_>>(),
+        vec![
+            ((None, AccountField::AccountCount), access(1, 0)),
+            ((None, AccountField::InstructionData), access(0, 1)),
+            ((Some(0), AccountField::Lamports), access(1, 1)),
+            ((Some(0), AccountField::DataLength), access(1, 0)),
+            ((Some(0), AccountField::Data), access(0, 1)),
+        ]
+    );
+    assert_eq!(tracker.unused_accounts(), vec![0]);
+
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r0, [r1+96]
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(2);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = tracker.execute(&mut vm, &executable);
+    assert!(matches!(result, ProgramResult::Ok(1)));
+    assert!(tracker.unused_accounts().is_empty());
+}
+
+#[test]
+fn test_memory_alias() {
+    let mut input = vec![0u8; 10402];
+    input[0] = 2;
+    input[8] = u8::MAX;
+    input[88] = 4;
+    input[10360] = 2;
+    let layout = AccountLayout::parse_aligned(&input).unwrap();
+    // Loads the second byte of the data of account 0 through an alias, stores into the third
+    // one through the alias and loads it from the input region
+    let executable = assemble::<TestContextObject>(
+        "
+        mov32 r2, 0x60
+        hor64 r2, 0x5
+        ldxb r3, [r2+1]
+        mov64 r4, 7
+        stxb [r2+2], r4
+        ldxb r0, [r1+98]
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input_region = MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START);
+    input_region.taint_offset = Some(0);
+    let mut context_object = TestContextObject::new(7);
+    let regions = {
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![input_region],
+            None
+        );
+        vm.memory_mapping
+            .add_alias(0x500000060, ebpf::MM_INPUT_START + 96, 4)
+            .unwrap();
+        let mut tracker = AccountWriteTracker::new(layout);
+        let (_instruction_count, result) = tracker.execute(&mut vm, &executable);
+        assert!(matches!(result, ProgramResult::Ok(7)));
+        assert_eq!(
+            tracker
+                .account_write_summary()
+                .get(&(Some(0), AccountField::Data)),
+            Some(&1)
+        );
+        assert_eq!(
+            tracker.access_histogram()[&(Some(0), AccountField::Data)],
+            AccessCount {
+                reads: 2,
+                writes: 1
+            }
+        );
+        vm.memory_mapping.get_regions().to_vec()
+    };
+    assert_eq!(input[98], 7);
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint =
+        InputTaint::from_trace_log_with_regions(&analysis, &context_object.trace_log, &regions);
+    assert_eq!(taint.tainted_loads.get(&2), Some(&(97..98)));
+    // The constant stored through the alias overwrote the input byte
+    assert_eq!(taint.tainted_loads.get(&5), None);
+}
+
+#[test]
+fn test_call_stack() {
+    let executable = assemble::<TestContextObject>(
+        "
+        entrypoint:
+        mov64 r1, 0
+        call function_outer
+        exit
+        function_outer:
+        mov64 r0, 1
+        call function_inner
+        exit
+        function_inner:
+        mov64 r0, 1
+        udiv64 r0, r1
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(7);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert_error!(result, "DivideByZero");
+    let call_stack = vm.call_stack(&executable, vm.registers[11]);
+    assert_eq!(
+        call_stack
+            .iter()
+            .map(|frame| frame.to_string())
+            .collect::<Vec<_>>(),
+        vec![
+            "function_inner+1 (pc 7)",
+            "function_outer+1 (pc 4)",
+            "entrypoint+1 (pc 1)",
+        ]
+    );
+}
+
+#[test]
+fn test_memory_region_builder_of_execution() {
+    #[allow(unused_mut)]
+    let mut executable = assemble::<TestContextObject>(
+        "
+        ldxb r0, [r1+16]
+        ldxb r2, [r1+8]
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    #[cfg(all(not(target_os = "windows"), target_arch = "x86_64"))]
+    executable.jit_compile().unwrap();
+    let config = executable.get_config();
+    let sbpf_version = executable.get_sbpf_version();
+    let input = [1u8; 8];
+    let account = [2u8; 8];
+    for interpreted in [true, false] {
+        if !interpreted && !cfg!(all(not(target_os = "windows"), target_arch = "x86_64")) {
+            continue;
+        }
+        let mut stack = AlignedMemory::<{ ebpf::HOST_ALIGN }>::zero_filled(config.stack_size());
+        let stack_len = stack.len();
+        let memory_mapping = MemoryRegionBuilder::default()
+            .regions([
+                executable.get_ro_region(),
+                MemoryRegion::new_writable(stack.as_slice_mut(), ebpf::MM_STACK_START),
+                MemoryRegion::new_writable(&mut [], ebpf::MM_HEAP_START),
+                MemoryRegion::new_readonly(&input, ebpf::MM_INPUT_START)
+                    .with_alignment(RegionAlignment::Unaligned),
+                MemoryRegion::new_readonly(&account, ebpf::MM_INPUT_START + 16)
+                    .with_alignment(RegionAlignment::Unaligned),
+            ])
+            .with_guard_regions(true)
+            .build(config, sbpf_version)
+            .unwrap();
+        let mut context_object = TestContextObject::new(3);
+        let mut vm = EbpfVm::new(
+            executable.get_loader().clone(),
+            sbpf_version,
+            &mut context_object,
+            memory_mapping,
+            stack_len,
+        );
+        vm.registers[1] = ebpf::MM_INPUT_START;
+        // The stray load between the input and the account hits a guard region
+        let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+        assert_eq!(
+            format!("{result:?}"),
+            format!(
+                "{:?}",
+                ProgramResult::Err(EbpfError::AccessViolation(
+                    AccessType::Load,
+                    ebpf::MM_INPUT_START + 8,
+                    1,
+                    "guard"
+                ))
+            )
+        );
+    }
+
+    // A writable region over the bytes of a readonly one could modify them unnoticed
+    let mut bytes = [3u8; 8];
+    let readonly = MemoryRegion::new_readonly(&bytes, ebpf::MM_INPUT_START)
+        .with_alignment(RegionAlignment::Unaligned);
+    let writable = MemoryRegion::new_writable(&mut bytes, ebpf::MM_INPUT_START + 16)
+        .with_alignment(RegionAlignment::Unaligned);
+    let mut stack = AlignedMemory::<{ ebpf::HOST_ALIGN }>::zero_filled(config.stack_size());
+    let regions = vec![
+        executable.get_ro_region(),
+        MemoryRegion::new_writable(stack.as_slice_mut(), ebpf::MM_STACK_START),
+        MemoryRegion::new_writable(&mut [], ebpf::MM_HEAP_START),
+        readonly,
+        writable,
+    ];
+    assert!(MemoryMapping::new(regions.clone(), config, sbpf_version).is_ok());
+    assert!(matches!(
+        MemoryRegionBuilder::default()
+            .regions(regions)
+            .build(config, sbpf_version),
+        Err(MemoryRegionError::HostOverlap(3, 4))
+    ));
+}
+
+#[test]
+fn test_address_space_map() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r0, 0
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut input = [0u8; 16];
+    let mut context_object = TestContextObject::new(2);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![
+            MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START),
+            MemoryRegion::new_guard(ebpf::MM_INPUT_START + ebpf::MM_REGION_SIZE, 64),
+        ],
+        None
+    );
+    let map = vm.address_space_map();
+    assert_eq!(
+        map.iter().map(|entry| entry.role).collect::<Vec<_>>(),
+        vec![
+            RegionRole::Text,
+            RegionRole::Stack,
+            RegionRole::Input,
+            RegionRole::Extra
+        ],
+    );
+    assert_eq!(
+        map[2],
+        AddressSpaceEntry {
+            vm_range: ebpf::MM_INPUT_START..ebpf::MM_INPUT_START + 16,
+            readable: true,
+            writable: true,
+            backing_size: 16,
+            vm_gap_size: None,
+            role: RegionRole::Input,
+        }
+    );
+    assert_eq!(
+        map[1].backing_size,
+        executable.get_config().stack_size() as u64
+    );
+    assert_eq!(
+        map.iter()
+            .map(|entry| entry.to_string())
+            .collect::<Vec<_>>(),
+        vec![
+            "0x0100000000..0x0100000010 r- Text 16 bytes",
+            "0x0200000000..0x0200040000 rw Stack 262144 bytes",
+            "0x0400000000..0x0400000010 rw Input 16 bytes",
+            "0x0500000000..0x0500000040 -- Extra 0 bytes",
+        ],
+    );
+}
+
+#[test]
+fn test_crash_report() {
+    let executable = assemble::<TestContextObject>(
+        "
+        entrypoint:
+        call function_store
+        exit
+        function_store:
+        mov64 r2, 1
+        stxdw [r1+80], r2
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut reports = Vec::new();
+    for data in [0, 1] {
+        let mut input = vec![0u8; 10402];
+        input[0] = 2;
+        input[8] = u8::MAX;
+        input[88] = 4;
+        input[96] = data;
+        input[10360] = 2;
+        let layout = AccountLayout::parse_aligned(&input);
+        let mut context_object = TestContextObject::new(5);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![MemoryRegion::new_readonly(&input, ebpf::MM_INPUT_START)],
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        let ProgramResult::Err(error) = result else {
+            panic!("expected an access violation");
+        };
+        reports.push(CrashReport::new(&vm, &executable, &error, layout.as_ref()));
+    }
+    let report = &reports[0];
+    assert_eq!(report.kind, "AccessViolation");
+    assert_eq!(report.instruction_error(), "ProgramFailedToComplete");
+    assert_eq!(report.vm_addr, Some(ebpf::MM_INPUT_START + 80));
+    assert_eq!(
+        report.account_field,
+        Some((Some(0), AccountField::Lamports))
+    );
+    assert_eq!(
+        report
+            .call_stack
+            .iter()
+            .map(|frame| frame.to_string())
+            .collect::<Vec<_>>(),
+        vec!["function_store+1 (pc 3)", "entrypoint+0 (pc 0)"]
+    );
+    assert!(report
+        .to_string()
+        .contains("in Lamports of account 0\n  at function_store+1 (pc 3)\n"));
+    assert_eq!(reports[1].signature, report.signature);
+    assert_ne!(
+        CrashReport::signature_of("StackAccessViolation", &report.call_stack),
+        report.signature
+    );
+}
+
+#[test]
+fn test_zero_fill_access_violation_handler() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r0, [r1+100]
+        stxb [r1+5000], r0
+        ldxb r2, [r1]
+        add64 r0, r2
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(5);
+    let mut mem = [7u8; 4];
+    let zero_fill = ZeroFillAccessViolationHandler::default();
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        Some(zero_fill.handler())
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(7)));
+    assert_eq!(
+        zero_fill.report(),
+        vec![SyntheticFill {
+            vm_addr: ebpf::MM_INPUT_START,
+            original_len: 4,
+            filled_len: 8192,
+        }]
+    );
+
+    let mut context_object = TestContextObject::new(5);
+    let zero_fill = ZeroFillAccessViolationHandler::default();
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_readonly(&[], ebpf::MM_INPUT_START)],
+        Some(zero_fill.handler())
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert_error!(result, "AccessViolation(Store");
+    assert_eq!(zero_fill.report()[0].filled_len, 4096);
+}
+
+#[test]
+fn test_copy_on_write_access_violation_handler() {
+    let writer = assemble::<TestContextObject>(
+        "
+        mov64 r2, 10
+        stxb [r1], r2
+        mov64 r2, 20
+        stxb [r1+1], r2
+        ldxb r0, [r1]
+        ldxb r3, [r1+1]
+        add64 r0, r3
+        ldxb r3, [r1+2]
+        add64 r0, r3
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let reader = assemble::<TestContextObject>(
+        "
+        ldxb r0, [r1]
+        ldxb r2, [r1+1]
+        add64 r0, r2
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let shared = [1u8, 2, 3, 4];
+    let copies = Rc::new(RefCell::new(Vec::new()));
+    let copy_on_write = {
+        let copies = copies.clone();
+        CopyOnWriteAccessViolationHandler::new(Box::new(move |payload, original| {
+            copies.borrow_mut().push(payload);
+            Box::from(original)
+        }))
+    };
+    let mut context_object = TestContextObject::new(10);
+    create_vm!(
+        vm,
+        &writer,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_copy_on_write(
+            &shared,
+            ebpf::MM_INPUT_START,
+            7
+        )],
+        Some(copy_on_write.handler())
+    );
+    let (_instruction_count, result) = vm.execute_program(&writer, true);
+    // the second store and the loads use the copy made by the first store
+    assert!(matches!(result, ProgramResult::Ok(33)));
+    assert_eq!(*copies.borrow(), vec![7]);
+    assert_eq!(copy_on_write.copied_regions(), vec![7]);
+    assert_eq!(shared, [1, 2, 3, 4]);
+
+    // another VM sharing the input still reads the original
+    let other_copy_on_write = CopyOnWriteAccessViolationHandler::default();
+    let mut context_object = TestContextObject::new(4);
+    create_vm!(
+        vm,
+        &reader,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_copy_on_write(
+            &shared,
+            ebpf::MM_INPUT_START,
+            7
+        )],
+        Some(other_copy_on_write.handler())
+    );
+    let (_instruction_count, result) = vm.execute_program(&reader, true);
+    assert!(matches!(result, ProgramResult::Ok(3)));
+    assert!(other_copy_on_write.copied_regions().is_empty());
+    assert_eq!(*copies.borrow(), vec![7]);
+}
+
+#[test]
+fn test_input_layout_inference() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxdw r2, [r1]
+        ldxdw r3, [r1+8]
+        ldxh r5, [r1+16]
+        ldxb r5, [r1+17]
+        mov64 r4, 0
+        add64 r4, 1
+        jlt r4, r2, -2
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut mem = [0u8; 18];
+    mem[0..8].copy_from_slice(&2u64.to_le_bytes());
+    mem[8..16].copy_from_slice(&ebpf::MM_INPUT_START.to_le_bytes());
+    let mut context_object = TestContextObject::new(10);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        None
+    );
+    let mut inference = InputLayoutInference::default();
+    let (_instruction_count, result) = inference.observe(&mut vm, &executable);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+    assert_eq!(
+        inference.infer(),
+        vec![
+            InferredField {
+                offset: 0,
+                len: 8,
+                kind: InferredFieldKind::Length,
+            },
+            InferredField {
+                offset: 8,
+                len: 8,
+                kind: InferredFieldKind::Pointer,
+            },
+            InferredField {
+                offset: 16,
+                len: 2,
+                kind: InferredFieldKind::Scalar,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_heap_sanitizer() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+        ..Config::default()
+    };
+    let mut loader = BuiltinProgram::new_loader(config);
+    loader
+        .register_function(
+            "sol_alloc_free_",
+            SyscallSanitizedAllocFree::vm::<TestContextObject>,
+        )
+        .unwrap();
+    let loader = Arc::new(loader);
+    for (source, (access_type, offset, len, poison)) in [
+        (
+            "
+            mov64 r1, 16
+            mov64 r2, 0
+            syscall sol_alloc_free_
+            stxdw [r0+8], r0
+            ldxdw r1, [r0+8]
+            stxb [r0+16], r1
+            return",
+            ("Store", 32, 1, "red zone"),
+        ),
+        (
+            "
+            mov64 r1, 16
+            mov64 r2, 0
+            syscall sol_alloc_free_
+            mov64 r6, r0
+            mov64 r1, 0
+            mov64 r2, r6
+            syscall sol_alloc_free_
+            ldxb r0, [r6]
+            return",
+            ("Load", 16, 1, "freed"),
+        ),
+        (
+            "
+            mov64 r1, 16
+            mov64 r2, 0
+            syscall sol_alloc_free_
+            mov64 r6, r0
+            mov64 r1, 0
+            mov64 r2, r6
+            syscall sol_alloc_free_
+            syscall sol_alloc_free_
+            return",
+            ("Store", 16, 0, "freed"),
+        ),
+    ] {
+        let executable = assemble::<TestContextObject>(source, loader.clone()).unwrap();
+        let mut heap_memory = vec![0u8; 256];
+        let mut context_object = TestContextObject::new(20);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        let (heap_index, _heap_region) =
+            vm.memory_mapping.find_region(ebpf::MM_HEAP_START).unwrap();
+        vm.memory_mapping
+            .replace_region(
+                heap_index,
+                MemoryRegion::new_writable(&mut heap_memory, ebpf::MM_HEAP_START),
+            )
+            .unwrap();
+        let heap_sanitizer = Rc::new(RefCell::new(HeapSanitizer::new(
+            ebpf::MM_HEAP_START,
+            heap_memory.len() as u64,
+            HeapSanitizer::DEFAULT_RED_ZONE_SIZE,
+        )));
+        vm.memory_mapping
+            .set_heap_sanitizer(Some(heap_sanitizer.clone()));
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        assert_error!(
+            result,
+            "HeapPoisonAccess({}, {}, {}, {:?})",
+            access_type,
+            ebpf::MM_HEAP_START + offset,
+            len,
+            poison
+        );
+        assert_eq!(
+            heap_sanitizer.borrow().live_allocations(),
+            usize::from(poison == "red zone")
+        );
+    }
+}
+
+#[test]
+fn test_stack_sanitizer() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let stack_frame_size = config.stack_frame_size as u64;
+    let executable = assemble::<TestContextObject>(
+        "
+        stxdw [r10-8], r1
+        ldxdw r0, [r10-8]
+        call function_callee
+        ldxb r3, [r10-9]
+        exit
+        function_callee:
+        ldxdw r0, [r10-8]
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config.clone())),
+    )
+    .unwrap();
+    for mode in [UninitializedReadMode::Warn, UninitializedReadMode::Error] {
+        let stack_sanitizer = Rc::new(RefCell::new(StackSanitizer::new(
+            &config,
+            SBPFVersion::V0,
+            mode,
+        )));
+        let mut context_object = TestContextObject::new(100);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        vm.memory_mapping
+            .set_stack_sanitizer(Some(stack_sanitizer.clone()));
+        vm.observers.push(Box::new(stack_sanitizer.clone()));
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        // The callee reads the same offset from its own frame, which it never wrote
+        let callee_read = UninitializedRead {
+            pc: 5,
+            vm_addr: ebpf::MM_STACK_START + stack_frame_size * 3 - 8,
+            len: 8,
+        };
+        match mode {
+            UninitializedReadMode::Warn => {
+                assert!(matches!(result, ProgramResult::Ok(0)));
+                assert_eq!(
+                    stack_sanitizer.borrow().uninitialized_reads(),
+                    &[
+                        callee_read,
+                        UninitializedRead {
+                            pc: 3,
+                            vm_addr: ebpf::MM_STACK_START + stack_frame_size - 9,
+                            len: 1,
+                        },
+                    ]
+                );
+            }
+            UninitializedReadMode::Error => {
+                assert_error!(
+                    result,
+                    "UninitializedRead({}, {})",
+                    callee_read.vm_addr,
+                    callee_read.len
+                );
+                assert_eq!(
+                    stack_sanitizer.borrow().uninitialized_reads(),
+                    &[callee_read]
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_instruction_profiler() {
+    let executable = assemble::<TestContextObject>(
+        "
+        entrypoint:
+        mov64 r1, 3
+        call function_loop
+        exit
+        function_loop:
+        add64 r1, -1
+        jne r1, 0, -2
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut context_object = TestContextObject::new(10);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.profiler = Some(Box::default());
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+    let profiler = vm.profiler.as_ref().unwrap();
+    assert_eq!(
+        profiler
+            .pc_counters()
+            .map(|(pc, counter)| (pc, counter.count))
+            .collect::<Vec<_>>(),
+        vec![(0, 1), (1, 1), (2, 1), (3, 3), (4, 3), (5, 1)]
+    );
+    assert_eq!(
+        profiler.opcode_counter(analysis.instructions[2].opc).count,
+        2
+    );
+    assert_eq!(
+        profiler.opcode_counter(ebpf::ADD64_IMM).cycles,
+        profiler.pc_counter(3).cycles
+    );
+    assert_eq!(vm.stopwatch_denominator, 10);
+    let total_cycles = profiler
+        .pc_counters()
+        .map(|(_pc, counter)| counter.cycles)
+        .sum::<u64>();
+    assert_eq!(vm.stopwatch_numerator, total_cycles);
+
+    let hot_basic_blocks = profiler.hot_basic_blocks(&analysis);
+    let loop_block = hot_basic_blocks
+        .iter()
+        .find(|basic_block| basic_block.pc == 3)
+        .unwrap();
+    assert_eq!(loop_block.function.as_deref(), Some("function_loop"));
+    assert_eq!(loop_block.counter.count, 6);
+    assert_eq!(
+        hot_basic_blocks
+            .iter()
+            .map(|basic_block| basic_block.counter.count)
+            .sum::<u64>(),
+        10
+    );
+    let mut report = Vec::new();
+    profiler.write_report(&mut report, &analysis, 1).unwrap();
+    assert_eq!(String::from_utf8(report).unwrap().lines().count(), 2);
+}
+
+#[test]
+fn test_taint_labels() {
+    let a = TaintLabels::new([0..1, 8..10, 9..12]);
+    let b = vec![4..5, 10..16].into_iter().collect::<TaintLabels>();
+    assert_eq!(a.ranges(), &[0..1, 8..12]);
+    assert_eq!(a.len(), 5);
+    assert!(a.contains(11) && !a.contains(12));
+    assert_eq!(a.union(&b).ranges(), &[0..1, 4..5, 8..16]);
+    assert_eq!(
+        a.intersection(&b),
+        TaintLabels::new(std::iter::once(10..12))
+    );
+    assert_eq!(a.difference(&b).ranges(), &[0..1, 8..10]);
+    assert!(TaintLabels::new(std::iter::once(9..11)).is_subset(&a));
+    assert!(!b.is_subset(&a));
+    assert!(!TaintLabels::new(std::iter::once(1..4)).intersects(&a));
+
+    let mut input = vec![0u8; 10402];
+    input[0] = 2;
+    input[8] = u8::MAX;
+    input[88] = 4;
+    input[10360] = 2;
+    let layout = AccountLayout::parse_aligned(&input).unwrap();
+    let key = TaintLabels::of_field(&layout, AccountField::Key, Some(0));
+    assert_eq!(key, TaintLabels::new(std::iter::once(16..48)));
+    // The second account is a duplicate and has no key of its own
+    assert_eq!(TaintLabels::of_field(&layout, AccountField::Key, None), key);
+    assert!(!key.intersects(&TaintLabels::of_field(
+        &layout,
+        AccountField::InstructionData,
+        None
+    )));
+
+    let mut registers: [[Option<std::ops::Range<u64>>; 8]; 2] = Default::default();
+    registers[0][0] = Some(0..1);
+    registers[0][1] = Some(0..1);
+    registers[1][7] = Some(0..5);
+    let memory = [0..1, 4..5];
+    assert_eq!(
+        LabelStatistics::count(0, &registers, memory.iter()),
+        LabelStatistics {
+            registers: 2,
+            register_bytes: 3,
+            memory_bytes: 1,
+        }
+    );
+    assert_eq!(
+        LabelStatistics::count(4, &registers, memory.iter()),
+        LabelStatistics {
+            registers: 1,
+            register_bytes: 1,
+            memory_bytes: 1,
+        }
+    );
+}
+
+#[test]
+fn test_syscall_fault_injection() {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+        ..Config::default()
+    });
+    loader
+        .register_function("bpf_gather_bytes", syscalls::SyscallGatherBytes::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 1
+        mov64 r2, 0
+        mov64 r3, 0
+        mov64 r4, 0
+        mov64 r5, 0
+        syscall bpf_gather_bytes
+        mov64 r6, r0
+        syscall bpf_gather_bytes
+        add64 r6, r0
+        mov64 r1, 9
+        syscall bpf_gather_bytes
+        add64 r6, r0
+        mov64 r0, r6
+        return",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let gather_bytes = ebpf::hash_symbol_name(b"bpf_gather_bytes");
+
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.fault_injector = Some(Box::new(PolicyFaultInjector::new(
+        vec![
+            FaultRule {
+                syscall: Some(gather_bytes),
+                trigger: FaultTrigger::NthCall(1),
+                action: FaultAction::Return(7),
+            },
+            FaultRule {
+                syscall: None,
+                trigger: FaultTrigger::ArgumentEquals { index: 0, value: 9 },
+                action: FaultAction::XorResult(1),
+            },
+        ],
+        0,
+    )));
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert_eq!(result.unwrap(), (1 << 32) + 7 + ((9 << 32) ^ 1));
+
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.fault_injector = Some(Box::new(PolicyFaultInjector::new(
+        vec![FaultRule {
+            syscall: None,
+            trigger: FaultTrigger::Probability {
+                numerator: 1,
+                denominator: 1,
+            },
+            action: FaultAction::Fail,
+        }],
+        42,
+    )));
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert_error!(
+        result,
+        "SyscallError(InjectedSyscallError {{ syscall: {} }})",
+        gather_bytes
+    );
+    assert_eq!(vm.registers[11], 5);
+}
+
+#[test]
+fn test_progress_tracker() {
+    let executable = assemble::<TestContextObject>(
+        "
+        entrypoint:
+        ldxb r2, [r1]
+        jeq r2, 0, +1
+        call function_deep
+        exit
+        function_deep:
+        call function_deeper
+        exit
+        function_deeper:
+        ja -1",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut tracker = ProgressTracker::default();
+    let mut run = |input: u8| {
+        let mut mem = [input];
+        let mut context_object = TestContextObject::new(20);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        tracker.evaluate(&analysis, &context_object.trace_log, &result)
+    };
+
+    let shallow = run(0);
+    assert!(!shallow.meter_exhausted);
+    assert_eq!(shallow.max_call_depth, 0);
+    assert_eq!(shallow.new_edges, shallow.covered_edges);
+
+    let deep = run(1);
+    assert!(deep.meter_exhausted);
+    assert_eq!(deep.max_call_depth, 2);
+    assert_eq!(deep.new_call_depth, 2);
+    assert!(deep.new_edges > 0);
+    assert!(deep.score() > shallow.score());
+
+    let repeated = run(1);
+    assert_eq!(repeated.new_edges, 0);
+    assert_eq!(repeated.new_call_depth, 0);
+    assert_eq!(repeated.covered_edges, deep.covered_edges);
+    assert_eq!(repeated.score(), repeated.covered_edges as u64);
+    assert_eq!(tracker.total_edges(), shallow.new_edges + deep.new_edges);
+}
+
+#[test]
+fn test_branch_distances() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1]
+        jeq r2, 42, +3
+        mov64 r3, -5
+        jslt r3, r2, +1
+        jset r2, 6, +0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let run = |input: u8| {
+        let mut mem = [input];
+        let mut context_object = TestContextObject::new(6);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+            None
+        );
+        vm.execute_program(&executable, true).1.unwrap();
+        BranchDistances::new(&executable, &context_object.trace_log)
+    };
+
+    let far = run(2);
+    assert_eq!(
+        far.branch_distance_map().iter().collect::<Vec<_>>(),
+        vec![(&1, &40), (&3, &7)]
+    );
+    let close = run(40);
+    assert_eq!(close.branch_distance_map()[&1], 2);
+    assert_eq!(far.improved_by(&close), vec![1]);
+    assert_eq!(close.improved_by(&far), vec![3]);
+
+    let mut merged = far.clone();
+    merged.merge(&close);
+    assert_eq!(merged.branch_distance_map()[&1], 2);
+    assert_eq!(merged.branch_distance_map()[&3], 7);
+
+    let taken = run(42);
+    assert_eq!(
+        taken.branch_distance_map().iter().collect::<Vec<_>>(),
+        vec![(&1, &0)]
+    );
+
+    // A trace which does not belong to the program is reported instead of panicking
+    let text = [
+        ebpf::Insn {
+            opc: ebpf::JEQ_IMM,
+            dst: 12,
+            ..ebpf::Insn::default()
+        },
+        ebpf::Insn {
+            opc: ebpf::JNE_REG,
+            dst: 1,
+            src: 13,
+            ..ebpf::Insn::default()
+        },
+        ebpf::Insn {
+            opc: ebpf::EXIT,
+            ..ebpf::Insn::default()
+        },
+    ]
+    .iter()
+    .flat_map(|insn| insn.to_vec())
+    .collect::<Vec<_>>();
+    let executable = Executable::<TestContextObject>::from_text_bytes(
+        &text,
+        Arc::new(BuiltinProgram::new_mock()),
+        SBPFVersion::V0,
+        FunctionRegistry::default(),
+    )
+    .unwrap();
+    let mut trace_log = vec![[0; 12]; 3];
+    trace_log[1][11] = 1;
+    trace_log[2][11] = 2;
+    let distances = BranchDistances::new(&executable, &trace_log);
+    assert!(distances.branch_distance_map().is_empty());
+    assert_eq!(
+        distances.errors(),
+        &[
+            BranchDistanceError::InvalidRegister {
+                pc: 0,
+                register: 12
+            },
+            BranchDistanceError::InvalidRegister {
+                pc: 1,
+                register: 13
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_vm_pool_health_check() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r0, [r1]
+        add64 r0, 1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let inputs: [&[u8]; 5] = [&[1], &[2], &[3], &[4], &[5]];
+    // The instruction meter is not reset for the canary, so the inputs leave too little of it
+    let pool_results = EbpfVmPool::new(&executable, 1)
+        .canary(&[9])
+        .health_check_interval(2)
+        .execute(
+            &inputs,
+            || TestContextObject::new(10),
+            |index, context_object| {
+                if index != usize::MAX {
+                    context_object.remaining = 4;
+                }
+            },
+        )
+        .unwrap();
+    let canary = pool_results.canary.as_ref().unwrap();
+    assert_eq!(canary.result, Ok(10));
+    assert_eq!(canary.instruction_count, 3);
+    assert_eq!(pool_results.replaced_vms, 2);
+    assert_eq!(
+        pool_results
+            .results
+            .iter()
+            .map(|result| result.result.clone())
+            .collect::<Vec<_>>(),
+        vec![Ok(2), Ok(3), Ok(4), Ok(5), Ok(6)]
+    );
+    assert_eq!(pool_results.results[0].trace.len, 3);
+    // The canary runs are not part of the coverage
+    assert_eq!(pool_results.coverage.executions(), 5);
+
+    // A healthy VM is kept
+    let pool_results = EbpfVmPool::new(&executable, 2)
+        .canary(&[9])
+        .health_check_interval(1)
+        .execute(
+            &inputs,
+            TestContextObject::default,
+            |_index, context_object| {
+                context_object.remaining = 10;
+            },
+        )
+        .unwrap();
+    assert_eq!(pool_results.replaced_vms, 0);
+    assert_eq!(pool_results.results.len(), 5);
+}
+
+#[test]
+fn test_instrumentation_failures() {
+    struct PanickingFaultInjector(u64);
+    impl FaultInjector for PanickingFaultInjector {
+        fn on_syscall(&mut self, _syscall: u32, _arguments: [u64; 5]) -> Option<FaultAction> {
+            self.0 += 1;
+            assert!(self.0 < 2, "out of faults");
+            None
+        }
+    }
+
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+        ..Config::default()
+    });
+    loader
+        .register_function("bpf_gather_bytes", syscalls::SyscallGatherBytes::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 1
+        mov64 r2, 0
+        mov64 r3, 0
+        mov64 r4, 0
+        mov64 r5, 0
+        syscall bpf_gather_bytes
+        mov64 r6, r0
+        syscall bpf_gather_bytes
+        add64 r6, r0
+        syscall bpf_gather_bytes
+        add64 r6, r0
+        mov64 r0, r6
+        return",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.fault_injector = Some(Box::new(PanickingFaultInjector(0)));
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    // The execution continues without the fault injector
+    assert_eq!(result.unwrap(), 3 << 32);
+    assert!(vm.fault_injector.is_none());
+    assert_eq!(
+        vm.instrumentation_failures,
+        vec![InstrumentationFailure {
+            component: InstrumentationComponent::FaultInjector,
+            pc: 7,
+            message: "out of faults".to_string(),
+        }]
+    );
+
+    #[derive(Default)]
+    struct PanickingTracer {
+        remaining: u64,
+        trace_log: Vec<[u64; 12]>,
+    }
+    impl ContextObject for PanickingTracer {
+        fn trace(&mut self, state: [u64; 12]) {
+            assert!(self.trace_log.len() < 2, "trace log is full");
+            self.trace_log.push(state);
+        }
+        fn consume(&mut self, amount: u64) {
+            self.remaining = self.remaining.saturating_sub(amount);
+        }
+        fn get_remaining(&self) -> u64 {
+            self.remaining
+        }
+    }
+
+    let executable = assemble::<PanickingTracer>(
+        "
+        mov64 r0, 1
+        add64 r0, 2
+        add64 r0, 3
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut context_object = PanickingTracer {
+        remaining: 4,
+        ..PanickingTracer::default()
+    };
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (instruction_count, result) = vm.execute_program(&executable, true);
+    assert_eq!(result.unwrap(), 6);
+    assert_eq!(instruction_count, 4);
+    assert_eq!(
+        vm.instrumentation_failures,
+        vec![InstrumentationFailure {
+            component: InstrumentationComponent::Tracer,
+            pc: 2,
+            message: "trace log is full".to_string(),
+        }]
+    );
+    // The tracer is not called again after it failed
+    assert_eq!(vm.context_object_pointer.trace_log.len(), 2);
+}
+
+#[test]
+fn test_value_profile() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1]
+        jeq r2, 42, +3
+        mov64 r3, -5
+        jslt r3, r2, +1
+        jset r2, 6, +0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut profile = ValueProfile::new(2);
+    let mut run = |input: u8| {
+        let mut mem = [input];
+        let mut context_object = TestContextObject::new(6);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+            None
+        );
+        vm.execute_program(&executable, true).1.unwrap();
+        profile.record_trace(&executable, &context_object.trace_log)
+    };
+    assert_eq!(run(2), 2);
+    assert_eq!(run(2), 0);
+    assert_eq!(run(40), 2);
+    // Both sites are full
+    assert_eq!(run(41), 0);
+    let minus_five = -5i64 as u64;
+    assert_eq!(
+        profile.value_profile(),
+        &BTreeMap::from([
+            (1, BTreeSet::from([(2, 42), (40, 42)])),
+            (3, BTreeSet::from([(minus_five, 2), (minus_five, 40)])),
+        ])
+    );
+
+    let mut merged = ValueProfile::new(4);
+    assert_eq!(merged.merge(&profile), 4);
+    assert_eq!(merged.merge(&profile), 0);
+    merged.clear();
+    assert!(merged.value_profile().is_empty());
+}
+
+#[test]
+fn test_input_pointer_annotations() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r6, r1
+        ldxdw r2, [r6+8]
+        ldxb r3, [r2]
+        ldxw r4, [r6+16]
+        ldxdw r5, [r4]
+        add64 r6, 24
+        mov64 r1, r6
+        call function_parse
+        exit
+        function_parse:
+        ldxdw r2, [r1]
+        stxb [r2+1], r3
+        mov64 r1, r2
+        ldxdw r0, [r1]
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.annotate_input_pointers(),
+        InputPointerAnnotations {
+            pointer_loads: BTreeSet::from([1, 9]),
+            dereferences: BTreeSet::from([2, 10, 12]),
+        }
+    );
+}
+
+#[test]
+fn test_coverage_listing() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxdw r2, [r1+8]
+        ldxb r3, [r2]
+        mov64 r1, 2
+        add64 r1, -1
+        jne r1, 0, -2
+        jeq r1, 1, +1
+        mov64 r0, 7
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input = [0u8; 16];
+    input[8..].copy_from_slice(&ebpf::MM_INPUT_START.to_le_bytes());
+    let mut context_object = TestContextObject::new(10);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(7)));
+    let trace_log = &context_object.trace_log;
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let coverage = analysis.instruction_coverage(trace_log);
+    assert_eq!(
+        coverage[&4],
+        InstructionCoverage {
+            executions: 2,
+            taken: 1,
+            not_taken: 1,
+        }
+    );
+    assert_eq!(
+        coverage[&5],
+        InstructionCoverage {
+            executions: 1,
+            taken: 0,
+            not_taken: 1,
+        }
+    );
+    assert_eq!(coverage[&6].executions, 1);
+
+    let mut text = Vec::new();
+    analysis
+        .disassemble_coverage(&mut text, trace_log, CoverageFormat::Text)
+        .unwrap();
+    let text = String::from_utf8(text).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "; 8 of 8 instructions executed, 1 of 2 branches taken in both directions"
+    );
+    assert_eq!(lines[1], "entrypoint:");
+    assert!(lines[2].ends_with("I ldxdw r2, [r1+0x8]"));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("       2 T:1 N:1") && line.contains("jne r1, 0,")));
+
+    let mut html = Vec::new();
+    analysis
+        .disassemble_coverage(&mut html, trace_log, CoverageFormat::Html)
+        .unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert_eq!(html.matches("<span class=\"hit\">").count(), 7);
+    assert_eq!(html.matches("<span class=\"partial\">").count(), 1);
+}
+
+#[test]
+fn test_loop_detector() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 10
+        add64 r1, -1
+        jne r1, 0, -2
+        ja +0
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    for budget in [None, Some(9), Some(5)] {
+        let mut context_object = TestContextObject::new(100);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        vm.loop_detector = Some(Box::new(LoopDetector::new(budget)));
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        let loop_detector = vm.loop_detector.as_ref().unwrap();
+        if budget == Some(5) {
+            assert_error!(result, "LoopBudgetExceeded(2, 1)");
+            assert_eq!(loop_detector.hottest(), Some(((2, 1), 6)));
+        } else {
+            assert!(matches!(result, ProgramResult::Ok(0)));
+            assert_eq!(loop_detector.back_edges(), &BTreeMap::from([((2, 1), 9)]));
+        }
+    }
+}
+
+#[test]
+fn test_deadline() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 0
+        add64 r1, 1
+        ja -2",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    for timeout in [Duration::ZERO, Duration::from_millis(10)] {
+        let mut context_object = TestContextObject::new(u64::MAX);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        vm.deadline = Deadline::after(timeout, 100);
+        let (instruction_count, result) = vm.execute_program(&executable, true);
+        assert_error!(result, "Timeout");
+        // The clock is read before the first instruction
+        assert_eq!(instruction_count == 1, timeout.is_zero());
+    }
+}
+
+#[test]
+fn test_shadow_call_stack() {
+    let executable = assemble::<TestContextObject>(
+        "
+        call function_foo
+        exit
+        function_foo:
+        mov64 r0, 42
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    for corrupt in [false, true] {
+        let mut context_object = TestContextObject::new(4);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        vm.shadow_call_stack = Some(Vec::new());
+        vm.previous_instruction_meter = vm.context_object_pointer.get_remaining();
+        let registers = vm.registers;
+        let mut interpreter = Interpreter::new(&mut vm, &executable, registers);
+        assert!(interpreter.step());
+        let registers = interpreter.reg;
+        assert_eq!(vm.shadow_call_stack, Some(vec![1]));
+        if corrupt {
+            vm.call_frames[0].target_pc = 0;
+        }
+        let mut interpreter = Interpreter::new(&mut vm, &executable, registers);
+        while interpreter.step() {}
+        if corrupt {
+            assert_error!(vm.program_result, "ControlFlowIntegrity(3, 0, 1)");
+        } else {
+            assert!(matches!(vm.program_result, ProgramResult::Ok(42)));
+        }
+    }
+}
+
+#[test]
+fn test_swap_context_object() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 5
+        add64 r1, -1
+        jne r1, 0, -2
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(4);
+    let mut next_context_object = TestContextObject::new(20);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.previous_instruction_meter = vm.context_object_pointer.get_remaining();
+    let registers = vm.registers;
+    let mut interpreter = Interpreter::new(&mut vm, &executable, registers);
+    for _ in 0..3 {
+        assert!(interpreter.step());
+    }
+    let registers = interpreter.reg;
+    let previous = vm.swap_context_object(&mut next_context_object);
+    assert_eq!(previous.get_remaining(), 1);
+    let mut interpreter = Interpreter::new(&mut vm, &executable, registers);
+    while interpreter.step() {}
+    assert!(matches!(vm.program_result, ProgramResult::Ok(0)));
+    vm.context_object_pointer.consume(vm.due_insn_count);
+    assert_eq!(vm.context_object_pointer.get_remaining(), 11);
+}
+
+#[test]
+fn test_step_with() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 5
+        stxdw [r10-8], r1
+        ldxdw r0, [r10-8]
+        add64 r0, 1
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    for abort in [false, true] {
+        let mut context_object = TestContextObject::new(5);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        vm.previous_instruction_meter = vm.context_object_pointer.get_remaining();
+        let registers = vm.registers;
+        let stack_addr = registers[ebpf::FRAME_PTR_REG] - 8;
+        let mut interpreter = Interpreter::new(&mut vm, &executable, registers);
+        let mut steps = Vec::new();
+        while interpreter.step_with(|step| {
+            steps.push((step.pc, step.memory_access));
+            match step.pc {
+                2 if abort => StepControl::Abort,
+                3 => StepControl::Skip,
+                _ => StepControl::Continue,
+            }
+        }) {}
+        let access = |access_type, value| {
+            Some(PendingAccess {
+                access_type,
+                vm_addr: stack_addr,
+                len: 8,
+                value,
+            })
+        };
+        if abort {
+            assert_error!(vm.program_result, "ExecutionAborted(2)");
+            assert_eq!(steps.len(), 3);
+        } else {
+            assert!(matches!(vm.program_result, ProgramResult::Ok(5)));
+            assert_eq!(
+                steps,
+                vec![
+                    (0, None),
+                    (1, access(AccessType::Store, 5)),
+                    (2, access(AccessType::Load, 0)),
+                    (3, None),
+                    (4, None),
+                ]
+            );
+            // The skipped instruction is not metered
+            assert_eq!(vm.due_insn_count, 4);
+        }
+    }
+}
+
+#[test]
+fn test_input_taint() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1+2]
+        stxb [r10-1], r2
+        ldxb r3, [r10-1]
+        mov64 r4, 5
+        jeq r4, 5, +1
+        mov64 r0, 1
+        jeq r3, 7, +1
+        mov64 r0, 2
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input = [0u8, 0, 7];
+    let mut context_object = TestContextObject::new(7);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    assert_eq!(taint.tainted_loads, BTreeMap::from([(0, 2..3), (2, 2..3)]));
+    assert_eq!(taint.tainted_comparisons, BTreeMap::from([(6, 2..3)]));
+
+    let dynamic_analysis = DynamicAnalysis::new(&context_object.trace_log, &analysis);
+    let mut dot = Vec::new();
+    analysis
+        .visualize_with_taint(&mut dot, &dynamic_analysis, &taint)
+        .unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+    assert_eq!(
+        dot.matches("fillcolor=\"#ffc0c0\";tooltip=\"input[2..3]\";")
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn test_input_taint_labels() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1+0]
+        ldxb r3, [r1+4]
+        stxb [r10-1], r2
+        stxb [r10-2], r2
+        jeq r3, 1, +0
+        add64 r2, r3
+        jeq r2, 0, +0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input = [1u8; 8];
+    let mut context_object = TestContextObject::new(8);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(result.is_ok());
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    assert!(taint.errors.is_empty());
+    // The sum is derived from the hull of both bytes
+    assert_eq!(taint.labels(), TaintLabels::new(std::iter::once(0..5)));
+    let first = TaintLabels::new(std::iter::once(0..1));
+    let second = TaintLabels::new(std::iter::once(4..5));
+    assert_eq!(taint.meeting_pcs(&first, &second), vec![6]);
+    assert_eq!(taint.meeting_pcs(&second, &second), vec![1, 4, 6]);
+    assert_eq!(
+        taint.label_statistics(0),
+        LabelStatistics {
+            registers: 1,
+            register_bytes: 8,
+            memory_bytes: 2,
+        }
+    );
+    assert_eq!(
+        taint.label_statistics(4),
+        LabelStatistics {
+            registers: 2,
+            register_bytes: 9,
+            memory_bytes: 0,
+        }
+    );
+}
+
+#[test]
+fn test_input_independent_instructions() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r6, 3
+        ldxb r2, [r1+2]
+        add64 r6, r2
+        mov64 r3, 1
+        jeq r3, 1, +1
+        mov64 r0, 1
+        call function_foo
+        mov64 r7, r6
+        mov64 r1, 2
+        mov64 r8, 4
+        exit
+        function_foo:
+        mov64 r0, r6
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis
+            .input_independent
+            .iter()
+            .enumerate()
+            .filter_map(|(pc, independent)| independent.then_some(pc))
+            .collect::<Vec<_>>(),
+        vec![0, 3, 4, 5, 9],
+    );
+
+    let mut input = [0u8, 0, 7];
+    let mut context_object = TestContextObject::new(12);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(10)));
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    assert_eq!(taint.tainted_loads, BTreeMap::from([(1, 2..3)]));
+    // Pcs 0, 3, 4 and 9 of the 12 executed instructions are skipped
+    #[cfg(feature = "diagnostics")]
+    {
+        assert_eq!(
+            taint.diagnostics,
+            solana_sbpf::diagnostics::TaintDiagnostics {
+                fast_path_hits: 4,
+                followed_instructions: 8,
+            }
+        );
+        assert_eq!(taint.diagnostics.hit_rate(), Some(4.0 / 12.0));
+    }
+}
+
+#[test]
+fn test_influence_matrix() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1+2]
+        ldxh r3, [r1+4]
+        jeq r2, 7, +1
+        mov64 r0, 1
+        jgt r3, 9, +1
+        mov64 r0, 2
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut matrix = InfluenceMatrix::default();
+    for input in [[0u8, 0, 7, 0, 10, 0], [0u8, 0, 3, 0, 1, 0]] {
+        let mut input = input;
+        let mut context_object = TestContextObject::new(7);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        assert!(result.is_ok());
+        let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+        matrix.record(&taint);
+    }
+    matrix.record(&InputTaint {
+        tainted_comparisons: BTreeMap::from([(4, 3..5)]),
+        ..InputTaint::default()
+    });
+    assert_eq!(matrix.executions(), 3);
+    assert_eq!(matrix.len(), 4);
+    assert_eq!(matrix.count(2, 2), 2);
+    assert_eq!(matrix.count(2, 4), 0);
+    assert_eq!(matrix.bytes_influencing(4), vec![(4, 3), (5, 2), (3, 1)]);
+    assert_eq!(matrix.branches_influenced_by(2), vec![(2, 2)]);
+    assert_eq!(matrix.branches_influenced_by(4), vec![(4, 3)]);
+
+    let mut merged = matrix.clone();
+    merged.merge(&matrix);
+    assert_eq!(merged.executions(), 6);
+    assert_eq!(merged.count(4, 4), 6);
+
+    let bytes = matrix.to_bytes();
+    assert_eq!(InfluenceMatrix::from_bytes(&bytes), Ok(matrix.clone()));
+    assert_eq!(
+        InfluenceMatrix::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(InfluenceMatrixError::Malformed)
+    );
+    assert_eq!(
+        InfluenceMatrix::from_bytes(&bytes[1..]),
+        Err(InfluenceMatrixError::InvalidMagic)
+    );
+    assert_eq!(
+        InfluenceMatrix::from_bytes(&InfluenceMatrix::default().to_bytes()),
+        Ok(InfluenceMatrix::default())
+    );
+}
+
+#[test]
+fn test_input_taint_concolic() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxh r2, [r1+1]
+        jne r2, 0x203, +3
+        ldxb r3, [r1+0]
+        jlt r3, r2, +0
+        mov64 r4, 1
+        jeq r4, 1, +0
+        mov64 r0, 0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input = [5u8, 3, 2];
+    let mut context_object = TestContextObject::new(8);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    assert!(taint.path_constraints.is_empty());
+    let taint = InputTaint::from_trace_log_concolic(&analysis, &context_object.trace_log);
+    let labels = |offsets: &[u64]| {
+        std::array::from_fn(|index| offsets.get(index).map(|offset| *offset..*offset + 1))
+    };
+    assert_eq!(
+        taint.path_constraints,
+        vec![
+            PathConstraint {
+                pc: 1,
+                opcode: ebpf::JNE_IMM,
+                dst: ConcolicOperand {
+                    value: 0x203,
+                    labels: labels(&[1, 2]),
+                },
+                src: ConcolicOperand {
+                    value: 0x203,
+                    labels: Default::default(),
+                },
+                taken: false,
+            },
+            PathConstraint {
+                pc: 3,
+                opcode: ebpf::JLT_REG,
+                dst: ConcolicOperand {
+                    value: 5,
+                    labels: labels(&[0]),
+                },
+                src: ConcolicOperand {
+                    value: 0x203,
+                    labels: labels(&[1, 2]),
+                },
+                taken: true,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_input_taint_dictionary() {
+    let mut serialized = InputBuilder::default()
+        .account(AccountDescription {
+            lamports: 5,
+            ..AccountDescription::default()
+        })
+        .instruction_data(&[1, 2, 3, 4, 42])
+        .build();
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxw r2, [r1+10352]
+        jne r2, 0x4030201, +0
+        ldxb r3, [r1+10356]
+        jeq r3, 42, +0
+        jeq r3, r2, +0
+        ldxb r4, [r1+80]
+        jgt r4, 3, +0
+        mov64 r0, 0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(9);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![serialized.region()],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log_concolic(&analysis, &context_object.trace_log);
+    assert_eq!(taint.path_constraints.len(), 4);
+    assert_eq!(
+        taint.extract_dictionary(&serialized.layout),
+        vec![
+            DictionaryEntry {
+                offset: 10352,
+                value: vec![1, 2, 3, 4],
+            },
+            DictionaryEntry {
+                offset: 10356,
+                value: vec![42],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_input_grammar() {
+    let mut serialized = InputBuilder::default()
+        .account(AccountDescription {
+            lamports: 5,
+            ..AccountDescription::default()
+        })
+        .instruction_data(&[1, 2, 3, 4, 42])
+        .build();
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxw r2, [r1+10352]
+        jne r2, 0x4030201, +0
+        ldxb r3, [r1+10356]
+        jeq r3, 42, +0
+        jne r3, 7, +0
+        ldxdw r4, [r1+80]
+        jgt r4, 3, +0
+        jeq r4, 5, +0
+        mov64 r0, 0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(10);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![serialized.region()],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log_concolic(&analysis, &context_object.trace_log);
+    let memory = SemanticMemory::with_input(serialized.layout.clone());
+    let grammar = InputGrammar::derive(&memory, &taint);
+    let field =
+        |offset, width, index, attribute: &str, constants: &[u64], enum_candidates| GrammarField {
+            offset,
+            width,
+            region: Some("input".to_string()),
+            index,
+            attribute: Some(attribute.to_string()),
+            constants: constants.to_vec(),
+            enum_candidates,
+        };
+    assert_eq!(
+        grammar.fields,
+        vec![
+            field(80, 8, Some(0), "Lamports", &[3, 5], vec![]),
+            field(
+                10352,
+                4,
+                None,
+                "InstructionData",
+                &[0x4030201],
+                vec![0x4030201]
+            ),
+            field(10356, 1, None, "InstructionData", &[7, 42], vec![7, 42]),
+        ]
+    );
+    assert_eq!(grammar.field_at(83), Some(&grammar.fields[0]));
+    assert_eq!(grammar.field_at(88), None);
+}
+
+#[test]
+fn test_input_taint_crash_relevant_bytes() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r3, [r1+2]
+        jeq r3, 7, +0
+        ldxw r2, [r1+8]
+        ldxw r4, [r1+12]
+        add64 r1, r2
+        stxb [r1+0], r4
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input = [0u8; 16];
+    input[8..12].copy_from_slice(&0x10000u32.to_le_bytes());
+    let mut context_object = TestContextObject::new(6);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert_error!(result, "AccessViolation");
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &vm.context_object_pointer.trace_log);
+    assert_eq!(taint.crash_relevant_bytes(), vec![8..16]);
+    assert_eq!(
+        taint
+            .tainted_comparisons
+            .values()
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![2..3]
+    );
+    assert_eq!(taint.last_address, std::array::from_fn(|_| Some(8..12)));
+
+    let ProgramResult::Err(error) = &result else {
+        panic!("expected an access violation");
+    };
+    let report = CrashReport::new(&vm, &executable, error, None);
+    assert_eq!(report.address_taint(), None);
+    let report = report.with_taint(&taint);
+    assert_eq!(report.address_taint(), Some(8..12));
+    assert!(report
+        .to_string()
+        .contains("\n  address derived from input[8..12]\n"));
+}
+
+#[test]
+fn test_input_taint_locations() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1+0]
+        stxb [r10-1], r2
+        mov64 r0, 0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input = [7u8; 4];
+    let mut context_object = TestContextObject::new(4);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(result.is_ok());
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    let stored = TaintLocation::Memory {
+        vm_addr: context_object.trace_log[1][10] - 1,
+    };
+    let register = TaintLocation::Register { index: 2, byte: 0 };
+    assert_eq!(
+        taint.final_locations().collect::<Vec<_>>(),
+        vec![(register, &(0..1)), (stored, &(0..1))]
+    );
+    assert_eq!(taint.final_taint(register), Some(0..1));
+    assert_eq!(taint.final_taint(stored), Some(0..1));
+    // The memory byte at the address of the register index is a different location
+    assert_eq!(
+        taint.final_taint(TaintLocation::Memory { vm_addr: 2 }),
+        None
+    );
+    assert_eq!(
+        taint.final_taint(TaintLocation::Register { index: 2, byte: 1 }),
+        None
+    );
+    assert_eq!(
+        taint.final_taint(TaintLocation::Register { index: 11, byte: 0 }),
+        None
+    );
+}
+
+#[test]
+fn test_input_taint_of_low_memory_addresses() {
+    // Memory at the virtual address 2 and the register r2 hold different taint
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1+0]
+        mov64 r3, 2
+        stxb [r3+0], r2
+        mov64 r2, 0
+        ldxb r4, [r3+0]
+        ldxb r5, [r1+1]
+        mov64 r2, r5
+        ldxb r6, [r3+1]
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V3,
+            enable_instruction_tracing: true,
+            aligned_memory_mapping: false,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input = [7u8; 4];
+    let mut low = [0u8; 8];
+    let mut context_object = TestContextObject::new(9);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![
+            MemoryRegion::new_writable(&mut low, 0),
+            MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START),
+        ],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(result.is_ok());
+    assert_eq!(low[2], 7);
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    assert_eq!(
+        taint.final_taint(TaintLocation::Memory { vm_addr: 2 }),
+        Some(0..1)
+    );
+    assert_eq!(
+        taint.final_taint(TaintLocation::Register { index: 4, byte: 0 }),
+        Some(0..1)
+    );
+    assert_eq!(
+        taint.final_taint(TaintLocation::Register { index: 2, byte: 0 }),
+        Some(1..2)
+    );
+    // Neither the register r2 nor the register r3 holding the address taints the memory
+    assert_eq!(
+        taint.final_taint(TaintLocation::Memory { vm_addr: 3 }),
+        None
+    );
+    assert_eq!(
+        taint.final_taint(TaintLocation::Register { index: 6, byte: 0 }),
+        None
+    );
+    assert_eq!(
+        taint.tainted_loads,
+        BTreeMap::from([(0, 0..1), (4, 0..1), (5, 1..2)])
+    );
+}
+
+#[test]
+fn test_input_taint_of_constants() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxdw r2, [r1+0]
+        hor64 r2, 1
+        jeq r2, 0, +0
+        ldxdw r3, [r1+0]
+        mov32 r3, 5
+        hor64 r3, 7
+        jeq r3, 0, +0
+        ldxdw r4, [r1+0]
+        hor64 r4, -1
+        jeq r4, 0, +0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input = [1u8; 8];
+    let mut context_object = TestContextObject::new(11);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(result.is_ok());
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    assert_eq!(
+        taint.tainted_loads,
+        BTreeMap::from([(0, 0..8), (3, 0..8), (7, 0..8)])
+    );
+    // Or-ing a constant into r2 keeps all of its bytes tainted, r3 holds a constant and the
+    // upper half of r4 is all ones
+    assert_eq!(
+        taint.tainted_comparisons,
+        BTreeMap::from([(2, 0..8), (9, 0..4)])
+    );
+    assert_eq!(
+        taint.final_taint(TaintLocation::Register { index: 2, byte: 7 }),
+        Some(7..8)
+    );
+    assert_eq!(
+        taint.final_taint(TaintLocation::Register { index: 3, byte: 0 }),
+        None
+    );
+    assert_eq!(
+        taint.final_taint(TaintLocation::Register { index: 4, byte: 3 }),
+        Some(3..4)
+    );
+    assert_eq!(
+        taint.final_taint(TaintLocation::Register { index: 4, byte: 4 }),
+        None
+    );
+}
+
+#[test]
+fn test_input_taint_across_stack_frames() {
+    let executable = assemble::<TestContextObject>(
+        "
+        entrypoint:
+        ldxb r2, [r1+1]
+        stxb [r10-1], r2
+        mov64 r1, r10
+        add64 r1, -1
+        call function_callee
+        ldxb r4, [r10-2]
+        jeq r4, 3, +0
+        exit
+        function_callee:
+        add64 r10, -64
+        ldxb r3, [r1+0]
+        stxb [r10-1], r3
+        ldxb r5, [r10-1]
+        jeq r5, 2, +0
+        stxb [r1-1], r5
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input = [0u8, 7];
+    let mut context_object = TestContextObject::new(15);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(_)));
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    // The taint follows the value from the frame of the caller into the (dynamically
+    // allocated) frame of the callee and back
+    assert_eq!(
+        taint.tainted_loads,
+        BTreeMap::from([(0, 1..2), (5, 1..2), (9, 1..2), (11, 1..2)])
+    );
+    assert_eq!(
+        taint.tainted_comparisons,
+        BTreeMap::from([(6, 1..2), (12, 1..2)])
+    );
+}
+
+#[test]
+fn test_input_taint_through_call_to_next_instruction() {
+    let executable = assemble::<TestContextObject>(
+        "
+        entrypoint:
+        ldxb r0, [r1]
+        call function_next
+        function_next:
+        jeq r0, 1, +0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut input = [7u8];
+    let mut context_object = TestContextObject::new(6);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(7)));
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    // The call continues at the next instruction, but unlike a syscall it keeps r0
+    assert_eq!(taint.tainted_comparisons, BTreeMap::from([(2, 0..1)]));
+}
+
+#[test]
+fn test_input_taint_of_tainted_regions() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1+2]
+        jeq r2, 7, +0
+        mov64 r3, 5
+        lsh64 r3, 32
+        ldxb r4, [r3+1]
+        jeq r4, 0, +0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let input = [0u8, 0, 7];
+    let other = [0u8; 2];
+    let other_vm_addr = ebpf::MM_REGION_SIZE * 5;
+    let regions = vec![
+        MemoryRegion::new_readonly_tainted(&input, ebpf::MM_INPUT_START, 16),
+        MemoryRegion::new_readonly(&other, other_vm_addr),
+    ];
+    let mut context_object = TestContextObject::new(7);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        regions.clone(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint =
+        InputTaint::from_trace_log_with_regions(&analysis, &context_object.trace_log, &regions);
+    assert_eq!(taint.tainted_loads, BTreeMap::from([(0, 18..19)]));
+    assert_eq!(taint.tainted_comparisons, BTreeMap::from([(1, 18..19)]));
+    let taint = InputTaint::from_trace_log_with_regions(
+        &analysis,
+        &context_object.trace_log,
+        &[MemoryRegion::new_readonly_tainted(&other, other_vm_addr, 0)],
+    );
+    assert_eq!(taint.tainted_comparisons, BTreeMap::from([(5, 1..2)]));
+}
+
+/// Maps the heap pages it hands out, see [SyscallGrowHeap]
+#[derive(Debug, Default)]
+struct GrowingHeapContextObject {
+    trace_log: Vec<TraceLogEntry>,
+    remaining: u64,
+    pages: Vec<Box<[u8]>>,
+}
+
+impl ContextObject for GrowingHeapContextObject {
+    fn trace(&mut self, state: [u64; 12]) {
+        self.trace_log.push(state);
+    }
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+declare_builtin_function!(
+    /// For test_heap_growth(), maps a new page at the end of the heap and returns its address
+    SyscallGrowHeap,
+    fn rust(
+        context_object: &mut GrowingHeapContextObject,
+        len: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let heap = ebpf::MM_HEAP_START..ebpf::MM_HEAP_START + ebpf::MM_REGION_SIZE;
+        let vm_addr = memory_mapping
+            .get_regions()
+            .iter()
+            .filter(|region| heap.contains(&region.vm_addr))
+            .map(|region| region.vm_addr + region.len)
+            .max()
+            .unwrap_or(ebpf::MM_HEAP_START);
+        let mut page = vec![0; len as usize].into_boxed_slice();
+        memory_mapping.add_region(MemoryRegion::new_writable(&mut page, vm_addr))?;
+        context_object.pages.push(page);
+        Ok(vm_addr)
+    }
+);
+
+#[test]
+fn test_heap_growth() {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V3,
+        enable_instruction_tracing: true,
+        aligned_memory_mapping: false,
+        ..Config::default()
+    });
+    loader
+        .register_function("grow_heap", SyscallGrowHeap::vm)
+        .unwrap();
+    let executable = assemble::<GrowingHeapContextObject>(
+        "
+        mov64 r6, r1
+        mov64 r1, 16
+        syscall grow_heap
+        mov64 r7, r0
+        mov64 r1, 16
+        syscall grow_heap
+        mov64 r8, r0
+        ldxb r2, [r6]
+        stxb [r7+15], r2
+        stxb [r8], r2
+        ldxb r3, [r8]
+        jeq r3, 7, +0
+        ldxb r0, [r7+15]
+        stxb [r8+16], r0
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let mut input = [7u8];
+    let mut context_object = GrowingHeapContextObject {
+        remaining: 15,
+        ..GrowingHeapContextObject::default()
+    };
+    let regions = {
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        assert_eq!(
+            format!("{result:?}"),
+            format!(
+                "{:?}",
+                ProgramResult::Err(EbpfError::AccessViolation(
+                    AccessType::Store,
+                    ebpf::MM_HEAP_START + 32,
+                    1,
+                    "heap"
+                ))
+            )
+        );
+        vm.memory_mapping.get_regions().to_vec()
+    };
+    assert_eq!(context_object.pages.len(), 2);
+    assert_eq!(context_object.pages[0][15], 7);
+    assert_eq!(context_object.pages[1][0], 7);
+    let grown = regions
+        .iter()
+        .filter(|region| region.vm_addr >= ebpf::MM_HEAP_START && region.len == 16)
+        .map(|region| region.vm_addr)
+        .collect::<Vec<_>>();
+    assert_eq!(grown, vec![ebpf::MM_HEAP_START, ebpf::MM_HEAP_START + 16]);
+
+    // The taint follows the input byte through the grown heap
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    assert_eq!(taint.tainted_comparisons, BTreeMap::from([(11, 0..1)]));
+    assert_eq!(
+        taint.tainted_loads,
+        BTreeMap::from([(7, 0..1), (10, 0..1), (12, 0..1)])
+    );
+}
+
+#[test]
+fn test_input_taint_through_memory_builtins() {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    });
+    register_memory_builtins(&mut loader).unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r6, r1
+        mov64 r1, r10
+        add64 r1, -16
+        mov64 r2, r6
+        add64 r2, 2
+        mov64 r3, 4
+        syscall sol_memcpy_
+        mov64 r1, r10
+        add64 r1, -14
+        mov64 r2, 0
+        mov64 r3, 1
+        syscall sol_memset_
+        ldxb r4, [r10-16]
+        ldxb r5, [r10-14]
+        mov64 r1, r10
+        add64 r1, -16
+        mov64 r2, r6
+        mov64 r3, 2
+        mov64 r4, r10
+        add64 r4, -8
+        syscall sol_memcmp_
+        ldxw r0, [r10-8]
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let mut input = [0u8, 1, 0, 1, 2, 3];
+    let mut context_object = TestContextObject::new(23);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    // The byte overwritten by `sol_memset_` no longer stems from the input
+    assert_eq!(
+        taint.tainted_loads,
+        BTreeMap::from([(12, 2..3), (21, 0..4)])
+    );
+    assert_eq!(taint.tainted_comparisons, BTreeMap::from([(20, 0..4)]));
+}
+
+#[test]
+fn test_prng() {
+    let mut prng = Prng::new(42);
+    let first = prng.next_u64();
+    assert_ne!(first, prng.next_u64());
+    assert_eq!(Prng::new(42).next_u64(), first);
+    assert!(prng.next_below(10).unwrap() < 10);
+    assert_eq!(prng.next_below(0), None);
+
+    let run = |rng_seed: Option<u64>, len: u64, budget: u64| {
+        let mut loader = BuiltinProgram::new_loader(Config {
+            rng_seed,
+            ..Config::default()
+        });
+        register_random_syscalls(&mut loader).unwrap();
+        let executable = assemble::<TestContextObject>(
+            "
+            mov64 r1, r10
+            add64 r1, -4096
+            mov64 r2, r6
+            syscall sol_random_bytes
+            ldxdw r0, [r10-4096]
+            exit",
+            Arc::new(loader),
+        )
+        .unwrap();
+        let mut context_object = TestContextObject::new(budget);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        vm.registers[6] = len;
+        vm.execute_program(&executable, true)
+    };
+    let mut bytes = [0u8; 8];
+    Prng::new(42).fill_bytes(&mut bytes);
+    assert_eq!(u64::from_le_bytes(bytes), first);
+    let cost = random_bytes_cost(12);
+    assert_eq!(cost, RANDOM_BYTES_BASE_COST);
+    let (instruction_count, result) = run(Some(42), 12, 6 + cost);
+    assert_eq!(instruction_count, 6 + cost);
+    assert_eq!(result.unwrap(), first);
+    assert_eq!(run(Some(42), 12, 6 + cost).1.unwrap(), first);
+    assert_ne!(run(Some(43), 12, 6 + cost).1.unwrap(), first);
+
+    // The cost grows with the number of bytes
+    let cost = random_bytes_cost(4096);
+    assert_eq!(cost, 4096 / RANDOM_BYTES_PER_UNIT);
+    assert!(cost > RANDOM_BYTES_BASE_COST);
+    let (instruction_count, result) = run(Some(42), 4096, 6 + cost);
+    assert_eq!(instruction_count, 6 + cost);
+    assert_eq!(result.unwrap(), first);
+    let (_instruction_count, result) = run(Some(42), 4096, 6 + cost - 1);
+    assert!(matches!(
+        result,
+        ProgramResult::Err(EbpfError::ExceededMaxInstructions)
+    ));
+}
+
+#[test]
+fn test_sysvars() {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    });
+    register_sysvar_syscalls(&mut loader).unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, r10
+        add64 r1, -40
+        syscall sol_get_clock_sysvar
+        ldxdw r6, [r10-40]
+        mov64 r1, r10
+        add64 r1, -24
+        syscall sol_get_rent_sysvar
+        ldxb r0, [r10-8]
+        add64 r0, r6
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let sysvars = Sysvars {
+        clock: Clock {
+            slot: 7,
+            ..Clock::default()
+        },
+        ..Sysvars::default()
+    };
+    let bytes = sysvars.to_bytes();
+    assert_eq!(
+        bytes.len() as u64,
+        Sysvar::ALL.iter().map(|sysvar| sysvar.size()).sum::<u64>()
+    );
+    for mapped in [true, false] {
+        let mut input = [0u8; 8];
+        let mut regions = vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)];
+        if mapped {
+            regions.push(Sysvars::region(&bytes, Some(100)));
+        }
+        let mut context_object = TestContextObject::new(10);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            regions,
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        if !mapped {
+            assert_error!(result, "NotMapped(\"sol_get_clock_sysvar\")");
+            continue;
+        }
+        // The slot plus the burn percent of the default rent
+        assert!(matches!(result, ProgramResult::Ok(57)));
+        let analysis = Analysis::from_executable(&executable).unwrap();
+        let taint = InputTaint::from_trace_log_with_regions(
+            &analysis,
+            &context_object.trace_log,
+            &[Sysvars::region(&bytes, Some(100))],
+        );
+        let burn_percent = 100 + Sysvar::Rent.offset() + 16;
+        assert_eq!(
+            taint.tainted_loads,
+            BTreeMap::from([(3, 100..108), (7, burn_percent..burn_percent + 1)])
+        );
+    }
+}
+
+#[test]
+fn test_taint_sinks() {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    });
+    register_memory_builtins(&mut loader).unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxdw r2, [r1+16]
+        add64 r2, 1
+        stxdw [r1+80], r2
+        stxdw [r1+24], r2
+        mov64 r1, r10
+        add64 r1, -8
+        mov64 r3, 1
+        syscall sol_memset_
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let mut input = vec![0u8; 10402];
+    input[0] = 2;
+    input[8] = u8::MAX;
+    input[10360] = 2;
+    let layout = AccountLayout::parse_aligned(&input).unwrap();
+    let mut context_object = TestContextObject::new(9);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut sinks = TaintSink::account_field(&layout, AccountField::Lamports);
+    assert_eq!(
+        sinks,
+        vec![TaintSink::Store(
+            ebpf::MM_INPUT_START + 80..ebpf::MM_INPUT_START + 88
+        )]
+    );
+    sinks.push(TaintSink::syscall_argument("sol_memset_", 2));
+    sinks.push(TaintSink::syscall_argument("sol_memset_", 3));
+    let taint = InputTaint::from_trace_log_with_sinks(&analysis, &context_object.trace_log, &sinks);
+    assert_eq!(
+        taint.policy_violations,
+        vec![
+            PolicyViolation {
+                sink: 0,
+                pc: 2,
+                offsets: 16..24,
+                propagation: vec![0, 1, 2],
+            },
+            PolicyViolation {
+                sink: 1,
+                pc: 7,
+                offsets: 16..24,
+                propagation: vec![0, 1, 7],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_semantic_memory() {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum ReturnDataField {
+        ProgramId,
+        Data,
+    }
+    const RETURN_DATA_START: u64 = ebpf::MM_INPUT_START + 0x1_0000_0000;
+
+    let loader = BuiltinProgram::new_loader(Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    });
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxdw r2, [r1+16]
+        mov64 r3, 5
+        lsh64 r3, 32
+        stxdw [r3+32], r2
+        stxdw [r3+8], r3
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let mut input = vec![0u8; 10402];
+    input[0] = 2;
+    input[8] = u8::MAX;
+    input[10360] = 2;
+    let layout = AccountLayout::parse_aligned(&input).unwrap();
+    let mut return_data = vec![0u8; 64];
+    let mut context_object = TestContextObject::new(6);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![
+            MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START),
+            MemoryRegion::new_writable(&mut return_data, RETURN_DATA_START),
+        ],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(0)));
+
+    let mut memory = SemanticMemory::with_input(layout);
+    memory.register(
+        "return data",
+        RETURN_DATA_START,
+        RegionLayout::default()
+            .with_field(32..64, None, ReturnDataField::Data)
+            .with_field(0..32, None, ReturnDataField::ProgramId)
+            .with_field(16..48, None, ReturnDataField::Data),
+    );
+    assert_eq!(
+        memory.lookup(ebpf::MM_INPUT_START + 80),
+        Some(SemanticTag {
+            region: "input".to_string(),
+            index: Some(0),
+            attribute: "Lamports".to_string(),
+            offset: 80,
+        })
+    );
+    assert_eq!(
+        memory.lookup(RETURN_DATA_START + 40),
+        Some(SemanticTag {
+            region: "return data".to_string(),
+            index: None,
+            attribute: "Data".to_string(),
+            offset: 40,
+        })
+    );
+    assert_eq!(memory.lookup(RETURN_DATA_START + 64), None);
+
+    let mut sinks = memory.taint_sinks("return data", ReturnDataField::Data);
+    assert_eq!(
+        sinks,
+        vec![TaintSink::Store(
+            RETURN_DATA_START + 32..RETURN_DATA_START + 64
+        )]
+    );
+    sinks.extend(memory.taint_sinks("return data", ReturnDataField::ProgramId));
+    sinks.extend(memory.taint_sinks("input", AccountField::Lamports));
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let taint = InputTaint::from_trace_log_with_sinks(&analysis, &context_object.trace_log, &sinks);
+    assert_eq!(
+        taint.policy_violations,
+        vec![PolicyViolation {
+            sink: 0,
+            pc: 3,
+            offsets: 16..24,
+            propagation: vec![0, 3],
+        }]
+    );
+}
+
+#[test]
+fn test_input_hook() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxdw r0, [r1+80]
+        ldxb r2, [r1+0]
+        stxdw [r10-8], r2
+        ldxdw r3, [r10-8]
+        add64 r0, r3
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let mut input = vec![0u8; 10402];
+    input[0] = 2;
+    input[8] = u8::MAX;
+    input[10360] = 2;
+    let layout = AccountLayout::parse_aligned(&input).unwrap();
+    let mut context_object = TestContextObject::new(6);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let loaded_offsets = Rc::new(RefCell::new(Vec::new()));
+    let recorded_offsets = loaded_offsets.clone();
+    vm.input_hook = Some(Box::new(
+        AttributeOverrides::new(SemanticMemory::with_input(layout)).with_override(
+            "input",
+            AccountField::Lamports,
+            move |tag| {
+                recorded_offsets.borrow_mut().push(tag.offset);
+                (tag.offset - 79) as u8
+            },
+        ),
+    ));
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(0x0807060504030203)));
+    assert_eq!(*loaded_offsets.borrow(), (80..88).collect::<Vec<_>>());
+    assert_eq!(input[80], 0);
+}
+
+#[test]
+fn test_execution_observers() {
+    #[derive(Default)]
+    struct CallRecorder {
+        events: Vec<String>,
+    }
+    impl ExecutionObserver for CallRecorder {
+        fn on_call(&mut self, pc: u64, target_pc: u64, call_depth: u64) {
+            self.events
+                .push(format!("call {pc} -> {target_pc} at depth {call_depth}"));
+        }
+        fn on_syscall(&mut self, pc: u64, _syscall: u32, arguments: [u64; 5]) {
+            self.events
+                .push(format!("syscall {pc} with r1 = {}", arguments[0]));
+        }
+        fn on_exit(&mut self, pc: u64, call_depth: u64, return_value: u64) {
+            self.events.push(format!(
+                "exit {pc} at depth {call_depth} with {return_value}"
+            ));
+        }
+    }
+
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+        ..Config::default()
+    });
+    loader
+        .register_function("bpf_gather_bytes", syscalls::SyscallGatherBytes::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1]
+        stxh [r1+1], r2
+        jeq r2, 42, +1
+        call function_foo
+        mov64 r1, 3
+        syscall bpf_gather_bytes
+        return
+        function_foo:
+        jne r2, 42, +1
+        mov64 r0, 6
+        mov64 r0, 7
+        return",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let branches = Rc::new(RefCell::new(BranchRecorder::default()));
+    let accesses = Rc::new(RefCell::new(MemoryAccessRecorder::default()));
+    let calls = Rc::new(RefCell::new(CallRecorder::default()));
+
+    let mut mem = [5, 0, 0];
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        None
+    );
+    vm.observers.push(Box::new(branches.clone()));
+    vm.observers.push(Box::new(accesses.clone()));
+    vm.observers.push(Box::new(calls.clone()));
+    let return_value = vm.execute_program(&executable, true).1.unwrap();
+
+    assert_eq!(
+        accesses.borrow().accesses,
+        vec![
+            ObservedAccess {
+                pc: 0,
+                is_store: false,
+                vm_addr: ebpf::MM_INPUT_START,
+                len: 1,
+                value: 5,
+            },
+            ObservedAccess {
+                pc: 1,
+                is_store: true,
+                vm_addr: ebpf::MM_INPUT_START + 1,
+                len: 2,
+                value: 5,
+            },
+        ]
+    );
+    let branches = branches.borrow();
+    assert_eq!(branches.branches[&2].not_taken, 1);
+    assert_eq!(branches.branches[&7].taken, 1);
+    assert_eq!(branches.one_sided_branches(), vec![2, 7]);
+    assert_eq!(
+        calls.borrow().events,
+        vec![
+            "call 3 -> 7 at depth 1",
+            "exit 10 at depth 1 with 7",
+            "syscall 5 with r1 = 3",
+            format!("exit 6 at depth 0 with {return_value}").as_str(),
+        ]
+    );
+}
+
+#[test]
+fn test_panicking_observer() {
+    struct PanicOnBranch;
+    impl ExecutionObserver for PanicOnBranch {
+        fn on_branch(&mut self, pc: u64, _target_pc: u64, _taken: bool) {
+            panic!("branch at {}", pc);
+        }
+    }
+
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r0, 1
+        jeq r0, 1, +1
+        mov64 r0, 2
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let branches = Rc::new(RefCell::new(BranchRecorder::default()));
+    let mut context_object = TestContextObject::new(10);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.observers.push(Box::new(PanicOnBranch));
+    vm.observers.push(Box::new(branches.clone()));
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(1)));
+    // The other observers are still notified
+    assert_eq!(branches.borrow().branches[&1].taken, 1);
+    assert_eq!(
+        vm.instrumentation_failures,
+        vec![InstrumentationFailure {
+            component: InstrumentationComponent::Observer(0),
+            pc: 1,
+            message: "branch at 1".to_string(),
+        }]
+    );
+    assert_eq!(
+        vm.instrumentation_failures[0].to_string(),
+        "Observer(0) panicked at pc 1: branch at 1"
+    );
+}
+
+#[test]
+fn test_coverage_map_observer() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 2
+        add64 r1, -1
+        jne r1, 0, -2
+        mov64 r0, 7
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let mut map = [0u8; 64];
+    {
+        let mut context_object = TestContextObject::new(100);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        // Safety: The map is only read after the VM was dropped
+        vm.observers.push(Box::new(unsafe {
+            CoverageMapObserver::from_raw_parts(map.as_mut_ptr(), map.len())
+        }));
+        for _ in 0..2 {
+            vm.context_object_pointer.remaining = 100;
+            let (_instruction_count, result) = vm.execute_program(&executable, true);
+            assert!(matches!(result, ProgramResult::Ok(7)));
+        }
+    }
+
+    // The back edge and the fall through of the loop, but no edge between the two runs
+    let mut expected = [0u8; 64];
+    expected[CoverageMapObserver::edge_index(2, 1, 64)] += 2;
+    expected[CoverageMapObserver::edge_index(2, 3, 64)] += 2;
+    assert_eq!(map, expected);
+}
+
+#[test]
+fn test_coverage_map_observer_control_flow() {
+    let executable = assemble::<TestContextObject>(
+        "
+        lddw r0, 0x1122334455667788
+        call function_foo
+        ja +1
+        mov64 r0, 1
+        exit
+        function_foo:
+        mov64 r1, 1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut map = [0u8; 1024];
+    // Safety: The map is only read after the observer was dropped
+    let observer = Rc::new(RefCell::new(unsafe {
+        CoverageMapObserver::from_raw_parts(map.as_mut_ptr(), map.len())
+    }));
+    // The first run fails in the callee
+    for (remaining, expected_result) in [
+        (2, "Err(ExceededMaxInstructions)"),
+        (100, "Ok(1234605616436508552)"),
+    ] {
+        let mut context_object = TestContextObject::new(remaining);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        vm.observers.push(Box::new(observer.clone()));
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        assert_eq!(format!("{result:?}"), expected_result);
+    }
+    drop(observer);
+
+    // The call, the return and the jump, but neither an edge over the lddw nor between the runs
+    let mut expected = [0u8; 1024];
+    expected[CoverageMapObserver::edge_index(2, 6, 1024)] += 2;
+    expected[CoverageMapObserver::edge_index(7, 3, 1024)] += 1;
+    expected[CoverageMapObserver::edge_index(3, 5, 1024)] += 1;
+    assert_eq!(map, expected);
+}
+
+#[test]
+fn test_program_mutation() {
+    let executable = assemble::<TestContextObject>(
+        "
+        add64 r10, 0
+        ldxdw r2, [r1]
+        mov64 r0, 0
+        jeq r2, 0, +3
+        add64 r0, r2
+        sub64 r2, 1
+        ja -4
+        stxdw [r1+8], r0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    let (_text_vaddr, text) = executable.get_text_bytes();
+    let mut input = 5u64.to_le_bytes().to_vec();
+    input.extend_from_slice(&[0; 8]);
+
+    let mut mutator = ProgramMutator::new(&executable, 42);
+    let (mutant, mutations) = mutator.mutate(text).unwrap();
+    assert!(!mutations.is_empty());
+    assert_ne!(mutant, text);
+
+    let bundles = mutator.fuzz(text, &input, &TestContextObject::new(1000), 200);
+    assert!(bundles.is_empty(), "{:?}", bundles);
+
+    let bundle = ReproductionBundle {
+        sbpf_version: executable.get_sbpf_version(),
+        functions: vec![(0, 0)],
+        text: mutant,
+        input,
+        mutations,
+        fault: "example".to_string(),
+    };
+    let mut restored = ReproductionBundle::from_bytes(&bundle.to_bytes()).unwrap();
+    assert!(restored.mutations.is_empty());
+    restored.mutations = bundle.mutations.clone();
+    assert_eq!(restored, bundle);
+    let mut executable = restored
+        .executable(executable.get_loader().clone())
+        .unwrap();
+    assert_eq!(
+        check_program(
+            &mut executable,
+            &TestContextObject::new(1000),
+            &restored.input
+        ),
+        None
+    );
+}
+
+#[test]
+fn test_block_trace() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1]
+        mov64 r0, 0
+        jeq r2, 0, +3
+        add64 r0, r2
+        add64 r2, -1
+        ja -4
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let recorder = Rc::new(RefCell::new(BlockTraceRecorder::new(&analysis)));
+    let mut mem = [3];
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+        None
+    );
+    vm.observers.push(Box::new(recorder.clone()));
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(6)));
+
+    let trace = recorder.borrow().trace.clone();
+    assert_eq!(
+        trace,
+        BlockTrace::from_trace_log(&analysis, &context_object.trace_log)
+    );
+    assert_eq!(
+        trace.instruction_count,
+        context_object.trace_log.len() as u64
+    );
+    assert_eq!(trace.loop_iterations, BTreeMap::from([(2, 3)]));
+    assert_eq!(trace.block_count(), 9);
+    assert_eq!(
+        trace.expand(&analysis),
+        context_object
+            .trace_log
+            .iter()
+            .map(|entry| entry[11])
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_global_coverage() {
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxb r2, [r1]
+        mov64 r0, 0
+        jeq r2, 0, +3
+        add64 r0, r2
+        add64 r2, -1
+        ja -4
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut coverage = GlobalCoverage::default();
+    let mut scores = Vec::new();
+    for input in [0, 3, 0] {
+        let mut mem = [input];
+        let mut context_object = TestContextObject::new(100);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)],
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        assert!(matches!(result, ProgramResult::Ok(_)));
+        let trace = BlockTrace::from_trace_log(&analysis, &context_object.trace_log);
+        scores.push(coverage.record_trace(&trace));
+    }
+    // The loop body at pc 3 is only entered by the second input
+    assert_eq!(scores, vec![3.0, 2.5, 1.0]);
+    assert_eq!(coverage.executions(), 3);
+    assert_eq!(
+        coverage.hit_counts(),
+        &BTreeMap::from([(0, 3), (2, 3), (3, 1), (6, 3)])
+    );
+    assert_eq!(coverage.rarest(1), vec![(3, 1)]);
+    assert!((coverage.rarity([0, 2, 3, 6]) - 2.0).abs() < 1e-9);
+    assert_eq!(coverage.rarity([7]), 1.0);
+    coverage.reset();
+    assert_eq!(coverage, GlobalCoverage::default());
+}
+
+#[test]
+fn test_instrumentation_meter() {
+    let run = |enable_instruction_tracing: bool, interpreted: bool| {
+        #[allow(unused_mut)]
+        let mut executable = assemble::<TestContextObject>(
+            "
+            mov64 r0, 0
+            mov64 r1, 4
+            add64 r0, r1
+            add64 r1, -1
+            jne r1, 0, -3
+            exit",
+            Arc::new(BuiltinProgram::new_loader(Config {
+                enable_instruction_tracing,
+                ..Config::default()
+            })),
+        )
+        .unwrap();
+        #[cfg(all(not(target_os = "windows"), target_arch = "x86_64"))]
+        executable.jit_compile().unwrap();
+        let mut context_object = TestContextObject::new(100);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+        assert!(matches!(result, ProgramResult::Ok(10)));
+        context_object
+    };
+
+    let plain = run(false, true);
+    assert_eq!(plain.get_instrumentation(), 0);
+    let traced = run(true, true);
+    assert_eq!(traced.get_remaining(), plain.get_remaining());
+    assert_eq!(traced.get_instrumentation(), traced.trace_log.len() as u64);
+    assert_eq!(traced.get_instrumentation(), 100 - plain.get_remaining());
+    #[cfg(all(not(target_os = "windows"), target_arch = "x86_64"))]
+    {
+        let traced_jit = run(true, false);
+        assert_eq!(traced_jit.get_remaining(), plain.get_remaining());
+        assert_eq!(
+            traced_jit.get_instrumentation(),
+            traced_jit.trace_log.len() as u64
+        );
+    }
+}
+
+#[test]
+fn test_call_frame_hooks() {
+    #[derive(Default)]
+    struct FrameRecorder {
+        events: Vec<(bool, CallFrameEvent)>,
+    }
+    impl ExecutionObserver for FrameRecorder {
+        fn on_push_frame(&mut self, frame: &CallFrameEvent) {
+            self.events.push((true, *frame));
+        }
+        fn on_pop_frame(&mut self, frame: &CallFrameEvent) {
+            self.events.push((false, *frame));
+        }
+    }
+
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let stack_frame_size = config.stack_frame_size as u64;
+    let executable = assemble::<TestContextObject>(
+        "
+        call function_outer
+        call function_inner
+        exit
+        function_outer:
+        call function_inner
+        exit
+        function_inner:
+        mov64 r0, 1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    let frames = Rc::new(RefCell::new(FrameRecorder::default()));
+    let call_graph = Rc::new(RefCell::new(CallGraphRecorder::default()));
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.observers.push(Box::new(frames.clone()));
+    vm.observers.push(Box::new(call_graph.clone()));
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(1)));
+
+    let frame = |caller_pc, callee_pc, call_depth| CallFrameEvent {
+        caller_pc,
+        callee_pc,
+        call_depth,
+        // Every frame is followed by a gap of the same size
+        frame_pointer: ebpf::MM_STACK_START + stack_frame_size * (2 * call_depth + 1),
+    };
+    assert_eq!(
+        frames.borrow().events,
+        vec![
+            (true, frame(0, 3, 1)),
+            (true, frame(3, 5, 2)),
+            (false, frame(3, 6, 2)),
+            (false, frame(0, 4, 1)),
+            (true, frame(1, 5, 1)),
+            (false, frame(1, 6, 1)),
+        ]
+    );
+    let call_graph = call_graph.borrow();
+    assert_eq!(
+        call_graph.edges,
+        BTreeMap::from([((0, 3), 1), ((1, 5), 1), ((3, 5), 1)])
+    );
+    assert!(call_graph.shadow_stack.is_empty());
+    assert_eq!(call_graph.max_call_depth, 2);
+}
+
+#[test]
+fn test_stack_profiler() {
+    // Fixed frames
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let executable = assemble::<TestContextObject>(
+        "
+        call function_outer
+        exit
+        function_outer:
+        stxdw [r10-8], r1
+        call function_inner
+        exit
+        function_inner:
+        stxdw [r10-4000], r1
+        ldxdw r0, [r10-4000]
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    let profiler = Rc::new(RefCell::new(StackProfiler::new(&executable)));
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.observers.push(Box::new(profiler.clone()));
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(_)));
+    let report = profiler.borrow().stack_report().clone();
+    assert_eq!(report.max_call_depth, 2);
+    assert_eq!(report.max_stack_usage, 4008);
+    assert!(!report.near_call_depth_limit);
+    assert!(!report.near_stack_limit);
+    let usage = |pc: u64| report.functions.get(&pc).cloned().unwrap();
+    assert_eq!(usage(0).max_frame_usage, 0);
+    assert_eq!((usage(2).max_frame_usage, usage(2).max_call_depth), (8, 1));
+    assert_eq!(
+        (usage(5).max_frame_usage, usage(5).max_call_depth),
+        (4000, 2)
+    );
+    assert!(!usage(2).near_frame_limit);
+    assert!(usage(5).near_frame_limit);
+    profiler.borrow_mut().reset();
+    assert_eq!(profiler.borrow().stack_report(), &Default::default());
+
+    // Dynamic frames, where moving the frame pointer down reserves stack
+    let config = Config {
+        max_call_depth: 3,
+        stack_frame_size: 64,
+        ..Config::default()
+    };
+    let executable = assemble::<TestContextObject>(
+        "
+        add64 r10, -64
+        call function
+        return
+        function:
+        add64 r10, -112
+        stxdw [r10+8], r1
+        return",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    let profiler = Rc::new(RefCell::new(StackProfiler::new(&executable)));
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    vm.observers.push(Box::new(profiler.clone()));
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(_)));
+    let report = profiler.borrow().stack_report().clone();
+    assert_eq!(report.max_call_depth, 1);
+    assert_eq!(report.max_stack_usage, 176);
+    assert!(!report.near_call_depth_limit);
+    assert!(report.near_stack_limit);
+    assert_eq!(report.functions.get(&0).unwrap().max_frame_usage, 64);
+    assert_eq!(report.functions.get(&3).unwrap().max_frame_usage, 112);
+    assert!(!report.functions.get(&3).unwrap().near_frame_limit);
+}
+
+#[test]
+fn test_solana_input_builder() {
+    let mut serialized = InputBuilder::default()
+        .account(AccountDescription {
+            key: [1; 32],
+            owner: [2; 32],
+            lamports: 42,
+            data: vec![7, 8, 9],
+            is_signer: true,
+            is_writable: true,
+            ..AccountDescription::default()
+        })
+        .duplicate(0)
+        .instruction_data(&[5, 6])
+        .program_id([3; 32])
+        .build();
+    let input = serialized.input.as_slice();
+    assert_eq!(input.len(), 8 + 10344 + 8 + 10 + 32);
+    assert_eq!(&input[8..12], &[u8::MAX, 1, 1, 0]);
+    assert_eq!(&input[96..99], &[7, 8, 9]);
+    assert_eq!(&input[10352..10354], &[0, 0]);
+    let layout = serialized.layout.clone();
+    assert_eq!(layout.lookup(16), Some((Some(0), AccountField::Key)));
+    assert_eq!(layout.lookup(80), Some((Some(0), AccountField::Lamports)));
+    assert_eq!(layout.lookup(98), Some((Some(0), AccountField::Data)));
+    assert_eq!(layout.lookup(10352), Some((Some(1), AccountField::Header)));
+    assert_eq!(
+        layout.lookup(10368),
+        Some((None, AccountField::InstructionData))
+    );
+    assert_eq!(layout.lookup(10370), Some((None, AccountField::ProgramId)));
+
+    let executable = assemble::<TestContextObject>(
+        "
+        ldxdw r0, [r1+80]
+        ldxb r2, [r1+98]
+        add64 r0, r2
+        exit",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(4);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![serialized.region()],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(51)));
+}
+/// Shares its trace with nested executions and keeps their entries apart
+#[derive(Debug, Default)]
+struct NestingContextObject {
+    trace_log: Vec<TraceLogEntry>,
+    remaining: u64,
+    checkpoints: TraceCheckpoints,
+    nested_trace_logs: Vec<Vec<TraceLogEntry>>,
+}
+
+impl ContextObject for NestingContextObject {
+    fn trace(&mut self, state: [u64; 12]) {
+        self.trace_log.push(state);
+    }
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+/// Starts a nested execution on the input `[0, 9, 9]` if the first input byte is not 0,
+/// otherwise compares the third input byte
+fn nesting_executable() -> Executable<NestingContextObject> {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    });
+    loader
+        .register_function("nested_execution", SyscallNestedExecution::vm)
+        .unwrap();
+    assemble::<NestingContextObject>(
+        "
+        ldxb r2, [r1]
+        jeq r2, 0, +3
+        ldxb r3, [r1+1]
+        syscall nested_execution
+        ja +2
+        ldxb r4, [r1+2]
+        jeq r4, 9, +0
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap()
+}
+
+declare_builtin_function!(
+    /// For test_input_taint_of_nested_executions()
+    SyscallNestedExecution,
+    fn rust(
+        context_object: &mut NestingContextObject,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let executable = nesting_executable();
+        context_object.checkpoints.push(&context_object.trace_log);
+        let mut input = [0u8, 9, 9];
+        {
+            create_vm!(
+                vm,
+                &executable,
+                &mut *context_object,
+                stack,
+                heap,
+                vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+                None
+            );
+            let (_instruction_count, result) = vm.execute_program(&executable, true);
+            assert!(result.is_ok());
+        }
+        let nested_trace_log = context_object
+            .checkpoints
+            .pop(&mut context_object.trace_log)
+            .unwrap();
+        context_object.nested_trace_logs.push(nested_trace_log);
+        Ok(0)
+    }
+);
+
+#[test]
+fn test_input_taint_of_nested_executions() {
+    let executable = nesting_executable();
+    let mut input = [1u8, 5, 7];
+    let mut context_object = NestingContextObject {
+        remaining: 100,
+        ..NestingContextObject::default()
+    };
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(result.is_ok());
+    assert_eq!(context_object.checkpoints.depth(), 0);
+
+    // The trace of the outer execution continues after the syscall at pc 3
+    let outer_pcs = context_object
+        .trace_log
+        .iter()
+        .map(|entry| entry[11])
+        .collect::<Vec<_>>();
+    assert_eq!(outer_pcs, vec![0, 1, 2, 3, 4, 7]);
+    assert_eq!(context_object.nested_trace_logs.len(), 1);
+    let nested_pcs = context_object.nested_trace_logs[0]
+        .iter()
+        .map(|entry| entry[11])
+        .collect::<Vec<_>>();
+    assert_eq!(nested_pcs, vec![0, 1, 5, 6, 7]);
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut taint = InputTaint::from_trace_log(&analysis, &context_object.trace_log);
+    let nested_taint = InputTaint::from_trace_log(&analysis, &context_object.nested_trace_logs[0]);
+    assert_eq!(taint.tainted_comparisons, BTreeMap::from([(1, 0..1)]));
+    assert_eq!(taint.tainted_loads, BTreeMap::from([(0, 0..1), (2, 1..2)]));
+    assert_eq!(
+        nested_taint.tainted_comparisons,
+        BTreeMap::from([(1, 0..1), (6, 2..3)])
+    );
+    let final_registers = taint.final_registers.clone();
+    taint.merge(&nested_taint);
+    assert_eq!(
+        taint.tainted_comparisons,
+        BTreeMap::from([(1, 0..1), (6, 2..3)])
+    );
+    assert_eq!(
+        taint.tainted_loads,
+        BTreeMap::from([(0, 0..1), (2, 1..2), (5, 2..3)])
+    );
+    // The final state stays the one of the outer execution
+    assert_eq!(taint.final_registers, final_registers);
+}
+/// Streams its trace to a sink instead of keeping it in memory
+struct StreamingContextObject<S: TraceSink> {
+    trace: StreamingTrace<S>,
+    remaining: u64,
+    /// Most entries the trace queued at once
+    max_queued: usize,
+}
+
+impl<S: TraceSink> ContextObject for StreamingContextObject<S> {
+    fn trace(&mut self, state: [u64; 12]) {
+        self.trace.push(state);
+        self.max_queued = self.max_queued.max(self.trace.queued());
+    }
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+/// Hands the bytes to another thread, would block while the channel is full
+struct ChannelWriter(SyncSender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        match self.0.try_send(bytes.to_vec()) {
+            Ok(()) => Ok(bytes.len()),
+            Err(TrySendError::Full(_)) => Err(io::ErrorKind::WouldBlock.into()),
+            Err(TrySendError::Disconnected(_)) => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+const STREAMED_PROGRAM: &str = "
+    mov64 r0, 0
+    add64 r0, 1
+    jlt r0, 100, -2
+    exit";
+
+fn execute_streaming<S: TraceSink>(sink: S, capacity: usize) -> StreamingContextObject<S> {
+    let executable = assemble::<StreamingContextObject<S>>(
+        STREAMED_PROGRAM,
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut context_object = StreamingContextObject {
+        trace: StreamingTrace::new(sink, capacity),
+        remaining: 202,
+        max_queued: 0,
+    };
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (instruction_count, result) = vm.execute_program(&executable, true);
+    assert_eq!(instruction_count, 202);
+    assert!(matches!(result, ProgramResult::Ok(100)));
+    context_object
+}
+
+#[test]
+fn test_streaming_trace_of_execution() {
+    let executable = assemble::<TestContextObject>(
+        STREAMED_PROGRAM,
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(202);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(matches!(result, ProgramResult::Ok(100)));
+    let expected_trace_log = context_object.trace_log;
+
+    // Streamed to a file and read back
+    let path = std::env::temp_dir().join(format!("sbpf-trace-{}", std::process::id()));
+    let context_object = execute_streaming(WriterSink::new(File::create(&path).unwrap()), 4);
+    assert_eq!(context_object.trace.written(), 202);
+    assert_eq!(context_object.trace.dropped(), 0);
+    drop(context_object.trace.finish().unwrap());
+    assert_eq!(
+        std::fs::metadata(&path).unwrap().len(),
+        (202 * TRACE_LOG_ENTRY_SIZE) as u64
+    );
+    let trace_log = TraceLogReader::new(BufReader::new(File::open(&path).unwrap()))
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(trace_log, expected_trace_log);
+
+    // Streamed to a slower consumer, which holds the execution back instead of growing the queue
+    let (sender, receiver) = sync_channel::<Vec<u8>>(1);
+    let consumer = std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        for chunk in receiver {
+            std::thread::sleep(Duration::from_micros(10));
+            bytes.extend_from_slice(&chunk);
+        }
+        bytes
+    });
+    let mut context_object = execute_streaming(WriterSink::new(ChannelWriter(sender)), 2);
+    assert!(context_object.max_queued <= 2);
+    context_object.trace.flush().unwrap();
+    assert_eq!(context_object.trace.written(), 202);
+    assert_eq!(context_object.trace.dropped(), 0);
+    drop(context_object.trace.finish().unwrap());
+    let bytes = consumer.join().unwrap();
+    let trace_log = TraceLogReader::new(bytes.as_slice())
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(trace_log, expected_trace_log);
+
+    // Entries after the consumer hung up are dropped
+    let (sender, receiver) = sync_channel::<Vec<u8>>(1);
+    drop(receiver);
+    let mut context_object = execute_streaming(WriterSink::new(ChannelWriter(sender)), 2);
+    assert_eq!(context_object.trace.written(), 0);
+    assert_eq!(context_object.trace.dropped(), 202);
+    assert_eq!(
+        context_object.trace.flush().unwrap_err().kind(),
+        io::ErrorKind::BrokenPipe
+    );
+}